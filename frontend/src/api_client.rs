@@ -0,0 +1,297 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use gloo::events::EventListener;
+use gloo::utils::format::JsValueSerdeExt;
+use jnickg_imaging::documents::Pyramid;
+use serde::{de::DeserializeOwned, Deserialize};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    wasm_bindgen::{JsCast, JsValue},
+    ProgressEvent, Request, Response, XmlHttpRequest,
+};
+use yew::Callback;
+
+/// Where the tile server's API lives
+pub const API_ROOT: &str = "http://localhost:8080/api/v1";
+
+/// The part of the server's error bodies we show the user
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+/// Send `request`, failing with a description of what went wrong. For error responses that's
+/// the server's own explanation, if it sent one. `what` says what the request was doing, as in
+/// "Uploading photo.jpg", to start those descriptions with. `304 Not Modified` isn't an error
+pub async fn send(request: &Request, what: &str) -> Result<Response, String> {
+    let window = web_sys::window().ok_or("Failed to get window")?;
+    let response = JsFuture::from(window.fetch_with_request(request))
+        .await
+        .map_err(|e| format!("{} failed: {:?}", what, e))?
+        .dyn_into::<Response>()
+        .map_err(|_| format!("{} failed: the response couldn't be read", what))?;
+    if response.ok() || response.status() == 304 {
+        return Ok(response);
+    }
+
+    let body = match response.json() {
+        Ok(promise) => JsFuture::from(promise)
+            .await
+            .ok()
+            .and_then(|json| json.into_serde::<ErrorBody>().ok()),
+        Err(_) => None,
+    };
+    Err(failure(what, response.status(), body))
+}
+
+/// Describe an error response, preferring the server's own explanation
+fn failure(what: &str, status: u16, body: Option<ErrorBody>) -> String {
+    match body {
+        Some(body) => format!("{} failed: {}", what, body.message),
+        None => format!("{} failed with status {}", what, status),
+    }
+}
+
+/// Read and decode the JSON body of `response`. See [`send`] for `what`
+pub async fn read_json<T: DeserializeOwned>(response: &Response, what: &str) -> Result<T, String> {
+    let promise = response
+        .json()
+        .map_err(|e| format!("{} failed: {:?}", what, e))?;
+    JsFuture::from(promise)
+        .await
+        .map_err(|e| format!("{} failed: the response isn't JSON: {:?}", what, e))?
+        .into_serde::<T>()
+        .map_err(|e| format!("{} failed: the response couldn't be decoded: {}", what, e))
+}
+
+/// GET `url`, and decode its JSON body. See [`send`] for `what`
+pub async fn get_json<T: DeserializeOwned>(url: &str, what: &str) -> Result<T, String> {
+    let request = Request::new_with_str(url).map_err(|e| format!("{} failed: {:?}", what, e))?;
+    let response = send(&request, what).await?;
+    read_json(&response, what).await
+}
+
+/// GET `url`, returning its body and `Content-Type`. See [`send`] for `what`
+pub async fn get_bytes(url: &str, what: &str) -> Result<(Vec<u8>, Option<String>), String> {
+    let request = Request::new_with_str(url).map_err(|e| format!("{} failed: {:?}", what, e))?;
+    let response = send(&request, what).await?;
+    let promise = response
+        .array_buffer()
+        .map_err(|e| format!("{} failed: {:?}", what, e))?;
+    let buffer = JsFuture::from(promise)
+        .await
+        .map_err(|e| format!("{} failed: {:?}", what, e))?;
+    let content_type = response.headers().get("Content-Type").ok().flatten();
+    Ok((js_sys::Uint8Array::new(&buffer).to_vec(), content_type))
+}
+
+/// An upload in progress. Dropping it stops reporting on the upload, but doesn't cancel it
+pub struct Upload {
+    _request: XmlHttpRequest,
+    _listeners: [EventListener; 3],
+}
+
+/// Upload a file to be built into a pyramid. `fetch` can't report how much of a request body has
+/// been sent, so this uses `XMLHttpRequest`, which can: `on_progress` is called with the bytes
+/// sent so far and the total, as they go. `on_done` is called once, with the new pyramid or a
+/// description of what went wrong.
+///
+/// Analogous to `curl --data-binary "@photo.jpg" -H "Content-Type: image/jpeg" -X POST .../pyramid`
+pub fn upload_pyramid(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    on_progress: Callback<(f64, f64)>,
+    on_done: Callback<Result<Pyramid, String>>,
+) -> Result<Upload, String> {
+    let what = format!("Uploading {}", file_name);
+    let failed = |e: JsValue| format!("{} failed: {:?}", what, e);
+    let request = XmlHttpRequest::new().map_err(failed)?;
+    request
+        .open("POST", &format!("{}/pyramid", API_ROOT))
+        .map_err(failed)?;
+    request
+        .set_request_header("Content-Type", file_type)
+        .map_err(failed)?;
+    request
+        .set_request_header(
+            "Content-Disposition",
+            &format!("attachment; filename={}", file_name),
+        )
+        .map_err(failed)?;
+
+    let progress = EventListener::new(
+        &request.upload().map_err(failed)?,
+        "progress",
+        move |event| {
+            if let Some(event) = event.dyn_ref::<ProgressEvent>() {
+                if event.length_computable() {
+                    on_progress.emit((event.loaded(), event.total()));
+                }
+            }
+        },
+    );
+    let load = {
+        let (on_done, what, finished) = (on_done.clone(), what.clone(), request.clone());
+        EventListener::once(&request, "load", move |_| {
+            on_done.emit(upload_result(&finished, &what))
+        })
+    };
+    let error = {
+        let what = what.clone();
+        EventListener::once(&request, "error", move |_| {
+            on_done.emit(Err(format!(
+                "{} failed: the server couldn't be reached",
+                what
+            )))
+        })
+    };
+
+    request.send_with_opt_u8_array(Some(data)).map_err(failed)?;
+    Ok(Upload {
+        _request: request,
+        _listeners: [progress, load, error],
+    })
+}
+
+/// The pyramid a finished upload created, or why it didn't
+fn upload_result(request: &XmlHttpRequest, what: &str) -> Result<Pyramid, String> {
+    let status = request
+        .status()
+        .map_err(|e| format!("{} failed: {:?}", what, e))?;
+    let body = request.response_text().ok().flatten().unwrap_or_default();
+    if !(200..300).contains(&status) {
+        return Err(failure(
+            what,
+            status,
+            serde_json::from_str::<ErrorBody>(&body).ok(),
+        ));
+    }
+    serde_json::from_str::<Pyramid>(&body)
+        .map_err(|e| format!("{} failed: the response couldn't be decoded: {}", what, e))
+}
+
+/// Called with the (possibly cached) pyramid manifest, or a description of what went wrong
+pub type ManifestCallback = Callback<Result<Pyramid, String>>;
+
+/// A manifest we've already fetched, along with the validator the server sent with it (if any)
+struct CachedManifest {
+    etag: Option<String>,
+    pyramid: Pyramid,
+}
+
+#[derive(Default)]
+struct ManifestCache {
+    /// Manifests we've seen, keyed by pyramid UUID
+    entries: HashMap<String, CachedManifest>,
+
+    /// Requests currently in flight, keyed by pyramid UUID, with everyone waiting on them
+    in_flight: HashMap<String, Vec<ManifestCallback>>,
+}
+
+/// Client for the tile server's API.
+///
+/// Cheap to clone; clones share the same manifest cache.
+#[derive(Clone, Default)]
+pub struct ApiClient {
+    manifests: Rc<RefCell<ManifestCache>>,
+}
+
+impl ApiClient {
+    /// Get the manifest we last saw for the given pyramid, without going to the server
+    pub fn cached_manifest(&self, pyramid_id: &str) -> Option<Pyramid> {
+        self.manifests
+            .borrow()
+            .entries
+            .get(pyramid_id)
+            .map(|m| m.pyramid.clone())
+    }
+
+    /// Remember a manifest we got some other way (e.g. from listing all pyramids, or from the
+    /// response to creating one). Keeps any validator we already had for it.
+    pub fn seed_manifest(&self, pyramid_id: &str, pyramid: Pyramid) {
+        let mut cache = self.manifests.borrow_mut();
+        match cache.entries.get_mut(pyramid_id) {
+            Some(m) => m.pyramid = pyramid,
+            None => {
+                cache.entries.insert(
+                    pyramid_id.to_string(),
+                    CachedManifest {
+                        etag: None,
+                        pyramid,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Fetch the manifest for the given pyramid, calling `callback` when it's available.
+    ///
+    /// - If a request for this pyramid is already in flight, no new request is made; `callback`
+    ///   is called when the existing one completes.
+    /// - If we have a cached copy with an ETag, the request is conditional (`If-None-Match`), and
+    ///   a `304 Not Modified` response is answered from the cache.
+    pub fn fetch_manifest(&self, pyramid_id: &str, callback: ManifestCallback) {
+        let etag = {
+            let mut cache = self.manifests.borrow_mut();
+            if let Some(waiting) = cache.in_flight.get_mut(pyramid_id) {
+                waiting.push(callback);
+                return;
+            }
+            cache
+                .in_flight
+                .insert(pyramid_id.to_string(), vec![callback]);
+            cache.entries.get(pyramid_id).and_then(|m| m.etag.clone())
+        };
+
+        let this = self.clone();
+        let pyramid_id = pyramid_id.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = this.request_manifest(&pyramid_id, etag).await;
+            let waiting = this
+                .manifests
+                .borrow_mut()
+                .in_flight
+                .remove(&pyramid_id)
+                .unwrap_or_default();
+            for callback in waiting {
+                callback.emit(result.clone());
+            }
+        });
+    }
+
+    async fn request_manifest(
+        &self,
+        pyramid_id: &str,
+        etag: Option<String>,
+    ) -> Result<Pyramid, String> {
+        let what = format!("Fetching the manifest for {}", pyramid_id);
+        let url = format!("{}/pyramid/{}", API_ROOT, pyramid_id);
+        let request = Request::new_with_str(&url).map_err(|e| format!("{:?}", e))?;
+        if let Some(etag) = etag.as_ref() {
+            request
+                .headers()
+                .set("If-None-Match", etag)
+                .map_err(|e| format!("{:?}", e))?;
+        }
+
+        let response = send(&request, &what).await?;
+        if response.status() == 304 {
+            return self
+                .cached_manifest(pyramid_id)
+                .ok_or_else(|| "Server says manifest is unchanged, but it isn't cached".into());
+        }
+
+        let new_etag = response.headers().get("ETag").ok().flatten();
+        let pyramid = read_json::<Pyramid>(&response, &what).await?;
+
+        self.manifests.borrow_mut().entries.insert(
+            pyramid_id.to_string(),
+            CachedManifest {
+                etag: new_etag,
+                pyramid: pyramid.clone(),
+            },
+        );
+        Ok(pyramid)
+    }
+}
@@ -1,18 +1,47 @@
 extern crate base64;
-use std::collections::HashMap;
+mod api_client;
+mod minimap;
+mod toasts;
+mod view;
+
+use std::collections::{HashMap, HashSet};
 
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use gloo::events::EventListener;
+use gloo::file::callbacks::FileReader;
 use gloo::file::File;
-use gloo::{file::callbacks::FileReader, utils::format::JsValueSerdeExt};
-use js_sys::Uint8Array;
-use wasm_bindgen::JsValue;
+use gloo::timers::callback::Timeout;
 use web_sys::HtmlImageElement;
 use web_sys::{
     wasm_bindgen::JsCast, CanvasRenderingContext2d, DragEvent, Event, FileList, HtmlCanvasElement,
-    HtmlInputElement, Request, Response,
+    HtmlInputElement,
+};
+use yew::{
+    html, Callback, Component, Context, Html, KeyboardEvent, MouseEvent, TargetCast, WheelEvent,
 };
-use yew::{html, Callback, Component, Context, Html, MouseEvent, TargetCast, WheelEvent};
+
+use api_client::{ApiClient, Upload};
+use jnickg_imaging::documents::{Pyramid, PyramidPage, PyramidTiles, TilingStatus};
+use minimap::Minimap;
+use toasts::{ToastLevel, ToastList, Toasts};
+use view::{level_and_relative_zoom_for, CanvasRoiPair, Dims, Roi2D, View2D};
+
+/// Longest side, in pixels, of the preview thumbnails we ask the server for
+const PREVIEW_SIZE: u32 = 256;
+
+/// Most we zoom out and in: 0.01% and 10000%
+const MIN_ZOOM: f64 = 0.0001;
+const MAX_ZOOM: f64 = 100.0;
+
+/// How far, in canvas pixels, the arrow keys and toolbar pan the view
+const PAN_STEP: f64 = 100.0;
+
+/// How much the +/- keys and toolbar zoom the view by
+const ZOOM_STEP: f64 = 1.25;
+
+/// How often to check on pyramids the server is still tiling, in milliseconds
+const TILING_POLL_MS: u32 = 2000;
 
 struct FileDetails {
     name: String,
@@ -21,177 +50,108 @@ struct FileDetails {
     image: HtmlImageElement,
 }
 
-/// A region of interest (ROI) in some target 2D coordinate space
-#[derive(Clone, Copy, Debug)]
-struct Roi2D {
-    /// The top-left corner
-    x: f64,
-    /// The top-left corner
-    y: f64,
-    /// The width
-    w: f64,
-    /// The height
-    h: f64,
+/// An upload that hasn't finished yet
+struct PendingUpload {
+    /// Reports back on the upload, for as long as we keep it
+    _upload: Upload,
+    /// How much of the file has been sent, from 0 to 1, once the browser says
+    sent: Option<f64>,
 }
 
-#[derive(Clone, Copy, Debug)]
-struct View2D {
-    /// (x, y) - The _center_ of the view, in unit coordinates.
-    ///
-    /// (0.0, 0.0) is the top-left corner of the image, and (1.0, 1.0) is the bottom-right corner.
-    unit_loc: (f64, f64),
+/// How far along a file is, from upload to tiles, as shown on its preview
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FileStatus {
+    /// Being sent to the server, with how much has been sent once that's known
+    Uploading(Option<f64>),
+    /// The server has the pyramid, and is tiling it
+    Processing,
+    /// The pyramid is tiled
+    Ready,
+    /// The upload, or tiling, failed
+    Failed,
+}
 
-    /// The zoom level of our view.
-    zoom: f64,
+impl FileStatus {
+    fn label(&self) -> String {
+        match self {
+            FileStatus::Uploading(Some(sent)) => format!("Uploading {:.0}%", sent * 100.0),
+            FileStatus::Uploading(None) => "Uploading".to_string(),
+            FileStatus::Processing => "Building tiles".to_string(),
+            FileStatus::Ready => "Tiles ready".to_string(),
+            FileStatus::Failed => "Failed".to_string(),
+        }
+    }
 
-    /// You have three guesses to figure out what this is for, and the first two guesses do not count.
-    is_pan_active: bool,
+    fn class(&self) -> &'static str {
+        match self {
+            FileStatus::Uploading(_) => "status-badge uploading",
+            FileStatus::Processing => "status-badge processing",
+            FileStatus::Ready => "status-badge ready",
+            FileStatus::Failed => "status-badge failed",
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-struct Dims {
-    w: f64,
-    h: f64,
+/// Identifies one tile of one pyramid level. `x` and `y` are the tile's top-left corner, in pixels
+/// of that level
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct TileKey {
+    pyramid_id: String,
+    level: u32,
+    x: u32,
+    y: u32,
 }
 
-#[derive(Clone, Copy, Debug)]
-struct CanvasRoiPair {
-    s: Roi2D,
-    d: Roi2D,
+/// One tile of a pyramid level, as described by the pyramid manifest
+#[derive(Clone, Debug)]
+struct TileInfo {
+    /// The tile's image name on the server
+    name: String,
+    /// Location and size of the tile within its pyramid level
+    roi: Roi2D,
 }
 
-/// Gets the pyramid level and re-scaled zoom factor, for the given effective zoom
-///
-/// 1.0 means full resolution, and 2.0 means we are zoomed in.
-/// For every factor of half, we should increase pyramid level by 1. Anything above 1.0
-/// should be considered as zoomed in.
-///
-/// Rescaled zoom means "relative zoom you need to use on the returned pyramid level to
-/// achieve the given effective zoom"
-fn level_and_relative_zoom_for(effective_zoom: f64) -> (u16, f64) {
-    let effective_zoom = effective_zoom + f64::EPSILON; // Prevent dbz
-                                                        // This computes the nearest _larger_ pyramid level, so that the browser only downsamples
-                                                        // pyramid levels. We should only upsample on canvas when the user zooms into L0.
-    let level = (1.0 / effective_zoom).log2().floor() as u16;
-    // Compute how to achieve the desired effective zoom based on the level we've chosen
-    let level_zoom = 0.5_f64.powi(level as i32);
-    let relative_zoom = effective_zoom / level_zoom;
-    (level, relative_zoom)
+impl TileInfo {
+    fn intersects(&self, other: &Roi2D) -> bool {
+        self.roi.x < other.x + other.w
+            && other.x < self.roi.x + self.roi.w
+            && self.roi.y < other.y + other.h
+            && other.y < self.roi.y + self.roi.h
+    }
 }
 
-impl View2D {
-    /// Convert the given view into a source ROI, and destination ROI
-    ///
-    /// This function accounts for relative aspect ratios and zoom level to determine the appropriate
-    /// (sx, sy, sw, sh) and (dx, dy, dw, dh) values for the view.
-    ///
-    /// The source dimensions are the size of the actual image data to be sampled, not necessarily the
-    /// original dimensions. That is, for low zoom levels the source dimensions may have been subsampled
-    /// using a mipmap / image pyramid.
-    ///
-    /// The dest dimensions are the size of the canvas onto which the source is to be drawn. Most often
-    /// these are scaled to the size of the original image, but that is not guaranteed on all viewports.
-    ///
-    /// This function accounts for mipmap subsampling by re-scaling the zoom factor to to be in terms of
-    /// the image pyramid level that was used. If zoom is 0.5, the re-scaled zoom factor is 1.0, meaning
-    /// the source image should be shown 1:1 in destination space. But if zoom is 0.6, the re-scaled zoom
-    /// becomes 1.2, as the L1 pyramid was used, and that needs to be upsampled by 20% (6:5 ratio) in
-    /// destination space to achieve a final effective zoom of 0.6.
-    ///
-    /// Finally, this function accounts for the aspect ratio of source and destination to determine what
-    /// can (and can't) be shown in destination space. Based on the source dimensions and re-scaled zoom
-    /// factor, we are able to determine whether the entire source image can be shown in the destination.
-    /// If it can't, the source ROI is cropped to fit within the aspect ratio of the destination.
-    ///
-    /// This function ensures that [`View2D::unit_loc`], remains in the center of the destination
-    ///
-    /// # Returns
-    /// A tuple of [`Roi2D`] structures
-    ///
-    /// # Notes
-    /// - See: https://developer.mozilla.org/en-US/docs/Web/API/CanvasRenderingContext2D/drawImage
-    ///   for explanation of values
-    fn to_roi(
-        self,
-        Dims { w: src_w, h: src_h }: Dims,
-        Dims {
-            w: dest_w,
-            h: dest_h,
-        }: Dims,
-        use_relative_zoom: bool,
-    ) -> CanvasRoiPair {
-        let (_, relative_zoom) = if use_relative_zoom {
-            level_and_relative_zoom_for(self.zoom)
-        } else {
-            (0u16, self.zoom)
-        };
-        web_sys::console::log_1(
-            &format!("Relative zoom: {}, effective: {}", relative_zoom, self.zoom).into(),
-        );
-        web_sys::console::log_1(
-            &format!(
-                "Src dims: ({}, {}), dest dims: ({}, {})",
-                src_w, src_h, dest_w, dest_h
-            )
-            .into(),
-        );
-        // Center of view in source image coordinates
-        let csx = self.unit_loc.0 * src_w;
-        let csy = self.unit_loc.1 * src_h;
-        // ... scaled to dest space based on relative zoom
-        let csxz = csx * relative_zoom;
-        let csyz = csy * relative_zoom;
-        // Center of the destination canvas (NOT the view). This is where we want to PUT the center
-        // of the view
-        let center_x_d = 0.5 * dest_w;
-        let center_y_d = 0.5 * dest_h;
-        // Origin of source image in destination space
-        let sxd = center_x_d - csxz;
-        let syd = center_y_d - csyz;
-        // dx, dy need to get as close to sxd, syd as possible, within canvas bounds
-        let dx = sxd.max(0.0).min(dest_w);
-        let dy = syd.max(0.0).min(dest_h);
-        // dw, dh can be computed based on the difference
-        let swz = src_w * relative_zoom;
-        let shz = src_h * relative_zoom;
-        let dw = (swz - (dx - sxd)).min(dest_w).max(0.0);
-        let dh = (shz - (dy - syd)).min(dest_h).max(0.0);
-        // Offset from dest origin and source origin, in dest space
-        let dxsz = dx - sxd;
-        let dysz = dy - syd;
-        // Now we can compute source ROI based on dest ROI offset
-        let sx = (dxsz / relative_zoom).min(src_w).max(0.0);
-        let sy = (dysz / relative_zoom).min(src_h).max(0.0);
-        let sw = dw / relative_zoom;
-        let sh = dh / relative_zoom;
-
-        let result = CanvasRoiPair {
-            s: Roi2D {
-                x: sx,
-                y: sy,
-                w: sw,
-                h: sh,
-            },
-            d: Roi2D {
-                x: dx,
-                y: dy,
-                w: dw,
-                h: dh,
-            },
-        };
-        web_sys::console::log_1(&format!("ROIs: {:?}", result).into());
-        result
-    }
+enum CachedTile {
+    /// The browser is still fetching the tile. The listeners report back when it's done, and
+    /// dropping them means we no longer care about the result
+    Loading(HtmlImageElement, [EventListener; 2]),
+    Ready(HtmlImageElement),
+    /// The tile couldn't be loaded, so don't keep asking for it
+    Failed,
 }
 
-impl Default for View2D {
-    fn default() -> Self {
-        Self {
-            unit_loc: (0.5, 0.5),
-            zoom: 1.0,
-            is_pan_active: false,
-        }
-    }
+/// Get the dimensions of the given pyramid level, and its tiles, from a pyramid manifest.
+///
+/// Returns `None` if the server hasn't finished tiling the pyramid yet.
+fn level_tiles(pyramid: &Pyramid, level: usize) -> Option<(Dims, Vec<TileInfo>)> {
+    let level = pyramid.tiles.levels()?.get(level)?;
+    let dims = Dims {
+        w: level.width as f64,
+        h: level.height as f64,
+    };
+    let tiles = level
+        .tiles
+        .iter()
+        .map(|t| TileInfo {
+            name: t.name.clone(),
+            roi: Roi2D {
+                x: t.x as f64,
+                y: t.y as f64,
+                w: t.width as f64,
+                h: t.height as f64,
+            },
+        })
+        .collect();
+    Some((dims, tiles))
 }
 
 pub enum Msg {
@@ -204,18 +164,61 @@ pub enum Msg {
     ViewPanState(bool),
     /// An existing pyramid was discovered on the server
     ///
-    /// pyramid_id, pyramid_json
-    ExistingPyramid(String, serde_json::Value),
+    /// pyramid_id, pyramid
+    ExistingPyramid(String, Pyramid),
     /// A new pyramid has been created
     ///
-    /// pyramid_id, file_name, pyramid_json
-    Pyramid(String, String, serde_json::Value),
+    /// pyramid_id, file_name, pyramid
+    Pyramid(String, String, Pyramid),
     /// A new pyramid level is available for the given pyramid
     ///
     /// (pyramid_id, pyramid_level, file_type, data)
     PyramidLevel(String, u8, String, Vec<u8>),
-    ViewZoom(f64),
+    /// A fresh (or revalidated) manifest is available for the given pyramid
+    ///
+    /// pyramid_id, pyramid
+    Manifest(String, Pyramid),
+    /// A tile has finished loading (or failed to)
+    ///
+    /// tile_key, succeeded
+    TileLoaded(TileKey, bool),
+    /// Zoom in or out, keeping the point under the mouse where it is
+    ///
+    /// wheel_delta, (x, y) on the canvas
+    ViewZoom(f64, (f64, f64)),
+    /// Center the view on the given unit coordinates, e.g. from clicking on the minimap
+    ViewJump((f64, f64)),
+    /// Pan by the given canvas pixels, whether or not the mouse is dragging
+    ViewStep((f64, f64)),
+    /// Zoom by the given factor, around the center of the view
+    ViewZoomBy(f64),
+    /// Go back to the default view
+    ViewReset,
+    /// Show the whole image, as large as it fits
+    ViewFit,
     SelectImage(String),
+    /// Part of an upload has been sent
+    ///
+    /// file_name, bytes_sent, total_bytes
+    UploadProgress(String, f64, f64),
+    /// An upload failed
+    ///
+    /// file_name, error
+    UploadFailed(String, String),
+    /// Check whether the server has finished tiling the given pyramid
+    PollTiling(String),
+    /// Tell the user something, with a toast
+    ///
+    /// level, message
+    Notify(ToastLevel, String),
+    /// Stop showing the toast with the given ID
+    DismissToast(u64),
+}
+
+impl Msg {
+    fn error(message: impl Into<String>) -> Msg {
+        Msg::Notify(ToastLevel::Error, message.into())
+    }
 }
 
 pub struct App {
@@ -223,9 +226,22 @@ pub struct App {
     files: Vec<FileDetails>,
     file_to_pyramid_id: HashMap<String, String>,
     pyramid_id_to_cached_pyramid_images: HashMap<String, Vec<Option<HtmlImageElement>>>,
-    pyramid_id_to_json: HashMap<String, serde_json::Value>,
+    pyramid_id_to_pyramid: HashMap<String, Pyramid>,
     selected_image: Option<String>,
     current_view: View2D,
+    /// Dimensions of the viewer canvas when we last drew on it
+    viewer_dims: Option<Dims>,
+    api: ApiClient,
+    tile_cache: HashMap<TileKey, CachedTile>,
+    toasts: Toasts,
+    /// Uploads still being sent, by file name
+    uploads: HashMap<String, PendingUpload>,
+    /// Files whose upload failed
+    failed_uploads: HashSet<String>,
+    /// Pyramids we'll check on again soon, because they aren't tiled yet
+    polling: HashSet<String>,
+    /// Turns key presses into view changes, for as long as we keep it
+    _keyboard: EventListener,
 }
 
 impl Component for App {
@@ -233,55 +249,64 @@ impl Component for App {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        let request = Request::new_with_str_and_init(
-            "http://localhost:8080/api/v1/pyramids",
-            web_sys::RequestInit::new().method("GET"),
-        )
-        .unwrap();
-        // When we get a response to that request, we should send Msg::ExistingPyramid for each JSON
-        // object in the response.
+        // Listing is paginated, so keep asking for the next page until there isn't one. We send
+        // Msg::ExistingPyramid for each pyramid in each page.
         let link = ctx.link().clone();
-        let future = wasm_bindgen_futures::JsFuture::from(
-            web_sys::window().unwrap().fetch_with_request(&request),
-        );
         wasm_bindgen_futures::spawn_local(async move {
-            match future.await {
-                Ok(response) => {
-                    let response = response
-                        .dyn_into::<Response>()
-                        .expect("Failed to convert response");
-                    let json_promise = response.json().unwrap();
-                    let json = wasm_bindgen_futures::JsFuture::from(json_promise)
-                        .await
-                        .unwrap();
-                    let pyramids = json.into_serde::<Vec<serde_json::Value>>().unwrap();
-                    for pyramid in pyramids {
-                        link.send_message(Msg::ExistingPyramid(
-                            pyramid.get("uuid").unwrap().as_str().unwrap().to_string(),
-                            pyramid,
-                        ));
+            let mut offset = Some(0);
+            while let Some(o) = offset {
+                let url = format!("{}/pyramids?offset={}", api_client::API_ROOT, o);
+                match api_client::get_json::<PyramidPage>(&url, "Listing pyramids").await {
+                    Ok(page) => {
+                        for pyramid in page.pyramids {
+                            link.send_message(Msg::ExistingPyramid(pyramid.uuid.clone(), pyramid));
+                        }
+                        offset = page.next_offset;
+                    }
+                    Err(e) => {
+                        link.send_message(Msg::error(e));
+                        break;
                     }
-                }
-                Err(e) => {
-                    web_sys::console::log_1(&format!("Error fetching: {:?}", e).into());
                 }
             }
         });
 
+        let keyboard = {
+            let link = ctx.link().clone();
+            EventListener::new(&web_sys::window().unwrap(), "keydown", move |event| {
+                let event = match event.dyn_ref::<KeyboardEvent>() {
+                    Some(event) => event,
+                    None => return,
+                };
+                if let Some(msg) = Self::key_to_msg(event) {
+                    event.prevent_default();
+                    link.send_message(msg);
+                }
+            })
+        };
+
         Self {
             readers: HashMap::default(),
             files: Vec::default(),
             file_to_pyramid_id: HashMap::default(),
             pyramid_id_to_cached_pyramid_images: HashMap::default(),
-            pyramid_id_to_json: HashMap::default(),
+            pyramid_id_to_pyramid: HashMap::default(),
             selected_image: None,
             current_view: View2D::default(),
+            viewer_dims: None,
+            api: ApiClient::default(),
+            tile_cache: HashMap::default(),
+            toasts: Toasts::default(),
+            uploads: HashMap::default(),
+            failed_uploads: HashSet::default(),
+            polling: HashSet::default(),
+            _keyboard: keyboard,
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::ExistingPyramid(pyramid_id, pyramid_json) => {
+            Msg::ExistingPyramid(pyramid_id, pyramid) => {
                 // This does three things. First, it fetches L0 image data first.
                 //
                 // Then, it sends Msg::Loaded (without POSTing) with the `original_filename` field
@@ -291,122 +316,73 @@ impl Component for App {
                 // Finally, it sends Msg::Pyramid so that we cache the rest of the pyramid levels
 
                 // First, get L0 data.
-                let image_url = pyramid_json.get("image_urls").unwrap()[0].as_str().unwrap();
-                let request = Request::new_with_str(image_url).unwrap();
+                let image_url = pyramid.image_urls[0].clone();
                 let link = ctx.link().clone();
                 let pyramid_id = pyramid_id.clone();
-                let file_name = pyramid_json
-                    .get("original_filename")
-                    .unwrap()
-                    .as_str()
-                    .unwrap()
-                    .to_string();
+                let file_name = pyramid.original_filename.clone();
                 let file_name_moveable = file_name.clone();
-                let file_type = pyramid_json
-                    .get("mime_type")
-                    .unwrap()
-                    .as_str()
-                    .unwrap()
-                    .to_string();
-                let future = wasm_bindgen_futures::JsFuture::from(
-                    web_sys::window().unwrap().fetch_with_request(&request),
-                );
+                let file_type = pyramid.mime_type.clone();
                 wasm_bindgen_futures::spawn_local(async move {
-                    match future.await {
-                        Ok(response) => {
-                            let response = response
-                                .dyn_into::<Response>()
-                                .expect("Failed to convert response");
-                            let ab_promise = response.array_buffer().unwrap();
-                            let ab = wasm_bindgen_futures::JsFuture::from(ab_promise)
-                                .await
-                                .unwrap();
-                            let data = js_sys::Uint8Array::new(&ab).to_vec();
-                            // Then, when we get the L0 data, send Msg::Loaded
-                            link.send_message(Msg::Loaded(
-                                file_name_moveable.to_string(),
-                                file_type.to_string(),
-                                data,
-                                false,
-                            ));
-                        }
-                        Err(e) => {
-                            web_sys::console::log_1(&format!("Error fetching: {:?}", e).into());
-                        }
+                    let what = format!("Fetching {}", file_name_moveable);
+                    match api_client::get_bytes(&image_url, &what).await {
+                        // Then, when we get the L0 data, send Msg::Loaded
+                        Ok((data, _)) => link.send_message(Msg::Loaded(
+                            file_name_moveable,
+                            file_type,
+                            data,
+                            false,
+                        )),
+                        Err(e) => link.send_message(Msg::error(e)),
                     }
                 });
 
                 // Finally, send Msg::Pyramid
                 let link = ctx.link().clone();
-                link.send_message(Msg::Pyramid(pyramid_id, file_name, pyramid_json.clone()));
+                link.send_message(Msg::Pyramid(pyramid_id, file_name, pyramid));
 
                 false
             }
-            Msg::Pyramid(pyramid_id, file_name, pyramid_json) => {
+            Msg::Pyramid(pyramid_id, file_name, pyramid) => {
                 web_sys::console::log_2(
                     &"Received pyramid ID and JSON".into(),
-                    &pyramid_json.to_string().into(),
+                    &serde_json::to_string(&pyramid).unwrap_or_default().into(),
                 );
+                self.uploads.remove(&file_name);
                 self.file_to_pyramid_id
                     .insert(file_name.clone(), pyramid_id.clone());
-                self.pyramid_id_to_json
-                    .insert(pyramid_id.clone(), pyramid_json.clone());
+                self.pyramid_id_to_pyramid
+                    .insert(pyramid_id.clone(), pyramid.clone());
+                self.api.seed_manifest(&pyramid_id, pyramid.clone());
+                self.poll_tiling(ctx, &pyramid_id);
                 // LONG TERM:
                 // We need to use some kind of cache system to fetch (and delete) pyramid-level images
                 // based on the user's current view. We can't just fetch all the images at once, because
                 // that would be a lot of data to transfer.
                 //
-                // And, continuously poll pyramid/<pyramid_id> to check for tiles. When THOSE are available
-                // cache them and use them instead of the pyramid images.
-                //
                 // SHORT TERM:
                 // Fetch all the pyramid level images and cache them locally when available.
                 //
-                // Example JSON (paste into separate doc and prettify)
-                // {"image_docs":[{"$oid":"6660de9402834efab622c479"},{"$oid":"6660de9402834efab622c47a"},{"$oid":"6660de9402834efab622c47b"},{"$oid":"6660de9402834efab622c47c"},{"$oid":"6660de9402834efab622c47d"},{"$oid":"6660de9402834efab622c47e"},{"$oid":"6660de9402834efab622c47f"},{"$oid":"6660de9402834efab622c480"},{"$oid":"6660de9402834efab622c481"},{"$oid":"6660de9402834efab622c482"},{"$oid":"6660de9402834efab622c483"},{"$oid":"6660de9402834efab622c484"}],"image_files":[{"$oid":"6660de9402834efab622c460"},{"$oid":"6660de9402834efab622c463"},{"$oid":"6660de9402834efab622c465"},{"$oid":"6660de9402834efab622c467"},{"$oid":"6660de9402834efab622c469"},{"$oid":"6660de9402834efab622c46b"},{"$oid":"6660de9402834efab622c46d"},{"$oid":"6660de9402834efab622c46f"},{"$oid":"6660de9402834efab622c471"},{"$oid":"6660de9402834efab622c473"},{"$oid":"6660de9402834efab622c475"},{"$oid":"6660de9402834efab622c477"}],"image_names":["1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L0","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L1","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L2","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L3","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L4","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L5","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L6","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L7","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L8","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L9","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L10","1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L11"],"image_urls":["/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L0","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L1","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L2","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L3","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L4","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L5","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L6","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L7","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L8","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L9","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L10","/api/v1/image/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221_L11"],"mime_type":"image/jpeg","tiles":"todo","url":"/api/v1/pyramid/1e1a4169-5bbf-4eed-bc3c-51a2a81d5221","uuid":"1e1a4169-5bbf-4eed-bc3c-51a2a81d5221"}
-                //
-                // Using that JSON structure we need to grab the `image_urls` and fetch the images then
-                // send Msg::PyramidLevel for each image when received.
-                let image_urls = pyramid_json.get("image_urls").unwrap().as_array().unwrap();
+                // Grab the `image_urls` and fetch the images then send Msg::PyramidLevel for each
+                // image when received.
+                let image_urls = &pyramid.image_urls;
                 self.pyramid_id_to_cached_pyramid_images
                     .insert(pyramid_id.clone(), vec![None; image_urls.len()]);
-                let window = match web_sys::window() {
-                    Some(window) => window,
-                    None => {
-                        web_sys::console::log_1(&"Failed to get window".into());
-                        return false;
-                    }
-                };
                 for (i, image_url) in image_urls.iter().enumerate() {
-                    let image_url = image_url.as_str().unwrap();
-                    let request = Request::new_with_str(image_url).unwrap();
+                    let image_url = image_url.clone();
                     let link = ctx.link().clone();
                     let pyramid_id = pyramid_id.clone();
                     let pyramid_level = i as u8;
-                    let future =
-                        wasm_bindgen_futures::JsFuture::from(window.fetch_with_request(&request));
+                    let mime_type = pyramid.mime_type.clone();
+                    let what = format!("Fetching level {} of {}", i, file_name);
                     wasm_bindgen_futures::spawn_local(async move {
-                        match future.await {
-                            Ok(response) => {
-                                let response = response
-                                    .dyn_into::<Response>()
-                                    .expect("Failed to convert response");
-                                let ab_promise = response.array_buffer().unwrap();
-                                let ab = wasm_bindgen_futures::JsFuture::from(ab_promise)
-                                    .await
-                                    .unwrap();
-                                let data = js_sys::Uint8Array::new(&ab).to_vec();
-                                let file_type = response.headers().get("Content-Type").unwrap();
-                                link.send_message(Msg::PyramidLevel(
-                                    pyramid_id,
-                                    pyramid_level,
-                                    file_type.unwrap(),
-                                    data,
-                                ));
-                            }
-                            Err(e) => {
-                                web_sys::console::log_1(&format!("Error fetching: {:?}", e).into());
-                            }
+                        match api_client::get_bytes(&image_url, &what).await {
+                            Ok((data, file_type)) => link.send_message(Msg::PyramidLevel(
+                                pyramid_id,
+                                pyramid_level,
+                                file_type.unwrap_or(mime_type),
+                                data,
+                            )),
+                            Err(e) => link.send_message(Msg::error(e)),
                         }
                     });
                 }
@@ -426,77 +402,37 @@ impl Component for App {
                 pyramid_images[pyramid_level as usize] = Some(image);
                 true
             }
+            Msg::Manifest(pyramid_id, pyramid) => {
+                let changed = self.pyramid_id_to_pyramid.get(&pyramid_id) != Some(&pyramid);
+                self.pyramid_id_to_pyramid
+                    .insert(pyramid_id.clone(), pyramid);
+                self.poll_tiling(ctx, &pyramid_id);
+                if !changed {
+                    return false;
+                }
+                // The manifest may now list tiles we can draw
+                self.render_canvas(ctx);
+                true
+            }
+            Msg::TileLoaded(tile_key, succeeded) => {
+                let tile = match self.tile_cache.remove(&tile_key) {
+                    Some(CachedTile::Loading(image, _)) if succeeded => CachedTile::Ready(image),
+                    Some(CachedTile::Loading(..)) => {
+                        web_sys::console::log_1(
+                            &format!("Failed to load tile {:?}", tile_key).into(),
+                        );
+                        CachedTile::Failed
+                    }
+                    // We stopped caring about this tile while it was loading
+                    _ => return false,
+                };
+                self.tile_cache.insert(tile_key, tile);
+                self.render_canvas(ctx);
+                false
+            }
             Msg::Loaded(file_name, file_type, data, do_post) => {
                 if do_post {
-                    // analogous curl:
-                    //      `curl --data-binary "@helldivers.jpg" -H "Content-Type: image/jpeg" -X POST http://localhost:3000/api/v1/pyramid`
-                    let data_as_uint8array = Uint8Array::from(data.as_slice());
-                    let data_as_jsvalue = JsValue::from(data_as_uint8array);
-
-                    let request = Request::new_with_str_and_init(
-                        "http://localhost:8080/api/v1/pyramid",
-                        web_sys::RequestInit::new()
-                            .method("POST")
-                            .body(Some(&data_as_jsvalue)),
-                    )
-                    .unwrap();
-                    request
-                        .headers()
-                        .append("Content-Type", &file_type.clone())
-                        .unwrap();
-                    request
-                        .headers()
-                        .append(
-                            "Content-Disposition",
-                            &format!("attachment; filename={}", file_name.clone()),
-                        )
-                        .unwrap();
-
-                    let link = ctx.link().clone();
-                    match web_sys::window() {
-                        Some(window) => {
-                            let fetch = window.fetch_with_request(&request);
-                            let future = wasm_bindgen_futures::JsFuture::from(fetch);
-                            let file_name_local = file_name.clone();
-                            wasm_bindgen_futures::spawn_local(async move {
-                                match future.await {
-                                    Ok(response) => {
-                                        let response = response
-                                            .dyn_into::<Response>()
-                                            .expect("Failed to convert response");
-                                        let json_promise = response.json().unwrap();
-                                        let json =
-                                            wasm_bindgen_futures::JsFuture::from(json_promise)
-                                                .await
-                                                .unwrap();
-                                        let pyramid_json =
-                                            json.into_serde::<serde_json::Value>().unwrap();
-                                        // get the "uuid" field from the JSON
-                                        let pyramid_id = pyramid_json
-                                            .get("uuid")
-                                            .unwrap()
-                                            .as_str()
-                                            .unwrap()
-                                            .to_string();
-
-                                        link.send_message(Msg::Pyramid(
-                                            pyramid_id,
-                                            file_name_local,
-                                            pyramid_json,
-                                        ));
-                                    }
-                                    Err(e) => {
-                                        web_sys::console::log_1(
-                                            &format!("Error fetching: {:?}", e).into(),
-                                        );
-                                    }
-                                }
-                            });
-                        }
-                        None => {
-                            web_sys::console::log_1(&"Failed to get window".into());
-                        }
-                    }
+                    self.start_upload(ctx, &file_name, &file_type, &data);
                 }
 
                 let image = HtmlImageElement::new().unwrap();
@@ -530,13 +466,14 @@ impl Component for App {
                         let link = ctx.link().clone();
                         let file_name = file_name.clone();
 
-                        gloo::file::callbacks::read_as_bytes(&file, move |res| {
-                            link.send_message(Msg::Loaded(
-                                file_name,
-                                file_type,
-                                res.expect("failed to read file"),
-                                true,
-                            ))
+                        gloo::file::callbacks::read_as_bytes(&file, move |res| match res {
+                            Ok(data) => {
+                                link.send_message(Msg::Loaded(file_name, file_type, data, true))
+                            }
+                            Err(e) => link.send_message(Msg::error(format!(
+                                "Reading {} failed: {}",
+                                file_name, e
+                            ))),
                         })
                     };
                     self.readers.insert(file_name, task);
@@ -547,15 +484,12 @@ impl Component for App {
                 if !self.current_view.is_pan_active {
                     return false;
                 }
-                let (x_unit, y_unit) = self.current_view.unit_loc;
-                let dx_unit =
-                    dx / self.current_view.zoom / self.get_canvas_ctx().unwrap().0.width() as f64;
-                let dy_unit =
-                    dy / self.current_view.zoom / self.get_canvas_ctx().unwrap().0.height() as f64;
-                let x_unit = (x_unit + dx_unit).clamp(0.0, 1.0);
-                let y_unit = (y_unit + dy_unit).clamp(0.0, 1.0);
-                self.current_view.unit_loc = (x_unit, y_unit);
-
+                self.pan_by(dx, dy);
+                self.render_canvas(ctx);
+                true
+            }
+            Msg::ViewStep((dx, dy)) => {
+                self.pan_by(dx, dy);
                 self.render_canvas(ctx);
                 true
             }
@@ -563,15 +497,70 @@ impl Component for App {
                 self.current_view.is_pan_active = is_panning;
                 true
             }
-            Msg::ViewZoom(dz) => {
-                self.current_view.zoom *= 1.0 + dz / 1000.0;
-                // Clamps to 0.01% / 10000% range
-                self.current_view.zoom = self.current_view.zoom.clamp(0.0001, 100.0);
+            Msg::ViewZoom(dz, cursor) => {
+                let zoom = self.current_view.zoom * (1.0 + dz / 1000.0);
+                let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+                self.current_view = match (self.selected_image_dims(), self.viewer_dims) {
+                    (Some(image_dims), Some(viewer_dims)) => {
+                        self.current_view
+                            .zoomed_at(zoom, cursor, image_dims, viewer_dims)
+                    }
+                    _ => View2D {
+                        zoom,
+                        ..self.current_view
+                    },
+                };
+                self.render_canvas(ctx);
+                true
+            }
+            Msg::ViewJump(unit_loc) => {
+                self.current_view.unit_loc = unit_loc;
+                self.render_canvas(ctx);
+                true
+            }
+            Msg::ViewZoomBy(factor) => {
+                self.current_view.zoom =
+                    (self.current_view.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+                self.render_canvas(ctx);
+                true
+            }
+            Msg::ViewReset => {
+                self.current_view = View2D::default();
+                self.render_canvas(ctx);
+                true
+            }
+            Msg::ViewFit => {
+                let (image_dims, viewer_dims) = match (self.selected_image_dims(), self.viewer_dims)
+                {
+                    (Some(image_dims), Some(viewer_dims)) => (image_dims, viewer_dims),
+                    _ => return false,
+                };
+                let fitted = View2D::fitted(image_dims, viewer_dims);
+                self.current_view.zoom = fitted.zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+                self.current_view.unit_loc = fitted.unit_loc;
                 self.render_canvas(ctx);
                 true
             }
             Msg::SelectImage(file_name) => {
                 web_sys::console::log_1(&format!("Selected image: {}", file_name).into());
+                // Revalidate the manifest, in case tiles have become available since we last
+                // looked. The client coalesces repeat requests, so rapid switching is cheap.
+                if let Some(pyramid_id) = self.file_to_pyramid_id.get(&file_name) {
+                    let link = ctx.link().clone();
+                    let pyramid_id_moveable = pyramid_id.clone();
+                    self.api.fetch_manifest(
+                        pyramid_id,
+                        Callback::from(move |result| match result {
+                            Ok(pyramid) => link
+                                .send_message(Msg::Manifest(pyramid_id_moveable.clone(), pyramid)),
+                            Err(e) => link.send_message(Msg::error(e)),
+                        }),
+                    );
+                }
+                // Only keep tiles for the image we're looking at
+                let selected_pyramid_id = self.file_to_pyramid_id.get(&file_name);
+                self.tile_cache
+                    .retain(|key, _| Some(&key.pyramid_id) == selected_pyramid_id);
                 self.selected_image = Some(file_name);
                 // Delete the pyramid-level images that we don't need anymore, and cache the
                 // pyramid-level images for the currently-selected image.
@@ -579,23 +568,80 @@ impl Component for App {
                 self.render_canvas(ctx);
                 true
             }
+            Msg::UploadProgress(file_name, sent, total) => match self.uploads.get_mut(&file_name) {
+                Some(upload) if total > 0.0 => {
+                    upload.sent = Some(sent / total);
+                    true
+                }
+                _ => false,
+            },
+            Msg::UploadFailed(file_name, error) => {
+                self.uploads.remove(&file_name);
+                self.failed_uploads.insert(file_name);
+                self.toasts.push(ToastLevel::Error, error);
+                true
+            }
+            Msg::PollTiling(pyramid_id) => {
+                self.polling.remove(&pyramid_id);
+                let link = ctx.link().clone();
+                let pyramid_id_moveable = pyramid_id.clone();
+                self.api.fetch_manifest(
+                    &pyramid_id,
+                    Callback::from(move |result| match result {
+                        Ok(pyramid) => {
+                            link.send_message(Msg::Manifest(pyramid_id_moveable.clone(), pyramid))
+                        }
+                        Err(e) => link.send_message(Msg::error(e)),
+                    }),
+                );
+                false
+            }
+            Msg::Notify(level, message) => {
+                let id = self.toasts.push(level, message);
+                if level != ToastLevel::Error {
+                    let link = ctx.link().clone();
+                    Timeout::new(toasts::INFO_TIMEOUT_MS, move || {
+                        link.send_message(Msg::DismissToast(id))
+                    })
+                    .forget();
+                }
+                true
+            }
+            Msg::DismissToast(id) => self.toasts.dismiss(id),
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         html! {
             <div id="wrapper">
+                <ToastList
+                    toasts={self.toasts.list()}
+                    on_dismiss={ctx.link().callback(Msg::DismissToast)}
+                />
                 <div id="viewer-area">
                     <div class="info">
                         <p id="title">{ "Image Viewer" }</p>
-                        <p>{ "A Rust/Wasm based image viewer. Upload or select an image, then use the mouse wheel to zoom, and click and drag to pan." }</p>
+                        <p>{ "A Rust/Wasm based image viewer. Upload or select an image, then use the mouse wheel to zoom, and click and drag to pan. Click or drag on the overview in the corner to jump elsewhere. The arrow keys pan too, +/- zoom, 0 resets the view, and f fits the image to the viewer." }</p>
+                    </div>
+                    <div id="toolbar">
+                        <button title="Pan left (\u{2190})" onclick={ctx.link().callback(|_| Msg::ViewStep((-PAN_STEP, 0.0)))}>{ "\u{2190}" }</button>
+                        <button title="Pan up (\u{2191})" onclick={ctx.link().callback(|_| Msg::ViewStep((0.0, -PAN_STEP)))}>{ "\u{2191}" }</button>
+                        <button title="Pan down (\u{2193})" onclick={ctx.link().callback(|_| Msg::ViewStep((0.0, PAN_STEP)))}>{ "\u{2193}" }</button>
+                        <button title="Pan right (\u{2192})" onclick={ctx.link().callback(|_| Msg::ViewStep((PAN_STEP, 0.0)))}>{ "\u{2192}" }</button>
+                        <button title="Zoom in (+)" onclick={ctx.link().callback(|_| Msg::ViewZoomBy(ZOOM_STEP))}>{ "+" }</button>
+                        <button title="Zoom out (-)" onclick={ctx.link().callback(|_| Msg::ViewZoomBy(1.0 / ZOOM_STEP))}>{ "\u{2212}" }</button>
+                        <button title="Reset view (0)" onclick={ctx.link().callback(|_| Msg::ViewReset)}>{ "Reset" }</button>
+                        <button title="Fit to viewer (f)" onclick={ctx.link().callback(|_| Msg::ViewFit)}>{ "Fit" }</button>
                     </div>
                     <div class="content">
                         <canvas
                             id="viewer-canvas"
                             onwheel={ctx.link().callback(|event: WheelEvent| {
                                 event.prevent_default();
-                                Msg::ViewZoom(-event.delta_y())
+                                Msg::ViewZoom(
+                                    -event.delta_y(),
+                                    (event.offset_x() as f64, event.offset_y() as f64),
+                                )
                             })}
                             onmousedown={ctx.link().callback(|_| Msg::ViewPanState(true))}
                             onmouseup={ctx.link().callback(|_| Msg::ViewPanState(false))}
@@ -605,6 +651,11 @@ impl Component for App {
                                 Msg::ViewPan((-event.movement_x() as f64, -event.movement_y() as f64))
                             })}
                         />
+                        <Minimap
+                            image={self.minimap_image()}
+                            viewport={self.viewport()}
+                            on_jump={ctx.link().callback(Msg::ViewJump)}
+                        />
                     </div>
                 </div>
 
@@ -653,6 +704,39 @@ impl Component for App {
 }
 
 impl App {
+    /// What a key press does to the view, if anything. Presses with modifiers are left to the
+    /// browser, so e.g. Ctrl and + still zooms the page
+    fn key_to_msg(event: &KeyboardEvent) -> Option<Msg> {
+        if event.ctrl_key() || event.meta_key() || event.alt_key() {
+            return None;
+        }
+        Some(match event.key().as_str() {
+            "ArrowLeft" => Msg::ViewStep((-PAN_STEP, 0.0)),
+            "ArrowRight" => Msg::ViewStep((PAN_STEP, 0.0)),
+            "ArrowUp" => Msg::ViewStep((0.0, -PAN_STEP)),
+            "ArrowDown" => Msg::ViewStep((0.0, PAN_STEP)),
+            "+" | "=" => Msg::ViewZoomBy(ZOOM_STEP),
+            "-" | "_" => Msg::ViewZoomBy(1.0 / ZOOM_STEP),
+            "0" => Msg::ViewReset,
+            "f" | "F" => Msg::ViewFit,
+            _ => return None,
+        })
+    }
+
+    /// Move the view by `dx` and `dy` canvas pixels
+    fn pan_by(&mut self, dx: f64, dy: f64) {
+        let canvas = match self.get_canvas_ctx() {
+            Ok((canvas, _)) => canvas,
+            Err(_) => return,
+        };
+        let (x_unit, y_unit) = self.current_view.unit_loc;
+        let dx_unit = dx / self.current_view.zoom / canvas.width() as f64;
+        let dy_unit = dy / self.current_view.zoom / canvas.height() as f64;
+        let x_unit = (x_unit + dx_unit).clamp(0.0, 1.0);
+        let y_unit = (y_unit + dy_unit).clamp(0.0, 1.0);
+        self.current_view.unit_loc = (x_unit, y_unit);
+    }
+
     fn get_canvas_ctx(&self) -> Result<(HtmlCanvasElement, CanvasRenderingContext2d), ()> {
         let canvas = web_sys::window()
             .ok_or(())?
@@ -705,7 +789,206 @@ impl App {
         None
     }
 
-    fn render_canvas(&self, _ctx: &Context<Self>) {
+    /// The image to show in the minimap: the smallest pyramid level we have for the selected image,
+    /// or the image itself if we have none
+    fn minimap_image(&self) -> Option<HtmlImageElement> {
+        let selected_image = self.selected_image.as_ref()?;
+        let smallest_level = self
+            .file_to_pyramid_id
+            .get(selected_image)
+            .and_then(|pyramid_id| self.pyramid_id_to_cached_pyramid_images.get(pyramid_id))
+            .and_then(|images| images.iter().rev().flatten().next());
+        match smallest_level {
+            Some(image) => Some(image.clone()),
+            None => self
+                .files
+                .iter()
+                .find(|file| file.name == *selected_image)
+                .map(|file| file.image.clone()),
+        }
+    }
+
+    /// Full-resolution dimensions of the selected image, once it has loaded
+    fn selected_image_dims(&self) -> Option<Dims> {
+        let selected_image = self.selected_image.as_ref()?;
+        let image = &self
+            .files
+            .iter()
+            .find(|file| file.name == *selected_image)?
+            .image;
+        if image.width() == 0 || image.height() == 0 {
+            return None;
+        }
+        Some(Dims {
+            w: image.width() as f64,
+            h: image.height() as f64,
+        })
+    }
+
+    /// The part of the selected image that's in view, in unit coordinates
+    fn viewport(&self) -> Option<Roi2D> {
+        Some(
+            self.current_view
+                .viewport(self.selected_image_dims()?, self.viewer_dims?),
+        )
+    }
+
+    /// Start fetching the given tile, if we haven't already
+    fn request_tile(&mut self, ctx: &Context<Self>, key: &TileKey, tile: &TileInfo) {
+        if self.tile_cache.contains_key(key) {
+            return;
+        }
+        let image = match HtmlImageElement::new() {
+            Ok(image) => image,
+            Err(_) => return,
+        };
+        let on_load = {
+            let link = ctx.link().clone();
+            let key = key.clone();
+            EventListener::once(&image, "load", move |_| {
+                link.send_message(Msg::TileLoaded(key, true))
+            })
+        };
+        let on_error = {
+            let link = ctx.link().clone();
+            let key = key.clone();
+            EventListener::once(&image, "error", move |_| {
+                link.send_message(Msg::TileLoaded(key, false))
+            })
+        };
+        image.set_src(&format!("{}/image/{}", api_client::API_ROOT, tile.name));
+        self.tile_cache
+            .insert(key.clone(), CachedTile::Loading(image, [on_load, on_error]));
+    }
+
+    /// Draw whichever tiles of the appropriate pyramid level are visible and loaded, and start
+    /// fetching the visible ones that aren't.
+    ///
+    /// Returns the number of visible tiles, and how many of those were drawn, or `None` if the
+    /// pyramid hasn't been tiled yet.
+    fn render_tiles(
+        &mut self,
+        ctx: &Context<Self>,
+        canvas_ctx: &CanvasRenderingContext2d,
+        pyramid_id: &str,
+        dest_dims: Dims,
+    ) -> Option<(usize, usize)> {
+        let pyramid = self.pyramid_id_to_pyramid.get(pyramid_id)?;
+        let level_count = pyramid.tiles.levels()?.len();
+        if level_count == 0 {
+            return None;
+        }
+        let (level, _) = level_and_relative_zoom_for(self.current_view.zoom);
+        let level = (level as usize).min(level_count - 1);
+        let (l0_dims, _) = level_tiles(pyramid, 0)?;
+        let (level_dims, tiles) = level_tiles(pyramid, level)?;
+
+        // Work out the view in terms of this level's pixels. Levels needn't be exactly half the
+        // size of the one above, so derive the zoom from actual dimensions
+        let level_view = View2D {
+            zoom: self.current_view.zoom * l0_dims.w / level_dims.w,
+            ..self.current_view
+        };
+        let CanvasRoiPair { s, d } = level_view.to_roi(level_dims, dest_dims, false);
+        let scale = level_view.zoom;
+
+        let visible = tiles
+            .into_iter()
+            .filter(|t| t.intersects(&s))
+            .collect::<Vec<_>>();
+        let mut drawn = 0;
+        for tile in visible.iter() {
+            let key = TileKey {
+                pyramid_id: pyramid_id.to_string(),
+                level: level as u32,
+                x: tile.roi.x as u32,
+                y: tile.roi.y as u32,
+            };
+            match self.tile_cache.get(&key) {
+                Some(CachedTile::Ready(image)) => {
+                    // Tiles keep the same offset from the source ROI in destination space
+                    let dx = d.x + (tile.roi.x - s.x) * scale;
+                    let dy = d.y + (tile.roi.y - s.y) * scale;
+                    match canvas_ctx.draw_image_with_html_image_element_and_dw_and_dh(
+                        image,
+                        dx,
+                        dy,
+                        tile.roi.w * scale,
+                        tile.roi.h * scale,
+                    ) {
+                        Ok(_) => drawn += 1,
+                        Err(e) => {
+                            web_sys::console::log_1(&format!("Error drawing tile: {:?}", e).into());
+                        }
+                    }
+                }
+                Some(_) => {}
+                None => self.request_tile(ctx, &key, tile),
+            }
+        }
+        self.prefetch_tiles(ctx, pyramid_id, level, &s);
+        Some((visible.len(), drawn))
+    }
+
+    /// Start fetching the tiles the user is likely to want next, so that panning and zooming
+    /// don't wait on the network: the ring of tiles around the visible ROI on the current level,
+    /// and the tiles covering the same ROI one level up and one level down.
+    ///
+    /// `roi` is the visible region, in pixels of `level`. Call this after requesting the visible
+    /// tiles, so those are fetched first.
+    fn prefetch_tiles(&mut self, ctx: &Context<Self>, pyramid_id: &str, level: usize, roi: &Roi2D) {
+        let pyramid = match self.pyramid_id_to_pyramid.get(pyramid_id) {
+            Some(pyramid) => pyramid,
+            None => return,
+        };
+        let level_count = pyramid.tiles.levels().map_or(0, |levels| levels.len());
+        let (level_dims, tiles) = match level_tiles(pyramid, level) {
+            Some(level) => level,
+            None => return,
+        };
+
+        // Adjacent tiles on this level. Growing the ROI by one tile in each direction reaches
+        // exactly one ring further out
+        let (tile_w, tile_h) = tiles.iter().fold((0.0_f64, 0.0_f64), |(w, h), t| {
+            (w.max(t.roi.w), h.max(t.roi.h))
+        });
+        let ring = roi.grown(tile_w, tile_h);
+        let mut wanted = tiles
+            .into_iter()
+            .filter(|t| t.intersects(&ring) && !t.intersects(roi))
+            .map(|t| (level, t))
+            .collect::<Vec<_>>();
+
+        // The same region, on the levels we'd switch to when zooming in or out
+        let neighbors = [level.checked_sub(1), Some(level + 1)];
+        for other in neighbors.into_iter().flatten() {
+            if other >= level_count {
+                continue;
+            }
+            if let Some((other_dims, other_tiles)) = level_tiles(pyramid, other) {
+                let other_roi =
+                    roi.scaled(other_dims.w / level_dims.w, other_dims.h / level_dims.h);
+                wanted.extend(
+                    other_tiles
+                        .into_iter()
+                        .filter(|t| t.intersects(&other_roi))
+                        .map(|t| (other, t)),
+                );
+            }
+        }
+
+        for (level, tile) in wanted {
+            let key = TileKey {
+                pyramid_id: pyramid_id.to_string(),
+                level: level as u32,
+                x: tile.roi.x as u32,
+                y: tile.roi.y as u32,
+            };
+            self.request_tile(ctx, &key, &tile);
+        }
+    }
+
+    fn render_canvas(&mut self, ctx: &Context<Self>) {
         let (canvas, canvas_ctx) = match self.get_canvas_ctx() {
             Ok((canvas, ctx)) => (canvas, ctx),
             Err(_) => return,
@@ -752,6 +1035,7 @@ impl App {
             w: canvas.width() as f64,
             h: canvas.height() as f64,
         };
+        self.viewer_dims = Some(dest_dims);
         let CanvasRoiPair {
             s:
                 Roi2D {
@@ -769,6 +1053,7 @@ impl App {
                 },
         } = current_view.to_roi(src_dims, dest_dims, use_relative_zoom);
 
+        // Whole pyramid levels are drawn first, as a backdrop for any tiles still in flight
         match canvas_ctx
             .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
                 image, sx, sy, sw, sh, dx, dy, dw, dh,
@@ -779,12 +1064,17 @@ impl App {
             }
         }
 
+        let tile_counts = match self.file_to_pyramid_id.get(selected_image).cloned() {
+            Some(pyramid_id) => self.render_tiles(ctx, &canvas_ctx, &pyramid_id, dest_dims),
+            None => None,
+        };
+
         let (level, relative_zoom) = level_and_relative_zoom_for(current_view.zoom);
         let effective_zoom = current_view.zoom;
 
         // Info display should eventually be refactored.
         canvas_ctx.set_fill_style(&"black".into());
-        canvas_ctx.fill_rect(0.0, 0.0, 225.0, 60.0);
+        canvas_ctx.fill_rect(0.0, 0.0, 225.0, 75.0);
         canvas_ctx.set_fill_style(&"white".into());
         canvas_ctx.set_font("14px Courier New"); // Larger font size
         match canvas_ctx.fill_text(&format!("Level:          {}", level), 10.0, 15.0) {
@@ -813,6 +1103,16 @@ impl App {
                 web_sys::console::log_1(&format!("Error drawing text: {:?}", e).into());
             }
         }
+        let tiles_text = match tile_counts {
+            Some((visible, drawn)) => format!("Tiles:          {}/{}", drawn, visible),
+            None => "Tiles:          n/a".to_string(),
+        };
+        match canvas_ctx.fill_text(&tiles_text, 10.0, 60.0) {
+            Ok(_) => {}
+            Err(e) => {
+                web_sys::console::log_1(&format!("Error drawing text: {:?}", e).into());
+            }
+        }
     }
 
     fn preview_file(&self, ctx: &Context<Self>, file: &FileDetails) -> Html {
@@ -825,6 +1125,7 @@ impl App {
         } else {
             "preview-tile"
         };
+        let status = self.file_status(&file.name);
         html! {
             <div
                 class={class_str}
@@ -835,16 +1136,127 @@ impl App {
                     })
                 }
             >
+                <span class={status.class()}>
+                    { status.label() }
+                    if let FileStatus::Uploading(Some(sent)) = status {
+                        <progress max="1" value={sent.to_string()} />
+                    }
+                </span>
                 <p class="preview-name">{ format!("{}", file.name) }</p>
                 <div class="preview-media">
                     if file.file_type.contains("image") {
-                        <img src={format!("data:{};base64,{}", file.file_type, STANDARD.encode(&file.data))} />
+                        <img src={self.preview_src(file)} />
                     }
                 </div>
             </div>
         }
     }
 
+    /// Where to load a file's preview from. Once the server has a pyramid for the file, ask it for
+    /// a thumbnail rather than embedding the full-resolution data in the page.
+    fn preview_src(&self, file: &FileDetails) -> String {
+        match self.file_to_pyramid_id.get(&file.name) {
+            Some(pyramid_id) => format!(
+                "{}/image/{}_L0/thumbnail?max={}",
+                api_client::API_ROOT,
+                pyramid_id,
+                PREVIEW_SIZE
+            ),
+            None => format!(
+                "data:{};base64,{}",
+                file.file_type,
+                STANDARD.encode(&file.data)
+            ),
+        }
+    }
+
+    /// Start sending a file to the server, to be built into a pyramid. Progress, and the result,
+    /// come back as messages
+    fn start_upload(&mut self, ctx: &Context<Self>, file_name: &str, file_type: &str, data: &[u8]) {
+        let on_progress = {
+            let file_name = file_name.to_string();
+            ctx.link().callback(move |(sent, total): (f64, f64)| {
+                Msg::UploadProgress(file_name.clone(), sent, total)
+            })
+        };
+        let on_done = {
+            let link = ctx.link().clone();
+            let file_name = file_name.to_string();
+            Callback::from(move |result: Result<Pyramid, String>| match result {
+                Ok(pyramid) => {
+                    link.send_message(Msg::Notify(
+                        ToastLevel::Info,
+                        format!("Uploaded {}", file_name),
+                    ));
+                    link.send_message(Msg::Pyramid(
+                        pyramid.uuid.clone(),
+                        file_name.clone(),
+                        pyramid,
+                    ));
+                }
+                Err(e) => link.send_message(Msg::UploadFailed(file_name.clone(), e)),
+            })
+        };
+
+        self.failed_uploads.remove(file_name);
+        match api_client::upload_pyramid(file_name, file_type, data, on_progress, on_done) {
+            Ok(upload) => {
+                self.uploads.insert(
+                    file_name.to_string(),
+                    PendingUpload {
+                        _upload: upload,
+                        sent: None,
+                    },
+                );
+            }
+            Err(e) => {
+                self.failed_uploads.insert(file_name.to_string());
+                self.toasts.push(ToastLevel::Error, e);
+            }
+        }
+    }
+
+    /// Check on a pyramid again in a while, if the server is still tiling it. At most one check
+    /// per pyramid is pending at a time
+    fn poll_tiling(&mut self, ctx: &Context<Self>, pyramid_id: &str) {
+        let tiling = matches!(
+            self.pyramid_id_to_pyramid.get(pyramid_id).map(|p| &p.tiles),
+            Some(PyramidTiles::Status(
+                TilingStatus::Todo | TilingStatus::Processing
+            ))
+        );
+        if !tiling || !self.polling.insert(pyramid_id.to_string()) {
+            return;
+        }
+        let link = ctx.link().clone();
+        let pyramid_id = pyramid_id.to_string();
+        Timeout::new(TILING_POLL_MS, move || {
+            link.send_message(Msg::PollTiling(pyramid_id))
+        })
+        .forget();
+    }
+
+    /// How far along a file is, for its preview's badge
+    fn file_status(&self, file_name: &str) -> FileStatus {
+        if let Some(upload) = self.uploads.get(file_name) {
+            return FileStatus::Uploading(upload.sent);
+        }
+        if self.failed_uploads.contains(file_name) {
+            return FileStatus::Failed;
+        }
+        let pyramid = self
+            .file_to_pyramid_id
+            .get(file_name)
+            .and_then(|pyramid_id| self.pyramid_id_to_pyramid.get(pyramid_id));
+        match pyramid.map(|p| &p.tiles) {
+            // Read, but the server hasn't answered yet
+            None => FileStatus::Uploading(None),
+            Some(PyramidTiles::Levels(_)) => FileStatus::Ready,
+            Some(PyramidTiles::Status(TilingStatus::Failed)) => FileStatus::Failed,
+            Some(PyramidTiles::Status(_)) => FileStatus::Processing,
+        }
+    }
+
     fn upload_files(files: Option<FileList>) -> Msg {
         let mut result = Vec::new();
 
@@ -0,0 +1,194 @@
+//! An overview of the whole image, with a rectangle showing which part of it is in view. Clicking
+//! or dragging on it moves the view there.
+
+use gloo::events::EventListener;
+use web_sys::{
+    wasm_bindgen::JsCast, CanvasRenderingContext2d, HtmlCanvasElement, HtmlImageElement,
+};
+use yew::{html, Callback, Component, Context, Html, MouseEvent, NodeRef, Properties};
+
+use crate::view::{Dims, Roi2D};
+
+#[derive(Properties, PartialEq)]
+pub struct MinimapProps {
+    /// The image to show, usually the smallest level of a pyramid. Nothing is shown without one
+    pub image: Option<HtmlImageElement>,
+
+    /// The part of the image in view, in unit coordinates
+    pub viewport: Option<Roi2D>,
+
+    /// Called with the unit coordinates the user clicked or dragged to
+    pub on_jump: Callback<(f64, f64)>,
+}
+
+pub enum MinimapMsg {
+    DragState(bool),
+    /// The mouse is at the given canvas coordinates
+    Pointer(f64, f64),
+    /// The image has finished loading, so can be drawn
+    ImageLoaded,
+}
+
+pub struct Minimap {
+    canvas: NodeRef,
+    is_drag_active: bool,
+    /// Redraws once the image loads, if it hadn't when we were given it
+    on_load: Option<EventListener>,
+}
+
+impl Minimap {
+    fn watch_image(&mut self, ctx: &Context<Self>) {
+        self.on_load = ctx
+            .props()
+            .image
+            .as_ref()
+            .filter(|image| !image.complete())
+            .map(|image| {
+                let link = ctx.link().clone();
+                EventListener::once(image, "load", move |_| {
+                    link.send_message(MinimapMsg::ImageLoaded)
+                })
+            });
+    }
+
+    /// Where `image` is drawn on the canvas, or `None` if it can't be (yet)
+    fn image_roi(&self, image: &HtmlImageElement) -> Option<Roi2D> {
+        let canvas = self.canvas.cast::<HtmlCanvasElement>()?;
+        if image.width() == 0 || image.height() == 0 || canvas.width() == 0 {
+            return None;
+        }
+        Some(Roi2D::fitted(
+            Dims {
+                w: image.width() as f64,
+                h: image.height() as f64,
+            },
+            Dims {
+                w: canvas.width() as f64,
+                h: canvas.height() as f64,
+            },
+        ))
+    }
+
+    fn draw(&self, ctx: &Context<Self>) {
+        let (canvas, image) = match (
+            self.canvas.cast::<HtmlCanvasElement>(),
+            ctx.props().image.as_ref(),
+        ) {
+            (Some(canvas), Some(image)) => (canvas, image),
+            _ => return,
+        };
+        let canvas_ctx = match canvas
+            .get_context("2d")
+            .ok()
+            .flatten()
+            .and_then(|c| c.dyn_into::<CanvasRenderingContext2d>().ok())
+        {
+            Some(canvas_ctx) => canvas_ctx,
+            None => return,
+        };
+
+        canvas.set_width(canvas.offset_width() as u32);
+        canvas.set_height(canvas.offset_height() as u32);
+        canvas_ctx.clear_rect(0.0, 0.0, canvas.width() as f64, canvas.height() as f64);
+
+        let roi = match self.image_roi(image) {
+            Some(roi) => roi,
+            None => return,
+        };
+        if let Err(e) = canvas_ctx
+            .draw_image_with_html_image_element_and_dw_and_dh(image, roi.x, roi.y, roi.w, roi.h)
+        {
+            web_sys::console::log_1(&format!("Error drawing minimap: {:?}", e).into());
+        }
+
+        if let Some(viewport) = ctx.props().viewport {
+            canvas_ctx.set_stroke_style(&"red".into());
+            canvas_ctx.set_line_width(2.0);
+            canvas_ctx.stroke_rect(
+                roi.x + viewport.x * roi.w,
+                roi.y + viewport.y * roi.h,
+                viewport.w * roi.w,
+                viewport.h * roi.h,
+            );
+        }
+    }
+}
+
+impl Component for Minimap {
+    type Message = MinimapMsg;
+    type Properties = MinimapProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut minimap = Self {
+            canvas: NodeRef::default(),
+            is_drag_active: false,
+            on_load: None,
+        };
+        minimap.watch_image(ctx);
+        minimap
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            MinimapMsg::DragState(is_dragging) => {
+                self.is_drag_active = is_dragging;
+                false
+            }
+            MinimapMsg::Pointer(x, y) => {
+                if !self.is_drag_active {
+                    return false;
+                }
+                let roi = match ctx.props().image.as_ref().and_then(|i| self.image_roi(i)) {
+                    Some(roi) => roi,
+                    None => return false,
+                };
+                ctx.props().on_jump.emit((
+                    ((x - roi.x) / roi.w).clamp(0.0, 1.0),
+                    ((y - roi.y) / roi.h).clamp(0.0, 1.0),
+                ));
+                false
+            }
+            MinimapMsg::ImageLoaded => {
+                self.on_load = None;
+                self.draw(ctx);
+                false
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>, old_props: &Self::Properties) -> bool {
+        if ctx.props().image != old_props.image {
+            self.watch_image(ctx);
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if ctx.props().image.is_none() {
+            return html! {};
+        }
+        html! {
+            <canvas
+                id="minimap-canvas"
+                ref={self.canvas.clone()}
+                onmousedown={ctx.link().batch_callback(|event: MouseEvent| {
+                    event.prevent_default();
+                    vec![
+                        MinimapMsg::DragState(true),
+                        MinimapMsg::Pointer(event.offset_x() as f64, event.offset_y() as f64),
+                    ]
+                })}
+                onmouseup={ctx.link().callback(|_| MinimapMsg::DragState(false))}
+                onmouseleave={ctx.link().callback(|_| MinimapMsg::DragState(false))}
+                onmousemove={ctx.link().callback(|event: MouseEvent| {
+                    event.prevent_default();
+                    MinimapMsg::Pointer(event.offset_x() as f64, event.offset_y() as f64)
+                })}
+            />
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        self.draw(ctx);
+    }
+}
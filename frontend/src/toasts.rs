@@ -0,0 +1,95 @@
+//! Messages for the user, shown as toasts in the corner of the page. Errors stay up until they're
+//! dismissed; other messages go away on their own after a few seconds.
+//!
+//! Everything shown is logged to the console too, so nothing is lost once a toast is gone.
+
+use std::collections::VecDeque;
+
+use yew::{function_component, html, Callback, Html, MouseEvent, Properties};
+
+/// Most toasts shown at once. The oldest are dropped to make room for new ones
+pub const MAX_TOASTS: usize = 5;
+
+/// How long toasts that aren't errors stay up, in milliseconds
+pub const INFO_TIMEOUT_MS: u32 = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Toast {
+    /// Identifies the toast, for dismissing it
+    pub id: u64,
+    pub level: ToastLevel,
+    pub message: String,
+}
+
+/// The toasts currently shown, oldest first
+#[derive(Default)]
+pub struct Toasts {
+    next_id: u64,
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    /// Show `message`, returning the new toast's ID
+    pub fn push(&mut self, level: ToastLevel, message: String) -> u64 {
+        match level {
+            ToastLevel::Info => web_sys::console::log_1(&message.as_str().into()),
+            ToastLevel::Error => web_sys::console::error_1(&message.as_str().into()),
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push_back(Toast { id, level, message });
+        while self.queue.len() > MAX_TOASTS {
+            self.queue.pop_front();
+        }
+        id
+    }
+
+    /// Stop showing the toast with the given ID. Returns whether it was still shown
+    pub fn dismiss(&mut self, id: u64) -> bool {
+        let before = self.queue.len();
+        self.queue.retain(|t| t.id != id);
+        self.queue.len() != before
+    }
+
+    pub fn list(&self) -> Vec<Toast> {
+        self.queue.iter().cloned().collect()
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ToastListProps {
+    pub toasts: Vec<Toast>,
+
+    /// Called with the ID of a toast the user closed
+    pub on_dismiss: Callback<u64>,
+}
+
+#[function_component]
+pub fn ToastList(props: &ToastListProps) -> Html {
+    html! {
+        <div id="toasts">
+            { for props.toasts.iter().map(|toast| {
+                let class = match toast.level {
+                    ToastLevel::Info => "toast info",
+                    ToastLevel::Error => "toast error",
+                };
+                let id = toast.id;
+                let on_dismiss = props.on_dismiss.clone();
+                html! {
+                    <div class={class} key={id}>
+                        <p>{ toast.message.clone() }</p>
+                        <button title="Dismiss" onclick={Callback::from(move |_: MouseEvent| on_dismiss.emit(id))}>
+                            { "\u{00d7}" }
+                        </button>
+                    </div>
+                }
+            }) }
+        </div>
+    }
+}
@@ -0,0 +1,253 @@
+//! Where things go on the viewer's canvases: the view's location and zoom, and the regions of
+//! images and canvases they map to.
+
+/// A region of interest (ROI) in some target 2D coordinate space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Roi2D {
+    /// The top-left corner
+    pub x: f64,
+    /// The top-left corner
+    pub y: f64,
+    /// The width
+    pub w: f64,
+    /// The height
+    pub h: f64,
+}
+
+impl Roi2D {
+    /// This ROI, extended by `dx` on the left and right, and `dy` on the top and bottom
+    pub fn grown(&self, dx: f64, dy: f64) -> Roi2D {
+        Roi2D {
+            x: self.x - dx,
+            y: self.y - dy,
+            w: self.w + 2.0 * dx,
+            h: self.h + 2.0 * dy,
+        }
+    }
+
+    /// This ROI, in a coordinate space `sx` times as wide and `sy` times as tall
+    pub fn scaled(&self, sx: f64, sy: f64) -> Roi2D {
+        Roi2D {
+            x: self.x * sx,
+            y: self.y * sy,
+            w: self.w * sx,
+            h: self.h * sy,
+        }
+    }
+
+    /// Where an image with dimensions `src` goes when scaled to fit `dest`, keeping its aspect
+    /// ratio, and centered
+    pub fn fitted(src: Dims, dest: Dims) -> Roi2D {
+        let scale = (dest.w / src.w).min(dest.h / src.h);
+        let (w, h) = (src.w * scale, src.h * scale);
+        Roi2D {
+            x: 0.5 * (dest.w - w),
+            y: 0.5 * (dest.h - h),
+            w,
+            h,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct View2D {
+    /// (x, y) - The _center_ of the view, in unit coordinates.
+    ///
+    /// (0.0, 0.0) is the top-left corner of the image, and (1.0, 1.0) is the bottom-right corner.
+    pub unit_loc: (f64, f64),
+
+    /// The zoom level of our view.
+    pub zoom: f64,
+
+    /// You have three guesses to figure out what this is for, and the first two guesses do not count.
+    pub is_pan_active: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Dims {
+    pub w: f64,
+    pub h: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CanvasRoiPair {
+    pub s: Roi2D,
+    pub d: Roi2D,
+}
+
+/// Gets the pyramid level and re-scaled zoom factor, for the given effective zoom
+///
+/// 1.0 means full resolution, and 2.0 means we are zoomed in.
+/// For every factor of half, we should increase pyramid level by 1. Anything above 1.0
+/// should be considered as zoomed in.
+///
+/// Rescaled zoom means "relative zoom you need to use on the returned pyramid level to
+/// achieve the given effective zoom"
+pub fn level_and_relative_zoom_for(effective_zoom: f64) -> (u16, f64) {
+    let effective_zoom = effective_zoom + f64::EPSILON; // Prevent dbz
+                                                        // This computes the nearest _larger_ pyramid level, so that the browser only downsamples
+                                                        // pyramid levels. We should only upsample on canvas when the user zooms into L0.
+    let level = (1.0 / effective_zoom).log2().floor() as u16;
+    // Compute how to achieve the desired effective zoom based on the level we've chosen
+    let level_zoom = 0.5_f64.powi(level as i32);
+    let relative_zoom = effective_zoom / level_zoom;
+    (level, relative_zoom)
+}
+
+impl View2D {
+    /// Convert the given view into a source ROI, and destination ROI
+    ///
+    /// This function accounts for relative aspect ratios and zoom level to determine the appropriate
+    /// (sx, sy, sw, sh) and (dx, dy, dw, dh) values for the view.
+    ///
+    /// The source dimensions are the size of the actual image data to be sampled, not necessarily the
+    /// original dimensions. That is, for low zoom levels the source dimensions may have been subsampled
+    /// using a mipmap / image pyramid.
+    ///
+    /// The dest dimensions are the size of the canvas onto which the source is to be drawn. Most often
+    /// these are scaled to the size of the original image, but that is not guaranteed on all viewports.
+    ///
+    /// This function accounts for mipmap subsampling by re-scaling the zoom factor to to be in terms of
+    /// the image pyramid level that was used. If zoom is 0.5, the re-scaled zoom factor is 1.0, meaning
+    /// the source image should be shown 1:1 in destination space. But if zoom is 0.6, the re-scaled zoom
+    /// becomes 1.2, as the L1 pyramid was used, and that needs to be upsampled by 20% (6:5 ratio) in
+    /// destination space to achieve a final effective zoom of 0.6.
+    ///
+    /// Finally, this function accounts for the aspect ratio of source and destination to determine what
+    /// can (and can't) be shown in destination space. Based on the source dimensions and re-scaled zoom
+    /// factor, we are able to determine whether the entire source image can be shown in the destination.
+    /// If it can't, the source ROI is cropped to fit within the aspect ratio of the destination.
+    ///
+    /// This function ensures that [`View2D::unit_loc`], remains in the center of the destination
+    ///
+    /// # Returns
+    /// A tuple of [`Roi2D`] structures
+    ///
+    /// # Notes
+    /// - See: https://developer.mozilla.org/en-US/docs/Web/API/CanvasRenderingContext2D/drawImage
+    ///   for explanation of values
+    pub fn to_roi(
+        self,
+        Dims { w: src_w, h: src_h }: Dims,
+        Dims {
+            w: dest_w,
+            h: dest_h,
+        }: Dims,
+        use_relative_zoom: bool,
+    ) -> CanvasRoiPair {
+        let (_, relative_zoom) = if use_relative_zoom {
+            level_and_relative_zoom_for(self.zoom)
+        } else {
+            (0u16, self.zoom)
+        };
+        web_sys::console::log_1(
+            &format!("Relative zoom: {}, effective: {}", relative_zoom, self.zoom).into(),
+        );
+        web_sys::console::log_1(
+            &format!(
+                "Src dims: ({}, {}), dest dims: ({}, {})",
+                src_w, src_h, dest_w, dest_h
+            )
+            .into(),
+        );
+        // Center of view in source image coordinates
+        let csx = self.unit_loc.0 * src_w;
+        let csy = self.unit_loc.1 * src_h;
+        // ... scaled to dest space based on relative zoom
+        let csxz = csx * relative_zoom;
+        let csyz = csy * relative_zoom;
+        // Center of the destination canvas (NOT the view). This is where we want to PUT the center
+        // of the view
+        let center_x_d = 0.5 * dest_w;
+        let center_y_d = 0.5 * dest_h;
+        // Origin of source image in destination space
+        let sxd = center_x_d - csxz;
+        let syd = center_y_d - csyz;
+        // dx, dy need to get as close to sxd, syd as possible, within canvas bounds
+        let dx = sxd.max(0.0).min(dest_w);
+        let dy = syd.max(0.0).min(dest_h);
+        // dw, dh can be computed based on the difference
+        let swz = src_w * relative_zoom;
+        let shz = src_h * relative_zoom;
+        let dw = (swz - (dx - sxd)).min(dest_w).max(0.0);
+        let dh = (shz - (dy - syd)).min(dest_h).max(0.0);
+        // Offset from dest origin and source origin, in dest space
+        let dxsz = dx - sxd;
+        let dysz = dy - syd;
+        // Now we can compute source ROI based on dest ROI offset
+        let sx = (dxsz / relative_zoom).min(src_w).max(0.0);
+        let sy = (dysz / relative_zoom).min(src_h).max(0.0);
+        let sw = dw / relative_zoom;
+        let sh = dh / relative_zoom;
+
+        let result = CanvasRoiPair {
+            s: Roi2D {
+                x: sx,
+                y: sy,
+                w: sw,
+                h: sh,
+            },
+            d: Roi2D {
+                x: dx,
+                y: dy,
+                w: dw,
+                h: dh,
+            },
+        };
+        web_sys::console::log_1(&format!("ROIs: {:?}", result).into());
+        result
+    }
+
+    /// The view showing all of an image with (full-resolution) dimensions `image`, as large as it
+    /// fits on a canvas with dimensions `dest`
+    pub fn fitted(image: Dims, dest: Dims) -> View2D {
+        View2D {
+            zoom: (dest.w / image.w).min(dest.h / image.h),
+            ..View2D::default()
+        }
+    }
+
+    /// This view, at `zoom` instead, with whatever is at `point` on a canvas with dimensions `dest`
+    /// staying there. `image` is the full-resolution dimensions of the image in view
+    pub fn zoomed_at(self, zoom: f64, point: (f64, f64), image: Dims, dest: Dims) -> View2D {
+        // The point's offset from the center of the canvas covers 1/zoom as many image pixels
+        // at the new zoom, so the center moves by the difference
+        let (px, py) = (point.0 - 0.5 * dest.w, point.1 - 0.5 * dest.h);
+        let shift = 1.0 / self.zoom - 1.0 / zoom;
+        View2D {
+            unit_loc: (
+                (self.unit_loc.0 + px * shift / image.w).clamp(0.0, 1.0),
+                (self.unit_loc.1 + py * shift / image.h).clamp(0.0, 1.0),
+            ),
+            zoom,
+            ..self
+        }
+    }
+
+    /// The part of an image with (full-resolution) dimensions `image` that this view shows on a
+    /// canvas with dimensions `dest`, in unit coordinates and clipped to the image
+    pub fn viewport(self, image: Dims, dest: Dims) -> Roi2D {
+        let half_w = 0.5 * dest.w / (self.zoom * image.w);
+        let half_h = 0.5 * dest.h / (self.zoom * image.h);
+        let (x, y) = (
+            (self.unit_loc.0 - half_w).max(0.0),
+            (self.unit_loc.1 - half_h).max(0.0),
+        );
+        Roi2D {
+            x,
+            y,
+            w: ((self.unit_loc.0 + half_w).min(1.0) - x).max(0.0),
+            h: ((self.unit_loc.1 + half_h).min(1.0) - y).max(0.0),
+        }
+    }
+}
+
+impl Default for View2D {
+    fn default() -> Self {
+        Self {
+            unit_loc: (0.5, 0.5),
+            zoom: 1.0,
+            is_pan_active: false,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use bson::oid::ObjectId;
+use clap::Parser;
+use image::{GenericImageView, ImageFormat};
+use jnickg_imaging::compression::Compression;
+use jnickg_imaging::documents::{self, LevelDims, PyramidLevel, PyramidTiles, TileDescriptor};
+use jnickg_imaging::errors::{Error, Result};
+use jnickg_imaging::ipr::*;
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    long_about = "Build an image pyramid and its tiles without a server. Writes each level to \
+    levels/, each tile to tiles/ (compressed, as the tile server stores them), and a manifest.json \
+    in the same shape as the server's pyramid documents"
+)]
+struct Args {
+    /// Path to an image for which to compute a pyramid
+    #[arg(long, value_name = "STR")]
+    input: String,
+
+    /// Path to a directory where result files will be saved. Created if missing
+    #[arg(long, value_name = "STR")]
+    output: String,
+
+    /// Name of the pyramid, used in level and tile file names. Defaults to the input's file name,
+    /// without its extension
+    #[arg(long, value_name = "STR")]
+    name: Option<String>,
+
+    /// The (max) width of the tiles
+    #[arg(long, value_name = "INT", default_value_t = 512)]
+    tile_width: u32,
+
+    /// The (max) height of the tiles
+    #[arg(long, value_name = "INT", default_value_t = 512)]
+    tile_height: u32,
+
+    /// lowpass, gaussian, or laplacian
+    #[arg(long, value_name = "STR", default_value = "gaussian")]
+    pyramid_type: String,
+
+    /// The factor by which each level is scaled relative to the one before it
+    #[arg(long, value_name = "FLOAT", default_value_t = 0.5)]
+    scale_factor: f32,
+
+    /// Filter to resample levels with: nearest, box, triangle, catmullrom, gaussian, or lanczos3.
+    /// Defaults to the pyramid type's own
+    #[arg(long, value_name = "STR")]
+    filter: Option<String>,
+
+    /// Skip levels whose longest side is smaller than this many pixels
+    #[arg(long, value_name = "INT")]
+    min_dimension: Option<u32>,
+
+    /// The most levels to generate, counting the full-size image
+    #[arg(long, value_name = "INT")]
+    max_levels: Option<u32>,
+
+    /// br, gzip, or zstd. zstd needs the library's `server-codecs` feature
+    #[arg(long, value_name = "STR", default_value = "br")]
+    compression: String,
+
+    /// Compression level. Defaults to what the tile server uses for the chosen codec
+    #[arg(long, value_name = "INT")]
+    level: Option<u32>,
+
+    /// Format of the tiles, e.g. png, jpeg or webp, or auto for PNG where tiles have transparency
+    /// and JPEG elsewhere. Tiles that would lose transparency in the given format are PNG instead
+    #[arg(long, value_name = "STR", default_value = "png")]
+    tile_format: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(_e) = make_pyramid(&args) {
+        eprintln!("Error: {}", _e);
+    }
+}
+
+fn make_pyramid(args: &Args) -> Result<()> {
+    let image =
+        image::open(&args.input).map_err(|_| Error::image_decode("Failed to open image"))?;
+    let input = Path::new(&args.input);
+    let name = match &args.name {
+        Some(name) => name.clone(),
+        None => input
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| Error::validation("Input has no file name; pass --name"))?
+            .to_string(),
+    };
+
+    let pyramid_type = serde_json::from_value(serde_json::Value::from(args.pyramid_type.as_str()))
+        .map_err(|_| Error::validation("Pyramid type must be lowpass, gaussian, or laplacian"))?;
+    let filter = args
+        .filter
+        .as_deref()
+        .map(|f| {
+            serde_json::from_value(serde_json::Value::from(f)).map_err(|_| {
+                Error::validation(
+                    "Filter must be nearest, box, triangle, catmullrom, gaussian, or lanczos3",
+                )
+            })
+        })
+        .transpose()?;
+    let tile_format = match args.tile_format.as_str() {
+        documents::AUTO_TILE_FORMAT => None,
+        ext => {
+            let format = ImageFormat::from_extension(ext).ok_or_else(|| {
+                Error::validation("Tile format must be auto, or a writable format")
+            })?;
+            Some(format)
+        }
+    };
+    let compression = Compression::from_content_encoding(&args.compression)
+        .ok_or_else(|| Error::validation("Compression must be br, gzip, or zstd"))?;
+    let plan = PyramidBuilder::new()
+        .pyramid_type(pyramid_type)
+        .scale_factor(args.scale_factor)
+        .filter(filter)
+        .min_dimension(args.min_dimension)
+        .max_levels(args.max_levels)
+        .tile_size(args.tile_width, args.tile_height)
+        .tile_format(tile_format)
+        .compression(compression)
+        .compression_level(args.level)
+        .build()?;
+    let params = plan.params();
+
+    let output = Path::new(&args.output);
+    let (levels_dir, tiles_dir) = (output.join("levels"), output.join("tiles"));
+    for dir in [&levels_dir, &tiles_dir] {
+        std::fs::create_dir_all(dir)
+            .map_err(|_| Error::internal(format!("Failed to create {}", dir.display())))?;
+    }
+
+    let pyramid = plan.execute(&image)?;
+
+    let mut image_names = Vec::new();
+    let mut image_urls = Vec::new();
+    let mut tiled_levels = Vec::new();
+    for (l, level_image) in pyramid.levels().iter().enumerate() {
+        let level_name = documents::level_name(&name, l);
+        let level_file = format!("levels/{}.png", level_name);
+        level_image
+            .save_with_format(output.join(&level_file), ImageFormat::Png)
+            .map_err(|_| Error::image_encode(format!("Failed to save {}", level_file)))?;
+        println!("Saved level to {}", level_file);
+        image_names.push(level_name);
+        image_urls.push(level_file);
+
+        let level_tiles = pyramid.tiles(l).expect("Every level was tiled");
+        let mut tiles = Vec::new();
+        for (t_idx, (x, y, _, _, tile)) in level_tiles.iter_with_coords().enumerate() {
+            let tile_name = documents::tile_name(&name, l, t_idx);
+            let (format, data) = plan.compress_tile(tile)?;
+            let tile_file = tiles_dir.join(format!(
+                "{}.{}.{}",
+                tile_name,
+                format.extensions_str()[0],
+                compression.content_encoding()
+            ));
+            std::fs::write(&tile_file, data)
+                .map_err(|_| Error::internal(format!("Failed to write {}", tile_file.display())))?;
+
+            tiles.push(TileDescriptor {
+                x,
+                y,
+                width: tile.width(),
+                height: tile.height(),
+                index: t_idx as u32,
+                tile_id: ObjectId::new(),
+                name: tile_name,
+                mime_type: Some(format.to_mime_type().to_string()),
+                sha256: None,
+            });
+        }
+        println!("Saved {} tiles of level {}", tiles.len(), l);
+        tiled_levels.push(PyramidLevel {
+            level: l as u32,
+            width: level_image.width(),
+            height: level_image.height(),
+            tiles,
+        });
+    }
+
+    // There's no database to assign IDs, so every ID is freshly made up. They're unique, but
+    // refer to nothing
+    let (width, height) = image.dimensions();
+    let manifest = documents::Pyramid {
+        id: None,
+        uuid: name,
+        url: "manifest.json".to_string(),
+        original_filename: input
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        content_sha256: None,
+        image_files: image_names.iter().map(|_| ObjectId::new()).collect(),
+        image_sha256: Vec::new(),
+        image_docs: image_names.iter().map(|_| ObjectId::new()).collect(),
+        image_names,
+        image_urls,
+        mime_type: ImageFormat::Png.to_mime_type().to_string(),
+        compression,
+        tile_format: Some(match plan.tile_format() {
+            Some(format) => format.to_mime_type().to_string(),
+            None => documents::AUTO_TILE_FORMAT.to_string(),
+        }),
+        page: 0,
+        reoriented: false,
+        pyramid_type: params.pyramid_type,
+        scale_factor: params.scale_factor,
+        filter: Some(params.resample_filter()),
+        dimension_rounding: PYRAMID_DIMENSION_ROUNDING.to_string(),
+        level_dims: pyramid
+            .level_dims()
+            .into_iter()
+            .map(|(width, height)| LevelDims { width, height })
+            .collect(),
+        min_dimension: params.min_dimension,
+        max_levels: params.max_levels,
+        total_levels: Some(pyramid_level_dims(width, height, params.scale_factor).len() as u32),
+        tiles: PyramidTiles::Levels(tiled_levels),
+        tile_sets: Vec::new(),
+        tags: Vec::new(),
+    };
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|_| Error::internal("Failed to serialize manifest"))?;
+    std::fs::write(output.join("manifest.json"), manifest_json)
+        .map_err(|_| Error::internal("Failed to write manifest.json"))?;
+    println!("Saved manifest to manifest.json");
+    Ok(())
+}
@@ -50,9 +50,7 @@ fn main() {
         }
     };
 
-    for (t_idx, tile) in tiles.tiles.iter().enumerate() {
-        let tile_x = (t_idx % tiles.count_across as usize) * args.tile_width as usize;
-        let tile_y = (t_idx / tiles.count_across as usize) * args.tile_height as usize;
+    for (t_idx, (tile_x, tile_y, _, _, tile)) in tiles.iter_with_coords().enumerate() {
         let filename = std::path::Path::new(&args.output).join(format!(
             "Tile-{}_X{}_Y{}.{}",
             t_idx, tile_x, tile_y, image_extension
@@ -11,7 +11,8 @@ pub struct CircularBuffer<T: BufferElement, const N: usize> {
     /// The current size of the buffer, since elements might be popped off
     size: usize,
 
-    /// The current cursor position in the buffer, for indexing
+    /// The slot the next appended element will be written to. The newest element sits just
+    /// behind it, and the oldest element sits `size` slots behind it.
     cursor: usize,
 
     /// Equal to N. The total capaciy of the circular buffer
@@ -38,7 +39,7 @@ impl<T: BufferElement, const N: usize> CircularBuffer<T, N> {
         Self {
             data,
             size: N,
-            cursor: N - 1,
+            cursor: 0,
             capacity: N,
             overwrites: false,
         }
@@ -48,7 +49,7 @@ impl<T: BufferElement, const N: usize> CircularBuffer<T, N> {
         Self {
             data,
             size: N,
-            cursor: N - 1,
+            cursor: 0,
             capacity: N,
             overwrites: true,
         }
@@ -76,14 +77,14 @@ impl<T: BufferElement, const N: usize> CircularBuffer<T, N> {
 
     pub fn append(&mut self, el: T) -> Result<usize, &'static str> {
         if self.size < self.capacity {
-            self.data[self.size] = el;
+            self.data[self.cursor] = el;
             self.size += 1;
-            self.cursor += 1;
+            self.cursor = (self.cursor + 1) % self.capacity;
             return Ok(self.size);
         }
 
         if self.overwrites {
-            self.data[self.cursor % self.capacity] = el;
+            self.data[self.cursor] = el;
             // We don't increment size here, because we're overwriting an existing element
             self.cursor = (self.cursor + 1) % self.capacity;
             return Ok(self.size);
@@ -98,9 +99,43 @@ impl<T: BufferElement, const N: usize> CircularBuffer<T, N> {
         }
 
         self.size -= 1;
-        let idx = self.cursor % self.capacity;
         self.cursor = (self.cursor + self.capacity - 1) % self.capacity;
-        Ok(self.data[idx])
+        Ok(self.data[self.cursor])
+    }
+
+    /// Get the newest element, i.e. the one [`CircularBuffer::pop`] would return, without
+    /// removing it
+    pub fn peek(&self) -> Result<T, &'static str> {
+        if self.size == 0 {
+            return Err("Buffer is empty");
+        }
+
+        Ok(self.data[(self.cursor + self.capacity - 1) % self.capacity])
+    }
+
+    /// The number of elements currently in the buffer
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Removes all elements from the buffer. Capacity and overwrite behavior are unchanged.
+    pub fn clear(&mut self) {
+        self.size = 0;
+        self.cursor = 0;
+    }
+
+    /// Iterates over the elements in the buffer, from oldest to newest
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.data, self.logical_index(0), self.size)
+    }
+
+    /// The physical index of the `i`th-oldest element, which is also what `buffer[i]` refers to
+    fn logical_index(&self, i: usize) -> usize {
+        (self.cursor + self.capacity - self.size + i) % self.capacity
     }
 
     pub fn data_mut(&mut self) -> &mut [T] {
@@ -112,18 +147,350 @@ impl<T: BufferElement, const N: usize> Index<usize> for CircularBuffer<T, N> {
     type Output = T;
 
     fn index(&self, i: usize) -> &Self::Output {
-        let actual_index = i % self.size;
+        let actual_index = self.logical_index(i % self.size);
         &self.data[actual_index]
     }
 }
 
 impl<T: BufferElement, const N: usize> IndexMut<usize> for CircularBuffer<T, N> {
     fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        let actual_index = i % self.size;
+        let actual_index = self.logical_index(i % self.size);
+        &mut self.data[actual_index]
+    }
+}
+
+/// Iterator over a [`CircularBuffer`] or [`DynCircularBuffer`], from oldest to newest element
+pub struct Iter<'a, T: BufferElement> {
+    data: &'a [T],
+
+    /// Physical index of the oldest element
+    start: usize,
+
+    /// Logical index of the next element to yield from the front
+    front: usize,
+
+    /// One past the logical index of the next element to yield from the back
+    back: usize,
+}
+
+impl<'a, T: BufferElement> Iter<'a, T> {
+    fn new(data: &'a [T], start: usize, size: usize) -> Self {
+        Self {
+            data,
+            start,
+            front: 0,
+            back: size,
+        }
+    }
+
+    fn get(&self, i: usize) -> &'a T {
+        &self.data[(self.start + i) % self.data.len()]
+    }
+}
+
+impl<'a, T: BufferElement> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+
+        let el = self.get(self.front);
+        self.front += 1;
+        Some(el)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: BufferElement> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        Some(self.get(self.back))
+    }
+}
+
+impl<T: BufferElement> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: BufferElement, const N: usize> IntoIterator for &'a CircularBuffer<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Owning iterator over a [`CircularBuffer`], from oldest to newest element
+pub struct IntoIter<T: BufferElement, const N: usize> {
+    buffer: CircularBuffer<T, N>,
+    front: usize,
+    back: usize,
+}
+
+impl<T: BufferElement, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+
+        let el = self.buffer.data[self.buffer.logical_index(self.front)];
+        self.front += 1;
+        Some(el)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: BufferElement, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        Some(self.buffer.data[self.buffer.logical_index(self.back)])
+    }
+}
+
+impl<T: BufferElement, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T: BufferElement, const N: usize> IntoIterator for CircularBuffer<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            back: self.size,
+            buffer: self,
+            front: 0,
+        }
+    }
+}
+
+/// A Circular Buffer whose capacity is chosen at construction, and whose storage lives on the
+/// heap. Otherwise behaves the same as [`CircularBuffer`].
+#[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
+pub struct DynCircularBuffer<T: BufferElement> {
+    /// The actual data storage. Its length is the capacity of the buffer
+    data: Vec<T>,
+
+    /// The current size of the buffer, since elements might be popped off
+    size: usize,
+
+    /// The slot the next appended element will be written to. The newest element sits just
+    /// behind it, and the oldest element sits `size` slots behind it.
+    cursor: usize,
+
+    /// Whether to overwrite the oldest elements when the buffer is full
+    overwrites: bool,
+}
+
+impl<T: BufferElement> DynCircularBuffer<T> {
+    pub fn new(data: Vec<T>) -> Self {
+        Self {
+            size: data.len(),
+            data,
+            cursor: 0,
+            overwrites: false,
+        }
+    }
+
+    pub fn new_overwriting(data: Vec<T>) -> Self {
+        Self {
+            size: data.len(),
+            data,
+            cursor: 0,
+            overwrites: true,
+        }
+    }
+
+    pub fn new_empty(capacity: usize) -> Self {
+        Self {
+            data: vec![T::default(); capacity],
+            size: 0,
+            cursor: 0,
+            overwrites: false,
+        }
+    }
+
+    pub fn new_empty_overwriting(capacity: usize) -> Self {
+        Self {
+            data: vec![T::default(); capacity],
+            size: 0,
+            cursor: 0,
+            overwrites: true,
+        }
+    }
+
+    /// The total number of elements the buffer can hold
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn append(&mut self, el: T) -> Result<usize, &'static str> {
+        if self.capacity() == 0 {
+            return Err("Buffer has no capacity");
+        }
+
+        if self.size < self.capacity() {
+            self.data[self.cursor] = el;
+            self.size += 1;
+            self.cursor = (self.cursor + 1) % self.capacity();
+            return Ok(self.size);
+        }
+
+        if self.overwrites {
+            self.data[self.cursor] = el;
+            // We don't increment size here, because we're overwriting an existing element
+            self.cursor = (self.cursor + 1) % self.capacity();
+            return Ok(self.size);
+        }
+
+        Err("Buffer is full")
+    }
+
+    pub fn pop(&mut self) -> Result<T, &'static str> {
+        if self.size == 0 {
+            return Err("Buffer is empty");
+        }
+
+        self.size -= 1;
+        self.cursor = (self.cursor + self.capacity() - 1) % self.capacity();
+        Ok(self.data[self.cursor])
+    }
+
+    /// Get the newest element, i.e. the one [`DynCircularBuffer::pop`] would return, without
+    /// removing it
+    pub fn peek(&self) -> Result<T, &'static str> {
+        if self.size == 0 {
+            return Err("Buffer is empty");
+        }
+
+        Ok(self.data[(self.cursor + self.capacity() - 1) % self.capacity()])
+    }
+
+    /// The number of elements currently in the buffer
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Removes all elements from the buffer. Capacity and overwrite behavior are unchanged.
+    pub fn clear(&mut self) {
+        self.size = 0;
+        self.cursor = 0;
+    }
+
+    /// Changes the capacity of the buffer, keeping elements in order. If the new capacity is
+    /// smaller than the current number of elements, the oldest elements are dropped.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        let keep = self.size.min(capacity);
+        let mut data: Vec<T> = self.iter().skip(self.size - keep).copied().collect();
+        data.resize(capacity, T::default());
+
+        self.data = data;
+        self.size = keep;
+        self.cursor = if capacity == 0 { 0 } else { keep % capacity };
+    }
+
+    /// Iterates over the elements in the buffer, from oldest to newest
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.data, self.logical_index(0), self.size)
+    }
+
+    /// The physical index of the `i`th-oldest element
+    fn logical_index(&self, i: usize) -> usize {
+        if self.capacity() == 0 {
+            return 0;
+        }
+        (self.cursor + self.capacity() - self.size + i) % self.capacity()
+    }
+
+    pub fn data_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+impl<T: BufferElement> Index<usize> for DynCircularBuffer<T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        let actual_index = self.logical_index(i % self.size);
+        &self.data[actual_index]
+    }
+}
+
+impl<T: BufferElement> IndexMut<usize> for DynCircularBuffer<T> {
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        let actual_index = self.logical_index(i % self.size);
         &mut self.data[actual_index]
     }
 }
 
+impl<'a, T: BufferElement> IntoIterator for &'a DynCircularBuffer<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: BufferElement> IntoIterator for DynCircularBuffer<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().copied().collect::<Vec<T>>().into_iter()
+    }
+}
+
+impl<T: BufferElement, const N: usize> From<CircularBuffer<T, N>> for DynCircularBuffer<T> {
+    fn from(b: CircularBuffer<T, N>) -> Self {
+        Self {
+            data: b.data.to_vec(),
+            size: b.size,
+            cursor: b.cursor,
+            overwrites: b.overwrites,
+        }
+    }
+}
+
+impl<T: BufferElement, const N: usize> TryFrom<DynCircularBuffer<T>> for CircularBuffer<T, N> {
+    type Error = &'static str;
+
+    fn try_from(b: DynCircularBuffer<T>) -> Result<Self, Self::Error> {
+        let data: [T; N] = b
+            .data
+            .try_into()
+            .map_err(|_| "Buffer capacity does not match")?;
+        Ok(Self {
+            data,
+            size: b.size,
+            cursor: b.cursor,
+            capacity: N,
+            overwrites: b.overwrites,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -193,4 +560,166 @@ mod tests {
         assert_eq!(buffer.pop(), Err("Buffer is empty"));
         assert_eq!(buffer.size, 0);
     }
+
+    #[test]
+    fn circular_buffer_pop_after_append_returns_newest() {
+        let mut buffer = CircularBuffer::<u32, 3>::new_empty();
+        buffer.append(1).unwrap();
+        buffer.append(2).unwrap();
+        assert_eq!(buffer.pop(), Ok(2));
+        buffer.append(3).unwrap();
+        assert_eq!(buffer.pop(), Ok(3));
+        assert_eq!(buffer.pop(), Ok(1));
+    }
+
+    #[test]
+    fn circular_buffer_peek_does_not_remove() {
+        let mut buffer = CircularBuffer::<u32, 3>::new_empty();
+        assert_eq!(buffer.peek(), Err("Buffer is empty"));
+        buffer.append(1).unwrap();
+        buffer.append(2).unwrap();
+        assert_eq!(buffer.peek(), Ok(2));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Ok(2));
+        assert_eq!(buffer.peek(), Ok(1));
+    }
+
+    #[test]
+    fn circular_buffer_iterates_oldest_to_newest() {
+        let mut buffer = CircularBuffer::<u32, 3>::new_empty_overwriting();
+        for i in 1..=5 {
+            buffer.append(i).unwrap();
+        }
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(
+            buffer.iter().rev().copied().collect::<Vec<_>>(),
+            vec![5, 4, 3]
+        );
+        assert_eq!(buffer.iter().len(), 3);
+        assert_eq!(buffer.into_iter().collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn circular_buffer_iterates_partially_filled() {
+        let mut buffer = CircularBuffer::<u32, 4>::new_empty();
+        buffer.append(1).unwrap();
+        buffer.append(2).unwrap();
+        let mut seen = Vec::new();
+        for el in &buffer {
+            seen.push(*el);
+        }
+        assert_eq!(seen, vec![1, 2]);
+    }
+
+    #[test]
+    fn circular_buffer_indexes_match_iteration_after_wrapping() {
+        let mut buffer = CircularBuffer::<u32, 3>::new_empty_overwriting();
+        for i in 1..=5 {
+            buffer.append(i).unwrap();
+        }
+        let iterated = buffer.iter().copied().collect::<Vec<_>>();
+        assert_eq!((0..3).map(|i| buffer[i]).collect::<Vec<_>>(), iterated);
+        assert_eq!(buffer[3], 3);
+
+        buffer[0] = 6;
+        assert_eq!(buffer.pop(), Ok(5));
+        assert_eq!(buffer.pop(), Ok(4));
+        assert_eq!(buffer.pop(), Ok(6));
+
+        let mut buffer = DynCircularBuffer::<u32>::new_empty_overwriting(3);
+        for i in 1..=4 {
+            buffer.append(i).unwrap();
+        }
+        assert_eq!(
+            (0..3).map(|i| buffer[i]).collect::<Vec<_>>(),
+            buffer.iter().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn circular_buffer_clear_empties() {
+        let mut buffer = CircularBuffer::new([1, 2, 3]);
+        assert!(!buffer.is_empty());
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.iter().next(), None);
+        assert_eq!(buffer.append(4), Ok(1));
+        assert_eq!(buffer.append(5), Ok(2));
+        assert_eq!(buffer.append(6), Ok(3));
+        assert_eq!(buffer.append(7), Err("Buffer is full"));
+    }
+
+    #[test]
+    fn dyn_circular_buffer_appends_until_full_when_non_overwriting() {
+        let mut buffer = DynCircularBuffer::<u32>::new_empty(3);
+        assert_eq!(buffer.append(1), Ok(1));
+        assert_eq!(buffer.append(2), Ok(2));
+        assert_eq!(buffer.append(3), Ok(3));
+        assert_eq!(buffer.append(4), Err("Buffer is full"));
+        assert_eq!(buffer.data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn dyn_circular_buffer_appends_forever_when_overwriting() {
+        let mut buffer = DynCircularBuffer::<u32>::new_empty_overwriting(3);
+        for i in 1..=5 {
+            assert_eq!(buffer.append(i), Ok(i.min(3) as usize));
+        }
+        assert_eq!(buffer.data, vec![4, 5, 3]);
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(buffer.peek(), Ok(5));
+        assert_eq!(buffer.pop(), Ok(5));
+        assert_eq!(buffer.pop(), Ok(4));
+        assert_eq!(buffer.pop(), Ok(3));
+        assert_eq!(buffer.pop(), Err("Buffer is empty"));
+    }
+
+    #[test]
+    fn dyn_circular_buffer_with_no_capacity_rejects_appends() {
+        let mut buffer = DynCircularBuffer::<u32>::new_empty_overwriting(0);
+        assert_eq!(buffer.append(1), Err("Buffer has no capacity"));
+        assert_eq!(buffer.iter().next(), None);
+    }
+
+    #[test]
+    fn dyn_circular_buffer_grows_and_shrinks_in_order() {
+        let mut buffer = DynCircularBuffer::<u32>::new_empty_overwriting(3);
+        for i in 1..=5 {
+            buffer.append(i).unwrap();
+        }
+        buffer.set_capacity(5);
+        assert_eq!(buffer.capacity(), 5);
+        buffer.append(6).unwrap();
+        buffer.append(7).unwrap();
+        assert_eq!(
+            buffer.iter().copied().collect::<Vec<_>>(),
+            vec![3, 4, 5, 6, 7]
+        );
+
+        buffer.set_capacity(2);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.into_iter().collect::<Vec<_>>(), vec![6, 7]);
+    }
+
+    #[test]
+    fn circular_buffer_converts_to_and_from_dyn() {
+        let mut buffer = CircularBuffer::<u32, 3>::new_empty_overwriting();
+        for i in 1..=4 {
+            buffer.append(i).unwrap();
+        }
+
+        let dyn_buffer = DynCircularBuffer::from(buffer);
+        assert_eq!(dyn_buffer.capacity(), 3);
+        assert_eq!(
+            dyn_buffer.iter().copied().collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+
+        let back: CircularBuffer<u32, 3> = dyn_buffer.clone().try_into().unwrap();
+        assert_eq!(back, buffer);
+
+        let wrong_size: Result<CircularBuffer<u32, 4>, _> = dyn_buffer.try_into();
+        assert_eq!(wrong_size, Err("Buffer capacity does not match"));
+    }
 }
@@ -0,0 +1,275 @@
+//! Compression applied to stored image data, on top of the image format's own.
+//!
+//! Each codec is also an HTTP content coding, so compressed data can be sent to clients that
+//! accept it exactly as stored, with a `Content-Encoding` header, and decompressed only for those
+//! that don't.
+//!
+//! Zstandard wraps a C library, so it's only available with the `server-codecs` feature. Without
+//! it, zstd data can't be compressed or decompressed, though the codec can still be named.
+
+use std::{
+    io::{Cursor, Read, Write},
+    ops::RangeInclusive,
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::errors::{Error, Result};
+
+/// A codec stored image data may be compressed with. Serialized as its content coding
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Compression {
+    #[default]
+    #[serde(rename = "br")]
+    Brotli,
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Every codec, in the order we'd rather serve them
+    pub const ALL: [Compression; 3] = [Compression::Brotli, Compression::Zstd, Compression::Gzip];
+
+    /// The HTTP content coding for this codec, e.g. `br`. This is also how it's recorded in the
+    /// database
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Brotli => "br",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// The codec for an HTTP content coding, if we support it. Case-insensitive, and `x-gzip` is
+    /// taken to mean `gzip`, as RFC 9110 asks
+    pub fn from_content_encoding(coding: &str) -> Option<Compression> {
+        let coding = coding.trim().to_ascii_lowercase();
+        match coding.as_str() {
+            "br" => Some(Compression::Brotli),
+            "gzip" | "x-gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The compression levels this codec accepts, from fastest to smallest output
+    pub fn levels(&self) -> RangeInclusive<u32> {
+        match self {
+            Compression::Brotli => 0..=11,
+            Compression::Gzip => 0..=9,
+            Compression::Zstd => 1..=22,
+        }
+    }
+
+    /// The level to compress at when none is given. Stored data is written once and read many
+    /// times, so these lean toward smaller output over speed
+    pub fn default_level(&self) -> u32 {
+        match self {
+            Compression::Brotli => 10,
+            Compression::Gzip => 9,
+            Compression::Zstd => 19,
+        }
+    }
+
+    /// Compress `data` at the given level, which must be within [`Compression::levels`]. Brotli
+    /// uses its default window size
+    pub fn compress(&self, data: &[u8], level: u32) -> Result<Vec<u8>> {
+        let levels = self.levels();
+        if !levels.contains(&level) {
+            return Err(Error::validation(format!(
+                "{} level must be between {} and {}",
+                self.content_encoding(),
+                levels.start(),
+                levels.end()
+            )));
+        }
+
+        let mut compressed = Vec::new();
+        let result = match self {
+            Compression::Brotli => {
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: level as i32,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut Cursor::new(data), &mut compressed, &params).map(|_| ())
+            }
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut compressed, flate2::Compression::new(level));
+                encoder
+                    .write_all(data)
+                    .and_then(|_| encoder.finish().map(|_| ()))
+            }
+            #[cfg(feature = "server-codecs")]
+            Compression::Zstd => zstd::stream::copy_encode(data, &mut compressed, level as i32),
+            #[cfg(not(feature = "server-codecs"))]
+            Compression::Zstd => return Err(zstd_unavailable()),
+        };
+        result.map_err(|e| {
+            Error::internal(format!(
+                "Failed to compress data with {}: {}",
+                self.content_encoding(),
+                e
+            ))
+        })?;
+        Ok(compressed)
+    }
+
+    /// Undo this compression
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decompress_at_most(data, u64::MAX)
+    }
+
+    /// Undo this compression, unless `data` inflates to more than `max_bytes`, in which case this
+    /// fails with [`Error::TooLarge`] once it's read that much
+    pub fn decompress_at_most(&self, data: &[u8], max_bytes: u64) -> Result<Vec<u8>> {
+        let limit = max_bytes.saturating_add(1);
+        let mut decompressed = Vec::new();
+        let result = match self {
+            Compression::Brotli => brotli::Decompressor::new(data, 4096)
+                .take(limit)
+                .read_to_end(&mut decompressed),
+            Compression::Gzip => flate2::read::GzDecoder::new(data)
+                .take(limit)
+                .read_to_end(&mut decompressed),
+            #[cfg(feature = "server-codecs")]
+            Compression::Zstd => zstd::stream::read::Decoder::new(data)
+                .and_then(|d| d.take(limit).read_to_end(&mut decompressed)),
+            #[cfg(not(feature = "server-codecs"))]
+            Compression::Zstd => return Err(zstd_unavailable()),
+        };
+        result.map_err(|e| {
+            Error::image_decode(format!(
+                "Failed to decompress {} data: {}",
+                self.content_encoding(),
+                e
+            ))
+        })?;
+        if decompressed.len() as u64 > max_bytes {
+            return Err(Error::too_large(format!(
+                "{} data decompresses to more than {} bytes",
+                self.content_encoding(),
+                max_bytes
+            )));
+        }
+        Ok(decompressed)
+    }
+}
+
+#[cfg(not(feature = "server-codecs"))]
+fn zstd_unavailable() -> Error {
+    Error::unsupported_format("zstd needs the server-codecs feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const DATA: &[u8] = b"Some data that compresses well. Some data that compresses well.";
+
+    #[test_case(Compression::Brotli)]
+    #[test_case(Compression::Gzip)]
+    #[test_case(Compression::Zstd)]
+    fn content_encoding_round_trips(c: Compression) {
+        assert_eq!(
+            Compression::from_content_encoding(c.content_encoding()),
+            Some(c)
+        );
+        assert_eq!(
+            serde_json::to_value(c).unwrap(),
+            serde_json::Value::from(c.content_encoding())
+        );
+        assert!(c.levels().contains(&c.default_level()));
+    }
+
+    #[test]
+    fn from_content_encoding_handles_aliases() {
+        assert_eq!(
+            Compression::from_content_encoding(" GZip"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_content_encoding("x-gzip"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::from_content_encoding("identity"), None);
+    }
+
+    #[test]
+    fn decompress_reads_each_codec() {
+        let mut br = Vec::new();
+        brotli::BrotliCompress(&mut Cursor::new(DATA), &mut br, &Default::default()).unwrap();
+        assert_eq!(Compression::Brotli.decompress(&br).unwrap(), DATA);
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(DATA).unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(Compression::Gzip.decompress(&gz).unwrap(), DATA);
+
+        #[cfg(feature = "server-codecs")]
+        {
+            let zst = zstd::encode_all(DATA, 3).unwrap();
+            assert_eq!(Compression::Zstd.decompress(&zst).unwrap(), DATA);
+        }
+    }
+
+    #[cfg(not(feature = "server-codecs"))]
+    #[test]
+    fn zstd_needs_server_codecs() {
+        assert!(matches!(
+            Compression::Zstd.compress(DATA, 3),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            Compression::Zstd.decompress(DATA),
+            Err(Error::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test_case(Compression::Brotli)]
+    #[test_case(Compression::Gzip)]
+    #[cfg_attr(feature = "server-codecs", test_case(Compression::Zstd))]
+    fn compress_round_trips_at_every_level(c: Compression) {
+        for level in c.levels() {
+            let compressed = c.compress(DATA, level).unwrap();
+            assert_eq!(c.decompress(&compressed).unwrap(), DATA);
+        }
+    }
+
+    #[test]
+    fn compress_rejects_out_of_range_levels() {
+        assert!(matches!(
+            Compression::Gzip.compress(DATA, 10),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            Compression::Zstd.compress(DATA, 0),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test_case(Compression::Brotli)]
+    #[test_case(Compression::Gzip)]
+    #[cfg_attr(feature = "server-codecs", test_case(Compression::Zstd))]
+    fn decompress_at_most_stops_at_the_limit(c: Compression) {
+        let compressed = c.compress(DATA, c.default_level()).unwrap();
+        let len = DATA.len() as u64;
+        assert_eq!(c.decompress_at_most(&compressed, len).unwrap(), DATA);
+        assert!(matches!(
+            c.decompress_at_most(&compressed, len - 1),
+            Err(Error::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn decompress_rejects_garbage() {
+        assert!(matches!(
+            Compression::Gzip.decompress(DATA),
+            Err(Error::ImageDecode(_))
+        ));
+    }
+}
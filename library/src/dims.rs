@@ -1,15 +1,127 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Rows(pub usize);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Cols(pub usize);
+
+/// The size of a matrix: its number of rows, then its number of columns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Dims(pub Rows, pub Cols);
 
+impl Dims {
+    pub fn rows(&self) -> usize {
+        self.0 .0
+    }
+
+    pub fn cols(&self) -> usize {
+        self.1 .0
+    }
+
+    /// The dimensions with rows and columns swapped, as of a transposed matrix
+    pub fn transposed(&self) -> Dims {
+        Dims(Rows(self.cols()), Cols(self.rows()))
+    }
+
+    /// Number of elements
+    pub fn area(&self) -> usize {
+        self.rows() * self.cols()
+    }
+
+    /// Whether something this size fits inside something of size `other`, i.e. has no more rows
+    /// and no more columns
+    pub fn fits_within(&self, other: &Dims) -> bool {
+        self.rows() <= other.rows() && self.cols() <= other.cols()
+    }
+}
+
 impl From<(usize, usize)> for Dims {
     fn from((r, c): (usize, usize)) -> Self {
         Dims(Rows(r), Cols(c))
     }
 }
 
+impl From<Dims> for (usize, usize) {
+    fn from(dims: Dims) -> Self {
+        (dims.rows(), dims.cols())
+    }
+}
+
+impl fmt::Display for Dims {
+    /// Rows by columns, e.g. `3x4`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.rows(), self.cols())
+    }
+}
+
+/// A rectangular region of interest within a matrix: `rows` x `cols` elements, whose top-left
+/// element is at (`row`, `col`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Roi {
+    pub row: usize,
+    pub col: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Roi {
+    pub fn dims(&self) -> Dims {
+        (self.rows, self.cols).into()
+    }
+
+    /// Dimensions of the smallest matrix the region fits in: one past its last row and column
+    pub fn extent(&self) -> Dims {
+        (self.row + self.rows, self.col + self.cols).into()
+    }
+}
+
+impl From<((usize, usize), (usize, usize))> for Roi {
+    /// ((row, col), (rows, cols))
+    fn from(((row, col), (rows, cols)): ((usize, usize), (usize, usize))) -> Self {
+        Roi {
+            row,
+            col,
+            rows,
+            cols,
+        }
+    }
+}
+
 pub trait HasDims {
     fn rows(&self) -> usize;
     fn cols(&self) -> usize;
     fn dims(&self) -> Dims;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn dims_compare_and_print() {
+        let dims = Dims::from((3, 4));
+        assert_eq!(dims, Dims(Rows(3), Cols(4)));
+        assert_ne!(dims, dims.transposed());
+        assert_eq!(dims.transposed().transposed(), dims);
+        assert_eq!(dims.area(), 12);
+        assert_eq!(dims.to_string(), "3x4");
+        assert_eq!(<(usize, usize)>::from(dims), (3, 4));
+    }
+
+    #[test_case((2, 3), (2, 3), true)]
+    #[test_case((2, 3), (5, 5), true)]
+    #[test_case((2, 3), (3, 2), false)]
+    #[test_case((0, 0), (0, 0), true)]
+    fn fits_within(a: (usize, usize), b: (usize, usize), fits: bool) {
+        assert_eq!(Dims::from(a).fits_within(&b.into()), fits);
+    }
+
+    #[test]
+    fn roi_extent() {
+        let roi = Roi::from(((1, 2), (3, 4)));
+        assert_eq!(roi.dims(), Dims::from((3, 4)));
+        assert_eq!(roi.extent(), Dims::from((4, 6)));
+    }
+}
@@ -0,0 +1,841 @@
+//! Typed forms of the documents the tile server keeps in MongoDB, and returns from its API.
+//!
+//! The server reads and writes its collections through these, and clients parse its responses
+//! into them, so both ends agree on field names and types. Database IDs are BSON ObjectIds, which
+//! appear in JSON as `{ "$oid": "<hex>" }`.
+
+use bson::oid::ObjectId;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::compression::Compression;
+use crate::errors::{Error, Result};
+use crate::ipr::{
+    pyramid_level_dims, PyramidParams, PyramidType, ResampleFilter, PYRAMID_DIMENSION_ROUNDING,
+};
+
+/// An image pyramid, as stored in the `pyramids` collection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Pyramid {
+    /// The database's ID for this document. Absent until it's been inserted
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<ObjectId>,
+
+    pub uuid: String,
+
+    /// Where the API serves this document
+    pub url: String,
+
+    /// Name of the image the pyramid was built from
+    pub original_filename: String,
+
+    /// SHA-256 of the uploaded image, in lowercase hex. Uploading the same image with the same
+    /// parameters again gets this pyramid back, rather than a new one. Absent for pyramids that
+    /// weren't built from an upload, or were built before uploads were hashed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_sha256: Option<String>,
+
+    /// GridFS file of each stored level, largest first
+    #[schema(value_type = Vec<Object>)]
+    pub image_files: Vec<ObjectId>,
+
+    /// SHA-256 of each stored level's GridFS file, largest first, in lowercase hex. Empty for
+    /// pyramids created before levels were checksummed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub image_sha256: Vec<String>,
+
+    /// Image name of each stored level, largest first. See [`level_name`]
+    pub image_names: Vec<String>,
+
+    /// `images` document of each stored level, largest first
+    #[schema(value_type = Vec<Object>)]
+    pub image_docs: Vec<ObjectId>,
+
+    /// Where the API serves each stored level, largest first
+    pub image_urls: Vec<String>,
+
+    /// Format the levels (and their tiles) are stored in
+    pub mime_type: String,
+
+    /// Codec the tiles are compressed with. Absent for pyramids created before there was a
+    /// choice, which were all Brotli
+    #[serde(default)]
+    pub compression: Compression,
+
+    /// Format asked for the tiles: a MIME type, or [`AUTO_TILE_FORMAT`]. Tiles that would lose
+    /// their transparency in it fall back to another, so see each tile's `mime_type` for what it
+    /// ended up as. Absent if tiles are asked for in `mime_type`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile_format: Option<String>,
+
+    /// For multi-page sources (TIFF), the page the pyramid was built from
+    #[serde(default)]
+    pub page: u32,
+
+    /// Whether the source was turned upright, per its EXIF orientation, before the pyramid was
+    /// built
+    #[serde(default)]
+    pub reoriented: bool,
+
+    #[serde(default)]
+    pub pyramid_type: PyramidType,
+
+    #[serde(default = "default_scale_factor")]
+    pub scale_factor: f32,
+
+    /// Filter the levels were resampled with. Absent for pyramids created before it could be
+    /// chosen, which used their type's own (see [`PyramidParams::resample_filter`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<ResampleFilter>,
+
+    /// How level dimensions were rounded. See [`PYRAMID_DIMENSION_ROUNDING`]
+    #[serde(default = "default_dimension_rounding")]
+    pub dimension_rounding: String,
+
+    /// Dimensions of each stored level, largest first. Empty for pyramids created before these
+    /// were recorded
+    #[serde(default)]
+    pub level_dims: Vec<LevelDims>,
+
+    #[serde(default)]
+    pub min_dimension: Option<u32>,
+
+    #[serde(default)]
+    pub max_levels: Option<u32>,
+
+    /// Number of levels down to 1x1, including any that weren't stored. Absent for pyramids
+    /// created before levels could be skipped, in which case every level was stored
+    #[serde(default)]
+    pub total_levels: Option<u32>,
+
+    pub tiles: PyramidTiles,
+
+    /// Tile sets made later, at other tile sizes or in other formats, alongside `tiles`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tile_sets: Vec<TileSet>,
+
+    /// Labels to find the pyramid by. See [`normalize_tags`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+fn default_scale_factor() -> f32 {
+    0.5
+}
+
+fn default_dimension_rounding() -> String {
+    PYRAMID_DIMENSION_ROUNDING.to_string()
+}
+
+impl Pyramid {
+    /// The parameters the pyramid was built with
+    pub fn params(&self) -> PyramidParams {
+        PyramidParams {
+            pyramid_type: self.pyramid_type,
+            scale_factor: self.scale_factor,
+            min_dimension: self.min_dimension,
+            max_levels: self.max_levels,
+            filter: self.filter,
+        }
+    }
+
+    /// Number of levels down to 1x1, including any that weren't stored
+    pub fn total_levels(&self) -> usize {
+        self.total_levels
+            .map_or(self.image_names.len(), |t| t as usize)
+    }
+
+    /// Width and height of the given level, whether or not it was stored. `None` past the last
+    /// level, or for pyramids that neither record their level dimensions nor have been tiled
+    pub fn dims_of_level(&self, level: usize) -> Option<LevelDims> {
+        if level >= self.total_levels() {
+            return None;
+        }
+        if let Some(dims) = self.level_dims.get(level) {
+            return Some(*dims);
+        }
+        let full = self.level_dims.first().copied().or_else(|| {
+            self.levels()?.first().map(|l| LevelDims {
+                width: l.width,
+                height: l.height,
+            })
+        })?;
+        pyramid_level_dims(full.width, full.height, self.scale_factor)
+            .get(level)
+            .map(|&(width, height)| LevelDims { width, height })
+    }
+
+    /// The format asked for the tiles, for [`crate::ipr::choose_tile_format`]. `None` if each
+    /// tile's format is to be picked automatically
+    pub fn requested_tile_format(&self) -> Option<ImageFormat> {
+        match self.tile_format.as_deref() {
+            Some(AUTO_TILE_FORMAT) => None,
+            Some(mime_type) => ImageFormat::from_mime_type(mime_type),
+            None => ImageFormat::from_mime_type(&self.mime_type),
+        }
+    }
+
+    /// The tile set with the given key. See [`tile_set_key`]
+    pub fn tile_set(&self, key: &str) -> Option<&TileSet> {
+        self.tile_sets.iter().find(|s| s.key == key)
+    }
+}
+
+/// Tiles of a pyramid's stored levels, made on request besides its own (see [`Pyramid::tiles`]),
+/// e.g. to serve a viewer that wants smaller tiles. They're compressed with the pyramid's codec
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TileSet {
+    /// Tells the pyramid's tile sets apart. See [`tile_set_key`]
+    pub key: String,
+
+    pub tile_width: u32,
+    pub tile_height: u32,
+
+    /// Format asked for the tiles: a MIME type, or [`AUTO_TILE_FORMAT`]. As with the pyramid's
+    /// own tiles, see each tile's `mime_type` for what it ended up as
+    pub tile_format: String,
+
+    pub tiles: PyramidTiles,
+}
+
+impl TileSet {
+    /// The format asked for the tiles, for [`crate::ipr::choose_tile_format`]. `None` if each
+    /// tile's format is to be picked automatically
+    pub fn requested_tile_format(&self) -> Option<ImageFormat> {
+        match self.tile_format.as_str() {
+            AUTO_TILE_FORMAT => None,
+            mime_type => ImageFormat::from_mime_type(mime_type),
+        }
+    }
+}
+
+/// Key of the tile set with the given parameters, e.g. `256x256_png`. `tile_format` is a MIME type
+/// or [`AUTO_TILE_FORMAT`]
+pub fn tile_set_key(tile_width: u32, tile_height: u32, tile_format: &str) -> String {
+    let format =
+        ImageFormat::from_mime_type(tile_format).map_or(tile_format, |f| f.extensions_str()[0]);
+    format!("{}x{}_{}", tile_width, tile_height, format)
+}
+
+/// Name of the image holding the given tile of the given level, in the tile set with the given
+/// key. Unlike [`tile_name`], this isn't matched by the pyramid's own tiles' names
+pub fn tile_set_tile_name(pyramid_uuid: &str, key: &str, level: usize, index: usize) -> String {
+    format!("{}_{}_L{}_T{}", pyramid_uuid, key, level, index)
+}
+
+/// The [`Pyramid::tile_format`] picking each tile's format by its content: PNG if it has any
+/// transparency, and JPEG otherwise
+pub const AUTO_TILE_FORMAT: &str = "auto";
+
+/// Most tags an image or pyramid can have
+pub const MAX_TAGS: usize = 32;
+
+/// Longest a tag can be, in characters
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// Tidy up a list of tags: surrounding whitespace is trimmed, empty tags dropped, and repeats
+/// removed, keeping the first of each. Fails if there are more than [`MAX_TAGS`] left, or any is
+/// longer than [`MAX_TAG_LENGTH`] or has a comma in it (which separates tags in query strings)
+pub fn normalize_tags<S: AsRef<str>>(tags: &[S]) -> Result<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|t| t.as_ref().trim()) {
+        if tag.is_empty() || normalized.iter().any(|t| t == tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH || tag.contains(',') {
+            return Err(Error::validation(format!(
+                "Tags must be at most {} characters, without commas, not {:?}",
+                MAX_TAG_LENGTH, tag
+            )));
+        }
+        normalized.push(tag.to_string());
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(Error::validation(format!(
+            "At most {} tags are allowed, not {}",
+            MAX_TAGS,
+            normalized.len()
+        )));
+    }
+    Ok(normalized)
+}
+
+/// One page of the pyramid listing served by `GET /api/v1/pyramids`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PyramidPage {
+    pub pyramids: Vec<Pyramid>,
+
+    /// Number of pyramids matching the listing's filters, across all pages
+    pub total: u64,
+
+    /// Offset of the next page, if there is one
+    pub next_offset: Option<u64>,
+}
+
+/// Name of the image holding the given level of the pyramid with the given UUID
+pub fn level_name(pyramid_uuid: &str, level: usize) -> String {
+    format!("{}_L{}", pyramid_uuid, level)
+}
+
+/// Name of the image holding the given tile of the given level
+pub fn tile_name(pyramid_uuid: &str, level: usize, index: usize) -> String {
+    format!("{}_L{}_T{}", pyramid_uuid, level, index)
+}
+
+/// Width and height of a pyramid level, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct LevelDims {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where a pyramid's tiles are at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum PyramidTiles {
+    /// Not tiled yet
+    Status(TilingStatus),
+
+    /// Tiled. One entry per stored level, largest first
+    Levels(Vec<PyramidLevel>),
+}
+
+impl PyramidTiles {
+    /// The tiled levels, if tiling is done
+    pub fn levels(&self) -> Option<&[PyramidLevel]> {
+        match self {
+            PyramidTiles::Levels(levels) => Some(levels),
+            PyramidTiles::Status(_) => None,
+        }
+    }
+}
+
+/// Why a pyramid has no tiles yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TilingStatus {
+    /// Waiting for a tiling job to start
+    Todo,
+
+    /// A tiling job is running (or was, when the server last stopped)
+    Processing,
+
+    /// The last tiling job failed. It can be retried
+    Failed,
+}
+
+impl TilingStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TilingStatus::Todo => "todo",
+            TilingStatus::Processing => "processing",
+            TilingStatus::Failed => "failed",
+        }
+    }
+}
+
+/// The tiles of one pyramid level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PyramidLevel {
+    pub level: u32,
+    pub width: u32,
+    pub height: u32,
+
+    /// In row-major order, starting from the top-left
+    pub tiles: Vec<TileDescriptor>,
+}
+
+impl PyramidLevel {
+    /// Number of tiles across the level. Zero until tiled
+    pub fn columns(&self) -> u32 {
+        self.tiles.iter().filter(|t| t.y == 0).count() as u32
+    }
+
+    /// The tile in the given column and row of the level's grid, counting from the top-left, if
+    /// there is one
+    pub fn tile_at(&self, column: u32, row: u32) -> Option<&TileDescriptor> {
+        let columns = self.columns();
+        if column >= columns {
+            return None;
+        }
+        self.tiles
+            .get(row as usize * columns as usize + column as usize)
+    }
+}
+
+/// One tile of a pyramid level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TileDescriptor {
+    /// Left edge of the tile, in pixels of its level
+    pub x: u32,
+
+    /// Top edge of the tile, in pixels of its level
+    pub y: u32,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Position of the tile within its level, in row-major order
+    pub index: u32,
+
+    /// GridFS file holding the tile
+    #[schema(value_type = Object)]
+    pub tile_id: ObjectId,
+
+    /// Image name of the tile. See [`tile_name`]
+    pub name: String,
+
+    /// Format of the tile. Absent for tiles made before tiles could differ from their pyramid,
+    /// which are in its `mime_type`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+
+    /// SHA-256 of the tile's GridFS file (compressed, as stored), in lowercase hex. Absent for
+    /// tiles made before tiles were checksummed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// A pyramid's levels and tiles, flattened for viewers, as served by
+/// `GET /api/v1/pyramid/{uuid}/manifest`. Unlike [`Pyramid`], it holds no database IDs, and
+/// spells out the URL of everything a viewer would fetch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PyramidManifest {
+    pub uuid: String,
+
+    /// Width of the largest level, in pixels. Zero if no level's dimensions are known
+    pub width: u32,
+
+    /// Height of the largest level, in pixels. Zero if no level's dimensions are known
+    pub height: u32,
+
+    pub pyramid_type: PyramidType,
+    pub scale_factor: f32,
+
+    /// Codec the tiles are stored with. Tiles are sent as stored, with a matching
+    /// `Content-Encoding`, to clients that accept it, and decompressed for those that don't
+    pub compression: Compression,
+
+    /// Why the levels have no tiles yet. Absent once they're tiled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<TilingStatus>,
+
+    /// Stored levels, largest first
+    pub levels: Vec<ManifestLevel>,
+}
+
+/// One level of a [`PyramidManifest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ManifestLevel {
+    pub level: u32,
+    pub width: u32,
+    pub height: u32,
+
+    /// Where the API serves the whole level
+    pub url: String,
+
+    /// Width of the level's tiles, in pixels. Tiles in the last column may be narrower. Zero
+    /// until tiled
+    pub tile_width: u32,
+
+    /// Height of the level's tiles, in pixels. Tiles in the last row may be shorter. Zero until
+    /// tiled
+    pub tile_height: u32,
+
+    /// Number of tiles across the level. Zero until tiled
+    pub columns: u32,
+
+    /// Number of tiles down the level. Zero until tiled
+    pub rows: u32,
+
+    /// In row-major order, starting from the top-left. Empty until tiled
+    pub tiles: Vec<ManifestTile>,
+}
+
+/// One tile of a [`ManifestLevel`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ManifestTile {
+    pub column: u32,
+    pub row: u32,
+
+    /// Left edge of the tile, in pixels of its level
+    pub x: u32,
+
+    /// Top edge of the tile, in pixels of its level
+    pub y: u32,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Where the API serves the tile
+    pub url: String,
+
+    /// Format of the tile
+    pub mime_type: String,
+}
+
+impl ManifestLevel {
+    fn tiled(pyramid: &Pyramid, level: &PyramidLevel, url: String) -> Self {
+        let columns = level.columns().max(1);
+        let rows = level.tiles.iter().filter(|t| t.x == 0).count() as u32;
+        let tiles = level
+            .tiles
+            .iter()
+            .map(|t| ManifestTile {
+                column: t.index % columns,
+                row: t.index / columns,
+                x: t.x,
+                y: t.y,
+                width: t.width,
+                height: t.height,
+                url: format!("/api/v1/image/{}", t.name),
+                mime_type: t
+                    .mime_type
+                    .clone()
+                    .unwrap_or_else(|| pyramid.mime_type.clone()),
+            })
+            .collect();
+        Self {
+            level: level.level,
+            width: level.width,
+            height: level.height,
+            url,
+            // The first tile is never cut short, unless it's the only one
+            tile_width: level.tiles.first().map_or(0, |t| t.width),
+            tile_height: level.tiles.first().map_or(0, |t| t.height),
+            columns: match rows {
+                0 => 0,
+                _ => columns,
+            },
+            rows,
+            tiles,
+        }
+    }
+
+    fn untiled(level: usize, dims: LevelDims, url: String) -> Self {
+        Self {
+            level: level as u32,
+            width: dims.width,
+            height: dims.height,
+            url,
+            tile_width: 0,
+            tile_height: 0,
+            columns: 0,
+            rows: 0,
+            tiles: Vec::new(),
+        }
+    }
+}
+
+impl From<&Pyramid> for PyramidManifest {
+    fn from(pyramid: &Pyramid) -> Self {
+        let urls = pyramid.image_urls.iter().cloned();
+        let (status, levels): (_, Vec<ManifestLevel>) = match &pyramid.tiles {
+            PyramidTiles::Levels(levels) => (
+                None,
+                levels
+                    .iter()
+                    .zip(urls)
+                    .map(|(level, url)| ManifestLevel::tiled(pyramid, level, url))
+                    .collect(),
+            ),
+            // Pyramids made before level dimensions were recorded have no levels to show until
+            // they're tiled
+            PyramidTiles::Status(status) => (
+                Some(*status),
+                pyramid
+                    .level_dims
+                    .iter()
+                    .zip(urls)
+                    .enumerate()
+                    .map(|(level, (dims, url))| ManifestLevel::untiled(level, *dims, url))
+                    .collect(),
+            ),
+        };
+        Self {
+            uuid: pyramid.uuid.clone(),
+            width: levels.first().map_or(0, |l| l.width),
+            height: levels.first().map_or(0, |l| l.height),
+            pyramid_type: pyramid.pyramid_type,
+            scale_factor: pyramid.scale_factor,
+            compression: pyramid.compression,
+            status,
+            levels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pyramid(tiles: PyramidTiles) -> Pyramid {
+        let uuid = "00000000-0000-4000-8000-000000000001";
+        Pyramid {
+            id: None,
+            uuid: uuid.to_string(),
+            url: format!("/api/v1/pyramid/{}", uuid),
+            original_filename: "test.png".to_string(),
+            content_sha256: None,
+            image_files: vec![ObjectId::new()],
+            image_sha256: Vec::new(),
+            image_names: vec![level_name(uuid, 0)],
+            image_docs: vec![ObjectId::new()],
+            image_urls: vec![format!("/api/v1/image/{}", level_name(uuid, 0))],
+            mime_type: "image/png".to_string(),
+            compression: Compression::Zstd,
+            tile_format: None,
+            page: 0,
+            reoriented: false,
+            pyramid_type: PyramidType::Gaussian,
+            scale_factor: 0.5,
+            filter: None,
+            dimension_rounding: PYRAMID_DIMENSION_ROUNDING.to_string(),
+            level_dims: vec![LevelDims {
+                width: 40,
+                height: 24,
+            }],
+            min_dimension: None,
+            max_levels: None,
+            total_levels: Some(7),
+            tiles,
+            tile_sets: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dims_of_stored_and_skipped_levels() {
+        let p = pyramid(PyramidTiles::Status(TilingStatus::Todo));
+        let dims = |(width, height)| Some(LevelDims { width, height });
+        assert_eq!(p.dims_of_level(0), dims((40, 24)));
+        assert_eq!(p.dims_of_level(1), dims((20, 12)));
+        assert_eq!(p.dims_of_level(6), dims(pyramid_level_dims(40, 24, 0.5)[6]));
+        assert_eq!(p.dims_of_level(7), None);
+
+        let untiled = Pyramid {
+            level_dims: Vec::new(),
+            ..p
+        };
+        assert_eq!(untiled.dims_of_level(0), None);
+    }
+
+    #[test]
+    fn tags_are_tidied() {
+        let tags = normalize_tags(&[" cats ", "", "dogs", "cats"]).unwrap();
+        assert_eq!(tags, ["cats", "dogs"]);
+        assert!(normalize_tags::<&str>(&[]).unwrap().is_empty());
+
+        let long = "x".repeat(MAX_TAG_LENGTH + 1);
+        assert!(matches!(normalize_tags(&[long]), Err(Error::Validation(_))));
+        assert!(matches!(
+            normalize_tags(&["a,b"]),
+            Err(Error::Validation(_))
+        ));
+        let many = (0..=MAX_TAGS).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert!(matches!(normalize_tags(&many), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn tile_sets_are_keyed_by_their_parameters() {
+        assert_eq!(tile_set_key(256, 256, "image/png"), "256x256_png");
+        assert_eq!(tile_set_key(512, 128, "image/jpeg"), "512x128_jpg");
+        assert_eq!(tile_set_key(256, 256, AUTO_TILE_FORMAT), "256x256_auto");
+
+        let uuid = "00000000-0000-4000-8000-000000000001";
+        let name = tile_set_tile_name(uuid, "256x256_png", 1, 2);
+        assert_eq!(name, format!("{}_256x256_png_L1_T2", uuid));
+        assert!(!name.starts_with(&format!("{}_L", uuid)));
+    }
+
+    #[test]
+    fn pyramids_without_tile_sets_leave_them_out() {
+        let mut p = pyramid(PyramidTiles::Status(TilingStatus::Todo));
+        let json = serde_json::to_value(&p).unwrap();
+        assert!(json.get("tile_sets").is_none());
+        assert_eq!(serde_json::from_value::<Pyramid>(json).unwrap(), p);
+
+        p.tile_sets.push(TileSet {
+            key: tile_set_key(16, 16, "image/png"),
+            tile_width: 16,
+            tile_height: 16,
+            tile_format: "image/png".to_string(),
+            tiles: PyramidTiles::Status(TilingStatus::Todo),
+        });
+        let json = serde_json::to_value(&p).unwrap();
+        assert_eq!(json["tile_sets"][0]["key"], "16x16_png");
+        assert_eq!(
+            p.tile_set("16x16_png").unwrap().requested_tile_format(),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(p.tile_set("16x16_jpg"), None);
+    }
+
+    #[test]
+    fn tiling_status_is_a_plain_string() {
+        let p = pyramid(PyramidTiles::Status(TilingStatus::Processing));
+        let json = serde_json::to_value(&p).unwrap();
+        assert_eq!(json["tiles"], "processing");
+        assert!(json.get("_id").is_none());
+        assert_eq!(serde_json::from_value::<Pyramid>(json).unwrap(), p);
+    }
+
+    #[test]
+    fn tiled_levels_round_trip_through_bson() {
+        let uuid = "00000000-0000-4000-8000-000000000001";
+        let p = pyramid(PyramidTiles::Levels(vec![PyramidLevel {
+            level: 0,
+            width: 40,
+            height: 24,
+            tiles: vec![TileDescriptor {
+                x: 0,
+                y: 0,
+                width: 40,
+                height: 24,
+                index: 0,
+                tile_id: ObjectId::new(),
+                name: tile_name(uuid, 0, 0),
+                mime_type: Some("image/webp".to_string()),
+                sha256: Some("ab".repeat(32)),
+            }],
+        }]));
+        let doc = bson::to_document(&p).unwrap();
+        assert!(doc.get_array("tiles").is_ok());
+        let parsed: Pyramid = bson::from_document(doc).unwrap();
+        assert_eq!(parsed, p);
+        assert_eq!(
+            parsed.tiles.levels().unwrap()[0].tiles[0].name,
+            tile_name(uuid, 0, 0)
+        );
+    }
+
+    fn tile(
+        uuid: &str,
+        index: u32,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+    ) -> TileDescriptor {
+        TileDescriptor {
+            x,
+            y,
+            width,
+            height,
+            index,
+            tile_id: ObjectId::new(),
+            name: tile_name(uuid, 0, index as usize),
+            mime_type: (index == 0).then(|| "image/webp".to_string()),
+            sha256: None,
+        }
+    }
+
+    #[test]
+    fn manifest_flattens_tiled_levels() {
+        let uuid = "00000000-0000-4000-8000-000000000001";
+        let p = pyramid(PyramidTiles::Levels(vec![PyramidLevel {
+            level: 0,
+            width: 40,
+            height: 24,
+            tiles: vec![
+                tile(uuid, 0, (0, 0), (32, 16)),
+                tile(uuid, 1, (32, 0), (8, 16)),
+                tile(uuid, 2, (0, 16), (32, 8)),
+                tile(uuid, 3, (32, 16), (8, 8)),
+            ],
+        }]));
+        let manifest = PyramidManifest::from(&p);
+        assert_eq!(manifest.status, None);
+        assert_eq!((manifest.width, manifest.height), (40, 24));
+
+        let level = &manifest.levels[0];
+        assert_eq!(level.url, p.image_urls[0]);
+        assert_eq!((level.tile_width, level.tile_height), (32, 16));
+        assert_eq!((level.columns, level.rows), (2, 2));
+        let last = &level.tiles[3];
+        assert_eq!((last.column, last.row), (1, 1));
+        assert_eq!((last.x, last.y, last.width, last.height), (32, 16, 8, 8));
+        assert_eq!(last.url, format!("/api/v1/image/{}", tile_name(uuid, 0, 3)));
+        // Tiles without a format of their own are in the pyramid's
+        assert_eq!(level.tiles[0].mime_type, "image/webp");
+        assert_eq!(last.mime_type, "image/png");
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert!(json.get("status").is_none());
+        assert_eq!(
+            serde_json::from_value::<PyramidManifest>(json).unwrap(),
+            manifest
+        );
+    }
+
+    #[test]
+    fn tiles_are_found_by_column_and_row() {
+        let uuid = "00000000-0000-4000-8000-000000000001";
+        let level = PyramidLevel {
+            level: 0,
+            width: 72,
+            height: 24,
+            tiles: vec![
+                tile(uuid, 0, (0, 0), (32, 16)),
+                tile(uuid, 1, (32, 0), (32, 16)),
+                tile(uuid, 2, (64, 0), (8, 16)),
+                tile(uuid, 3, (0, 16), (32, 8)),
+                tile(uuid, 4, (32, 16), (32, 8)),
+                tile(uuid, 5, (64, 16), (8, 8)),
+            ],
+        };
+        assert_eq!(level.columns(), 3);
+        assert_eq!(level.tile_at(0, 0).map(|t| t.index), Some(0));
+        assert_eq!(level.tile_at(2, 0).map(|t| t.index), Some(2));
+        assert_eq!(level.tile_at(1, 1).map(|t| (t.x, t.y)), Some((32, 16)));
+        // Off the right edge doesn't wrap around to the next row
+        assert_eq!(level.tile_at(3, 0), None);
+        assert_eq!(level.tile_at(0, 2), None);
+    }
+
+    #[test]
+    fn manifest_of_untiled_pyramid_has_levels_without_tiles() {
+        let p = pyramid(PyramidTiles::Status(TilingStatus::Processing));
+        let manifest = PyramidManifest::from(&p);
+        assert_eq!(manifest.status, Some(TilingStatus::Processing));
+        assert_eq!(manifest.levels.len(), 1);
+        let level = &manifest.levels[0];
+        assert_eq!((level.width, level.height), (40, 24));
+        assert_eq!((level.columns, level.rows), (0, 0));
+        assert!(level.tiles.is_empty());
+    }
+
+    #[test]
+    fn old_documents_get_defaults() {
+        let json = serde_json::json!({
+            "uuid": "u",
+            "url": "/api/v1/pyramid/u",
+            "original_filename": "a.jpg",
+            "image_files": [{ "$oid": "6660de9402834efab622c410" }],
+            "image_names": ["u_L0"],
+            "image_docs": [{ "$oid": "6660de9402834efab622c420" }],
+            "image_urls": ["/api/v1/image/u_L0"],
+            "mime_type": "image/jpeg",
+            "tiles": "todo",
+        });
+        let p: Pyramid = serde_json::from_value(json).unwrap();
+        assert_eq!(p.pyramid_type, PyramidType::default());
+        assert_eq!(p.scale_factor, 0.5);
+        assert_eq!(p.compression, Compression::Brotli);
+        assert!(p.level_dims.is_empty());
+        assert!(p.image_sha256.is_empty());
+        assert_eq!(p.total_levels(), 1);
+        assert_eq!(p.tiles, PyramidTiles::Status(TilingStatus::Todo));
+        assert_eq!(p.requested_tile_format(), Some(ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn tile_format_overrides_level_format() {
+        let mut p = pyramid(PyramidTiles::Status(TilingStatus::Todo));
+        assert_eq!(p.requested_tile_format(), Some(ImageFormat::Png));
+        p.tile_format = Some("image/webp".to_string());
+        assert_eq!(p.requested_tile_format(), Some(ImageFormat::WebP));
+        p.tile_format = Some(AUTO_TILE_FORMAT.to_string());
+        assert_eq!(p.requested_tile_format(), None);
+    }
+}
@@ -1,34 +1,98 @@
 use std::fmt::Display;
 use std::ops::{Add, AddAssign, Index, IndexMut, Mul, MulAssign, Sub, SubAssign};
 
-use crate::dims::{Cols, Dims, HasDims, Rows};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use num::Float;
+
+use crate::dims::{Cols, Dims, HasDims, Roi, Rows};
 use crate::element::Element;
+use crate::errors::{Error, Result};
 use crate::matrix::Matrix;
+use crate::simd::SliceOps;
 // use crate::my_traits::{AreNotSame, IsTrue, Multiplied, TheTypes, Values, AreEqual};
 
+/// Matrices with at least this many elements have their row-wise operations spread across the
+/// rayon thread pool, when the `parallel` feature is enabled. Below it, the overhead of
+/// dispatching work outweighs the gains.
+pub const PARALLEL_THRESHOLD: usize = 128 * 128;
+
+/// Products where every dimension (rows and columns of the output, and the shared inner
+/// dimension) is at least this large are computed block by block. Below it, the operands fit in
+/// cache anyway and the bookkeeping of blocking only slows things down.
+pub const BLOCKED_MUL_THRESHOLD: usize = 256;
+
+/// Side length of the square blocks used by blocked multiplication. A 64x64 block of `f64` is
+/// 32 KiB, so a block of each operand and of the output stay cache-resident together.
+pub const MUL_BLOCK_SIZE: usize = 64;
+
 /// A matrix of elements of type `T`, with `R` rows and `C` columns.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Elements are stored in a single contiguous buffer, in row-major order. Row `i` starts at
+/// element `i * stride`, and `stride >= cols`.
+///
+/// Matrices may be empty, with no rows, no columns, or neither. An empty matrix has no elements,
+/// but keeps its dimensions, and otherwise behaves like any other: a 3x0 matrix has three (empty)
+/// rows, transposes to a 0x3 one, and times a 0x2 matrix makes a 3x2 matrix of zeros.
+#[derive(Debug, Clone)]
 pub struct DynMatrix<T: Element> {
     /// The elements of this matrix
-    els: Vec<Vec<T>>,
+    els: Vec<T>,
+
+    /// Number of rows
+    rows: usize,
+
+    /// Number of columns
+    cols: usize,
+
+    /// Distance, in elements, from the start of one row to the start of the next
+    stride: usize,
 }
 
 impl<T: Element> DynMatrix<T> {
-    /// Create a new matrix with all elements set to zero.
+    /// Number of elements in an `r`x`c` matrix, or an error if that's too many to count
+    fn checked_area(r: usize, c: usize) -> Result<usize> {
+        r.checked_mul(c)
+            .ok_or_else(|| Error::validation(format!("A {}x{} matrix is too large", r, c)))
+    }
+
+    /// Create a new matrix with every element set to `value`, or an error if it's too large
+    fn try_filled(r: usize, c: usize, value: T) -> Result<Self> {
+        Ok(Self {
+            els: vec![value; Self::checked_area(r, c)?],
+            rows: r,
+            cols: c,
+            stride: c,
+        })
+    }
+
+    /// Create a new matrix with every element set to `value`. Panics if it's too large
+    fn filled(r: usize, c: usize, value: T) -> Self {
+        Self::try_filled(r, c, value).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Create a new matrix with all elements set to zero. Panics if its number of elements
+    /// doesn't fit in a `usize`; see [`DynMatrix::try_zeros`]
     pub fn zeros<D>(dims: D) -> Self
     where
         D: Into<Dims>,
     {
         let Dims(Rows(r), Cols(c)) = dims.into();
-        Self {
-            els: vec![vec![T::zero(); c]; r],
-        }
+        Self::filled(r, c, T::zero())
+    }
+
+    /// [`DynMatrix::zeros`], or an error if the matrix would be too large
+    pub fn try_zeros<D>(dims: D) -> Result<Self>
+    where
+        D: Into<Dims>,
+    {
+        let Dims(Rows(r), Cols(c)) = dims.into();
+        Self::try_filled(r, c, T::zero())
     }
 
     pub fn zeros_like(m: &Self) -> Self {
-        Self {
-            els: vec![vec![T::zero(); m.cols()]; m.rows()],
-        }
+        Self::filled(m.rows(), m.cols(), T::zero())
     }
 
     pub fn ones<D>(dims: D) -> Self
@@ -36,53 +100,80 @@ impl<T: Element> DynMatrix<T> {
         D: Into<Dims>,
     {
         let Dims(Rows(r), Cols(c)) = dims.into();
-        Self {
-            els: vec![vec![T::one(); c]; r],
-        }
+        Self::filled(r, c, T::one())
     }
 
     pub fn ones_like(m: &Self) -> Self {
-        Self {
-            els: vec![vec![T::one(); m.cols()]; m.rows()],
-        }
+        Self::filled(m.rows(), m.cols(), T::one())
     }
 
-    /// Create a new matrix of the given size from a flat array
+    /// Create a new matrix of the given size from a flat array, in row-major order. Panics unless
+    /// `data` has exactly as many elements as the matrix; see [`DynMatrix::try_from_flat`]
     pub fn from_flat<D>(data: &[T], dims: D) -> Self
+    where
+        D: Into<Dims>,
+    {
+        Self::try_from_flat(data, dims).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// [`DynMatrix::from_flat`], or an error unless `data` has exactly as many elements as the
+    /// matrix
+    pub fn try_from_flat<D>(data: &[T], dims: D) -> Result<Self>
     where
         D: Into<Dims>,
     {
         let Dims(Rows(r), Cols(c)) = dims.into();
-        let num_els = r * c;
-        assert_eq!(data.len(), num_els);
-        let mut matrix = Self::zeros((r, c));
-        for i in 0..r {
-            for j in 0..c {
-                matrix.els[i][j] = data[i * c + j];
-            }
+        if Self::checked_area(r, c)? != data.len() {
+            return Err(Error::validation(format!(
+                "A {}x{} matrix needs {} elements, but data has {}",
+                r,
+                c,
+                r * c,
+                data.len()
+            )));
         }
-        matrix
+        Ok(Self {
+            els: data.to_vec(),
+            rows: r,
+            cols: c,
+            stride: c,
+        })
     }
 
     /// Create a new matrix of the given size from a nested array
     pub fn from_nested<const R: usize, const C: usize>(data: &[[T; C]; R]) -> Self {
-        let mut matrix = Self::zeros((R, C));
-        for (i, row) in data.iter().enumerate().take(R) {
-            for (j, el) in row.iter().enumerate().take(C) {
-                matrix.els[i][j] = *el;
-            }
+        Self {
+            els: data.iter().flatten().copied().collect(),
+            rows: R,
+            cols: C,
+            stride: C,
         }
-        matrix
     }
 
+    /// Create a new matrix from its rows. No rows make a 0x0 matrix. Panics unless every row is
+    /// as long as the first; see [`DynMatrix::try_from_vec`]
     pub fn from_vec(data: &[Vec<T>]) -> Self {
-        let mut matrix = Self::zeros((data.len(), data[0].len()));
-        for (i, row) in data.iter().enumerate() {
-            for (j, el) in row.iter().enumerate() {
-                matrix.els[i][j] = *el;
-            }
+        Self::try_from_vec(data).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// [`DynMatrix::from_vec`], or an error unless every row is as long as the first
+    pub fn try_from_vec(data: &[Vec<T>]) -> Result<Self> {
+        let cols = data.first().map_or(0, Vec::len);
+        if let Some((i, row)) = data.iter().enumerate().find(|(_, row)| row.len() != cols) {
+            return Err(Error::validation(format!(
+                "Every row of a matrix must be the same length, but row 0 has {} elements and \
+                 row {} has {}",
+                cols,
+                i,
+                row.len()
+            )));
         }
-        matrix
+        Ok(Self {
+            els: data.iter().flatten().copied().collect(),
+            rows: data.len(),
+            cols,
+            stride: cols,
+        })
     }
 
     pub fn identity<D>(dims: D) -> Self
@@ -92,7 +183,7 @@ impl<T: Element> DynMatrix<T> {
         let Dims(Rows(r), Cols(c)) = dims.into();
         let mut matrix = Self::zeros((r, c));
         for i in 0..r {
-            matrix.els[i][i] = T::one();
+            matrix[(i, i)] = T::one();
         }
         matrix
     }
@@ -100,29 +191,476 @@ impl<T: Element> DynMatrix<T> {
     pub fn identity_like(m: &Self) -> Self {
         let mut matrix = Self::zeros(m.dims());
         for i in 0..m.rows() {
-            matrix.els[i][i] = T::one();
+            matrix[(i, i)] = T::one();
         }
         matrix
     }
 
+    /// Convert into a fixed-size [`Matrix`], for when the dimensions are known. Fails unless this
+    /// matrix has exactly `R` rows and `C` columns
+    pub fn into_fixed<const R: usize, const C: usize>(self) -> Result<Matrix<T, R, C>> {
+        self.try_into()
+    }
+
+    /// Distance, in elements, between the starts of consecutive rows in the underlying storage
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Iterate over the storage one row at a time. Chunks may include padding past `cols`
+    fn row_chunks(&self) -> std::slice::Chunks<'_, T> {
+        // A zero-column matrix has no elements, so any non-zero chunk size works
+        self.els.chunks(self.stride.max(1))
+    }
+
+    fn row_chunks_mut(&mut self) -> std::slice::ChunksMut<'_, T> {
+        self.els.chunks_mut(self.stride.max(1))
+    }
+
+    /// Apply `f` to each row of this matrix, along with its row index.
+    ///
+    /// With the `parallel` feature, rows are processed concurrently when the matrix has at least
+    /// [`PARALLEL_THRESHOLD`] elements.
+    fn for_each_row_mut<F>(&mut self, f: F)
+    where
+        F: Fn(usize, &mut [T]) + Send + Sync,
+    {
+        let cols = self.cols;
+
+        #[cfg(feature = "parallel")]
+        if self.dims().area() >= PARALLEL_THRESHOLD {
+            self.els
+                .par_chunks_mut(self.stride.max(1))
+                .enumerate()
+                .for_each(|(i, row)| f(i, &mut row[..cols]));
+            return;
+        }
+
+        self.row_chunks_mut()
+            .enumerate()
+            .for_each(|(i, row)| f(i, &mut row[..cols]));
+    }
+
+    /// Apply `f` to each band of up to `block_rows` consecutive rows, along with the index of the
+    /// band's first row. Row `r` of the band starts at `r * stride` within the slice, and the
+    /// last band may be shorter.
+    ///
+    /// Parallelized like [`DynMatrix::for_each_row_mut`].
+    fn for_each_row_block_mut<F>(&mut self, block_rows: usize, f: F)
+    where
+        F: Fn(usize, &mut [T]) + Send + Sync,
+    {
+        let chunk = (self.stride * block_rows).max(1);
+
+        #[cfg(feature = "parallel")]
+        if self.dims().area() >= PARALLEL_THRESHOLD {
+            self.els
+                .par_chunks_mut(chunk)
+                .enumerate()
+                .for_each(|(b, rows)| f(b * block_rows, rows));
+            return;
+        }
+
+        self.els
+            .chunks_mut(chunk)
+            .enumerate()
+            .for_each(|(b, rows)| f(b * block_rows, rows));
+    }
+
+    /// `self * other`, one output row at a time. Fast while `other` fits in cache.
+    fn mul_by_rows(&self, other: &Self) -> Self {
+        let mut result = DynMatrix::zeros((self.rows(), other.cols()));
+        // Each output row only depends on the matching row of `self`, so rows can be computed
+        // independently of one another. Within a row, accumulate `lhs[i][k] * other[k]` across
+        // the whole output row at once, so that both `other` and the output are walked
+        // contiguously instead of striding down columns of `other`
+        result.for_each_row_mut(|i, row| {
+            for (k, &a) in self[i].iter().enumerate() {
+                for (el, &b) in row.iter_mut().zip(&other[k]) {
+                    *el += a * b;
+                }
+            }
+        });
+        result
+    }
+
+    /// `self * other`, computed in [`MUL_BLOCK_SIZE`] blocks.
+    ///
+    /// Same access pattern as [`DynMatrix::mul_by_rows`], but restricted to one block of `other`
+    /// at a time, which is reused across a band of output rows before moving on. Each output
+    /// element still accumulates its products in increasing `k`, so the result is identical.
+    fn mul_blocked(&self, other: &Self) -> Self {
+        let mut result = DynMatrix::zeros((self.rows(), other.cols()));
+        let (inner, cols, stride) = (self.cols(), result.cols(), result.stride);
+        result.for_each_row_block_mut(MUL_BLOCK_SIZE, |first_row, band| {
+            let band_rows = band.len().div_ceil(stride);
+            for k0 in (0..inner).step_by(MUL_BLOCK_SIZE) {
+                let k1 = (k0 + MUL_BLOCK_SIZE).min(inner);
+                for j0 in (0..cols).step_by(MUL_BLOCK_SIZE) {
+                    let j1 = (j0 + MUL_BLOCK_SIZE).min(cols);
+                    for r in 0..band_rows {
+                        let lhs = &self[first_row + r][k0..k1];
+                        let out = &mut band[r * stride + j0..r * stride + j1];
+                        for (k, &a) in (k0..k1).zip(lhs) {
+                            for (el, &b) in out.iter_mut().zip(&other[k][j0..j1]) {
+                                *el += a * b;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        result
+    }
+
     pub fn transpose(&self) -> Self {
-        let mut result = Self::zeros(Dims(Rows(self.cols()), Cols(self.rows())));
+        let mut result = Self::zeros(self.dims().transposed());
         for i in 0..self.rows() {
             for j in 0..self.cols() {
-                result.els[j][i] = self.els[i][j];
+                result[(j, i)] = self[(i, j)];
             }
         }
         result
     }
+
+    /// Multiply this matrix elementwise by `other` (the Hadamard product), in place.
+    ///
+    /// Panics if the matrices have different dimensions.
+    pub fn hadamard_assign(&mut self, other: &Self) {
+        self.zip_apply(other, |a, b| a * b);
+    }
+
+    /// The elementwise (Hadamard) product of this matrix and `other`
+    pub fn hadamard(&self, other: &Self) -> Self {
+        self.zip_map(other, |a, b| a * b)
+    }
+
+    /// Every element, in row-major order
+    fn elements(&self) -> impl Iterator<Item = &T> {
+        let cols = self.cols;
+        self.row_chunks().flat_map(move |row| &row[..cols])
+    }
+
+    /// A matrix of the same size, with `f` applied to each element.
+    ///
+    /// Parallelized like the arithmetic operators, so `f` may be called from several threads.
+    pub fn map<U, F>(&self, f: F) -> DynMatrix<U>
+    where
+        U: Element,
+        F: Fn(T) -> U + Send + Sync,
+    {
+        let mut result = DynMatrix::zeros(self.dims());
+        result.for_each_row_mut(|i, row| {
+            for (out, &a) in row.iter_mut().zip(&self[i]) {
+                *out = f(a);
+            }
+        });
+        result
+    }
+
+    /// Apply `f` to each element, in place
+    pub fn apply<F>(&mut self, f: F)
+    where
+        F: Fn(T) -> T + Send + Sync,
+    {
+        self.for_each_row_mut(|_, row| {
+            for a in row.iter_mut() {
+                *a = f(*a);
+            }
+        });
+    }
+
+    /// A matrix of the same size, with `f` applied to each pair of elements at the same position
+    /// in this matrix and `other`.
+    ///
+    /// Panics if the matrices have different dimensions.
+    pub fn zip_map<U, V, F>(&self, other: &DynMatrix<U>, f: F) -> DynMatrix<V>
+    where
+        U: Element,
+        V: Element,
+        F: Fn(T, U) -> V + Send + Sync,
+    {
+        assert_eq!(self.rows(), other.rows());
+        assert_eq!(self.cols(), other.cols());
+        let mut result = DynMatrix::zeros(self.dims());
+        result.for_each_row_mut(|i, row| {
+            for ((out, &a), &b) in row.iter_mut().zip(&self[i]).zip(&other[i]) {
+                *out = f(a, b);
+            }
+        });
+        result
+    }
+
+    /// Replace each element with `f` of it and the element at the same position in `other`.
+    ///
+    /// Panics if the matrices have different dimensions.
+    pub fn zip_apply<U, F>(&mut self, other: &DynMatrix<U>, f: F)
+    where
+        U: Element,
+        F: Fn(T, U) -> T + Send + Sync,
+    {
+        assert_eq!(self.rows(), other.rows());
+        assert_eq!(self.cols(), other.cols());
+        self.for_each_row_mut(|i, row| {
+            for (a, &b) in row.iter_mut().zip(&other[i]) {
+                *a = f(*a, b);
+            }
+        });
+    }
+
+    /// The sum of every element. Zero for an empty matrix
+    pub fn sum(&self) -> T {
+        self.elements().fold(T::zero(), |acc, &a| acc + a)
+    }
+
+    /// The sum of the elements on the main diagonal. Matrices needn't be square: for one that
+    /// isn't, that's the diagonal starting at the top left corner
+    pub fn trace(&self) -> T {
+        (0..self.rows.min(self.cols)).fold(T::zero(), |acc, i| acc + self[(i, i)])
+    }
+}
+
+impl<T: Element + PartialOrd> DynMatrix<T> {
+    /// The smallest element, or `None` for an empty matrix. Elements that don't compare (NaN)
+    /// are never smaller than the one before them
+    pub fn min(&self) -> Option<T> {
+        self.elements()
+            .copied()
+            .reduce(|least, a| if a < least { a } else { least })
+    }
+
+    /// The largest element, or `None` for an empty matrix. Elements that don't compare (NaN) are
+    /// never larger than the one before them
+    pub fn max(&self) -> Option<T> {
+        self.elements()
+            .copied()
+            .reduce(|most, a| if a > most { a } else { most })
+    }
+}
+
+/// Fallible forms of the arithmetic operators, for matrices whose dimensions aren't known to fit
+/// together (e.g. ones supplied by a user). The operators themselves panic on a mismatch.
+impl<T: Element> DynMatrix<T>
+where
+    T: for<'x> MulAssign<&'x T>,
+    for<'x> &'x T: Add<Output = T> + Sub<Output = T>,
+{
+    /// Fail unless `other` has the same dimensions as this matrix
+    fn check_same_dims(&self, other: &Self, verb: &str) -> Result<()> {
+        if self.dims() != other.dims() {
+            return Err(Error::unprocessable(format!(
+                "Cannot {} a {} matrix and a {} matrix: their dimensions must match",
+                verb,
+                self.dims(),
+                other.dims()
+            )));
+        }
+        Ok(())
+    }
+
+    /// `self + other`, or an error if their dimensions differ
+    pub fn try_add(&self, other: &Self) -> Result<Self> {
+        self.check_same_dims(other, "add")?;
+        Ok(self.clone() + other)
+    }
+
+    /// `self - other`, or an error if their dimensions differ
+    pub fn try_sub(&self, other: &Self) -> Result<Self> {
+        self.check_same_dims(other, "subtract")?;
+        Ok(self.clone() - other)
+    }
+
+    /// `self * other`, or an error if `self` doesn't have as many columns as `other` has rows
+    pub fn try_mul(&self, other: &Self) -> Result<Self> {
+        if self.cols() != other.rows() {
+            return Err(Error::unprocessable(format!(
+                "Cannot multiply a {} matrix by a {} matrix: the first must have as many \
+                 columns as the second has rows",
+                self.dims(),
+                other.dims()
+            )));
+        }
+        Ok(self.clone() * other)
+    }
+
+    /// The elementwise product of `self` and `other`, or an error if their dimensions differ
+    pub fn try_hadamard(&self, other: &Self) -> Result<Self> {
+        self.check_same_dims(other, "take the elementwise product of")?;
+        Ok(self.hadamard(other))
+    }
+}
+
+impl<T: Element + Float> DynMatrix<T> {
+    /// Solve `self · x = b` for `x`, by LU decomposition with partial pivoting.
+    ///
+    /// `self` must be square, and `b` must have as many rows as `self`. Each column of `b` is a
+    /// separate right-hand side, with a matching column in the result.
+    ///
+    /// Fails if the dimensions don't fit, or if `self` is singular (to within rounding error).
+    pub fn solve(&self, b: &DynMatrix<T>) -> Result<DynMatrix<T>> {
+        let n = self.rows();
+        if self.cols() != n {
+            return Err(Error::unprocessable(format!(
+                "Only square systems can be solved, but the coefficient matrix is {}x{}",
+                self.rows(),
+                self.cols()
+            )));
+        }
+        if b.rows() != n {
+            return Err(Error::unprocessable(format!(
+                "The right-hand side has {} rows, but the {}x{} coefficient matrix needs {}",
+                b.rows(),
+                n,
+                n,
+                n
+            )));
+        }
+
+        // Pivots smaller than this, relative to the largest element, are treated as zero
+        let largest = (0..n)
+            .flat_map(|i| self[i].iter())
+            .fold(T::zero(), |acc, &el| acc.max(el.abs()));
+        let tolerance = largest * T::epsilon() * T::from(n).unwrap_or_else(T::one);
+
+        // Factor in place: U ends up on and above the diagonal, and the multipliers of L below
+        // it. The same row operations are applied to `x`, which leaves it holding L⁻¹·P·b
+        let mut lu = self.clone();
+        let mut x = b.clone();
+        for k in 0..n {
+            let pivot = (k..n)
+                .max_by(|&i, &j| {
+                    lu[(i, k)]
+                        .abs()
+                        .partial_cmp(&lu[(j, k)].abs())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap_or(k);
+            let pivot_value = lu[(pivot, k)].abs();
+            if pivot_value.is_nan() || pivot_value <= tolerance {
+                return Err(Error::unprocessable(
+                    "The coefficient matrix is singular, so the system has no unique solution",
+                ));
+            }
+            lu.swap_rows(k, pivot);
+            x.swap_rows(k, pivot);
+
+            for i in k + 1..n {
+                let factor = lu[(i, k)] / lu[(k, k)];
+                lu[(i, k)] = factor;
+                for j in k + 1..n {
+                    let el = lu[(k, j)];
+                    lu[(i, j)] -= factor * el;
+                }
+                for j in 0..x.cols() {
+                    let el = x[(k, j)];
+                    x[(i, j)] -= factor * el;
+                }
+            }
+        }
+
+        // Back-substitute through U, from the bottom row up
+        for i in (0..n).rev() {
+            for j in 0..x.cols() {
+                let mut sum = x[(i, j)];
+                for k in i + 1..n {
+                    sum -= lu[(i, k)] * x[(k, j)];
+                }
+                x[(i, j)] = sum / lu[(i, i)];
+            }
+        }
+        Ok(x)
+    }
+
+    /// The 1-norm: the largest sum of the absolute values in any one column
+    pub fn norm_l1(&self) -> T {
+        (0..self.cols)
+            .map(|j| (0..self.rows).fold(T::zero(), |acc, i| acc + self[(i, j)].abs()))
+            .fold(T::zero(), |a, b| a.max(b))
+    }
+
+    /// The infinity-norm: the largest sum of the absolute values in any one row
+    pub fn norm_inf(&self) -> T {
+        (0..self.rows)
+            .map(|i| self[i].iter().fold(T::zero(), |acc, &el| acc + el.abs()))
+            .fold(T::zero(), |a, b| a.max(b))
+    }
+
+    /// The Frobenius norm: the square root of the sum of the squares of every element
+    pub fn norm_fro(&self) -> T {
+        self.elements()
+            .fold(T::zero(), |acc, &el| acc + el * el)
+            .sqrt()
+    }
+
+    /// The largest absolute difference between corresponding elements of `self` and `other`. NaN
+    /// if either has a NaN. Fails if their dimensions differ
+    pub fn max_abs_diff(&self, other: &Self) -> Result<T> {
+        if self.dims() != other.dims() {
+            return Err(Error::unprocessable(format!(
+                "Cannot compare a {} matrix and a {} matrix: their dimensions must match",
+                self.dims(),
+                other.dims()
+            )));
+        }
+        Ok(max_abs_diff(
+            (0..self.rows).flat_map(|i| self[i].iter().zip(other[i].iter())),
+        ))
+    }
+
+    /// Whether `self` and `other` have the same dimensions, and no corresponding elements differ
+    /// by more than `epsilon`. Never true of matrices with NaNs
+    pub fn approx_eq(&self, other: &Self, epsilon: T) -> bool {
+        self.max_abs_diff(other).is_ok_and(|d| d <= epsilon)
+    }
+
+    /// The condition number in the 1-norm, `‖A‖₁·‖A⁻¹‖₁`: roughly how much relative error in `b`
+    /// can be amplified in the solution of `A·x = b`.
+    ///
+    /// Infinite if `self` is singular. Fails if `self` isn't square.
+    pub fn condition_number(&self) -> Result<T> {
+        if self.rows != self.cols {
+            return Err(Error::unprocessable(format!(
+                "Only square matrices have a condition number, but this one is {}x{}",
+                self.rows, self.cols
+            )));
+        }
+        match self.solve(&Self::identity_like(self)) {
+            Ok(inverse) => Ok(self.norm_l1() * inverse.norm_l1()),
+            Err(Error::Unprocessable(_)) => Ok(T::infinity()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The largest absolute difference between the elements of any pair, or NaN if any are NaN
+pub(crate) fn max_abs_diff<'a, T: Element + Float + 'a>(
+    pairs: impl Iterator<Item = (&'a T, &'a T)>,
+) -> T {
+    pairs.map(|(&a, &b)| (a - b).abs()).fold(
+        T::zero(),
+        |acc, d| if d.is_nan() || d > acc { d } else { acc },
+    )
+}
+
+impl<T: Element> DynMatrix<T> {
+    /// Exchange two rows of this matrix
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        for j in 0..self.cols {
+            self.els.swap(a * self.stride + j, b * self.stride + j);
+        }
+    }
 }
 
 impl<T: Element> HasDims for DynMatrix<T> {
     fn rows(&self) -> usize {
-        self.els.len()
+        self.rows
     }
 
     fn cols(&self) -> usize {
-        self.els[0].len()
+        self.cols
     }
 
     fn dims(&self) -> Dims {
@@ -138,85 +676,562 @@ pub struct DynMatrixIterator<'a, T: Element> {
 impl<'a, T: Element> Iterator for DynMatrixIterator<'a, T> {
     type Item = Vec<T>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.row < self.matrix.rows() {
-            let result = &self.matrix.els[self.row];
-            self.row += 1;
-            Some(result.to_vec())
-        } else {
-            None
-        }
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row < self.matrix.rows() {
+            let result = &self.matrix[self.row];
+            self.row += 1;
+            Some(result.to_vec())
+        } else {
+            None
+        }
+    }
+}
+
+pub struct DynMatrixIntoIterator<T: Element> {
+    matrix: DynMatrix<T>,
+    row: usize,
+}
+
+impl<T: Element> Iterator for DynMatrixIntoIterator<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row < self.matrix.rows() {
+            let result = &self.matrix[self.row];
+            self.row += 1;
+            Some(result.to_vec())
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Element> IntoIterator for DynMatrix<T> {
+    type Item = Vec<T>;
+    type IntoIter = DynMatrixIntoIterator<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        DynMatrixIntoIterator {
+            matrix: self,
+            row: 0,
+        }
+    }
+}
+
+impl<'a, T: Element> IntoIterator for &'a DynMatrix<T> {
+    type Item = Vec<T>;
+    type IntoIter = DynMatrixIterator<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        DynMatrixIterator {
+            matrix: self,
+            row: 0,
+        }
+    }
+}
+
+impl<T: Element> Index<(usize, usize)> for DynMatrix<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        assert!(y < self.cols);
+        &self.els[x * self.stride + y]
+    }
+}
+
+impl<T: Element> Index<usize> for DynMatrix<T> {
+    type Output = [T];
+
+    fn index(&self, x: usize) -> &Self::Output {
+        let start = x * self.stride;
+        &self.els[start..start + self.cols]
+    }
+}
+
+impl<T: Element> IndexMut<usize> for DynMatrix<T> {
+    fn index_mut(&mut self, x: usize) -> &mut Self::Output {
+        let start = x * self.stride;
+        &mut self.els[start..start + self.cols]
+    }
+}
+
+impl<T: Element> IndexMut<(usize, usize)> for DynMatrix<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
+        assert!(y < self.cols);
+        &mut self.els[x * self.stride + y]
+    }
+}
+
+/// Views onto part of a matrix, for working on rows, columns, or regions in place without copying
+/// them out first.
+impl<T: Element> DynMatrix<T> {
+    pub fn row(&self, i: usize) -> RowView<'_, T> {
+        RowView { els: &self[i] }
+    }
+
+    pub fn row_mut(&mut self, i: usize) -> RowViewMut<'_, T> {
+        RowViewMut { els: &mut self[i] }
+    }
+
+    pub fn col(&self, j: usize) -> ColView<'_, T> {
+        assert!(j < self.cols());
+        ColView {
+            matrix: self,
+            col: j,
+        }
+    }
+
+    pub fn col_mut(&mut self, j: usize) -> ColViewMut<'_, T> {
+        assert!(j < self.cols());
+        ColViewMut {
+            matrix: self,
+            col: j,
+        }
+    }
+
+    /// View the given region of this matrix. Panics if the region is out of bounds
+    pub fn submatrix<R: Into<Roi>>(&self, roi: R) -> SubMatrixView<'_, T> {
+        let roi = roi.into();
+        assert!(roi.extent().fits_within(&self.dims()));
+        SubMatrixView { matrix: self, roi }
+    }
+
+    /// Mutably view the given region of this matrix. Panics if the region is out of bounds
+    pub fn submatrix_mut<R: Into<Roi>>(&mut self, roi: R) -> SubMatrixViewMut<'_, T> {
+        let roi = roi.into();
+        assert!(roi.extent().fits_within(&self.dims()));
+        SubMatrixViewMut { matrix: self, roi }
+    }
+
+    /// Fail unless the given region lies within this matrix
+    fn check_block(&self, roi: Roi) -> Result<()> {
+        // Checked, since the region may come from far outside the matrix
+        let fits = roi
+            .row
+            .checked_add(roi.rows)
+            .is_some_and(|r| r <= self.rows)
+            && roi
+                .col
+                .checked_add(roi.cols)
+                .is_some_and(|c| c <= self.cols);
+        if !fits {
+            return Err(Error::unprocessable(format!(
+                "A {} block at ({}, {}) doesn't fit in a {} matrix",
+                roi.dims(),
+                roi.row,
+                roi.col,
+                self.dims()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Copy out the `rows`x`cols` block whose top-left element is at (`r0`, `c0`), or an error if
+    /// it doesn't fit within this matrix. See [`DynMatrix::submatrix`] to look at it in place
+    pub fn block(&self, r0: usize, c0: usize, rows: usize, cols: usize) -> Result<Self> {
+        let roi = Roi {
+            row: r0,
+            col: c0,
+            rows,
+            cols,
+        };
+        self.check_block(roi)?;
+        Ok(self.submatrix(roi).to_matrix())
+    }
+
+    /// Overwrite the block whose top-left element is at (`r0`, `c0`) with `other`, or fail,
+    /// leaving this matrix as it was, if `other` doesn't fit there
+    pub fn set_block(&mut self, r0: usize, c0: usize, other: &Self) -> Result<()> {
+        let roi = Roi {
+            row: r0,
+            col: c0,
+            rows: other.rows,
+            cols: other.cols,
+        };
+        self.check_block(roi)?;
+        let mut block = self.submatrix_mut(roi);
+        for (i, row) in block.iter_rows_mut().enumerate() {
+            row.copy_from_slice(&other[i]);
+        }
+        Ok(())
+    }
+}
+
+/// A view of one row of a [`DynMatrix`]
+#[derive(Clone, Copy, Debug)]
+pub struct RowView<'a, T: Element> {
+    els: &'a [T],
+}
+
+impl<'a, T: Element> RowView<'a, T> {
+    pub fn len(&self) -> usize {
+        self.els.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.els.is_empty()
+    }
+
+    pub fn get(&self, j: usize) -> Option<&'a T> {
+        self.els.get(j)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'a, T> {
+        self.els.iter()
+    }
+
+    pub fn as_slice(&self) -> &'a [T] {
+        self.els
+    }
+}
+
+impl<T: Element> Index<usize> for RowView<'_, T> {
+    type Output = T;
+
+    fn index(&self, j: usize) -> &Self::Output {
+        &self.els[j]
+    }
+}
+
+impl<'a, T: Element> IntoIterator for RowView<'a, T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.els.iter()
+    }
+}
+
+/// A mutable view of one row of a [`DynMatrix`]
+#[derive(Debug)]
+pub struct RowViewMut<'a, T: Element> {
+    els: &'a mut [T],
+}
+
+impl<'a, T: Element> RowViewMut<'a, T> {
+    pub fn len(&self) -> usize {
+        self.els.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.els.is_empty()
+    }
+
+    pub fn get(&self, j: usize) -> Option<&T> {
+        self.els.get(j)
+    }
+
+    pub fn get_mut(&mut self, j: usize) -> Option<&mut T> {
+        self.els.get_mut(j)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.els.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.els.iter_mut()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        self.els
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.els
+    }
+}
+
+impl<T: Element> Index<usize> for RowViewMut<'_, T> {
+    type Output = T;
+
+    fn index(&self, j: usize) -> &Self::Output {
+        &self.els[j]
+    }
+}
+
+impl<T: Element> IndexMut<usize> for RowViewMut<'_, T> {
+    fn index_mut(&mut self, j: usize) -> &mut Self::Output {
+        &mut self.els[j]
+    }
+}
+
+impl<'a, T: Element> IntoIterator for RowViewMut<'a, T> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.els.iter_mut()
+    }
+}
+
+/// A view of one column of a [`DynMatrix`]
+#[derive(Clone, Copy, Debug)]
+pub struct ColView<'a, T: Element> {
+    matrix: &'a DynMatrix<T>,
+    col: usize,
+}
+
+impl<'a, T: Element> ColView<'a, T> {
+    pub fn len(&self) -> usize {
+        self.matrix.rows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<&'a T> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(&self.matrix[(i, self.col)])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &'a T> + ExactSizeIterator {
+        let col = self.col;
+        self.matrix.row_chunks().map(move |row| &row[col])
+    }
+}
+
+impl<T: Element> Index<usize> for ColView<'_, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.matrix[(i, self.col)]
+    }
+}
+
+/// A mutable view of one column of a [`DynMatrix`]
+#[derive(Debug)]
+pub struct ColViewMut<'a, T: Element> {
+    matrix: &'a mut DynMatrix<T>,
+    col: usize,
+}
+
+impl<T: Element> ColViewMut<'_, T> {
+    pub fn len(&self) -> usize {
+        self.matrix.rows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(&self.matrix[(i, self.col)])
+    }
+
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(&mut self.matrix[(i, self.col)])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        let col = self.col;
+        self.matrix.row_chunks().map(move |row| &row[col])
+    }
+
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut T> + ExactSizeIterator {
+        let col = self.col;
+        self.matrix.row_chunks_mut().map(move |row| &mut row[col])
+    }
+}
+
+impl<T: Element> Index<usize> for ColViewMut<'_, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.matrix[(i, self.col)]
+    }
+}
+
+impl<T: Element> IndexMut<usize> for ColViewMut<'_, T> {
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        &mut self.matrix[(i, self.col)]
+    }
+}
+
+/// A view of a rectangular region of a [`DynMatrix`]. Indices are relative to the region
+#[derive(Clone, Copy, Debug)]
+pub struct SubMatrixView<'a, T: Element> {
+    matrix: &'a DynMatrix<T>,
+    roi: Roi,
+}
+
+impl<'a, T: Element> SubMatrixView<'a, T> {
+    pub fn roi(&self) -> Roi {
+        self.roi
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Option<&'a T> {
+        if i >= self.roi.rows || j >= self.roi.cols {
+            return None;
+        }
+        Some(&self.matrix[(self.roi.row + i, self.roi.col + j)])
+    }
+
+    /// The `i`th row of the region
+    pub fn row(&self, i: usize) -> &'a [T] {
+        assert!(i < self.roi.rows);
+        &self.matrix[self.roi.row + i][self.roi.col..self.roi.col + self.roi.cols]
+    }
+
+    /// Iterates over the rows of the region, as slices
+    pub fn iter_rows(&self) -> impl DoubleEndedIterator<Item = &'a [T]> + ExactSizeIterator {
+        let Roi {
+            row,
+            col,
+            rows,
+            cols,
+        } = self.roi;
+        self.matrix
+            .row_chunks()
+            .skip(row)
+            .take(rows)
+            .map(move |r| &r[col..col + cols])
+    }
+
+    /// Iterates over every element of the region, in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> {
+        self.iter_rows().flatten()
+    }
+
+    /// Copy the region out into its own matrix
+    pub fn to_matrix(&self) -> DynMatrix<T> {
+        DynMatrix {
+            els: self.iter_rows().flatten().copied().collect(),
+            rows: self.roi.rows,
+            cols: self.roi.cols,
+            stride: self.roi.cols,
+        }
+    }
+}
+
+impl<T: Element> HasDims for SubMatrixView<'_, T> {
+    fn rows(&self) -> usize {
+        self.roi.rows
+    }
+
+    fn cols(&self) -> usize {
+        self.roi.cols
+    }
+
+    fn dims(&self) -> Dims {
+        (self.roi.rows, self.roi.cols).into()
+    }
+}
+
+impl<T: Element> Index<(usize, usize)> for SubMatrixView<'_, T> {
+    type Output = T;
+
+    fn index(&self, (i, j): (usize, usize)) -> &Self::Output {
+        self.get(i, j).expect("Index out of bounds of submatrix")
     }
 }
 
-pub struct DynMatrixIntoIterator<T: Element> {
-    matrix: DynMatrix<T>,
-    row: usize,
+/// A mutable view of a rectangular region of a [`DynMatrix`]. Indices are relative to the region
+#[derive(Debug)]
+pub struct SubMatrixViewMut<'a, T: Element> {
+    matrix: &'a mut DynMatrix<T>,
+    roi: Roi,
 }
 
-impl<T: Element> Iterator for DynMatrixIntoIterator<T> {
-    type Item = Vec<T>;
+impl<T: Element> SubMatrixViewMut<'_, T> {
+    pub fn roi(&self) -> Roi {
+        self.roi
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.row < self.matrix.rows() {
-            let result = &self.matrix.els[self.row];
-            self.row += 1;
-            Some(result.to_vec())
-        } else {
-            None
+    /// Reborrow as an immutable view
+    pub fn as_view(&self) -> SubMatrixView<'_, T> {
+        SubMatrixView {
+            matrix: self.matrix,
+            roi: self.roi,
         }
     }
-}
 
-impl<T: Element> IntoIterator for DynMatrix<T> {
-    type Item = Vec<T>;
-    type IntoIter = DynMatrixIntoIterator<T>;
+    pub fn get(&self, i: usize, j: usize) -> Option<&T> {
+        if i >= self.roi.rows || j >= self.roi.cols {
+            return None;
+        }
+        Some(&self.matrix[(self.roi.row + i, self.roi.col + j)])
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        DynMatrixIntoIterator {
-            matrix: self,
-            row: 0,
+    pub fn get_mut(&mut self, i: usize, j: usize) -> Option<&mut T> {
+        if i >= self.roi.rows || j >= self.roi.cols {
+            return None;
         }
+        Some(&mut self.matrix[(self.roi.row + i, self.roi.col + j)])
     }
-}
 
-impl<'a, T: Element> IntoIterator for &'a DynMatrix<T> {
-    type Item = Vec<T>;
-    type IntoIter = DynMatrixIterator<'a, T>;
+    /// The `i`th row of the region
+    pub fn row_mut(&mut self, i: usize) -> &mut [T] {
+        assert!(i < self.roi.rows);
+        let (col, cols) = (self.roi.col, self.roi.cols);
+        &mut self.matrix[self.roi.row + i][col..col + cols]
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        DynMatrixIterator {
-            matrix: self,
-            row: 0,
-        }
+    /// Iterates over the rows of the region, as mutable slices
+    pub fn iter_rows_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut [T]> + ExactSizeIterator {
+        let Roi {
+            row,
+            col,
+            rows,
+            cols,
+        } = self.roi;
+        self.matrix
+            .row_chunks_mut()
+            .skip(row)
+            .take(rows)
+            .map(move |r| &mut r[col..col + cols])
     }
-}
 
-impl<T: Element> Index<(usize, usize)> for DynMatrix<T> {
-    type Output = T;
+    /// Iterates over every element of the region, in row-major order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.iter_rows_mut().flatten()
+    }
 
-    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
-        &self.els[x][y]
+    /// Set every element of the region to `value`
+    pub fn fill(&mut self, value: T) {
+        for row in self.iter_rows_mut() {
+            row.fill(value);
+        }
     }
 }
 
-impl<T: Element> Index<usize> for DynMatrix<T> {
-    type Output = [T];
+impl<T: Element> HasDims for SubMatrixViewMut<'_, T> {
+    fn rows(&self) -> usize {
+        self.roi.rows
+    }
 
-    fn index(&self, x: usize) -> &Self::Output {
-        &self.els[x]
+    fn cols(&self) -> usize {
+        self.roi.cols
+    }
+
+    fn dims(&self) -> Dims {
+        (self.roi.rows, self.roi.cols).into()
     }
 }
 
-impl<T: Element> IndexMut<usize> for DynMatrix<T> {
-    fn index_mut(&mut self, x: usize) -> &mut Self::Output {
-        &mut self.els[x]
+impl<T: Element> Index<(usize, usize)> for SubMatrixViewMut<'_, T> {
+    type Output = T;
+
+    fn index(&self, (i, j): (usize, usize)) -> &Self::Output {
+        self.get(i, j).expect("Index out of bounds of submatrix")
     }
 }
 
-impl<T: Element> IndexMut<(usize, usize)> for DynMatrix<T> {
-    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
-        &mut self.els[x][y]
+impl<T: Element> IndexMut<(usize, usize)> for SubMatrixViewMut<'_, T> {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut Self::Output {
+        self.get_mut(i, j)
+            .expect("Index out of bounds of submatrix")
     }
 }
 
@@ -228,11 +1243,7 @@ where
     fn add_assign(&mut self, other: &Self) {
         assert_eq!(self.rows(), other.rows());
         assert_eq!(self.cols(), other.cols());
-        for i in 0..self.rows() {
-            for j in 0..self.cols() {
-                self[(i, j)] += other[(i, j)];
-            }
-        }
+        self.for_each_row_mut(|i, row| T::add_slices(row, &other[i]));
     }
 }
 
@@ -242,11 +1253,7 @@ where
     for<'x> &'x T: Add<Output = T>,
 {
     fn add_assign(&mut self, other: &T) {
-        for i in 0..self.rows() {
-            for j in 0..self.cols() {
-                self[(i, j)] += *other;
-            }
-        }
+        self.apply(|a| a + *other);
     }
 }
 
@@ -258,11 +1265,7 @@ where
     fn sub_assign(&mut self, other: &Self) {
         assert_eq!(self.rows(), other.rows());
         assert_eq!(self.cols(), other.cols());
-        for i in 0..self.rows() {
-            for j in 0..self.cols() {
-                self[(i, j)] -= other[(i, j)];
-            }
-        }
+        self.for_each_row_mut(|i, row| T::sub_slices(row, &other[i]));
     }
 }
 
@@ -272,11 +1275,7 @@ where
     for<'x> &'x T: Sub<Output = T>,
 {
     fn sub_assign(&mut self, other: &T) {
-        for i in 0..self.rows() {
-            for j in 0..self.cols() {
-                self[(i, j)] -= *other;
-            }
-        }
+        self.apply(|a| a - *other);
     }
 }
 
@@ -287,15 +1286,14 @@ where
 {
     fn mul_assign(&mut self, other: &DynMatrix<T>) {
         assert_eq!(self.cols(), other.rows());
-        let mut result = DynMatrix::zeros((self.rows(), other.cols()));
-        for i in 0..self.rows() {
-            for j in 0..other.cols() {
-                for k in 0..self.cols() {
-                    result[(i, j)] += self[(i, k)] * other[(k, j)];
-                }
-            }
-        }
-        *self = result;
+        let large = [self.rows(), self.cols(), other.cols()]
+            .iter()
+            .all(|&d| d >= BLOCKED_MUL_THRESHOLD);
+        *self = if large {
+            self.mul_blocked(other)
+        } else {
+            self.mul_by_rows(other)
+        };
     }
 }
 
@@ -305,20 +1303,26 @@ where
     T: Element + Sized + for<'x> MulAssign<&'x T>,
 {
     fn mul_assign(&mut self, other: &T) {
-        for i in 0..self.rows() {
-            for j in 0..self.cols() {
-                self[(i, j)] *= other;
-            }
-        }
+        self.for_each_row_mut(|_, row| T::scale_slice(row, *other));
     }
 }
 
 impl<T: Element> From<DynMatrix<T>> for Vec<Vec<T>> {
     fn from(matrix: DynMatrix<T>) -> Self {
-        matrix.els
+        matrix.into_iter().collect()
     }
 }
 
+impl<T: Element> PartialEq for DynMatrix<T> {
+    /// Matrices are equal when they have the same dimensions and elements, regardless of how
+    /// they're laid out in memory
+    fn eq(&self, other: &Self) -> bool {
+        self.dims() == other.dims() && (0..self.rows()).all(|i| self[i] == other[i])
+    }
+}
+
+impl<T: Element + Eq> Eq for DynMatrix<T> {}
+
 impl<T: Element> From<&DynMatrix<T>> for String {
     fn from(matrix: &DynMatrix<T>) -> Self {
         serde_json::to_string(matrix).unwrap()
@@ -344,11 +1348,41 @@ impl<T: Element, const R: usize, const C: usize> From<Matrix<T, R, C>> for DynMa
     }
 }
 
+impl<T: Element, const R: usize, const C: usize> TryFrom<DynMatrix<T>> for Matrix<T, R, C> {
+    type Error = Error;
+
+    /// Fails unless `matrix` has exactly `R` rows and `C` columns
+    fn try_from(matrix: DynMatrix<T>) -> Result<Self> {
+        if matrix.rows() != R || matrix.cols() != C {
+            return Err(Error::unprocessable(format!(
+                "Cannot make a {}x{} matrix from a {}x{} matrix: their dimensions must match",
+                R,
+                C,
+                matrix.rows(),
+                matrix.cols()
+            )));
+        }
+        let mut result = Matrix::zeros();
+        for i in 0..R {
+            result[i].copy_from_slice(&matrix[i]);
+        }
+        Ok(result)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::from_mat::FromDynMat;
 
     use super::*;
+    extern crate test;
+    use test::Bencher;
+
+    /// A matrix large enough to cross [`PARALLEL_THRESHOLD`], with distinct values everywhere
+    fn large_matrix(rows: usize, cols: usize) -> DynMatrix<i64> {
+        let data: Vec<i64> = (0..rows * cols).map(|x| (x % 97) as i64 - 48).collect();
+        DynMatrix::from_flat(&data, (rows, cols))
+    }
 
     #[test]
     fn zeros() {
@@ -369,6 +1403,60 @@ mod tests {
         assert_eq!(matrix[(1, 1)], 4);
     }
 
+    #[test]
+    fn try_constructors_reject_bad_shapes() {
+        assert!(matches!(
+            DynMatrix::try_from_flat(&[1, 2, 3], (2, 2)),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            DynMatrix::try_from_vec(&[vec![1, 2], vec![3]]),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            DynMatrix::try_from_vec(&[vec![], vec![1]]),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            DynMatrix::<u8>::try_zeros((usize::MAX, 2)),
+            Err(Error::Validation(_))
+        ));
+
+        let matrix = DynMatrix::try_from_vec(&[vec![1, 2], vec![3, 4]]).unwrap();
+        assert_eq!(matrix, DynMatrix::from_nested(&[[1, 2], [3, 4]]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_flat_panics_when_data_does_not_fit() {
+        DynMatrix::from_flat(&[1, 2, 3], (2, 2));
+    }
+
+    #[test]
+    fn empty_matrices_keep_their_dims() {
+        let no_cols = DynMatrix::<i32>::zeros((3, 0));
+        assert_eq!((no_cols.rows(), no_cols.cols()), (3, 0));
+        assert_eq!(no_cols.clone().into_iter().count(), 3);
+        assert!(no_cols[2].is_empty());
+        let transposed = no_cols.transpose();
+        assert_eq!((transposed.rows(), transposed.cols()), (0, 3));
+        assert_eq!(no_cols.sum(), 0);
+        assert_eq!(no_cols.max(), None);
+
+        let no_rows = DynMatrix::<i32>::zeros((0, 2));
+        assert_eq!((no_rows.rows(), no_rows.cols()), (0, 2));
+        assert_eq!(no_cols.try_mul(&no_rows).unwrap(), DynMatrix::zeros((3, 2)));
+        let product = no_rows.try_mul(&DynMatrix::ones((2, 3))).unwrap();
+        assert_eq!((product.rows(), product.cols()), (0, 3));
+
+        let none = DynMatrix::<i32>::from_vec(&[]);
+        assert_eq!((none.rows(), none.cols()), (0, 0));
+        assert_eq!(
+            DynMatrix::<i32>::from_vec(&[vec![], vec![]]),
+            no_cols.block(0, 0, 2, 0).unwrap()
+        );
+    }
+
     #[test]
     fn from() {
         let matrix = DynMatrix::from_nested(&[[1, 2], [3, 4]]);
@@ -422,6 +1510,109 @@ mod tests {
         assert_eq!(result[(1, 2)], 6);
     }
 
+    #[test]
+    fn hadamard_2x2() {
+        let matrix1 = DynMatrix::<u8>::from_flat(&[1, 2, 3, 4], (2, 2));
+        let matrix2 = DynMatrix::<u8>::from_flat(&[5, 6, 7, 8], (2, 2));
+        let result = matrix1.hadamard(&matrix2);
+        assert_eq!(result, DynMatrix::from_flat(&[5, 12, 21, 32], (2, 2)));
+    }
+
+    #[test]
+    fn large_hadamard_matches_elementwise() {
+        let m1 = large_matrix(150, 130);
+        let m2 = large_matrix(150, 130);
+        let result = m1.hadamard(&m2);
+        for i in 0..m1.rows() {
+            for j in 0..m1.cols() {
+                assert_eq!(result[(i, j)], m1[(i, j)] * m2[(i, j)]);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn hadamard_mismatched_dims_panics() {
+        let matrix1 = DynMatrix::<u8>::zeros((2, 2));
+        let matrix2 = DynMatrix::<u8>::zeros((2, 3));
+        matrix1.hadamard(&matrix2);
+    }
+
+    #[test]
+    fn map_and_apply_work_elementwise() {
+        let matrix = DynMatrix::from_nested(&[[1, -2, 3], [-4, 5, -6]]);
+        let halved: DynMatrix<f64> = matrix.map(|x| x as f64 / 2.0);
+        assert_eq!(
+            halved,
+            DynMatrix::from_nested(&[[0.5, -1.0, 1.5], [-2.0, 2.5, -3.0]])
+        );
+
+        let mut abs = matrix.clone();
+        abs.apply(|x: i32| x.abs());
+        assert_eq!(abs, DynMatrix::from_nested(&[[1, 2, 3], [4, 5, 6]]));
+
+        let m = large_matrix(150, 130);
+        let doubled = m.map(|x| x * 2);
+        assert_eq!(doubled, m.clone() + &m);
+    }
+
+    #[test]
+    fn zip_map_combines_matching_elements() {
+        let a = DynMatrix::from_nested(&[[1, 5], [3, 2]]);
+        let b = DynMatrix::from_nested(&[[4, 4], [4, 4]]);
+        let bigger: DynMatrix<u8> = a.zip_map(&b, |x, y| u8::from(x > y));
+        assert_eq!(bigger, DynMatrix::from_nested(&[[0, 1], [0, 0]]));
+
+        let mut c = a.clone();
+        c.zip_apply(&b, |x, y| x.max(y));
+        assert_eq!(c, DynMatrix::from_nested(&[[4, 5], [4, 4]]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zip_map_mismatched_dims_panics() {
+        let matrix1 = DynMatrix::<u8>::zeros((2, 2));
+        let matrix2 = DynMatrix::<u8>::zeros((3, 2));
+        matrix1.zip_map(&matrix2, |a, b| a + b);
+    }
+
+    #[test]
+    fn converts_to_fixed_size_when_dims_match() {
+        let matrix = DynMatrix::from_nested(&[[1, 2, 3], [4, 5, 6]]);
+        let fixed: Matrix<i32, 2, 3> = matrix.clone().into_fixed().unwrap();
+        assert_eq!(fixed, Matrix::from_nested(&[[1, 2, 3], [4, 5, 6]]));
+        assert_eq!(DynMatrix::from(fixed), matrix);
+
+        assert!(matches!(
+            matrix.clone().into_fixed::<3, 2>(),
+            Err(Error::Unprocessable(_))
+        ));
+        assert!(matches!(
+            Matrix::<i32, 2, 2>::try_from(matrix),
+            Err(Error::Unprocessable(_))
+        ));
+
+        let empty: Matrix<f32, 0, 4> = DynMatrix::zeros((0, 4)).into_fixed().unwrap();
+        assert_eq!((empty.rows(), empty.cols()), (0, 4));
+    }
+
+    #[test]
+    fn reductions() {
+        let matrix = DynMatrix::from_nested(&[[1, -2, 3], [-4, 5, -6]]);
+        assert_eq!(matrix.sum(), -3);
+        assert_eq!(matrix.min(), Some(-6));
+        assert_eq!(matrix.max(), Some(5));
+        assert_eq!(matrix.trace(), 6);
+        assert_eq!(matrix.transpose().trace(), 6);
+        assert_eq!(DynMatrix::<i32>::identity((4, 4)).trace(), 4);
+
+        let empty = DynMatrix::<f32>::zeros((0, 3));
+        assert_eq!(empty.sum(), 0.0);
+        assert_eq!(empty.trace(), 0.0);
+        assert_eq!(empty.min(), None);
+        assert_eq!(empty.max(), None);
+    }
+
     #[test]
     fn add() {
         let matrix1 = DynMatrix::<u8>::from_flat(&[1, 2, 3, 4], (2, 2));
@@ -485,4 +1676,379 @@ mod tests {
         assert_eq!(result[(1, 0)], 3);
         assert_eq!(result[(1, 1)], 4);
     }
+
+    #[test]
+    fn large_add_sub_scalar_match_elementwise() {
+        let a = large_matrix(200, 150);
+        let b = large_matrix(200, 150) * 3;
+
+        let sum = a.clone() + &b;
+        let diff = a.clone() - &b;
+        let shifted = a.clone() + 5;
+        for i in 0..200 {
+            for j in 0..150 {
+                assert_eq!(sum[(i, j)], a[(i, j)] + b[(i, j)]);
+                assert_eq!(diff[(i, j)], a[(i, j)] - b[(i, j)]);
+                assert_eq!(shifted[(i, j)], a[(i, j)] + 5);
+            }
+        }
+    }
+
+    #[test]
+    fn large_mul_matches_naive() {
+        let a = large_matrix(130, 140);
+        let b = large_matrix(140, 150);
+        let result = a.clone() * &b;
+        for i in 0..130 {
+            for j in 0..150 {
+                let expected: i64 = (0..140).map(|k| a[(i, k)] * b[(k, j)]).sum();
+                assert_eq!(result[(i, j)], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn blocked_mul_matches_row_mul() {
+        // Sizes that aren't multiples of MUL_BLOCK_SIZE, so every edge block is partial
+        let a = large_matrix(300, 270);
+        let b = large_matrix(270, 290);
+        let blocked = a.mul_blocked(&b);
+        let by_rows = a.mul_by_rows(&b);
+        assert_eq!((blocked.rows(), blocked.cols()), (300, 290));
+        assert_eq!(blocked, by_rows);
+        assert_eq!(a.clone() * &b, by_rows);
+    }
+
+    #[test]
+    fn row_and_col_views() {
+        let matrix = DynMatrix::from_flat(&[1, 2, 3, 4, 5, 6], (2, 3));
+
+        let row = matrix.row(1);
+        assert_eq!(row.len(), 3);
+        assert_eq!(row[2], 6);
+        assert_eq!(row.iter().copied().collect::<Vec<_>>(), vec![4, 5, 6]);
+
+        let col = matrix.col(1);
+        assert_eq!(col.len(), 2);
+        assert_eq!(col[1], 5);
+        assert_eq!(col.get(2), None);
+        assert_eq!(col.iter().rev().copied().collect::<Vec<_>>(), vec![5, 2]);
+    }
+
+    #[test]
+    fn row_and_col_views_mutate_in_place() {
+        let mut matrix = DynMatrix::from_flat(&[1, 2, 3, 4, 5, 6], (2, 3));
+
+        for el in matrix.row_mut(0) {
+            *el *= 10;
+        }
+        let mut col = matrix.col_mut(2);
+        for el in col.iter_mut() {
+            *el = 0;
+        }
+        col[0] = 7;
+
+        assert_eq!(matrix, DynMatrix::from_flat(&[10, 20, 7, 4, 5, 0], (2, 3)));
+    }
+
+    #[test]
+    fn submatrix_view() {
+        let data: Vec<i32> = (0..16).collect();
+        let matrix = DynMatrix::from_flat(&data, (4, 4));
+
+        let sub = matrix.submatrix(((1, 2), (3, 2)));
+        assert_eq!((sub.rows(), sub.cols()), (3, 2));
+        assert_eq!(sub[(0, 0)], 6);
+        assert_eq!(sub[(2, 1)], 15);
+        assert_eq!(sub.get(3, 0), None);
+        assert_eq!(sub.row(1), &[10, 11]);
+        assert_eq!(
+            sub.iter().copied().collect::<Vec<_>>(),
+            vec![6, 7, 10, 11, 14, 15]
+        );
+        assert_eq!(
+            sub.to_matrix(),
+            DynMatrix::from_flat(&[6, 7, 10, 11, 14, 15], (3, 2))
+        );
+    }
+
+    #[test]
+    fn submatrix_view_mutates_in_place() {
+        let mut matrix = DynMatrix::<i32>::zeros((3, 3));
+
+        let mut sub = matrix.submatrix_mut(((1, 1), (2, 2)));
+        sub.fill(1);
+        sub[(0, 1)] = 2;
+        for el in sub.iter_mut() {
+            *el += 1;
+        }
+
+        assert_eq!(
+            matrix,
+            DynMatrix::from_flat(&[0, 0, 0, 0, 2, 3, 0, 2, 2], (3, 3))
+        );
+    }
+
+    #[test]
+    fn blocks_are_copied_out_and_back() {
+        let data: Vec<i32> = (0..16).collect();
+        let matrix = DynMatrix::from_flat(&data, (4, 4));
+        let block = matrix.block(1, 2, 3, 2).unwrap();
+        assert_eq!(block, DynMatrix::from_flat(&[6, 7, 10, 11, 14, 15], (3, 2)));
+        assert_eq!(block.stride(), 2);
+
+        // Swap the left and right halves, a block at a time
+        let mut swapped = matrix.clone();
+        swapped
+            .set_block(0, 0, &matrix.block(0, 2, 4, 2).unwrap())
+            .unwrap();
+        swapped
+            .set_block(0, 2, &matrix.block(0, 0, 4, 2).unwrap())
+            .unwrap();
+        assert_eq!(swapped.row(0).as_slice(), &[2, 3, 0, 1]);
+        assert_eq!(swapped.row(3).as_slice(), &[14, 15, 12, 13]);
+
+        assert_eq!(matrix.block(4, 4, 0, 0).unwrap().dims(), Dims::default());
+    }
+
+    #[test]
+    fn blocks_must_fit() {
+        let mut matrix = DynMatrix::<i32>::zeros((3, 3));
+        assert!(matches!(
+            matrix.block(2, 2, 2, 1),
+            Err(Error::Unprocessable(_))
+        ));
+        assert!(matrix.block(usize::MAX, 0, 1, 1).is_err());
+
+        let ones = DynMatrix::ones((2, 2));
+        assert!(matches!(
+            matrix.set_block(0, 2, &ones),
+            Err(Error::Unprocessable(_))
+        ));
+        assert_eq!(matrix, DynMatrix::zeros((3, 3)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn submatrix_out_of_bounds_panics() {
+        let matrix = DynMatrix::<i32>::zeros((3, 3));
+        matrix.submatrix(((2, 2), (2, 1)));
+    }
+
+    #[test]
+    fn storage_is_flat_and_row_major() {
+        let matrix = DynMatrix::from_nested(&[[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(matrix.stride(), 3);
+        assert_eq!(matrix[1], [4, 5, 6]);
+        assert_eq!(matrix[(1, 0)], 4);
+        assert_eq!(
+            Vec::<Vec<i32>>::from(matrix.clone()),
+            vec![vec![1, 2, 3], vec![4, 5, 6]]
+        );
+        assert_eq!(
+            DynMatrix::from_vec(&Vec::<Vec<i32>>::from(matrix.clone())),
+            matrix
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn column_out_of_bounds_panics() {
+        // With flat storage this would otherwise silently read from the next row
+        let matrix = DynMatrix::<i32>::zeros((3, 3));
+        let _ = matrix[(0, 3)];
+    }
+
+    #[test]
+    fn try_ops_match_operators() {
+        let m1 = DynMatrix::from_nested(&[[1.0, 2.0], [3.0, 4.0]]);
+        let m2 = DynMatrix::from_nested(&[[5.0, 6.0], [7.0, 8.0]]);
+        assert_eq!(m1.try_add(&m2).unwrap(), m1.clone() + &m2);
+        assert_eq!(m1.try_sub(&m2).unwrap(), m1.clone() - &m2);
+        assert_eq!(m1.try_mul(&m2).unwrap(), m1.clone() * &m2);
+        assert_eq!(m1.try_hadamard(&m2).unwrap(), m1.hadamard(&m2));
+    }
+
+    #[test]
+    fn try_ops_reject_mismatched_dimensions() {
+        let m1 = DynMatrix::<f64>::ones((2, 3));
+        let m2 = DynMatrix::<f64>::ones((2, 2));
+        assert!(matches!(m1.try_add(&m2), Err(Error::Unprocessable(_))));
+        assert!(matches!(m1.try_sub(&m2), Err(Error::Unprocessable(_))));
+        assert!(matches!(m1.try_mul(&m2), Err(Error::Unprocessable(_))));
+        assert!(matches!(m1.try_hadamard(&m2), Err(Error::Unprocessable(_))));
+        // 2x2 by 2x3 is fine, though
+        assert_eq!(m2.try_mul(&m1).unwrap().cols(), 3);
+    }
+
+    #[test]
+    fn solve_2x2() {
+        let a = DynMatrix::from_nested(&[[2.0, 1.0], [1.0, 3.0]]);
+        let b = DynMatrix::from_nested(&[[3.0], [5.0]]);
+        let x = a.solve(&b).unwrap();
+        assert!((x[(0, 0)] - 0.8).abs() < 1e-12);
+        assert!((x[(1, 0)] - 1.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn solve_needs_pivoting() {
+        // Without row exchanges, the zero in the top-left would be divided by
+        let a = DynMatrix::from_nested(&[[0.0, 1.0, 2.0], [1.0, 0.0, 3.0], [4.0, -3.0, 8.0]]);
+        let expected = DynMatrix::from_nested(&[[1.0], [-2.0], [3.0]]);
+        let b = a.clone() * &expected;
+        let x = a.solve(&b).unwrap();
+        for i in 0..3 {
+            assert!((x[(i, 0)] - expected[(i, 0)]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn solve_multiple_right_hand_sides() {
+        let a = DynMatrix::from_nested(&[[4.0, 7.0], [2.0, 6.0]]);
+        let x = a.solve(&DynMatrix::identity_like(&a)).unwrap();
+        let product = a.clone() * &x;
+        for i in 0..2 {
+            for j in 0..2 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product[(i, j)] - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn solve_rejects_singular_matrices() {
+        let a = DynMatrix::from_nested(&[[1.0, 2.0], [2.0, 4.0]]);
+        let b = DynMatrix::from_nested(&[[1.0], [2.0]]);
+        assert!(matches!(a.solve(&b), Err(Error::Unprocessable(_))));
+    }
+
+    #[test]
+    fn solve_rejects_mismatched_dimensions() {
+        let square = DynMatrix::<f64>::identity((2, 2));
+        let wide = DynMatrix::<f64>::ones((2, 3));
+        assert!(matches!(
+            wide.solve(&DynMatrix::ones((2, 1))),
+            Err(Error::Unprocessable(_))
+        ));
+        assert!(matches!(
+            square.solve(&DynMatrix::ones((3, 1))),
+            Err(Error::Unprocessable(_))
+        ));
+    }
+
+    #[test]
+    fn norms() {
+        let m = DynMatrix::from_nested(&[[1.0, -2.0], [3.0, 4.0]]);
+        assert_eq!(m.norm_l1(), 6.0);
+        assert_eq!(m.norm_inf(), 7.0);
+        assert!((m.norm_fro() - 30.0_f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn approx_eq_within_tolerance() {
+        let a = DynMatrix::from_nested(&[[1.0, 2.0], [3.0, 4.0]]);
+        let b = DynMatrix::from_nested(&[[1.0, 2.001], [2.998, 4.0]]);
+        assert!((a.max_abs_diff(&b).unwrap() - 0.002).abs() < 1e-12);
+        assert!(a.approx_eq(&b, 0.01));
+        assert!(!a.approx_eq(&b, 0.001));
+        assert!(a.approx_eq(&a, 0.0));
+
+        let wide = DynMatrix::<f64>::zeros((1, 4));
+        assert!(matches!(
+            a.max_abs_diff(&wide),
+            Err(Error::Unprocessable(_))
+        ));
+        assert!(!a.approx_eq(&wide, f64::INFINITY));
+
+        let mut nan = a.clone();
+        nan[(1, 1)] = f64::NAN;
+        assert!(a.max_abs_diff(&nan).unwrap().is_nan());
+        assert!(!a.approx_eq(&nan, f64::INFINITY));
+    }
+
+    #[test]
+    fn condition_number() {
+        assert_eq!(
+            DynMatrix::<f64>::identity((3, 3))
+                .condition_number()
+                .unwrap(),
+            1.0
+        );
+
+        // ‖A‖₁ = 13, and A⁻¹ = [[0.6, -0.7], [-0.2, 0.4]], so ‖A⁻¹‖₁ = 1.1
+        let a = DynMatrix::from_nested(&[[4.0, 7.0], [2.0, 6.0]]);
+        assert!((a.condition_number().unwrap() - 14.3).abs() < 1e-12);
+
+        let singular = DynMatrix::from_nested(&[[1.0, 2.0], [2.0, 4.0]]);
+        assert!(singular.condition_number().unwrap().is_infinite());
+
+        assert!(matches!(
+            DynMatrix::<f64>::ones((2, 3)).condition_number(),
+            Err(Error::Unprocessable(_))
+        ));
+    }
+
+    // Run with and without `--features parallel` (and with varying RAYON_NUM_THREADS) to see how
+    // these scale across cores
+
+    #[bench]
+    fn bench_add_1024(b: &mut Bencher) {
+        let m1 = DynMatrix::<f64>::ones((1024, 1024));
+        let m2 = DynMatrix::<f64>::ones((1024, 1024));
+        b.iter(|| m1.clone() + &m2);
+    }
+
+    #[bench]
+    fn bench_mul_scalar_1024(b: &mut Bencher) {
+        let m = DynMatrix::<f64>::ones((1024, 1024));
+        b.iter(|| m.clone() * 2.0);
+    }
+
+    #[bench]
+    fn bench_mul_256(b: &mut Bencher) {
+        let m1 = DynMatrix::<f64>::ones((256, 256));
+        let m2 = DynMatrix::<f64>::ones((256, 256));
+        b.iter(|| m1.clone() * &m2);
+    }
+
+    #[bench]
+    fn bench_mul_512_f64(b: &mut Bencher) {
+        let m1 = DynMatrix::<f64>::ones((512, 512));
+        let m2 = DynMatrix::<f64>::ones((512, 512));
+        b.iter(|| m1.clone() * &m2);
+    }
+
+    #[bench]
+    fn bench_mul_1024_f64(b: &mut Bencher) {
+        let m1 = DynMatrix::<f64>::ones((1024, 1024));
+        let m2 = DynMatrix::<f64>::ones((1024, 1024));
+        b.iter(|| m1.clone() * &m2);
+    }
+
+    /// Row-at-a-time multiply without blocking, to compare against [`bench_mul_1024_f64`]
+    #[bench]
+    fn bench_mul_1024_f64_unblocked(b: &mut Bencher) {
+        let m1 = DynMatrix::<f64>::ones((1024, 1024));
+        let m2 = DynMatrix::<f64>::ones((1024, 1024));
+        b.iter(|| m1.mul_by_rows(&m2));
+    }
+
+    /// The naive nested-`Vec` multiply `DynMatrix` used before storage was flattened, kept to
+    /// compare against [`bench_mul_512_f64`]
+    #[bench]
+    fn bench_mul_512_f64_nested_vec_reference(b: &mut Bencher) {
+        let m1 = vec![vec![1.0_f64; 512]; 512];
+        let m2 = vec![vec![1.0_f64; 512]; 512];
+        b.iter(|| {
+            let mut result = vec![vec![0.0_f64; 512]; 512];
+            for (i, row) in result.iter_mut().enumerate() {
+                for (j, el) in row.iter_mut().enumerate() {
+                    for k in 0..512 {
+                        *el += m1[i][k] * m2[k][j];
+                    }
+                }
+            }
+            result
+        });
+    }
 }
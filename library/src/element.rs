@@ -5,13 +5,35 @@ use std::{
     ops::{AddAssign, SubAssign},
 };
 pub trait Element:
-    Num + Display + Clone + Default + Copy + Zero + One + AddAssign + SubAssign + Serialize
+    Num
+    + Display
+    + Clone
+    + Default
+    + Copy
+    + Zero
+    + One
+    + AddAssign
+    + SubAssign
+    + Serialize
+    + Send
+    + Sync
 {
     type ElementType;
 }
 impl<T> Element for T
 where
-    T: Num + Display + Clone + Default + Copy + Zero + One + AddAssign + SubAssign + Serialize,
+    T: Num
+        + Display
+        + Clone
+        + Default
+        + Copy
+        + Zero
+        + One
+        + AddAssign
+        + SubAssign
+        + Serialize
+        + Send
+        + Sync,
 {
     type ElementType = T;
 }
@@ -1,19 +1,173 @@
-#[derive(Debug)]
-enum MyError {
-    #[allow(dead_code)]
-    IoError(std::io::Error),
-    #[allow(dead_code)]
-    ParseError(std::num::ParseIntError),
+//! The error type shared by this crate and the services built on it.
+//!
+//! Messages describe what went wrong in a sentence fragment, without trailing punctuation, so
+//! callers can add context or punctuation as they see fit.
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    /// Image data couldn't be read, either because it's malformed or because of its format
+    #[error("{0}")]
+    ImageDecode(String),
+
+    /// An image couldn't be written in the requested format
+    #[error("{0}")]
+    ImageEncode(String),
+
+    /// The requested image format isn't supported for this operation
+    #[error("{0}")]
+    UnsupportedFormat(String),
+
+    /// Something went wrong reading from or writing to the database
+    #[error("{0}")]
+    Database(String),
+
+    /// The named thing doesn't exist. Holds a description of the thing, e.g. `Matrix foo`
+    #[error("{0} not found")]
+    NotFound(String),
+
+    /// The named thing already exists, and the operation would replace it
+    #[error("{0} already exists")]
+    Conflict(String),
+
+    /// The caller asked for something that doesn't make sense, e.g. mismatched dimensions or an
+    /// out-of-range parameter
+    #[error("{0}")]
+    Validation(String),
+
+    /// The caller didn't show that it may do what it asked, e.g. by sending a valid token
+    #[error("{0}")]
+    Unauthorized(String),
+
+    /// The caller has made too many requests lately, and should wait before making more
+    #[error("{0}")]
+    RateLimited(String),
+
+    /// The caller sent more data than we accept
+    #[error("{0}")]
+    TooLarge(String),
+
+    /// The caller's request is well-formed, but what it holds can't be processed, e.g. an image
+    /// with more pixels than we're willing to handle
+    #[error("{0}")]
+    Unprocessable(String),
+
+    /// Anything else. These are bugs or environmental problems, not the caller's fault
+    #[error("{0}")]
+    Internal(String),
 }
 
-impl From<std::io::Error> for MyError {
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn image_decode(msg: impl Into<String>) -> Self {
+        Error::ImageDecode(msg.into())
+    }
+
+    pub fn image_encode(msg: impl Into<String>) -> Self {
+        Error::ImageEncode(msg.into())
+    }
+
+    pub fn unsupported_format(msg: impl Into<String>) -> Self {
+        Error::UnsupportedFormat(msg.into())
+    }
+
+    pub fn database(msg: impl Into<String>) -> Self {
+        Error::Database(msg.into())
+    }
+
+    pub fn not_found(what: impl Into<String>) -> Self {
+        Error::NotFound(what.into())
+    }
+
+    pub fn conflict(what: impl Into<String>) -> Self {
+        Error::Conflict(what.into())
+    }
+
+    pub fn validation(msg: impl Into<String>) -> Self {
+        Error::Validation(msg.into())
+    }
+
+    pub fn unauthorized(msg: impl Into<String>) -> Self {
+        Error::Unauthorized(msg.into())
+    }
+
+    pub fn rate_limited(msg: impl Into<String>) -> Self {
+        Error::RateLimited(msg.into())
+    }
+
+    pub fn too_large(msg: impl Into<String>) -> Self {
+        Error::TooLarge(msg.into())
+    }
+
+    pub fn unprocessable(msg: impl Into<String>) -> Self {
+        Error::Unprocessable(msg.into())
+    }
+
+    pub fn internal(msg: impl Into<String>) -> Self {
+        Error::Internal(msg.into())
+    }
+
+    /// A short, stable, machine-readable name for the kind of error, e.g. `not_found`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ImageDecode(_) => "image_decode",
+            Error::ImageEncode(_) => "image_encode",
+            Error::UnsupportedFormat(_) => "unsupported_format",
+            Error::Database(_) => "database",
+            Error::NotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::Validation(_) => "validation",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::RateLimited(_) => "rate_limited",
+            Error::TooLarge(_) => "too_large",
+            Error::Unprocessable(_) => "unprocessable",
+            Error::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        use image::ImageError;
+        match e {
+            ImageError::Decoding(e) => Error::ImageDecode(e.to_string()),
+            ImageError::Encoding(e) => Error::ImageEncode(e.to_string()),
+            ImageError::Unsupported(e) => Error::UnsupportedFormat(e.to_string()),
+            ImageError::Parameter(e) => Error::Validation(e.to_string()),
+            ImageError::Limits(e) => Error::Validation(e.to_string()),
+            ImageError::IoError(e) => Error::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        MyError::IoError(e)
+        Error::Internal(e.to_string())
     }
 }
 
-impl From<std::num::ParseIntError> for MyError {
-    fn from(e: std::num::ParseIntError) -> Self {
-        MyError::ParseError(e)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_compose() {
+        assert_eq!(
+            Error::not_found("Matrix foo").to_string(),
+            "Matrix foo not found"
+        );
+        assert_eq!(
+            Error::validation("Matrices must have the same dimensions").to_string(),
+            "Matrices must have the same dimensions"
+        );
+    }
+
+    #[test]
+    fn image_errors_keep_their_category() {
+        let e = image::load_from_memory_with_format(&[0, 1, 2, 3], image::ImageFormat::Png)
+            .unwrap_err();
+        assert!(matches!(Error::from(e), Error::ImageDecode(_)));
     }
 }
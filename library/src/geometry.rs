@@ -0,0 +1,113 @@
+//! Tiling math, shared by everything that breaks images (or matrices) into tiles.
+//!
+//! Sizes are [`Dims`], so rows are an image's height and columns its width. Tiles are numbered
+//! in row-major order, from the top-left, and those in the last row and column are cut short to
+//! fit.
+
+use crate::dims::{Dims, Roi};
+
+/// How many tiles of (at most) `tile_dims` it takes to cover something of size `dims`, as rows
+/// and columns of tiles. Tiles with no rows or columns cover nothing, so there are none of them
+pub fn tile_count_for(dims: Dims, tile_dims: Dims) -> Dims {
+    if tile_dims.area() == 0 {
+        return Dims::default();
+    }
+    (
+        dims.rows().div_ceil(tile_dims.rows()),
+        dims.cols().div_ceil(tile_dims.cols()),
+    )
+        .into()
+}
+
+/// The tiles of (at most) `tile_dims` covering something of size `dims`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TileGrid {
+    pub dims: Dims,
+    pub tile_dims: Dims,
+}
+
+impl TileGrid {
+    pub fn new(dims: Dims, tile_dims: Dims) -> Self {
+        Self { dims, tile_dims }
+    }
+
+    /// Rows and columns of tiles. See [`tile_count_for`]
+    pub fn count(&self) -> Dims {
+        tile_count_for(self.dims, self.tile_dims)
+    }
+
+    /// Number of tiles
+    pub fn len(&self) -> usize {
+        self.count().area()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The region covered by the tile with the given index, or `None` if there's no such tile
+    pub fn tile_rect(&self, index: usize) -> Option<Roi> {
+        let count = self.count();
+        if index >= count.area() {
+            return None;
+        }
+        let row = (index / count.cols()) * self.tile_dims.rows();
+        let col = (index % count.cols()) * self.tile_dims.cols();
+        Some(Roi {
+            row,
+            col,
+            rows: self.tile_dims.rows().min(self.dims.rows() - row),
+            cols: self.tile_dims.cols().min(self.dims.cols() - col),
+        })
+    }
+
+    /// The region covered by each tile, in index order
+    pub fn iter(&self) -> impl Iterator<Item = Roi> + '_ {
+        (0..self.len()).filter_map(|index| self.tile_rect(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case((1024, 1024), (512, 512), (2, 2))]
+    #[test_case((24, 600), (512, 512), (1, 2))]
+    #[test_case((1, 1), (512, 512), (1, 1))]
+    #[test_case((0, 600), (512, 512), (0, 2))]
+    #[test_case((20, 40), (0, 16), (0, 0))]
+    fn counts_tiles(dims: (usize, usize), tile_dims: (usize, usize), count: (usize, usize)) {
+        assert_eq!(
+            tile_count_for(dims.into(), tile_dims.into()),
+            Dims::from(count)
+        );
+    }
+
+    #[test]
+    fn edge_tiles_are_cut_short() {
+        // 3 tiles across, 2 down
+        let grid = TileGrid::new((20, 40).into(), (16, 16).into());
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid.tile_rect(0), Some(Roi::from(((0, 0), (16, 16)))));
+        assert_eq!(grid.tile_rect(2), Some(Roi::from(((0, 32), (16, 8)))));
+        assert_eq!(grid.tile_rect(3), Some(Roi::from(((16, 0), (4, 16)))));
+        assert_eq!(grid.tile_rect(5), Some(Roi::from(((16, 32), (4, 8)))));
+        assert_eq!(grid.tile_rect(6), None);
+    }
+
+    #[test]
+    fn tiles_cover_everything_once() {
+        let grid = TileGrid::new((37, 53).into(), (8, 16).into());
+        let mut covered = vec![0; grid.dims.area()];
+        for rect in grid.iter() {
+            assert!(rect.extent().fits_within(&grid.dims));
+            for r in rect.row..rect.row + rect.rows {
+                for c in rect.col..rect.col + rect.cols {
+                    covered[r * grid.dims.cols() + c] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&n| n == 1));
+    }
+}
@@ -1,11 +1,480 @@
-use image::{DynamicImage, GenericImageView, ImageFormat};
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder},
+    DynamicImage, GenericImageView, ImageBuffer, ImageDecoder, ImageFormat, Luma, LumaA, Pixel,
+    Rgb, Rgba,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::io::Cursor;
+use tiff::{decoder::DecodingResult, ColorType as TiffColorType};
+use utoipa::ToSchema;
 
-use crate::dims::{Cols, Dims, HasDims, Rows};
+use crate::compression::Compression;
+use crate::dims::{Dims, HasDims, Roi};
 use crate::dyn_matrix::DynMatrix;
+use crate::errors::{Error, Result};
+use crate::geometry::TileGrid;
+use crate::my_image::{MyImage, PixelComponent};
+use crate::simd::SliceOps;
 
 pub struct IprImage<'a>(pub &'a DynamicImage);
 
+impl<'a> HasDims for IprImage<'a> {
+    fn rows(&self) -> usize {
+        self.0.height() as usize
+    }
+
+    fn cols(&self) -> usize {
+        self.0.width() as usize
+    }
+
+    fn dims(&self) -> Dims {
+        (self.rows(), self.cols()).into()
+    }
+}
+
+/// Encoder speed used for AVIF output, from 1 (slowest, smallest) to 10 (fastest, largest).
+///
+/// AVIF encoding is orders of magnitude slower than the other formats we emit, so we err towards
+/// speed here. Tiles are small, and clients asking for AVIF are mostly after the better quality
+/// per byte rather than the smallest possible payload.
+pub const AVIF_SPEED: u8 = 8;
+
+/// Quality used for AVIF output, from 1 to 100
+pub const AVIF_QUALITY: u8 = 80;
+
+/// Quality used for JPEG output, from 1 to 100. Same as the `image` crate's default
+pub const JPEG_QUALITY: u8 = 75;
+
+/// Decodes an image of the given format from memory.
+///
+/// For TIFF sources, `page` selects which image of a (potentially multi-page) file is decoded,
+/// defaulting to the first. It is ignored for all other formats. TIFFs the `image` crate can't
+/// read, like floating-point grayscale ones, are read with [`decode_tiff_page`] instead.
+pub fn decode_image(data: &[u8], fmt: ImageFormat, page: Option<u32>) -> Result<DynamicImage> {
+    if !fmt.reading_enabled() {
+        return Err(Error::unsupported_format(
+            "Decoding is not supported for the given image format",
+        ));
+    }
+    match (fmt, page) {
+        (ImageFormat::Tiff, Some(p)) if p > 0 => decode_tiff_page(data, p),
+        (ImageFormat::Tiff, _) => image::load_from_memory_with_format(data, fmt)
+            .or_else(|e| decode_tiff_page(data, 0).map_err(|_| e.into())),
+        _ => Ok(image::load_from_memory_with_format(data, fmt)?),
+    }
+}
+
+/// Reads the dimensions of an encoded image from its header, without decoding any pixels.
+///
+/// As with [`decode_image`], `page` selects the page of a TIFF source, and is ignored for all
+/// other formats.
+pub fn encoded_image_dimensions(
+    data: &[u8],
+    fmt: ImageFormat,
+    page: Option<u32>,
+) -> Result<(u32, u32)> {
+    if let (ImageFormat::Tiff, Some(p)) = (fmt, page) {
+        let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data))
+            .map_err(|_| Error::image_decode("Failed to read TIFF"))?;
+        decoder
+            .seek_to_image(p as usize)
+            .map_err(|_| Error::not_found(format!("TIFF page {}", p)))?;
+        return decoder
+            .dimensions()
+            .map_err(|_| Error::image_decode("Failed to read TIFF page dimensions"));
+    }
+    Ok(image::ImageReader::with_format(Cursor::new(data), fmt).into_dimensions()?)
+}
+
+/// What an encoded image's header says about it. See [`read_metadata`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ImageMetadata {
+    pub width: u32,
+    pub height: u32,
+
+    /// Channels per pixel, e.g. 3 for RGB, or 4 for RGBA
+    pub channels: u8,
+
+    /// Bits per channel, e.g. 8, or 16 for high bit depth PNGs and TIFFs
+    pub bit_depth: u8,
+
+    /// The EXIF orientation tag, from 1 (upright) to 8, if the image has one. See
+    /// <https://www.exif.org/Exif2-2.PDF>, page 18
+    pub exif_orientation: Option<u8>,
+}
+
+/// Reads an encoded image's dimensions, pixel layout, and EXIF orientation from its header,
+/// without decoding any pixels
+pub fn read_metadata(data: &[u8], fmt: ImageFormat) -> Result<ImageMetadata> {
+    if !fmt.reading_enabled() {
+        return Err(Error::unsupported_format(
+            "Decoding is not supported for the given image format",
+        ));
+    }
+    let mut decoder = image::ImageReader::with_format(Cursor::new(data), fmt).into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let channels = color.channel_count();
+    let exif_orientation = decoder
+        .exif_metadata()?
+        .as_deref()
+        .and_then(exif_orientation);
+    Ok(ImageMetadata {
+        width,
+        height,
+        channels,
+        bit_depth: (color.bits_per_pixel() / channels as u16) as u8,
+        exif_orientation,
+    })
+}
+
+/// The orientation tag from a raw EXIF chunk, which is laid out like a TIFF file: a header giving
+/// the byte order, then directories of 12-byte entries. Orientation is always in the first
+fn exif_orientation(exif: &[u8]) -> Option<u8> {
+    const ORIENTATION_TAG: u16 = 0x0112;
+
+    // JPEGs' EXIF segments start with this, which not every decoder strips
+    let exif = exif.strip_prefix(b"Exif\0\0").unwrap_or(exif);
+    let big_endian = match exif.get(0..4)? {
+        [b'I', b'I', 42, 0] => false,
+        [b'M', b'M', 0, 42] => true,
+        _ => return None,
+    };
+    let u16_at = |at: usize| -> Option<u16> {
+        let bytes = exif.get(at..at + 2)?.try_into().ok()?;
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let bytes = exif.get(at..at + 4)?.try_into().ok()?;
+        Some(match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    };
+
+    let directory = u32_at(4)? as usize;
+    let entries = u16_at(directory)? as usize;
+    // The tag is a SHORT, so its value is in the first two bytes of the entry's last four
+    (0..entries)
+        .map(|i| directory + 2 + 12 * i)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+        .and_then(|value| u8::try_from(value).ok())
+        .filter(|value| (1..=8).contains(value))
+}
+
+/// Rotates and flips `image` to appear upright, given its EXIF orientation (from 1, already upright,
+/// to 8). Other values leave it as it is
+pub fn apply_exif_orientation(image: DynamicImage, orientation: u8) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Counts the number of images (pages) in a TIFF file
+pub fn tiff_page_count(data: &[u8]) -> Result<u32> {
+    let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data))
+        .map_err(|_| Error::image_decode("Failed to read TIFF"))?;
+    let mut count = 1;
+    while decoder.more_images() {
+        decoder
+            .next_image()
+            .map_err(|_| Error::image_decode("Failed to read TIFF page"))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Decodes a single page of a multi-page TIFF file.
+///
+/// The `image` crate's TIFF decoder only ever reads the first page, so we go to the `tiff` crate
+/// directly and rebuild a [`DynamicImage`] from the raw samples. Samples keep their bit depth.
+/// [`DynamicImage`] has no floating-point grayscale layouts, so 32-bit float gray (and alpha)
+/// pages become RGB (and alpha) with the gray value in each color channel.
+pub fn decode_tiff_page(data: &[u8], page: u32) -> Result<DynamicImage> {
+    let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data))
+        .map_err(|_| Error::image_decode("Failed to read TIFF"))?;
+    decoder
+        .seek_to_image(page as usize)
+        .map_err(|_| Error::not_found(format!("TIFF page {}", page)))?;
+    let (width, height) = decoder
+        .dimensions()
+        .map_err(|_| Error::image_decode("Failed to read TIFF page dimensions"))?;
+    let color_type = decoder
+        .colortype()
+        .map_err(|_| Error::image_decode("Failed to read TIFF page color type"))?;
+    let samples = decoder
+        .read_image()
+        .map_err(|_| Error::image_decode("Failed to decode TIFF page"))?;
+
+    let image = match (color_type, samples) {
+        (TiffColorType::Gray(8), DecodingResult::U8(buf)) => {
+            ImageBuffer::<Luma<u8>, _>::from_raw(width, height, buf).map(DynamicImage::ImageLuma8)
+        }
+        (TiffColorType::GrayA(8), DecodingResult::U8(buf)) => {
+            ImageBuffer::<LumaA<u8>, _>::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8)
+        }
+        (TiffColorType::RGB(8), DecodingResult::U8(buf)) => {
+            ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgb8)
+        }
+        (TiffColorType::RGBA(8), DecodingResult::U8(buf)) => {
+            ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgba8)
+        }
+        (TiffColorType::Gray(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, buf).map(DynamicImage::ImageLuma16)
+        }
+        (TiffColorType::GrayA(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, buf)
+                .map(DynamicImage::ImageLumaA16)
+        }
+        (TiffColorType::RGB(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgb16)
+        }
+        (TiffColorType::RGBA(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgba16)
+        }
+        (TiffColorType::Gray(32), DecodingResult::F32(buf)) => {
+            let buf = buf.iter().flat_map(|&g| [g, g, g]).collect();
+            ImageBuffer::<Rgb<f32>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgb32F)
+        }
+        (TiffColorType::GrayA(32), DecodingResult::F32(buf)) => {
+            let buf = buf
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect();
+            ImageBuffer::<Rgba<f32>, _>::from_raw(width, height, buf)
+                .map(DynamicImage::ImageRgba32F)
+        }
+        (TiffColorType::RGB(32), DecodingResult::F32(buf)) => {
+            ImageBuffer::<Rgb<f32>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgb32F)
+        }
+        (TiffColorType::RGBA(32), DecodingResult::F32(buf)) => {
+            ImageBuffer::<Rgba<f32>, _>::from_raw(width, height, buf)
+                .map(DynamicImage::ImageRgba32F)
+        }
+        _ => {
+            return Err(Error::unsupported_format(
+                "Unsupported TIFF page color type",
+            ))
+        }
+    };
+
+    image.ok_or_else(|| Error::image_decode("TIFF page data does not match its dimensions"))
+}
+
+/// Encodes the given image in the given format.
+///
+/// This should be preferred over [`DynamicImage::write_to`] since some formats need encoder
+/// settings (or pixel layouts) that differ from the `image` crate defaults. Samples keep as much
+/// of their bit depth as the format can hold (see [`format_supports_16_bit`] and
+/// [`format_supports_float`]).
+pub fn encode_image(image: &DynamicImage, fmt: ImageFormat) -> Result<Vec<u8>> {
+    encode_image_with_quality(image, fmt, None)
+}
+
+/// Encodes the given image in the given format, at the given quality (from 1 to 100) if the
+/// format is lossy. See [`format_supports_quality`].
+///
+/// Without a quality, JPEG and AVIF use [`JPEG_QUALITY`] and [`AVIF_QUALITY`], and WebP is
+/// lossless. With one, WebP is lossy, if the `server-codecs` feature is enabled; otherwise it
+/// stays lossless. Other formats ignore it.
+pub fn encode_image_with_quality(
+    image: &DynamicImage,
+    fmt: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>> {
+    if !fmt.writing_enabled() {
+        return Err(Error::unsupported_format(
+            "Encoding is not supported for the given image format",
+        ));
+    }
+    if let Some(q) = quality {
+        if !(1..=100).contains(&q) {
+            return Err(Error::validation(format!(
+                "Quality must be from 1 to 100, not {}",
+                q
+            )));
+        }
+    }
+
+    #[cfg(feature = "server-codecs")]
+    if let (ImageFormat::WebP, Some(q)) = (fmt, quality) {
+        return encode_lossy_webp(image, q);
+    }
+
+    let mut data = Vec::new();
+    match fmt {
+        ImageFormat::Avif => {
+            // The AVIF encoder only deals in 8-bit RGB(A), so flatten anything else first
+            let encoder = AvifEncoder::new_with_speed_quality(
+                &mut data,
+                AVIF_SPEED,
+                quality.unwrap_or(AVIF_QUALITY),
+            );
+            let image = match image {
+                DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => image.clone(),
+                i if i.color().has_alpha() => DynamicImage::ImageRgba8(i.to_rgba8()),
+                i => DynamicImage::ImageRgb8(i.to_rgb8()),
+            };
+            image
+                .write_with_encoder(encoder)
+                .map_err(|_| Error::image_encode("Failed to encode image as AVIF"))?;
+        }
+        ImageFormat::Jpeg => {
+            // Likewise, the JPEG encoder only takes 8-bit grayscale or RGB
+            let encoder = JpegEncoder::new_with_quality(&mut data, quality.unwrap_or(JPEG_QUALITY));
+            let image = match image {
+                DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => image.clone(),
+                i if i.color().has_color() => DynamicImage::ImageRgb8(i.to_rgb8()),
+                i => DynamicImage::ImageLuma8(i.to_luma8()),
+            };
+            image
+                .write_with_encoder(encoder)
+                .map_err(|_| Error::image_encode("Failed to encode image as JPEG"))?;
+        }
+        f if image.color().has_alpha() && !format_supports_alpha(f) => {
+            // Some encoders reject alpha outright rather than dropping it, so drop it here
+            let opaque = match sample_bytes(image) {
+                1 => DynamicImage::ImageRgb8(image.to_rgb8()),
+                2 => DynamicImage::ImageRgb16(image.to_rgb16()),
+                _ => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+            };
+            let mut cursor = Cursor::new(&mut data);
+            fit_bit_depth(&opaque, f)
+                .write_to(&mut cursor, fmt)
+                .map_err(|_| Error::image_encode("Failed to encode image"))?;
+        }
+        f => {
+            let mut cursor = Cursor::new(&mut data);
+            fit_bit_depth(image, f)
+                .write_to(&mut cursor, fmt)
+                .map_err(|_| Error::image_encode("Failed to encode image"))?;
+        }
+    }
+    Ok(data)
+}
+
+/// The `image` crate only writes lossless WebP, so lossy WebP comes from libwebp
+#[cfg(feature = "server-codecs")]
+fn encode_lossy_webp(image: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let encoded = match image.color().has_alpha() {
+        true => webp::Encoder::from_rgba(&image.to_rgba8(), width, height)
+            .encode_simple(false, quality as f32),
+        false => webp::Encoder::from_rgb(&image.to_rgb8(), width, height)
+            .encode_simple(false, quality as f32),
+    };
+    encoded
+        .map(|data| data.to_vec())
+        .map_err(|e| Error::image_encode(format!("Failed to encode image as WebP: {:?}", e)))
+}
+
+/// Whether `fmt` is lossy, so that the quality passed to [`encode_image_with_quality`] matters.
+/// WebP is only lossy with the `server-codecs` feature
+pub fn format_supports_quality(fmt: ImageFormat) -> bool {
+    match fmt {
+        ImageFormat::Jpeg | ImageFormat::Avif => true,
+        ImageFormat::WebP => cfg!(feature = "server-codecs"),
+        _ => false,
+    }
+}
+
+/// Bytes in each sample of `image`: 1 for 8-bit images, 2 for 16-bit, and 4 for floating-point
+fn sample_bytes(image: &DynamicImage) -> u8 {
+    let color = image.color();
+    color.bytes_per_pixel() / color.channel_count()
+}
+
+/// Whether images encoded as `fmt` keep 16-bit samples
+pub fn format_supports_16_bit(fmt: ImageFormat) -> bool {
+    matches!(
+        fmt,
+        ImageFormat::Png | ImageFormat::Tiff | ImageFormat::Pnm | ImageFormat::Farbfeld
+    )
+}
+
+/// Whether images encoded as `fmt` keep floating-point samples
+pub fn format_supports_float(fmt: ImageFormat) -> bool {
+    matches!(fmt, ImageFormat::Tiff | ImageFormat::OpenExr)
+}
+
+/// `image`, in a layout `fmt` can be written in, losing as little precision as possible.
+/// Floating-point samples become 16-bit where the format can hold that, and anything deeper than
+/// the format allows becomes 8-bit. OpenEXR only holds floating-point RGB(A), so everything is
+/// converted to that for it
+fn fit_bit_depth(image: &DynamicImage, fmt: ImageFormat) -> Cow<'_, DynamicImage> {
+    let alpha = image.color().has_alpha();
+    let fitted = match sample_bytes(image) {
+        4 if format_supports_float(fmt) => return Cow::Borrowed(image),
+        _ if fmt == ImageFormat::OpenExr => match alpha {
+            true => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+            false => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        },
+        1 => return Cow::Borrowed(image),
+        2 if format_supports_16_bit(fmt) => return Cow::Borrowed(image),
+        _ if format_supports_16_bit(fmt) => match alpha {
+            true => DynamicImage::ImageRgba16(image.to_rgba16()),
+            false => DynamicImage::ImageRgb16(image.to_rgb16()),
+        },
+        _ => match image.color().channel_count() {
+            1 => DynamicImage::ImageLuma8(image.to_luma8()),
+            2 => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+            3 => DynamicImage::ImageRgb8(image.to_rgb8()),
+            _ => DynamicImage::ImageRgba8(image.to_rgba8()),
+        },
+    };
+    Cow::Owned(fitted)
+}
+
+/// Whether images encoded as `fmt` keep their alpha channel
+pub fn format_supports_alpha(fmt: ImageFormat) -> bool {
+    matches!(
+        fmt,
+        ImageFormat::Png
+            | ImageFormat::WebP
+            | ImageFormat::Tiff
+            | ImageFormat::Avif
+            | ImageFormat::Gif
+            | ImageFormat::Ico
+            | ImageFormat::Qoi
+            | ImageFormat::OpenExr
+            | ImageFormat::Tga
+            | ImageFormat::Farbfeld
+    )
+}
+
+/// Whether any pixel of `image` is less than fully opaque. Images without an alpha channel
+/// never are
+pub fn has_transparency(image: &DynamicImage) -> bool {
+    image.color().has_alpha() && image.pixels().any(|(_, _, p)| p[3] < u8::MAX)
+}
+
+/// The format to encode `image` in, given the one asked for (if any).
+///
+/// Without a format asked for, transparent images, and those deeper than 8 bits, are encoded as
+/// PNG, and others as JPEG. Asking for a format that would drop the image's transparency, or that
+/// can't be written, falls back to PNG
+pub fn choose_tile_format(image: &DynamicImage, requested: Option<ImageFormat>) -> ImageFormat {
+    let transparent = has_transparency(image);
+    match requested {
+        Some(fmt) if fmt.writing_enabled() && (format_supports_alpha(fmt) || !transparent) => fmt,
+        Some(_) => ImageFormat::Png,
+        None if transparent || sample_bytes(image) > 1 => ImageFormat::Png,
+        None => ImageFormat::Jpeg,
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ImageTiles {
     pub original_width: u32,
@@ -17,6 +486,63 @@ pub struct ImageTiles {
     pub count_down: u32,
 }
 
+impl ImageTiles {
+    /// Where the tiles are in the original image
+    pub fn grid(&self) -> TileGrid {
+        TileGrid::new(
+            self.dims(),
+            (self.tile_height as usize, self.tile_width as usize).into(),
+        )
+    }
+
+    /// The region of the original image covered by the tile with the given index, or `None` if
+    /// there's no such tile
+    pub fn tile_rect(&self, index: usize) -> Option<Roi> {
+        self.grid().tile_rect(index)
+    }
+
+    /// Every tile, in index order, as `(x, y, col, row, tile)`: the pixel coordinates of its
+    /// top-left corner in the original image, and its column and row in the grid of tiles
+    pub fn iter_with_coords(&self) -> impl Iterator<Item = (u32, u32, u32, u32, &DynamicImage)> {
+        self.tiles.iter().enumerate().map(move |(index, tile)| {
+            let col = index as u32 % self.count_across;
+            let row = index as u32 / self.count_across;
+            (
+                col * self.tile_width,
+                row * self.tile_height,
+                col,
+                row,
+                tile,
+            )
+        })
+    }
+
+    /// The tile in the given column and row of the grid, counting from the top-left, if there is
+    /// one
+    pub fn get(&self, col: u32, row: u32) -> Option<&DynamicImage> {
+        if col >= self.count_across || row >= self.count_down {
+            return None;
+        }
+        self.tiles
+            .get(row as usize * self.count_across as usize + col as usize)
+    }
+}
+
+/// The dimensions of the original image
+impl HasDims for ImageTiles {
+    fn rows(&self) -> usize {
+        self.original_height as usize
+    }
+
+    fn cols(&self) -> usize {
+        self.original_width as usize
+    }
+
+    fn dims(&self) -> Dims {
+        (self.rows(), self.cols()).into()
+    }
+}
+
 impl Clone for ImageTiles {
     fn clone(&self) -> Self {
         ImageTiles {
@@ -31,221 +557,2980 @@ impl Clone for ImageTiles {
     }
 }
 
-pub trait HasImageProcessingRoutines {
-    fn convolve_in_place(&mut self, k: DynMatrix<f64>) -> Result<(), &'static str>;
-    fn generate_image_pyramid(&self) -> Result<Vec<DynamicImage>, &'static str>;
-    fn make_tiles(&self, tile_width: u32, tile_height: u32) -> Result<ImageTiles, &'static str>;
-    fn compress_brotli(
-        &self,
-        brotli_level: u32,
-        brotli_lg_window_size: u32,
-        fmt: Option<ImageFormat>,
-    ) -> Result<Vec<u8>, &'static str>;
+/// The kind of image pyramid to generate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PyramidType {
+    /// Each level is a box-filtered, downsampled copy of the level before it
+    Lowpass,
+
+    /// Each level is a Gaussian-smoothed, downsampled copy of the level before it
+    #[default]
+    Gaussian,
+
+    /// Each level holds the detail lost between successive levels of a Gaussian pyramid. The
+    /// original image is reconstructed by upsampling the smallest level and summing it with each
+    /// larger level in turn.
+    Laplacian,
 }
 
-impl<'a> HasImageProcessingRoutines for IprImage<'a> {
-    fn convolve_in_place(&mut self, k: DynMatrix<f64>) -> Result<(), &'static str> {
-        let Dims(Rows(r), Cols(c)) = k.dims();
-        if r != c {
-            return Err("Kernel matrix must be square in shape!");
-        }
-        if r % 2 == 0 {
-            return Err("Kernel matrix must have an odd number of rows and columns!");
+impl PyramidType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PyramidType::Lowpass => "lowpass",
+            PyramidType::Gaussian => "gaussian",
+            PyramidType::Laplacian => "laplacian",
         }
+    }
+}
 
-        let i = &self.0;
-        let (_width, _height) = i.dimensions();
+/// The filter used when resampling an image to a new size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleFilter {
+    /// Nearest neighbor. Fastest, and keeps hard edges, but blocky
+    Nearest,
 
-        todo!("Iterate through image pixels and convolve neighborhood. Lose outer data");
-    }
+    /// Box, so each pixel is the average of those it covers when downsampling
+    Box,
 
-    fn generate_image_pyramid(&self) -> Result<Vec<DynamicImage>, &'static str> {
-        use image_pyramid::*;
+    /// Linear (bilinear, in two dimensions)
+    #[default]
+    Triangle,
 
-        let params = ImagePyramidParams {
-            pyramid_type: ImagePyramidType::Lowpass,
-            scale_factor: 0.5,
-            smoothing_type: SmoothingType::Gaussian,
-        };
+    /// Cubic, with Catmull-Rom weights
+    CatmullRom,
 
-        let pyramid = ImagePyramid::create(self.0, Some(&params))?;
+    /// Gaussian, which slightly blurs the result
+    Gaussian,
 
-        Ok(pyramid.levels)
+    /// Lanczos with a window of 3. Sharpest, and slowest
+    Lanczos3,
+}
+
+impl ResampleFilter {
+    /// How far from its center, in pixels, the filter's kernel reaches before downsampling
+    /// stretches it
+    fn support(&self) -> f64 {
+        match self {
+            ResampleFilter::Nearest | ResampleFilter::Box => 0.5,
+            ResampleFilter::Triangle => 1.0,
+            ResampleFilter::CatmullRom => 2.0,
+            ResampleFilter::Gaussian | ResampleFilter::Lanczos3 => 3.0,
+        }
     }
 
-    /// Splits this image into tiles of the given dimensions or smaller.
-    ///
-    /// The number of tiles returned is the product of the following:
-    ///     - The ceiling of `self.width() / tile_width`
-    ///     - The ceiling of `self.height() / tile_height`
-    ///
-    /// That is, if the instance's dimensions are (1000,1000) and the tile dimensions are (300,300)
-    /// then there will be sixteen tiles, as illustrated below. (9 full-size tiles, 3 tiles along
-    /// the bottom with a height of 100, 3 tiles along the right side with a width of 100, and one
-    /// tile in the bottom-right corner with dimensions (100,100)).
-    ///
-    /// ```text
-    ///           300px       300px       300px   100px
-    ///       ┌───────────┬───────────┬──────────┬─────┐
-    ///       │           │           │          │     │
-    ///  300px│           │           │          │     │
-    ///       │           │           │          │     │
-    ///       │           │           │          │     │
-    ///       ├───────────┼───────────┼──────────┼─────┤
-    ///       │           │           │          │     │
-    ///  300px│           │           │          │     │
-    ///       │           │           │          │     │
-    ///       │           │           │          │     │
-    ///       ├───────────┼───────────┼──────────┼─────┤
-    ///       │           │           │          │     │
-    ///  300px│           │           │          │     │
-    ///       │           │           │          │     │
-    ///       │           │           │          │     │
-    ///       ├───────────┼───────────┼──────────┼─────┤
-    ///  100px│           │           │          │     │
-    ///       │           │           │          │     │
-    ///       └───────────┴───────────┴──────────┴─────┘
-    /// ``````
-    fn make_tiles(&self, tile_width: u32, tile_height: u32) -> Result<ImageTiles, &'static str> {
-        let i = &self.0;
-        let (width, height) = i.dimensions();
-
-        let count_across = (width + tile_width - 1) / tile_width;
-        let count_down = (height + tile_height - 1) / tile_height;
-
-        let mut tiles = Vec::<DynamicImage>::new();
-        // let mut tiles = Vec::new();
-        for y in 0..count_down {
-            for x in 0..count_across {
-                let actual_tile_width = if x == count_across - 1 {
-                    width - x * tile_width
-                } else {
-                    tile_width
-                };
-                let actual_tile_height = if y == count_down - 1 {
-                    height - y * tile_height
-                } else {
-                    tile_height
-                };
-
-                let tile = i.crop_imm(
-                    x * tile_width,
-                    y * tile_height,
-                    actual_tile_width,
-                    actual_tile_height,
-                );
-                tiles.push(tile);
+    /// The filter's (unnormalized) weight for a pixel `x` pixels from where a sample is taken.
+    /// These are the kernels the `image` crate uses for its filters of the same names
+    fn kernel(&self, x: f64) -> f64 {
+        let sinc = |x: f64| {
+            if x == 0.0 {
+                return 1.0;
+            }
+            (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+        };
+        let a = x.abs();
+        match self {
+            ResampleFilter::Nearest | ResampleFilter::Box if a <= 0.5 => 1.0,
+            ResampleFilter::Nearest | ResampleFilter::Box => 0.0,
+            ResampleFilter::Triangle => (1.0 - a).max(0.0),
+            // Cubic, with B = 0 and C = 0.5
+            ResampleFilter::CatmullRom => match a {
+                a if a < 1.0 => (9.0 * a * a * a - 15.0 * a * a + 6.0) / 6.0,
+                a if a < 2.0 => (-3.0 * a * a * a + 15.0 * a * a - 24.0 * a + 12.0) / 6.0,
+                _ => 0.0,
+            },
+            // Standard deviation of 0.5
+            ResampleFilter::Gaussian => {
+                (-2.0 * x * x).exp() / ((2.0 * std::f64::consts::PI).sqrt() * 0.5)
             }
+            ResampleFilter::Lanczos3 if a < 3.0 => sinc(x) * sinc(x / 3.0),
+            ResampleFilter::Lanczos3 => 0.0,
         }
+    }
+}
 
-        Ok(ImageTiles {
-            original_height: i.height(),
-            original_width: i.width(),
-            tiles,
-            tile_width,
-            tile_height,
-            count_across,
-            count_down,
-        })
+/// The kernels used to estimate an image's gradient, for edge detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeOperator {
+    /// 3x3 Sobel kernels
+    #[default]
+    Sobel,
+
+    /// 3x3 Scharr kernels, which respond more evenly to edges at any angle than Sobel's
+    Scharr,
+}
+
+impl EdgeOperator {
+    /// Weights of the three rows (or columns) each kernel differentiates across
+    fn smoothing(&self) -> [f32; 3] {
+        match self {
+            EdgeOperator::Sobel => [1.0, 2.0, 1.0],
+            EdgeOperator::Scharr => [3.0, 10.0, 3.0],
+        }
     }
+}
 
-    fn compress_brotli(
-        &self,
-        brotli_level: u32,
-        brotli_lg_window_size: u32,
-        fmt: Option<ImageFormat>,
-    ) -> Result<Vec<u8>, &'static str> {
-        let i = &self.0;
-        let dest_fmt = fmt.unwrap_or(ImageFormat::Png);
+/// Parameters controlling how an image pyramid is generated
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PyramidParams {
+    /// The kind of pyramid to generate
+    pub pyramid_type: PyramidType,
 
-        let mut data = Vec::new();
-        let mut cursor = Cursor::new(&mut data);
-        i.write_to(&mut cursor, dest_fmt).unwrap();
+    /// The factor by which each level is scaled relative to the one before it. Must be in the
+    /// range (0.0, 1.0)
+    pub scale_factor: f32,
 
-        let brotli_params = brotli::enc::BrotliEncoderParams {
-            quality: match brotli_level.try_into() {
-                Ok(v) => v,
-                Err(_) => return Err("Brotli level must be between 0 and 11"),
-            },
-            lgwin: match brotli_lg_window_size.try_into() {
-                Ok(v) => v,
-                Err(_) => return Err("Brotli lg_window_size must be between 10 and 24"),
-            },
-            // This is a neat feature :-)
-            ..Default::default()
-        };
+    /// Levels whose longest side would be smaller than this many pixels are not generated. The
+    /// full-size image is always generated. `None` generates every level down to 1x1
+    #[serde(default)]
+    pub min_dimension: Option<u32>,
 
-        let mut compressed_data = Vec::new();
-        brotli::BrotliCompress(
-            &mut Cursor::new(&mut data),
-            &mut compressed_data,
-            &brotli_params,
-        )
-        .unwrap();
+    /// The most levels to generate, counting the full-size image. Must be at least 1. `None`
+    /// means no limit
+    #[serde(default)]
+    pub max_levels: Option<u32>,
 
-        Ok(compressed_data)
+    /// The filter each level is resampled with. `None` uses the pyramid type's own: see
+    /// [`PyramidParams::resample_filter`]
+    #[serde(default)]
+    pub filter: Option<ResampleFilter>,
+}
+
+impl Default for PyramidParams {
+    fn default() -> Self {
+        Self {
+            pyramid_type: PyramidType::Gaussian,
+            scale_factor: 0.5,
+            min_dimension: None,
+            max_levels: None,
+            filter: None,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::sync::{Arc, Mutex};
+impl PyramidParams {
+    /// The filter levels are resampled with: `filter` if given, or else Gaussian for Gaussian
+    /// pyramids, box for lowpass pyramids, and triangle for Laplacian pyramids.
+    ///
+    /// Laplacian pyramids always smooth with a Gaussian between levels; their filter is only used
+    /// to fit each level to its dimensions (see [`pyramid_level_dims`]).
+    pub fn resample_filter(&self) -> ResampleFilter {
+        self.filter.unwrap_or(match self.pyramid_type {
+            PyramidType::Gaussian => ResampleFilter::Gaussian,
+            PyramidType::Lowpass => ResampleFilter::Box,
+            PyramidType::Laplacian => ResampleFilter::Triangle,
+        })
+    }
 
-    use super::*;
-    use rayon::prelude::*;
-    extern crate test;
-    use test::Bencher;
-    use test_case::test_case;
+    /// The dimensions of each level that will be generated for an image of the given size.
+    ///
+    /// This is [`pyramid_level_dims`], cut short by `min_dimension` and `max_levels`. Levels are
+    /// only ever dropped from the small end, so level `n` has the same dimensions no matter which
+    /// limits are used.
+    pub fn level_dims(&self, width: u32, height: u32) -> Vec<(u32, u32)> {
+        let min_dimension = self.min_dimension.unwrap_or(0);
+        let max_levels = self.max_levels.map_or(usize::MAX, |m| m.max(1) as usize);
+        pyramid_level_dims(width, height, self.scale_factor)
+            .into_iter()
+            .enumerate()
+            .take_while(|&(idx, (w, h))| idx == 0 || w.max(h) >= min_dimension)
+            .take(max_levels)
+            .map(|(_, dims)| dims)
+            .collect()
+    }
+}
 
-    #[test_case("test_files/elden_ring.jpg")]
-    #[test_case("test_files/totk.bmp")]
-    #[test_case("test_files/totk.jpg")]
-    #[test_case("test_files/totk.png")]
-    #[test_case("test_files/totk.webp")]
-    fn compress_brotli(i_path: &str) {
-        let i = image::open(i_path).unwrap();
-        let i = IprImage(&i);
+/// How level dimensions are rounded when they don't divide evenly by the scale factor. Stored
+/// alongside pyramids so clients doing tile math know which rule was used.
+pub const PYRAMID_DIMENSION_ROUNDING: &str = "ceil";
 
-        let original_data_format = ImageFormat::from_path(i_path).unwrap();
-        let compressed_buf = match i.compress_brotli(10, 24, Some(original_data_format)) {
-            Ok(v) => v,
-            Err(_) => panic!("You fool! You cannot compress such a thing."),
-        };
+/// Computes the dimensions of every level of an image pyramid, starting with the full-size image.
+///
+/// Each level is `ceil(previous * scale_factor)` in both dimensions, the same rule used by Deep
+/// Zoom (DZI). With the default scale factor of 0.5, level `n` is therefore exactly
+/// `ceil(width / 2^n)` by `ceil(height / 2^n)`, so an odd-sized level never loses its last row or
+/// column of pixels. Dimensions that have reached 1 stay at 1, and every other dimension shrinks
+/// by at least one pixel per level. The final level is always 1x1.
+pub fn pyramid_level_dims(width: u32, height: u32, scale_factor: f32) -> Vec<(u32, u32)> {
+    let shrink = |d: u32| -> u32 {
+        if d <= 1 {
+            return 1;
+        }
+        let scaled = (d as f64 * scale_factor as f64).ceil() as u32;
+        scaled.clamp(1, d - 1)
+    };
 
-        let mut original_buf = Vec::<u8>::new();
-        let mut cursor = Cursor::new(&mut original_buf);
-        match i.0.write_to(&mut cursor, original_data_format) {
-            Ok(_) => (),
-            Err(_) => panic!("You fool! You cannot write such a thing."),
-        };
+    let mut dims = vec![(width, height)];
+    while let Some(&(w, h)) = dims.last() {
+        if w <= 1 && h <= 1 {
+            break;
+        }
+        dims.push((shrink(w), shrink(h)));
+    }
+    dims
+}
 
-        assert!(compressed_buf.len() < original_buf.len());
+/// Generates the pyramid level `levels_below` levels smaller than `source`, where `source` is
+/// itself a level of a pyramid built with `params`.
+///
+/// This lets a level that was skipped (see [`PyramidParams::min_dimension`] and
+/// [`PyramidParams::max_levels`]) be built later, from the smallest level that was kept, without
+/// going back to the full-size image. Laplacian levels only hold the detail lost between levels,
+/// so they can't be built this way.
+pub fn synthesize_pyramid_level(
+    source: &DynamicImage,
+    levels_below: usize,
+    params: &PyramidParams,
+) -> Result<DynamicImage> {
+    if params.pyramid_type == PyramidType::Laplacian {
+        return Err(Error::validation(
+            "Laplacian pyramid levels can't be synthesized from other levels",
+        ));
     }
 
-    #[test_case("test_files/elden_ring.jpg")]
-    #[test_case("test_files/totk.bmp")]
-    #[test_case("test_files/totk.jpg")]
-    #[test_case("test_files/totk.png")]
+    let params = PyramidParams {
+        min_dimension: None,
+        max_levels: Some((levels_below + 1) as u32),
+        ..*params
+    };
+    let mut levels = IprImage(source).generate_image_pyramid(Some(&params))?;
+    if levels.len() != levels_below + 1 {
+        return Err(Error::validation(
+            "Requested pyramid level is smaller than 1x1",
+        ));
+    }
+    Ok(levels.pop().unwrap())
+}
+
+/// An image pyramid: every level of an image, largest first, along with the parameters it was
+/// built with and, for levels that have been tiled, their tiles.
+///
+/// Where [`crate::documents::Pyramid`] describes a pyramid stored in the database, this holds the
+/// level images themselves, so it can be built, tiled and saved without one.
+#[derive(Debug, Clone)]
+pub struct Pyramid {
+    params: PyramidParams,
+    levels: Vec<DynamicImage>,
+
+    /// One entry per level, filled in by [`Pyramid::tile_level`]
+    tiles: Vec<Option<ImageTiles>>,
+}
+
+impl Pyramid {
+    /// Build a pyramid from `image`. See [`HasImageProcessingRoutines::generate_image_pyramid`]
+    pub fn new(image: &DynamicImage, params: Option<&PyramidParams>) -> Result<Self> {
+        let levels = IprImage(image).generate_image_pyramid(params)?;
+        Self::from_levels(levels, params.copied().unwrap_or_default())
+    }
+
+    /// Wrap levels already built with `params`, largest first
+    pub fn from_levels(levels: Vec<DynamicImage>, params: PyramidParams) -> Result<Self> {
+        if levels.is_empty() {
+            return Err(Error::validation("Pyramid must have at least one level"));
+        }
+        Ok(Self {
+            params,
+            tiles: vec![None; levels.len()],
+            levels,
+        })
+    }
+
+    pub fn params(&self) -> &PyramidParams {
+        &self.params
+    }
+
+    /// Number of levels, including the full-size image
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Always false, as a pyramid has at least its full-size image
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Every level, largest first
+    pub fn levels(&self) -> &[DynamicImage] {
+        &self.levels
+    }
+
+    pub fn level(&self, level: usize) -> Option<&DynamicImage> {
+        self.levels.get(level)
+    }
+
+    /// Width and height of every level, largest first
+    pub fn level_dims(&self) -> Vec<(u32, u32)> {
+        self.levels.iter().map(|l| l.dimensions()).collect()
+    }
+
+    /// The tiles of the given level, if it has been tiled
+    pub fn tiles(&self, level: usize) -> Option<&ImageTiles> {
+        self.tiles.get(level)?.as_ref()
+    }
+
+    /// Break the given level into tiles of (at most) `tile_width`x`tile_height`, replacing any
+    /// tiles it already had. See [`HasImageProcessingRoutines::make_tiles`]
+    pub fn tile_level(
+        &mut self,
+        level: usize,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Result<&ImageTiles> {
+        let image = self
+            .levels
+            .get(level)
+            .ok_or_else(|| Error::not_found(format!("Pyramid level {}", level)))?;
+        let tiles = IprImage(image).make_tiles(tile_width, tile_height)?;
+        Ok(self.tiles[level].insert(tiles))
+    }
+
+    /// [`Pyramid::tile_level`] for every level. With the `parallel` feature, levels are tiled
+    /// concurrently
+    pub fn tile_all_levels(&mut self, tile_width: u32, tile_height: u32) -> Result<()> {
+        let tile = |l: &DynamicImage| IprImage(l).make_tiles(tile_width, tile_height).map(Some);
+
+        #[cfg(feature = "parallel")]
+        let tiles = {
+            use rayon::prelude::*;
+            self.levels.par_iter().map(tile).collect::<Result<_>>()?
+        };
+        #[cfg(not(feature = "parallel"))]
+        let tiles = self.levels.iter().map(tile).collect::<Result<_>>()?;
+
+        self.tiles = tiles;
+        Ok(())
+    }
+
+    /// The level to draw when showing the image at `zoom` times its full size: the smallest level
+    /// that is still at least that large, so it only ever needs scaling down. Zooms of 1.0 or more
+    /// get the full-size image, and zooms too small for any level get the smallest one
+    pub fn level_for_zoom(&self, zoom: f64) -> usize {
+        let (width, height) = self.levels[0].dimensions();
+        let wanted = zoom * width.max(height) as f64;
+        self.levels
+            .iter()
+            .rposition(|l| l.width().max(l.height()) as f64 >= wanted)
+            .unwrap_or(0)
+    }
+
+    /// Give up the level images, largest first
+    pub fn into_levels(self) -> Vec<DynamicImage> {
+        self.levels
+    }
+}
+
+/// Width and height of tiles when none are given, in pixels
+pub const DEFAULT_TILE_SIZE: u32 = 512;
+
+/// Builds a [`PyramidPlan`]: how an image's pyramid levels are made, what size tiles they're cut
+/// into, and how those tiles are encoded and compressed. Anything not set keeps its default, and
+/// [`PyramidBuilder::build`] checks it all at once, so callers don't each validate their own
+/// share of the parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PyramidBuilder {
+    params: PyramidParams,
+    tile_width: u32,
+    tile_height: u32,
+    tile_format: Option<ImageFormat>,
+    compression: Compression,
+    compression_level: Option<u32>,
+    brotli_lg_window_size: Option<u32>,
+}
+
+impl Default for PyramidBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PyramidBuilder {
+    /// Start from the default plan: a Gaussian pyramid (see [`PyramidParams::default`]), cut into
+    /// [`DEFAULT_TILE_SIZE`] PNG tiles, compressed with Brotli at its default level
+    pub fn new() -> Self {
+        Self {
+            params: PyramidParams::default(),
+            tile_width: DEFAULT_TILE_SIZE,
+            tile_height: DEFAULT_TILE_SIZE,
+            tile_format: Some(ImageFormat::Png),
+            compression: Compression::default(),
+            compression_level: None,
+            brotli_lg_window_size: None,
+        }
+    }
+
+    /// Set every parameter of the levels at once, e.g. those of a pyramid that's already stored
+    pub fn params(mut self, params: PyramidParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// See [`PyramidParams::pyramid_type`]
+    pub fn pyramid_type(mut self, pyramid_type: PyramidType) -> Self {
+        self.params.pyramid_type = pyramid_type;
+        self
+    }
+
+    /// See [`PyramidParams::scale_factor`]
+    pub fn scale_factor(mut self, scale_factor: f32) -> Self {
+        self.params.scale_factor = scale_factor;
+        self
+    }
+
+    /// The filter levels are smoothed and resampled with, or `None` for the pyramid type's own.
+    /// See [`PyramidParams::filter`]
+    pub fn filter(mut self, filter: Option<ResampleFilter>) -> Self {
+        self.params.filter = filter;
+        self
+    }
+
+    /// See [`PyramidParams::min_dimension`]
+    pub fn min_dimension(mut self, min_dimension: Option<u32>) -> Self {
+        self.params.min_dimension = min_dimension;
+        self
+    }
+
+    /// See [`PyramidParams::max_levels`]
+    pub fn max_levels(mut self, max_levels: Option<u32>) -> Self {
+        self.params.max_levels = max_levels;
+        self
+    }
+
+    /// The (max) size of the tiles, in pixels
+    pub fn tile_size(mut self, tile_width: u32, tile_height: u32) -> Self {
+        self.tile_width = tile_width;
+        self.tile_height = tile_height;
+        self
+    }
+
+    /// The format to encode tiles in, or `None` to pick each tile's by its content. See
+    /// [`choose_tile_format`]
+    pub fn tile_format(mut self, tile_format: Option<ImageFormat>) -> Self {
+        self.tile_format = tile_format;
+        self
+    }
+
+    /// The codec to compress tiles with
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// The level to compress tiles at, or `None` for the codec's default (see
+    /// [`Compression::default_level`])
+    pub fn compression_level(mut self, level: Option<u32>) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Brotli's window size (log2), from 10 to 24, or `None` for its default. Other codecs
+    /// ignore it
+    pub fn brotli_window_size(mut self, lg_window_size: Option<u32>) -> Self {
+        self.brotli_lg_window_size = lg_window_size;
+        self
+    }
+
+    /// Check the parameters, and fill in the defaults of those left unset
+    pub fn build(self) -> Result<PyramidPlan> {
+        let scale_factor = self.params.scale_factor;
+        if !(scale_factor > 0.0 && scale_factor < 1.0) {
+            return Err(Error::validation(
+                "Pyramid scale factor must be between 0.0 and 1.0 (exclusive)",
+            ));
+        }
+        if self.params.max_levels == Some(0) {
+            return Err(Error::validation("Pyramid must have at least one level"));
+        }
+        // Skipped levels are built later from the levels above them, which Laplacian levels can't
+        // be
+        let skips_levels = self.params.min_dimension.is_some() || self.params.max_levels.is_some();
+        if self.params.pyramid_type == PyramidType::Laplacian && skips_levels {
+            return Err(Error::validation(
+                "Laplacian pyramids keep every level, so can't take min_dimension or max_levels",
+            ));
+        }
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(Error::validation("Tiles must be at least 1x1"));
+        }
+        if let Some(format) = self.tile_format.filter(|f| !f.writing_enabled()) {
+            return Err(Error::validation(format!(
+                "Tiles can't be encoded as {:?}",
+                format
+            )));
+        }
+
+        let compression_level = self
+            .compression_level
+            .unwrap_or(self.compression.default_level());
+        let levels = self.compression.levels();
+        if !levels.contains(&compression_level) {
+            return Err(Error::validation(format!(
+                "{} level must be between {} and {}",
+                self.compression.content_encoding(),
+                levels.start(),
+                levels.end()
+            )));
+        }
+        if let Some(lg_window_size) = self.brotli_lg_window_size {
+            if !(10..=24).contains(&lg_window_size) {
+                return Err(Error::validation(
+                    "Brotli lg_window_size must be between 10 and 24",
+                ));
+            }
+        }
+
+        Ok(PyramidPlan {
+            params: self.params,
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            tile_format: self.tile_format,
+            compression: self.compression,
+            compression_level,
+            brotli_lg_window_size: self.brotli_lg_window_size,
+        })
+    }
+}
+
+/// Everything needed to turn an image into a tiled, compressed pyramid, checked by
+/// [`PyramidBuilder::build`].
+///
+/// The plan doesn't hold the image, so it can be made (and compared against pyramids that already
+/// exist) before the image is even decoded, and its steps run wherever they're needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PyramidPlan {
+    params: PyramidParams,
+    tile_width: u32,
+    tile_height: u32,
+    tile_format: Option<ImageFormat>,
+    compression: Compression,
+    compression_level: u32,
+    brotli_lg_window_size: Option<u32>,
+}
+
+impl PyramidPlan {
+    pub fn params(&self) -> &PyramidParams {
+        &self.params
+    }
+
+    /// The (max) width and height of the tiles
+    pub fn tile_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    /// The format asked for the tiles, for [`choose_tile_format`]. `None` if each tile's format is
+    /// picked by its content
+    pub fn tile_format(&self) -> Option<ImageFormat> {
+        self.tile_format
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn compression_level(&self) -> u32 {
+        self.compression_level
+    }
+
+    /// Build the levels of `image`'s pyramid, calling `progress` (if given) as each is finished.
+    /// The levels aren't tiled
+    pub fn generate(
+        &self,
+        image: &DynamicImage,
+        progress: Option<&PyramidProgress>,
+    ) -> Result<Pyramid> {
+        let levels =
+            IprImage(image).generate_image_pyramid_with_progress(Some(&self.params), progress)?;
+        Pyramid::from_levels(levels, self.params)
+    }
+
+    /// Break `image`, usually one of the pyramid's levels, into tiles of the plan's size
+    pub fn tile(&self, image: &DynamicImage) -> Result<ImageTiles> {
+        IprImage(image).make_tiles(self.tile_width, self.tile_height)
+    }
+
+    /// Encode `tile` in the format picked for it (see [`choose_tile_format`]), and compress it.
+    /// Returns the format, along with the compressed data
+    pub fn compress_tile(&self, tile: &DynamicImage) -> Result<(ImageFormat, Vec<u8>)> {
+        let format = choose_tile_format(tile, self.tile_format);
+        let data = match (self.compression, self.brotli_lg_window_size) {
+            (Compression::Brotli, Some(lg_window_size)) => IprImage(tile).compress_brotli(
+                self.compression_level,
+                lg_window_size,
+                Some(format),
+            )?,
+            (codec, _) => IprImage(tile).compress(codec, self.compression_level, Some(format))?,
+        };
+        Ok((format, data))
+    }
+
+    /// Build `image`'s pyramid, and tile every level
+    pub fn execute(&self, image: &DynamicImage) -> Result<Pyramid> {
+        let mut pyramid = self.generate(image, None)?;
+        pyramid.tile_all_levels(self.tile_width, self.tile_height)?;
+        Ok(pyramid)
+    }
+}
+
+/// Called as each level of a pyramid is finished, with the level's index and the total number of
+/// levels being generated. Levels may finish out of order, on any thread
+pub type PyramidProgress = dyn Fn(usize, usize) + Send + Sync;
+
+pub trait HasImageProcessingRoutines {
+    /// Convolve each color channel with `kernel`, which must be square with an odd number of rows.
+    /// Pixels past the edges take the value of the nearest edge pixel, so the result is the same
+    /// size as the image. Alpha is left alone, and results are clamped to the channel's range
+    fn convolve(&self, kernel: &DynMatrix<f64>) -> Result<DynamicImage>;
+    fn generate_image_pyramid(&self, params: Option<&PyramidParams>) -> Result<Vec<DynamicImage>>;
+
+    /// Like [`HasImageProcessingRoutines::generate_image_pyramid`], calling `progress` (if given)
+    /// as each level is finished
+    fn generate_image_pyramid_with_progress(
+        &self,
+        params: Option<&PyramidParams>,
+        progress: Option<&PyramidProgress>,
+    ) -> Result<Vec<DynamicImage>>;
+    fn make_tiles(&self, tile_width: u32, tile_height: u32) -> Result<ImageTiles>;
+    fn compress_brotli(
+        &self,
+        brotli_level: u32,
+        brotli_lg_window_size: u32,
+        fmt: Option<ImageFormat>,
+    ) -> Result<Vec<u8>>;
+
+    /// Encode the image as `fmt` (PNG by default), then gzip it at `level`, from 0 to 9
+    fn compress_gzip(&self, level: u32, fmt: Option<ImageFormat>) -> Result<Vec<u8>>;
+
+    /// Encode the image as `fmt` (PNG by default), then compress it with Zstandard at `level`,
+    /// from 1 to 22. Needs the `server-codecs` feature
+    fn compress_zstd(&self, level: u32, fmt: Option<ImageFormat>) -> Result<Vec<u8>>;
+
+    /// Encode the image as `fmt` (PNG by default), then compress it with `codec` at `level`. See
+    /// [`Compression::levels`] for the levels each codec accepts. Brotli uses its default window
+    /// size; use [`HasImageProcessingRoutines::compress_brotli`] to pick another
+    fn compress(&self, codec: Compression, level: u32, fmt: Option<ImageFormat>)
+        -> Result<Vec<u8>>;
+
+    /// Count the samples of each channel falling into each of [`HISTOGRAM_BINS`] evenly-sized
+    /// bins, which together span the channel's range. 8-bit images get one bin per value
+    fn histogram(&self) -> Result<Vec<[u64; HISTOGRAM_BINS]>>;
+
+    /// Mean and (population) standard deviation of each channel
+    fn mean_stddev(&self) -> Result<Vec<(f64, f64)>>;
+
+    /// Smallest and largest value of each channel
+    fn min_max(&self) -> Result<Vec<(f64, f64)>>;
+
+    /// Remap each color channel so its values are spread evenly across the channel's range.
+    /// Alpha is left alone. Samples are ranked by histogram bin, so images deeper than 8 bits
+    /// come out with (at most) [`HISTOGRAM_BINS`] distinct values per channel
+    fn equalize_histogram(&self) -> Result<DynamicImage>;
+
+    /// Linearly stretch each color channel so that its `low_pct` percentile becomes zero and its
+    /// `high_pct` percentile becomes the channel's maximum, clipping anything beyond. Alpha is
+    /// left alone. Percentiles are from 0.0 to 100.0, and `low_pct` must be below `high_pct`
+    fn stretch_contrast(&self, low_pct: f64, high_pct: f64) -> Result<DynamicImage>;
+
+    /// Resample the image to exactly `width`x`height` pixels, which needn't keep its aspect ratio
+    fn resize(&self, width: u32, height: u32, filter: ResampleFilter) -> Result<DynamicImage>;
+
+    /// Rotate the image clockwise by `degrees`, which must be a multiple of 90
+    fn rotate(&self, degrees: u32) -> Result<DynamicImage>;
+
+    /// Measure how closely `other` matches this image, which must be the same size. Samples are
+    /// compared as floating-point RGBA from 0.0 to 1.0, so images of different layouts and bit
+    /// depths can be compared. SSIM is slower than the rest, so it's only computed if asked for
+    fn compare(&self, other: &DynamicImage, with_ssim: bool) -> Result<ImageComparison>;
+
+    /// The absolute difference between the color of each pixel of this image and of `other`,
+    /// which must be the same size, as 8-bit RGB
+    fn difference(&self, other: &DynamicImage) -> Result<DynamicImage>;
+
+    /// The luminance of the image, keeping its bit depth and any alpha
+    fn to_grayscale(&self) -> Result<DynamicImage>;
+
+    /// Each of the image's channels as its own 8-bit, single-component image: gray (and alpha)
+    /// for grayscale images, otherwise red, green, blue (and alpha). See [`merge_channels`] to
+    /// put them back together
+    fn split_channels(&self) -> Result<Vec<MyImage<u8>>>;
+
+    /// Binarize the image: pixels whose 8-bit luminance is above `value` become white, and the
+    /// rest black, in an 8-bit grayscale image
+    fn threshold(&self, value: u8) -> Result<DynamicImage>;
+
+    /// The threshold that best separates the image's 8-bit luminance into a dark and a light
+    /// class, by Otsu's method. Pass it to [`HasImageProcessingRoutines::threshold`] to binarize
+    fn otsu_threshold(&self) -> Result<u8>;
+
+    /// The magnitude of the image's luminance gradient, estimated with `operator`, as 8-bit
+    /// grayscale. Flat areas are black, and the steepest possible edge is white
+    fn edges(&self, operator: EdgeOperator) -> Result<DynamicImage>;
+
+    /// The horizontal and vertical components of the image's luminance gradient, estimated with
+    /// `operator`, as 8-bit grayscale images. No change is mid-gray (128); brighter means the
+    /// luminance increases to the right (or downwards), and darker that it decreases
+    fn gradients(&self, operator: EdgeOperator) -> Result<(DynamicImage, DynamicImage)>;
+}
+
+/// Interleave 1 to 4 single-component images of the same size into one image, e.g. after
+/// [`HasImageProcessingRoutines::split_channels`]. The channels become gray, gray and alpha, RGB,
+/// or RGBA, in that order
+pub fn merge_channels(channels: &[MyImage<u8>]) -> Result<DynamicImage> {
+    let first = match channels {
+        [] => return Err(Error::validation("Merging needs at least one channel")),
+        [_, _, _, _, _, ..] => {
+            return Err(Error::validation("Images can have at most 4 channels"));
+        }
+        [first, ..] => first,
+    };
+    let (width, height) = (first.width(), first.height());
+    for channel in channels {
+        if channel.components_per_pixel() != 1 {
+            return Err(Error::unprocessable(format!(
+                "Only single-channel images can be merged, not one with {} channels",
+                channel.components_per_pixel()
+            )));
+        }
+        if (channel.width(), channel.height()) != (width, height) {
+            return Err(Error::unprocessable(format!(
+                "Can't merge a {}x{} channel with a {}x{} one",
+                width,
+                height,
+                channel.width(),
+                channel.height()
+            )));
+        }
+    }
+
+    let mut merged = MyImage::<u8>::new(width, height, channels.len() as u32);
+    for (i, pixel) in merged
+        .data_mut()
+        .chunks_exact_mut(channels.len())
+        .enumerate()
+    {
+        for (component, channel) in pixel.iter_mut().zip(channels) {
+            *component = channel.data()[i];
+        }
+    }
+    merged.to_dynamic_image()
+}
+
+/// How closely two images match, from [`HasImageProcessingRoutines::compare`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct ImageComparison {
+    /// Mean squared error across every sample, from 0.0 (identical) to 1.0
+    pub mse: f64,
+
+    /// Peak signal-to-noise ratio, in decibels. Missing for identical images, where it's infinite
+    pub psnr: Option<f64>,
+
+    /// Mean structural similarity of the images' luma, from -1.0 to 1.0 (identical)
+    pub ssim: Option<f64>,
+}
+
+/// Number of bins in each channel of a [`HasImageProcessingRoutines::histogram`]
+pub const HISTOGRAM_BINS: usize = 256;
+
+/// The largest value a sample of the given image can take: 255 for 8-bit images, 65535 for
+/// 16-bit, and 1.0 for floating-point
+pub fn sample_max(image: &DynamicImage) -> f64 {
+    match sample_bytes(image) {
+        1 => u8::MAX as f64,
+        2 => u16::MAX as f64,
+        _ => 1.0,
+    }
+}
+
+/// The samples of the pixel at (`x`, `y`), in their native range (see [`sample_max`]). There's one
+/// per channel: luma, luma and alpha, RGB, or RGBA
+pub fn pixel_samples(image: &DynamicImage, x: u32, y: u32) -> Result<Vec<f64>> {
+    fn at<T: Copy + Into<f64>>(samples: &[T], channels: usize, pixel: usize) -> Vec<f64> {
+        samples[pixel * channels..(pixel + 1) * channels]
+            .iter()
+            .map(|&s| s.into())
+            .collect()
+    }
+
+    if x >= image.width() || y >= image.height() {
+        return Err(Error::validation(format!(
+            "Pixel ({}, {}) is outside the {}x{} image",
+            x,
+            y,
+            image.width(),
+            image.height()
+        )));
+    }
+    let channels = image.color().channel_count() as usize;
+    let pixel = y as usize * image.width() as usize + x as usize;
+    let samples = if let Some(flat) = image.as_flat_samples_u8() {
+        at(flat.samples, channels, pixel)
+    } else if let Some(flat) = image.as_flat_samples_u16() {
+        at(flat.samples, channels, pixel)
+    } else if let Some(flat) = image.as_flat_samples_f32() {
+        at(flat.samples, channels, pixel)
+    } else {
+        // As in `for_each_sample`, fall back to floating-point RGBA
+        at(image.to_rgba32f().as_raw(), 4, pixel)
+    };
+    Ok(samples)
+}
+
+/// Call `f` with the channel index and value of every sample in the image, in its native range
+/// (see [`sample_max`])
+fn for_each_sample(image: &DynamicImage, mut f: impl FnMut(usize, f64)) {
+    fn visit<T: Copy + Into<f64>>(samples: &[T], channels: usize, f: &mut impl FnMut(usize, f64)) {
+        for (idx, &s) in samples.iter().enumerate() {
+            f(idx % channels, s.into());
+        }
+    }
+
+    let channels = image.color().channel_count() as usize;
+    if let Some(flat) = image.as_flat_samples_u8() {
+        visit(flat.samples, channels, &mut f);
+    } else if let Some(flat) = image.as_flat_samples_u16() {
+        visit(flat.samples, channels, &mut f);
+    } else if let Some(flat) = image.as_flat_samples_f32() {
+        visit(flat.samples, channels, &mut f);
+    } else {
+        // Some layout `image` added after this was written. Floating-point RGBA holds anything
+        visit(image.to_rgba32f().as_raw(), 4, &mut f);
+    }
+}
+
+/// A sample type of some [`DynamicImage`] layout
+trait Sample: Copy + Into<f64> {
+    /// Convert back from a value in the sample's native range, rounding and clamping as needed
+    fn from_f64(v: f64) -> Self;
+}
+
+impl Sample for u8 {
+    fn from_f64(v: f64) -> Self {
+        v.round().clamp(0.0, u8::MAX as f64) as u8
+    }
+}
+
+impl Sample for u16 {
+    fn from_f64(v: f64) -> Self {
+        v.round().clamp(0.0, u16::MAX as f64) as u16
+    }
+}
+
+impl Sample for f32 {
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+}
+
+/// Copy `image`, replacing every color sample `v` of channel `c` with `f(c, v)`. Alpha samples
+/// are copied as-is
+fn map_color_samples(image: &DynamicImage, mut f: impl FnMut(usize, f64) -> f64) -> DynamicImage {
+    map_color_samples_indexed(image, |_, c, v| f(c, v))
+}
+
+/// Like [`map_color_samples`], also passing `f` the index of each sample, in the order
+/// [`for_each_sample`] visits them
+fn map_color_samples_indexed(
+    image: &DynamicImage,
+    mut f: impl FnMut(usize, usize, f64) -> f64,
+) -> DynamicImage {
+    fn apply<T: Sample>(
+        samples: &mut [T],
+        channels: usize,
+        has_alpha: bool,
+        f: &mut impl FnMut(usize, usize, f64) -> f64,
+    ) {
+        for (idx, s) in samples.iter_mut().enumerate() {
+            let c = idx % channels;
+            if !(has_alpha && c == channels - 1) {
+                *s = T::from_f64(f(idx, c, (*s).into()));
+            }
+        }
+    }
+
+    let mut out = image.clone();
+    let color = out.color();
+    let (channels, has_alpha) = (color.channel_count() as usize, color.has_alpha());
+    match &mut out {
+        DynamicImage::ImageLuma8(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageLumaA8(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageRgb8(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageRgba8(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageLuma16(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageLumaA16(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageRgb16(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageRgba16(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageRgb32F(b) => apply(b, channels, has_alpha, &mut f),
+        DynamicImage::ImageRgba32F(b) => apply(b, channels, has_alpha, &mut f),
+        _ => {
+            // As in `for_each_sample`, fall back to floating-point RGBA
+            let mut b = image.to_rgba32f();
+            apply(&mut b, 4, true, &mut f);
+            return DynamicImage::ImageRgba32F(b);
+        }
+    }
+    out
+}
+
+/// For each of `dst_len` pixels along an axis resampled from `src_len` pixels with `filter`, the
+/// first source pixel it reads, and its normalized weights for that pixel and the ones after it
+fn resample_weights(
+    src_len: usize,
+    dst_len: usize,
+    filter: ResampleFilter,
+) -> Vec<(usize, Vec<f64>)> {
+    let ratio = src_len as f64 / dst_len as f64;
+    // Downsampling stretches the kernel over every source pixel a destination pixel covers
+    let scale = ratio.max(1.0);
+    let radius = filter.support() * scale;
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * ratio;
+            let nearest = (center as usize).min(src_len - 1);
+            if filter == ResampleFilter::Nearest {
+                return (nearest, vec![1.0]);
+            }
+
+            let first = (center - radius).floor().max(0.0) as usize;
+            let last = ((center + radius).ceil() as usize).min(src_len);
+            let weights: Vec<f64> = (first..last)
+                .map(|j| filter.kernel((j as f64 + 0.5 - center) / scale))
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            match sum != 0.0 {
+                true => (first, weights.iter().map(|w| w / sum).collect()),
+                false => (nearest, vec![1.0]),
+            }
+        })
+        .collect()
+}
+
+/// Call `f` with the index and contents of each `row_len`-long row of `data`, concurrently if
+/// `parallel` is set
+fn for_each_row<T: Send>(
+    data: &mut [T],
+    row_len: usize,
+    parallel: bool,
+    f: impl Fn(usize, &mut [T]) + Sync,
+) {
+    use rayon::prelude::*;
+
+    match parallel {
+        true => data
+            .par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row)),
+        false => data
+            .chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row)),
+    }
+}
+
+/// Resample interleaved `src` samples of the given dimensions to new ones: first each row to the
+/// new width, then each column to the new height.
+///
+/// Every sample is computed the same way, from the same weights in the same order, however rows
+/// are scheduled, so `parallel` never changes the result.
+fn resample_samples<T: Sample + Send + Sync>(
+    src: &[T],
+    channels: usize,
+    (width, height): (usize, usize),
+    (new_width, new_height): (usize, usize),
+    filter: ResampleFilter,
+    parallel: bool,
+) -> Vec<T> {
+    let columns = resample_weights(width, new_width, filter);
+    let rows = resample_weights(height, new_height, filter);
+    let row_len = new_width * channels;
+
+    let mut wide = vec![0.0; row_len * height];
+    for_each_row(&mut wide, row_len, parallel, |y, row| {
+        let src_row = &src[y * width * channels..][..width * channels];
+        for (x, (first, weights)) in columns.iter().enumerate() {
+            for c in 0..channels {
+                row[x * channels + c] = weights
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| w * Into::<f64>::into(src_row[(first + k) * channels + c]))
+                    .sum();
+            }
+        }
+    });
+
+    let mut out = vec![T::from_f64(0.0); row_len * new_height];
+    for_each_row(&mut out, row_len, parallel, |y, row| {
+        let (first, weights) = &rows[y];
+        for (i, s) in row.iter_mut().enumerate() {
+            *s = T::from_f64(
+                weights
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| w * wide[(first + k) * row_len + i])
+                    .sum(),
+            );
+        }
+    });
+    out
+}
+
+/// Copy `image`, resampled to exactly `width`x`height` with `filter`. See [`resample_samples`]
+fn resample_image(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: ResampleFilter,
+    parallel: bool,
+) -> DynamicImage {
+    fn resample<P>(
+        b: &ImageBuffer<P, Vec<P::Subpixel>>,
+        (width, height): (u32, u32),
+        filter: ResampleFilter,
+        parallel: bool,
+    ) -> DynamicImage
+    where
+        P: Pixel + 'static,
+        P::Subpixel: Sample + Send + Sync,
+        DynamicImage: From<ImageBuffer<P, Vec<P::Subpixel>>>,
+    {
+        let samples = resample_samples(
+            b.as_raw(),
+            P::CHANNEL_COUNT as usize,
+            (b.width() as usize, b.height() as usize),
+            (width as usize, height as usize),
+            filter,
+            parallel,
+        );
+        ImageBuffer::<P, _>::from_raw(width, height, samples)
+            .expect("Resampled samples should fill the new dimensions")
+            .into()
+    }
+
+    if image.width() == 0 || image.height() == 0 {
+        // Nothing to sample from
+        return DynamicImage::new(width, height, image.color());
+    }
+    let dims = (width, height);
+    match image {
+        DynamicImage::ImageLuma8(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageLumaA8(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageRgb8(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageRgba8(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageLuma16(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageLumaA16(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageRgb16(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageRgba16(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageRgb32F(b) => resample(b, dims, filter, parallel),
+        DynamicImage::ImageRgba32F(b) => resample(b, dims, filter, parallel),
+        // As in `for_each_sample`, fall back to floating-point RGBA
+        _ => resample(&image.to_rgba32f(), dims, filter, parallel),
+    }
+}
+
+/// `image`, then each of the rest of `level_dims` resampled from the level before it, calling
+/// `report` with each level's index once it's built
+fn downsample_levels(
+    image: &DynamicImage,
+    level_dims: &[(u32, u32)],
+    filter: ResampleFilter,
+    parallel: bool,
+    report: impl Fn(usize),
+) -> Vec<DynamicImage> {
+    let mut levels = Vec::with_capacity(level_dims.len());
+    levels.push(image.clone());
+    report(0);
+    for (idx, &(w, h)) in level_dims.iter().enumerate().skip(1) {
+        let level = resample_image(&levels[idx - 1], w, h, filter, parallel);
+        levels.push(level);
+        report(idx);
+    }
+    levels
+}
+
+/// The value at the lower edge of the first histogram bin by which `fraction` of the samples
+/// have been seen, in the channel's native range
+fn histogram_percentile(bins: &[u64; HISTOGRAM_BINS], fraction: f64, max: f64) -> f64 {
+    let total = bins.iter().sum::<u64>() as f64;
+    let mut seen = 0;
+    for (b, &count) in bins.iter().enumerate() {
+        seen += count;
+        if seen as f64 >= fraction * total && seen > 0 {
+            return b as f64 * max / (HISTOGRAM_BINS - 1) as f64;
+        }
+    }
+    max
+}
+
+/// The bin that splits a histogram into two classes with the greatest variance between them
+/// (Otsu's method). Everything in that bin or below is in the lower class
+fn otsu_level(bins: &[u64; HISTOGRAM_BINS]) -> u8 {
+    let total = bins.iter().sum::<u64>() as f64;
+    let sum: f64 = bins
+        .iter()
+        .enumerate()
+        .map(|(b, &n)| b as f64 * n as f64)
+        .sum();
+    let (mut weight_low, mut sum_low) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (b, &count) in bins.iter().enumerate() {
+        weight_low += count as f64;
+        if weight_low == 0.0 {
+            continue;
+        }
+        let weight_high = total - weight_low;
+        if weight_high == 0.0 {
+            break;
+        }
+        sum_low += b as f64 * count as f64;
+        let mean_low = sum_low / weight_low;
+        let mean_high = (sum - sum_low) / weight_high;
+        let variance = weight_low * weight_high * (mean_low - mean_high).powi(2);
+        if variance > best_variance {
+            (best, best_variance) = (b, variance);
+        }
+    }
+    best as u8
+}
+
+/// The horizontal and vertical luminance gradients of `image` at each pixel, estimated with
+/// `operator` and scaled so that the steepest possible edge is 1.0 (or -1.0). Edges are clamped
+fn luma_gradients(image: &DynamicImage, operator: EdgeOperator) -> (Vec<f32>, Vec<f32>) {
+    let luma = image.to_luma32f();
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+        luma.get_pixel(x, y).0[0]
+    };
+    let weights = operator.smoothing();
+    let scale: f32 = weights.iter().sum();
+
+    let (mut dx, mut dy) = (Vec::new(), Vec::new());
+    for y in 0..height {
+        for x in 0..width {
+            let (mut gx, mut gy) = (0.0, 0.0);
+            for (k, w) in (-1..=1).zip(weights) {
+                gx += w * (at(x + 1, y + k) - at(x - 1, y + k));
+                gy += w * (at(x + k, y + 1) - at(x + k, y - 1));
+            }
+            dx.push(gx / scale);
+            dy.push(gy / scale);
+        }
+    }
+    (dx, dy)
+}
+
+/// An 8-bit grayscale image of `width`x`height` values from 0.0 to 1.0, clamping anything beyond
+fn unit_values_to_luma8(
+    width: u32,
+    height: u32,
+    values: impl Iterator<Item = f32>,
+) -> DynamicImage {
+    let data = values.map(u8::from_unit).collect();
+    // `values` holds one value per pixel, so the buffer always fits
+    DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, data).unwrap())
+}
+
+/// Fail unless both images are the same size, as comparing them pixel by pixel requires
+fn require_same_size(a: &DynamicImage, b: &DynamicImage) -> Result<()> {
+    if a.dimensions() != b.dimensions() {
+        return Err(Error::unprocessable(format!(
+            "Can't compare a {}x{} image with a {}x{} one",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        )));
+    }
+    Ok(())
+}
+
+/// Mean structural similarity (SSIM) of two grayscale images of `width`x`height` samples from 0.0
+/// to 1.0, over 8x8 windows placed every 4 pixels. Images smaller than a window are one window
+fn mean_ssim(a: &[f32], b: &[f32], width: usize, height: usize) -> f64 {
+    const WINDOW: usize = 8;
+    const STEP: usize = 4;
+    // Stabilizing constants from Wang et al., for a dynamic range of 1.0
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let (win_w, win_h) = (WINDOW.min(width), WINDOW.min(height));
+    let n = (win_w * win_h) as f64;
+    let (mut total, mut windows) = (0.0, 0);
+    for y0 in (0..=height - win_h).step_by(STEP) {
+        for x0 in (0..=width - win_w).step_by(STEP) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y0 + win_h {
+                for x in x0..x0 + win_w {
+                    let (va, vb) = (a[y * width + x] as f64, b[y * width + x] as f64);
+                    sum_a += va;
+                    sum_b += vb;
+                    sum_aa += va * va;
+                    sum_bb += vb * vb;
+                    sum_ab += va * vb;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let cov = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+/// Fail for images without any pixels, which have no meaningful statistics
+fn require_pixels(image: &DynamicImage) -> Result<()> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(Error::validation("Image has no pixels"));
+    }
+    Ok(())
+}
+
+/// Fail unless `kernel` can be convolved with: square, with an odd number of rows. Returns its
+/// number of rows
+pub(crate) fn check_kernel(kernel: &DynMatrix<f64>) -> Result<usize> {
+    let (r, c) = kernel.dims().into();
+    if r != c {
+        return Err(Error::validation("Kernel matrix must be square in shape!"));
+    }
+    if r % 2 == 0 {
+        return Err(Error::validation(
+            "Kernel matrix must have an odd number of rows and columns!",
+        ));
+    }
+    Ok(r)
+}
+
+impl<'a> HasImageProcessingRoutines for IprImage<'a> {
+    fn convolve(&self, kernel: &DynMatrix<f64>) -> Result<DynamicImage> {
+        let r = check_kernel(kernel)?;
+        let c = r;
+
+        let i = &self.0;
+        require_pixels(i)?;
+        let (width, height) = (i.width() as usize, i.height() as usize);
+        let mut samples = Vec::new();
+        for_each_sample(i, |_, v| samples.push(v));
+        let channels = samples.len() / (width * height);
+
+        // Pad each channel into its own plane, repeating the nearest edge sample `radius` deep on
+        // every side, so that every window is in bounds and each window row is contiguous
+        let radius = r / 2;
+        let (padded_w, padded_h) = (width + 2 * radius, height + 2 * radius);
+        let planes: Vec<Vec<f64>> = (0..channels)
+            .map(|ch| {
+                let mut plane = Vec::with_capacity(padded_w * padded_h);
+                for py in 0..padded_h {
+                    let sy = py.saturating_sub(radius).min(height - 1);
+                    for px in 0..padded_w {
+                        let sx = px.saturating_sub(radius).min(width - 1);
+                        plane.push(samples[(sy * width + sx) * channels + ch]);
+                    }
+                }
+                plane
+            })
+            .collect();
+
+        // Convolving flips the kernel, so its bottom-right entry weighs the top-left neighbor.
+        // Flip it once here, and each window sum becomes a plain dot product per row
+        let flipped: Vec<Vec<f64>> = (0..r)
+            .rev()
+            .map(|ky| kernel[ky].iter().rev().copied().collect())
+            .collect();
+
+        Ok(map_color_samples_indexed(i, |idx, ch, _| {
+            let p = idx / channels;
+            let (x, y) = (p % width, p / width);
+            let plane = &planes[ch];
+            flipped
+                .iter()
+                .enumerate()
+                .map(|(ky, k_row)| f64::dot(k_row, &plane[(y + ky) * padded_w + x..][..c]))
+                .sum()
+        }))
+    }
+
+    fn generate_image_pyramid(&self, params: Option<&PyramidParams>) -> Result<Vec<DynamicImage>> {
+        self.generate_image_pyramid_with_progress(params, None)
+    }
+
+    /// Lowpass and Gaussian levels are each resampled from the level just above them. With the
+    /// `parallel` feature, the rows of each level are resampled concurrently, which gives the
+    /// same levels as building them sequentially.
+    ///
+    /// Laplacian levels depend on their neighbors, so they are built in one sequential pass and
+    /// reported once it's done.
+    fn generate_image_pyramid_with_progress(
+        &self,
+        params: Option<&PyramidParams>,
+        progress: Option<&PyramidProgress>,
+    ) -> Result<Vec<DynamicImage>> {
+        use image_pyramid::*;
+
+        let all_params = params.copied().unwrap_or_default();
+        let PyramidParams {
+            pyramid_type,
+            scale_factor,
+            max_levels,
+            ..
+        } = all_params;
+        if !(scale_factor > 0.0 && scale_factor < 1.0) {
+            return Err(Error::validation(
+                "Pyramid scale factor must be between 0.0 and 1.0 (exclusive)",
+            ));
+        }
+        if max_levels == Some(0) {
+            return Err(Error::validation("Pyramid must have at least one level"));
+        }
+
+        let (width, height) = self.0.dimensions();
+        let level_dims = all_params.level_dims(width, height);
+        let total = level_dims.len();
+        let report = |idx: usize| {
+            if let Some(progress) = progress {
+                progress(idx, total);
+            }
+        };
+
+        if total == 1 && pyramid_type != PyramidType::Laplacian {
+            // Nothing to downsample
+            report(0);
+            return Ok(vec![self.0.clone()]);
+        }
+
+        let filter = all_params.resample_filter();
+        let parallel = cfg!(feature = "parallel");
+        if pyramid_type == PyramidType::Laplacian {
+            let params = ImagePyramidParams {
+                pyramid_type: ImagePyramidType::Bandpass,
+                scale_factor,
+                smoothing_type: SmoothingType::Gaussian,
+            };
+            let pyramid = ImagePyramid::create(self.0, Some(&params)).map_err(Error::internal)?;
+
+            // `image_pyramid` floors level dimensions, and stops once either dimension
+            // reaches 1. Force its levels onto our (ceil-rounded, down to 1x1) dimensions
+            // instead, so tile math holds at every level. See `pyramid_level_dims`
+            let mut levels = pyramid.levels;
+            levels.truncate(total);
+            for (idx, &(w, h)) in level_dims.iter().enumerate() {
+                if idx >= levels.len() {
+                    let next = resample_image(&levels[idx - 1], w, h, filter, parallel);
+                    levels.push(next);
+                } else if levels[idx].dimensions() != (w, h) {
+                    levels[idx] = resample_image(&levels[idx], w, h, filter, parallel);
+                }
+                report(idx);
+            }
+            return Ok(levels);
+        }
+
+        Ok(downsample_levels(
+            self.0,
+            &level_dims,
+            filter,
+            parallel,
+            report,
+        ))
+    }
+
+    /// Splits this image into tiles of the given dimensions or smaller.
+    ///
+    /// The number of tiles returned is the product of the following:
+    ///     - The ceiling of `self.width() / tile_width`
+    ///     - The ceiling of `self.height() / tile_height`
+    ///
+    /// That is, if the instance's dimensions are (1000,1000) and the tile dimensions are (300,300)
+    /// then there will be sixteen tiles, as illustrated below. (9 full-size tiles, 3 tiles along
+    /// the bottom with a height of 100, 3 tiles along the right side with a width of 100, and one
+    /// tile in the bottom-right corner with dimensions (100,100)).
+    ///
+    /// ```text
+    ///           300px       300px       300px   100px
+    ///       ┌───────────┬───────────┬──────────┬─────┐
+    ///       │           │           │          │     │
+    ///  300px│           │           │          │     │
+    ///       │           │           │          │     │
+    ///       │           │           │          │     │
+    ///       ├───────────┼───────────┼──────────┼─────┤
+    ///       │           │           │          │     │
+    ///  300px│           │           │          │     │
+    ///       │           │           │          │     │
+    ///       │           │           │          │     │
+    ///       ├───────────┼───────────┼──────────┼─────┤
+    ///       │           │           │          │     │
+    ///  300px│           │           │          │     │
+    ///       │           │           │          │     │
+    ///       │           │           │          │     │
+    ///       ├───────────┼───────────┼──────────┼─────┤
+    ///  100px│           │           │          │     │
+    ///       │           │           │          │     │
+    ///       └───────────┴───────────┴──────────┴─────┘
+    /// ``````
+    fn make_tiles(&self, tile_width: u32, tile_height: u32) -> Result<ImageTiles> {
+        if tile_width == 0 || tile_height == 0 {
+            return Err(Error::validation("Tiles must be at least 1x1"));
+        }
+        let i = &self.0;
+        let grid = TileGrid::new(
+            self.dims(),
+            (tile_height as usize, tile_width as usize).into(),
+        );
+        let tiles = grid
+            .iter()
+            .map(|r| i.crop_imm(r.col as u32, r.row as u32, r.cols as u32, r.rows as u32))
+            .collect();
+
+        let count = grid.count();
+        Ok(ImageTiles {
+            original_height: i.height(),
+            original_width: i.width(),
+            tiles,
+            tile_width,
+            tile_height,
+            count_across: count.cols() as u32,
+            count_down: count.rows() as u32,
+        })
+    }
+
+    fn compress_brotli(
+        &self,
+        brotli_level: u32,
+        brotli_lg_window_size: u32,
+        fmt: Option<ImageFormat>,
+    ) -> Result<Vec<u8>> {
+        let i = &self.0;
+        let dest_fmt = fmt.unwrap_or(ImageFormat::Png);
+
+        let mut data = encode_image(i, dest_fmt)?;
+
+        let brotli_params = brotli::enc::BrotliEncoderParams {
+            quality: match brotli_level.try_into() {
+                Ok(v) => v,
+                Err(_) => return Err(Error::validation("Brotli level must be between 0 and 11")),
+            },
+            lgwin: match brotli_lg_window_size.try_into() {
+                Ok(v) => v,
+                Err(_) => {
+                    return Err(Error::validation(
+                        "Brotli lg_window_size must be between 10 and 24",
+                    ))
+                }
+            },
+            // This is a neat feature :-)
+            ..Default::default()
+        };
+
+        let mut compressed_data = Vec::new();
+        brotli::BrotliCompress(
+            &mut Cursor::new(&mut data),
+            &mut compressed_data,
+            &brotli_params,
+        )
+        .unwrap();
+
+        Ok(compressed_data)
+    }
+
+    fn compress_gzip(&self, level: u32, fmt: Option<ImageFormat>) -> Result<Vec<u8>> {
+        self.compress(Compression::Gzip, level, fmt)
+    }
+
+    fn compress_zstd(&self, level: u32, fmt: Option<ImageFormat>) -> Result<Vec<u8>> {
+        self.compress(Compression::Zstd, level, fmt)
+    }
+
+    fn compress(
+        &self,
+        codec: Compression,
+        level: u32,
+        fmt: Option<ImageFormat>,
+    ) -> Result<Vec<u8>> {
+        let data = encode_image(self.0, fmt.unwrap_or(ImageFormat::Png))?;
+        codec.compress(&data, level)
+    }
+
+    fn histogram(&self) -> Result<Vec<[u64; HISTOGRAM_BINS]>> {
+        let i = &self.0;
+        require_pixels(i)?;
+
+        let scale = (HISTOGRAM_BINS - 1) as f64 / sample_max(i);
+        let mut bins = vec![[0u64; HISTOGRAM_BINS]; i.color().channel_count() as usize];
+        for_each_sample(i, |c, v| {
+            let bin = (v * scale).round().clamp(0.0, (HISTOGRAM_BINS - 1) as f64) as usize;
+            bins[c][bin] += 1;
+        });
+        Ok(bins)
+    }
+
+    fn mean_stddev(&self) -> Result<Vec<(f64, f64)>> {
+        let i = &self.0;
+        require_pixels(i)?;
+
+        let channels = i.color().channel_count() as usize;
+        let mut sums = vec![(0.0, 0.0); channels];
+        for_each_sample(i, |c, v| {
+            sums[c].0 += v;
+            sums[c].1 += v * v;
+        });
+
+        let n = i.width() as f64 * i.height() as f64;
+        Ok(sums
+            .into_iter()
+            .map(|(sum, sum_sq)| {
+                let mean = sum / n;
+                // Rounding can push this a hair below zero for flat channels
+                let variance = (sum_sq / n - mean * mean).max(0.0);
+                (mean, variance.sqrt())
+            })
+            .collect())
+    }
+
+    fn min_max(&self) -> Result<Vec<(f64, f64)>> {
+        let i = &self.0;
+        require_pixels(i)?;
+
+        let channels = i.color().channel_count() as usize;
+        let mut extremes = vec![(f64::INFINITY, f64::NEG_INFINITY); channels];
+        for_each_sample(i, |c, v| {
+            extremes[c].0 = extremes[c].0.min(v);
+            extremes[c].1 = extremes[c].1.max(v);
+        });
+        Ok(extremes)
+    }
+
+    fn equalize_histogram(&self) -> Result<DynamicImage> {
+        let i = &self.0;
+        let max = sample_max(i);
+        let scale = (HISTOGRAM_BINS - 1) as f64 / max;
+
+        // For each channel, map each bin to the fraction of samples at or below it, discounting
+        // the darkest occupied bin so that it maps to zero
+        let lookup = self
+            .histogram()?
+            .iter()
+            .map(|bins| {
+                let mut cdf = [0u64; HISTOGRAM_BINS];
+                let mut seen = 0;
+                for (b, &count) in bins.iter().enumerate() {
+                    seen += count;
+                    cdf[b] = seen;
+                }
+                let cdf_min = cdf.iter().copied().find(|&c| c > 0).unwrap_or(0);
+                let spread = (seen - cdf_min) as f64;
+                cdf.map(|c| match spread > 0.0 {
+                    true => c.saturating_sub(cdf_min) as f64 / spread * max,
+                    // A flat channel has nothing to spread out
+                    false => f64::NAN,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(map_color_samples(i, |c, v| {
+            let bin = (v * scale).round().clamp(0.0, (HISTOGRAM_BINS - 1) as f64) as usize;
+            match lookup[c][bin] {
+                mapped if mapped.is_nan() => v,
+                mapped => mapped,
+            }
+        }))
+    }
+
+    fn stretch_contrast(&self, low_pct: f64, high_pct: f64) -> Result<DynamicImage> {
+        if !(0.0..=100.0).contains(&low_pct) || !(0.0..=100.0).contains(&high_pct) {
+            return Err(Error::validation(
+                "Contrast stretch percentiles must be between 0 and 100",
+            ));
+        }
+        if low_pct >= high_pct {
+            return Err(Error::validation(
+                "Low percentile must be below high percentile",
+            ));
+        }
+
+        let i = &self.0;
+        let max = sample_max(i);
+        let bounds = self
+            .histogram()?
+            .iter()
+            .map(|bins| {
+                (
+                    histogram_percentile(bins, low_pct / 100.0, max),
+                    histogram_percentile(bins, high_pct / 100.0, max),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Ok(map_color_samples(i, |c, v| {
+            let (low, high) = bounds[c];
+            match high > low {
+                true => ((v - low) / (high - low) * max).clamp(0.0, max),
+                // A flat channel has nothing to stretch
+                false => v,
+            }
+        }))
+    }
+
+    fn resize(&self, width: u32, height: u32, filter: ResampleFilter) -> Result<DynamicImage> {
+        if width == 0 || height == 0 {
+            return Err(Error::validation("Image must be resized to at least 1x1"));
+        }
+        Ok(resample_image(
+            self.0,
+            width,
+            height,
+            filter,
+            cfg!(feature = "parallel"),
+        ))
+    }
+
+    fn rotate(&self, degrees: u32) -> Result<DynamicImage> {
+        match degrees % 360 {
+            0 => Ok(self.0.clone()),
+            90 => Ok(self.0.rotate90()),
+            180 => Ok(self.0.rotate180()),
+            270 => Ok(self.0.rotate270()),
+            _ => Err(Error::validation(
+                "Images can only be rotated by multiples of 90 degrees",
+            )),
+        }
+    }
+
+    fn compare(&self, other: &DynamicImage, with_ssim: bool) -> Result<ImageComparison> {
+        let i = &self.0;
+        require_pixels(i)?;
+        require_same_size(i, other)?;
+
+        let (a, b) = (i.to_rgba32f(), other.to_rgba32f());
+        let sum_sq: f64 = a
+            .as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+            .sum();
+        let mse = sum_sq / a.as_raw().len() as f64;
+        let psnr = (mse > 0.0).then(|| -10.0 * mse.log10());
+
+        let ssim = with_ssim.then(|| {
+            let (a, b) = (i.to_luma32f(), other.to_luma32f());
+            let (width, height) = (i.width() as usize, i.height() as usize);
+            mean_ssim(a.as_raw(), b.as_raw(), width, height)
+        });
+
+        Ok(ImageComparison { mse, psnr, ssim })
+    }
+
+    fn difference(&self, other: &DynamicImage) -> Result<DynamicImage> {
+        let i = &self.0;
+        require_same_size(i, other)?;
+
+        let (a, b) = (i.to_rgba32f(), other.to_rgba32f());
+        Ok(DynamicImage::ImageRgb8(ImageBuffer::from_fn(
+            i.width(),
+            i.height(),
+            |x, y| {
+                let (pa, pb) = (a.get_pixel(x, y).0, b.get_pixel(x, y).0);
+                Rgb(std::array::from_fn(|c| {
+                    ((pa[c] - pb[c]).abs() * u8::MAX as f32).round() as u8
+                }))
+            },
+        )))
+    }
+
+    fn to_grayscale(&self) -> Result<DynamicImage> {
+        Ok(self.0.grayscale())
+    }
+
+    fn split_channels(&self) -> Result<Vec<MyImage<u8>>> {
+        let i = &self.0;
+        let (data, channels) = match i.color().channel_count() {
+            1 => (i.to_luma8().into_raw(), 1),
+            2 => (i.to_luma_alpha8().into_raw(), 2),
+            3 => (i.to_rgb8().into_raw(), 3),
+            _ => (i.to_rgba8().into_raw(), 4),
+        };
+        Ok((0..channels)
+            .map(|c| {
+                let mut channel = MyImage::new(i.width(), i.height(), 1);
+                for (component, &v) in channel
+                    .data_mut()
+                    .iter_mut()
+                    .zip(data.iter().skip(c).step_by(channels))
+                {
+                    *component = v;
+                }
+                channel
+            })
+            .collect())
+    }
+
+    fn threshold(&self, value: u8) -> Result<DynamicImage> {
+        let mut luma = self.0.to_luma8();
+        for p in luma.pixels_mut() {
+            p.0[0] = match p.0[0] > value {
+                true => u8::MAX,
+                false => 0,
+            };
+        }
+        Ok(DynamicImage::ImageLuma8(luma))
+    }
+
+    fn otsu_threshold(&self) -> Result<u8> {
+        let i = &self.0;
+        require_pixels(i)?;
+
+        let mut bins = [0u64; HISTOGRAM_BINS];
+        for p in i.to_luma8().pixels() {
+            bins[p.0[0] as usize] += 1;
+        }
+        Ok(otsu_level(&bins))
+    }
+
+    fn edges(&self, operator: EdgeOperator) -> Result<DynamicImage> {
+        let i = &self.0;
+        require_pixels(i)?;
+
+        let (dx, dy) = luma_gradients(i, operator);
+        let magnitudes = dx.iter().zip(&dy).map(|(gx, gy)| gx.hypot(*gy));
+        Ok(unit_values_to_luma8(i.width(), i.height(), magnitudes))
+    }
+
+    fn gradients(&self, operator: EdgeOperator) -> Result<(DynamicImage, DynamicImage)> {
+        let i = &self.0;
+        require_pixels(i)?;
+
+        let (dx, dy) = luma_gradients(i, operator);
+        let centered = |g: Vec<f32>| {
+            unit_values_to_luma8(i.width(), i.height(), g.into_iter().map(|v| v * 0.5 + 0.5))
+        };
+        Ok((centered(dx), centered(dy)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::testgen;
+    use rayon::prelude::*;
+    extern crate test;
+    use test::Bencher;
+    use test_case::test_case;
+
+    #[test_case("test_files/elden_ring.jpg")]
+    #[test_case("test_files/totk.bmp")]
+    #[test_case("test_files/totk.jpg")]
+    #[test_case("test_files/totk.png")]
+    #[test_case("test_files/totk.webp")]
+    fn compress_brotli(i_path: &str) {
+        let i = image::open(i_path).unwrap();
+        let i = IprImage(&i);
+
+        let original_data_format = ImageFormat::from_path(i_path).unwrap();
+        let compressed_buf = match i.compress_brotli(10, 24, Some(original_data_format)) {
+            Ok(v) => v,
+            Err(_) => panic!("You fool! You cannot compress such a thing."),
+        };
+
+        let mut original_buf = Vec::<u8>::new();
+        let mut cursor = Cursor::new(&mut original_buf);
+        match i.0.write_to(&mut cursor, original_data_format) {
+            Ok(_) => (),
+            Err(_) => panic!("You fool! You cannot write such a thing."),
+        };
+
+        assert!(compressed_buf.len() < original_buf.len());
+    }
+
+    #[test_case(Compression::Gzip, 6)]
+    #[cfg_attr(feature = "server-codecs", test_case(Compression::Zstd, 19))]
+    fn compress_with_codec_round_trips(codec: Compression, level: u32) {
+        let i = image::open("test_files/totk.png").unwrap();
+        let compressed = IprImage(&i)
+            .compress(codec, level, Some(ImageFormat::Png))
+            .unwrap();
+        let decompressed = codec.decompress(&compressed).unwrap();
+        let decoded = decode_image(&decompressed, ImageFormat::Png, None).unwrap();
+        assert_eq!(decoded.dimensions(), i.dimensions());
+    }
+
+    fn make_multi_page_tiff() -> Vec<u8> {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        let mut data = Vec::new();
+        let mut encoder = TiffEncoder::new(Cursor::new(&mut data)).unwrap();
+        encoder
+            .write_image::<colortype::Gray8>(4, 2, &[0u8; 8])
+            .unwrap();
+        encoder
+            .write_image::<colortype::RGB8>(3, 5, &[255u8; 45])
+            .unwrap();
+        drop(encoder);
+        data
+    }
+
+    #[test]
+    fn tiff_page_count_counts_every_page() {
+        let data = make_multi_page_tiff();
+        assert_eq!(tiff_page_count(&data), Ok(2));
+    }
+
+    #[test]
+    fn decode_image_selects_tiff_page() {
+        let data = make_multi_page_tiff();
+
+        let first = decode_image(&data, ImageFormat::Tiff, None).unwrap();
+        assert_eq!(first.dimensions(), (4, 2));
+        assert!(matches!(first, DynamicImage::ImageLuma8(_)));
+
+        let second = decode_image(&data, ImageFormat::Tiff, Some(1)).unwrap();
+        assert_eq!(second.dimensions(), (3, 5));
+        assert!(matches!(second, DynamicImage::ImageRgb8(_)));
+
+        assert!(matches!(
+            decode_image(&data, ImageFormat::Tiff, Some(2)),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    /// A 16-bit gradient whose samples aren't multiples of 257, so squashing it to 8 bits and
+    /// back can't give the same values
+    fn deep_gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma16(ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([(x * 4099 + y * 131 + 1) as u16])
+        }))
+    }
+
+    #[test]
+    fn sixteen_bit_png_round_trips() {
+        let gray = deep_gradient(16, 8);
+        let rgba = DynamicImage::ImageRgba16(gray.to_rgba16());
+        for image in [gray, rgba] {
+            let encoded = encode_image(&image, ImageFormat::Png).unwrap();
+            let decoded = decode_image(&encoded, ImageFormat::Png, None).unwrap();
+            assert_eq!(decoded, image);
+        }
+    }
+
+    #[test]
+    fn float_tiff_pages_keep_their_samples() {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        let samples: Vec<f32> = (0..12).map(|i| i as f32 / 7.0).collect();
+        let mut data = Vec::new();
+        TiffEncoder::new(Cursor::new(&mut data))
+            .unwrap()
+            .write_image::<colortype::Gray32Float>(4, 3, &samples)
+            .unwrap();
+
+        let decoded = decode_image(&data, ImageFormat::Tiff, None).unwrap();
+        let DynamicImage::ImageRgb32F(rgb) = &decoded else {
+            panic!("Expected floating-point RGB, not {:?}", decoded.color());
+        };
+        for (pixel, &gray) in rgb.pixels().zip(&samples) {
+            assert_eq!(pixel.0, [gray; 3]);
+        }
+
+        // PNG can't hold floats, but keeps 16 bits of them
+        let png = encode_image(&decoded, ImageFormat::Png).unwrap();
+        let from_png = decode_image(&png, ImageFormat::Png, None).unwrap();
+        assert_eq!(from_png, DynamicImage::ImageRgb16(decoded.to_rgb16()));
+    }
+
+    #[test]
+    fn deep_images_are_narrowed_only_for_formats_that_need_it() {
+        let image = deep_gradient(4, 4);
+        assert_eq!(fit_bit_depth(&image, ImageFormat::Tiff).as_ref(), &image);
+        assert_eq!(
+            fit_bit_depth(&image, ImageFormat::Bmp).as_ref(),
+            &DynamicImage::ImageLuma8(image.to_luma8())
+        );
+        let bmp = encode_image(&image, ImageFormat::Bmp).unwrap();
+        assert_eq!(
+            decode_image(&bmp, ImageFormat::Bmp, None)
+                .unwrap()
+                .dimensions(),
+            (4, 4)
+        );
+    }
+
+    #[test_case(PyramidType::Lowpass)]
+    #[test_case(PyramidType::Gaussian)]
+    fn pyramids_and_tiles_keep_bit_depth(pyramid_type: PyramidType) {
+        let image = deep_gradient(37, 21);
+        let params = PyramidParams {
+            pyramid_type,
+            ..Default::default()
+        };
+        let mut pyramid = Pyramid::new(&image, Some(&params)).unwrap();
+        assert_eq!(pyramid.level(0), Some(&image));
+        assert!(pyramid
+            .levels()
+            .iter()
+            .all(|l| matches!(l, DynamicImage::ImageLuma16(_))));
+
+        pyramid.tile_all_levels(16, 16).unwrap();
+        let tiles = pyramid.tiles(0).unwrap();
+        assert_eq!(tiles.tiles[0], image.crop_imm(0, 0, 16, 16));
+        for tile in &tiles.tiles {
+            assert_eq!(choose_tile_format(tile, None), ImageFormat::Png);
+            let encoded = encode_image(tile, ImageFormat::Png).unwrap();
+            assert_eq!(
+                &decode_image(&encoded, ImageFormat::Png, None).unwrap(),
+                tile
+            );
+        }
+    }
+
+    #[test]
+    fn encoded_image_dimensions_reads_headers() {
+        let data = make_multi_page_tiff();
+        assert_eq!(
+            encoded_image_dimensions(&data, ImageFormat::Tiff, None),
+            Ok((4, 2))
+        );
+        assert_eq!(
+            encoded_image_dimensions(&data, ImageFormat::Tiff, Some(1)),
+            Ok((3, 5))
+        );
+
+        let i = image::open("test_files/totk.png").unwrap();
+        let data = std::fs::read("test_files/totk.png").unwrap();
+        assert_eq!(
+            encoded_image_dimensions(&data, ImageFormat::Png, None),
+            Ok(i.dimensions())
+        );
+        assert!(encoded_image_dimensions(&[0, 1, 2, 3], ImageFormat::Png, None).is_err());
+    }
+
+    /// A minimal EXIF chunk with just an orientation tag
+    fn exif_with_orientation(big_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |v: u16| match big_endian {
+            true => v.to_be_bytes(),
+            false => v.to_le_bytes(),
+        };
+        let u32_bytes = |v: u32| match big_endian {
+            true => v.to_be_bytes(),
+            false => v.to_le_bytes(),
+        };
+        let mut exif = match big_endian {
+            true => b"MM\0*".to_vec(),
+            false => b"II*\0".to_vec(),
+        };
+        exif.extend(u32_bytes(8));
+        exif.extend(u16_bytes(1));
+        exif.extend(u16_bytes(0x0112));
+        exif.extend(u16_bytes(3)); // SHORT
+        exif.extend(u32_bytes(1));
+        exif.extend(u16_bytes(orientation));
+        exif.extend([0, 0]);
+        exif.extend(u32_bytes(0));
+        exif
+    }
+
+    #[test]
+    fn exif_orientation_is_read_in_either_byte_order() {
+        assert_eq!(exif_orientation(&exif_with_orientation(false, 6)), Some(6));
+        assert_eq!(exif_orientation(&exif_with_orientation(true, 3)), Some(3));
+
+        let mut prefixed = b"Exif\0\0".to_vec();
+        prefixed.extend(exif_with_orientation(true, 8));
+        assert_eq!(exif_orientation(&prefixed), Some(8));
+
+        assert_eq!(exif_orientation(&exif_with_orientation(false, 9)), None);
+        assert_eq!(exif_orientation(b"II*\0"), None);
+        assert_eq!(exif_orientation(b"not exif"), None);
+    }
+
+    // Where the top-left pixel of a 3x2 image ends up, and the oriented image's dimensions
+    #[test_case(1, (0, 0), (3, 2))]
+    #[test_case(2, (2, 0), (3, 2))]
+    #[test_case(3, (2, 1), (3, 2))]
+    #[test_case(4, (0, 1), (3, 2))]
+    #[test_case(5, (0, 0), (2, 3))]
+    #[test_case(6, (1, 0), (2, 3))]
+    #[test_case(7, (1, 2), (2, 3))]
+    #[test_case(8, (0, 2), (2, 3))]
+    #[test_case(0, (0, 0), (3, 2))]
+    fn exif_orientation_is_applied(orientation: u8, corner: (u32, u32), dims: (u32, u32)) {
+        let image = DynamicImage::ImageLuma8(ImageBuffer::from_fn(3, 2, |x, y| {
+            Luma([if (x, y) == (0, 0) { u8::MAX } else { 0 }])
+        }));
+        let oriented = apply_exif_orientation(image, orientation);
+        assert_eq!(oriented.dimensions(), dims);
+        assert_eq!(oriented.get_pixel(corner.0, corner.1).0[0], u8::MAX);
+    }
+
+    #[test]
+    fn metadata_is_read_from_headers() {
+        let i = image::open("test_files/totk.png").unwrap();
+        let data = std::fs::read("test_files/totk.png").unwrap();
+        let metadata = read_metadata(&data, ImageFormat::Png).unwrap();
+        assert_eq!((metadata.width, metadata.height), i.dimensions());
+        assert_eq!(metadata.channels, i.color().channel_count());
+        assert_eq!(metadata.bit_depth, 8);
+        assert_eq!(metadata.exif_orientation, None);
+
+        let mut data = Vec::new();
+        DynamicImage::ImageLumaA16(ImageBuffer::new(3, 2))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        let metadata = read_metadata(&data, ImageFormat::Png).unwrap();
+        assert_eq!((metadata.width, metadata.height), (3, 2));
+        assert_eq!((metadata.channels, metadata.bit_depth), (2, 16));
+
+        assert!(read_metadata(&[0, 1, 2, 3], ImageFormat::Png).is_err());
+    }
+
+    /// A 4x4 RGBA image, fully transparent on the left half if `transparent`
+    fn half_transparent(transparent: bool) -> DynamicImage {
+        let alpha = if transparent { 0 } else { u8::MAX };
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 4, |x, _| {
+            Rgba([200, 100, 50, if x < 2 { alpha } else { u8::MAX }])
+        }))
+    }
+
+    #[test_case(true, None, ImageFormat::Png ; "auto transparent")]
+    #[test_case(false, None, ImageFormat::Jpeg ; "auto opaque")]
+    #[test_case(true, Some(ImageFormat::WebP), ImageFormat::WebP ; "webp keeps alpha")]
+    #[test_case(true, Some(ImageFormat::Jpeg), ImageFormat::Png ; "jpeg falls back")]
+    #[test_case(false, Some(ImageFormat::Jpeg), ImageFormat::Jpeg ; "opaque rgba as jpeg")]
+    #[test_case(false, Some(ImageFormat::Dds), ImageFormat::Png ; "unwritable falls back")]
+    fn choose_tile_format_keeps_transparency(
+        transparent: bool,
+        requested: Option<ImageFormat>,
+        expected: ImageFormat,
+    ) {
+        let image = half_transparent(transparent);
+        assert_eq!(choose_tile_format(&image, requested), expected);
+    }
+
+    #[test]
+    fn images_without_alpha_are_never_transparent() {
+        let image = DynamicImage::new_rgb8(2, 2);
+        assert!(!has_transparency(&image));
+        assert_eq!(choose_tile_format(&image, None), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn encoding_rgba_as_jpeg_drops_alpha() {
+        let encoded = encode_image(&half_transparent(false), ImageFormat::Jpeg).unwrap();
+        let decoded = decode_image(&encoded, ImageFormat::Jpeg, None).unwrap();
+        assert_eq!(decoded.dimensions(), (4, 4));
+        assert!(!decoded.color().has_alpha());
+    }
+
+    #[test_case(ImageFormat::Avif)]
+    #[test_case(ImageFormat::Tiff)]
+    fn encode_image_round_trips_dimensions(fmt: ImageFormat) {
+        let i = image::open("test_files/totk.png").unwrap();
+        let i = i.thumbnail(64, 64);
+        let encoded = encode_image(&i, fmt).unwrap();
+        if fmt.reading_enabled() {
+            let decoded = decode_image(&encoded, fmt, None).unwrap();
+            assert_eq!(decoded.dimensions(), i.dimensions());
+        } else {
+            assert!(!encoded.is_empty());
+        }
+    }
+
+    #[test_case(ImageFormat::Jpeg)]
+    #[cfg_attr(feature = "server-codecs", test_case(ImageFormat::WebP))]
+    #[test_case(ImageFormat::Avif)]
+    fn lower_quality_encodes_smaller(fmt: ImageFormat) {
+        let i = image::open("test_files/totk.png")
+            .unwrap()
+            .thumbnail(128, 128);
+        let low = encode_image_with_quality(&i, fmt, Some(10)).unwrap();
+        let high = encode_image_with_quality(&i, fmt, Some(95)).unwrap();
+        assert!(low.len() < high.len());
+        if fmt.reading_enabled() {
+            let decoded = decode_image(&low, fmt, None).unwrap();
+            assert_eq!(decoded.dimensions(), i.dimensions());
+        }
+    }
+
+    #[test]
+    fn quality_is_validated_and_ignored_by_lossless_formats() {
+        let i = half_transparent(true);
+        for q in [0, 101] {
+            assert!(matches!(
+                encode_image_with_quality(&i, ImageFormat::Jpeg, Some(q)),
+                Err(Error::Validation(_))
+            ));
+        }
+        assert_eq!(
+            encode_image_with_quality(&i, ImageFormat::Png, Some(10)).unwrap(),
+            encode_image(&i, ImageFormat::Png).unwrap()
+        );
+
+        // Lossy WebP keeps transparency, and without libwebp, WebP stays lossless
+        let webp = encode_image_with_quality(&i, ImageFormat::WebP, Some(50)).unwrap();
+        let decoded = image::load_from_memory_with_format(&webp, ImageFormat::WebP).unwrap();
+        assert!(has_transparency(&decoded));
+        if !cfg!(feature = "server-codecs") {
+            assert_eq!(webp, encode_image(&i, ImageFormat::WebP).unwrap());
+        }
+    }
+
+    #[test_case("test_files/elden_ring.jpg")]
+    #[test_case("test_files/totk.bmp")]
+    #[test_case("test_files/totk.jpg")]
+    #[test_case("test_files/totk.png")]
     #[test_case("test_files/totk.webp")]
     fn generate_image_pyramid(i_path: &str) {
         let i = image::open(i_path).unwrap();
         let i = IprImage(&i);
 
-        let pyramid = match i.generate_image_pyramid() {
+        let pyramid = match i.generate_image_pyramid(None) {
             Ok(v) => v,
             Err(_) => panic!("You fool! You cannot construct the pyramids with such a thing."),
         };
 
         // Compute expected pyramid levels using the image dimensions. We compute the full image
-        // pyramid down to a single-pixel
+        // pyramid down to a single pixel, so there is one level per halving of the larger
+        // dimension (rounding up), plus the original
+        let larger = i.0.width().max(i.0.height());
         let expected_pyramid_levels = (0..)
-            .map(|n| 2u32.pow(n))
-            .take_while(|&n| n <= i.0.width() && n <= i.0.height())
-            .collect::<Vec<u32>>();
+            .map(|n| 2u64.pow(n))
+            .take_while(|&n| n < 2 * larger as u64)
+            .collect::<Vec<u64>>();
 
         assert_eq!(pyramid.len(), expected_pyramid_levels.len());
     }
 
+    #[test_case(1, 1 ; "single pixel")]
+    #[test_case(2, 1 ; "two pixels")]
+    #[test_case(3, 3 ; "odd square")]
+    #[test_case(5, 7 ; "small primes")]
+    #[test_case(13, 1 ; "prime row")]
+    #[test_case(1, 29 ; "prime column")]
+    #[test_case(17, 31 ; "prime rect")]
+    #[test_case(97, 89 ; "larger primes")]
+    #[test_case(257, 129 ; "power of two plus one")]
+    #[test_case(255, 127 ; "power of two minus one")]
+    #[test_case(256, 128 ; "power of two")]
+    #[test_case(1021, 3 ; "wide prime")]
+    fn pyramid_level_dims_round_up(width: u32, height: u32) {
+        let dims = pyramid_level_dims(width, height, 0.5);
+
+        // ceil(d / 2^n), computed with integer math rather than repeated rounding
+        let ceil_div = |d: u32, n: usize| -> u32 { ((d as u64 + (1 << n) - 1) >> n) as u32 };
+        for (n, &(w, h)) in dims.iter().enumerate() {
+            assert_eq!((w, h), (ceil_div(width, n), ceil_div(height, n)));
+        }
+
+        assert_eq!(dims.last(), Some(&(1, 1)));
+        let larger = width.max(height);
+        let expected_levels = (u32::BITS - (larger - 1).leading_zeros()) as usize + 1;
+        assert_eq!(dims.len(), expected_levels);
+    }
+
+    #[test_case(0.3)]
+    #[test_case(0.75)]
+    #[test_case(0.99)]
+    fn pyramid_level_dims_terminate_for_any_scale_factor(scale_factor: f32) {
+        let dims = pyramid_level_dims(101, 37, scale_factor);
+        assert_eq!(dims.first(), Some(&(101, 37)));
+        assert_eq!(dims.last(), Some(&(1, 1)));
+        for pair in dims.windows(2) {
+            let ((w0, h0), (w1, h1)) = (pair[0], pair[1]);
+            assert!(w1 < w0 || w0 == 1);
+            assert!(h1 < h0 || h0 == 1);
+        }
+    }
+
+    #[test_case(1, 1)]
+    #[test_case(3, 3)]
+    #[test_case(5, 7)]
+    #[test_case(17, 31)]
+    #[test_case(97, 89)]
+    #[test_case(257, 129)]
+    #[test_case(1021, 3)]
+    fn odd_dims_survive_pyramid_and_tiling(width: u32, height: u32) {
+        let i = DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        }));
+        let i = IprImage(&i);
+
+        let expected_dims = pyramid_level_dims(width, height, 0.5);
+        for pyramid_type in [
+            PyramidType::Lowpass,
+            PyramidType::Gaussian,
+            PyramidType::Laplacian,
+        ] {
+            let params = PyramidParams {
+                pyramid_type,
+                ..Default::default()
+            };
+            let pyramid = i.generate_image_pyramid(Some(&params)).unwrap();
+            let dims = pyramid.iter().map(|l| l.dimensions()).collect::<Vec<_>>();
+            assert_eq!(dims, expected_dims);
+        }
+
+        let pyramid = i.generate_image_pyramid(None).unwrap();
+        for level in &pyramid {
+            let (w, h) = level.dimensions();
+            // Deliberately awkward tile size, so most levels have ragged edge tiles
+            let tiles = IprImage(level).make_tiles(4, 3).unwrap();
+            assert_eq!(tiles.count_across, w.div_ceil(4));
+            assert_eq!(tiles.count_down, h.div_ceil(3));
+            assert_eq!(
+                tiles.tiles.len(),
+                (tiles.count_across * tiles.count_down) as usize
+            );
+
+            // Tiles cover the level exactly, with nothing empty or oversized
+            let mut area = 0;
+            for (t_idx, tile) in tiles.tiles.iter().enumerate() {
+                let (tw, th) = tile.dimensions();
+                assert!((1..=4).contains(&tw));
+                assert!((1..=3).contains(&th));
+                area += tw * th;
+
+                let rect = tiles.tile_rect(t_idx).unwrap();
+                assert_eq!((rect.cols as u32, rect.rows as u32), (tw, th));
+                assert_eq!(
+                    *tile,
+                    level.crop_imm(rect.col as u32, rect.row as u32, tw, th)
+                );
+            }
+            assert_eq!(area, w * h);
+            assert_eq!(tiles.dims(), IprImage(level).dims());
+        }
+    }
+
+    #[test]
+    fn tiles_know_where_they_are() {
+        let i = testgen::gradient(10, 7).unwrap();
+        let tiles = IprImage(&i).make_tiles(4, 3).unwrap();
+        assert_eq!((tiles.count_across, tiles.count_down), (3, 3));
+
+        let coords = tiles
+            .iter_with_coords()
+            .map(|(x, y, col, row, _)| (x, y, col, row))
+            .collect::<Vec<_>>();
+        assert_eq!(coords.len(), 9);
+        assert_eq!(coords[0], (0, 0, 0, 0));
+        assert_eq!(coords[2], (8, 0, 2, 0));
+        assert_eq!(coords[4], (4, 3, 1, 1));
+        assert_eq!(coords[8], (8, 6, 2, 2));
+
+        for (index, (x, y, col, row, tile)) in tiles.iter_with_coords().enumerate() {
+            let rect = tiles.tile_rect(index).unwrap();
+            assert_eq!((x, y), (rect.col as u32, rect.row as u32));
+            assert_eq!(tiles.get(col, row), Some(tile));
+            assert_eq!(*tile, i.crop_imm(x, y, tile.width(), tile.height()));
+        }
+        assert_eq!(tiles.get(3, 0), None);
+        assert_eq!(tiles.get(0, 3), None);
+        assert_eq!(tiles.get(2, 2).unwrap().dimensions(), (2, 1));
+    }
+
+    #[test]
+    fn make_tiles_rejects_empty_tiles() {
+        let i = DynamicImage::new_rgb8(8, 8);
+        assert!(matches!(
+            IprImage(&i).make_tiles(0, 4),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test_case(PyramidType::Lowpass)]
+    #[test_case(PyramidType::Gaussian)]
+    #[test_case(PyramidType::Laplacian)]
+    fn generate_image_pyramid_of_each_type(pyramid_type: PyramidType) {
+        let i = image::open("test_files/totk.png").unwrap();
+        let i = IprImage(&i);
+
+        let params = PyramidParams {
+            pyramid_type,
+            ..Default::default()
+        };
+        let pyramid = i.generate_image_pyramid(Some(&params)).unwrap();
+        let default_pyramid = i.generate_image_pyramid(None).unwrap();
+
+        // Pyramid type changes level contents, but never level count or dimensions
+        assert_eq!(pyramid.len(), default_pyramid.len());
+        for (a, b) in pyramid.iter().zip(default_pyramid.iter()) {
+            assert_eq!(a.dimensions(), b.dimensions());
+        }
+    }
+
+    #[test_case(PyramidType::Lowpass, None, ResampleFilter::Box)]
+    #[test_case(PyramidType::Gaussian, None, ResampleFilter::Gaussian)]
+    #[test_case(PyramidType::Laplacian, None, ResampleFilter::Triangle)]
+    #[test_case(
+        PyramidType::Gaussian,
+        Some(ResampleFilter::Lanczos3),
+        ResampleFilter::Lanczos3
+    )]
+    fn pyramid_filter_defaults_by_type(
+        pyramid_type: PyramidType,
+        filter: Option<ResampleFilter>,
+        expected: ResampleFilter,
+    ) {
+        let params = PyramidParams {
+            pyramid_type,
+            filter,
+            ..Default::default()
+        };
+        assert_eq!(params.resample_filter(), expected);
+    }
+
+    #[test]
+    fn generate_image_pyramid_with_filter() {
+        let i = testgen::noise(64, 48, 7).unwrap();
+        let i = IprImage(&i);
+        let with = |pyramid_type, filter| {
+            let params = PyramidParams {
+                pyramid_type,
+                filter,
+                ..Default::default()
+            };
+            i.generate_image_pyramid(Some(&params)).unwrap()
+        };
+
+        // A lowpass pyramid resampled with a Gaussian is just a Gaussian pyramid
+        let gaussian = with(PyramidType::Gaussian, None);
+        assert_eq!(
+            with(PyramidType::Lowpass, Some(ResampleFilter::Gaussian)),
+            gaussian
+        );
+        let lanczos = with(PyramidType::Gaussian, Some(ResampleFilter::Lanczos3));
+        assert_eq!(lanczos.len(), gaussian.len());
+        assert_ne!(lanczos[1], gaussian[1]);
+    }
+
+    #[test_case(PyramidType::Lowpass)]
+    #[test_case(PyramidType::Gaussian)]
+    #[test_case(PyramidType::Laplacian)]
+    fn generate_image_pyramid_reports_every_level(pyramid_type: PyramidType) {
+        let i = testgen::gradient(257, 129).unwrap();
+        let params = PyramidParams {
+            pyramid_type,
+            ..Default::default()
+        };
+
+        let reported = Mutex::new(Vec::new());
+        let progress = |idx: usize, total: usize| reported.lock().unwrap().push((idx, total));
+        let pyramid = IprImage(&i)
+            .generate_image_pyramid_with_progress(Some(&params), Some(&progress))
+            .unwrap();
+
+        let dims = pyramid.iter().map(|l| l.dimensions()).collect::<Vec<_>>();
+        assert_eq!(dims, params.level_dims(257, 129));
+
+        // Each level is reported exactly once
+        let mut reported = reported.into_inner().unwrap();
+        reported.sort();
+        let expected = (0..pyramid.len())
+            .map(|idx| (idx, pyramid.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(reported, expected);
+    }
+
+    #[test_case(ResampleFilter::Nearest)]
+    #[test_case(ResampleFilter::Box)]
+    #[test_case(ResampleFilter::Triangle)]
+    #[test_case(ResampleFilter::Gaussian)]
+    #[test_case(ResampleFilter::Lanczos3)]
+    fn parallel_pyramid_levels_match_sequential(filter: ResampleFilter) {
+        let i = testgen::noise(257, 129, 7).unwrap();
+        let (w, h) = i.dimensions();
+        let level_dims = pyramid_level_dims(w, h, 0.5);
+
+        let sequential = downsample_levels(&i, &level_dims, filter, false, |_| {});
+        let parallel = downsample_levels(&i, &level_dims, filter, true, |_| {});
+        assert_eq!(sequential.len(), level_dims.len());
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test_case(None, None, 11)]
+    #[test_case(Some(64), None, 5)]
+    #[test_case(Some(65), None, 4)]
+    #[test_case(None, Some(3), 3)]
+    #[test_case(Some(64), Some(3), 3)]
+    #[test_case(Some(100_000), None, 1)]
+    fn pyramid_params_limit_levels(
+        min_dimension: Option<u32>,
+        max_levels: Option<u32>,
+        expected_levels: usize,
+    ) {
+        let params = PyramidParams {
+            min_dimension,
+            max_levels,
+            ..Default::default()
+        };
+        let dims = params.level_dims(1024, 600);
+        assert_eq!(dims.len(), expected_levels);
+
+        // Limits only ever trim the small end
+        let full = pyramid_level_dims(1024, 600, 0.5);
+        assert_eq!(dims[..], full[..expected_levels]);
+    }
+
+    #[test]
+    fn generate_image_pyramid_respects_level_limits() {
+        let i = image::open("test_files/totk.png").unwrap();
+        let (w, h) = i.dimensions();
+        let i = IprImage(&i);
+
+        let params = PyramidParams {
+            max_levels: Some(3),
+            ..Default::default()
+        };
+        let pyramid = i.generate_image_pyramid(Some(&params)).unwrap();
+        let dims = pyramid.iter().map(|l| l.dimensions()).collect::<Vec<_>>();
+        assert_eq!(dims, params.level_dims(w, h));
+        assert_eq!(dims.len(), 3);
+
+        let params = PyramidParams {
+            max_levels: Some(0),
+            ..Default::default()
+        };
+        assert!(i.generate_image_pyramid(Some(&params)).is_err());
+    }
+
+    #[test_case(PyramidType::Lowpass)]
+    #[test_case(PyramidType::Gaussian)]
+    fn synthesized_level_matches_generated_dims(pyramid_type: PyramidType) {
+        let i = DynamicImage::ImageRgb8(ImageBuffer::from_fn(257, 129, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        }));
+        let params = PyramidParams {
+            pyramid_type,
+            ..Default::default()
+        };
+        let full = IprImage(&i).generate_image_pyramid(Some(&params)).unwrap();
+
+        // Build L5 from L2, as if only L0-L2 had been stored. Each level is built from the one
+        // above it either way, so it's the very same level
+        let synthesized = synthesize_pyramid_level(&full[2], 3, &params).unwrap();
+        assert_eq!(synthesized, full[5]);
+
+        // Past the 1x1 level there's nothing to synthesize
+        assert!(synthesize_pyramid_level(&full[2], full.len(), &params).is_err());
+    }
+
+    #[test]
+    fn laplacian_levels_cannot_be_synthesized() {
+        let i = DynamicImage::ImageRgb8(ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgb([x as u8, y as u8, 0])
+        }));
+        let params = PyramidParams {
+            pyramid_type: PyramidType::Laplacian,
+            ..Default::default()
+        };
+        assert!(synthesize_pyramid_level(&i, 1, &params).is_err());
+    }
+
+    #[test_case(0.0)]
+    #[test_case(1.0)]
+    #[test_case(-0.5)]
+    #[test_case(2.0)]
+    fn generate_image_pyramid_rejects_bad_scale_factor(scale_factor: f32) {
+        let i = image::open("test_files/totk.png").unwrap();
+        let i = IprImage(&i);
+
+        let params = PyramidParams {
+            scale_factor,
+            ..Default::default()
+        };
+        assert!(i.generate_image_pyramid(Some(&params)).is_err());
+    }
+
+    #[test]
+    fn pyramid_builder_fills_in_defaults() {
+        let plan = PyramidBuilder::new().build().unwrap();
+        assert_eq!(*plan.params(), PyramidParams::default());
+        assert_eq!(plan.tile_size(), (DEFAULT_TILE_SIZE, DEFAULT_TILE_SIZE));
+        assert_eq!(plan.tile_format(), Some(ImageFormat::Png));
+        assert_eq!(plan.compression(), Compression::Brotli);
+        assert_eq!(
+            plan.compression_level(),
+            Compression::Brotli.default_level()
+        );
+
+        let plan = PyramidBuilder::new()
+            .compression(Compression::Gzip)
+            .build()
+            .unwrap();
+        assert_eq!(plan.compression_level(), Compression::Gzip.default_level());
+    }
+
+    #[test_case(PyramidBuilder::new().scale_factor(1.0) ; "scale factor of one")]
+    #[test_case(PyramidBuilder::new().scale_factor(0.0) ; "scale factor of zero")]
+    #[test_case(PyramidBuilder::new().max_levels(Some(0)) ; "no levels")]
+    #[test_case(PyramidBuilder::new().pyramid_type(PyramidType::Laplacian).max_levels(Some(2)) ; "laplacian with max levels")]
+    #[test_case(PyramidBuilder::new().pyramid_type(PyramidType::Laplacian).min_dimension(Some(64)) ; "laplacian with min dimension")]
+    #[test_case(PyramidBuilder::new().tile_size(0, 256) ; "empty tiles")]
+    #[test_case(PyramidBuilder::new().compression_level(Some(12)) ; "brotli level too high")]
+    #[test_case(PyramidBuilder::new().compression(Compression::Zstd).compression_level(Some(0)) ; "zstd level too low")]
+    #[test_case(PyramidBuilder::new().brotli_window_size(Some(30)) ; "brotli window too big")]
+    fn pyramid_builder_rejects_bad_plans(builder: PyramidBuilder) {
+        assert!(matches!(builder.build(), Err(Error::Validation(_))));
+    }
+
+    #[cfg(feature = "server-codecs")]
+    #[test]
+    fn pyramid_plans_build_tile_and_compress() {
+        let image = testgen::gradient(100, 60).unwrap();
+        let plan = PyramidBuilder::new()
+            .pyramid_type(PyramidType::Lowpass)
+            .max_levels(Some(3))
+            .tile_size(32, 32)
+            .compression(Compression::Zstd)
+            .compression_level(Some(3))
+            .build()
+            .unwrap();
+
+        let pyramid = plan.execute(&image).unwrap();
+        assert_eq!(pyramid.level_dims(), vec![(100, 60), (50, 30), (25, 15)]);
+        let tiles = pyramid.tiles(0).unwrap();
+        assert_eq!(tiles.tiles.len(), 4 * 2);
+        assert_eq!(pyramid.tiles(2).unwrap().tiles.len(), 1);
+
+        let (format, data) = plan.compress_tile(&tiles.tiles[0]).unwrap();
+        assert_eq!(format, ImageFormat::Png);
+        let decoded =
+            decode_image(&Compression::Zstd.decompress(&data).unwrap(), format, None).unwrap();
+        assert_eq!(decoded, tiles.tiles[0]);
+    }
+
+    #[test]
+    fn histogram_counts_each_channel() {
+        let i = DynamicImage::ImageRgb8(ImageBuffer::from_fn(4, 2, |x, _| {
+            Rgb([x as u8, 255, if x < 2 { 0 } else { 128 }])
+        }));
+        let hist = IprImage(&i).histogram().unwrap();
+        assert_eq!(hist.len(), 3);
+        assert_eq!(&hist[0][..4], &[2, 2, 2, 2]);
+        assert_eq!(hist[1][255], 8);
+        assert_eq!((hist[2][0], hist[2][128]), (4, 4));
+        for channel in &hist {
+            assert_eq!(channel.iter().sum::<u64>(), 8);
+        }
+    }
+
+    #[test]
+    fn histogram_scales_16_bit_samples() {
+        let i = DynamicImage::ImageLuma16(ImageBuffer::from_fn(3, 1, |x, _| {
+            Luma([[0, 32896, u16::MAX][x as usize]])
+        }));
+        let hist = IprImage(&i).histogram().unwrap();
+        assert_eq!(hist.len(), 1);
+        assert_eq!((hist[0][0], hist[0][128], hist[0][255]), (1, 1, 1));
+    }
+
+    #[test]
+    fn mean_stddev_and_min_max() {
+        let i = DynamicImage::ImageLumaA8(ImageBuffer::from_fn(2, 2, |x, y| {
+            LumaA([[10, 20, 30, 40][(y * 2 + x) as usize], 255])
+        }));
+        let i = IprImage(&i);
+
+        let stats = i.mean_stddev().unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].0, 25.0);
+        assert!((stats[0].1 - 125.0_f64.sqrt()).abs() < 1e-9);
+        assert_eq!(stats[1], (255.0, 0.0));
+
+        assert_eq!(i.min_max().unwrap(), vec![(10.0, 40.0), (255.0, 255.0)]);
+    }
+
+    #[test]
+    fn statistics_of_empty_image_fail() {
+        let i = DynamicImage::new_rgb8(0, 0);
+        let i = IprImage(&i);
+        assert!(matches!(i.histogram(), Err(Error::Validation(_))));
+        assert!(i.mean_stddev().is_err());
+        assert!(i.min_max().is_err());
+    }
+
+    /// An 8-bit RGBA image whose color values are squashed into 100..=131
+    fn low_contrast_image() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(32, 8, |x, y| {
+            Rgba([100 + x as u8, 100 + x as u8, 120, (y * 30) as u8])
+        }))
+    }
+
+    #[test]
+    fn equalize_histogram_spreads_values() {
+        let i = low_contrast_image();
+        let equalized = IprImage(&i).equalize_histogram().unwrap();
+        let equalized = IprImage(&equalized);
+
+        let min_max = equalized.min_max().unwrap();
+        assert_eq!(min_max[0], (0.0, 255.0));
+        assert_eq!(min_max[1], (0.0, 255.0));
+
+        // Flat channels and alpha are left alone
+        assert_eq!(min_max[2], (120.0, 120.0));
+        assert_eq!(min_max[3], IprImage(&i).min_max().unwrap()[3]);
+
+        // Equalizing preserves order
+        let e = equalized.0.to_rgba8();
+        for x in 1..32 {
+            assert!(e.get_pixel(x, 0)[0] > e.get_pixel(x - 1, 0)[0]);
+        }
+    }
+
+    #[test]
+    fn stretch_contrast_maps_percentiles_to_range() {
+        let i = low_contrast_image();
+        let stretched = IprImage(&i).stretch_contrast(0.0, 100.0).unwrap();
+        let s = stretched.to_rgba8();
+        assert_eq!(s.get_pixel(0, 0)[0], 0);
+        assert_eq!(s.get_pixel(31, 0)[0], 255);
+        assert_eq!(s.get_pixel(0, 3)[3], 90);
+
+        // Clipping a quarter off each end saturates the outer pixels
+        let clipped = IprImage(&i).stretch_contrast(25.0, 75.0).unwrap();
+        let c = clipped.to_rgba8();
+        assert_eq!(c.get_pixel(2, 0)[0], 0);
+        assert_eq!(c.get_pixel(29, 0)[0], 255);
+    }
+
+    #[test_case(-1.0, 50.0)]
+    #[test_case(50.0, 101.0)]
+    #[test_case(60.0, 40.0)]
+    #[test_case(50.0, 50.0)]
+    fn stretch_contrast_rejects_bad_percentiles(low_pct: f64, high_pct: f64) {
+        let i = low_contrast_image();
+        assert!(matches!(
+            IprImage(&i).stretch_contrast(low_pct, high_pct),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn convolve_with_identity_kernel_is_a_no_op() {
+        let i = low_contrast_image();
+        let identity = DynMatrix::from_nested(&[[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]]);
+        let convolved = IprImage(&i).convolve(&identity).unwrap();
+        assert_eq!(convolved.to_rgba8(), i.to_rgba8());
+    }
+
+    #[test]
+    fn convolve_flips_kernel_and_clamps_edges() {
+        let i = DynamicImage::ImageLuma8(ImageBuffer::from_fn(3, 1, |x, _| {
+            Luma([10 * (x as u8 + 1)])
+        }));
+
+        // Weighing the neighbor to the left shifts the image right, repeating the left edge
+        let shift = DynMatrix::from_nested(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]]);
+        let shifted = IprImage(&i).convolve(&shift).unwrap().to_luma8();
+        assert_eq!(shifted.as_raw(), &vec![10, 10, 20]);
+
+        // Results are clamped to the channel's range
+        let boost = DynMatrix::from_nested(&[[0.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 0.0]]);
+        let boosted = IprImage(&i).convolve(&boost).unwrap().to_luma8();
+        assert_eq!(boosted.as_raw(), &vec![100, 200, 255]);
+    }
+
+    #[test]
+    fn convolve_rejects_bad_kernels() {
+        let i = low_contrast_image();
+        let even = DynMatrix::from_nested(&[[0.25, 0.25], [0.25, 0.25]]);
+        let not_square = DynMatrix::from_nested(&[[0.5], [0.0], [0.5]]);
+        for kernel in [even, not_square] {
+            assert!(matches!(
+                IprImage(&i).convolve(&kernel),
+                Err(Error::Validation(_))
+            ));
+        }
+    }
+
+    #[test_case(ResampleFilter::Nearest)]
+    #[test_case(ResampleFilter::Box)]
+    #[test_case(ResampleFilter::Triangle)]
+    #[test_case(ResampleFilter::CatmullRom)]
+    #[test_case(ResampleFilter::Gaussian)]
+    #[test_case(ResampleFilter::Lanczos3)]
+    fn resize_gives_exact_dimensions(filter: ResampleFilter) {
+        let i = low_contrast_image();
+        let resized = IprImage(&i).resize(13, 40, filter).unwrap();
+        assert_eq!(resized.dimensions(), (13, 40));
+        assert_eq!(resized.color(), i.color());
+        assert!(matches!(
+            IprImage(&i).resize(0, 40, filter),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn box_filter_averages_covered_pixels() {
+        let i = DynamicImage::ImageLuma8(ImageBuffer::from_fn(4, 2, |x, y| {
+            Luma([(x * 40 + y * 20) as u8])
+        }));
+        let r = IprImage(&i)
+            .resize(2, 1, ResampleFilter::Box)
+            .unwrap()
+            .to_luma8();
+        assert_eq!(r.as_raw(), &vec![30, 110]);
+    }
+
+    #[test]
+    fn rotate_turns_clockwise() {
+        // A 2x1 image, dark on the left and bright on the right
+        let i = DynamicImage::ImageLuma8(ImageBuffer::from_fn(2, 1, |x, _| Luma([x as u8 * 255])));
+
+        let r = IprImage(&i).rotate(90).unwrap().to_luma8();
+        assert_eq!(r.dimensions(), (1, 2));
+        assert_eq!(r.as_raw(), &vec![0, 255]);
+
+        let r = IprImage(&i).rotate(180).unwrap().to_luma8();
+        assert_eq!(r.as_raw(), &vec![255, 0]);
+
+        let r = IprImage(&i).rotate(270).unwrap().to_luma8();
+        assert_eq!(r.as_raw(), &vec![255, 0]);
+
+        assert_eq!(IprImage(&i).rotate(360).unwrap().to_luma8(), i.to_luma8());
+        assert!(matches!(IprImage(&i).rotate(45), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn pixel_samples_reads_each_channel() {
+        let i = low_contrast_image();
+        assert_eq!(
+            pixel_samples(&i, 3, 2).unwrap(),
+            vec![103.0, 103.0, 120.0, 60.0]
+        );
+
+        let gray = DynamicImage::ImageLuma16(ImageBuffer::from_fn(2, 2, |x, y| {
+            Luma([(x + 2 * y) as u16 * 1000])
+        }));
+        assert_eq!(pixel_samples(&gray, 1, 1).unwrap(), vec![3000.0]);
+
+        assert!(matches!(
+            pixel_samples(&gray, 2, 0),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn pyramid_tiles_its_levels() {
+        let i = DynamicImage::ImageRgb8(ImageBuffer::new(100, 60));
+        let mut pyramid = Pyramid::new(&i, None).unwrap();
+        assert_eq!(pyramid.len(), 8);
+        assert_eq!(pyramid.level_dims()[1], (50, 30));
+        assert!(pyramid.tiles(1).is_none());
+
+        let tiles = pyramid.tile_level(1, 16, 16).unwrap();
+        assert_eq!((tiles.count_across, tiles.count_down), (4, 2));
+        assert!(pyramid.tiles(0).is_none());
+        assert!(matches!(
+            pyramid.tile_level(8, 16, 16),
+            Err(Error::NotFound(_))
+        ));
+
+        pyramid.tile_all_levels(32, 32).unwrap();
+        assert!((0..pyramid.len()).all(|l| pyramid.tiles(l).is_some()));
+        assert_eq!(pyramid.tiles(0).unwrap().tiles.len(), 8);
+
+        assert!(matches!(
+            Pyramid::from_levels(vec![], PyramidParams::default()),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test_case(2.0, 0)]
+    #[test_case(1.0, 0)]
+    #[test_case(0.5, 1)]
+    #[test_case(0.4, 1)]
+    #[test_case(0.25, 2)]
+    #[test_case(0.0, 7)]
+    fn level_for_zoom_picks_smallest_sufficient_level(zoom: f64, level: usize) {
+        let i = DynamicImage::ImageLuma8(ImageBuffer::new(100, 60));
+        let pyramid = Pyramid::new(&i, None).unwrap();
+        assert_eq!(pyramid.level_for_zoom(zoom), level);
+    }
+
+    #[test]
+    fn compare_measures_error() {
+        let dark = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(16, 16, Luma([0])));
+        let gray = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(16, 16, Luma([51])));
+
+        let same = IprImage(&dark).compare(&dark, true).unwrap();
+        assert_eq!(same.mse, 0.0);
+        assert_eq!(same.psnr, None);
+        assert!((same.ssim.unwrap() - 1.0).abs() < 1e-9);
+
+        // Each of R, G and B is off by 0.2, and alpha matches
+        let off = IprImage(&dark).compare(&gray, false).unwrap();
+        assert!((off.mse - 0.03).abs() < 1e-6);
+        assert!((off.psnr.unwrap() - 15.2288).abs() < 1e-3);
+        assert_eq!(off.ssim, None);
+
+        // Different layouts of the same image match exactly
+        let rgba = DynamicImage::ImageRgba16(gray.to_rgba16());
+        assert_eq!(IprImage(&gray).compare(&rgba, false).unwrap().mse, 0.0);
+    }
+
+    #[test]
+    fn difference_is_absolute() {
+        let dark = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(3, 2, Luma([10])));
+        let gray = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(3, 2, Luma([61])));
+        let diff = IprImage(&gray).difference(&dark).unwrap();
+        assert_eq!(diff.color(), image::ColorType::Rgb8);
+        assert!(diff.to_rgb8().pixels().all(|p| p.0 == [51, 51, 51]));
+
+        let small = DynamicImage::ImageLuma8(ImageBuffer::new(2, 2));
+        assert!(matches!(
+            IprImage(&gray).difference(&small),
+            Err(Error::Unprocessable(_))
+        ));
+        assert!(matches!(
+            IprImage(&gray).compare(&small, true),
+            Err(Error::Unprocessable(_))
+        ));
+    }
+
+    #[test]
+    fn to_grayscale_keeps_depth_and_alpha() {
+        let rgba = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            3,
+            2,
+            Rgba([u16::MAX, u16::MAX, u16::MAX, 1234]),
+        ));
+        let gray = IprImage(&rgba).to_grayscale().unwrap();
+        assert_eq!(gray.color(), image::ColorType::La16);
+        assert!(gray
+            .to_luma_alpha16()
+            .pixels()
+            .all(|p| p.0 == [u16::MAX, 1234]));
+
+        let rgb = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(3, 2, Rgb([255, 0, 0])));
+        let gray = IprImage(&rgb).to_grayscale().unwrap();
+        assert_eq!(gray.color(), image::ColorType::L8);
+    }
+
+    #[test_case(DynamicImage::ImageLuma8(ImageBuffer::from_pixel(3, 2, Luma([7]))), 1)]
+    #[test_case(DynamicImage::ImageLumaA8(ImageBuffer::from_pixel(3, 2, LumaA([7, 8]))), 2)]
+    #[test_case(DynamicImage::ImageRgb8(ImageBuffer::from_pixel(3, 2, Rgb([7, 8, 9]))), 3)]
+    #[test_case(DynamicImage::ImageRgba8(ImageBuffer::from_pixel(3, 2, Rgba([7, 8, 9, 10]))), 4)]
+    fn split_channels_round_trips_through_merge(image: DynamicImage, count: usize) {
+        let channels = IprImage(&image).split_channels().unwrap();
+        assert_eq!(channels.len(), count);
+        for (c, channel) in channels.iter().enumerate() {
+            assert_eq!(
+                (
+                    channel.width(),
+                    channel.height(),
+                    channel.components_per_pixel()
+                ),
+                (3, 2, 1)
+            );
+            assert!(channel.data().iter().all(|&v| v == 7 + c as u8));
+        }
+
+        let merged = merge_channels(&channels).unwrap();
+        assert_eq!(merged, image);
+    }
+
+    #[test]
+    fn merge_channels_validates_its_inputs() {
+        let channel = MyImage::<u8>::new(3, 2, 1);
+        assert!(matches!(merge_channels(&[]), Err(Error::Validation(_))));
+        assert!(matches!(
+            merge_channels(&vec![channel.clone(); 5]),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            merge_channels(&[channel.clone(), MyImage::new(2, 2, 1)]),
+            Err(Error::Unprocessable(_))
+        ));
+        assert!(matches!(
+            merge_channels(&[channel, MyImage::new(3, 2, 3)]),
+            Err(Error::Unprocessable(_))
+        ));
+    }
+
+    #[test]
+    fn threshold_binarizes_luminance() {
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(4, 1, |x, _| {
+            Rgb([[0, 127, 128, 255][x as usize]; 3])
+        }));
+        let binary = IprImage(&image).threshold(127).unwrap();
+        assert_eq!(binary.color(), image::ColorType::L8);
+        assert_eq!(binary.as_bytes(), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn otsu_threshold_separates_two_levels() {
+        let image = DynamicImage::ImageLuma8(ImageBuffer::from_fn(8, 8, |x, y| {
+            Luma([match (x + y) % 3 {
+                0 => 200,
+                _ => 20,
+            }])
+        }));
+        let i = IprImage(&image);
+        let level = i.otsu_threshold().unwrap();
+        assert!((20..200).contains(&level));
+
+        let binary = i.threshold(level).unwrap().to_luma8();
+        for (x, y, p) in binary.enumerate_pixels() {
+            let expected = match (x + y) % 3 {
+                0 => 255,
+                _ => 0,
+            };
+            assert_eq!(p.0, [expected]);
+        }
+
+        let empty = DynamicImage::ImageLuma8(ImageBuffer::new(0, 0));
+        assert!(IprImage(&empty).otsu_threshold().is_err());
+    }
+
+    #[test_case(EdgeOperator::Sobel)]
+    #[test_case(EdgeOperator::Scharr)]
+    fn edges_find_vertical_step(operator: EdgeOperator) {
+        // Black on the left half, white on the right
+        let image = DynamicImage::ImageLuma8(ImageBuffer::from_fn(8, 4, |x, _| {
+            Luma([if x < 4 { 0 } else { 255 }])
+        }));
+        let i = IprImage(&image);
+
+        let edges = i.edges(operator).unwrap().to_luma8();
+        for (x, _, p) in edges.enumerate_pixels() {
+            let expected = match x {
+                3 | 4 => 255,
+                _ => 0,
+            };
+            assert_eq!(p.0, [expected], "x = {}", x);
+        }
+
+        let (dx, dy) = i.gradients(operator).unwrap();
+        let (dx, dy) = (dx.to_luma8(), dy.to_luma8());
+        assert_eq!(dx.get_pixel(3, 1).0, [255]);
+        assert_eq!(dx.get_pixel(0, 1).0, [128]);
+        assert!(dy.pixels().all(|p| p.0 == [128]));
+
+        // Reversing the step reverses the gradient
+        let flipped = image.fliph();
+        let (dx, _) = IprImage(&flipped).gradients(operator).unwrap();
+        assert_eq!(dx.to_luma8().get_pixel(3, 1).0, [0]);
+    }
+
+    #[bench]
+    fn bench_histogram(b: &mut Bencher) {
+        let i = image::open("test_files/totk.png").unwrap();
+        let i = IprImage(&i);
+        b.iter(|| test::black_box(i.histogram().unwrap()));
+    }
+
+    // Run with and without `--features simd` to compare scalar and vectorized window sums
+    #[bench]
+    fn bench_convolve_9x9(b: &mut Bencher) {
+        let i = image::open("test_files/totk.png").unwrap();
+        let i = IprImage(&i);
+        let kernel = DynMatrix::<f64>::ones((9, 9)) * (1.0 / 81.0);
+        b.iter(|| test::black_box(i.convolve(&kernel).unwrap()));
+    }
+
     #[bench]
     fn bench_generate_image_pyramid(b: &mut Bencher) {
         let i_path = "test_files/totk.png";
@@ -253,7 +3538,7 @@ mod tests {
         let i = IprImage(&i);
 
         let bench_loop = move || {
-            let pyramid = match i.generate_image_pyramid() {
+            let pyramid = match i.generate_image_pyramid(None) {
                 Ok(v) => v,
                 Err(_) => panic!("You fool! You cannot construct the pyramids with such a thing."),
             };
@@ -295,7 +3580,7 @@ mod tests {
         let i = image::open(i_path).unwrap();
         let i = IprImage(&i);
 
-        let pyramid = match i.generate_image_pyramid() {
+        let pyramid = match i.generate_image_pyramid(None) {
             Ok(v) => v,
             Err(_) => panic!("You fool! You cannot construct the pyramids with such a thing."),
         };
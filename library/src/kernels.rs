@@ -0,0 +1,244 @@
+//! Ready-made convolution kernels, for use with
+//! [`HasImageProcessingRoutines::convolve`](crate::ipr::HasImageProcessingRoutines::convolve).
+//!
+//! Every kernel is square, with an odd number of rows. Blurs sum to 1, so they keep an image's
+//! overall brightness; derivative kernels sum to 0, so flat regions come out black.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::dyn_matrix::DynMatrix;
+use crate::errors::{Error, Result};
+
+/// Largest kernel we'll build, in rows (and columns)
+pub const MAX_SIZE: usize = 101;
+
+/// Standard deviation of [`Kernel::Gaussian`] when none is given
+pub const DEFAULT_SIGMA: f64 = 1.0;
+
+/// Size of [`Kernel::Box`] when none is given
+pub const DEFAULT_BOX_SIZE: usize = 3;
+
+fn check_size(size: usize) -> Result<()> {
+    if size % 2 == 0 || size > MAX_SIZE {
+        return Err(Error::validation(format!(
+            "Kernel size must be odd, and at most {}, not {}",
+            MAX_SIZE, size
+        )));
+    }
+    Ok(())
+}
+
+/// The size that holds a Gaussian of standard deviation `sigma` out to three standard
+/// deviations either side of its center, which is all but 0.3% of it
+pub fn gaussian_size_for(sigma: f64) -> usize {
+    2 * (3.0 * sigma).ceil() as usize + 1
+}
+
+/// A `size`x`size` Gaussian blur with standard deviation `sigma`, normalized to sum to 1
+pub fn gaussian(sigma: f64, size: usize) -> Result<DynMatrix<f64>> {
+    if !(sigma > 0.0 && sigma.is_finite()) {
+        return Err(Error::validation(format!(
+            "Gaussian sigma must be a positive number, not {}",
+            sigma
+        )));
+    }
+    check_size(size)?;
+
+    let radius = (size / 2) as f64;
+    let weights = (0..size)
+        .map(|i| {
+            let d = i as f64 - radius;
+            (-(d * d) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect::<Vec<f64>>();
+    let total = weights.iter().sum::<f64>();
+
+    // The 2D Gaussian is separable: each entry is the product of its row's and column's weights
+    let mut kernel = DynMatrix::zeros((size, size));
+    for (i, wi) in weights.iter().enumerate() {
+        for (j, wj) in weights.iter().enumerate() {
+            kernel[(i, j)] = wi * wj / (total * total);
+        }
+    }
+    Ok(kernel)
+}
+
+/// A `size`x`size` box blur: the mean of every pixel in the window
+pub fn box_blur(size: usize) -> Result<DynMatrix<f64>> {
+    check_size(size)?;
+    Ok(DynMatrix::ones((size, size)) * (1.0 / (size * size) as f64))
+}
+
+/// The 3x3 Sobel kernel for the horizontal gradient, responding to vertical edges. Brighter to
+/// the right comes out positive
+pub fn sobel_x() -> DynMatrix<f64> {
+    // Convolution flips the kernel, so the positive weights go on the left
+    DynMatrix::from_nested(&[[1.0, 0.0, -1.0], [2.0, 0.0, -2.0], [1.0, 0.0, -1.0]])
+}
+
+/// The 3x3 Sobel kernel for the vertical gradient, responding to horizontal edges. Brighter
+/// below comes out positive
+pub fn sobel_y() -> DynMatrix<f64> {
+    sobel_x().transpose()
+}
+
+/// The 3x3 (4-neighbor) Laplacian, which responds to fine detail in every direction
+pub fn laplacian() -> DynMatrix<f64> {
+    DynMatrix::from_nested(&[[0.0, 1.0, 0.0], [1.0, -4.0, 1.0], [0.0, 1.0, 0.0]])
+}
+
+/// The 3x3 sharpening kernel: the image, minus its Laplacian
+pub fn sharpen() -> DynMatrix<f64> {
+    DynMatrix::from_nested(&[[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]])
+}
+
+/// Each of the kernels above, by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Kernel {
+    /// See [`gaussian`]. Takes `sigma` and `size`, which defaults to [`gaussian_size_for`] it
+    Gaussian,
+
+    /// See [`box_blur`]. Takes `size`
+    Box,
+
+    /// See [`sobel_x`]
+    SobelX,
+
+    /// See [`sobel_y`]
+    SobelY,
+
+    /// See [`laplacian`]
+    Laplacian,
+
+    /// See [`sharpen`]
+    Sharpen,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 6] = [
+        Kernel::Gaussian,
+        Kernel::Box,
+        Kernel::SobelX,
+        Kernel::SobelY,
+        Kernel::Laplacian,
+        Kernel::Sharpen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kernel::Gaussian => "gaussian",
+            Kernel::Box => "box",
+            Kernel::SobelX => "sobel_x",
+            Kernel::SobelY => "sobel_y",
+            Kernel::Laplacian => "laplacian",
+            Kernel::Sharpen => "sharpen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Kernel> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Kernel::Gaussian => "Gaussian blur, normalized to sum to 1",
+            Kernel::Box => "Box blur: the mean of the window",
+            Kernel::SobelX => "Sobel horizontal gradient, for vertical edges",
+            Kernel::SobelY => "Sobel vertical gradient, for horizontal edges",
+            Kernel::Laplacian => "4-neighbor Laplacian, for fine detail",
+            Kernel::Sharpen => "Sharpening: the image minus its Laplacian",
+        }
+    }
+
+    /// Names of the parameters [`Kernel::build`] uses for this kernel. It ignores the rest
+    pub fn parameters(&self) -> &'static [&'static str] {
+        match self {
+            Kernel::Gaussian => &["sigma", "size"],
+            Kernel::Box => &["size"],
+            _ => &[],
+        }
+    }
+
+    /// Build this kernel, with the given parameters where it takes them, or the defaults
+    pub fn build(&self, sigma: Option<f64>, size: Option<usize>) -> Result<DynMatrix<f64>> {
+        match self {
+            Kernel::Gaussian => {
+                let sigma = sigma.unwrap_or(DEFAULT_SIGMA);
+                gaussian(sigma, size.unwrap_or_else(|| gaussian_size_for(sigma)))
+            }
+            Kernel::Box => box_blur(size.unwrap_or(DEFAULT_BOX_SIZE)),
+            Kernel::SobelX => Ok(sobel_x()),
+            Kernel::SobelY => Ok(sobel_y()),
+            Kernel::Laplacian => Ok(laplacian()),
+            Kernel::Sharpen => Ok(sharpen()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dims::HasDims;
+    use crate::ipr::{HasImageProcessingRoutines, IprImage};
+    use image::{DynamicImage, GenericImageView, Luma};
+    use test_case::test_case;
+
+    fn elements(kernel: &DynMatrix<f64>) -> impl Iterator<Item = f64> + '_ {
+        (0..kernel.rows()).flat_map(|i| kernel[i].iter().copied())
+    }
+
+    fn sum(kernel: &DynMatrix<f64>) -> f64 {
+        elements(kernel).sum()
+    }
+
+    #[test_case(0.5, 3)]
+    #[test_case(1.0, 7)]
+    #[test_case(2.5, 17)]
+    fn gaussians_are_normalized_and_symmetric(sigma: f64, size: usize) {
+        assert_eq!(gaussian_size_for(sigma), size);
+        let k = gaussian(sigma, size).unwrap();
+        assert_eq!(k.dims(), (size, size).into());
+        assert!((sum(&k) - 1.0).abs() < 1e-12);
+        assert!(k.approx_eq(&k.transpose(), 1e-15));
+        let center = size / 2;
+        assert_eq!(elements(&k).fold(0.0_f64, f64::max), k[(center, center)]);
+    }
+
+    #[test_case(Kernel::Gaussian, 1.0)]
+    #[test_case(Kernel::Box, 1.0)]
+    #[test_case(Kernel::SobelX, 0.0)]
+    #[test_case(Kernel::SobelY, 0.0)]
+    #[test_case(Kernel::Laplacian, 0.0)]
+    #[test_case(Kernel::Sharpen, 1.0)]
+    fn every_kernel_builds_with_defaults(kernel: Kernel, total: f64) {
+        let k = kernel.build(None, None).unwrap();
+        assert_eq!(k.rows(), k.cols());
+        assert_eq!(k.rows() % 2, 1);
+        assert!((sum(&k) - total).abs() < 1e-12);
+        assert_eq!(Kernel::from_name(kernel.name()), Some(kernel));
+    }
+
+    #[test]
+    fn bad_parameters_are_rejected() {
+        assert!(matches!(gaussian(0.0, 3), Err(Error::Validation(_))));
+        assert!(matches!(gaussian(f64::NAN, 3), Err(Error::Validation(_))));
+        assert!(matches!(gaussian(1.0, 4), Err(Error::Validation(_))));
+        assert!(matches!(box_blur(MAX_SIZE + 2), Err(Error::Validation(_))));
+        assert_eq!(Kernel::from_name("emboss"), None);
+    }
+
+    #[test]
+    fn sobel_x_is_positive_where_it_gets_brighter_to_the_right() {
+        let ramp = DynamicImage::ImageLuma8(image::GrayImage::from_fn(5, 5, |x, _| {
+            Luma([(x * 10) as u8])
+        }));
+        // Biased, so the (positive) gradient isn't clamped away
+        let mut kernel = sobel_x();
+        kernel[(1, 1)] = 1.0;
+        let out = IprImage(&ramp).convolve(&kernel).unwrap();
+        // Right minus left is 20 on every row, weighted 1, 2 and 1, plus the pixel itself
+        assert_eq!(out.get_pixel(2, 2).0[0], 80 + 20);
+    }
+}
@@ -1,15 +1,24 @@
 #![feature(test)]
 #![feature(negative_impls)]
+#![feature(min_specialization)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
 pub mod buffer_element;
 pub mod circular_buffer;
+pub mod compression;
 pub mod dims;
+pub mod documents;
 pub mod dyn_matrix;
 pub mod element;
 pub mod errors;
 pub mod from_mat;
+pub mod geometry;
 pub mod ipr;
+pub mod kernels;
+pub mod luma;
 pub mod matrix;
 pub mod my_image;
 pub mod my_traits;
 pub mod serde;
+pub mod simd;
+pub mod testgen;
@@ -0,0 +1,200 @@
+//! Moving image data in and out of matrices, so matrix algorithms can be run on an image's
+//! brightness and their results looked at.
+//!
+//! Matrices have one element per pixel, with rows running down the image and columns across it,
+//! so a matrix's [`Dims`](crate::dims::Dims) are its image's height by width. Luminance runs from
+//! 0.0 (black) to 1.0 (white), whatever the image's bit depth.
+
+use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::dims::HasDims;
+use crate::dyn_matrix::DynMatrix;
+use crate::errors::{Error, Result};
+use crate::ipr;
+
+/// How [`DynMatrix::to_luma_image`] maps a matrix's values onto black through white
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    /// Values are luminance already: 0.0 is black and 1.0 white, and anything outside that is
+    /// clamped to it
+    #[default]
+    Clamp,
+
+    /// Like [`Normalization::Clamp`], but of each value's magnitude, for results like gradients
+    /// whose sign doesn't matter
+    Abs,
+
+    /// The smallest value is black and the largest white, stretching whatever range the matrix
+    /// covers over all of them. A matrix of one value comes out black
+    MinMax,
+}
+
+impl DynMatrix<f64> {
+    /// The luminance of each of `image`'s pixels. Color images are weighted the way
+    /// [`DynamicImage::to_luma32f`] weighs them, and alpha is ignored
+    pub fn from_luma_image(image: &DynamicImage) -> Self {
+        let (width, height) = image.dimensions();
+        let luma = image.to_luma32f();
+        let samples = luma.as_raw().iter().map(|&v| v as f64).collect::<Vec<_>>();
+        DynMatrix::from_flat(&samples, (height as usize, width as usize))
+    }
+
+    /// An 8-bit grayscale image of this matrix, one pixel per element, with values mapped to
+    /// luminance by `normalization`
+    pub fn to_luma_image(&self, normalization: Normalization) -> Result<DynamicImage> {
+        let (rows, cols) = self.dims().into();
+        let (Ok(height), Ok(width)) = (u32::try_from(rows), u32::try_from(cols)) else {
+            return Err(Error::unprocessable(format!(
+                "A {}x{} matrix is too large to be an image",
+                rows, cols
+            )));
+        };
+
+        let to_luma: Box<dyn Fn(f64) -> f64> = match normalization {
+            Normalization::Clamp => Box::new(|v| v),
+            Normalization::Abs => Box::new(f64::abs),
+            Normalization::MinMax => {
+                let min = self.min().unwrap_or_default();
+                let range = self.max().unwrap_or_default() - min;
+                match range > 0.0 {
+                    true => Box::new(move |v| (v - min) / range),
+                    false => Box::new(|_| 0.0),
+                }
+            }
+        };
+        Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(
+            width,
+            height,
+            |x, y| {
+                let v = to_luma(self[(y as usize, x as usize)]);
+                Luma([(v.clamp(0.0, 1.0) * u8::MAX as f64).round() as u8])
+            },
+        )))
+    }
+
+    /// Convolve this matrix with `kernel`, the way
+    /// [`HasImageProcessingRoutines::convolve`](crate::ipr::HasImageProcessingRoutines::convolve)
+    /// convolves an image: the kernel must be square with an odd number of rows, and elements past
+    /// the edges repeat the nearest one. The result is the same size, and isn't clamped
+    pub fn convolve(&self, kernel: &DynMatrix<f64>) -> Result<Self> {
+        let size = ipr::check_kernel(kernel)?;
+        let (rows, cols) = self.dims().into();
+        if rows == 0 || cols == 0 {
+            return Ok(self.clone());
+        }
+
+        let radius = size / 2;
+        let mut out = DynMatrix::zeros((rows, cols));
+        for r in 0..rows {
+            for c in 0..cols {
+                let mut sum = 0.0;
+                // Convolving flips the kernel, so its bottom-right entry weighs the top-left
+                // neighbor
+                for kr in 0..size {
+                    let sr = (r + radius).saturating_sub(kr).min(rows - 1);
+                    for kc in 0..size {
+                        let sc = (c + radius).saturating_sub(kc).min(cols - 1);
+                        sum += kernel[(kr, kc)] * self[(sr, sc)];
+                    }
+                }
+                out[(r, c)] = sum;
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipr::{HasImageProcessingRoutines, IprImage};
+    use crate::kernels;
+    use image::{Rgb, RgbImage};
+    use test_case::test_case;
+
+    fn ramp(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            Luma([(x * 20 + y * 3) as u8])
+        }))
+    }
+
+    #[test]
+    fn luma_images_round_trip() {
+        let image = ramp(7, 4);
+        let m = DynMatrix::from_luma_image(&image);
+        assert_eq!(m.dims(), (4, 7).into());
+        assert!((m[(1, 2)] - 43.0 / 255.0).abs() < 1e-6);
+        assert_eq!(m.to_luma_image(Normalization::Clamp).unwrap(), image);
+    }
+
+    #[test]
+    fn color_images_are_reduced_to_luminance() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgb([255, 255, 255]),
+            _ => Rgb([0, 0, 0]),
+        }));
+        let m = DynMatrix::from_luma_image(&image);
+        assert!(m.approx_eq(&DynMatrix::from_nested(&[[1.0, 0.0]]), 1e-6));
+    }
+
+    #[test_case(Normalization::Clamp, [0, 0, 128, 255])]
+    #[test_case(Normalization::Abs, [255, 0, 128, 255])]
+    #[test_case(Normalization::MinMax, [0, 85, 128, 255])]
+    fn values_are_normalized(normalization: Normalization, expected: [u8; 4]) {
+        let m = DynMatrix::from_nested(&[[-1.0, 0.0], [0.5, 2.0]]);
+        let image = m.to_luma_image(normalization).unwrap();
+        assert_eq!(image.as_luma8().unwrap().as_raw().as_slice(), &expected);
+    }
+
+    #[test]
+    fn flat_matrices_stretch_to_black() {
+        let m = DynMatrix::ones((2, 3)) * 0.25;
+        let image = m.to_luma_image(Normalization::MinMax).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert!(image.as_luma8().unwrap().iter().all(|&v| v == 0));
+    }
+
+    #[test]
+    fn convolution_matches_images() {
+        let image = ramp(9, 6);
+        let kernel = kernels::gaussian(1.0, 5).unwrap();
+        let expected = IprImage(&image).convolve(&kernel).unwrap();
+        let m = DynMatrix::from_luma_image(&image)
+            .convolve(&kernel)
+            .unwrap();
+        let actual = m.to_luma_image(Normalization::Clamp).unwrap();
+        // Images are convolved in whole samples, and matrices in fractions of them, so they can
+        // round apart
+        let (actual, expected) = (actual.as_luma8().unwrap(), expected.as_luma8().unwrap());
+        assert!(actual
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, e)| a.abs_diff(*e) <= 1));
+    }
+
+    #[test]
+    fn gradients_keep_their_sign() {
+        // Right minus left is 40 on every row, weighted 1, 2 and 1
+        let m = DynMatrix::from_luma_image(&ramp(5, 5));
+        let gradient = m.convolve(&kernels::sobel_x()).unwrap();
+        assert!((gradient[(2, 2)] - 160.0 / 255.0).abs() < 1e-6);
+        let flipped = m.convolve(&(kernels::sobel_x() * -1.0)).unwrap();
+        assert!((flipped[(2, 2)] + 160.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bad_kernels_are_rejected() {
+        let m = DynMatrix::<f64>::zeros((3, 3));
+        assert!(matches!(
+            m.convolve(&DynMatrix::zeros((2, 2))),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            m.convolve(&DynMatrix::zeros((3, 5))),
+            Err(Error::Validation(_))
+        ));
+    }
+}
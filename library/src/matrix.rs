@@ -1,10 +1,12 @@
 use crate::{
     dims::{Dims, HasDims},
+    dyn_matrix,
     element::Element,
 };
+use num::Float;
 use std::{
     fmt::Display,
-    ops::{Add, Index, IndexMut, Mul, Sub},
+    ops::{Add, AddAssign, Index, IndexMut, Mul, MulAssign, Sub, SubAssign},
 };
 use utoipa::ToSchema;
 
@@ -93,6 +95,20 @@ impl<T: Element, const R: usize, const C: usize> Matrix<T, R, C> {
     }
 }
 
+impl<T: Element + Float, const R: usize, const C: usize> Matrix<T, R, C> {
+    /// The largest absolute difference between corresponding elements of `self` and `other`.
+    /// NaN if either has a NaN
+    pub fn max_abs_diff(&self, other: &Self) -> T {
+        dyn_matrix::max_abs_diff(self.els.iter().flatten().zip(other.els.iter().flatten()))
+    }
+
+    /// Whether no corresponding elements of `self` and `other` differ by more than `epsilon`.
+    /// Never true of matrices with NaNs
+    pub fn approx_eq(&self, other: &Self, epsilon: T) -> bool {
+        self.max_abs_diff(other) <= epsilon
+    }
+}
+
 impl<T: Element, const R: usize, const C: usize> HasDims for Matrix<T, R, C> {
     fn rows(&self) -> usize {
         R
@@ -197,31 +213,46 @@ impl<T: Element, const R: usize, const C: usize> IndexMut<(usize, usize)> for Ma
     }
 }
 
-impl<T: Element, const R: usize, const C: usize> Add for Matrix<T, R, C> {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self::Output {
-        let mut result = Matrix::<T, R, C>::zeros();
-        for i in 0..R {
-            for j in 0..C {
-                result[(i, j)] = self[(i, j)] + other[(i, j)];
+#[auto_impl_ops::auto_ops]
+impl<T: Element, const R: usize, const C: usize> AddAssign<&Matrix<T, R, C>> for Matrix<T, R, C> {
+    fn add_assign(&mut self, other: &Self) {
+        for (row, other_row) in self.els.iter_mut().zip(&other.els) {
+            for (a, &b) in row.iter_mut().zip(other_row) {
+                *a += b;
             }
         }
-        result
     }
 }
 
-impl<T: Element, const R: usize, const C: usize> Sub for Matrix<T, R, C> {
-    type Output = Self;
+#[auto_impl_ops::auto_ops]
+impl<T: Element, const R: usize, const C: usize> AddAssign<&T> for Matrix<T, R, C> {
+    fn add_assign(&mut self, other: &T) {
+        self.els.iter_mut().flatten().for_each(|a| *a += *other);
+    }
+}
 
-    fn sub(self, other: Self) -> Self::Output {
-        let mut result = Matrix::<T, R, C>::zeros();
-        for i in 0..R {
-            for j in 0..C {
-                result[(i, j)] = self[(i, j)] - other[(i, j)];
+#[auto_impl_ops::auto_ops]
+impl<T: Element, const R: usize, const C: usize> SubAssign<&Matrix<T, R, C>> for Matrix<T, R, C> {
+    fn sub_assign(&mut self, other: &Self) {
+        for (row, other_row) in self.els.iter_mut().zip(&other.els) {
+            for (a, &b) in row.iter_mut().zip(other_row) {
+                *a -= b;
             }
         }
-        result
+    }
+}
+
+#[auto_impl_ops::auto_ops]
+impl<T: Element, const R: usize, const C: usize> SubAssign<&T> for Matrix<T, R, C> {
+    fn sub_assign(&mut self, other: &T) {
+        self.els.iter_mut().flatten().for_each(|a| *a -= *other);
+    }
+}
+
+#[auto_impl_ops::auto_ops]
+impl<T: Element, const R: usize, const C: usize> MulAssign<&T> for Matrix<T, R, C> {
+    fn mul_assign(&mut self, other: &T) {
+        self.els.iter_mut().flatten().for_each(|a| *a = *a * *other);
     }
 }
 
@@ -237,6 +268,19 @@ impl<T: Element, const R1: usize, const I: usize, const C2: usize> DotProduct<T,
     type Output = Matrix<T, R1, C2>;
 
     fn dot_product(&self, m2: Matrix<T, I, C2>) -> Self::Output {
+        self * &m2
+    }
+}
+
+// Matrix products change the shape of their output, so unlike the elementwise operators above,
+// every combination of owned and borrowed operands is spelled out here rather than derived from
+// `MulAssign` (which only makes sense for square right-hand sides)
+impl<T: Element, const R1: usize, const I: usize, const C2: usize> Mul<&Matrix<T, I, C2>>
+    for &Matrix<T, R1, I>
+{
+    type Output = Matrix<T, R1, C2>;
+
+    fn mul(self, m2: &Matrix<T, I, C2>) -> Self::Output {
         let mut result = Matrix::<T, R1, C2>::zeros();
         for i in 0..R1 {
             for j in 0..C2 {
@@ -249,35 +293,45 @@ impl<T: Element, const R1: usize, const I: usize, const C2: usize> DotProduct<T,
     }
 }
 
+impl<T: Element, const R1: usize, const I: usize, const C2: usize> Mul<Matrix<T, I, C2>>
+    for &Matrix<T, R1, I>
+{
+    type Output = Matrix<T, R1, C2>;
+
+    fn mul(self, m2: Matrix<T, I, C2>) -> Self::Output {
+        self * &m2
+    }
+}
+
+impl<T: Element, const R1: usize, const I: usize, const C2: usize> Mul<&Matrix<T, I, C2>>
+    for Matrix<T, R1, I>
+{
+    type Output = Matrix<T, R1, C2>;
+
+    fn mul(self, m2: &Matrix<T, I, C2>) -> Self::Output {
+        &self * m2
+    }
+}
+
 impl<T: Element, const R1: usize, const I: usize, const C2: usize> Mul<Matrix<T, I, C2>>
     for Matrix<T, R1, I>
 {
     type Output = Matrix<T, R1, C2>;
 
     fn mul(self, m2: Matrix<T, I, C2>) -> Self::Output {
-        let mut result = Matrix::<T, R1, C2>::zeros();
-        for i in 0..R1 {
-            for j in 0..C2 {
-                for k in 0..I {
-                    result[(i, j)] += self[(i, k)] * m2[(k, j)];
-                }
-            }
-        }
-        result
+        &self * &m2
     }
 }
 
-impl<T: Element, const R: usize, const C: usize> Mul<T> for Matrix<T, R, C> {
-    type Output = Matrix<T, R, C>;
+impl<T: Element, const R: usize, const C: usize> MulAssign<&Matrix<T, C, C>> for Matrix<T, R, C> {
+    fn mul_assign(&mut self, other: &Matrix<T, C, C>) {
+        *self = &*self * other;
+    }
+}
 
-    fn mul(self, scalar: T) -> Self::Output {
-        let mut result = Matrix::<T, R, C>::zeros();
-        for i in 0..R {
-            for j in 0..C {
-                result[(i, j)] = self[(i, j)] * scalar;
-            }
-        }
-        result
+impl<T: Element, const R: usize, const C: usize> MulAssign<Matrix<T, C, C>> for Matrix<T, R, C> {
+    fn mul_assign(&mut self, other: Matrix<T, C, C>) {
+        *self *= &other;
     }
 }
 
@@ -303,6 +357,7 @@ impl<T: Element, const R: usize, const C: usize> Display for Matrix<T, R, C> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dyn_matrix::DynMatrix;
     use crate::from_mat::FromMat;
 
     #[test]
@@ -449,6 +504,93 @@ mod tests {
         assert_eq!(result[(1, 1)], 8);
     }
 
+    #[test]
+    fn scalar_add_sub() {
+        let matrix = Matrix::<i8, 2, 2>::from_flat(&[1, 2, 3, 4]);
+        assert_eq!(&matrix + 2, Matrix::from_flat(&[3, 4, 5, 6]));
+        assert_eq!(matrix.clone() - 2, Matrix::from_flat(&[-1, 0, 1, 2]));
+
+        let mut m = matrix;
+        m += 1;
+        m -= &3;
+        m *= 2;
+        assert_eq!(m, Matrix::from_flat(&[-2, 0, 2, 4]));
+    }
+
+    #[test]
+    fn assign_ops() {
+        let m1 = Matrix::<i32, 2, 2>::from_flat(&[1, 2, 3, 4]);
+        let m2 = Matrix::<i32, 2, 2>::from_flat(&[5, 6, 7, 8]);
+        let mut m = m1.clone();
+        m += &m2;
+        assert_eq!(m, Matrix::from_flat(&[6, 8, 10, 12]));
+        m -= m1.clone();
+        assert_eq!(m, m2);
+        m *= &m1;
+        assert_eq!(m, Matrix::from_flat(&[23, 34, 31, 46]));
+
+        // Multiplying in place needs a square right-hand side, but not a square left-hand one
+        let mut row = Matrix::<i32, 1, 2>::from_flat(&[1, 1]);
+        row *= m1;
+        assert_eq!(row, Matrix::from_flat(&[4, 6]));
+    }
+
+    #[test]
+    fn reference_operators() {
+        let m1 = Matrix::<i32, 2, 3>::from_flat(&[1, 2, 3, 4, 5, 6]);
+        let m2 = Matrix::<i32, 3, 1>::from_flat(&[1, 0, -1]);
+        let sum = Matrix::from_flat(&[2, 4, 6, 8, 10, 12]);
+        assert_eq!(&m1 + &m1, sum);
+        assert_eq!(&m1 + m1.clone(), sum);
+        assert_eq!(m1.clone() + &m1, sum);
+        assert_eq!(&m1 - &m1, Matrix::zeros());
+
+        let product = Matrix::<i32, 2, 1>::from_flat(&[-2, -2]);
+        assert_eq!(&m1 * &m2, product);
+        assert_eq!(&m1 * m2.clone(), product);
+        assert_eq!(m1.clone() * &m2, product);
+        assert_eq!(m1 * m2, product);
+    }
+
+    /// Every operator should agree with its [`DynMatrix`] counterpart
+    #[test]
+    fn matches_dyn_matrix() {
+        let a = Matrix::<i64, 3, 2>::from_flat(&[1, -2, 3, -4, 5, -6]);
+        let b = Matrix::<i64, 3, 2>::from_flat(&[7, 8, -9, 10, 11, -12]);
+        let c = Matrix::<i64, 2, 2>::from_flat(&[2, -1, 0, 3]);
+        let (da, db, dc) = (
+            DynMatrix::from(a.clone()),
+            DynMatrix::from(b.clone()),
+            DynMatrix::from(c.clone()),
+        );
+
+        assert_eq!(DynMatrix::from(&a + &b), &da + &db);
+        assert_eq!(DynMatrix::from(&a - &b), &da - &db);
+        assert_eq!(DynMatrix::from(&a * &c), &da * &dc);
+        assert_eq!(DynMatrix::from(&a + 5), &da + 5);
+        assert_eq!(DynMatrix::from(&a - 5), &da - 5);
+        assert_eq!(DynMatrix::from(&a * 5), &da * 5);
+        assert_eq!(DynMatrix::from(a.transpose()), da.transpose());
+
+        let (mut m, mut dm) = (a, da);
+        m += &b;
+        m *= c;
+        m -= 1;
+        dm += &db;
+        dm *= dc;
+        dm -= 1;
+        assert_eq!(DynMatrix::from(m), dm);
+    }
+
+    #[test]
+    fn approx_eq_within_tolerance() {
+        let a = Matrix::<f32, 2, 2>::from_flat(&[1.0, 2.0, 3.0, 4.0]);
+        let b = Matrix::<f32, 2, 2>::from_flat(&[1.0, 2.5, 3.0, 4.0]);
+        assert_eq!(a.max_abs_diff(&b), 0.5);
+        assert!(a.approx_eq(&b, 0.5));
+        assert!(!a.approx_eq(&b, 0.25));
+    }
+
     #[test]
     fn from_other_element_type() {
         let matrix = Matrix::<u8, 2, 2>::from_flat(&[1, 2, 3, 4]);
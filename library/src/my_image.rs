@@ -1,8 +1,63 @@
-use num::Unsigned;
+use image::{DynamicImage, ImageBuffer};
+use num::Num;
 use std::ops::{Index, IndexMut};
 
-pub trait PixelComponent: Unsigned + Clone + Default {}
-impl<T> PixelComponent for T where T: Unsigned + Clone + Default {}
+use crate::dims::{Dims, HasDims};
+use crate::errors::{Error, Result};
+
+/// A type that can hold one component of a pixel: `u8`, `u16`, or `f32`.
+///
+/// As in `image`, integer components span their type's whole range, while float components
+/// nominally run from 0.0 to 1.0. Floats may go past that range, e.g. for HDR data or the
+/// intermediate results of a convolution.
+pub trait PixelComponent: Num + Copy + Default + PartialOrd {
+    /// The value of a fully-saturated component: the type's maximum for integers, or 1.0 for
+    /// floats
+    const MAX: Self;
+
+    /// This component, scaled so that [`PixelComponent::MAX`] becomes 1.0
+    fn to_unit(self) -> f32;
+
+    /// The component for a value scaled so that 1.0 is [`PixelComponent::MAX`]. Integer types
+    /// round, and clamp to their range; floats keep the value as-is
+    fn from_unit(v: f32) -> Self;
+}
+
+impl PixelComponent for u8 {
+    const MAX: Self = u8::MAX;
+
+    fn to_unit(self) -> f32 {
+        self as f32 / u8::MAX as f32
+    }
+
+    fn from_unit(v: f32) -> Self {
+        (v * u8::MAX as f32).round().clamp(0.0, u8::MAX as f32) as u8
+    }
+}
+
+impl PixelComponent for u16 {
+    const MAX: Self = u16::MAX;
+
+    fn to_unit(self) -> f32 {
+        self as f32 / u16::MAX as f32
+    }
+
+    fn from_unit(v: f32) -> Self {
+        (v * u16::MAX as f32).round().clamp(0.0, u16::MAX as f32) as u16
+    }
+}
+
+impl PixelComponent for f32 {
+    const MAX: Self = 1.0;
+
+    fn to_unit(self) -> f32 {
+        self
+    }
+
+    fn from_unit(v: f32) -> Self {
+        v
+    }
+}
 
 pub struct XIndex(pub u32);
 pub struct YIndex(pub u32);
@@ -17,7 +72,7 @@ impl From<ImageIndex> for (u32, u32, u32) {
     }
 }
 
-/// An Image type that stores pixel data in a contiguous array of unsigned integral elements.
+/// An Image type that stores pixel data in a contiguous array of [`PixelComponent`]s.
 #[derive(Debug, Clone)]
 pub struct MyImage<T: PixelComponent> {
     /// The image data, stored as a contiguous array of values.
@@ -69,6 +124,61 @@ impl<T: PixelComponent> MyImage<T> {
     pub fn data_mut(&mut self) -> &mut [T] {
         &mut self.data
     }
+
+    /// Iterate over the rows of the image, top to bottom. Each row holds the components of its
+    /// pixels, left to right
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        let row_len = (self.width * self.components_per_pixel) as usize;
+        self.data.chunks_exact(row_len.max(1))
+    }
+
+    /// Iterate over the pixels of the image, in row-major order. Each pixel is a slice of
+    /// [`MyImage::components_per_pixel`] components
+    pub fn pixels(&self) -> impl Iterator<Item = &[T]> {
+        self.data
+            .chunks_exact((self.components_per_pixel as usize).max(1))
+    }
+
+    /// Iterate over the pixels of the image, in row-major order, along with their x and y
+    /// coordinates, so that they can be modified in place
+    pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (u32, u32, &mut [T])> {
+        let width = self.width.max(1);
+        self.data
+            .chunks_exact_mut((self.components_per_pixel as usize).max(1))
+            .enumerate()
+            .map(move |(i, p)| (i as u32 % width, i as u32 / width, p))
+    }
+
+    /// Convert to another component type, rescaling each component so that full saturation stays
+    /// full saturation. E.g. `u8` 255 becomes `f32` 1.0, or `u16` 65535
+    pub fn convert<U: PixelComponent>(&self) -> MyImage<U> {
+        MyImage {
+            data: self
+                .data
+                .iter()
+                .map(|&c| U::from_unit(c.to_unit()))
+                .collect(),
+            width: self.width,
+            height: self.height,
+            components_per_pixel: self.components_per_pixel,
+        }
+    }
+}
+
+/// Because [`MyImage::rows`] iterates over the rows, call [`HasDims::rows`] explicitly for the
+/// number of them
+impl<T: PixelComponent> HasDims for MyImage<T> {
+    fn rows(&self) -> usize {
+        self.height as usize
+    }
+
+    fn cols(&self) -> usize {
+        self.width as usize
+    }
+
+    fn dims(&self) -> Dims {
+        (self.height as usize, self.width as usize).into()
+    }
 }
 
 impl<T: PixelComponent> Index<u32> for MyImage<T> {
@@ -131,6 +241,126 @@ impl<T: PixelComponent> IndexMut<(u32, u32, u32)> for MyImage<T> {
     }
 }
 
+/// Implements conversions between `MyImage<$t>` and [`DynamicImage`], for a component type that
+/// `image` has buffer variants for.
+///
+/// Grayscale sources become single-component images; sources with alpha become 4-component
+/// (RGBA) images; everything else becomes 3-component (RGB). Going the other way, 1, 2, 3, and 4
+/// components map to Luma, LumaA, Rgb, and Rgba respectively.
+macro_rules! impl_dynamic_image_conversions {
+    ($t:ty, $to_luma:ident, $to_rgb:ident, $to_rgba:ident, $luma:ident, $luma_a:ident, $rgb:ident, $rgba:ident) => {
+        impl From<&DynamicImage> for MyImage<$t> {
+            fn from(image: &DynamicImage) -> Self {
+                let color = image.color();
+                let (data, components_per_pixel) = if color.channel_count() == 1 {
+                    (image.$to_luma().into_raw(), 1)
+                } else if color.has_alpha() {
+                    (image.$to_rgba().into_raw(), 4)
+                } else {
+                    (image.$to_rgb().into_raw(), 3)
+                };
+                Self {
+                    data,
+                    width: image.width(),
+                    height: image.height(),
+                    components_per_pixel,
+                }
+            }
+        }
+
+        impl MyImage<$t> {
+            /// Convert this image into a [`DynamicImage`], so it can be used with `ipr` routines
+            /// or encoded for clients.
+            pub fn to_dynamic_image(&self) -> Result<DynamicImage> {
+                let (w, h, data) = (self.width, self.height, self.data.clone());
+                let image = match self.components_per_pixel {
+                    1 => ImageBuffer::from_raw(w, h, data).map(DynamicImage::$luma),
+                    2 => ImageBuffer::from_raw(w, h, data).map(DynamicImage::$luma_a),
+                    3 => ImageBuffer::from_raw(w, h, data).map(DynamicImage::$rgb),
+                    4 => ImageBuffer::from_raw(w, h, data).map(DynamicImage::$rgba),
+                    _ => {
+                        return Err(Error::validation(
+                            "Only images with 1 to 4 components can be converted",
+                        ))
+                    }
+                };
+                image.ok_or_else(|| Error::internal("Image data does not match its dimensions"))
+            }
+        }
+    };
+}
+
+impl_dynamic_image_conversions!(
+    u8,
+    to_luma8,
+    to_rgb8,
+    to_rgba8,
+    ImageLuma8,
+    ImageLumaA8,
+    ImageRgb8,
+    ImageRgba8
+);
+impl_dynamic_image_conversions!(
+    u16,
+    to_luma16,
+    to_rgb16,
+    to_rgba16,
+    ImageLuma16,
+    ImageLumaA16,
+    ImageRgb16,
+    ImageRgba16
+);
+
+// `image` has no single-channel float layouts, so gray float images are converted by hand
+impl From<&DynamicImage> for MyImage<f32> {
+    fn from(image: &DynamicImage) -> Self {
+        let color = image.color();
+        let (data, components_per_pixel) = if color.channel_count() == 1 {
+            (image.to_luma32f().into_raw(), 1)
+        } else if color.has_alpha() {
+            (image.to_rgba32f().into_raw(), 4)
+        } else {
+            (image.to_rgb32f().into_raw(), 3)
+        };
+        Self {
+            data,
+            width: image.width(),
+            height: image.height(),
+            components_per_pixel,
+        }
+    }
+}
+
+impl MyImage<f32> {
+    /// Convert this image into a [`DynamicImage`], so it can be used with `ipr` routines or
+    /// encoded for clients. `image` only has RGB and RGBA float layouts, so 1- and 2-component
+    /// images have their gray component copied into each of red, green, and blue
+    pub fn to_dynamic_image(&self) -> Result<DynamicImage> {
+        let (w, h) = (self.width, self.height);
+        let expand_gray = |with_alpha: bool| -> Vec<f32> {
+            self.data
+                .chunks_exact(self.components_per_pixel as usize)
+                .flat_map(|p| {
+                    let alpha = with_alpha.then(|| p[1]);
+                    [p[0], p[0], p[0]].into_iter().chain(alpha)
+                })
+                .collect()
+        };
+        let image = match self.components_per_pixel {
+            1 => ImageBuffer::from_raw(w, h, expand_gray(false)).map(DynamicImage::ImageRgb32F),
+            2 => ImageBuffer::from_raw(w, h, expand_gray(true)).map(DynamicImage::ImageRgba32F),
+            3 => ImageBuffer::from_raw(w, h, self.data.clone()).map(DynamicImage::ImageRgb32F),
+            4 => ImageBuffer::from_raw(w, h, self.data.clone()).map(DynamicImage::ImageRgba32F),
+            _ => {
+                return Err(Error::validation(
+                    "Only images with 1 to 4 components can be converted",
+                ))
+            }
+        };
+        image.ok_or_else(|| Error::internal("Image data does not match its dimensions"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,4 +382,116 @@ mod tests {
         assert_eq!(image[(1, 1, 1)], 128);
         assert_eq!(image[(1, 1, 2)], 255);
     }
+
+    fn gradient_rgba8(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8, y as u8, (x + y) as u8, 200])
+        }))
+    }
+
+    #[test]
+    fn from_dynamic_image_keeps_layout() {
+        let rgba = gradient_rgba8(3, 2);
+        let image = MyImage::<u8>::from(&rgba);
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(image.components_per_pixel(), 4);
+        assert_eq!(&image[(2, 1)], &[2, 1, 3, 200]);
+
+        let rgb = MyImage::<u8>::from(&DynamicImage::ImageRgb8(rgba.to_rgb8()));
+        assert_eq!(rgb.components_per_pixel(), 3);
+        assert_eq!(&rgb[(2, 1)], &[2, 1, 3]);
+
+        let luma = MyImage::<u8>::from(&DynamicImage::ImageLuma8(rgba.to_luma8()));
+        assert_eq!(luma.components_per_pixel(), 1);
+
+        // Gray + alpha is promoted to RGBA
+        let luma_a = MyImage::<u8>::from(&DynamicImage::ImageLumaA8(rgba.to_luma_alpha8()));
+        assert_eq!(luma_a.components_per_pixel(), 4);
+    }
+
+    #[test]
+    fn to_dynamic_image_round_trips() {
+        let rgba = gradient_rgba8(5, 4);
+        let round_tripped = MyImage::<u8>::from(&rgba).to_dynamic_image().unwrap();
+        assert_eq!(round_tripped, rgba);
+
+        let rgb16 = DynamicImage::ImageRgb16(rgba.to_rgb16());
+        let round_tripped = MyImage::<u16>::from(&rgb16).to_dynamic_image().unwrap();
+        assert_eq!(round_tripped, rgb16);
+    }
+
+    #[test]
+    fn rows_and_pixels_follow_layout() {
+        let rgba = MyImage::<u8>::from(&gradient_rgba8(3, 2));
+        let rows: Vec<&[u8]> = rgba.rows().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1], &[0, 1, 1, 200, 1, 1, 2, 200, 2, 1, 3, 200]);
+
+        let pixels: Vec<&[u8]> = rgba.pixels().collect();
+        assert_eq!(pixels.len(), 6);
+        assert_eq!(pixels[4], &rgba[(1, 1)]);
+
+        let luma = MyImage::<u8>::from(&DynamicImage::ImageLuma8(gradient_rgba8(3, 2).to_luma8()));
+        assert_eq!(luma.rows().map(<[u8]>::len).collect::<Vec<_>>(), [3, 3]);
+        assert!(luma.pixels().all(|p| p.len() == 1));
+    }
+
+    #[test]
+    fn enumerate_pixels_mut_gives_coordinates() {
+        let mut image = MyImage::<u16>::new(3, 2, 2);
+        for (x, y, p) in image.enumerate_pixels_mut() {
+            p[0] = x as u16;
+            p[1] = y as u16;
+        }
+        assert_eq!(&image[(2, 0)], &[2, 0]);
+        assert_eq!(&image[(1, 1)], &[1, 1]);
+        assert_eq!(image.enumerate_pixels_mut().count(), 6);
+    }
+
+    #[test]
+    fn float_images_hold_intermediate_values() {
+        let mut image = MyImage::<f32>::new(2, 1, 1);
+        image[(0, 0, 0)] = -0.5;
+        image[(1, 0, 0)] = 1.5;
+        assert_eq!(image.data(), &[-0.5, 1.5]);
+
+        // Out-of-range values are clamped when converted to integers
+        let clamped = image.convert::<u8>();
+        assert_eq!(clamped.data(), &[0, 255]);
+    }
+
+    #[test]
+    fn convert_rescales_components() {
+        let mut image = MyImage::<u8>::new(3, 1, 1);
+        image.data_mut().copy_from_slice(&[0, 51, 255]);
+
+        let float = image.convert::<f32>();
+        assert_eq!(float.data()[2], 1.0);
+        assert!((float.data()[1] - 0.2).abs() < 1e-6);
+
+        let wide = float.convert::<u16>();
+        assert_eq!(wide.data(), &[0, 13107, 65535]);
+        assert_eq!(wide.convert::<u8>().data(), image.data());
+    }
+
+    #[test]
+    fn float_images_convert_to_dynamic_images() {
+        let rgba = DynamicImage::ImageRgba32F(gradient_rgba8(4, 3).to_rgba32f());
+        let round_tripped = MyImage::<f32>::from(&rgba).to_dynamic_image().unwrap();
+        assert_eq!(round_tripped, rgba);
+
+        // Gray is spread across RGB
+        let gray = MyImage::<f32>::from(&DynamicImage::ImageLuma8(gradient_rgba8(4, 3).to_luma8()));
+        assert_eq!(gray.components_per_pixel(), 1);
+        let expanded = gray.to_dynamic_image().unwrap().to_rgb32f();
+        let p = expanded.get_pixel(3, 2).0;
+        assert_eq!([p[1], p[2]], [p[0], p[0]]);
+        assert_eq!(p[0], gray[(3, 2, 0)]);
+    }
+
+    #[test]
+    fn to_dynamic_image_rejects_unsupported_components() {
+        let image = MyImage::<u8>::new(2, 2, 5);
+        assert!(image.to_dynamic_image().is_err());
+    }
 }
@@ -1,6 +1,16 @@
-use serde::{de::Visitor, ser::SerializeSeq, Deserialize, Deserializer, Serialize};
-
-use crate::{dims::HasDims, dyn_matrix::DynMatrix, element::Element, matrix::Matrix};
+use serde::{
+    de::{DeserializeOwned, MapAccess, Visitor},
+    ser::{SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize,
+};
+
+use crate::{
+    dims::{Dims, HasDims},
+    dyn_matrix::DynMatrix,
+    element::Element,
+    errors::{self, Error},
+    matrix::Matrix,
+};
 
 struct DataArr<'a, T: Element, const SIZE: usize>(&'a [T; SIZE]);
 
@@ -58,6 +68,81 @@ impl<T: Element> Serialize for DynMatrix<T> {
     }
 }
 
+/// A [`DynMatrix`] that serializes as an object naming its shape and element type, rather than as
+/// nested arrays, e.g. `{"rows": 2, "cols": 2, "dtype": "f64", "data": [1.0, 2.0, 3.0, 4.0]}`.
+/// `data` is in row-major order.
+///
+/// Deserializing a plain [`DynMatrix`] from a self-describing format (like JSON) accepts this
+/// form too, so this wrapper is only needed to pick how matrices are written.
+pub struct NamedDynMatrix<T: Element>(pub DynMatrix<T>);
+
+/// The name of an element type, as written to `dtype`, e.g. `f64`
+pub fn dtype_name<T: Element>() -> &'static str {
+    std::any::type_name::<T>()
+}
+
+/// Every element of a matrix, in row-major order
+struct FlatData<'a, T: Element>(&'a DynMatrix<T>);
+
+impl<'a, T: Element> Serialize for FlatData<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let m = self.0;
+        let mut seq = serializer.serialize_seq(Some(m.dims().area()))?;
+        for i in 0..m.rows() {
+            for el in m[i].iter() {
+                seq.serialize_element(el)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<T: Element> Serialize for NamedDynMatrix<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let m = &self.0;
+        let mut obj = serializer.serialize_struct("DynMatrix", 4)?;
+        obj.serialize_field("rows", &m.rows())?;
+        obj.serialize_field("cols", &m.cols())?;
+        obj.serialize_field("dtype", dtype_name::<T>())?;
+        obj.serialize_field("data", &FlatData(m))?;
+        obj.end()
+    }
+}
+
+impl<'de, T: Element> Deserialize<'de> for NamedDynMatrix<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<NamedDynMatrix<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        DynMatrix::deserialize(deserializer).map(NamedDynMatrix)
+    }
+}
+
+/// [`Dims`] that serialize as `{"rows": R, "cols": C}`
+pub struct NamedDims(pub Dims);
+
+impl Serialize for NamedDims {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let NamedDims(dims) = self;
+        let mut obj = serializer.serialize_struct("Dims", 2)?;
+        obj.serialize_field("rows", &dims.rows())?;
+        obj.serialize_field("cols", &dims.cols())?;
+        obj.end()
+    }
+}
+
 pub struct MatrixVisitor<T: Element, const R: usize, const C: usize> {
     marker: std::marker::PhantomData<T>,
 }
@@ -131,19 +216,35 @@ where
         A: serde::de::SeqAccess<'de>,
     {
         let mut rows = Vec::new();
-        let mut row_len: usize = 0;
         while let Some(row) = seq.next_element::<Vec<T>>()? {
-            match row_len {
-                0 => row_len = row.len(),
+            rows.push(row);
+        }
+        DynMatrix::try_from_vec(&rows).map_err(serde::de::Error::custom)
+    }
+
+    /// The object form written by [`NamedDynMatrix`]. `dtype` is optional, and informational:
+    /// elements are read as `T` regardless, just as they are from nested arrays
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut rows: Option<usize> = None;
+        let mut cols: Option<usize> = None;
+        let mut data: Option<Vec<T>> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "rows" => rows = Some(map.next_value()?),
+                "cols" => cols = Some(map.next_value()?),
+                "data" => data = Some(map.next_value()?),
                 _ => {
-                    if row.len() != row_len {
-                        return Err(serde::de::Error::custom("inconsistent row lengths"));
-                    }
+                    map.next_value::<serde::de::IgnoredAny>()?;
                 }
             }
-            rows.push(row);
         }
-        Ok(DynMatrix::from_vec(&rows))
+        let rows = rows.ok_or_else(|| serde::de::Error::missing_field("rows"))?;
+        let cols = cols.ok_or_else(|| serde::de::Error::missing_field("cols"))?;
+        let data = data.ok_or_else(|| serde::de::Error::missing_field("data"))?;
+        DynMatrix::try_from_flat(&data, (rows, cols)).map_err(serde::de::Error::custom)
     }
 }
 
@@ -155,18 +256,83 @@ where
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_seq(DynMatrixVisitor::<T> {
+        let visitor = DynMatrixVisitor::<T> {
             // This is so yucky
             marker: std::marker::PhantomData,
-        })
+        };
+        // Only self-describing formats can tell us which form they hold
+        match deserializer.is_human_readable() {
+            true => deserializer.deserialize_any(visitor),
+            false => deserializer.deserialize_seq(visitor),
+        }
+    }
+}
+
+/// Compact binary encodings, for matrices too big to send as JSON comfortably. Both round-trip
+/// floats bit for bit, and both write matrices in their nested-array form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+    /// [bincode](https://github.com/bincode-org/bincode): fixed-width little-endian elements, with
+    /// no framing beyond sequence lengths. Not self-describing, so both ends must agree on the
+    /// element type
+    Bincode,
+
+    /// [CBOR](https://www.rfc-editor.org/rfc/rfc8949): self-describing, like JSON, but binary
+    Cbor,
+}
+
+impl BinaryFormat {
+    /// Every binary format, in order of preference
+    pub const ALL: [BinaryFormat; 2] = [BinaryFormat::Cbor, BinaryFormat::Bincode];
+
+    /// The MIME type of data in this format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            BinaryFormat::Bincode => "application/octet-stream",
+            BinaryFormat::Cbor => "application/cbor",
+        }
+    }
+
+    /// The format with the given MIME type, ignoring case and parameters, if any
+    pub fn from_mime_type(mime_type: &str) -> Option<BinaryFormat> {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        BinaryFormat::ALL
+            .into_iter()
+            .find(|f| f.mime_type().eq_ignore_ascii_case(essence))
+    }
+
+    /// Encode `value` in this format
+    pub fn to_vec<T: Serialize + ?Sized>(&self, value: &T) -> errors::Result<Vec<u8>> {
+        match self {
+            BinaryFormat::Bincode => bincode::serialize(value)
+                .map_err(|e| Error::internal(format!("Failed to encode bincode: {}", e))),
+            BinaryFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| Error::internal(format!("Failed to encode CBOR: {}", e)))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Decode a `T` from `bytes` in this format
+    pub fn from_slice<T: DeserializeOwned>(&self, bytes: &[u8]) -> errors::Result<T> {
+        match self {
+            BinaryFormat::Bincode => bincode::deserialize(bytes)
+                .map_err(|e| Error::validation(format!("Invalid bincode: {}", e))),
+            BinaryFormat::Cbor => ciborium::from_reader(bytes)
+                .map_err(|e| Error::validation(format!("Invalid CBOR: {}", e))),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::dyn_matrix::DynMatrix;
     use crate::matrix::Matrix;
     use serde_json;
+    use test_case::test_case;
 
     #[test]
     fn serialize_matrix() {
@@ -251,6 +417,20 @@ mod tests {
         assert!(deserialized.is_err());
     }
 
+    #[test]
+    fn deserialize_dyn_matrix_keeps_empty_dims() {
+        let deserialized = serde_json::from_str::<DynMatrix<f64>>("[[],[]]").unwrap();
+        assert_eq!((deserialized.rows(), deserialized.cols()), (2, 0));
+        let deserialized = serde_json::from_str::<DynMatrix<f64>>("[]").unwrap();
+        assert_eq!((deserialized.rows(), deserialized.cols()), (0, 0));
+        let serialized_mat = r#"{"rows":0,"cols":3,"data":[]}"#;
+        let deserialized = serde_json::from_str::<DynMatrix<f64>>(serialized_mat).unwrap();
+        assert_eq!((deserialized.rows(), deserialized.cols()), (0, 3));
+
+        // An empty first row doesn't let the others be any length
+        assert!(serde_json::from_str::<DynMatrix<f64>>("[[],[1.0]]").is_err());
+    }
+
     #[test]
     fn deserialize_dyn_matrix_constructs_f64_from_int_types() {
         let serialized_mat = "[[1,2],[3,4]]";
@@ -273,4 +453,99 @@ mod tests {
         let deserialized = serde_json::from_str::<DynMatrix<i32>>(serialized_mat);
         assert!(deserialized.is_err());
     }
+
+    #[test]
+    fn serialize_named_dyn_matrix() {
+        let m = DynMatrix::from_nested(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let serialized = serde_json::to_string(&NamedDynMatrix(m)).unwrap();
+        assert_eq!(
+            serialized,
+            r#"{"rows":2,"cols":3,"dtype":"f64","data":[1.0,2.0,3.0,4.0,5.0,6.0]}"#
+        );
+    }
+
+    #[test]
+    fn deserialize_dyn_matrix_accepts_named_form() {
+        let serialized_mat = r#"{"rows":2,"cols":2,"dtype":"i32","data":[1,2,3,4]}"#;
+        let deserialized = serde_json::from_str::<DynMatrix<i32>>(serialized_mat).unwrap();
+        assert_eq!(deserialized, DynMatrix::from_nested(&[[1, 2], [3, 4]]));
+
+        // dtype can be left out
+        let serialized_mat = r#"{"data":[1,2],"rows":1,"cols":2}"#;
+        let deserialized = serde_json::from_str::<DynMatrix<f64>>(serialized_mat).unwrap();
+        assert_eq!(deserialized, DynMatrix::from_nested(&[[1.0, 2.0]]));
+    }
+
+    #[test]
+    fn named_dyn_matrix_round_trips() {
+        let m = DynMatrix::from_nested(&[[1.5, -2.0], [3.0, 4.25], [0.0, 8.0]]);
+        let serialized = serde_json::to_string(&NamedDynMatrix(m.clone())).unwrap();
+        let NamedDynMatrix(deserialized) = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, m);
+    }
+
+    #[test]
+    fn deserialize_named_form_fails_when_data_does_not_fit() {
+        let serialized_mat = r#"{"rows":2,"cols":2,"data":[1.0,2.0,3.0]}"#;
+        assert!(serde_json::from_str::<DynMatrix<f64>>(serialized_mat).is_err());
+        let serialized_mat = r#"{"rows":2,"data":[1.0,2.0]}"#;
+        assert!(serde_json::from_str::<DynMatrix<f64>>(serialized_mat).is_err());
+    }
+
+    #[test]
+    fn serialize_named_dims() {
+        let serialized = serde_json::to_string(&NamedDims((3, 4).into())).unwrap();
+        assert_eq!(serialized, r#"{"rows":3,"cols":4}"#);
+    }
+
+    #[test_case(BinaryFormat::Bincode ; "bincode")]
+    #[test_case(BinaryFormat::Cbor ; "cbor")]
+    fn binary_formats_round_trip_dyn_matrix(format: BinaryFormat) {
+        let m = DynMatrix::from_nested(&[
+            [0.1 + 0.2, f64::MIN_POSITIVE, -0.0],
+            [1e300, std::f64::consts::PI, f64::EPSILON],
+        ]);
+        let bytes = format.to_vec(&m).unwrap();
+        let decoded = format.from_slice::<DynMatrix<f64>>(&bytes).unwrap();
+        assert_eq!((decoded.rows(), decoded.cols()), (m.rows(), m.cols()));
+        for r in 0..m.rows() {
+            for c in 0..m.cols() {
+                assert_eq!(decoded[r][c].to_bits(), m[r][c].to_bits());
+            }
+        }
+    }
+
+    #[test_case(BinaryFormat::Bincode ; "bincode")]
+    #[test_case(BinaryFormat::Cbor ; "cbor")]
+    fn binary_formats_round_trip_matrix(format: BinaryFormat) {
+        let m = Matrix::from_nested(&[[1, 2, 3], [4, 5, 6]]);
+        let bytes = format.to_vec(&m).unwrap();
+        assert_eq!(format.from_slice::<Matrix<i32, 2, 3>>(&bytes).unwrap(), m);
+        assert!(format.from_slice::<Matrix<i32, 3, 2>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn binary_formats_are_smaller_than_json() {
+        let m = DynMatrix::from_flat(&[1.0 / 3.0; 64 * 64], (64, 64));
+        let json = serde_json::to_vec(&m).unwrap();
+        for format in BinaryFormat::ALL {
+            assert!(format.to_vec(&m).unwrap().len() < json.len());
+        }
+    }
+
+    #[test]
+    fn binary_formats_reject_garbage() {
+        for format in BinaryFormat::ALL {
+            let decoded = format.from_slice::<DynMatrix<f64>>(&[0xff, 0x01]);
+            assert!(matches!(decoded, Err(Error::Validation(_))));
+        }
+    }
+
+    #[test_case("application/cbor", Some(BinaryFormat::Cbor) ; "cbor")]
+    #[test_case("Application/Octet-Stream", Some(BinaryFormat::Bincode) ; "case insensitive")]
+    #[test_case("application/cbor; charset=binary", Some(BinaryFormat::Cbor) ; "with parameters")]
+    #[test_case("application/json", None ; "json")]
+    fn binary_format_from_mime_type(mime_type: &str, expected: Option<BinaryFormat>) {
+        assert_eq!(BinaryFormat::from_mime_type(mime_type), expected);
+    }
 }
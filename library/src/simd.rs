@@ -0,0 +1,210 @@
+//! Element-wise operations on slices, used by [`DynMatrix`](crate::dyn_matrix::DynMatrix) and
+//! by convolution.
+//!
+//! Every [`Element`] gets a plain scalar loop. With the `simd` feature, `f32` and `f64` use
+//! `std::simd` vectors instead.
+
+#[cfg(feature = "simd")]
+use std::simd::prelude::*;
+
+use crate::element::Element;
+
+/// Slice kernels with a scalar default, specialized for floats when the `simd` feature is on.
+///
+/// Slices passed together must be the same length.
+pub trait SliceOps: Element {
+    /// `dst[i] += src[i]`
+    fn add_slices(dst: &mut [Self], src: &[Self]);
+
+    /// `dst[i] -= src[i]`
+    fn sub_slices(dst: &mut [Self], src: &[Self]);
+
+    /// `dst[i] *= k`
+    fn scale_slice(dst: &mut [Self], k: Self);
+
+    /// Sum of `a[i] * b[i]`
+    fn dot(a: &[Self], b: &[Self]) -> Self;
+}
+
+impl<T: Element> SliceOps for T {
+    default fn add_slices(dst: &mut [T], src: &[T]) {
+        scalar::add_slices(dst, src)
+    }
+
+    default fn sub_slices(dst: &mut [T], src: &[T]) {
+        scalar::sub_slices(dst, src)
+    }
+
+    default fn scale_slice(dst: &mut [T], k: T) {
+        scalar::scale_slice(dst, k)
+    }
+
+    default fn dot(a: &[T], b: &[T]) -> T {
+        scalar::dot(a, b)
+    }
+}
+
+/// The loops every [`Element`] falls back to. Public so benches can compare against them.
+pub mod scalar {
+    use crate::element::Element;
+
+    pub fn add_slices<T: Element>(dst: &mut [T], src: &[T]) {
+        debug_assert_eq!(dst.len(), src.len());
+        for (d, &s) in dst.iter_mut().zip(src) {
+            *d += s;
+        }
+    }
+
+    pub fn sub_slices<T: Element>(dst: &mut [T], src: &[T]) {
+        debug_assert_eq!(dst.len(), src.len());
+        for (d, &s) in dst.iter_mut().zip(src) {
+            *d -= s;
+        }
+    }
+
+    pub fn scale_slice<T: Element>(dst: &mut [T], k: T) {
+        for d in dst.iter_mut() {
+            *d = *d * k;
+        }
+    }
+
+    pub fn dot<T: Element>(a: &[T], b: &[T]) -> T {
+        debug_assert_eq!(a.len(), b.len());
+        a.iter().zip(b).fold(T::zero(), |acc, (&x, &y)| acc + x * y)
+    }
+}
+
+/// Implements [`SliceOps`] for a float type with `LANES`-wide vectors, finishing each slice's
+/// remainder with the scalar loops
+#[cfg(feature = "simd")]
+macro_rules! impl_simd_slice_ops {
+    ($t:ty, $lanes:literal) => {
+        impl SliceOps for $t {
+            fn add_slices(dst: &mut [$t], src: &[$t]) {
+                debug_assert_eq!(dst.len(), src.len());
+                let mut d = dst.chunks_exact_mut($lanes);
+                let mut s = src.chunks_exact($lanes);
+                for (d, s) in (&mut d).zip(&mut s) {
+                    let v = Simd::<$t, $lanes>::from_slice(d) + Simd::from_slice(s);
+                    v.copy_to_slice(d);
+                }
+                scalar::add_slices(d.into_remainder(), s.remainder());
+            }
+
+            fn sub_slices(dst: &mut [$t], src: &[$t]) {
+                debug_assert_eq!(dst.len(), src.len());
+                let mut d = dst.chunks_exact_mut($lanes);
+                let mut s = src.chunks_exact($lanes);
+                for (d, s) in (&mut d).zip(&mut s) {
+                    let v = Simd::<$t, $lanes>::from_slice(d) - Simd::from_slice(s);
+                    v.copy_to_slice(d);
+                }
+                scalar::sub_slices(d.into_remainder(), s.remainder());
+            }
+
+            fn scale_slice(dst: &mut [$t], k: $t) {
+                let k_v = Simd::<$t, $lanes>::splat(k);
+                let mut d = dst.chunks_exact_mut($lanes);
+                for d in &mut d {
+                    (Simd::from_slice(d) * k_v).copy_to_slice(d);
+                }
+                scalar::scale_slice(d.into_remainder(), k);
+            }
+
+            fn dot(a: &[$t], b: &[$t]) -> $t {
+                debug_assert_eq!(a.len(), b.len());
+                let mut a_chunks = a.chunks_exact($lanes);
+                let mut b_chunks = b.chunks_exact($lanes);
+                let mut acc = Simd::<$t, $lanes>::splat(0.0);
+                for (x, y) in (&mut a_chunks).zip(&mut b_chunks) {
+                    acc += Simd::from_slice(x) * Simd::from_slice(y);
+                }
+                acc.reduce_sum() + scalar::dot(a_chunks.remainder(), b_chunks.remainder())
+            }
+        }
+    };
+}
+
+#[cfg(feature = "simd")]
+impl_simd_slice_ops!(f32, 8);
+#[cfg(feature = "simd")]
+impl_simd_slice_ops!(f64, 4);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate test;
+    use test::Bencher;
+
+    /// `len` evenly spaced values, starting at zero
+    fn ramp(len: usize, step: f64) -> Vec<f64> {
+        (0..len).map(|i| i as f64 * step).collect()
+    }
+
+    #[test]
+    fn add_and_sub_match_scalar() {
+        let src = ramp(37, 0.5);
+        let mut added = ramp(37, 2.0);
+        let mut expected = added.clone();
+        f64::add_slices(&mut added, &src);
+        scalar::add_slices(&mut expected, &src);
+        assert_eq!(added, expected);
+
+        f64::sub_slices(&mut added, &src);
+        assert_eq!(added, ramp(37, 2.0));
+    }
+
+    #[test]
+    fn scale_matches_scalar() {
+        let mut scaled: Vec<f32> = (0..21).map(|i| i as f32).collect();
+        f32::scale_slice(&mut scaled, 3.0);
+        assert_eq!(scaled, (0..21).map(|i| i as f32 * 3.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dot_matches_scalar() {
+        let (a, b) = (ramp(13, 1.0), ramp(13, 2.0));
+        assert_eq!(f64::dot(&a, &b), scalar::dot(&a, &b));
+        assert_eq!(i32::dot(&[1, 2, 3], &[4, 5, 6]), 32);
+        assert_eq!(f64::dot(&[], &[]), 0.0);
+    }
+
+    // Run with and without `--features simd` to see the difference
+    #[bench]
+    fn bench_add_slices_f64(b: &mut Bencher) {
+        let src = ramp(1 << 20, 1.0);
+        let mut dst = ramp(1 << 20, 1.0);
+        b.iter(|| f64::add_slices(&mut dst, &src));
+    }
+
+    #[bench]
+    fn bench_add_slices_f64_scalar(b: &mut Bencher) {
+        let src = ramp(1 << 20, 1.0);
+        let mut dst = ramp(1 << 20, 1.0);
+        b.iter(|| scalar::add_slices(&mut dst, &src));
+    }
+
+    #[bench]
+    fn bench_scale_slice_f64(b: &mut Bencher) {
+        let mut dst = ramp(1 << 20, 1.0);
+        b.iter(|| f64::scale_slice(&mut dst, 1.0000001));
+    }
+
+    #[bench]
+    fn bench_scale_slice_f64_scalar(b: &mut Bencher) {
+        let mut dst = ramp(1 << 20, 1.0);
+        b.iter(|| scalar::scale_slice(&mut dst, 1.0000001));
+    }
+
+    #[bench]
+    fn bench_dot_f64(b: &mut Bencher) {
+        let (x, y) = (ramp(1 << 20, 1.0), ramp(1 << 20, 0.5));
+        b.iter(|| f64::dot(&x, &y));
+    }
+
+    #[bench]
+    fn bench_dot_f64_scalar(b: &mut Bencher) {
+        let (x, y) = (ramp(1 << 20, 1.0), ramp(1 << 20, 0.5));
+        b.iter(|| scalar::dot(&x, &y));
+    }
+}
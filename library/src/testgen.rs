@@ -0,0 +1,299 @@
+//! Synthetic test images: checkerboards, gradients, noise, and Siemens stars, at any size.
+//!
+//! Every pattern is a pure function of its size and parameters, so the same call gives the same
+//! pixels on every machine. That makes them handy wherever a test (or a demo) needs an image
+//! without shipping one. Noise is seeded, and built from a hash of each pixel's position rather
+//! than a random number generator, so it doesn't change with the `rand` version either.
+
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::errors::{Error, Result};
+use crate::my_image::MyImage;
+
+/// Side of each [`checkerboard`] square when none is given, in pixels
+pub const DEFAULT_CELL_SIZE: u32 = 32;
+
+/// Number of dark wedges in a [`siemens_star`] when none is given
+pub const DEFAULT_SPOKES: u32 = 16;
+
+/// Most wedges a [`siemens_star`] may have. Past this, they're thinner than a pixel for all but
+/// the largest images
+pub const MAX_SPOKES: u32 = 512;
+
+fn check_dims(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(Error::validation(format!(
+            "Generated images must be at least 1x1, not {}x{}",
+            width, height
+        )));
+    }
+    Ok(())
+}
+
+/// `i` of `n` steps from 0 to 255, rounded. Zero if there's only one step
+fn ramp(i: u32, n: u32) -> u8 {
+    if n <= 1 {
+        return 0;
+    }
+    let (i, last) = (i as u64, n as u64 - 1);
+    ((i * 255 + last / 2) / last) as u8
+}
+
+/// One of 2^64 well-mixed values for each `x` (see SplitMix64)
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// A grayscale checkerboard of `cell_size` squares, white in the top-left corner
+pub fn checkerboard(width: u32, height: u32, cell_size: u32) -> Result<DynamicImage> {
+    check_dims(width, height)?;
+    if cell_size == 0 {
+        return Err(Error::validation(
+            "Checkerboard cells must be at least 1 pixel",
+        ));
+    }
+    Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(
+        width,
+        height,
+        |x, y| match (x / cell_size + y / cell_size) % 2 {
+            0 => Luma([255]),
+            _ => Luma([0]),
+        },
+    )))
+}
+
+/// An RGB gradient: red goes from 0 to 255 left to right, green top to bottom, and blue from the
+/// top-left corner to the bottom-right
+pub fn gradient(width: u32, height: u32) -> Result<DynamicImage> {
+    check_dims(width, height)?;
+    Ok(DynamicImage::ImageRgb8(RgbImage::from_fn(
+        width,
+        height,
+        |x, y| {
+            Rgb([
+                ramp(x, width),
+                ramp(y, height),
+                ramp(x + y, width + height - 1),
+            ])
+        },
+    )))
+}
+
+/// Grayscale white noise: each pixel is independent and uniformly distributed, and the same for
+/// the same `seed`
+pub fn noise(width: u32, height: u32, seed: u64) -> Result<DynamicImage> {
+    check_dims(width, height)?;
+    let seed = mix(seed);
+    Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(
+        width,
+        height,
+        |x, y| {
+            let position = ((y as u64) << 32) | x as u64;
+            Luma([(mix(seed ^ position) >> 56) as u8])
+        },
+    )))
+}
+
+/// A grayscale Siemens star: `spokes` dark wedges, alternating with as many light ones, meeting
+/// at the center. Its detail gets finer toward the center, so it shows how much of it a filter
+/// keeps
+pub fn siemens_star(width: u32, height: u32, spokes: u32) -> Result<DynamicImage> {
+    check_dims(width, height)?;
+    if spokes == 0 || spokes > MAX_SPOKES {
+        return Err(Error::validation(format!(
+            "Siemens stars must have 1 to {} spokes, not {}",
+            MAX_SPOKES, spokes
+        )));
+    }
+    let (cx, cy) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+    let wedge = std::f64::consts::PI / spokes as f64;
+    Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(
+        width,
+        height,
+        |x, y| {
+            // From 0 to 2π, counterclockwise from the right
+            let angle = (cy - y as f64)
+                .atan2(x as f64 - cx)
+                .rem_euclid(std::f64::consts::TAU);
+            match (angle / wedge) as u32 % 2 {
+                0 => Luma([255]),
+                _ => Luma([0]),
+            }
+        },
+    )))
+}
+
+/// Parameters for [`Pattern::build`]. Each pattern uses only its own (see
+/// [`Pattern::parameters`]), or its default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatternParams {
+    /// See [`checkerboard`]. Defaults to [`DEFAULT_CELL_SIZE`]
+    #[serde(default)]
+    pub cell_size: Option<u32>,
+
+    /// See [`noise`]. Defaults to 0
+    #[serde(default)]
+    pub seed: Option<u64>,
+
+    /// See [`siemens_star`]. Defaults to [`DEFAULT_SPOKES`]
+    #[serde(default)]
+    pub spokes: Option<u32>,
+}
+
+/// Each of the patterns above, by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// See [`checkerboard`]
+    Checkerboard,
+
+    /// See [`gradient`]
+    Gradient,
+
+    /// See [`noise`]
+    Noise,
+
+    /// See [`siemens_star`]
+    SiemensStar,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 4] = [
+        Pattern::Checkerboard,
+        Pattern::Gradient,
+        Pattern::Noise,
+        Pattern::SiemensStar,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Checkerboard => "checkerboard",
+            Pattern::Gradient => "gradient",
+            Pattern::Noise => "noise",
+            Pattern::SiemensStar => "siemens_star",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pattern> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// Names of the [`PatternParams`] this pattern uses. It ignores the rest
+    pub fn parameters(&self) -> &'static [&'static str] {
+        match self {
+            Pattern::Checkerboard => &["cell_size"],
+            Pattern::Gradient => &[],
+            Pattern::Noise => &["seed"],
+            Pattern::SiemensStar => &["spokes"],
+        }
+    }
+
+    /// Build this pattern at the given size, with the given parameters where it takes them, or
+    /// the defaults
+    pub fn build(&self, width: u32, height: u32, params: &PatternParams) -> Result<DynamicImage> {
+        match self {
+            Pattern::Checkerboard => {
+                checkerboard(width, height, params.cell_size.unwrap_or(DEFAULT_CELL_SIZE))
+            }
+            Pattern::Gradient => gradient(width, height),
+            Pattern::Noise => noise(width, height, params.seed.unwrap_or(0)),
+            Pattern::SiemensStar => {
+                siemens_star(width, height, params.spokes.unwrap_or(DEFAULT_SPOKES))
+            }
+        }
+    }
+
+    /// [`Pattern::build`], as a [`MyImage`]
+    pub fn build_my_image(
+        &self,
+        width: u32,
+        height: u32,
+        params: &PatternParams,
+    ) -> Result<MyImage<u8>> {
+        Ok(MyImage::from(&self.build(width, height, params)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use test_case::test_case;
+
+    #[test]
+    fn patterns_are_found_by_name() {
+        for pattern in Pattern::ALL {
+            assert_eq!(Pattern::from_name(pattern.name()), Some(pattern));
+        }
+        assert_eq!(Pattern::from_name("plaid"), None);
+    }
+
+    #[test_case(Pattern::Checkerboard)]
+    #[test_case(Pattern::Gradient)]
+    #[test_case(Pattern::Noise)]
+    #[test_case(Pattern::SiemensStar)]
+    fn patterns_are_deterministic_at_any_size(pattern: Pattern) {
+        let params = PatternParams::default();
+        for (w, h) in [(1, 1), (7, 3), (64, 65)] {
+            let image = pattern.build(w, h, &params).unwrap();
+            assert_eq!(image.dimensions(), (w, h));
+            assert_eq!(pattern.build(w, h, &params).unwrap(), image);
+        }
+        assert!(matches!(
+            pattern.build(0, 8, &params),
+            Err(Error::Validation(_))
+        ));
+
+        let mine = pattern.build_my_image(5, 4, &params).unwrap();
+        assert_eq!((mine.width(), mine.height()), (5, 4));
+    }
+
+    #[test]
+    fn checkerboard_alternates_cells() {
+        let image = checkerboard(8, 8, 4).unwrap().to_luma8();
+        assert_eq!(image.get_pixel(0, 0).0, [255]);
+        assert_eq!(image.get_pixel(3, 3).0, [255]);
+        assert_eq!(image.get_pixel(4, 0).0, [0]);
+        assert_eq!(image.get_pixel(0, 4).0, [0]);
+        assert_eq!(image.get_pixel(7, 7).0, [255]);
+        assert!(checkerboard(8, 8, 0).is_err());
+    }
+
+    #[test]
+    fn gradient_spans_the_full_range() {
+        let image = gradient(16, 9).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(15, 8).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(15, 0).0[..2], [255, 0]);
+    }
+
+    #[test]
+    fn noise_depends_on_its_seed() {
+        let a = noise(32, 32, 1).unwrap().to_luma8();
+        assert_ne!(noise(32, 32, 2).unwrap().to_luma8(), a);
+
+        // Roughly uniform: the mean is near the middle, and most values turn up
+        let mean = a.pixels().map(|p| p.0[0] as f64).sum::<f64>() / 1024.0;
+        assert!((mean - 127.5).abs() < 10.0, "mean was {}", mean);
+        let mut seen = [false; 256];
+        a.pixels().for_each(|p| seen[p.0[0] as usize] = true);
+        assert!(seen.iter().filter(|&&s| s).count() > 200);
+    }
+
+    #[test]
+    fn siemens_star_wedges_alternate() {
+        let image = siemens_star(101, 101, 4).unwrap().to_luma8();
+        // Just above the rightmost edge is the first (light) wedge, then dark past 45°
+        assert_eq!(image.get_pixel(100, 48).0, [255]);
+        assert_eq!(image.get_pixel(60, 30).0, [0]);
+        // With an even number of spokes, opposite wedges match
+        assert_eq!(image.get_pixel(0, 52).0, [255]);
+        assert!(siemens_star(8, 8, 0).is_err());
+        assert!(siemens_star(8, 8, MAX_SPOKES + 1).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! Access to the administration routes, under `/api/v1/admin`.
+//!
+//! These reload the server's settings and run benchmarks, so they aren't for just anyone. They're
+//! only served when the server is given an admin token (`--admin-token`, or `TILER_ADMIN_TOKEN`),
+//! and then only to requests bearing it, as `Authorization: Bearer <token>`. Without a token, the
+//! routes don't exist at all.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jnickg_imaging::errors::Error;
+
+use crate::wrappers::WrappedError;
+
+/// Whether `a` and `b` are equal, taking as long to find out wherever they differ, so a token
+/// can't be guessed a byte at a time by timing how quickly guesses are turned away
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Middleware letting through only requests bearing `token`
+pub async fn require_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if presented.is_some_and(|p| constant_time_eq(p.as_bytes(), token.as_bytes())) {
+        return next.run(request).await;
+    }
+
+    tracing::warn!("Rejected admin request without a valid token");
+    let mut response =
+        WrappedError(Error::unauthorized("A valid admin token is required")).into_response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode, middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/", post(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::<str>::from("secret"),
+                require_token,
+            ))
+    }
+
+    async fn status_with(authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::post("/");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn only_requests_with_the_token_get_through() {
+        assert_eq!(status_with(Some("Bearer secret")).await, StatusCode::OK);
+        for authorization in [
+            None,
+            Some("Bearer nope"),
+            Some("Bearer secret2"),
+            Some("secret"),
+        ] {
+            assert_eq!(
+                status_with(authorization).await,
+                StatusCode::UNAUTHORIZED,
+                "{:?}",
+                authorization
+            );
+        }
+    }
+}
@@ -0,0 +1,2101 @@
+//! End-to-end tests of the HTTP API.
+//!
+//! These drive the same router the server runs, in process, without binding a port. Matrix routes
+//! keep their state in memory. Image and pyramid routes need MongoDB, so those tests each get a
+//! throwaway test database (see [`crate::test_support`]).
+
+use std::{
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::DefaultBodyLimit,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use image::{DynamicImage, ImageFormat};
+use jnickg_imaging::{
+    compression::Compression,
+    documents::{self, Pyramid, PyramidManifest, PyramidPage, PyramidTiles, TileSet, TilingStatus},
+    dyn_matrix::DynMatrix,
+    ipr::ResampleFilter,
+    serde::BinaryFormat,
+    testgen,
+};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tower::ServiceExt;
+
+use crate::{
+    database::Db,
+    rate_limit::{RateLimitKey, RateLimiter},
+    test_support::{
+        compare_images, drop_test_database, fixture_documents, jpeg_with_orientation,
+        pyramid_archive_files, synthetic_image, test_database, zip_files, Tolerance,
+    },
+    tunables::{Tunables, TunablesStore},
+    web_api::MAX_BATCH_TILES,
+    web_appstate::RuntimeData,
+};
+
+/// How long to wait for a pyramid's tiles before giving up
+const TILING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Token the admin routes of [`test_app`] expect
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// The API routes, as the server nests them, serving `state`, with a worker to run the jobs they
+/// queue
+fn test_app(state: RuntimeData) -> Router {
+    test_app_with_admin_token(state, Some(ADMIN_TOKEN))
+}
+
+fn test_app_with_admin_token(state: RuntimeData, admin_token: Option<&str>) -> Router {
+    let upload_limit = DefaultBodyLimit::max(state.upload_limits.max_bytes);
+    // Tests make requests as fast as they can, so they'd trip any rate limit
+    let unlimited = TunablesStore::fixed(Tunables {
+        rate_limit_per_minute: 0,
+        ..Default::default()
+    });
+    let rate_limiter = Arc::new(RateLimiter::new(Arc::new(unlimited), RateLimitKey::Ip));
+    let app_state = Arc::new(RwLock::new(state));
+    crate::jobs::start_workers(app_state.clone(), 1);
+    Router::new()
+        .nest(
+            "/api/v1",
+            crate::api_routes(upload_limit, rate_limiter, admin_token),
+        )
+        .with_state(app_state)
+}
+
+/// [`test_app`] serving a fresh test database, along with the database, which the test should
+/// drop when done
+async fn db_app() -> (Router, Db) {
+    let db = test_database().await;
+    let mut state = RuntimeData::new();
+    state.db = Some(db.clone());
+    (test_app(state), db)
+}
+
+/// Upload `image` as a PNG called `name`, returning the PNG
+async fn upload_image(app: &Router, name: &str, image: &DynamicImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/image")
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename={}", name),
+        )
+        .body(Body::from(png.clone()))
+        .unwrap();
+    let (status, _, _) = send(app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    png
+}
+
+/// Send one request, and collect the whole response
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, headers, body)
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, HeaderMap, Bytes) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn send_json(app: &Router, method: Method, uri: &str, body: Value) -> (StatusCode, Bytes) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, _, body) = send(app, request).await;
+    (status, body)
+}
+
+fn json_body(body: &Bytes) -> Value {
+    serde_json::from_slice(body).expect("Response body should be JSON")
+}
+
+#[tokio::test]
+async fn matrix_arithmetic_round_trips() {
+    let app = test_app(RuntimeData::new());
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[1, 2], [3, 4]]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/b",
+        json!([[5, 6], [7, 8]]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/multiply/a/b",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body), json!([[19.0, 22.0], [43.0, 50.0]]));
+
+    let (status, body) = send_json(&app, Method::POST, "/api/v1/matrix/add/a/b", json!(null)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body), json!([[6.0, 8.0], [10.0, 12.0]]));
+
+    let (status, _, body) = get(&app, "/api/v1/matrix/a?format=object").await;
+    assert_eq!(status, StatusCode::OK);
+    let body = json_body(&body);
+    assert_eq!(body["rows"], 2);
+    assert_eq!(body["cols"], 2);
+    assert_eq!(body["data"], json!([1.0, 2.0, 3.0, 4.0]));
+}
+
+#[tokio::test]
+async fn matrix_uploads_use_the_upload_limit() {
+    // About 3 MiB of JSON, over axum's default limit of 2 MiB
+    let big = json!(vec![vec![0.123456789; 512]; 512]);
+    let app = test_app(RuntimeData::new());
+    let (status, _) = send_json(&app, Method::POST, "/api/v1/matrix/big", big.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send_json(&app, Method::PUT, "/api/v1/matrix/big", big.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut state = RuntimeData::new();
+    state.upload_limits.max_bytes = 1024 * 1024;
+    let app = test_app(state);
+    let (status, _) = send_json(&app, Method::POST, "/api/v1/matrix/big", big).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn matrix_solve_finds_solution() {
+    let app = test_app(RuntimeData::new());
+
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[2, 1], [1, 3]]),
+    )
+    .await;
+    send_json(&app, Method::POST, "/api/v1/matrix/b", json!([[3], [5]])).await;
+
+    let (status, body) =
+        send_json(&app, Method::POST, "/api/v1/matrix/solve/a/b", json!(null)).await;
+    assert_eq!(status, StatusCode::OK);
+    let x = json_body(&body);
+    assert!((x[0][0].as_f64().unwrap() - 0.8).abs() < 1e-9);
+    assert!((x[1][0].as_f64().unwrap() - 1.4).abs() < 1e-9);
+}
+
+/// Poll a background matrix operation's status until it's no longer running
+async fn wait_for_matrix(app: &Router, status_url: &str) -> Value {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let (status, _, body) = get(app, status_url).await;
+        assert_eq!(status, StatusCode::OK);
+        let body = json_body(&body);
+        if body["state"] != "running" {
+            return body;
+        }
+        assert!(Instant::now() < deadline, "Matrix took too long");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn matrix_products_are_computed_in_the_background() {
+    let app = test_app(RuntimeData::new());
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[1, 2], [3, 4]]),
+    )
+    .await;
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/b",
+        json!([[5, 6], [7, 8]]),
+    )
+    .await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/multiply/a/b?background=true&store_as=ab",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let started = json_body(&body);
+    assert_eq!(started["status_url"], "/api/v1/matrix/ab/status");
+    let done = wait_for_matrix(&app, "/api/v1/matrix/ab/status").await;
+    assert_eq!(done["state"], "done");
+    let (_, _, body) = get(&app, "/api/v1/matrix/ab").await;
+    assert_eq!(json_body(&body), json!([[19.0, 22.0], [43.0, 50.0]]));
+
+    // Results don't replace matrices
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/multiply/a/b?background=true&store_as=ab",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Results are named for us if we don't name them, and failures are reported
+    send_json(&app, Method::POST, "/api/v1/matrix/c", json!([[1, 2, 3]])).await;
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/multiply/a/c?background=true",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let started = json_body(&body);
+    let failed = wait_for_matrix(&app, started["status_url"].as_str().unwrap()).await;
+    assert_eq!(failed["state"], "failed");
+    assert!(failed["error"].is_string());
+    let (status, _, _) = get(&app, started["url"].as_str().unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = get(&app, "/api/v1/matrix/nope/status").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn matrix_results_are_stored_for_later_operations() {
+    let app = test_app(RuntimeData::new());
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[1, 2], [3, 4]]),
+    )
+    .await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/add/a/a?store_as=twice",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let stored = json_body(&body);
+    assert_eq!(stored["state"], "done");
+    assert_eq!(stored["url"], "/api/v1/matrix/twice");
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/transpose/twice?store_as=flipped",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)["url"], "/api/v1/matrix/flipped");
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/multiply/flipped/a?background=false",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body), json!([[20.0, 28.0], [28.0, 40.0]]));
+
+    // Stored results don't replace matrices
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/scale/a?factor=3&store_as=twice",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, _, body) = get(&app, "/api/v1/matrix/twice").await;
+    assert_eq!(json_body(&body), json!([[2.0, 4.0], [6.0, 8.0]]));
+}
+
+#[tokio::test]
+async fn kernels_are_listed_and_stored_as_matrices() {
+    let app = test_app(RuntimeData::new());
+
+    let (status, _, body) = get(&app, "/api/v1/kernels").await;
+    assert_eq!(status, StatusCode::OK);
+    let kernels = json_body(&body);
+    let gaussian = kernels
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|k| k["name"] == "gaussian")
+        .expect("Gaussian should be listed");
+    assert_eq!(gaussian["parameters"], json!(["sigma", "size"]));
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/blur/from_kernel/gaussian?sigma=0.5",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let blur = json_body(&body);
+    assert_eq!(blur.as_array().unwrap().len(), 3);
+    let total: f64 = blur
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|row| row.as_array().unwrap())
+        .map(|v| v.as_f64().unwrap())
+        .sum();
+    assert!((total - 1.0).abs() < 1e-9);
+
+    let (status, _, body) = get(&app, "/api/v1/matrix/blur").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body), blur);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/edges/from_kernel/sobel_x",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        json_body(&body),
+        json!([[1.0, 0.0, -1.0], [2.0, 0.0, -2.0], [1.0, 0.0, -1.0]])
+    );
+
+    for (uri, expected) in [
+        ("/api/v1/matrix/blur/from_kernel/box", StatusCode::CONFLICT),
+        ("/api/v1/matrix/x/from_kernel/emboss", StatusCode::NOT_FOUND),
+        (
+            "/api/v1/matrix/x/from_kernel/box?size=4",
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let (status, _) = send_json(&app, Method::POST, uri, json!(null)).await;
+        assert_eq!(status, expected, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn matrices_are_compared_within_tolerance() {
+    let app = test_app(RuntimeData::new());
+
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[1, 2], [3, 4]]),
+    )
+    .await;
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/b",
+        json!([[1, 2.25], [3, 4]]),
+    )
+    .await;
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/wide",
+        json!([[1, 2, 3]]),
+    )
+    .await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/compare/a/b?epsilon=0.5",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json_body(&body),
+        json!({ "matches": true, "max_abs_diff": 0.25, "epsilon": 0.5 })
+    );
+
+    let (_, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/compare/a/b",
+        json!(null),
+    )
+    .await;
+    assert_eq!(json_body(&body)["matches"], false);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/compare/a/wide",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body)["matches"], false);
+    assert_eq!(json_body(&body)["max_abs_diff"], Value::Null);
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/compare/a/b?epsilon=-1",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn matrix_norms_are_reported_together() {
+    let app = test_app(RuntimeData::new());
+
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[4, 7], [2, 6]]),
+    )
+    .await;
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/wide",
+        json!([[1, 2, 3]]),
+    )
+    .await;
+
+    let (status, _, body) = get(&app, "/api/v1/matrix/a/norms").await;
+    assert_eq!(status, StatusCode::OK);
+    let norms = json_body(&body);
+    assert_eq!(norms["l1"], 13.0);
+    assert_eq!(norms["inf"], 11.0);
+    assert!((norms["condition_number"].as_f64().unwrap() - 14.3).abs() < 1e-9);
+
+    let (_, _, body) = get(&app, "/api/v1/matrix/wide/norms").await;
+    assert_eq!(json_body(&body)["condition_number"], Value::Null);
+}
+
+#[tokio::test]
+async fn matrix_errors_are_json() {
+    let app = test_app(RuntimeData::new());
+
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/a",
+        json!([[1, 2], [3, 4]]),
+    )
+    .await;
+    send_json(&app, Method::POST, "/api/v1/matrix/c", json!([[1, 2, 3]])).await;
+
+    let (status, body) = send_json(&app, Method::POST, "/api/v1/matrix/a", json!([[1]])).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(json_body(&body)["error"], "conflict");
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/multiply/a/c",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let body = json_body(&body);
+    assert_eq!(body["status"], 422);
+    assert_eq!(body["error"], "unprocessable");
+
+    let (status, _, body) = get(&app, "/api/v1/matrix/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json_body(&body)["error"], "not_found");
+
+    let request = Request::post("/api/v1/matrix/d")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("not a matrix"))
+        .unwrap();
+    let (status, _, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn malformed_matrices_are_rejected_with_a_reason() {
+    let app = test_app(RuntimeData::new());
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/ragged",
+        json!([[1, 2], [3]]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body = json_body(&body);
+    assert_eq!(body["error"], "validation");
+    let message = body["message"].as_str().unwrap();
+    assert!(message.contains("same length"), "{}", message);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/short",
+        json!({"rows": 2, "cols": 2, "data": [1, 2, 3]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let message = json_body(&body)["message"].as_str().unwrap().to_string();
+    assert!(message.contains("needs 4 elements"), "{}", message);
+
+    // Empty matrices are fine, and keep their shape
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/empty",
+        json!({"rows": 0, "cols": 3, "data": []}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _, body) = get(&app, "/api/v1/matrix/empty/dims").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body), json!([0, 3]));
+}
+
+#[tokio::test]
+async fn matrices_transfer_in_binary_formats() {
+    let app = test_app(RuntimeData::new());
+    let m = DynMatrix::from_nested(&[[0.1 + 0.2, 1.0 / 3.0], [f64::MIN_POSITIVE, -2.5]]);
+
+    let request = Request::put("/api/v1/matrix/a")
+        .header(header::CONTENT_TYPE, BinaryFormat::Cbor.mime_type())
+        .body(Body::from(BinaryFormat::Cbor.to_vec(&m).unwrap()))
+        .unwrap();
+    let (status, headers, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+
+    let request = Request::get("/api/v1/matrix/a")
+        .header(
+            header::ACCEPT,
+            "application/octet-stream, application/json;q=0.5",
+        )
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/octet-stream");
+    let round_tripped = BinaryFormat::Bincode
+        .from_slice::<DynMatrix<f64>>(&body)
+        .unwrap();
+    assert_eq!(round_tripped, m);
+
+    let request = Request::post("/api/v1/matrix/b")
+        .header(header::CONTENT_TYPE, BinaryFormat::Cbor.mime_type())
+        .body(Body::from("not CBOR"))
+        .unwrap();
+    let (status, _, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn image_routes_need_a_database() {
+    let app = test_app(RuntimeData::new());
+    let (status, _, body) = get(&app, "/api/v1/image/anything").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(json_body(&body)["error"], "database");
+}
+
+/// Poll a pyramid until it's tiled, failing the test if tiling fails or takes too long.
+///
+/// Tiling runs as a background task, alongside the requests polling for it, so tests that wait
+/// for tiles need the multi-threaded runtime: `#[tokio::test(flavor = "multi_thread")]`
+async fn wait_for_tiles(app: &Router, uuid: &str) -> Pyramid {
+    wait_for_tiles_of(app, uuid, |p| Some(&p.tiles)).await
+}
+
+/// Like [`wait_for_tiles`], but for the tiles `tiles_of` picks out of the pyramid, e.g. those of
+/// one of its tile sets
+async fn wait_for_tiles_of(
+    app: &Router,
+    uuid: &str,
+    tiles_of: impl Fn(&Pyramid) -> Option<&PyramidTiles>,
+) -> Pyramid {
+    let start = Instant::now();
+    loop {
+        let (status, _, body) = get(app, &format!("/api/v1/pyramid/{}", uuid)).await;
+        assert_eq!(status, StatusCode::OK);
+        let pyramid: Pyramid = serde_json::from_slice(&body).unwrap();
+        match tiles_of(&pyramid).expect("No such tiles") {
+            PyramidTiles::Levels(_) => return pyramid,
+            PyramidTiles::Status(TilingStatus::Failed) => panic!("Tiling pyramid {} failed", uuid),
+            PyramidTiles::Status(_) => (),
+        }
+        assert!(
+            start.elapsed() < TILING_TIMEOUT,
+            "Timed out waiting for pyramid {} to be tiled",
+            uuid
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+fn decode_png(data: &[u8]) -> DynamicImage {
+    image::load_from_memory_with_format(data, ImageFormat::Png).expect("Expected a PNG")
+}
+
+// Skipped levels are built on request from the levels above them, which Laplacian levels can't be
+#[tokio::test]
+async fn laplacian_pyramids_keep_every_level() {
+    let app = test_app(RuntimeData::new());
+    for query in ["max_levels=2", "min_dimension=64"] {
+        let request = Request::post(format!("/api/v1/pyramid?pyramid_type=laplacian&{}", query))
+            .header(header::CONTENT_TYPE, "image/png")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8_lossy(&body).contains("Laplacian"));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uploaded_image_is_tiled_and_served() {
+    let (app, db) = db_app().await;
+
+    let source = synthetic_image(40, 24);
+    let mut png = Vec::new();
+    source
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?compression=gzip")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(created.compression, Compression::Gzip);
+    assert_eq!(created.tiles, PyramidTiles::Status(TilingStatus::Todo));
+
+    let pyramid = wait_for_tiles(&app, &created.uuid).await;
+    let levels = pyramid.tiles.levels().unwrap();
+    assert_eq!(levels.len(), pyramid.image_names.len());
+    assert_eq!((levels[0].width, levels[0].height), (40, 24));
+
+    // Level 0 is stored as uploaded
+    let (status, headers, body) = get(&app, &pyramid.image_urls[0]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+    let level0 = decode_png(&body);
+    assert!(compare_images(&source, &level0)
+        .unwrap()
+        .within(Tolerance::EXACT));
+
+    // Tiles are decompressed for clients that don't accept the codec...
+    let tile = &levels[0].tiles[0];
+    let tile_url = format!("/api/v1/image/{}", tile.name);
+    let (status, headers, plain) = get(&app, &tile_url).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get(header::CONTENT_ENCODING).is_none());
+    let tile_image = decode_png(&plain);
+    assert_eq!(
+        (tile_image.width(), tile_image.height()),
+        (tile.width, tile.height)
+    );
+
+    // ...and sent as stored to those that do
+    let request = Request::get(&tile_url)
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, compressed) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(Compression::Gzip.decompress(&compressed).unwrap(), plain);
+
+    let (status, _, _) = get(&app, "/api/v1/image/no_such_tile").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn undecodable_uploads_are_unprocessable() {
+    let (app, db) = db_app().await;
+
+    // Its dimensions can be read, so it passes the pixel budget, but most of its pixels are
+    // missing
+    let mut png = Vec::new();
+    synthetic_image(40, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let pixels = png.windows(4).position(|w| w == b"IDAT").unwrap();
+    png.truncate(pixels + 20);
+    let request = Request::post("/api/v1/pyramid")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json_body(&body)["error"], "unprocessable");
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transparent_tiles_keep_their_alpha() {
+    let (app, db) = db_app().await;
+
+    let request = Request::post("/api/v1/pyramid?tile_format=nonsense")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Two tiles across at the default tile size, and only the first has any transparency
+    let source = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(600, 24, |x, _| {
+        image::Rgba([200, 100, 50, if x < 100 { 0 } else { u8::MAX }])
+    }));
+    let mut png = Vec::new();
+    source
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?tile_format=jpeg&max_levels=1")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(created.tile_format.as_deref(), Some("image/jpeg"));
+
+    // The transparent tile falls back to PNG
+    let pyramid = wait_for_tiles(&app, &created.uuid).await;
+    let tiles = &pyramid.tiles.levels().unwrap()[0].tiles;
+    assert_eq!(tiles.len(), 2);
+    for (tile, expected) in tiles.iter().zip(["image/png", "image/jpeg"]) {
+        assert_eq!(tile.mime_type.as_deref(), Some(expected));
+        let (status, headers, _) = get(&app, &format!("/api/v1/image/{}", tile.name)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], expected);
+    }
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pyramid_manifest_lists_tiles() {
+    let (app, db) = db_app().await;
+
+    // Two tiles across at the default tile size
+    let mut png = Vec::new();
+    synthetic_image(600, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?max_levels=2")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    wait_for_tiles(&app, &created.uuid).await;
+
+    let (status, _, body) = get(&app, &format!("/api/v1/pyramid/{}/manifest", created.uuid)).await;
+    assert_eq!(status, StatusCode::OK);
+    let manifest: PyramidManifest = serde_json::from_slice(&body).unwrap();
+    assert_eq!(manifest.status, None);
+    assert_eq!((manifest.width, manifest.height), (600, 24));
+    assert_eq!(manifest.levels.len(), 2);
+
+    let level = &manifest.levels[0];
+    assert_eq!((level.columns, level.rows), (2, 1));
+    assert_eq!((level.tile_width, level.tile_height), (512, 24));
+    assert_eq!(manifest.levels[1].width, 300);
+    for tile in &level.tiles {
+        let (status, headers, body) = get(&app, &tile.url).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], tile.mime_type.as_str());
+        let image = decode_png(&body);
+        assert_eq!((image.width(), image.height()), (tile.width, tile.height));
+    }
+    let (status, _, _) = get(&app, &level.url).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, _) = get(&app, "/api/v1/pyramid/no_such_pyramid/manifest").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pyramid_verification_finds_damaged_files() {
+    let (app, db) = db_app().await;
+
+    let mut png = Vec::new();
+    synthetic_image(600, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?max_levels=2")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(created.image_sha256.len(), 2);
+    let pyramid = wait_for_tiles(&app, &created.uuid).await;
+    let tiles = pyramid
+        .tiles
+        .levels()
+        .unwrap()
+        .iter()
+        .flat_map(|l| &l.tiles)
+        .collect::<Vec<_>>();
+    assert!(tiles.iter().all(|t| t.sha256.is_some()));
+
+    let url = format!("/api/v1/pyramid/{}/verify", created.uuid);
+    let (status, _, body) = get(&app, &url).await;
+    assert_eq!(status, StatusCode::OK);
+    let report = json_body(&body);
+    assert_eq!(report["ok"], true);
+    assert_eq!(report["checked"], 2 + tiles.len());
+    assert_eq!(report["unchecked"], 0);
+
+    // A tile whose file went missing is reported, with nothing to compare
+    db.gridfs_bucket()
+        .delete(mongodb::bson::Bson::ObjectId(tiles[0].tile_id))
+        .await
+        .unwrap();
+    let (status, _, body) = get(&app, &url).await;
+    assert_eq!(status, StatusCode::OK);
+    let report = json_body(&body);
+    assert_eq!(report["ok"], false);
+    assert_eq!(
+        report["mismatches"],
+        json!([{
+            "name": tiles[0].name,
+            "expected": tiles[0].sha256,
+            "actual": null,
+        }])
+    );
+
+    let (status, _, _) = get(&app, "/api/v1/pyramid/no_such_pyramid/verify").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn level_info_reports_dimensions_and_pyramid() {
+    let (app, db) = db_app().await;
+
+    let mut png = Vec::new();
+    synthetic_image(600, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?max_levels=2")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    wait_for_tiles(&app, &created.uuid).await;
+
+    // A stored level, and one that was skipped and gets built on the way
+    for (level, dims) in [(0, (600, 24)), (3, (75, 3))] {
+        let name = documents::level_name(&created.uuid, level);
+        let (status, _, body) = get(&app, &format!("/api/v1/level/{}/info", name)).await;
+        assert_eq!(status, StatusCode::OK);
+        let info = json_body(&body);
+        assert_eq!(info["pyramid_uuid"], created.uuid.as_str());
+        assert_eq!(info["level"], level);
+        assert_eq!(info["width"], dims.0);
+        assert_eq!(info["height"], dims.1);
+        assert_eq!(info["mime_type"], "image/png");
+
+        let (status, _, body) = get(&app, info["url"].as_str().unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        let image = decode_png(&body);
+        assert_eq!((image.width(), image.height()), dims);
+    }
+
+    let name = documents::level_name(&created.uuid, created.total_levels());
+    let (status, _, _) = get(&app, &format!("/api/v1/level/{}/info", name)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn identical_uploads_share_a_pyramid() {
+    let (app, db) = db_app().await;
+
+    let mut png = Vec::new();
+    synthetic_image(40, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let upload = |uri: &str| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "image/png")
+            .body(Body::from(png.clone()))
+            .unwrap()
+    };
+
+    let (status, _, body) = send(&app, upload("/api/v1/pyramid")).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    let hash = created.content_sha256.clone().unwrap();
+    assert_eq!(hash.len(), 64);
+    wait_for_tiles(&app, &created.uuid).await;
+
+    let (status, _, body) = send(&app, upload("/api/v1/pyramid")).await;
+    assert_eq!(status, StatusCode::OK);
+    let again: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(again.uuid, created.uuid);
+    assert_eq!(created.filter, Some(ResampleFilter::Gaussian));
+
+    // Asking for the filter a Gaussian pyramid uses anyway builds it the same way
+    let (status, _, body) = send(&app, upload("/api/v1/pyramid?filter=gaussian")).await;
+    assert_eq!(status, StatusCode::OK);
+    let again: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(again.uuid, created.uuid);
+
+    let (status, _, body) = send(&app, upload("/api/v1/pyramid?filter=lanczos3")).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let sharper: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_ne!(sharper.uuid, created.uuid);
+    assert_eq!(sharper.filter, Some(ResampleFilter::Lanczos3));
+    wait_for_tiles(&app, &sharper.uuid).await;
+
+    // Built differently, so it's a pyramid of its own
+    let (status, _, body) = send(&app, upload("/api/v1/pyramid?max_levels=1")).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let other: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_ne!(other.uuid, created.uuid);
+    assert_eq!(other.content_sha256.as_deref(), Some(hash.as_str()));
+    wait_for_tiles(&app, &other.uuid).await;
+
+    let (_, _, body) = get(&app, "/api/v1/pyramids").await;
+    assert_eq!(json_body(&body)["total"], 3);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tile_sets_are_added_alongside_existing_tiles() {
+    let (app, db) = db_app().await;
+
+    let mut png = Vec::new();
+    synthetic_image(600, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?max_levels=2")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    let original = wait_for_tiles(&app, &created.uuid).await.tiles;
+
+    let uri = format!(
+        "/api/v1/pyramid/{}/tiles?tile_width=256&tile_height=16&format=png",
+        created.uuid
+    );
+    let (status, body) = send_json(&app, Method::POST, &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let tile_set: TileSet = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tile_set.key, "256x16_png");
+
+    let pyramid = wait_for_tiles_of(&app, &created.uuid, |p| {
+        p.tile_set("256x16_png").map(|s| &s.tiles)
+    })
+    .await;
+    assert_eq!(pyramid.tiles, original);
+    let levels = pyramid
+        .tile_set("256x16_png")
+        .unwrap()
+        .tiles
+        .levels()
+        .unwrap();
+    assert_eq!(levels.len(), 2);
+    // 600x24 is 3x2 tiles of 256x16
+    assert_eq!(levels[0].tiles.len(), 6);
+    let last = &levels[0].tiles[5];
+    assert_eq!((last.x, last.y, last.width, last.height), (512, 16, 88, 8));
+    let (status, _, body) = get(&app, &format!("/api/v1/image/{}", last.name)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decode_png(&body).width(), 88);
+
+    // The original tiles are still there too
+    let original_tile = &original.levels().unwrap()[0].tiles[0];
+    let (status, _, _) = get(&app, &format!("/api/v1/image/{}", original_tile.name)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send_json(&app, Method::POST, &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let uri = format!("/api/v1/pyramid/{}/tiles?tile_width=0", created.uuid);
+    let (status, _) = send_json(&app, Method::POST, &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/pyramid/no_such_pyramid/tiles",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    drop_test_database(db).await;
+}
+
+/// The parts of a multipart body, as each one's headers and content
+fn multipart_parts(body: &[u8], boundary: &str) -> Vec<(String, Vec<u8>)> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut rest = body;
+    loop {
+        assert!(rest.starts_with(&delimiter));
+        rest = &rest[delimiter.len()..];
+        if rest.starts_with(b"--") {
+            return parts;
+        }
+        let headers_end = rest.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let headers = String::from_utf8(rest[2..headers_end].to_vec()).unwrap();
+        let length: usize = headers
+            .lines()
+            .find_map(|l| l.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let start = headers_end + 4;
+        parts.push((headers, rest[start..start + length].to_vec()));
+        rest = &rest[start + length + 2..];
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tiles_are_fetched_in_batches() {
+    let (app, db) = db_app().await;
+
+    let mut png = Vec::new();
+    synthetic_image(600, 24)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let request = Request::post("/api/v1/pyramid?max_levels=2&compression=gzip")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    let tiles = wait_for_tiles(&app, &created.uuid).await.tiles;
+    let levels = tiles.levels().unwrap();
+
+    let uri = format!("/api/v1/pyramid/{}/tiles/batch", created.uuid);
+    let last_column = levels[0].columns() - 1;
+    let wanted = [
+        (0, last_column, 0),
+        (1, 0, 0),
+        (0, 0, 0),
+        (0, last_column, 0),
+    ];
+    let request = Request::post(&uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!(wanted
+                .iter()
+                .map(|&(level, x, y)| json!({ "level": level, "x": x, "y": y }))
+                .collect::<Vec<_>>())
+            .to_string(),
+        ))
+        .unwrap();
+    let (status, headers, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    let content_type = headers[header::CONTENT_TYPE].to_str().unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/mixed; boundary=")
+        .unwrap();
+
+    // In the order they were asked for, each the same as it's served alone
+    let parts = multipart_parts(&body, boundary);
+    assert_eq!(parts.len(), wanted.len());
+    for ((part_headers, data), &(level, x, y)) in parts.iter().zip(&wanted) {
+        let tile = levels[level as usize].tile_at(x, y).unwrap();
+        let url = format!("/api/v1/image/{}", tile.name);
+        assert!(part_headers.contains(&format!("Content-Location: {}", url)));
+        assert!(part_headers.contains("Content-Type: image/png"));
+        let (status, _, alone) = get(&app, &url).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data.as_slice(), &alone[..]);
+        assert_eq!(decode_png(data).width(), tile.width);
+    }
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        &uri,
+        json!([{ "level": 0, "x": last_column + 1, "y": 0 }]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        &uri,
+        json!([{ "level": 9, "x": 0, "y": 0 }]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let too_many = vec![json!({ "level": 0, "x": 0, "y": 0 }); MAX_BATCH_TILES + 1];
+    let (status, _) = send_json(&app, Method::POST, &uri, json!(too_many)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send_json(&app, Method::POST, &uri, json!({ "level": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn pyramids_are_imported_from_archives() {
+    let (app, db) = db_app().await;
+
+    let files = pyramid_archive_files("offline", &synthetic_image(40, 20), 16);
+    let request = Request::post("/api/v1/pyramid/import")
+        .header(header::CONTENT_TYPE, "application/zip")
+        .body(Body::from(zip_files(&files)))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let imported: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_ne!(imported.uuid, "offline");
+    assert_eq!(imported.original_filename, "offline.png");
+
+    // Tiled already, so there's nothing to wait for
+    let (status, _, body) = get(&app, &format!("/api/v1/pyramid/{}/manifest", imported.uuid)).await;
+    assert_eq!(status, StatusCode::OK);
+    let manifest: PyramidManifest = serde_json::from_slice(&body).unwrap();
+    assert_eq!((manifest.width, manifest.height), (40, 20));
+    assert_eq!(manifest.levels[0].tiles.len(), 6);
+    for tile in &manifest.levels[0].tiles {
+        let (status, _, body) = get(&app, &tile.url).await;
+        assert_eq!(status, StatusCode::OK);
+        let image = decode_png(&body);
+        assert_eq!((image.width(), image.height()), (tile.width, tile.height));
+    }
+    let (status, _, body) = get(&app, &manifest.levels[1].url).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decode_png(&body).width(), 20);
+
+    // Nothing is stored from an archive that doesn't match its manifest
+    let (status, _, body) = get(&app, "/api/v1/pyramids").await;
+    assert_eq!(status, StatusCode::OK);
+    let before = json_body(&body)["total"].clone();
+    let mut files = files;
+    files.retain(|(path, _)| !path.starts_with("tiles/offline_L0_T5."));
+    let request = Request::post("/api/v1/pyramid/import")
+        .header(header::CONTENT_TYPE, "application/zip")
+        .body(Body::from(zip_files(&files)))
+        .unwrap();
+    let (status, _, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, _, body) = get(&app, "/api/v1/pyramids").await;
+    assert_eq!(json_body(&body)["total"], before);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn stored_matrix_convolves_stored_image() {
+    let (app, db) = db_app().await;
+
+    let source = synthetic_image(16, 8);
+    upload_image(&app, "source", &source).await;
+
+    let identity = json!([[0, 0, 0], [0, 1, 0], [0, 0, 0]]);
+    send_json(&app, Method::POST, "/api/v1/matrix/identity", identity).await;
+    send_json(
+        &app,
+        Method::POST,
+        "/api/v1/matrix/even",
+        json!([[1, 0], [0, 1]]),
+    )
+    .await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/convolve/identity",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let stored = json_body(&body);
+    assert_eq!(stored["name"], "source_identity");
+    assert_eq!(stored["url"], "/api/v1/image/source_identity");
+
+    let (status, _, body) = get(&app, "/api/v1/image/source_identity").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(compare_images(&source, &decode_png(&body))
+        .unwrap()
+        .within(Tolerance::EXACT));
+
+    // Convolving again would overwrite the result
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/convolve/identity",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/convolve/even",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Normalizing convolves the image's luminance, and stores it as grayscale
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/convolve/identity?normalize=clamp&output=source_luma",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)["name"], "source_luma");
+    let (status, _, body) = get(&app, "/api/v1/image/source_luma").await;
+    assert_eq!(status, StatusCode::OK);
+    let luma = decode_png(&body);
+    assert_eq!(luma.color(), image::ColorType::L8);
+    assert!(compare_images(&source.grayscale(), &luma)
+        .unwrap()
+        .within(Tolerance::PERCEPTUAL));
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/convolve/identity?normalize=bogus&output=source_bogus",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn images_are_transcoded_at_the_requested_quality() {
+    let (app, db) = db_app().await;
+
+    upload_image(&app, "source", &synthetic_image(64, 64)).await;
+
+    let (status, low_headers, low) = get(&app, "/api/v1/image/source.jpg?quality=5").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(low_headers[header::CONTENT_TYPE], "image/jpeg");
+    let request = Request::get("/api/v1/image/source.jpg")
+        .header("X-Image-Quality", "95")
+        .body(Body::empty())
+        .unwrap();
+    let (status, high_headers, high) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(low.len() < high.len());
+    assert_ne!(low_headers[header::ETAG], high_headers[header::ETAG]);
+
+    // Lossless formats ignore quality
+    let (_, _, plain) = get(&app, "/api/v1/image/source").await;
+    let (status, _, with_quality) = get(&app, "/api/v1/image/source?quality=5").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(plain, with_quality);
+
+    for uri in [
+        "/api/v1/image/source.jpg?quality=0",
+        "/api/v1/image/source.jpg?quality=101",
+        "/api/v1/image/source.jpg?quality=best",
+    ] {
+        let (status, _, _) = get(&app, uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn byte_ranges_of_images_are_served() {
+    let (app, db) = db_app().await;
+
+    // Noise barely compresses, so this spans a few of GridFS's 255 KiB chunks
+    let mut seed = 1u32;
+    let noise = DynamicImage::ImageRgb8(image::RgbImage::from_fn(400, 300, |_, _| {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let [r, g, b, _] = seed.to_le_bytes();
+        image::Rgb([r, g, b])
+    }));
+    let png = upload_image(&app, "noise", &noise).await;
+    let chunk_size = 255 * 1024;
+    assert!(png.len() > 2 * chunk_size);
+
+    let (status, headers, _) = get(&app, "/api/v1/image/noise").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::ACCEPT_RANGES], "bytes");
+    let etag = headers[header::ETAG].to_str().unwrap().to_string();
+
+    async fn get_range(
+        app: &Router,
+        range: &str,
+        if_range: Option<&str>,
+    ) -> (StatusCode, HeaderMap, Bytes) {
+        let mut request = Request::get("/api/v1/image/noise").header(header::RANGE, range);
+        if let Some(if_range) = if_range {
+            request = request.header(header::IF_RANGE, if_range);
+        }
+        send(app, request.body(Body::empty()).unwrap()).await
+    }
+    let total = png.len();
+    for (range, start, end) in [
+        ("bytes=0-99".to_string(), 0, 99),
+        // Across a chunk boundary, into the next chunk but one
+        (
+            format!("bytes={}-{}", chunk_size - 10, 2 * chunk_size + 10),
+            chunk_size - 10,
+            2 * chunk_size + 10,
+        ),
+        (format!("bytes={}-", total - 5), total - 5, total - 1),
+        ("bytes=-5".to_string(), total - 5, total - 1),
+    ] {
+        let (status, headers, body) = get_range(&app, &range, Some(&etag)).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT, "{}", range);
+        assert_eq!(
+            headers[header::CONTENT_RANGE],
+            format!("bytes {}-{}/{}", start, end, total).as_str()
+        );
+        assert_eq!(&body[..], &png[start..=end], "{}", range);
+    }
+
+    let (status, headers, _) = get_range(&app, &format!("bytes={}-", total), None).await;
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        headers[header::CONTENT_RANGE],
+        format!("bytes */{}", total).as_str()
+    );
+
+    // A stale If-Range, or a transcode, gets the whole image instead
+    let (status, _, body) = get_range(&app, "bytes=0-99", Some("\"stale.png\"")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.len(), total);
+    let request = Request::get("/api/v1/image/noise.jpg")
+        .header(header::RANGE, "bytes=0-99")
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "image/jpeg");
+    assert!(headers.get(header::ACCEPT_RANGES).is_none());
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn images_are_resized_and_rotated_into_new_images() {
+    let (app, db) = db_app().await;
+
+    upload_image(&app, "source", &synthetic_image(40, 24)).await;
+
+    // Giving only the width keeps the aspect ratio
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/resize?w=20&filter=lanczos3",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)["name"], "source_resized");
+    let (_, _, body) = get(&app, "/api/v1/image/source_resized").await;
+    let resized = decode_png(&body);
+    assert_eq!((resized.width(), resized.height()), (20, 12));
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/rotate?deg=90&output=turned",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, _, body) = get(&app, "/api/v1/image/turned").await;
+    let rotated = decode_png(&body);
+    assert_eq!((rotated.width(), rotated.height()), (24, 40));
+
+    for uri in [
+        "/api/v1/image/source/resize",
+        "/api/v1/image/source/rotate?deg=45",
+    ] {
+        let (status, _) = send_json(&app, Method::POST, uri, json!(null)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn test_patterns_are_generated_as_images() {
+    let (app, db) = db_app().await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/generate?pattern=checkerboard&width=40&height=24&cell_size=8",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)["name"], "checkerboard_40x24");
+    let (_, _, body) = get(&app, "/api/v1/image/checkerboard_40x24").await;
+    assert_eq!(decode_png(&body), testgen::checkerboard(40, 24, 8).unwrap());
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/generate?pattern=noise&seed=3&output=static",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, _, body) = get(&app, "/api/v1/image/static").await;
+    assert_eq!(decode_png(&body), testgen::noise(256, 256, 3).unwrap());
+
+    for (uri, expected) in [
+        (
+            "/api/v1/image/generate?pattern=noise&output=static",
+            StatusCode::CONFLICT,
+        ),
+        (
+            "/api/v1/image/generate?pattern=plaid",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "/api/v1/image/generate?pattern=gradient&width=0",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "/api/v1/image/generate?pattern=siemens_star&spokes=0",
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let (status, _) = send_json(&app, Method::POST, uri, json!(null)).await;
+        assert_eq!(status, expected, "{}", uri);
+    }
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn channels_are_split_and_merged() {
+    let (app, db) = db_app().await;
+
+    let source = synthetic_image(40, 24);
+    upload_image(&app, "source", &source).await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/grayscale",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)["name"], "source_gray");
+    let (_, _, body) = get(&app, "/api/v1/image/source_gray").await;
+    assert_eq!(decode_png(&body).color(), image::ColorType::L8);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/split",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let names: Vec<_> = json_body(&body)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stored| stored["name"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(names, ["source_c0", "source_c1", "source_c2"]);
+    let (_, _, body) = get(&app, "/api/v1/image/source_c1").await;
+    let green = decode_png(&body);
+    assert_eq!(green.color(), image::ColorType::L8);
+    assert_eq!(green.to_luma8().get_pixel(3, 5).0, [50]);
+
+    // Splitting again would overwrite the channels
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/split",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Swap red and blue
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/images/merge?channels=source_c2,source_c1,source_c0&output=swapped",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, _, body) = get(&app, "/api/v1/image/swapped").await;
+    let swapped = decode_png(&body).to_rgb8();
+    let original = source.to_rgb8().get_pixel(3, 5).0;
+    assert_eq!(
+        swapped.get_pixel(3, 5).0,
+        [original[2], original[1], original[0]]
+    );
+
+    for (uri, expected) in [
+        (
+            "/api/v1/images/merge?channels=source_c0",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "/api/v1/images/merge?channels=source_c0,source&output=bad",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            "/api/v1/images/merge?channels=source_c0,missing&output=bad",
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        let (status, _) = send_json(&app, Method::POST, uri, json!(null)).await;
+        assert_eq!(status, expected, "{}", uri);
+    }
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn images_are_thresholded() {
+    let (app, db) = db_app().await;
+
+    // Dark on the left, light on the right
+    let source = DynamicImage::ImageLuma8(image::GrayImage::from_fn(16, 8, |x, _| {
+        image::Luma([if x < 8 { 30 } else { 220 }])
+    }));
+    upload_image(&app, "source", &source).await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/threshold?method=otsu",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let result = json_body(&body);
+    assert_eq!(result["name"], "source_binary");
+    let threshold = result["threshold"].as_u64().unwrap();
+    assert!((30..220).contains(&threshold));
+    let (_, _, body) = get(&app, "/api/v1/image/source_binary").await;
+    let binary = decode_png(&body).to_luma8();
+    assert_eq!(binary.get_pixel(0, 0).0, [0]);
+    assert_eq!(binary.get_pixel(15, 7).0, [255]);
+
+    // Everything is above a low enough fixed threshold
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/threshold?method=fixed&value=10&output=white",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)["threshold"], 10);
+    let (_, _, body) = get(&app, "/api/v1/image/white").await;
+    assert!(decode_png(&body).to_luma8().pixels().all(|p| p.0 == [255]));
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/threshold?method=median",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn edges_are_detected() {
+    let (app, db) = db_app().await;
+
+    // Black at the top, white at the bottom
+    let source = DynamicImage::ImageLuma8(image::GrayImage::from_fn(8, 8, |_, y| {
+        image::Luma([if y < 4 { 0 } else { 255 }])
+    }));
+    upload_image(&app, "source", &source).await;
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/edges",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json_body(&body)[0]["name"], "source_edges");
+    let (_, _, body) = get(&app, "/api/v1/image/source_edges").await;
+    let edges = decode_png(&body).to_luma8();
+    assert_eq!(edges.get_pixel(2, 0).0, [0]);
+    assert_eq!(edges.get_pixel(2, 4).0, [255]);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/edges?operator=scharr&mode=gradients&output=grad",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let stored = json_body(&body);
+    assert_eq!(stored[0]["name"], "grad_dx");
+    assert_eq!(stored[1]["name"], "grad_dy");
+    let (_, _, body) = get(&app, "/api/v1/image/grad_dx").await;
+    assert!(decode_png(&body).to_luma8().pixels().all(|p| p.0 == [128]));
+    let (_, _, body) = get(&app, "/api/v1/image/grad_dy").await;
+    assert_eq!(decode_png(&body).to_luma8().get_pixel(2, 3).0, [255]);
+
+    // Neither gradient is stored if either name is taken
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/source/edges?mode=gradients&output=grad",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn collection_prefixes_keep_instances_apart() {
+    let db = test_database().await;
+    let apps = ["a_", "b_"].map(|prefix| {
+        let mut state = RuntimeData::new();
+        state.db = Some(Db::new(db.inner(), prefix));
+        test_app(state)
+    });
+
+    for app in &apps {
+        upload_image(app, "source", &synthetic_image(8, 8)).await;
+    }
+    let (status, _) = send_json(
+        &apps[0],
+        Method::POST,
+        "/api/v1/image/source/rotate?deg=90&output=turned",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _, _) = get(&apps[0], "/api/v1/image/turned").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = get(&apps[1], "/api/v1/image/turned").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Nothing lands outside the prefixed collections
+    let collections = db.inner().list_collection_names(None).await.unwrap();
+    assert!(collections
+        .iter()
+        .all(|c| c.starts_with("a_") || c.starts_with("b_")));
+    for collection in ["a_images", "a_fs.files", "b_images", "b_fs.files"] {
+        assert!(
+            collections.iter().any(|c| c == collection),
+            "{}",
+            collection
+        );
+    }
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn image_info_describes_stored_images() {
+    let (app, db) = db_app().await;
+
+    let size = upload_image(&app, "described", &synthetic_image(40, 24))
+        .await
+        .len();
+
+    let (status, _, body) = get(&app, "/api/v1/image/described/info").await;
+    assert_eq!(status, StatusCode::OK);
+    let info = json_body(&body);
+    assert_eq!(info["name"], "described");
+    assert_eq!(
+        (info["width"].as_u64(), info["height"].as_u64()),
+        (Some(40), Some(24))
+    );
+    assert_eq!(info["channels"], 3);
+    assert_eq!(info["bit_depth"], 8);
+    assert_eq!(info["mime_type"], "image/png");
+    assert_eq!(info["size"], size);
+    assert!(info["uploaded"].is_string());
+    assert!(info["exif_orientation"].is_null());
+
+    let (status, _, _) = get(&app, "/api/v1/image/missing/info").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uploads_are_turned_upright() {
+    let (app, db) = db_app().await;
+
+    // Orientation 6 means the camera was turned a quarter clockwise
+    let jpeg = jpeg_with_orientation(&synthetic_image(40, 24), 6);
+    for (name, query, dims) in [
+        ("turned", "", (24, 40)),
+        ("as_taken", "?auto_orient=false", (40, 24)),
+    ] {
+        let request = Request::post(format!("/api/v1/image{}", query))
+            .header(header::CONTENT_TYPE, "image/jpeg")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename={}", name),
+            )
+            .body(Body::from(jpeg.clone()))
+            .unwrap();
+        let (status, _, _) = send(&app, request).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (_, _, body) = get(&app, &format!("/api/v1/image/{}/info", name)).await;
+        let info = json_body(&body);
+        assert_eq!(
+            (info["width"].as_u64(), info["height"].as_u64()),
+            (Some(dims.0), Some(dims.1))
+        );
+    }
+
+    let (_, _, body) = get(&app, "/api/v1/images").await;
+    let docs = json_body(&body);
+    let doc_of = |name: &str| {
+        docs.as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["name"] == name)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(doc_of("turned")["exif_orientation"], 6);
+    assert_eq!(doc_of("turned")["reoriented"], true);
+    assert_eq!(doc_of("as_taken")["reoriented"], false);
+
+    // Pyramids are built from the upright image
+    let request = Request::post("/api/v1/pyramid?max_levels=1")
+        .header(header::CONTENT_TYPE, "image/jpeg")
+        .body(Body::from(jpeg))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    let pyramid = wait_for_tiles(&app, &created.uuid).await;
+    let levels = pyramid.tiles.levels().unwrap();
+    assert_eq!((levels[0].width, levels[0].height), (24, 40));
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn images_are_compared() {
+    let (app, db) = db_app().await;
+
+    upload_image(&app, "source", &synthetic_image(32, 32)).await;
+    for uri in [
+        "/api/v1/image/source/rotate?deg=180&output=flipped",
+        "/api/v1/image/source/resize?w=16&output=small",
+    ] {
+        let (status, _) = send_json(&app, Method::POST, uri, json!(null)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/compare/source/source?ssim=true",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let same = json_body(&body);
+    assert_eq!(same["mse"], 0.0);
+    assert_eq!(same["psnr"], Value::Null);
+    assert_eq!(same["ssim"], 1.0);
+    assert_eq!(same["difference"], Value::Null);
+
+    let (status, body) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/compare/source/flipped?diff=delta",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let flipped = json_body(&body);
+    assert!(flipped["mse"].as_f64().unwrap() > 0.0);
+    assert!(flipped["psnr"].as_f64().is_some());
+    assert_eq!(flipped["ssim"], Value::Null);
+    assert_eq!(flipped["difference"]["url"], "/api/v1/image/delta");
+    let (status, _, body) = get(&app, "/api/v1/image/delta").await;
+    assert_eq!(status, StatusCode::OK);
+    let delta = decode_png(&body);
+    assert_eq!((delta.width(), delta.height()), (32, 32));
+
+    let (status, _) = send_json(
+        &app,
+        Method::POST,
+        "/api/v1/image/compare/source/small",
+        json!(null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn pyramid_listing_is_paginated_and_filtered() {
+    let (app, db) = db_app().await;
+
+    // Five copies of the fixture pyramid: two that failed to tile, and three that were tiled,
+    // alternately built from two different images
+    let template = fixture_documents("pyramids").unwrap().remove(0);
+    let docs = (0..5).map(|i| {
+        let mut doc = template.clone();
+        doc.insert("uuid", format!("p{}", i));
+        doc.insert("original_filename", ["even.png", "odd.png"][i % 2]);
+        match i < 2 {
+            true => doc.insert("tiles", "failed"),
+            false => doc.insert("tiles", mongodb::bson::Bson::Array(vec![])),
+        };
+        doc
+    });
+    db.collection::<mongodb::bson::Document>("pyramids")
+        .insert_many(docs, None)
+        .await
+        .unwrap();
+
+    let (status, _, body) = get(&app, "/api/v1/pyramids?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.total, 5);
+    assert_eq!(page.next_offset, Some(2));
+    let uuids: Vec<_> = page.pyramids.iter().map(|p| p.uuid.as_str()).collect();
+    assert_eq!(uuids, ["p0", "p1"]);
+
+    let (_, _, body) = get(&app, "/api/v1/pyramids?limit=2&offset=4").await;
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.pyramids.len(), 1);
+    assert_eq!(page.next_offset, None);
+
+    let (_, _, body) = get(&app, "/api/v1/pyramids?status=failed").await;
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.total, 2);
+
+    let (_, _, body) = get(
+        &app,
+        "/api/v1/pyramids?status=done&original_filename=even.png",
+    )
+    .await;
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    let uuids: Vec<_> = page.pyramids.iter().map(|p| p.uuid.as_str()).collect();
+    assert_eq!(uuids, ["p2", "p4"]);
+
+    let (status, _, _) = get(&app, "/api/v1/pyramids?status=tiled").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    drop_test_database(db).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn images_and_pyramids_are_found_by_tag_and_name() {
+    let (app, db) = db_app().await;
+
+    let mut png = Vec::new();
+    synthetic_image(8, 8)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    for (name, query) in [
+        ("Holiday_1", "?tags=beach,%20sun,beach"),
+        ("holiday_2", ""),
+        ("work.1", "?tags=sun"),
+    ] {
+        let request = Request::post(format!("/api/v1/image{}", query))
+            .header(header::CONTENT_TYPE, "image/png")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename={}", name),
+            )
+            .body(Body::from(png.clone()))
+            .unwrap();
+        let (status, _, _) = send(&app, request).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let names = |body: &Bytes| {
+        let mut names = json_body(body)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+
+    let (_, _, body) = get(&app, "/api/v1/images?tag=sun").await;
+    assert_eq!(names(&body), ["Holiday_1", "work.1"]);
+    let (_, _, body) = get(&app, "/api/v1/images?q=HOLIDAY").await;
+    assert_eq!(names(&body), ["Holiday_1", "holiday_2"]);
+    // Searches are literal, so the dot only matches a dot
+    let (_, _, body) = get(&app, "/api/v1/images?q=k.").await;
+    assert_eq!(names(&body), ["work.1"]);
+
+    let (status, body) = send_json(
+        &app,
+        Method::PUT,
+        "/api/v1/image/holiday_2/tags",
+        json!(["sun", " snow "]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json_body(&body), json!(["sun", "snow"]));
+    let (_, _, body) = get(&app, "/api/v1/images?tag=sun&q=holiday").await;
+    assert_eq!(names(&body), ["Holiday_1", "holiday_2"]);
+
+    let (status, _) = send_json(&app, Method::PUT, "/api/v1/image/nope/tags", json!([])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send_json(
+        &app,
+        Method::PUT,
+        "/api/v1/image/holiday_2/tags",
+        json!(["a,b"]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Replacing an image's data keeps its tags
+    let request = Request::put("/api/v1/image/holiday_2")
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(png.clone()))
+        .unwrap();
+    let (status, _, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, _, body) = get(&app, "/api/v1/images?tag=snow").await;
+    assert_eq!(names(&body), ["holiday_2"]);
+
+    let request = Request::post("/api/v1/pyramid?max_levels=1&tags=beach")
+        .header(header::CONTENT_TYPE, "image/png")
+        .header("Content-Disposition", "attachment; filename=Beach.png")
+        .body(Body::from(png))
+        .unwrap();
+    let (status, _, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let created: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(created.tags, ["beach"]);
+
+    let (_, _, body) = get(&app, "/api/v1/pyramids?tag=beach&q=beach").await;
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.total, 1);
+    let (_, _, body) = get(&app, "/api/v1/pyramids?q=beach&original_filename=Other.png").await;
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.total, 0);
+
+    let (status, body) = send_json(
+        &app,
+        Method::PUT,
+        &format!("/api/v1/pyramid/{}/tags", created.uuid),
+        json!(["dunes"]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let updated: Pyramid = serde_json::from_slice(&body).unwrap();
+    assert_eq!(updated.tags, ["dunes"]);
+    let (_, _, body) = get(&app, "/api/v1/pyramids?tag=beach").await;
+    let page: PyramidPage = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.total, 0);
+
+    wait_for_tiles(&app, &created.uuid).await;
+    drop_test_database(db).await;
+}
+
+#[tokio::test]
+async fn admin_routes_need_the_admin_token() {
+    let reload = |authorization: Option<&str>| {
+        let mut request = Request::post("/api/v1/admin/reload");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    let app = test_app(RuntimeData::new());
+    for authorization in [None, Some("Bearer wrong")] {
+        let (status, headers, body) = send(&app, reload(authorization)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(headers[header::WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(json_body(&body)["error"], "unauthorized");
+    }
+
+    // With no config file to reload from, there's nothing to reload
+    let authorization = format!("Bearer {}", ADMIN_TOKEN);
+    let (status, _, body) = send(&app, reload(Some(&authorization))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json_body(&body)["error"], "unprocessable");
+
+    // Without a token, there are no admin routes
+    let app = test_app_with_admin_token(RuntimeData::new(), None);
+    let (status, _, _) = send(&app, reload(Some(&authorization))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -1,17 +1,14 @@
 use axum::{
     async_trait,
+    body::Bytes,
     extract::{FromRequest, Request},
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 
-use jnickg_imaging::{
-    dims::{Cols, Dims, Rows},
-    dyn_matrix::DynMatrix,
-    element::Element,
-};
+use jnickg_imaging::{dyn_matrix::DynMatrix, element::Element, errors::Error, serde::BinaryFormat};
 
 use crate::wrappers::*;
 
@@ -36,19 +33,99 @@ where
     S: Send + Sync,
     T: DeserializeOwned,
 {
-    type Rejection = ();
+    type Rejection = WrappedError;
 
+    /// Reads JSON, or a [`BinaryFormat`] named by the request's `Content-Type`. Fails with a
+    /// validation error saying what's wrong with the matrix, e.g. that its rows differ in length
     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        let Json(matrix) = Json::<DynMatrix<T>>::from_request(req, state)
-            .await
-            .map_err(|_| ())?;
+        let binary = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(BinaryFormat::from_mime_type);
+        let matrix = match binary {
+            Some(format) => {
+                let bytes = Bytes::from_request(req, state)
+                    .await
+                    .map_err(|e| WrappedError(Error::validation(e.body_text())))?;
+                format
+                    .from_slice::<DynMatrix<T>>(&bytes)
+                    .map_err(WrappedError)?
+            }
+            None => {
+                let Json(matrix) = Json::<DynMatrix<T>>::from_request(req, state)
+                    .await
+                    .map_err(|e| WrappedError(Error::validation(e.body_text())))?;
+                matrix
+            }
+        };
         Ok(Self(matrix))
     }
 }
 
 impl IntoResponse for WrappedDims {
     fn into_response(self) -> Response {
-        let WrappedDims(Dims(Rows(r), Cols(c))) = self;
-        (StatusCode::OK, Json(&(r, c))).into_response()
+        let WrappedDims(dims) = self;
+        (StatusCode::OK, Json(<(usize, usize)>::from(dims))).into_response()
+    }
+}
+
+/// The JSON body of every error response
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    /// The HTTP status code, repeated for clients that only see the body
+    status: u16,
+
+    /// What kind of error this is. See [`Error::kind`]
+    error: &'static str,
+
+    /// What went wrong, for humans
+    message: String,
+}
+
+impl IntoResponse for WrappedError {
+    fn into_response(self) -> Response {
+        let WrappedError(e) = self;
+        let status = match &e {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::UnsupportedFormat(_) => StatusCode::NOT_ACCEPTABLE,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::ImageDecode(_)
+            | Error::ImageEncode(_)
+            | Error::Database(_)
+            | Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            tracing::error!("{}", e);
+        }
+        let body = ErrorBody {
+            status: status.as_u16(),
+            error: e.kind(),
+            message: e.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<Error> for WrappedError {
+    fn from(e: Error) -> Self {
+        Self(e)
+    }
+}
+
+impl From<mongodb::error::Error> for WrappedError {
+    fn from(e: mongodb::error::Error) -> Self {
+        Self(Error::database(e.to_string()))
+    }
+}
+
+impl From<std::io::Error> for WrappedError {
+    fn from(e: std::io::Error) -> Self {
+        Self(Error::from(e))
     }
 }
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use clap::{arg, Parser};
+use tokio::sync::RwLock;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+
+use jnickg_tile_server::{
+    connect_to_database, database::Db, jobs, tunables::TunablesStore, web_appstate::RuntimeData,
+};
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    long_about = "Generates pyramid tiles for the tile server. Claims tiling jobs queued in the database by the server and runs them, without serving anything over HTTP. Run as many as the tiling load needs, alongside servers started with --tile-workers 0"
+)]
+struct Args {
+    /// Hostname of the MongoDB server
+    #[arg(long, value_name = "STR")]
+    host: String,
+
+    /// Username with which to log into the MongoDB
+    #[arg(long, value_name = "STR")]
+    user: String,
+
+    /// The path to a file containing the password for MongoDB
+    #[arg(long, value_name = "PATH")]
+    pass: String,
+
+    /// The port through which to access MongoDB
+    #[arg(long = "db-port", value_name = "NUM")]
+    db_port: u16,
+
+    /// Name of the MongoDB database to use
+    #[arg(long = "db-name", value_name = "STR", env = "TILER_DB_NAME", default_value = Db::DEFAULT_NAME)]
+    db_name: String,
+
+    /// Prefix for the name of every collection (and GridFS bucket) we use, so that several
+    /// instances can share one database
+    #[arg(
+        long = "collection-prefix",
+        value_name = "STR",
+        env = "TILER_COLLECTION_PREFIX",
+        default_value = ""
+    )]
+    collection_prefix: String,
+
+    /// How many times to try reaching MongoDB when starting, backing off between attempts
+    #[arg(long = "db-connect-attempts", value_name = "NUM", default_value_t = Db::DEFAULT_CONNECT_ATTEMPTS)]
+    db_connect_attempts: u32,
+
+    /// Path to a JSON file of runtime-tunable settings. Re-read on SIGHUP
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+
+    /// Workers to run in this process, each working on one job at a time
+    #[arg(long, value_name = "NUM", default_value_t = jobs::DEFAULT_WORKERS)]
+    workers: usize,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    let mut state: RuntimeData = RuntimeData::new();
+
+    let tunables = match TunablesStore::new(args.config.as_ref().map(Into::into)) {
+        Ok(t) => t,
+        Err(_e) => {
+            eprintln!("Error: {}", _e);
+            return;
+        }
+    };
+    let (log_filter_layer, log_filter_handle) =
+        reload::Layer::new(EnvFilter::new(&tunables.load().log_filter));
+    tracing_subscriber::registry()
+        .with(log_filter_layer)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    state.tunables = Arc::new(tunables.with_log_filter(log_filter_handle));
+
+    #[cfg(unix)]
+    {
+        let tunables = state.tunables.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangups = signal(SignalKind::hangup()).unwrap();
+            while hangups.recv().await.is_some() {
+                if let Err(_e) = tunables.reload() {
+                    tracing::error!("Failed to reload tunables: {}", _e);
+                }
+            }
+        });
+    }
+
+    let database = match connect_to_database(
+        &args.host,
+        args.db_port,
+        &args.user,
+        &args.pass,
+        &args.db_name,
+        &args.collection_prefix,
+        args.db_connect_attempts,
+    )
+    .await
+    {
+        Ok(db) => db,
+        Err(_e) => {
+            eprintln!("Error: {}", _e);
+            return;
+        }
+    };
+    // Health checks notice if the connection goes bad, so it's replaced when next used
+    let _db_watch = database.watch(Db::DEFAULT_WATCH_INTERVAL);
+    state.db = Some(database);
+
+    // Nothing here queues jobs, so workers only find them by polling
+    let _workers = jobs::start_workers(Arc::new(RwLock::new(state)), args.workers);
+
+    // A job interrupted here is picked up again once its lease runs out
+    if let Err(_e) = tokio::signal::ctrl_c().await {
+        eprintln!("Error: {}", _e);
+    }
+    println!("Shutting down");
+}
@@ -0,0 +1,155 @@
+//! Parsing `Range` and `If-Range` headers, so large images can be fetched in pieces, or their
+//! downloads resumed.
+//!
+//! Follows RFC 9110, for the `bytes` unit only. We send at most one range per response, so a
+//! header asking for several is answered with the whole representation, as are headers we can't
+//! parse. Both are allowed: a server may always ignore `Range`.
+
+/// Bytes `start` through `end` (inclusive) of a representation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Number of bytes in the range. Never zero
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Ranges always hold at least one byte
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The `Content-Range` header for this range of a representation `total` bytes long
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// What a `Range` header asks us to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// The whole representation, with `200 OK`
+    Full,
+
+    /// Just this range, with `206 Partial Content`
+    Partial(ByteRange),
+
+    /// Nothing: the range starts past the end. Answered with `416 Range Not Satisfiable`
+    Unsatisfiable,
+}
+
+/// The `Content-Range` header for a `416` response, for a representation `total` bytes long
+pub fn unsatisfied_range(total: u64) -> String {
+    format!("bytes */{}", total)
+}
+
+/// What to send for the `Range` header `header`, of a representation `total` bytes long
+pub fn parse_range(header: &str, total: u64) -> RangeRequest {
+    let Some((unit, spec)) = header.trim().split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") || spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let parse = |s: &str| s.parse::<u64>().ok();
+
+    // `-n`: the last n bytes
+    if first.is_empty() {
+        return match parse(last) {
+            Some(suffix) if suffix > 0 && total > 0 => RangeRequest::Partial(ByteRange {
+                start: total.saturating_sub(suffix),
+                end: total - 1,
+            }),
+            Some(_) => RangeRequest::Unsatisfiable,
+            None => RangeRequest::Full,
+        };
+    }
+
+    // `a-b` and `a-`: from a to b, or to the end. Ends past the end are cut short
+    let Some(start) = parse(first) else {
+        return RangeRequest::Full;
+    };
+    let end = match last.is_empty() {
+        true => u64::MAX,
+        false => match parse(last) {
+            Some(end) if end >= start => end,
+            _ => return RangeRequest::Full,
+        },
+    };
+    match start < total {
+        true => RangeRequest::Partial(ByteRange {
+            start,
+            end: end.min(total - 1),
+        }),
+        false => RangeRequest::Unsatisfiable,
+    }
+}
+
+/// Whether an `If-Range` header lets a `Range` header through, for a representation with the
+/// given ETag. Only strong comparison counts: weak ETags, and dates (which we don't send), never
+/// match, so the whole representation is sent instead
+pub fn if_range_matches(header: &str, etag: &str) -> bool {
+    let header = header.trim();
+    !header.starts_with("W/") && header == etag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn partial(start: u64, end: u64) -> RangeRequest {
+        RangeRequest::Partial(ByteRange { start, end })
+    }
+
+    #[test_case("bytes=0-99", partial(0, 99))]
+    #[test_case("bytes=100-", partial(100, 999))]
+    #[test_case("bytes=-100", partial(900, 999))]
+    #[test_case("bytes=990-2000", partial(990, 999))]
+    #[test_case("bytes=-5000", partial(0, 999))]
+    #[test_case("Bytes = 5-5", partial(5, 5))]
+    #[test_case("bytes=1000-", RangeRequest::Unsatisfiable)]
+    #[test_case("bytes=1000-1001", RangeRequest::Unsatisfiable)]
+    #[test_case("bytes=-0", RangeRequest::Unsatisfiable)]
+    #[test_case("bytes=0-9,20-29", RangeRequest::Full)]
+    #[test_case("bytes=9-0", RangeRequest::Full)]
+    #[test_case("bytes=a-b", RangeRequest::Full)]
+    #[test_case("bytes=5-x", RangeRequest::Full)]
+    #[test_case("bytes=-", RangeRequest::Full)]
+    #[test_case("items=0-9", RangeRequest::Full)]
+    #[test_case("0-9", RangeRequest::Full)]
+    fn parses_ranges(header: &str, expected: RangeRequest) {
+        assert_eq!(parse_range(header, 1000), expected);
+    }
+
+    #[test]
+    fn nothing_is_in_range_of_nothing() {
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn ranges_describe_themselves() {
+        let range = ByteRange { start: 10, end: 19 };
+        assert_eq!(range.len(), 10);
+        assert_eq!(range.content_range(1000), "bytes 10-19/1000");
+        assert_eq!(unsatisfied_range(1000), "bytes */1000");
+    }
+
+    #[test]
+    fn if_range_needs_a_strong_match() {
+        let etag = "\"abc.png\"";
+        assert!(if_range_matches("\"abc.png\"", etag));
+        assert!(!if_range_matches("W/\"abc.png\"", etag));
+        assert!(!if_range_matches("\"abc.jpg\"", etag));
+        assert!(!if_range_matches("Wed, 21 Oct 2015 07:28:00 GMT", etag));
+    }
+}
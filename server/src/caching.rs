@@ -0,0 +1,120 @@
+//! Validators and cache policy for image responses.
+//!
+//! Image data in GridFS is never modified in place: replacing an image uploads a new file, with a
+//! new ID. So a file's ID, plus the format and content coding we send it in, makes a strong ETag
+//! without hashing any image data.
+
+use axum::http::HeaderValue;
+use image::ImageFormat;
+use jnickg_imaging::compression::Compression;
+use mongodb::bson::Bson;
+
+/// `Cache-Control` values sent with image responses. Set from the command line
+#[derive(Debug, Clone)]
+pub struct CacheControl {
+    /// For images, which may be replaced by a `PUT`
+    pub images: HeaderValue,
+
+    /// For pyramid tiles, which keep their names when a pyramid is retiled
+    pub tiles: HeaderValue,
+}
+
+impl CacheControl {
+    /// Always revalidate images; that's cheap, thanks to ETags
+    pub const DEFAULT_IMAGES: &'static str = "no-cache";
+
+    /// Tiles are regenerated under the same names when a pyramid is retiled, so they can't be
+    /// cached for good. Shared caches may keep them, but must revalidate, which new ETags make
+    /// them notice
+    pub const DEFAULT_TILES: &'static str = "public, no-cache";
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        Self {
+            images: HeaderValue::from_static(Self::DEFAULT_IMAGES),
+            tiles: HeaderValue::from_static(Self::DEFAULT_TILES),
+        }
+    }
+}
+
+/// The ETag for the GridFS file with the given ID, when sent as `format` (re-encoded at
+/// `quality`, if given), and compressed with `encoding` (if at all)
+pub fn image_etag(
+    file_id: &Bson,
+    format: ImageFormat,
+    quality: Option<u8>,
+    encoding: Option<Compression>,
+) -> String {
+    let id = match file_id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        other => other.to_string().replace('"', ""),
+    };
+    let ext = format.extensions_str().first().copied().unwrap_or("bin");
+    let mut etag = format!("\"{}", id);
+    if let Some(q) = quality {
+        etag.push_str(&format!(".q{}", q));
+    }
+    etag.push_str(&format!(".{}", ext));
+    if let Some(c) = encoding {
+        etag.push_str(&format!(".{}", c.content_encoding()));
+    }
+    etag.push('"');
+    etag
+}
+
+/// Whether an `If-None-Match` header matches `etag`, meaning the client's copy is current.
+///
+/// As RFC 9110 asks, this uses weak comparison: `W/` prefixes are ignored.
+pub fn if_none_match(header: &str, etag: &str) -> bool {
+    let strip_weak = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    let etag = strip_weak(etag);
+    header
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || strip_weak(candidate) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn etag_depends_on_file_format_quality_and_encoding() {
+        let a = Bson::ObjectId(ObjectId::new());
+        let b = Bson::ObjectId(ObjectId::new());
+        let png = image_etag(&a, ImageFormat::Png, None, None);
+        assert!(png.starts_with('"') && png.ends_with('"'));
+        assert_eq!(png, image_etag(&a, ImageFormat::Png, None, None));
+        assert_ne!(png, image_etag(&a, ImageFormat::Jpeg, None, None));
+        assert_ne!(png, image_etag(&b, ImageFormat::Png, None, None));
+        assert_ne!(
+            png,
+            image_etag(&a, ImageFormat::Png, None, Some(Compression::Brotli))
+        );
+
+        let jpeg = image_etag(&a, ImageFormat::Jpeg, Some(50), None);
+        assert_ne!(jpeg, image_etag(&a, ImageFormat::Jpeg, None, None));
+        assert_ne!(jpeg, image_etag(&a, ImageFormat::Jpeg, Some(90), None));
+    }
+
+    // Retiling replaces tiles under the same names, so clients must check back for new ETags
+    #[test]
+    fn tiles_are_revalidated_by_default() {
+        let tiles = CacheControl::default().tiles;
+        let tiles = tiles.to_str().unwrap();
+        assert!(tiles.contains("no-cache"));
+        assert!(!tiles.contains("immutable"));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let etag = "\"abc.png\"";
+        assert!(if_none_match("\"abc.png\"", etag));
+        assert!(if_none_match("W/\"abc.png\"", etag));
+        assert!(if_none_match("\"xyz.png\", \"abc.png\"", etag));
+        assert!(if_none_match("*", etag));
+        assert!(!if_none_match("\"abc.jpg\"", etag));
+        assert!(!if_none_match("", etag));
+    }
+}
@@ -0,0 +1,290 @@
+//! Parsing `Accept` and `Accept-Encoding` headers, and using them to pick the format (and
+//! content coding) to send an image in.
+//!
+//! Follows RFC 9110: each media range may carry a quality value (`q=0.8`), wildcards like
+//! `image/*` and `*/*` are understood, and a format is given the quality of the most specific
+//! range that matches it. A quality of zero means "not acceptable". Content codings work the same
+//! way, with `*` as the only wildcard.
+
+use image::ImageFormat;
+use jnickg_imaging::{compression::Compression, serde::BinaryFormat};
+
+/// One entry of an `Accept` header, e.g. `image/*;q=0.8`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// Top-level type, or `*`
+    pub type_: String,
+
+    /// Subtype, or `*`
+    pub subtype: String,
+
+    /// Quality value, from 0.0 (not acceptable) to 1.0 (the default)
+    pub q: f32,
+}
+
+impl MediaRange {
+    /// Whether this range covers the given MIME type
+    pub fn matches(&self, mime_type: &str) -> bool {
+        let Some((type_, subtype)) = mime_type.split_once('/') else {
+            return false;
+        };
+        (self.type_ == "*" || self.type_.eq_ignore_ascii_case(type_))
+            && (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(subtype))
+    }
+
+    /// 2 for `type/subtype`, 1 for `type/*`, and 0 for `*/*`
+    fn specificity(&self) -> u8 {
+        match (self.type_.as_str(), self.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// Parse an `Accept` header into its media ranges. Malformed entries are skipped, and malformed
+/// quality values count as 1.0
+pub fn parse_accept(header: &str) -> Vec<MediaRange> {
+    header
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';').map(str::trim);
+            let (type_, subtype) = params.next()?.split_once('/')?;
+            if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+                return None;
+            }
+            Some(MediaRange {
+                type_: type_.to_ascii_lowercase(),
+                subtype: subtype.to_ascii_lowercase(),
+                q: quality_param(params),
+            })
+        })
+        .collect()
+}
+
+/// The `q` parameter among the given parameters of a header entry. 1.0 if it's missing or
+/// malformed
+fn quality_param<'a>(mut params: impl Iterator<Item = &'a str>) -> f32 {
+    params
+        .find_map(|p| {
+            let (k, v) = p.split_once('=')?;
+            k.trim().eq_ignore_ascii_case("q").then_some(v)
+        })
+        .and_then(|v| v.trim().parse::<f32>().ok())
+        .map_or(1.0, |q| q.clamp(0.0, 1.0))
+}
+
+/// The quality the client gives `mime_type`: that of the most specific range matching it, or
+/// 0.0 if none do
+pub fn quality(ranges: &[MediaRange], mime_type: &str) -> f32 {
+    ranges
+        .iter()
+        .filter(|r| r.matches(mime_type))
+        .max_by_key(|r| r.specificity())
+        .map_or(0.0, |r| r.q)
+}
+
+/// Pick the acceptable candidate with the highest quality. Candidates are in the server's order
+/// of preference, which breaks ties. `None` if the client accepts none of them
+pub fn negotiate<'a>(ranges: &[MediaRange], candidates: &[&'a str]) -> Option<&'a str> {
+    let mut best: Option<(&str, f32)> = None;
+    for &c in candidates {
+        let q = quality(ranges, c);
+        let better = match best {
+            Some((_, best_q)) => q > best_q,
+            None => q > 0.0,
+        };
+        if better {
+            best = Some((c, q));
+        }
+    }
+    best.map(|(c, _)| c)
+}
+
+/// Choose the format to send an image in, given the request's `Accept` header (if any).
+///
+/// `preferred` lists the formats we'd rather send, best first. Typically that's the one named by
+/// the URL's extension, then the one the image is stored in (which spares us re-encoding it).
+/// These win whenever the client likes them as much as anything else, and any other format we
+/// can encode is fair game after them. If the client accepts none of them, we fall back to the
+/// first preferred format rather than failing the request.
+pub fn choose_image_format(accept: Option<&str>, preferred: &[ImageFormat]) -> ImageFormat {
+    let fallback = preferred.first().copied().unwrap_or(ImageFormat::Png);
+    let Some(accept) = accept else {
+        return fallback;
+    };
+
+    let mut candidates: Vec<ImageFormat> = Vec::new();
+    for f in preferred
+        .iter()
+        .copied()
+        .chain(ImageFormat::all().filter(|f| f.writing_enabled()))
+    {
+        if !candidates.contains(&f) {
+            candidates.push(f);
+        }
+    }
+    let mime_types = candidates
+        .iter()
+        .map(|f| f.to_mime_type())
+        .collect::<Vec<&str>>();
+
+    negotiate(&parse_accept(accept), &mime_types)
+        .and_then(ImageFormat::from_mime_type)
+        .unwrap_or(fallback)
+}
+
+/// Choose how to encode a matrix, given the request's `Accept` header (if any): as JSON, which
+/// is `None`, or in one of the [`BinaryFormat`]s.
+///
+/// JSON wins ties, and is what clients get without an `Accept` header or if they accept nothing
+/// we can write, so only clients that ask for a binary format get one.
+pub fn choose_matrix_encoding(accept: Option<&str>) -> Option<BinaryFormat> {
+    let accept = accept?;
+    let mut candidates = vec!["application/json"];
+    candidates.extend(BinaryFormat::ALL.iter().map(|f| f.mime_type()));
+    negotiate(&parse_accept(accept), &candidates).and_then(BinaryFormat::from_mime_type)
+}
+
+/// One entry of an `Accept-Encoding` header, e.g. `gzip;q=0.8`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodingRange {
+    /// Content coding, lowercase, or `*`
+    pub coding: String,
+
+    /// Quality value, from 0.0 (not acceptable) to 1.0 (the default)
+    pub q: f32,
+}
+
+/// Parse an `Accept-Encoding` header into its codings. Empty entries are skipped, and malformed
+/// quality values count as 1.0
+pub fn parse_accept_encoding(header: &str) -> Vec<CodingRange> {
+    header
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';').map(str::trim);
+            let coding = params.next()?;
+            if coding.is_empty() {
+                return None;
+            }
+            Some(CodingRange {
+                coding: coding.to_ascii_lowercase(),
+                q: quality_param(params),
+            })
+        })
+        .collect()
+}
+
+/// The quality the client gives a content coding: that of its own entry if it has one, else that
+/// of `*`, else 0.0
+fn coding_quality(ranges: &[CodingRange], compression: Compression) -> f32 {
+    let named = ranges
+        .iter()
+        .find(|r| Compression::from_content_encoding(&r.coding) == Some(compression));
+    let wildcard = ranges.iter().find(|r| r.coding == "*");
+    named.or(wildcard).map_or(0.0, |r| r.q)
+}
+
+/// Whether data stored with `compression` can be sent as is, given the request's
+/// `Accept-Encoding` header (if any).
+///
+/// RFC 9110 lets a server pick any coding when the header is missing, but plenty of clients omit
+/// it without being able to decode anything, so we only send compressed data to clients that ask
+/// for it.
+pub fn accepts_encoding(accept_encoding: Option<&str>, compression: Compression) -> bool {
+    accept_encoding.is_some_and(|h| coding_quality(&parse_accept_encoding(h), compression) > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn parse_accept_reads_quality_values() {
+        let ranges = parse_accept("image/webp, image/*;q=0.8 , */*; q=0.1");
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(
+            (ranges[0].type_.as_str(), ranges[0].subtype.as_str()),
+            ("image", "webp")
+        );
+        assert_eq!(ranges[0].q, 1.0);
+        assert_eq!(ranges[1].subtype, "*");
+        assert_eq!(ranges[1].q, 0.8);
+        assert_eq!(ranges[2].q, 0.1);
+    }
+
+    #[test]
+    fn parse_accept_skips_malformed_entries() {
+        let ranges = parse_accept("garbage, */png, image/png;q=oops, ,text/");
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].subtype, "png");
+        assert_eq!(ranges[0].q, 1.0);
+    }
+
+    #[test]
+    fn most_specific_range_sets_quality() {
+        let ranges = parse_accept("image/*;q=0.5, image/png;q=0, */*;q=0.1");
+        assert_eq!(quality(&ranges, "image/png"), 0.0);
+        assert_eq!(quality(&ranges, "image/jpeg"), 0.5);
+        assert_eq!(quality(&ranges, "application/json"), 0.1);
+    }
+
+    #[test]
+    fn negotiate_breaks_ties_by_server_preference() {
+        let ranges = parse_accept("image/jpeg, image/png");
+        assert_eq!(
+            negotiate(&ranges, &["image/png", "image/jpeg"]),
+            Some("image/png")
+        );
+        let ranges = parse_accept("image/jpeg, image/png;q=0.9");
+        assert_eq!(
+            negotiate(&ranges, &["image/png", "image/jpeg"]),
+            Some("image/jpeg")
+        );
+        assert_eq!(negotiate(&parse_accept("text/html"), &["image/png"]), None);
+    }
+
+    #[test]
+    fn parse_accept_encoding_reads_quality_values() {
+        let ranges = parse_accept_encoding("gzip;q=0.5, BR, , *;q=0");
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0].coding, "gzip");
+        assert_eq!(ranges[0].q, 0.5);
+        assert_eq!(ranges[1].coding, "br");
+        assert_eq!(ranges[1].q, 1.0);
+        assert_eq!(ranges[2].q, 0.0);
+    }
+
+    #[test_case(None, Compression::Brotli, false ; "no header")]
+    #[test_case(Some("gzip, deflate, br"), Compression::Brotli, true ; "listed")]
+    #[test_case(Some("gzip, deflate"), Compression::Brotli, false ; "not listed")]
+    #[test_case(Some("br;q=0, *"), Compression::Brotli, false ; "refused by name")]
+    #[test_case(Some("*"), Compression::Zstd, true ; "wildcard")]
+    #[test_case(Some("x-gzip"), Compression::Gzip, true ; "alias")]
+    #[test_case(Some("identity"), Compression::Gzip, false ; "identity only")]
+    fn accepts_encoding_cases(header: Option<&str>, compression: Compression, expected: bool) {
+        assert_eq!(accepts_encoding(header, compression), expected);
+    }
+
+    #[test_case(None, None ; "no header")]
+    #[test_case(Some("*/*"), None ; "anything")]
+    #[test_case(Some("application/cbor"), Some(BinaryFormat::Cbor) ; "cbor")]
+    #[test_case(Some("application/octet-stream"), Some(BinaryFormat::Bincode) ; "bincode")]
+    #[test_case(Some("application/json;q=0.5, application/*"), Some(BinaryFormat::Cbor) ; "json ranks lower")]
+    #[test_case(Some("text/html"), None ; "nothing acceptable")]
+    fn choose_matrix_encoding_cases(accept: Option<&str>, expected: Option<BinaryFormat>) {
+        assert_eq!(choose_matrix_encoding(accept), expected);
+    }
+
+    #[test_case(None, ImageFormat::Png ; "no header")]
+    #[test_case(Some("*/*"), ImageFormat::Png ; "anything")]
+    #[test_case(Some("image/jpeg"), ImageFormat::Jpeg ; "exact")]
+    #[test_case(Some("image/webp,image/*;q=0.8"), ImageFormat::WebP ; "wildcard ranks lower")]
+    #[test_case(Some("image/png;q=0, image/*"), ImageFormat::Jpeg ; "preferred refused")]
+    #[test_case(Some("text/html"), ImageFormat::Png ; "nothing acceptable")]
+    fn choose_image_format_cases(accept: Option<&str>, expected: ImageFormat) {
+        let preferred = [ImageFormat::Png, ImageFormat::Jpeg];
+        assert_eq!(choose_image_format(accept, &preferred), expected);
+    }
+}
@@ -0,0 +1,131 @@
+//! Cross-origin access to the API.
+//!
+//! The frontend is usually served from this server, so it needs no CORS headers at all. But when
+//! it's served from elsewhere (e.g. `trunk serve` on port 8080, talking to the API on port 3000)
+//! browsers will only let it call the API if the server says that origin is allowed.
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use jnickg_imaging::errors::Error;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+/// Which cross-origin requests the API allows. Set from the command line
+#[derive(Debug, Clone, Default)]
+pub struct CorsSettings {
+    /// Origins (e.g. `http://localhost:8080`) allowed to call the API, or `*` for any. If empty,
+    /// cross-origin requests aren't allowed
+    pub origins: Vec<String>,
+
+    /// Methods cross-origin requests may use, or `*` for any
+    pub methods: Vec<String>,
+
+    /// Request headers cross-origin requests may send, or `*` for any
+    pub headers: Vec<String>,
+}
+
+impl CorsSettings {
+    /// Everything the frontend uses
+    pub const DEFAULT_METHODS: &'static str = "GET,POST,PUT,DELETE";
+
+    /// Everything the frontend sends, including the name of uploaded images
+    pub const DEFAULT_HEADERS: &'static str = "content-type,content-disposition";
+
+    /// Build the layer enforcing these settings, or `None` if no origins are allowed
+    pub fn layer(&self) -> Result<Option<CorsLayer>, Error> {
+        if self.origins.is_empty() {
+            return Ok(None);
+        }
+
+        let origins = match is_any(&self.origins) {
+            true => AllowOrigin::any(),
+            false => AllowOrigin::list(parse_all(&self.origins, "origin", |o| {
+                HeaderValue::from_str(o).ok()
+            })?),
+        };
+        let methods = match is_any(&self.methods) {
+            true => AllowMethods::any(),
+            false => AllowMethods::list(parse_all(&self.methods, "method", |m| {
+                Method::from_bytes(m.to_ascii_uppercase().as_bytes()).ok()
+            })?),
+        };
+        let headers = match is_any(&self.headers) {
+            true => AllowHeaders::any(),
+            false => AllowHeaders::list(parse_all(&self.headers, "header", |h| {
+                HeaderName::from_bytes(h.as_bytes()).ok()
+            })?),
+        };
+
+        Ok(Some(
+            CorsLayer::new()
+                .allow_origin(origins)
+                .allow_methods(methods)
+                .allow_headers(headers),
+        ))
+    }
+}
+
+/// Whether a list of allowed values is the wildcard
+fn is_any(values: &[String]) -> bool {
+    values.iter().any(|v| v == "*")
+}
+
+/// Parse every (trimmed, non-empty) value with `parse`, failing on the first it rejects
+fn parse_all<T>(
+    values: &[String],
+    what: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, Error> {
+    values
+        .iter()
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| parse(v).ok_or_else(|| Error::validation(format!("Invalid CORS {}: {}", what, v))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(origins: &[&str], methods: &str, headers: &str) -> CorsSettings {
+        let split = |s: &str| s.split(',').map(String::from).collect();
+        CorsSettings {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            methods: split(methods),
+            headers: split(headers),
+        }
+    }
+
+    #[test]
+    fn no_origins_means_no_layer() {
+        let cors = settings(
+            &[],
+            CorsSettings::DEFAULT_METHODS,
+            CorsSettings::DEFAULT_HEADERS,
+        );
+        assert!(cors.layer().unwrap().is_none());
+    }
+
+    #[test]
+    fn lists_and_wildcards_are_accepted() {
+        let cors = settings(
+            &["http://localhost:8080", "https://tiles.example.com"],
+            "get, post",
+            CorsSettings::DEFAULT_HEADERS,
+        );
+        assert!(cors.layer().unwrap().is_some());
+
+        let cors = settings(&["*"], "*", "*");
+        assert!(cors.layer().unwrap().is_some());
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for cors in [
+            settings(&["http://bad\norigin"], "GET", "content-type"),
+            settings(&["*"], "GET,NOT A METHOD", "content-type"),
+            settings(&["*"], "GET", "bad header"),
+        ] {
+            assert!(matches!(cors.layer(), Err(Error::Validation(_))));
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! The server's handle on MongoDB. Every collection, and the GridFS bucket, is named with a
+//! configurable prefix, so several servers can keep their data apart in one database (or share a
+//! cluster with different database names).
+//!
+//! The handle also looks after the connection. [`Db::connect_with_retry`] waits out a database
+//! that isn't up yet when we start, backing off between attempts. After that, health checks
+//! ([`Db::ping`], run by [`Db::watch`] and the readiness probe) keep score, and once enough of
+//! them fail in a row the connection is replaced the next time anyone asks for the database.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use jnickg_imaging::errors::Error;
+use mongodb::{
+    bson::doc,
+    gridfs::GridFsBucket,
+    options::{ClientOptions, GridFsBucketOptions},
+    Client, Collection, Database,
+};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::Span;
+
+/// How many health checks in a row must fail before we replace the connection
+pub const RECONNECT_AFTER_FAILURES: u32 = 3;
+
+/// How long to wait before the first retry when connecting, doubling after each failure
+const CONNECT_RETRY_INITIAL: Duration = Duration::from_millis(500);
+
+/// Longest we wait between connection attempts
+const CONNECT_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// How long a ping may take before we count it as failed. Much shorter than the driver's server
+/// selection timeout, which would otherwise hold each check up for 30 seconds
+pub const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the connection to MongoDB has been doing, as of the last health check
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ConnectionHealth {
+    /// Health checks failed in a row, since the last one that passed (or the last reconnect)
+    pub consecutive_failures: u32,
+
+    /// Times the connection has been replaced since we started
+    pub reconnects: u64,
+
+    /// Why the last failed health check failed, until one passes
+    pub last_error: Option<String>,
+}
+
+struct Connection {
+    /// How to connect again. `None` for databases someone else connected to, which we can't
+    /// replace
+    options: Option<ClientOptions>,
+    name: String,
+    database: RwLock<Database>,
+    health: Mutex<ConnectionHealth>,
+}
+
+/// A MongoDB database, and the prefix of every collection we use in it. Clones share one
+/// connection
+#[derive(Clone)]
+pub struct Db {
+    connection: Arc<Connection>,
+    collection_prefix: String,
+}
+
+impl std::fmt::Debug for Db {
+    // Client options hold credentials, so leave them out
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Db")
+            .field("name", &self.connection.name)
+            .field("collection_prefix", &self.collection_prefix)
+            .finish()
+    }
+}
+
+impl Db {
+    /// Name of the database we use unless told otherwise
+    pub const DEFAULT_NAME: &'static str = "tiler";
+
+    /// How many times we try to reach the database when starting, unless told otherwise
+    pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 10;
+
+    /// How often [`Db::watch`] checks the connection, unless told otherwise
+    pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Name of the GridFS bucket image data is kept in, before prefixing. This is MongoDB's
+    /// default, so unprefixed data stays where it always was
+    const BUCKET: &'static str = "fs";
+
+    /// Use a database that's already connected. Its connection can't be replaced if it goes bad
+    pub fn new(inner: Database, collection_prefix: impl Into<String>) -> Self {
+        Self::from_parts(None, inner, collection_prefix.into())
+    }
+
+    fn from_parts(
+        options: Option<ClientOptions>,
+        inner: Database,
+        collection_prefix: String,
+    ) -> Self {
+        Self {
+            connection: Arc::new(Connection {
+                options,
+                name: inner.name().to_string(),
+                database: RwLock::new(inner),
+                health: Mutex::new(ConnectionHealth::default()),
+            }),
+            collection_prefix,
+        }
+    }
+
+    /// Set up a connection to the database called `name` on the server at `uri`. Nothing is sent
+    /// to the server yet, so this succeeds whether or not it's up
+    pub async fn connect(uri: &str, name: &str, collection_prefix: &str) -> Result<Self, Error> {
+        let options = ClientOptions::parse(uri)
+            .await
+            .map_err(|_e| Error::database(format!("Invalid MongoDB URI: {}", _e)))?;
+        let client = Client::with_options(options.clone())
+            .map_err(|_e| Error::database(format!("Failed to connect to MongoDB: {}", _e)))?;
+        Ok(Self::from_parts(
+            Some(options),
+            client.database(name),
+            collection_prefix.to_string(),
+        ))
+    }
+
+    /// Like [`Db::connect`], but don't return until the server answers a ping. Failed attempts
+    /// are retried with exponential backoff, up to `attempts` in all
+    pub async fn connect_with_retry(
+        uri: &str,
+        name: &str,
+        collection_prefix: &str,
+        attempts: u32,
+    ) -> Result<Self, Error> {
+        let mut delay = CONNECT_RETRY_INITIAL;
+        let mut attempt = 1;
+        loop {
+            let result = match Self::connect(uri, name, collection_prefix).await {
+                Ok(db) => db.ping(PING_TIMEOUT).await.map(|_| db),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(db) => return Ok(db),
+                Err(e) if attempt >= attempts => return Err(e),
+                Err(_e) => {
+                    tracing::warn!(
+                        "Couldn't reach MongoDB (attempt {} of {}), retrying in {:?}: {}",
+                        attempt,
+                        attempts,
+                        delay,
+                        _e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(CONNECT_RETRY_MAX);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// The database itself, for commands that aren't about any one collection
+    pub fn inner(&self) -> Database {
+        self.connection
+            .database
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn collection_prefix(&self) -> &str {
+        &self.collection_prefix
+    }
+
+    /// The full name of the collection we call `name`
+    pub fn collection_name(&self, name: &str) -> String {
+        format!("{}{}", self.collection_prefix, name)
+    }
+
+    /// The collection we call `name`
+    pub fn collection<T>(&self, name: &str) -> Collection<T> {
+        self.inner().collection(&self.collection_name(name))
+    }
+
+    /// The GridFS bucket image data is kept in
+    pub fn gridfs_bucket(&self) -> GridFsBucket {
+        let options = GridFsBucketOptions::builder()
+            .bucket_name(self.collection_name(Self::BUCKET))
+            .build();
+        self.inner().gridfs_bucket(options)
+    }
+
+    /// The collection of the GridFS bucket's file documents, which give each file's length and
+    /// chunk size
+    pub fn gridfs_files<T>(&self) -> Collection<T> {
+        self.collection(&format!("{}.files", Self::BUCKET))
+    }
+
+    /// The collection of the GridFS bucket's chunks, for reading parts of files without
+    /// downloading them whole
+    pub fn gridfs_chunks<T>(&self) -> Collection<T> {
+        self.collection(&format!("{}.chunks", Self::BUCKET))
+    }
+
+    /// How the connection has been doing
+    pub fn health(&self) -> ConnectionHealth {
+        self.lock_health().clone()
+    }
+
+    fn lock_health(&self) -> std::sync::MutexGuard<'_, ConnectionHealth> {
+        self.connection
+            .health
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Ping the server, giving up after `timeout`, and keep score. Returns the round-trip time
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration, Error> {
+        let start = Instant::now();
+        let database = self.inner();
+        let result =
+            match tokio::time::timeout(timeout, database.run_command(doc! { "ping": 1 }, None))
+                .await
+            {
+                Ok(Ok(_)) => Ok(start.elapsed()),
+                Ok(Err(e)) => Err(Error::database(format!("Database ping failed: {}", e))),
+                Err(_) => Err(Error::database("Database ping timed out")),
+            };
+
+        let mut health = self.lock_health();
+        match &result {
+            Ok(_) => {
+                health.consecutive_failures = 0;
+                health.last_error = None;
+            }
+            Err(e) => {
+                health.consecutive_failures += 1;
+                health.last_error = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    /// Replace the connection with a new one if the last [`RECONNECT_AFTER_FAILURES`] health
+    /// checks failed. Cheap otherwise, so it's done whenever the database is asked for
+    pub fn reconnect_if_unhealthy(&self) {
+        let Some(options) = &self.connection.options else {
+            return;
+        };
+        let mut health = self.lock_health();
+        if health.consecutive_failures < RECONNECT_AFTER_FAILURES {
+            return;
+        }
+
+        tracing::warn!(
+            "Reconnecting to MongoDB after {} failed health checks",
+            health.consecutive_failures
+        );
+        match Client::with_options(options.clone()) {
+            Ok(client) => {
+                *self
+                    .connection
+                    .database
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner) =
+                    client.database(&self.connection.name);
+                health.consecutive_failures = 0;
+                health.reconnects += 1;
+            }
+            Err(e) => health.last_error = Some(format!("Failed to reconnect: {}", e)),
+        }
+    }
+
+    /// Ping the server every `interval` in the background, so a connection that's gone bad is
+    /// noticed (and replaced, the next time it's used) even while nothing else is using it
+    pub fn watch(&self, interval: Duration) -> JoinHandle<()> {
+        let db = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                if let Err(_e) = db.ping(PING_TIMEOUT).await {
+                    tracing::warn!("MongoDB health check failed: {}", _e);
+                }
+            }
+        })
+    }
+}
+
+/// A database operation, running in a span of its own. See [`TraceDb::traced`]
+pub struct Traced<F> {
+    operation: Pin<Box<F>>,
+    span: Span,
+    started: Option<Instant>,
+}
+
+/// Tracing for database operations
+pub trait TraceDb: Future + Sized {
+    /// Run this database (or GridFS) operation in a `db` span, which records what it is (e.g.
+    /// `find_one`, or `upload` for GridFS), the collection it's on (`fs` for the GridFS bucket),
+    /// and once it's done, how long it took, in `elapsed_ms`. Slow queries and uploads then show
+    /// up on their own in tracing output, rather than disappearing into the request's span
+    fn traced(self, operation: &'static str, collection: &str) -> Traced<Self> {
+        Traced {
+            operation: Box::pin(self),
+            span: tracing::debug_span!(
+                "db",
+                operation,
+                collection,
+                elapsed_ms = tracing::field::Empty
+            ),
+            started: None,
+        }
+    }
+}
+
+impl<F: Future> TraceDb for F {}
+
+impl<F: Future> Future for Traced<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let _entered = this.span.enter();
+        // Timed from when it's first polled, which is when it starts doing anything
+        let started = *this.started.get_or_insert_with(Instant::now);
+        let poll = this.operation.as_mut().poll(cx);
+        if poll.is_ready() {
+            let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+            this.span.record("elapsed_ms", elapsed_ms);
+            tracing::debug!(elapsed_ms, "Database operation finished");
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nothing listens on port 1, and the short timeout makes each ping fail fast
+    const UNREACHABLE_URI: &str = "mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100";
+
+    #[tokio::test]
+    async fn connecting_gives_up_after_its_attempts() {
+        let start = Instant::now();
+        let result = Db::connect_with_retry(UNREACHABLE_URI, "tiler", "", 2).await;
+        assert!(matches!(result, Err(Error::Database(_))));
+        // One backoff between the two attempts
+        assert!(start.elapsed() >= CONNECT_RETRY_INITIAL);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_enough_failed_checks() {
+        let db = Db::connect(UNREACHABLE_URI, "tiler", "").await.unwrap();
+        for failures in 1..RECONNECT_AFTER_FAILURES {
+            assert!(db.ping(PING_TIMEOUT).await.is_err());
+            db.reconnect_if_unhealthy();
+            assert_eq!(db.health().consecutive_failures, failures);
+            assert_eq!(db.health().reconnects, 0);
+        }
+
+        assert!(db.ping(PING_TIMEOUT).await.is_err());
+        assert!(db.health().last_error.is_some());
+        // Clones share the connection, and so its health
+        db.clone().reconnect_if_unhealthy();
+        let health = db.health();
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.reconnects, 1);
+    }
+
+    #[tokio::test]
+    async fn traced_operations_give_their_output() {
+        let mut polls = 0;
+        let operation = std::future::poll_fn(|cx| {
+            polls += 1;
+            match polls {
+                1 => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                _ => Poll::Ready(polls),
+            }
+        });
+        assert_eq!(operation.traced("find_one", "images").await, 2);
+    }
+
+    #[tokio::test]
+    async fn borrowed_databases_are_never_replaced() {
+        let client = Client::with_uri_str(UNREACHABLE_URI).await.unwrap();
+        let db = Db::new(client.database("tiler"), "");
+        for _ in 0..RECONNECT_AFTER_FAILURES {
+            assert!(db.ping(PING_TIMEOUT).await.is_err());
+        }
+        db.reconnect_if_unhealthy();
+        assert_eq!(db.health().reconnects, 0);
+    }
+}
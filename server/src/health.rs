@@ -0,0 +1,161 @@
+//! Health checks, for orchestrators (e.g. Kubernetes probes, or docker compose healthchecks).
+//!
+//! `/healthz` answers as long as the process is serving requests at all. `/readyz` also checks
+//! that MongoDB answers and that GridFS can be queried, so traffic can be held back while the
+//! database is unreachable, without the server being restarted for it. Its checks count towards
+//! replacing a connection that's gone bad; see [`crate::database`].
+
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use mongodb::{bson::doc, options::GridFsFindOptions};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::database::{ConnectionHealth, Db};
+use crate::web_appstate::AppState;
+
+/// How long the database may take to answer a readiness check before we call it unreachable.
+/// Much shorter than the driver's server selection timeout, which probes won't wait for
+pub const READINESS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The body of a `/readyz` response
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// Whether every check passed
+    pub ready: bool,
+
+    /// Round-trip time of a `ping` command to MongoDB, in milliseconds. `None` if it failed
+    pub db_latency_ms: Option<f64>,
+
+    /// Whether the GridFS bucket holding image data could be queried
+    pub gridfs: bool,
+
+    /// Why we aren't ready, if we aren't
+    pub error: Option<String>,
+
+    /// How the connection has been doing lately, if we have one
+    pub connection: Option<ConnectionHealth>,
+}
+
+impl Readiness {
+    fn failed(db_latency_ms: Option<f64>, error: impl Into<String>) -> Self {
+        Self {
+            ready: false,
+            db_latency_ms,
+            gridfs: false,
+            error: Some(error.into()),
+            connection: None,
+        }
+    }
+}
+
+pub async fn get_healthz() -> Response {
+    (StatusCode::OK, Json(json!({ "status": "ok" }))).into_response()
+}
+
+pub async fn get_readyz(State(app_state): AppState) -> Response {
+    // Don't hold the lock while waiting on the database
+    let db = app_state.read().await.db().ok().cloned();
+    let readiness = match db {
+        Some(db) => {
+            let mut readiness = check_database(&db).await;
+            readiness.connection = Some(db.health());
+            readiness
+        }
+        None => Readiness::failed(None, "Not connected to a database"),
+    };
+    let status = match readiness.ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(readiness)).into_response()
+}
+
+/// Ping MongoDB, timing the round trip, then look for a GridFS file
+async fn check_database(db: &Db) -> Readiness {
+    let db_latency_ms = match db.ping(READINESS_TIMEOUT).await {
+        Ok(latency) => Some(latency.as_secs_f64() * 1000.0),
+        Err(e) => return Readiness::failed(None, e.to_string()),
+    };
+
+    let options = GridFsFindOptions::builder().limit(1).build();
+    let find = tokio::time::timeout(READINESS_TIMEOUT, db.gridfs_bucket().find(doc! {}, options));
+    match find.await {
+        Ok(Ok(_)) => Readiness {
+            ready: true,
+            db_latency_ms,
+            gridfs: true,
+            error: None,
+            connection: None,
+        },
+        Ok(Err(e)) => Readiness::failed(db_latency_ms, format!("GridFS query failed: {}", e)),
+        Err(_) => Readiness::failed(db_latency_ms, "GridFS query timed out"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_support::{drop_test_database, test_database};
+    use crate::web_appstate::RuntimeData;
+
+    fn app(state: RuntimeData) -> Router {
+        Router::new()
+            .route("/healthz", get(get_healthz))
+            .route("/readyz", get(get_readyz))
+            .with_state(Arc::new(RwLock::new(state)))
+    }
+
+    async fn get_json(app: &Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn alive_but_not_ready_without_a_database() {
+        let app = app(RuntimeData::new());
+
+        let (status, body) = get_json(&app, "/healthz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+
+        let (status, body) = get_json(&app, "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert!(body["db_latency_ms"].is_null());
+    }
+
+    #[tokio::test]
+    async fn ready_with_a_database() {
+        let db = test_database().await;
+        let mut state = RuntimeData::new();
+        state.db = Some(db.clone());
+        let app = app(state);
+
+        let (status, body) = get_json(&app, "/readyz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["gridfs"], true);
+        assert!(body["db_latency_ms"].as_f64().unwrap() >= 0.0);
+        assert_eq!(body["connection"]["consecutive_failures"], 0);
+
+        drop_test_database(db).await;
+    }
+}
@@ -0,0 +1,670 @@
+//! A persistent queue of background jobs, kept in the `jobs` collection.
+//!
+//! Tiling a pyramid takes a while, so the request that creates a pyramid (or asks for it to be
+//! retiled) only adds a job to the queue. A pool of workers, started alongside the server (see
+//! `--tile-workers`), claims queued jobs one at a time and runs them. Since the queue lives in the
+//! database, jobs survive the server restarting, and more workers can share the load just by
+//! pointing them at the same database, e.g. by running the `tiler_worker` binary, which runs
+//! workers without serving anything.
+//!
+//! A worker claims a job by atomically marking it `running`, with a lease it renews for as long as
+//! it's working on it. If the worker dies, the lease runs out and another worker picks the job up.
+//! A job that fails (or is abandoned) is retried, until it has been tried [`MAX_ATTEMPTS`] times,
+//! after which it and its pyramid are marked as failed.
+
+use std::{future::Future, panic::AssertUnwindSafe, sync::Arc, time::Duration};
+
+use axum::extract::State;
+use futures::FutureExt;
+use futures_util::StreamExt;
+use jnickg_imaging::{
+    documents::{Pyramid, TilingStatus},
+    errors::Error,
+};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{Notify, RwLock},
+    task::JoinHandle,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::database::Db;
+use crate::web_appstate::RuntimeData;
+use crate::web_routines;
+
+/// Collection holding the queue
+pub const JOBS_COLLECTION: &str = "jobs";
+
+/// How many times a job is tried before it's given up on
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// How long a claimed job stays claimed without its worker renewing the claim. Workers renew it
+/// three times as often as this, so only a dead (or badly stuck) worker lets one run out
+pub const LEASE: Duration = Duration::from_secs(60);
+
+/// How often idle workers look for jobs, in case they weren't woken for them (e.g. because
+/// another process queued them)
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Workers started by default
+pub const DEFAULT_WORKERS: usize = 2;
+
+/// What a job does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    /// Generate the tiles of a pyramid
+    Tile,
+
+    /// Generate one of a pyramid's tile sets, named by the job's `tile_set`
+    #[serde(rename = "tile_set")]
+    TileSet,
+}
+
+/// Where a job is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a worker
+    Queued,
+
+    /// Claimed by a worker, which is (or was, if its lease has run out) working on it
+    Running,
+
+    /// Finished successfully
+    Done,
+
+    /// Failed [`MAX_ATTEMPTS`] times
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+/// A document in the `jobs` collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub kind: JobKind,
+
+    /// The pyramid to work on
+    pub pyramid_uuid: String,
+
+    /// Key of the tile set to work on, for [`JobKind::TileSet`] jobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile_set: Option<String>,
+
+    pub state: JobState,
+
+    /// How many times a worker has claimed this job
+    pub attempts: u32,
+
+    pub created_at: DateTime,
+
+    pub updated_at: DateTime,
+
+    /// When the claim of the worker running this job runs out, unless renewed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_expires_at: Option<DateTime>,
+
+    /// The worker that last claimed this job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker: Option<String>,
+
+    /// Why the last attempt failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// ID of the request that queued this job, so its logs can be tied back to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+fn jobs(db: &Db) -> Collection<Job> {
+    db.collection(JOBS_COLLECTION)
+}
+
+/// `now`, plus a lease
+fn lease_from(now: DateTime) -> DateTime {
+    DateTime::from_millis(now.timestamp_millis() + LEASE.as_millis() as i64)
+}
+
+/// Queue up tiling the given pyramid. `request_id` is the ID of the request asking for it, if any
+pub async fn enqueue_tiling(
+    db: &Db,
+    pyramid_uuid: &str,
+    request_id: Option<String>,
+) -> Result<ObjectId, Error> {
+    enqueue(db, JobKind::Tile, pyramid_uuid, None, request_id).await
+}
+
+/// Queue up generating the given pyramid's tile set with the given key
+pub async fn enqueue_tile_set(
+    db: &Db,
+    pyramid_uuid: &str,
+    key: &str,
+    request_id: Option<String>,
+) -> Result<ObjectId, Error> {
+    let tile_set = Some(key.to_string());
+    enqueue(db, JobKind::TileSet, pyramid_uuid, tile_set, request_id).await
+}
+
+async fn enqueue(
+    db: &Db,
+    kind: JobKind,
+    pyramid_uuid: &str,
+    tile_set: Option<String>,
+    request_id: Option<String>,
+) -> Result<ObjectId, Error> {
+    let now = DateTime::now();
+    let job = Job {
+        id: None,
+        kind,
+        pyramid_uuid: pyramid_uuid.to_string(),
+        tile_set,
+        state: JobState::Queued,
+        attempts: 0,
+        created_at: now,
+        updated_at: now,
+        lease_expires_at: None,
+        worker: None,
+        error: None,
+        request_id,
+    };
+    let inserted = jobs(db)
+        .insert_one(job, None)
+        .await
+        .map_err(|_| Error::database("Error queueing tiling job"))?;
+    inserted
+        .inserted_id
+        .as_object_id()
+        .ok_or_else(|| Error::database("Queued job has no ID"))
+}
+
+/// The job for the given pyramid that is waiting for a worker, or being worked on, if any. With a
+/// `tile_set` key, the job generating that tile set; otherwise the one tiling the pyramid itself
+pub async fn active_job(
+    db: &Db,
+    pyramid_uuid: &str,
+    tile_set: Option<&str>,
+) -> Result<Option<Job>, Error> {
+    let filter = doc! {
+        "pyramid_uuid": pyramid_uuid,
+        // Matches jobs without a tile set, too
+        "tile_set": tile_set,
+        "$or": [
+            { "state": JobState::Queued.as_str() },
+            {
+                "state": JobState::Running.as_str(),
+                "lease_expires_at": { "$gt": DateTime::now() },
+            },
+        ],
+    };
+    jobs(db)
+        .find_one(filter, None)
+        .await
+        .map_err(|_| Error::database("Error querying jobs"))
+}
+
+/// Claim the oldest job that is queued, or whose worker's lease ran out, for `worker`
+async fn claim(db: &Db, worker: &str) -> Result<Option<Job>, Error> {
+    let now = DateTime::now();
+    let filter = doc! {
+        "$or": [
+            { "state": JobState::Queued.as_str() },
+            {
+                "state": JobState::Running.as_str(),
+                "lease_expires_at": { "$lt": now },
+                "attempts": { "$lt": MAX_ATTEMPTS },
+            },
+        ],
+    };
+    let update = doc! {
+        "$set": {
+            "state": JobState::Running.as_str(),
+            "worker": worker,
+            "lease_expires_at": lease_from(now),
+            "updated_at": now,
+        },
+        "$inc": { "attempts": 1 },
+    };
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! { "created_at": 1 })
+        .return_document(ReturnDocument::After)
+        .build();
+    jobs(db)
+        .find_one_and_update(filter, update, options)
+        .await
+        .map_err(|_| Error::database("Error claiming job"))
+}
+
+/// Extend `worker`'s claim on the given job. Returns whether `worker` still holds it: once its
+/// lease has run out and another worker has reclaimed the job, there's nothing left to renew
+async fn renew_lease(db: &Db, id: ObjectId, worker: &str) -> Result<bool, Error> {
+    let now = DateTime::now();
+    let renewed = jobs(db)
+        .update_one(
+            doc! {
+                "_id": id,
+                "worker": worker,
+                "state": JobState::Running.as_str(),
+            },
+            doc! { "$set": { "lease_expires_at": lease_from(now), "updated_at": now } },
+            None,
+        )
+        .await
+        .map_err(|_| Error::database("Error renewing job lease"))?;
+    Ok(renewed.matched_count > 0)
+}
+
+/// Record the outcome of an attempt at `job`. Failed jobs go back in the queue until they've been
+/// tried [`MAX_ATTEMPTS`] times.
+///
+/// Only the worker still holding the job can finish it: a worker whose lease ran out, and whose
+/// job was reclaimed by another, leaves the job (and its pyramid) alone
+async fn finish(db: &Db, job: &Job, result: Result<(), Error>) -> Result<(), Error> {
+    let id = job.id.ok_or_else(|| Error::internal("Job has no ID"))?;
+    let (state, error) = match result {
+        Ok(()) => (JobState::Done, None),
+        Err(e) if job.attempts < MAX_ATTEMPTS => (JobState::Queued, Some(e.to_string())),
+        Err(e) => (JobState::Failed, Some(e.to_string())),
+    };
+    let updated = jobs(db)
+        .update_one(
+            doc! {
+                "_id": id,
+                "worker": &job.worker,
+                "state": JobState::Running.as_str(),
+            },
+            doc! {
+                "$set": {
+                    "state": state.as_str(),
+                    "error": error,
+                    "updated_at": DateTime::now(),
+                },
+                "$unset": { "lease_expires_at": "" },
+            },
+            None,
+        )
+        .await
+        .map_err(|_| Error::database("Error updating job"))?;
+    if updated.matched_count == 0 {
+        tracing::warn!(worker = ?job.worker, "Job was reclaimed by another worker");
+        return Ok(());
+    }
+
+    // Clients waiting on the pyramid should know whether it's coming
+    let tiles = match state {
+        JobState::Queued => TilingStatus::Todo,
+        JobState::Failed => TilingStatus::Failed,
+        JobState::Running | JobState::Done => return Ok(()),
+    };
+    let (filter, update) = match &job.tile_set {
+        Some(key) => (
+            doc! { "uuid": &job.pyramid_uuid, "tile_sets.key": key },
+            doc! { "$set": { "tile_sets.$.tiles": tiles.as_str() } },
+        ),
+        None => (
+            doc! { "uuid": &job.pyramid_uuid },
+            doc! { "$set": { "tiles": tiles.as_str() } },
+        ),
+    };
+    db.collection::<Pyramid>("pyramids")
+        .update_one(filter, update, None)
+        .await
+        .map_err(|_| Error::database("Error updating pyramid"))?;
+    Ok(())
+}
+
+/// Give up on jobs whose worker stopped responding on their last attempt, since nobody will
+/// claim them again
+async fn fail_abandoned(db: &Db) -> Result<(), Error> {
+    let filter = doc! {
+        "state": JobState::Running.as_str(),
+        "lease_expires_at": { "$lt": DateTime::now() },
+        "attempts": { "$gte": MAX_ATTEMPTS },
+    };
+    let mut abandoned = jobs(db)
+        .find(filter, None)
+        .await
+        .map_err(|_| Error::database("Error querying jobs"))?;
+    while let Some(job) = abandoned.next().await {
+        let job = job.map_err(|_| Error::database("Error reading job"))?;
+        tracing::warn!(pyramid_uuid = %job.pyramid_uuid, "Giving up on abandoned job");
+        finish(db, &job, Err(Error::internal("Worker stopped responding"))).await?;
+    }
+    Ok(())
+}
+
+/// Run one attempt at `job`. Tiles left over from an earlier attempt are removed first
+async fn run(app_state: &Arc<RwLock<RuntimeData>>, db: &Db, job: &Job) -> Result<(), Error> {
+    let pyramid_uuid = Uuid::parse_str(&job.pyramid_uuid)
+        .map_err(|_| Error::internal("Job has an invalid pyramid UUID"))?;
+    match job.kind {
+        JobKind::Tile => {
+            if job.attempts > 1 {
+                let deleted = web_routines::delete_pyramid_tiles(db, &job.pyramid_uuid).await?;
+                tracing::info!(deleted, "Deleted tiles of earlier attempt");
+            }
+            web_routines::generate_tiles_for_pyramid(State(app_state.clone()), pyramid_uuid).await
+        }
+        JobKind::TileSet => {
+            let key = job
+                .tile_set
+                .as_deref()
+                .ok_or_else(|| Error::internal("Tile set job has no tile set"))?;
+            if job.attempts > 1 {
+                let deleted =
+                    web_routines::delete_tile_set_tiles(db, &job.pyramid_uuid, key).await?;
+                tracing::info!(deleted, "Deleted tiles of earlier attempt");
+            }
+            web_routines::generate_tile_set(State(app_state.clone()), pyramid_uuid, key).await
+        }
+    }
+}
+
+/// Drive `work` to completion, renewing `worker`'s lease on job `id` every `period` meanwhile.
+/// If the lease turns out to have been lost to another worker, `work` is dropped unfinished and
+/// `None` is returned, so two workers never keep working on the same job
+async fn while_leased<T>(
+    db: &Db,
+    id: ObjectId,
+    worker: &str,
+    period: Duration,
+    work: impl Future<Output = T>,
+) -> Option<T> {
+    tokio::pin!(work);
+    let mut heartbeat = tokio::time::interval(period);
+    heartbeat.tick().await;
+    loop {
+        tokio::select! {
+            result = &mut work => return Some(result),
+            _ = heartbeat.tick() => match renew_lease(db, id, worker).await {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => tracing::error!(error = %e, "Failed to renew lease"),
+            },
+        }
+    }
+}
+
+/// Run `job`, renewing `worker`'s lease on it until it's done, then record how it went. Stops
+/// working on it as soon as the lease is lost
+async fn work_on(app_state: &Arc<RwLock<RuntimeData>>, db: &Db, worker: &str, job: Job) {
+    let Some(id) = job.id else {
+        return;
+    };
+    let work = AssertUnwindSafe(run(app_state, db, &job)).catch_unwind();
+    let Some(result) = while_leased(db, id, worker, LEASE / 3, work).await else {
+        tracing::warn!("Lost the lease to another worker, abandoning job");
+        return;
+    };
+    let result = result.unwrap_or_else(|_| Err(Error::internal("Job panicked")));
+
+    match &result {
+        Ok(()) => tracing::info!("Job done"),
+        Err(e) => tracing::error!(error = %e, "Job failed"),
+    }
+    if let Err(e) = finish(db, &job, result).await {
+        tracing::error!(error = %e, "Failed to record job outcome");
+    }
+}
+
+/// Claim and run jobs until the end of time. Waits for [`RuntimeData::job_wakeup`] (or
+/// [`POLL_INTERVAL`]) whenever there's nothing to do
+async fn work(app_state: Arc<RwLock<RuntimeData>>, worker: String) {
+    let wakeup: Arc<Notify> = app_state.read().await.job_wakeup.clone();
+    loop {
+        // The database can come and go, but the worker carries on
+        let db = app_state.read().await.db().cloned();
+        if let Ok(db) = db {
+            match claim(&db, &worker).await {
+                Ok(Some(job)) => {
+                    let span = tracing::info_span!(
+                        "job",
+                        job_id = %job.id.map(|id| id.to_hex()).unwrap_or_default(),
+                        pyramid_uuid = %job.pyramid_uuid,
+                        tile_set = job.tile_set.as_deref().unwrap_or("-"),
+                        request_id = job.request_id.as_deref().unwrap_or("-"),
+                        attempt = job.attempts,
+                    );
+                    work_on(&app_state, &db, &worker, job)
+                        .instrument(span)
+                        .await;
+                    continue;
+                }
+                Ok(None) => {
+                    if let Err(e) = fail_abandoned(&db).await {
+                        tracing::error!(error = %e, "Failed to clean up abandoned jobs");
+                    }
+                }
+                Err(e) => tracing::error!(error = %e, "Failed to claim a job"),
+            }
+        }
+
+        tokio::select! {
+            _ = wakeup.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Start `count` workers claiming jobs from the database in `app_state`. They're woken early by
+/// [`RuntimeData::job_wakeup`] when a job is queued in this process
+pub fn start_workers(app_state: Arc<RwLock<RuntimeData>>, count: usize) -> Vec<JoinHandle<()>> {
+    // Workers in other processes may share the queue, so name ours uniquely
+    let process = Uuid::new_v4().simple().to_string();
+    (0..count)
+        .map(|n| {
+            let worker = format!("{}/{}", process, n);
+            tracing::info!(worker = %worker, "Starting worker");
+            tokio::spawn(work(app_state.clone(), worker))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{drop_test_database, test_database};
+
+    #[tokio::test]
+    async fn jobs_are_claimed_once_and_retried_until_exhausted() {
+        let db = test_database().await;
+
+        let uuid = Uuid::new_v4().to_string();
+        let id = enqueue_tiling(&db, &uuid, Some("req".to_string()))
+            .await
+            .unwrap();
+        assert!(active_job(&db, &uuid, None).await.unwrap().is_some());
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let job = claim(&db, "a").await.unwrap().expect("A queued job");
+            assert_eq!((job.id, job.attempts), (Some(id), attempt));
+            assert_eq!(job.state, JobState::Running);
+            assert_eq!(job.request_id.as_deref(), Some("req"));
+
+            // Nobody else gets it while it's leased
+            assert!(claim(&db, "b").await.unwrap().is_none());
+            assert!(active_job(&db, &uuid, None).await.unwrap().is_some());
+
+            finish(&db, &job, Err(Error::internal("Oops")))
+                .await
+                .unwrap();
+        }
+
+        let job = jobs(&db).find_one(doc! { "_id": id }, None).await.unwrap();
+        let job = job.unwrap();
+        assert_eq!(job.state, JobState::Failed);
+        assert_eq!(job.error.as_deref(), Some("Oops"));
+        assert!(active_job(&db, &uuid, None).await.unwrap().is_none());
+        assert!(claim(&db, "a").await.unwrap().is_none());
+
+        drop_test_database(db).await;
+    }
+
+    #[tokio::test]
+    async fn tile_set_jobs_are_tracked_apart_from_tiling() {
+        let db = test_database().await;
+
+        let uuid = Uuid::new_v4().to_string();
+        let id = enqueue_tile_set(&db, &uuid, "256x256_png", None)
+            .await
+            .unwrap();
+        assert!(active_job(&db, &uuid, None).await.unwrap().is_none());
+        let job = active_job(&db, &uuid, Some("256x256_png")).await.unwrap();
+        assert_eq!(job.unwrap().id, Some(id));
+        assert!(active_job(&db, &uuid, Some("128x128_png"))
+            .await
+            .unwrap()
+            .is_none());
+
+        let job = claim(&db, "a").await.unwrap().unwrap();
+        assert_eq!(job.kind, JobKind::TileSet);
+        assert_eq!(job.tile_set.as_deref(), Some("256x256_png"));
+
+        drop_test_database(db).await;
+    }
+
+    #[tokio::test]
+    async fn expired_leases_are_reclaimed() {
+        let db = test_database().await;
+
+        let uuid = Uuid::new_v4().to_string();
+        let id = enqueue_tiling(&db, &uuid, None).await.unwrap();
+        claim(&db, "dead").await.unwrap().unwrap();
+
+        // Pretend the worker died long enough ago for its lease to run out
+        let expired = DateTime::from_millis(DateTime::now().timestamp_millis() - 1000);
+        jobs(&db)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "lease_expires_at": expired } },
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(active_job(&db, &uuid, None).await.unwrap().is_none());
+
+        let job = claim(&db, "alive").await.unwrap().unwrap();
+        assert_eq!(job.worker.as_deref(), Some("alive"));
+        assert_eq!(job.attempts, 2);
+
+        drop_test_database(db).await;
+    }
+
+    #[tokio::test]
+    async fn stale_workers_cannot_finish_reclaimed_jobs() {
+        let db = test_database().await;
+
+        let uuid = Uuid::new_v4().to_string();
+        let pyramids = db.collection::<mongodb::bson::Document>("pyramids");
+        pyramids
+            .insert_one(
+                doc! { "uuid": &uuid, "tiles": TilingStatus::Processing.as_str() },
+                None,
+            )
+            .await
+            .unwrap();
+        let id = enqueue_tiling(&db, &uuid, None).await.unwrap();
+        let stale = claim(&db, "stale").await.unwrap().unwrap();
+
+        // Its lease runs out, and another worker takes over
+        let expired = DateTime::from_millis(DateTime::now().timestamp_millis() - 1000);
+        jobs(&db)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "lease_expires_at": expired } },
+                None,
+            )
+            .await
+            .unwrap();
+        let current = claim(&db, "current").await.unwrap().unwrap();
+
+        // Whatever the stale worker reports is ignored
+        finish(&db, &stale, Ok(())).await.unwrap();
+        finish(&db, &stale, Err(Error::internal("Oops")))
+            .await
+            .unwrap();
+        let job = jobs(&db).find_one(doc! { "_id": id }, None).await.unwrap();
+        let job = job.unwrap();
+        assert_eq!(job.state, JobState::Running);
+        assert_eq!(job.worker.as_deref(), Some("current"));
+        assert_eq!(job.error, None);
+        let pyramid = pyramids
+            .find_one(doc! { "uuid": &uuid }, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            pyramid.get_str("tiles").unwrap(),
+            TilingStatus::Processing.as_str()
+        );
+
+        // The worker holding it can still finish it
+        finish(&db, &current, Ok(())).await.unwrap();
+        let job = jobs(&db).find_one(doc! { "_id": id }, None).await.unwrap();
+        assert_eq!(job.unwrap().state, JobState::Done);
+
+        drop_test_database(db).await;
+    }
+
+    #[tokio::test]
+    async fn workers_stop_once_their_lease_is_taken_over() {
+        let db = test_database().await;
+
+        let uuid = Uuid::new_v4().to_string();
+        let id = enqueue_tiling(&db, &uuid, None).await.unwrap();
+        claim(&db, "stale").await.unwrap().unwrap();
+        assert!(renew_lease(&db, id, "stale").await.unwrap());
+
+        // Partway through the work, before the next renewal, another worker takes over
+        let take_over = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let expired = DateTime::from_millis(DateTime::now().timestamp_millis() - 1000);
+            jobs(&db)
+                .update_one(
+                    doc! { "_id": id },
+                    doc! { "$set": { "lease_expires_at": expired } },
+                    None,
+                )
+                .await
+                .unwrap();
+            claim(&db, "current").await.unwrap().unwrap();
+        };
+        let stale = while_leased(
+            &db,
+            id,
+            "stale",
+            Duration::from_millis(500),
+            std::future::pending::<()>(),
+        );
+        let (outcome, ()) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(stale, take_over)
+        })
+        .await
+        .expect("The stale worker should have stopped");
+        assert_eq!(outcome, None);
+
+        assert!(!renew_lease(&db, id, "stale").await.unwrap());
+        assert!(renew_lease(&db, id, "current").await.unwrap());
+
+        drop_test_database(db).await;
+    }
+}
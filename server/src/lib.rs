@@ -0,0 +1,283 @@
+#![feature(test)]
+//! The tile server's routes and the pieces behind them. The `jnickg_tile_server` binary serves them
+//! over HTTP, and `tiler_worker` runs only the tiling jobs they queue, so the two can be scaled and
+//! deployed separately.
+
+//
+// Modules
+//
+
+pub mod admin;
+#[cfg(test)]
+mod api_tests;
+pub mod axum_helpers;
+pub mod byte_ranges;
+pub mod caching;
+pub mod content_negotiation;
+pub mod cors;
+pub mod database;
+pub mod health;
+pub mod jobs;
+pub mod matrix_tasks;
+pub mod metrics;
+pub mod pyramid_import;
+pub mod rate_limit;
+pub mod request_id;
+pub mod schema;
+#[cfg(test)]
+mod test_support;
+pub mod tunables;
+pub mod upload_limits;
+pub mod utoipa_helpers;
+pub mod web_api;
+pub mod web_appstate;
+pub mod web_routines;
+pub mod window_iterator;
+pub mod wrappers;
+
+//
+// Uses
+//
+
+use std::sync::Arc;
+
+use axum::extract::DefaultBodyLimit;
+
+use tokio::sync::RwLock;
+
+use ::axum::{
+    body::Bytes,
+    extract::{FromRequest, Path, Request, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Router,
+};
+
+extern crate tracing;
+
+use jnickg_imaging::errors::Error;
+use rand::Rng;
+
+use database::Db;
+use rate_limit::RateLimiter;
+use web_api as api;
+use web_appstate::{AppState, RuntimeData};
+
+//
+// Implementation
+//
+
+/// Connect to the database named `db_name` on the MongoDB server at `host:port`, logging in as
+/// `user` with the password in the file at `pass_path`. Our collections' names all start with
+/// `collection_prefix`. The server is given `attempts` tries to answer, with backoff in between,
+/// so it may still be starting up
+pub async fn connect_to_database(
+    host: &str,
+    port: u16,
+    user: &str,
+    pass_path: &str,
+    db_name: &str,
+    collection_prefix: &str,
+    attempts: u32,
+) -> Result<Db, Error> {
+    let password = std::fs::read_to_string(pass_path)
+        .map_err(|_e| Error::internal(format!("Failed to read {}: {}", pass_path, _e)))?;
+    let uri = format!("mongodb://{}:{}@{}:{}/", user, password, host, port);
+    Db::connect_with_retry(&uri, db_name, collection_prefix, attempts).await
+}
+
+/// Response for routes that don't exist
+pub async fn handler_404() -> Response {
+    (StatusCode::NOT_FOUND, "404 Not Found").into_response()
+}
+
+/// Every route under `/api/v1`. Routes that take images or matrices get `upload_limit` as their
+/// body limit, and expensive routes are held to `rate_limiter`. The admin routes are only served
+/// given an `admin_token`, to requests bearing it (see [`admin`])
+pub fn api_routes(
+    upload_limit: DefaultBodyLimit,
+    rate_limiter: Arc<RateLimiter>,
+    admin_token: Option<&str>,
+) -> Router<Arc<RwLock<RuntimeData>>> {
+    let rate_limit = middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit);
+    let routes = Router::new()
+        .route("/", get(api::get_api_index))
+        .route("/index.html", get(api::get_api_index))
+        .route("/hello", get(api::get_hello))
+        .route("/something", post(api::post_something))
+        .route(
+            "/something/:id",
+            get(api::get_something)
+                .put(api::put_something)
+                .delete(api::delete_something)
+                .post(api::post_something_with_id),
+        )
+        .route("/image", post(api::post_image).layer(upload_limit))
+        .route("/images", get(api::get_images))
+        .route(
+            "/image/:name",
+            get(api::get_image)
+                .put(api::put_image)
+                .delete(api::delete_image)
+                .layer(upload_limit),
+        )
+        .route("/image/:name/thumbnail", get(api::get_image_thumbnail))
+        .route("/image/:name/info", get(api::get_image_info))
+        .route("/image/:name/stats", get(api::get_image_stats))
+        .route("/image/:name/pixel", get(api::get_image_pixel))
+        .route("/image/:name/tags", put(api::put_image_tags))
+        .route(
+            "/image/:name/equalize",
+            post(api::post_image_equalize).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/convolve/:matrix_name",
+            post(api::post_image_convolve).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/generate",
+            post(api::post_image_generate).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/resize",
+            post(api::post_image_resize).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/rotate",
+            post(api::post_image_rotate).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/grayscale",
+            post(api::post_image_grayscale).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/split",
+            post(api::post_image_split).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/threshold",
+            post(api::post_image_threshold).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/:name/edges",
+            post(api::post_image_edges).layer(rate_limit.clone()),
+        )
+        .route(
+            "/images/merge",
+            post(api::post_images_merge).layer(rate_limit.clone()),
+        )
+        .route(
+            "/image/compare/:name1/:name2",
+            post(api::post_image_compare).layer(rate_limit.clone()),
+        )
+        .route("/level/:name/info", get(api::get_level_info))
+        .route(
+            "/level/:name",
+            get(api::get_level)
+                .put(api::put_level)
+                .delete(api::delete_level)
+                .layer(upload_limit),
+        )
+        .route(
+            "/tile/:name",
+            get(api::get_tile)
+                .put(api::put_tile)
+                .delete(api::delete_tile)
+                .layer(upload_limit),
+        )
+        .route(
+            "/matrix/:name",
+            post(api::post_matrix_with_name)
+                .get(api::get_matrix)
+                .put(api::put_matrix)
+                .delete(api::delete_matrix)
+                .layer(upload_limit),
+        )
+        .route("/matrix/:name/dims", get(api::get_matrix_dims))
+        .route(
+            "/matrix/:name/from_kernel/:kernel",
+            post(api::post_matrix_from_kernel),
+        )
+        .route("/kernels", get(api::get_kernels))
+        .route("/matrix/:name/norms", get(api::get_matrix_norms))
+        .route("/matrix/:name/status", get(api::get_matrix_status))
+        .route(
+            "/matrix/multiply/:name1/:name2",
+            post(api::post_matrix_multiply).layer(rate_limit.clone()),
+        )
+        .route("/matrix/add/:name1/:name2", post(api::post_matrix_add))
+        .route(
+            "/matrix/subtract/:name1/:name2",
+            post(api::post_matrix_subtract),
+        )
+        .route("/matrix/scale/:name", post(api::post_matrix_scale))
+        .route(
+            "/matrix/hadamard/:name1/:name2",
+            post(api::post_matrix_hadamard),
+        )
+        .route("/matrix/transpose/:name", post(api::post_matrix_transpose))
+        .route("/matrix/compare/:a/:b", post(api::post_matrix_compare))
+        .route(
+            "/matrix/solve/:a/:b",
+            post(api::post_matrix_solve).layer(rate_limit.clone()),
+        )
+        .route(
+            "/pyramid",
+            post(api::post_pyramid)
+                .layer(upload_limit)
+                .layer(rate_limit.clone()),
+        )
+        .route(
+            "/pyramid/import",
+            post(api::post_pyramid_import)
+                .layer(upload_limit)
+                .layer(rate_limit.clone()),
+        )
+        .route("/pyramid/:uuid", get(api::get_pyramid))
+        .route("/pyramid/:uuid/manifest", get(api::get_pyramid_manifest))
+        .route(
+            "/pyramid/:uuid/verify",
+            get(api::get_pyramid_verify).layer(rate_limit.clone()),
+        )
+        .route(
+            "/pyramid/:uuid/retile",
+            post(api::post_pyramid_retile).layer(rate_limit.clone()),
+        )
+        .route(
+            "/pyramid/:uuid/tiles",
+            post(api::post_pyramid_tile_set).layer(rate_limit.clone()),
+        )
+        .route(
+            "/pyramid/:uuid/tiles/batch",
+            post(api::post_pyramid_tiles_batch),
+        )
+        .route("/pyramid/:uuid/tags", put(api::put_pyramid_tags))
+        .route("/pyramids", get(api::get_pyramids));
+    let routes = match admin_token {
+        // An empty token would let anyone in
+        Some(token) if !token.is_empty() => routes.merge(admin_routes(token, rate_limiter)),
+        _ => routes,
+    };
+    routes.route_layer(middleware::from_fn(metrics::track_requests))
+}
+
+/// The routes under `/api/v1/admin`, for requests bearing `token`. The token is checked before
+/// `rate_limiter`, so requests without it don't use up anyone's tokens
+fn admin_routes(token: &str, rate_limiter: Arc<RateLimiter>) -> Router<Arc<RwLock<RuntimeData>>> {
+    let rate_limit = middleware::from_fn_with_state(rate_limiter, rate_limit::limit);
+    Router::new()
+        .route(
+            "/admin/reload",
+            post(api::post_admin_reload).layer(rate_limit.clone()),
+        )
+        .route(
+            "/admin/benchmark",
+            post(api::post_admin_benchmark).layer(rate_limit),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            admin::require_token,
+        ))
+}
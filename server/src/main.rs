@@ -1,60 +1,29 @@
-#![feature(test)]
-//
-// Modules
-//
-
-mod axum_helpers;
-mod utoipa_helpers;
-mod web_api;
-mod web_appstate;
-mod web_routines;
-mod window_iterator;
-mod wrappers;
-
-//
-// Uses
-//
-
-use std::sync::Arc;
-
-use axum::extract::DefaultBodyLimit;
-use clap::{arg, Parser};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
+use axum::{extract::DefaultBodyLimit, http::HeaderValue, routing::get, Router};
+use clap::{arg, Parser};
 use tokio::sync::RwLock;
-
-use ::axum::{
-    body::Bytes,
-    extract::{FromRequest, Path, Request, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::{get, post},
-    Router,
-};
-
-use mongodb::Client;
-
 use tower::ServiceExt;
 use tower_http::{services::ServeDir, trace};
-extern crate tracing;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
 
 use ::utoipa::OpenApi;
 use ::utoipa_rapidoc::RapiDoc;
 use ::utoipa_redoc::{Redoc, Servable};
 use ::utoipa_swagger_ui::SwaggerUi;
 
-use rand::Rng;
-
-use web_api as api;
-use web_appstate::{AppState, RuntimeData};
-
-//
-// Implementation
-//
-
-async fn handler_404() -> Response {
-    (StatusCode::NOT_FOUND, "404 Not Found").into_response()
-}
+use jnickg_tile_server::{
+    api_routes, caching, connect_to_database,
+    cors::CorsSettings,
+    database::Db,
+    handler_404, health, jobs, metrics,
+    rate_limit::{RateLimitKey, RateLimiter},
+    request_id, schema,
+    tunables::TunablesStore,
+    upload_limits::UploadLimits,
+    web_api as api,
+    web_appstate::RuntimeData,
+};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -79,6 +48,24 @@ struct Args {
     #[arg(long = "db-port", value_name = "NUM")]
     db_port: u16,
 
+    /// Name of the MongoDB database to use
+    #[arg(long = "db-name", value_name = "STR", env = "TILER_DB_NAME", default_value = Db::DEFAULT_NAME)]
+    db_name: String,
+
+    /// Prefix for the name of every collection (and GridFS bucket) we use, so that several
+    /// instances can share one database
+    #[arg(
+        long = "collection-prefix",
+        value_name = "STR",
+        env = "TILER_COLLECTION_PREFIX",
+        default_value = ""
+    )]
+    collection_prefix: String,
+
+    /// How many times to try reaching MongoDB when starting, backing off between attempts
+    #[arg(long = "db-connect-attempts", value_name = "NUM", default_value_t = Db::DEFAULT_CONNECT_ATTEMPTS)]
+    db_connect_attempts: u32,
+
     /// The port on which the server runs
     #[arg(long, value_name = "NUM")]
     port: u16,
@@ -86,6 +73,69 @@ struct Args {
     /// Set the directory where static files are to be found for serving
     #[clap(long = "static-dir", default_value = "./dist")]
     static_dir: String,
+
+    /// Path to a JSON file of runtime-tunable settings. Re-read on SIGHUP, or when POSTing to
+    /// /api/v1/admin/reload
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+
+    /// Token that requests to /api/v1/admin must bear, as `Authorization: Bearer <token>`. Without
+    /// one, the admin routes aren't served. Prefer the environment variable, which doesn't show up
+    /// in process listings
+    #[arg(
+        long = "admin-token",
+        value_name = "STR",
+        env = "TILER_ADMIN_TOKEN",
+        hide_env_values = true
+    )]
+    admin_token: Option<String>,
+
+    /// Cache-Control header sent with images and pyramid levels. These can be replaced, but
+    /// responses carry an ETag, so revalidating is cheap
+    #[arg(long = "cache-control", value_name = "STR", default_value = caching::CacheControl::DEFAULT_IMAGES)]
+    cache_control: String,
+
+    /// Cache-Control header sent with pyramid tiles. Retiling a pyramid replaces its tiles under the
+    /// same names, so tiles shouldn't be cached as immutable
+    #[arg(long = "tile-cache-control", value_name = "STR", default_value = caching::CacheControl::DEFAULT_TILES)]
+    tile_cache_control: String,
+
+    /// Largest request body, in bytes, accepted by routes that take images or matrices. Other
+    /// routes keep axum's default limit of 2 MiB
+    #[arg(long = "max-upload-size", value_name = "BYTES", default_value_t = UploadLimits::DEFAULT_MAX_BYTES)]
+    max_upload_size: usize,
+
+    /// Most pixels (width times height) an uploaded image may have
+    #[arg(long = "max-image-pixels", value_name = "NUM", default_value_t = UploadLimits::DEFAULT_MAX_PIXELS)]
+    max_image_pixels: u64,
+
+    /// Comma-separated origins allowed to call the API from other sites, e.g.
+    /// http://localhost:8080, or * for any. By default, only same-origin requests are allowed
+    #[arg(long = "cors-origins", value_name = "LIST", value_delimiter = ',')]
+    cors_origins: Vec<String>,
+
+    /// Comma-separated methods cross-origin requests may use, or * for any
+    #[arg(long = "cors-methods", value_name = "LIST", value_delimiter = ',', default_value = CorsSettings::DEFAULT_METHODS)]
+    cors_methods: Vec<String>,
+
+    /// Comma-separated request headers cross-origin requests may send, or * for any
+    #[arg(long = "cors-headers", value_name = "LIST", value_delimiter = ',', default_value = CorsSettings::DEFAULT_HEADERS)]
+    cors_headers: Vec<String>,
+
+    /// How to tell clients apart for rate limiting: by IP address, or by their x-api-key header.
+    /// The limits themselves are tunables, set in the --config file
+    #[arg(long = "rate-limit-by", value_enum, default_value_t = RateLimitKey::Ip)]
+    rate_limit_by: RateLimitKey,
+
+    /// Requests taking longer than this many milliseconds are logged, with their route and sizes,
+    /// and counted under http_slow_requests_total. 0 turns this off
+    #[arg(long = "slow-request-ms", value_name = "MS", env = "TILER_SLOW_REQUEST_MS", default_value_t = metrics::DEFAULT_SLOW_REQUEST_MS)]
+    slow_request_ms: u64,
+
+    /// Workers to run in this process, claiming tiling jobs from the database. 0 leaves tiling to
+    /// workers elsewhere
+    #[arg(long = "tile-workers", value_name = "NUM", default_value_t = jobs::DEFAULT_WORKERS)]
+    tile_workers: usize,
 }
 
 #[tokio::main]
@@ -94,103 +144,138 @@ async fn main() {
 
     let mut state: RuntimeData = RuntimeData::new();
 
-    let password_str = std::fs::read_to_string(&args.pass).unwrap();
-    let uri = format!(
-        "mongodb://{}:{}@{}:{}/",
-        args.user, password_str, args.host, args.db_port
-    );
-    let client = Client::with_uri_str(uri).await;
-    let database = match client {
-        Ok(c) => c.database("tiler"),
+    let tunables = match TunablesStore::new(args.config.as_ref().map(Into::into)) {
+        Ok(t) => t,
         Err(_e) => {
-            eprintln!("Error: {:?}", _e);
+            eprintln!("Error: {}", _e);
             return;
         }
     };
-    state.db = Some(database);
-
+    let (log_filter_layer, log_filter_handle) =
+        reload::Layer::new(EnvFilter::new(&tunables.load().log_filter));
     tracing_subscriber::registry()
+        .with(log_filter_layer)
         .with(tracing_subscriber::fmt::layer())
         .init();
+    state.tunables = Arc::new(tunables.with_log_filter(log_filter_handle));
+
+    // Re-read tunables whenever we're poked with SIGHUP
+    #[cfg(unix)]
+    {
+        let tunables = state.tunables.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangups = signal(SignalKind::hangup()).unwrap();
+            while hangups.recv().await.is_some() {
+                if let Err(_e) = tunables.reload() {
+                    tracing::error!("Failed to reload tunables: {}", _e);
+                }
+            }
+        });
+    }
+
+    state.cache_control = match (
+        HeaderValue::from_str(&args.cache_control),
+        HeaderValue::from_str(&args.tile_cache_control),
+    ) {
+        (Ok(images), Ok(tiles)) => caching::CacheControl { images, tiles },
+        _ => {
+            eprintln!("Error: Cache-Control values must be valid header values");
+            return;
+        }
+    };
+
+    metrics::METRICS.set_slow_request_threshold(Duration::from_millis(args.slow_request_ms));
+
+    state.upload_limits = UploadLimits {
+        max_bytes: args.max_upload_size,
+        max_pixels: args.max_image_pixels,
+    };
+    let upload_limit = DefaultBodyLimit::max(args.max_upload_size);
+    // Limits are read from the tunables on every request, so reloading them takes effect at once
+    let rate_limiter = Arc::new(RateLimiter::new(state.tunables.clone(), args.rate_limit_by));
+
+    let database = match connect_to_database(
+        &args.host,
+        args.db_port,
+        &args.user,
+        &args.pass,
+        &args.db_name,
+        &args.collection_prefix,
+        args.db_connect_attempts,
+    )
+    .await
+    {
+        Ok(db) => db,
+        Err(_e) => {
+            eprintln!("Error: {}", _e);
+            return;
+        }
+    };
+    // Queries rely on these indexes, so they're made before we serve anything
+    if let Err(_e) = schema::bootstrap(&database).await {
+        eprintln!("Error: {}", _e);
+        return;
+    }
+    // Health checks notice if the connection goes bad, so it's replaced when next used
+    let _db_watch = database.watch(Db::DEFAULT_WATCH_INTERVAL);
+    state.db = Some(database);
+    let app_state = Arc::new(RwLock::new(state));
+
+    // Jobs queued before a restart are picked up again here
+    let _workers = jobs::start_workers(app_state.clone(), args.tile_workers);
+
     // https://carlosmv.hashnode.dev/adding-logging-and-tracing-to-an-axum-app-rust
     let trace_layer = trace::TraceLayer::new_for_http()
-        .make_span_with(trace::DefaultMakeSpan::new().level(tracing::Level::INFO))
+        .make_span_with(request_id::make_span)
         .on_response(trace::DefaultOnResponse::new().level(tracing::Level::INFO));
 
-    let api_routes = Router::new()
-        .route("/", get(api::get_api_index))
-        .route("/index.html", get(api::get_api_index))
-        .route("/hello", get(api::get_hello))
-        .route("/something", post(api::post_something))
-        .route(
-            "/something/:id",
-            get(api::get_something)
-                .put(api::put_something)
-                .delete(api::delete_something)
-                .post(api::post_something_with_id),
-        )
-        .route("/image", post(api::post_image))
-        .route("/images", get(api::get_images))
-        .route(
-            "/image/:name",
-            get(api::get_image)
-                .put(api::put_image)
-                .delete(api::delete_image),
-        )
-        .route(
-            "/level/:name",
-            get(api::get_level)
-                .put(api::put_level)
-                .delete(api::delete_level),
-        )
-        .route(
-            "/tile/:name",
-            get(api::get_tile)
-                .put(api::put_tile)
-                .delete(api::delete_tile),
-        )
-        .route(
-            "/matrix/:name",
-            post(api::post_matrix_with_name)
-                .get(api::get_matrix)
-                .put(api::put_matrix)
-                .delete(api::delete_matrix),
-        )
-        .route("/matrix/:name/dims", get(api::get_matrix_dims))
-        .route(
-            "/matrix/multiply/:name1/:name2",
-            post(api::post_matrix_multiply),
-        )
-        .route("/matrix/add/:name1/:name2", post(api::post_matrix_add))
-        .route(
-            "/matrix/subtract/:name1/:name2",
-            post(api::post_matrix_subtract),
-        )
-        .route("/pyramid", post(api::post_pyramid))
-        .route("/pyramid/:uuid", get(api::get_pyramid))
-        .route("/pyramids", get(api::get_pyramids));
+    let cors = CorsSettings {
+        origins: args.cors_origins,
+        methods: args.cors_methods,
+        headers: args.cors_headers,
+    };
+    let api_routes = api_routes(upload_limit, rate_limiter, args.admin_token.as_deref());
+    let api_routes = match cors.layer() {
+        Ok(Some(cors_layer)) => api_routes.layer(cors_layer),
+        Ok(None) => api_routes,
+        Err(_e) => {
+            eprintln!("Error: {}", _e);
+            return;
+        }
+    };
 
     let swagger_ui =
         SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", api::Documentation::openapi());
     let redoc_ui = Redoc::with_url("/redoc", api::Documentation::openapi());
     let rapidoc_ui = RapiDoc::new("/api-docs/openapi.json").path("/rapidoc");
 
-    let app = Router::new()
-        .fallback_service(get(|req| async move {
-            ServeDir::new(args.static_dir).oneshot(req).await.unwrap()
-        }))
-        .merge(swagger_ui)
-        .merge(redoc_ui)
-        .merge(rapidoc_ui)
-        .nest("/api/v1", api_routes)
-        .fallback(handler_404)
-        .layer(trace_layer)
-        .layer(DefaultBodyLimit::disable())
-        .with_state(Arc::new(RwLock::new(state)));
+    let app = request_id::with_request_ids(
+        Router::new()
+            .fallback_service(get(|req| async move {
+                ServeDir::new(args.static_dir).oneshot(req).await.unwrap()
+            }))
+            .merge(swagger_ui)
+            .merge(redoc_ui)
+            .merge(rapidoc_ui)
+            .route("/metrics", get(metrics::get_metrics))
+            .route("/healthz", get(health::get_healthz))
+            .route("/readyz", get(health::get_readyz))
+            .nest("/api/v1", api_routes)
+            .fallback(handler_404)
+            .layer(trace_layer),
+    )
+    .with_state(app_state);
 
     println!("Listening on port {}", args.port);
     let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", args.port))
         .await
         .unwrap();
-    ::axum::serve(listener, app).await.unwrap();
+    // Rate limits need each client's address
+    ::axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -0,0 +1,135 @@
+//! Matrix operations too big to run while a request waits.
+//!
+//! Multiplying (or solving with) large matrices can take long enough to hold up every other
+//! request that needs the app state. Instead, the inputs are copied out, the operation runs on a
+//! blocking thread with no lock held, and the result is stored as a named matrix when it's done.
+//! Until then, [`status`] says how the operation is going, and the matrix itself can't be read.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use jnickg_imaging::{dyn_matrix::DynMatrix, errors::Error};
+use serde::Serialize;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+use crate::web_appstate::RuntimeData;
+
+/// A matrix operation running in the background, or one that failed, by the name its result will
+/// be (or would have been) stored under
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatrixTask {
+    Running,
+    Failed(String),
+}
+
+/// Background operations, by result name. Finished ones are removed, their results having been
+/// stored as matrices
+pub type MatrixTasks = HashMap<String, MatrixTask>;
+
+/// How a background matrix operation is going
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MatrixTaskState {
+    Running,
+    Failed,
+
+    /// The result is stored, and can be fetched from `url`
+    Done,
+}
+
+/// The status of the matrix with a given name, as computed in the background
+#[derive(Clone, Debug, PartialEq, Serialize, ToSchema)]
+pub struct MatrixTaskStatus {
+    pub name: String,
+    pub state: MatrixTaskState,
+
+    /// Where the API serves the result, once it's done
+    pub url: String,
+
+    /// Where the API serves this status
+    pub status_url: String,
+
+    /// Why the operation failed, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl MatrixTaskStatus {
+    pub fn new(name: &str, state: MatrixTaskState, error: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            state,
+            url: format!("/api/v1/matrix/{}", name),
+            status_url: format!("/api/v1/matrix/{}/status", name),
+            error,
+        }
+    }
+}
+
+/// How the operation storing its result as the matrix `name` is going. Matrices that were stored
+/// some other way count as done. `None` if there's no such matrix or operation
+pub fn status(app: &RuntimeData, name: &str) -> Option<MatrixTaskStatus> {
+    match app.matrix_tasks.get(name) {
+        Some(MatrixTask::Running) => {
+            Some(MatrixTaskStatus::new(name, MatrixTaskState::Running, None))
+        }
+        Some(MatrixTask::Failed(error)) => Some(MatrixTaskStatus::new(
+            name,
+            MatrixTaskState::Failed,
+            Some(error.clone()),
+        )),
+        None => app
+            .matrices
+            .contains_key(name)
+            .then(|| MatrixTaskStatus::new(name, MatrixTaskState::Done, None)),
+    }
+}
+
+/// Fails if a result can't be stored as the matrix `name`: there's already a matrix of that name,
+/// or an operation running that will make one. Names of operations that failed can be reused
+pub fn check_name_free(app: &RuntimeData, name: &str) -> Result<(), Error> {
+    if app.matrices.contains_key(name) || app.matrix_tasks.get(name) == Some(&MatrixTask::Running) {
+        return Err(Error::conflict(format!("Matrix {}", name)));
+    }
+    Ok(())
+}
+
+/// Run `compute` on a blocking thread, and store its result as the matrix `name`, which must be
+/// free (see [`check_name_free`])
+pub async fn start<F>(
+    app_state: Arc<RwLock<RuntimeData>>,
+    name: String,
+    compute: F,
+) -> Result<MatrixTaskStatus, Error>
+where
+    F: FnOnce() -> Result<DynMatrix<f64>, Error> + Send + 'static,
+{
+    {
+        let app = &mut app_state.write().await;
+        check_name_free(app, &name)?;
+        app.matrix_tasks.insert(name.clone(), MatrixTask::Running);
+    }
+    tracing::info!(name, "Computing matrix in the background");
+
+    let status = MatrixTaskStatus::new(&name, MatrixTaskState::Running, None);
+    tokio::spawn(async move {
+        let result = tokio::task::spawn_blocking(compute)
+            .await
+            .unwrap_or_else(|_| Err(Error::internal("Matrix operation panicked")));
+        let app = &mut app_state.write().await;
+        match result {
+            Ok(matrix) => {
+                tracing::info!(name, "Computed matrix");
+                app.matrix_tasks.remove(&name);
+                app.matrices.insert(name, matrix);
+            }
+            Err(_e) => {
+                tracing::warn!(name, "Failed to compute matrix: {}", _e);
+                app.matrix_tasks
+                    .insert(name, MatrixTask::Failed(_e.to_string()));
+            }
+        }
+    });
+    Ok(status)
+}
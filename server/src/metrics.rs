@@ -0,0 +1,554 @@
+//! Server metrics, exposed at `/metrics` in the Prometheus text format.
+//!
+//! HTTP requests are counted, and timed, by [`track_requests`], which wraps the API routes. Besides
+//! a histogram of all their latencies, each route reports percentiles of its recent requests, and
+//! requests slower than a threshold (see [`Metrics::set_slow_request_threshold`]) are counted and
+//! logged with what they were for. Other metrics (tile generation, GridFS traffic, image cache
+//! validation) are recorded where the work happens, through [`METRICS`].
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, RawPathParams, Request},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Metrics for the whole server
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds, in seconds, of the buckets of request latency histograms
+pub const REQUEST_LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds, in seconds, of the buckets of the tile generation histogram. Tiling a pyramid
+/// takes far longer than serving a request
+pub const TILE_GENERATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// How many of each route's latest requests its rolling percentiles are taken over
+pub const RECENT_REQUESTS: usize = 1000;
+
+/// Percentiles reported over each route's recent requests
+pub const RECENT_QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+/// Requests taking longer than this are logged, unless told otherwise
+pub const DEFAULT_SLOW_REQUEST_MS: u64 = 1000;
+
+/// A Prometheus histogram, with fixed bucket bounds
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: &'static [f64],
+
+    /// Observations falling in each bucket (not cumulative). Sized on first use
+    counts: Vec<u64>,
+
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub const fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: Vec::new(),
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; self.bounds.len()];
+        }
+        if let Some(bucket) = self.bounds.iter().position(|&b| value <= b) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Append this histogram's samples to `out`. `labels` are the sample labels other than `le`,
+    /// already formatted (e.g. `method="GET"`), or empty
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (idx, bound) in self.bounds.iter().enumerate() {
+            cumulative += self.counts.get(idx).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, sep, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, sep, self.count
+        );
+        let braced = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, braced, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braced, self.count);
+    }
+}
+
+/// The latest observations, up to a fixed number, for percentiles that follow recent changes
+/// rather than averaging over everything since we started
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    capacity: usize,
+    samples: VecDeque<f64>,
+}
+
+impl RollingWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    /// The smallest observation at least a `q` fraction of them are no bigger than (the
+    /// nearest-rank percentile). `None` if there are none
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<f64>>();
+        sorted.sort_by(f64::total_cmp);
+        let rank = ((q * sorted.len() as f64).ceil() as usize).max(1);
+        sorted.get(rank - 1).copied()
+    }
+
+    /// Append the window's samples to `out`, as a Prometheus summary. `labels` are as for
+    /// [`Histogram`]'s
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for &q in RECENT_QUANTILES {
+            if let Some(value) = self.quantile(q) {
+                let _ = writeln!(
+                    out,
+                    "{}{{{}{}quantile=\"{}\"}} {}",
+                    name, labels, sep, q, value
+                );
+            }
+        }
+        let braced = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let sum = self.samples.iter().sum::<f64>();
+        let _ = writeln!(out, "{}_sum{} {}", name, braced, sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braced, self.samples.len());
+    }
+}
+
+/// What a request is counted under
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestKey {
+    method: String,
+
+    /// The route's pattern (e.g. `/api/v1/image/:name`), so each image doesn't get its own series
+    route: String,
+}
+
+#[derive(Debug, Clone)]
+struct RequestStats {
+    /// Responses sent, by status code
+    statuses: BTreeMap<u16, u64>,
+
+    latency: Histogram,
+
+    /// Latencies of the most recent requests, in seconds
+    recent: RollingWindow,
+
+    /// Requests slower than the slow request threshold
+    slow: u64,
+}
+
+/// Every metric the server keeps
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestKey, RequestStats>>,
+    tile_generation: Mutex<Histogram>,
+    gridfs_bytes_read: AtomicU64,
+    gridfs_bytes_written: AtomicU64,
+    image_cache_hits: AtomicU64,
+    image_cache_misses: AtomicU64,
+
+    /// Requests taking longer than this many milliseconds are slow. Zero if none are
+    slow_request_ms: AtomicU64,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            requests: Mutex::new(BTreeMap::new()),
+            tile_generation: Mutex::new(Histogram::new(TILE_GENERATION_BUCKETS)),
+            gridfs_bytes_read: AtomicU64::new(0),
+            gridfs_bytes_written: AtomicU64::new(0),
+            image_cache_hits: AtomicU64::new(0),
+            image_cache_misses: AtomicU64::new(0),
+            slow_request_ms: AtomicU64::new(DEFAULT_SLOW_REQUEST_MS),
+        }
+    }
+
+    /// Count requests taking longer than `threshold` as slow, and log them. Zero turns this off
+    pub fn set_slow_request_threshold(&self, threshold: Duration) {
+        self.slow_request_ms
+            .store(threshold.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Whether a request taking `latency` is slow
+    pub fn is_slow(&self, latency: Duration) -> bool {
+        match self.slow_request_ms.load(Ordering::Relaxed) {
+            0 => false,
+            ms => latency > Duration::from_millis(ms),
+        }
+    }
+
+    /// Count and time a request. Returns whether it was slow (see [`Metrics::is_slow`])
+    pub fn record_request(
+        &self,
+        method: &str,
+        route: &str,
+        status: u16,
+        latency: Duration,
+    ) -> bool {
+        let key = RequestKey {
+            method: method.to_string(),
+            route: route.to_string(),
+        };
+        let slow = self.is_slow(latency);
+        let mut requests = self.requests.lock().unwrap();
+        let stats = requests.entry(key).or_insert_with(|| RequestStats {
+            statuses: BTreeMap::new(),
+            latency: Histogram::new(REQUEST_LATENCY_BUCKETS),
+            recent: RollingWindow::new(RECENT_REQUESTS),
+            slow: 0,
+        });
+        *stats.statuses.entry(status).or_default() += 1;
+        stats.latency.observe(latency.as_secs_f64());
+        stats.recent.observe(latency.as_secs_f64());
+        stats.slow += slow as u64;
+        slow
+    }
+
+    /// Record how long it took to tile a whole pyramid
+    pub fn record_tile_generation(&self, duration: Duration) {
+        self.tile_generation
+            .lock()
+            .unwrap()
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn add_gridfs_bytes_read(&self, bytes: usize) {
+        self.gridfs_bytes_read
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_gridfs_bytes_written(&self, bytes: usize) {
+        self.gridfs_bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Record whether a conditional image request found the client's copy current
+    pub fn record_image_cache(&self, hit: bool) {
+        let counter = match hit {
+            true => &self.image_cache_hits,
+            false => &self.image_cache_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Everything, in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let requests = self.requests.lock().unwrap().clone();
+
+        out.push_str("# HELP http_requests_total HTTP requests handled, by route and status\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for (key, stats) in requests.iter() {
+            for (status, count) in stats.statuses.iter() {
+                let _ = writeln!(
+                    out,
+                    "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                    key.method,
+                    escape_label(&key.route),
+                    status,
+                    count
+                );
+            }
+        }
+
+        out.push_str("# HELP http_request_duration_seconds Time taken to handle HTTP requests\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (key, stats) in requests.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\"",
+                key.method,
+                escape_label(&key.route)
+            );
+            stats
+                .latency
+                .render(&mut out, "http_request_duration_seconds", &labels);
+        }
+
+        out.push_str(
+            "# HELP http_request_recent_duration_seconds Percentiles of the time taken to handle \
+             each route's most recent requests\n",
+        );
+        out.push_str("# TYPE http_request_recent_duration_seconds summary\n");
+        for (key, stats) in requests.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\"",
+                key.method,
+                escape_label(&key.route)
+            );
+            stats
+                .recent
+                .render(&mut out, "http_request_recent_duration_seconds", &labels);
+        }
+
+        out.push_str(
+            "# HELP http_slow_requests_total HTTP requests slower than the slow request threshold\n",
+        );
+        out.push_str("# TYPE http_slow_requests_total counter\n");
+        for (key, stats) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "http_slow_requests_total{{method=\"{}\",route=\"{}\"}} {}",
+                key.method,
+                escape_label(&key.route),
+                stats.slow
+            );
+        }
+
+        out.push_str("# HELP tile_generation_seconds Time taken to tile a whole pyramid\n");
+        out.push_str("# TYPE tile_generation_seconds histogram\n");
+        self.tile_generation
+            .lock()
+            .unwrap()
+            .render(&mut out, "tile_generation_seconds", "");
+
+        let counters = [
+            (
+                "gridfs_read_bytes_total",
+                "Bytes downloaded from GridFS",
+                &self.gridfs_bytes_read,
+            ),
+            (
+                "gridfs_written_bytes_total",
+                "Bytes uploaded to GridFS",
+                &self.gridfs_bytes_written,
+            ),
+            (
+                "image_cache_hits_total",
+                "Image requests answered with 304 Not Modified",
+                &self.image_cache_hits,
+            ),
+            (
+                "image_cache_misses_total",
+                "Image requests that sent the image",
+                &self.image_cache_misses,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Escape a label value as the exposition format requires
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The `Content-Length` of a request or response, if it says
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Middleware counting and timing every request to the routes it wraps. Slow ones are logged with
+/// the route, its parameters (e.g. the image's name), and how big the request and response were.
+///
+/// Add it with `route_layer`, so that the matched route is known by the time it runs.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |p| p.as_str().to_string());
+    let request_bytes = content_length(request.headers());
+    let (mut parts, body) = request.into_parts();
+    let params = match RawPathParams::from_request_parts(&mut parts, &()).await {
+        Ok(params) => params
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(","),
+        Err(_) => String::new(),
+    };
+    let request = Request::from_parts(parts, body);
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let latency = start.elapsed();
+    let status = response.status().as_u16();
+    if METRICS.record_request(&method, &route, status, latency) {
+        tracing::warn!(
+            method,
+            route,
+            params,
+            status,
+            elapsed_ms = latency.as_secs_f64() * 1000.0,
+            request_bytes = ?request_bytes,
+            response_bytes = ?content_length(response.headers()),
+            "Slow request"
+        );
+    }
+    response
+}
+
+pub async fn get_metrics() -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut h = Histogram::new(&[1.0, 2.0]);
+        h.observe(0.5);
+        h.observe(1.5);
+        h.observe(1.5);
+        h.observe(3.0);
+
+        let mut out = String::new();
+        h.render(&mut out, "x", "a=\"b\"");
+        assert!(out.contains("x_bucket{a=\"b\",le=\"1\"} 1\n"));
+        assert!(out.contains("x_bucket{a=\"b\",le=\"2\"} 3\n"));
+        assert!(out.contains("x_bucket{a=\"b\",le=\"+Inf\"} 4\n"));
+        assert!(out.contains("x_sum{a=\"b\"} 6.5\n"));
+        assert!(out.contains("x_count{a=\"b\"} 4\n"));
+    }
+
+    #[test]
+    fn empty_histogram_renders_zeroes() {
+        let mut out = String::new();
+        Histogram::new(&[1.0]).render(&mut out, "x", "");
+        assert!(out.contains("x_bucket{le=\"1\"} 0\n"));
+        assert!(out.contains("x_count 0\n"));
+    }
+
+    #[test]
+    fn requests_are_counted_by_route_and_status() {
+        let m = Metrics::new();
+        m.record_request("GET", "/image/:name", 200, Duration::from_millis(3));
+        m.record_request("GET", "/image/:name", 200, Duration::from_millis(30));
+        m.record_request("GET", "/image/:name", 404, Duration::from_millis(1));
+        m.add_gridfs_bytes_read(100);
+        m.add_gridfs_bytes_read(23);
+        m.record_image_cache(true);
+
+        let out = m.render();
+        assert!(out.contains(
+            "http_requests_total{method=\"GET\",route=\"/image/:name\",status=\"200\"} 2\n"
+        ));
+        assert!(out.contains(
+            "http_requests_total{method=\"GET\",route=\"/image/:name\",status=\"404\"} 1\n"
+        ));
+        assert!(out.contains(
+            "http_request_duration_seconds_count{method=\"GET\",route=\"/image/:name\"} 3\n"
+        ));
+        assert!(out.contains("gridfs_read_bytes_total 123\n"));
+        assert!(out.contains("image_cache_hits_total 1\n"));
+        assert!(out.contains("image_cache_misses_total 0\n"));
+    }
+
+    #[test]
+    fn recent_percentiles_follow_the_latest_requests() {
+        let mut window = RollingWindow::new(10);
+        assert_eq!(window.quantile(0.5), None);
+        for ms in 1..=10 {
+            window.observe(ms as f64);
+        }
+        assert_eq!(window.quantile(0.5), Some(5.0));
+        assert_eq!(window.quantile(0.9), Some(9.0));
+        assert_eq!(window.quantile(0.99), Some(10.0));
+
+        // The oldest requests are forgotten
+        for _ in 0..10 {
+            window.observe(100.0);
+        }
+        assert_eq!(window.quantile(0.5), Some(100.0));
+
+        let mut out = String::new();
+        window.render(&mut out, "x", "");
+        assert!(out.contains("x{quantile=\"0.5\"} 100\n"));
+        assert!(out.contains("x_count 10\n"));
+    }
+
+    #[test]
+    fn slow_requests_are_counted() {
+        let m = Metrics::new();
+        m.set_slow_request_threshold(Duration::from_millis(100));
+        assert!(!m.record_request("POST", "/pyramid", 201, Duration::from_millis(50)));
+        assert!(m.record_request("POST", "/pyramid", 201, Duration::from_millis(500)));
+        let out = m.render();
+        assert!(out.contains("http_slow_requests_total{method=\"POST\",route=\"/pyramid\"} 1\n"));
+        assert!(out.contains(
+            "http_request_recent_duration_seconds{method=\"POST\",route=\"/pyramid\",quantile=\"0.5\"} 0.05\n"
+        ));
+
+        m.set_slow_request_threshold(Duration::ZERO);
+        assert!(!m.record_request("POST", "/pyramid", 201, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
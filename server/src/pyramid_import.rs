@@ -0,0 +1,386 @@
+//! Reading pyramids that were built and tiled elsewhere, e.g. by the library's `make_pyramid`
+//! example, so they can be served without being built again.
+//!
+//! An import is a zip archive laid out the way `make_pyramid` writes its output directory:
+//!
+//! - `manifest.json`: a pyramid document (see [`documents::Pyramid`]), with its tiles listed. Its
+//!   `image_urls` are the paths of the level images within the archive
+//! - `levels/`: each level, in the manifest's `mime_type`
+//! - `tiles/{tile name}.{extension}.{content encoding}`: each tile, in its `mime_type` (or the
+//!   manifest's), compressed with the manifest's codec
+//!
+//! Everything is checked against the manifest before anything is stored: every file must be
+//! there, and every image must be the size the manifest says it is. Each entry (and each tile,
+//! once decompressed) is held to the upload limit, and all of them together to
+//! [`MAX_INFLATION_FACTOR`] times that, so a small archive can't inflate to gigabytes.
+
+use std::io::{Cursor, Read};
+
+use image::ImageFormat;
+use jnickg_imaging::{
+    documents::{self, PyramidLevel, PyramidTiles},
+    errors::Error,
+    ipr,
+};
+use zip::{result::ZipError, ZipArchive};
+
+use crate::upload_limits::UploadLimits;
+
+/// Where the manifest is in an archive
+pub const MANIFEST_PATH: &str = "manifest.json";
+
+/// How many times [`UploadLimits::max_bytes`] an archive may inflate to, counting every entry
+/// read from it and every tile decompressed
+pub const MAX_INFLATION_FACTOR: u64 = 4;
+
+/// A pyramid read from an archive, and checked against its manifest
+#[derive(Debug)]
+pub struct PyramidArchive {
+    pub manifest: documents::Pyramid,
+
+    /// Format the levels are in
+    pub level_format: ImageFormat,
+
+    /// Each level's encoded image, largest first
+    pub levels: Vec<Vec<u8>>,
+
+    /// Each level's tiles, in index order, as the manifest lists them
+    pub tiles: Vec<Vec<ArchivedTile>>,
+}
+
+/// One tile, still compressed as it was in the archive
+#[derive(Debug)]
+pub struct ArchivedTile {
+    pub format: ImageFormat,
+    pub data: Vec<u8>,
+}
+
+impl PyramidArchive {
+    /// Read and check the pyramid in the zip archive `data`. Entries larger than
+    /// `limits.max_bytes` uncompressed, archives inflating to more than [`MAX_INFLATION_FACTOR`]
+    /// times that in all, and images with more pixels than the limits allow, are turned away
+    pub fn read(data: &[u8], limits: &UploadLimits) -> Result<Self, Error> {
+        let max_inflated = (limits.max_bytes as u64).saturating_mul(MAX_INFLATION_FACTOR);
+        Self::read_at_most(data, limits, max_inflated)
+    }
+
+    /// [`PyramidArchive::read`], inflating at most `max_inflated` bytes in all
+    fn read_at_most(data: &[u8], limits: &UploadLimits, max_inflated: u64) -> Result<Self, Error> {
+        let mut archive = ZipArchive::new(Cursor::new(data))
+            .map_err(|_e| Error::unprocessable(format!("Body is not a zip archive: {}", _e)))?;
+        let mut budget = InflationBudget::new(max_inflated);
+        let manifest = read_entry(&mut archive, MANIFEST_PATH, limits, &mut budget)?;
+        let manifest: documents::Pyramid = serde_json::from_slice(&manifest)
+            .map_err(|_e| Error::unprocessable(format!("Invalid {}: {}", MANIFEST_PATH, _e)))?;
+
+        let level_docs = match &manifest.tiles {
+            PyramidTiles::Levels(levels) if !levels.is_empty() => levels,
+            _ => {
+                return Err(Error::unprocessable(
+                    "Only pyramids whose tiles are listed in the manifest can be imported",
+                ))
+            }
+        };
+        if manifest.image_urls.len() != level_docs.len() {
+            return Err(Error::unprocessable(format!(
+                "Manifest lists {} level images, but tiles for {} levels",
+                manifest.image_urls.len(),
+                level_docs.len()
+            )));
+        }
+        if !manifest.level_dims.is_empty() && manifest.level_dims.len() != level_docs.len() {
+            return Err(Error::unprocessable(format!(
+                "Manifest lists dimensions for {} levels, but tiles for {}",
+                manifest.level_dims.len(),
+                level_docs.len()
+            )));
+        }
+        let level_format = image_format(&manifest.mime_type)?;
+
+        let mut levels = Vec::new();
+        let mut tiles = Vec::new();
+        for (l, level) in level_docs.iter().enumerate() {
+            if level.level as usize != l {
+                return Err(Error::unprocessable(format!(
+                    "Manifest lists level {} where level {} should be",
+                    level.level, l
+                )));
+            }
+            if let Some(dims) = manifest.level_dims.get(l) {
+                check_dimensions(
+                    &format!("Level {}", l),
+                    (dims.width, dims.height),
+                    (level.width, level.height),
+                )?;
+            }
+
+            let path = &manifest.image_urls[l];
+            let data = read_entry(&mut archive, path, limits, &mut budget)?;
+            let dims = image_dimensions(path, &data, level_format, limits)?;
+            check_dimensions(path, (level.width, level.height), dims)?;
+            levels.push(data);
+
+            check_tile_layout(level)?;
+            let mut level_tiles = Vec::new();
+            for tile in &level.tiles {
+                let format = match &tile.mime_type {
+                    Some(mime_type) => image_format(mime_type)?,
+                    None => level_format,
+                };
+                let path = format!(
+                    "tiles/{}.{}.{}",
+                    tile.name,
+                    format.extensions_str()[0],
+                    manifest.compression.content_encoding()
+                );
+                let data = read_entry(&mut archive, &path, limits, &mut budget)?;
+                let max_bytes = budget.allowance(limits);
+                let decompressed = manifest
+                    .compression
+                    .decompress_at_most(&data, max_bytes)
+                    .map_err(|e| match e {
+                        Error::TooLarge(_) => Error::too_large(format!(
+                            "{} decompresses to more than {} bytes",
+                            path, max_bytes
+                        )),
+                        _e => {
+                            Error::unprocessable(format!("Failed to decompress {}: {}", path, _e))
+                        }
+                    })?;
+                budget.spend(&path, decompressed.len())?;
+                let dims = image_dimensions(&path, &decompressed, format, limits)?;
+                check_dimensions(&path, (tile.width, tile.height), dims)?;
+                level_tiles.push(ArchivedTile { format, data });
+            }
+            tiles.push(level_tiles);
+        }
+
+        Ok(Self {
+            manifest,
+            level_format,
+            levels,
+            tiles,
+        })
+    }
+}
+
+/// What's left of the bytes an archive may inflate to
+struct InflationBudget {
+    max_bytes: u64,
+    remaining: u64,
+}
+
+impl InflationBudget {
+    fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            remaining: max_bytes,
+        }
+    }
+
+    /// Most bytes the next entry or tile may inflate to
+    fn allowance(&self, limits: &UploadLimits) -> u64 {
+        (limits.max_bytes as u64).min(self.remaining)
+    }
+
+    /// Count `bytes` inflated from `path` against the budget
+    fn spend(&mut self, path: &str, bytes: usize) -> Result<(), Error> {
+        self.remaining = self.remaining.checked_sub(bytes as u64).ok_or_else(|| {
+            Error::too_large(format!(
+                "Archive inflates to more than the limit of {} bytes, as of {}",
+                self.max_bytes, path
+            ))
+        })?;
+        Ok(())
+    }
+}
+
+/// Read the whole of the entry at `path`, refusing to inflate more than `limits.max_bytes` of it,
+/// or more than is left of `budget`
+fn read_entry(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    path: &str,
+    limits: &UploadLimits,
+    budget: &mut InflationBudget,
+) -> Result<Vec<u8>, Error> {
+    let entry = archive.by_name(path).map_err(|e| match e {
+        ZipError::FileNotFound => Error::unprocessable(format!("Archive is missing {}", path)),
+        _e => Error::unprocessable(format!("Failed to read {} from archive: {}", path, _e)),
+    })?;
+    let max_bytes = limits.max_bytes as u64;
+    let mut data = Vec::new();
+    // The size in the archive's header is only what the archive claims, so the read is capped too
+    entry
+        .take(budget.allowance(limits) + 1)
+        .read_to_end(&mut data)
+        .map_err(|_e| Error::unprocessable(format!("Failed to read {}: {}", path, _e)))?;
+    if data.len() as u64 > max_bytes {
+        return Err(Error::too_large(format!(
+            "{} is larger than the limit of {} bytes",
+            path, max_bytes
+        )));
+    }
+    budget.spend(path, data.len())?;
+    Ok(data)
+}
+
+/// The readable image format with the given MIME type
+fn image_format(mime_type: &str) -> Result<ImageFormat, Error> {
+    ImageFormat::from_mime_type(mime_type)
+        .filter(|f| f.reading_enabled())
+        .ok_or_else(|| {
+            Error::unprocessable(format!(
+                "Images of type \"{}\" can't be imported",
+                mime_type
+            ))
+        })
+}
+
+/// Dimensions of the image `data`, read from its header, after checking them against the limits
+fn image_dimensions(
+    path: &str,
+    data: &[u8],
+    format: ImageFormat,
+    limits: &UploadLimits,
+) -> Result<(u32, u32), Error> {
+    let (width, height) = ipr::encoded_image_dimensions(data, format, None).map_err(|_e| {
+        Error::unprocessable(format!("Failed to read dimensions of {}: {}", path, _e))
+    })?;
+    limits.check_dimensions(width, height)?;
+    Ok((width, height))
+}
+
+fn check_dimensions(what: &str, expected: (u32, u32), actual: (u32, u32)) -> Result<(), Error> {
+    if expected != actual {
+        return Err(Error::unprocessable(format!(
+            "{} is {}x{}, but the manifest says {}x{}",
+            what, actual.0, actual.1, expected.0, expected.1
+        )));
+    }
+    Ok(())
+}
+
+/// Check that a level's tiles are numbered in order, lie within the level, and cover all of it
+fn check_tile_layout(level: &PyramidLevel) -> Result<(), Error> {
+    let mut area = 0u64;
+    for (t_idx, tile) in level.tiles.iter().enumerate() {
+        if tile.index as usize != t_idx {
+            return Err(Error::unprocessable(format!(
+                "Level {} lists tile {} where tile {} should be",
+                level.level, tile.index, t_idx
+            )));
+        }
+        let in_bounds = tile.width > 0
+            && tile.height > 0
+            && tile
+                .x
+                .checked_add(tile.width)
+                .is_some_and(|r| r <= level.width)
+            && tile
+                .y
+                .checked_add(tile.height)
+                .is_some_and(|b| b <= level.height);
+        if !in_bounds {
+            return Err(Error::unprocessable(format!(
+                "Tile {} of level {} doesn't fit in the level",
+                t_idx, level.level
+            )));
+        }
+        area += tile.width as u64 * tile.height as u64;
+    }
+    if area != level.width as u64 * level.height as u64 {
+        return Err(Error::unprocessable(format!(
+            "The tiles of level {} don't cover it",
+            level.level
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pyramid_archive_files, synthetic_image, zip_files};
+
+    fn files() -> Vec<(String, Vec<u8>)> {
+        pyramid_archive_files("imported", &synthetic_image(40, 20), 16)
+    }
+
+    fn read(files: &[(String, Vec<u8>)]) -> Result<PyramidArchive, Error> {
+        PyramidArchive::read(&zip_files(files), &UploadLimits::default())
+    }
+
+    #[test]
+    fn reads_a_tiled_pyramid() {
+        let archive = read(&files()).unwrap();
+        assert_eq!(archive.level_format, ImageFormat::Png);
+        assert_eq!(archive.levels.len(), archive.manifest.image_urls.len());
+        // 40x20 is 3x2 tiles, and 20x10 is 2x1
+        assert_eq!(archive.tiles[0].len(), 6);
+        assert_eq!(archive.tiles[1].len(), 2);
+        assert!(archive.tiles[2..].iter().all(|t| t.len() == 1));
+    }
+
+    #[test]
+    fn missing_tiles_are_rejected() {
+        let mut files = files();
+        files.retain(|(path, _)| !path.starts_with("tiles/imported_L1_T1."));
+        let e = read(&files).unwrap_err();
+        assert!(matches!(e, Error::Unprocessable(_)));
+        assert!(e.to_string().contains("imported_L1_T1"));
+    }
+
+    #[test]
+    fn mismatched_levels_are_rejected() {
+        let mut files = files();
+        let (_, level) = files
+            .iter_mut()
+            .find(|(path, _)| path == "levels/imported_L1.png")
+            .unwrap();
+        let mut png = Vec::new();
+        synthetic_image(21, 10)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        *level = png;
+        let e = read(&files).unwrap_err();
+        assert!(e.to_string().contains("21x10"));
+    }
+
+    #[test]
+    fn archives_without_manifests_are_rejected() {
+        let mut files = files();
+        files.retain(|(path, _)| path != MANIFEST_PATH);
+        assert!(matches!(read(&files), Err(Error::Unprocessable(_))));
+        assert!(matches!(
+            PyramidArchive::read(b"not a zip", &UploadLimits::default()),
+            Err(Error::Unprocessable(_))
+        ));
+    }
+
+    #[test]
+    fn archives_inflating_past_the_limit_are_rejected() {
+        // Enough for every entry, but not for the tiles once they're decompressed too
+        let files = files();
+        let entries = files.iter().map(|(_, data)| data.len() as u64).sum();
+        let e = PyramidArchive::read_at_most(&zip_files(&files), &UploadLimits::default(), entries)
+            .unwrap_err();
+        assert!(matches!(e, Error::TooLarge(_)));
+
+        let all = entries * MAX_INFLATION_FACTOR;
+        assert!(
+            PyramidArchive::read_at_most(&zip_files(&files), &UploadLimits::default(), all).is_ok()
+        );
+    }
+
+    #[test]
+    fn oversized_entries_are_rejected() {
+        let limits = UploadLimits {
+            max_bytes: 64,
+            ..UploadLimits::default()
+        };
+        assert!(matches!(
+            PyramidArchive::read(&zip_files(&files()), &limits),
+            Err(Error::TooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,359 @@
+//! Rate limits on expensive routes.
+//!
+//! Building a pyramid or multiplying big matrices ties up the server for a while, so a client
+//! hammering those routes could starve everyone else. Each client gets a token bucket: it holds up
+//! to `rate_limit_burst` tokens, refills at `rate_limit_per_minute` tokens a minute, and each
+//! request to a limited route takes a token. Requests finding the bucket empty get
+//! `429 Too Many Requests`, with a `Retry-After` header saying when a token will be available.
+//! Both settings are [`Tunables`](crate::tunables::Tunables), so reloading them changes the limits
+//! without a restart.
+//!
+//! Clients are told apart by IP address, or optionally by the `x-api-key` header they send. There
+//! are no real API keys (anyone can send any key), so keying by them only makes sense behind
+//! something that checks them, or to share one address between cooperating clients. Even so, an
+//! address only gets buckets for [`RateLimiter::MAX_KEYS_PER_IP`] keys; any other keys it sends
+//! share its address's bucket, so a client can't dodge its limit by making up keys.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderName},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::ValueEnum;
+use jnickg_imaging::errors::Error;
+
+use crate::{tunables::TunablesStore, wrappers::WrappedError};
+
+/// The header identifying a client when limiting by [`RateLimitKey::ApiKey`]
+pub const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// How to tell clients apart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RateLimitKey {
+    /// By the address they connect from
+    #[default]
+    Ip,
+
+    /// By their `x-api-key` header, or their address if they don't send one
+    ApiKey,
+}
+
+/// Who sent a request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Client {
+    /// The address they connect from, if known
+    pub ip: Option<IpAddr>,
+
+    /// The API key they sent, if clients are told apart by them
+    pub api_key: Option<String>,
+}
+
+impl Client {
+    /// Name of the bucket for the client's address. Clients at unknown addresses (which only
+    /// happens if the server wasn't started with connection info) share one bucket
+    fn ip_bucket(&self) -> String {
+        match self.ip {
+            Some(ip) => format!("ip:{}", ip),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+
+    /// For an API key's bucket, the bucket of the address that started it, which it counts
+    /// against
+    started_by: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_name: HashMap<String, Bucket>,
+
+    /// How many API key buckets were started by each address's bucket
+    keys_started_by: HashMap<String, usize>,
+}
+
+/// Token buckets for every client seen recently
+pub struct RateLimiter {
+    tunables: Arc<TunablesStore>,
+    key: RateLimitKey,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Once there are this many buckets, full ones are dropped. A full bucket is the same as no
+    /// bucket, so this only costs the time to look through them
+    const PRUNE_THRESHOLD: usize = 10_000;
+
+    /// Most API keys that get buckets of their own from one address
+    pub const MAX_KEYS_PER_IP: usize = 8;
+
+    /// Limit requests per the current rate limit settings in `tunables`, telling clients apart by
+    /// `key`
+    pub fn new(tunables: Arc<TunablesStore>, key: RateLimitKey) -> Self {
+        Self {
+            tunables,
+            key,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// Take a token from `client`'s bucket at time `now`. If it's empty, returns how long until
+    /// it won't be
+    pub fn check(&self, client: &Client, now: Instant) -> Result<(), Duration> {
+        let tunables = self.tunables.load();
+        let rate = tunables.rate_limit_per_minute as f64 / 60.0;
+        if rate <= 0.0 {
+            return Ok(());
+        }
+        let capacity = tunables.rate_limit_burst.max(1) as f64;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            by_name,
+            keys_started_by,
+        } = &mut *buckets;
+        if by_name.len() >= Self::PRUNE_THRESHOLD {
+            by_name.retain(|_, b| {
+                let elapsed = now.saturating_duration_since(b.updated).as_secs_f64();
+                let full = b.tokens + elapsed * rate >= capacity;
+                if full {
+                    let ip = b.started_by.as_ref();
+                    if let Some(count) = ip.and_then(|ip| keys_started_by.get_mut(ip)) {
+                        *count -= 1;
+                    }
+                }
+                !full
+            });
+            keys_started_by.retain(|_, count| *count > 0);
+        }
+
+        let ip_bucket = client.ip_bucket();
+        let (name, started_by) = match &client.api_key {
+            Some(key) => {
+                let name = format!("key:{}", key);
+                let started = keys_started_by.get(&ip_bucket).copied().unwrap_or(0);
+                if by_name.contains_key(&name) || started < Self::MAX_KEYS_PER_IP {
+                    (name, Some(ip_bucket))
+                } else {
+                    (ip_bucket, None)
+                }
+            }
+            None => (ip_bucket, None),
+        };
+        let bucket = by_name.entry(name).or_insert_with(|| {
+            if let Some(ip) = &started_by {
+                *keys_started_by.entry(ip.clone()).or_default() += 1;
+            }
+            Bucket {
+                tokens: capacity,
+                updated: now,
+                started_by,
+            }
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Who sent `request`. Its API key is only looked at when limiting by
+    /// [`RateLimitKey::ApiKey`]
+    fn client_of(&self, request: &Request) -> Client {
+        let api_key = match self.key {
+            RateLimitKey::ApiKey => request
+                .headers()
+                .get(X_API_KEY)
+                .map(|key| String::from_utf8_lossy(key.as_bytes()).into_owned()),
+            RateLimitKey::Ip => None,
+        };
+        let ip = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Client { ip, api_key }
+    }
+}
+
+/// Middleware enforcing `limiter` on the routes it wraps
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = limiter.client_of(&request);
+    let Err(retry_after) = limiter.check(&client, Instant::now()) else {
+        return next.run(request).await;
+    };
+
+    // Retry-After only takes whole seconds, so round up rather than invite an early retry
+    let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    tracing::warn!(client = ?client, retry_after, "Rate limited");
+    let error = Error::rate_limited(format!(
+        "Too many requests. Retry in {} seconds",
+        retry_after
+    ));
+    (
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        WrappedError(error),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::tunables::Tunables;
+
+    fn limiter(per_minute: u32, burst: u32) -> RateLimiter {
+        let tunables = TunablesStore::fixed(Tunables {
+            rate_limit_per_minute: per_minute,
+            rate_limit_burst: burst,
+            ..Default::default()
+        });
+        RateLimiter::new(Arc::new(tunables), RateLimitKey::ApiKey)
+    }
+
+    fn key(key: &str) -> Client {
+        Client {
+            ip: None,
+            api_key: Some(key.to_string()),
+        }
+    }
+
+    #[test]
+    fn bursts_are_allowed_then_refilled() {
+        let limiter = limiter(60, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check(&key("a"), start).is_ok());
+        }
+        let retry_after = limiter.check(&key("a"), start).unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+
+        // Other clients have their own buckets
+        assert!(limiter.check(&key("b"), start).is_ok());
+
+        // One token a second
+        assert!(limiter
+            .check(&key("a"), start + Duration::from_secs(1))
+            .is_ok());
+        assert!(limiter
+            .check(&key("a"), start + Duration::from_secs(1))
+            .is_err());
+
+        // Buckets never hold more than the burst
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert!(limiter.check(&key("a"), later).is_ok());
+        }
+        assert!(limiter.check(&key("a"), later).is_err());
+    }
+
+    #[test]
+    fn zero_rate_disables_limits() {
+        let limiter = limiter(0, 1);
+        let now = Instant::now();
+        assert!((0..1000).all(|_| limiter.check(&key("a"), now).is_ok()));
+    }
+
+    #[test]
+    fn addresses_only_get_buckets_for_a_few_keys() {
+        let limiter = limiter(60, 1);
+        let now = Instant::now();
+        let from = |ip: u8, key: &str| Client {
+            ip: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, ip))),
+            api_key: Some(key.to_string()),
+        };
+        for k in 0..RateLimiter::MAX_KEYS_PER_IP {
+            assert!(limiter.check(&from(1, &k.to_string()), now).is_ok());
+        }
+
+        // Further keys share the address's bucket
+        assert!(limiter.check(&from(1, "extra"), now).is_ok());
+        assert!(limiter.check(&from(1, "another"), now).is_err());
+
+        // Which other addresses don't
+        assert!(limiter.check(&from(2, "another"), now).is_ok());
+    }
+
+    #[test]
+    fn reloaded_limits_apply_right_away() {
+        let path =
+            std::env::temp_dir().join(format!("tunables_rate_limit_{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{ "rate_limit_per_minute": 60, "rate_limit_burst": 1 }"#,
+        )
+        .unwrap();
+        let tunables = Arc::new(TunablesStore::new(Some(path.clone())).unwrap());
+        let limiter = RateLimiter::new(tunables.clone(), RateLimitKey::ApiKey);
+        let now = Instant::now();
+        assert!(limiter.check(&key("a"), now).is_ok());
+        assert!(limiter.check(&key("a"), now).is_err());
+
+        std::fs::write(&path, r#"{ "rate_limit_per_minute": 0 }"#).unwrap();
+        tunables.reload().unwrap();
+        assert!(limiter.check(&key("a"), now).is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn limited_requests_get_429_with_retry_after() {
+        let limiter = Arc::new(limiter(1, 2));
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limiter, limit));
+        let request = |key: &str| {
+            Request::get("/")
+                .header(X_API_KEY, key)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            let response = app.clone().oneshot(request("a")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = app.clone().oneshot(request("a")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], 429);
+        assert_eq!(body["error"], "rate_limited");
+
+        let response = app.clone().oneshot(request("b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,79 @@
+//! Request IDs, for correlating log lines with the request that caused them.
+//!
+//! Every request gets an `x-request-id` header (a fresh UUID, unless the client or a proxy in
+//! front of us already set one), which is echoed back in the response. The span each request is
+//! traced in records the ID, and so do spans of jobs queued on its behalf, like tiling a newly
+//! uploaded pyramid (see [`crate::jobs`]), since the ID is stored with the job.
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, Request},
+    Router,
+};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tracing::Span;
+
+/// The header carrying request IDs
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Give every request routed by `router` an ID, and echo it in the response. Layers added to
+/// `router` before this see the ID
+pub fn with_request_ids<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(PropagateRequestIdLayer::new(X_REQUEST_ID))
+        .layer(SetRequestIdLayer::new(X_REQUEST_ID, MakeRequestUuid))
+}
+
+/// The ID of the request with the given headers, if it has one
+pub fn of(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The span to trace a request in, for `TraceLayer::make_span_with`
+pub fn make_span(request: &Request<Body>) -> Span {
+    let request_id = of(request.headers());
+    let request_id = request_id.as_deref().unwrap_or("-");
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app() -> Router {
+        with_request_ids(Router::new().route("/", get(|| async { "hi" })))
+    }
+
+    #[tokio::test]
+    async fn requests_are_given_ids() {
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        let id = response.headers()[X_REQUEST_ID].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn incoming_ids_are_kept() {
+        let request = Request::get("/")
+            .header(X_REQUEST_ID, "from-the-proxy")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[X_REQUEST_ID], "from-the-proxy");
+    }
+}
@@ -0,0 +1,223 @@
+//! Making sure the collections we use exist, with the indexes our queries need, before we serve
+//! anything. Run at startup by [`bootstrap`], which only creates what's missing, so it's cheap to
+//! run against a database that's already set up.
+//!
+//! Indexes are named, so they're recognized on later runs. One that can't be built (say, a unique
+//! index over data that already has duplicates) is logged and skipped, and the server starts
+//! without it: queries still work, only slower.
+
+use jnickg_imaging::errors::Error;
+use mongodb::{
+    bson::{doc, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+
+use crate::database::{Db, TraceDb};
+use crate::jobs::JOBS_COLLECTION;
+
+/// An index we want on one of our collections
+#[derive(Clone, Debug)]
+pub struct IndexSpec {
+    /// The collection, before prefixing
+    pub collection: &'static str,
+    pub name: &'static str,
+    pub keys: Document,
+    pub unique: bool,
+}
+
+impl IndexSpec {
+    fn model(&self) -> IndexModel {
+        let options = IndexOptions::builder()
+            .name(self.name.to_string())
+            .unique(self.unique)
+            .build();
+        IndexModel::builder()
+            .keys(self.keys.clone())
+            .options(options)
+            .build()
+    }
+}
+
+/// Every collection we keep documents in, before prefixing. GridFS makes its own
+pub fn collections() -> [&'static str; 4] {
+    ["images", "levels", "pyramids", JOBS_COLLECTION]
+}
+
+/// Every index we want, by collection
+pub fn indexes() -> Vec<IndexSpec> {
+    vec![
+        IndexSpec {
+            collection: "images",
+            name: "name_unique",
+            keys: doc! { "name": 1 },
+            unique: true,
+        },
+        // Tiles, by where they are (see `web_routines::tile_record`). Other images don't have
+        // these fields, and are all indexed together under nulls
+        IndexSpec {
+            collection: "images",
+            name: "tile_position",
+            keys: doc! { "pyramid_uuid": 1, "level": 1, "x": 1, "y": 1 },
+            unique: false,
+        },
+        IndexSpec {
+            collection: "levels",
+            name: "name_unique",
+            keys: doc! { "name": 1 },
+            unique: true,
+        },
+        IndexSpec {
+            collection: "pyramids",
+            name: "uuid_unique",
+            keys: doc! { "uuid": 1 },
+            unique: true,
+        },
+        // Pyramids of the same image are found by its hash when uploading it again
+        IndexSpec {
+            collection: "pyramids",
+            name: "content_sha256",
+            keys: doc! { "content_sha256": 1 },
+            unique: false,
+        },
+        // Workers claim jobs by state and lease, and jobs are found by pyramid
+        IndexSpec {
+            collection: JOBS_COLLECTION,
+            name: "state_lease",
+            keys: doc! { "state": 1, "lease_expires_at": 1 },
+            unique: false,
+        },
+        IndexSpec {
+            collection: JOBS_COLLECTION,
+            name: "pyramid_uuid",
+            keys: doc! { "pyramid_uuid": 1 },
+            unique: false,
+        },
+    ]
+}
+
+/// What [`bootstrap`] had to create, by full (prefixed) collection name
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BootstrapReport {
+    pub created_collections: Vec<String>,
+
+    /// Each as `collection.index`
+    pub created_indexes: Vec<String>,
+
+    /// Indexes that couldn't be built, as `collection.index`
+    pub failed_indexes: Vec<String>,
+}
+
+impl BootstrapReport {
+    /// Whether everything was already there
+    pub fn is_empty(&self) -> bool {
+        self.created_collections.is_empty()
+            && self.created_indexes.is_empty()
+            && self.failed_indexes.is_empty()
+    }
+}
+
+/// Create whichever of our [`collections`] and [`indexes`] `db` doesn't have yet, logging each
+/// one. Fails only if the database can't be read or a collection can't be created; indexes that
+/// can't be built are reported instead
+pub async fn bootstrap(db: &Db) -> Result<BootstrapReport, Error> {
+    let mut report = BootstrapReport::default();
+    let database = db.inner();
+
+    let existing = database
+        .list_collection_names(None)
+        .traced("list_collections", database.name())
+        .await
+        .map_err(|_e| Error::database(format!("Failed to list collections: {}", _e)))?;
+    for collection in collections() {
+        let name = db.collection_name(collection);
+        if existing.contains(&name) {
+            continue;
+        }
+        database
+            .create_collection(&name, None)
+            .traced("create_collection", &name)
+            .await
+            .map_err(|_e| {
+                Error::database(format!("Failed to create collection {}: {}", name, _e))
+            })?;
+        tracing::info!("Created collection {}", name);
+        report.created_collections.push(name);
+    }
+
+    for collection in collections() {
+        let name = db.collection_name(collection);
+        let handle = db.collection::<Document>(collection);
+        let existing = handle
+            .list_index_names()
+            .traced("list_indexes", &name)
+            .await
+            .map_err(|_e| Error::database(format!("Failed to list indexes of {}: {}", name, _e)))?;
+        for spec in indexes().iter().filter(|s| s.collection == collection) {
+            if existing.iter().any(|n| n == spec.name) {
+                continue;
+            }
+            let qualified = format!("{}.{}", name, spec.name);
+            match handle
+                .create_index(spec.model(), None)
+                .traced("create_index", &name)
+                .await
+            {
+                Ok(_) => {
+                    tracing::info!("Created index {}", qualified);
+                    report.created_indexes.push(qualified);
+                }
+                Err(_e) => {
+                    tracing::warn!("Failed to create index {}: {}", qualified, _e);
+                    report.failed_indexes.push(qualified);
+                }
+            }
+        }
+    }
+
+    if report.is_empty() {
+        tracing::info!("Database schema is up to date");
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{drop_test_database, test_database};
+
+    #[test]
+    fn every_index_is_on_one_of_our_collections() {
+        let specs = indexes();
+        assert!(specs.iter().all(|s| collections().contains(&s.collection)));
+        for (i, spec) in specs.iter().enumerate() {
+            assert!(!specs[..i]
+                .iter()
+                .any(|s| s.collection == spec.collection && s.name == spec.name));
+        }
+    }
+
+    #[tokio::test]
+    async fn bootstrap_creates_only_whats_missing() {
+        let db = test_database().await;
+
+        let first = bootstrap(&db).await.unwrap();
+        assert_eq!(first.created_collections.len(), collections().len());
+        assert_eq!(first.created_indexes.len(), indexes().len());
+        assert!(first.failed_indexes.is_empty());
+        assert!(bootstrap(&db).await.unwrap().is_empty());
+
+        // Names are unique now
+        let images = db.collection::<Document>("images");
+        images
+            .insert_one(doc! { "name": "dup" }, None)
+            .await
+            .unwrap();
+        assert!(images
+            .insert_one(doc! { "name": "dup" }, None)
+            .await
+            .is_err());
+
+        drop_test_database(db).await;
+    }
+}
@@ -0,0 +1,459 @@
+//! Helpers for tests that need database fixtures, or that check generated images against
+//! known-good ("golden") files.
+//!
+//! - Fixture documents live in `test_files/fixtures/<collection>.json`, as a JSON array of
+//!   documents. MongoDB extended JSON (e.g. `{ "$oid": "..." }`) is understood.
+//! - Golden images live in `test_files/golden/<name>.png`. Run tests with `UPDATE_GOLDEN=1` to
+//!   (re)write them from the current output, then review the new files before committing.
+//! - Tests that need a database run against the MongoDB instance at `TEST_MONGO_URI`, if set, or
+//!   else against one started with Docker (see [`test_database`]). Each one gets its own throwaway
+//!   database, which should be dropped with [`drop_test_database`] when done.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::database::Db;
+
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use jnickg_imaging::{
+    compression::Compression,
+    documents::{self, LevelDims, PyramidLevel, PyramidTiles, TileDescriptor},
+    ipr,
+};
+use mongodb::{
+    bson::{oid::ObjectId, Bson, Document},
+    Client,
+};
+use testcontainers::{runners::AsyncRunner, ImageExt, ReuseDirective};
+use testcontainers_modules::mongo::Mongo;
+use tokio::sync::OnceCell;
+
+pub const FIXTURES_DIR: &str = "test_files/fixtures";
+pub const GOLDEN_DIR: &str = "test_files/golden";
+
+/// Environment variable holding the MongoDB URI to run database tests against
+pub const TEST_MONGO_URI_VAR: &str = "TEST_MONGO_URI";
+
+/// Environment variable which, when set, makes golden comparisons overwrite the golden files
+pub const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+
+/// URI of the MongoDB instance database tests run against, found by the first test to need it
+static MONGO_URI: OnceCell<String> = OnceCell::const_new();
+
+/// The MongoDB instance to run database tests against: the one at `TEST_MONGO_URI` if it's set,
+/// or else a container started with Docker.
+///
+/// The container is marked for reuse, so later test runs share it rather than each starting (and
+/// leaving behind) their own. It's left running; remove it with `docker rm -f` when done
+async fn mongo_uri() -> &'static str {
+    MONGO_URI
+        .get_or_init(|| async {
+            if let Ok(uri) = std::env::var(TEST_MONGO_URI_VAR) {
+                return uri;
+            }
+            let mongo = Mongo::default()
+                .with_reuse(ReuseDirective::Always)
+                .start()
+                .await
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to start a MongoDB container ({}). Is Docker running? Set {} to \
+                         test against an existing instance instead",
+                        e, TEST_MONGO_URI_VAR
+                    )
+                });
+            let host = mongo
+                .get_host()
+                .await
+                .expect("Failed to find MongoDB container");
+            let port = mongo
+                .get_host_port_ipv4(27017)
+                .await
+                .expect("Failed to find MongoDB container port");
+            format!("mongodb://{}:{}", host, port)
+        })
+        .await
+}
+
+/// Connect to a fresh, uniquely-named test database
+pub async fn test_database() -> Db {
+    let client = Client::with_uri_str(mongo_uri().await)
+        .await
+        .expect("Failed to connect to test database");
+    let db = client.database(&format!("test_{}", uuid::Uuid::new_v4().simple()));
+    Db::new(db, "")
+}
+
+pub async fn drop_test_database(db: Db) {
+    db.inner()
+        .drop(None)
+        .await
+        .expect("Failed to drop test database");
+}
+
+/// Read the fixture documents for the given collection
+pub fn fixture_documents(collection: &str) -> Result<Vec<Document>, &'static str> {
+    let path = PathBuf::from(FIXTURES_DIR).join(format!("{}.json", collection));
+    let contents = std::fs::read_to_string(path).map_err(|_| "Failed to read fixture file")?;
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(&contents).map_err(|_| "Fixture file must be a JSON array")?;
+
+    values
+        .into_iter()
+        .map(|v| match Bson::try_from(v) {
+            Ok(Bson::Document(d)) => Ok(d),
+            _ => Err("Fixture entries must be documents"),
+        })
+        .collect()
+}
+
+/// Insert the fixture documents for each of the given collections into `db`
+pub async fn load_fixtures(db: &Db, collections: &[&str]) -> Result<(), &'static str> {
+    for &collection in collections {
+        let docs = fixture_documents(collection)?;
+        db.collection::<Document>(collection)
+            .insert_many(docs, None)
+            .await
+            .map_err(|_| "Failed to insert fixture documents")?;
+    }
+    Ok(())
+}
+
+/// How different an image may be from its golden file and still match.
+///
+/// PSNR tolerates the small, widespread differences that come from e.g. a resampling filter
+/// tweak, while the per-channel limit still catches a handful of badly wrong pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// Minimum peak signal-to-noise ratio, in dB
+    pub min_psnr: f64,
+
+    /// Largest allowed difference in any one channel of any one pixel
+    pub max_channel_delta: u8,
+}
+
+impl Tolerance {
+    /// Pixel-for-pixel identical
+    pub const EXACT: Tolerance = Tolerance {
+        min_psnr: f64::INFINITY,
+        max_channel_delta: 0,
+    };
+
+    /// Visually indistinguishable
+    pub const PERCEPTUAL: Tolerance = Tolerance {
+        min_psnr: 40.0,
+        max_channel_delta: 16,
+    };
+}
+
+/// How two images differ
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDiff {
+    /// Peak signal-to-noise ratio, in dB. Infinite if the images are identical
+    pub psnr: f64,
+
+    /// Largest difference in any one channel of any one pixel
+    pub max_channel_delta: u8,
+}
+
+impl ImageDiff {
+    pub fn within(&self, tolerance: Tolerance) -> bool {
+        self.psnr >= tolerance.min_psnr && self.max_channel_delta <= tolerance.max_channel_delta
+    }
+}
+
+/// Compare two images, channel-by-channel, as 8-bit RGBA
+pub fn compare_images(a: &DynamicImage, b: &DynamicImage) -> Result<ImageDiff, &'static str> {
+    if a.dimensions() != b.dimensions() {
+        return Err("Images have different dimensions");
+    }
+
+    let (a, b) = (a.to_rgba8(), b.to_rgba8());
+    let mut squared_error = 0.0;
+    let mut max_channel_delta = 0;
+    for (ca, cb) in a.as_raw().iter().zip(b.as_raw().iter()) {
+        let delta = ca.abs_diff(*cb);
+        max_channel_delta = max_channel_delta.max(delta);
+        squared_error += (delta as f64).powi(2);
+    }
+
+    let mse = squared_error / a.as_raw().len().max(1) as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0_f64.powi(2) / mse).log10()
+    };
+
+    Ok(ImageDiff {
+        psnr,
+        max_channel_delta,
+    })
+}
+
+/// Panic unless `image` matches `test_files/golden/<name>.png` within `tolerance`
+pub fn assert_matches_golden(image: &DynamicImage, name: &str, tolerance: Tolerance) {
+    let path = PathBuf::from(GOLDEN_DIR).join(format!("{}.png", name));
+
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        image
+            .save_with_format(&path, ImageFormat::Png)
+            .expect("Failed to write golden file");
+        return;
+    }
+
+    let golden = image::open(&path).unwrap_or_else(|_| {
+        panic!(
+            "Missing golden file {}. Run with {}=1 to create it",
+            path.display(),
+            UPDATE_GOLDEN_VAR
+        )
+    });
+    let diff = compare_images(image, &golden).unwrap_or_else(|e| panic!("{}: {}", name, e));
+    assert!(
+        diff.within(tolerance),
+        "{} differs from golden file: {:?}, allowed {:?}",
+        name,
+        diff,
+        tolerance
+    );
+}
+
+/// A small, deterministic RGB image whose dimensions don't divide evenly into tiles
+pub fn synthetic_image(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x * 6) as u8, (y * 10) as u8, ((x + y) * 3) as u8])
+    }))
+}
+
+/// The files `make_pyramid` (in the library's examples) writes for a pyramid of `image` called
+/// `name`, with square tiles of (at most) `tile_size`, compressed with gzip. Paths are relative to
+/// its output directory
+pub fn pyramid_archive_files(
+    name: &str,
+    image: &DynamicImage,
+    tile_size: u32,
+) -> Vec<(String, Vec<u8>)> {
+    let compression = Compression::Gzip;
+    let mut pyramid = ipr::Pyramid::new(image, None).unwrap();
+    pyramid.tile_all_levels(tile_size, tile_size).unwrap();
+
+    let mut files = Vec::new();
+    let mut image_names = Vec::new();
+    let mut image_urls = Vec::new();
+    let mut levels = Vec::new();
+    for (l, level_image) in pyramid.levels().iter().enumerate() {
+        let level_name = documents::level_name(name, l);
+        let level_file = format!("levels/{}.png", level_name);
+        let mut png = Vec::new();
+        level_image
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        files.push((level_file.clone(), png));
+        image_names.push(level_name);
+        image_urls.push(level_file);
+
+        let level_tiles = pyramid.tiles(l).unwrap();
+        let mut tiles = Vec::new();
+        for (t_idx, (x, y, _, _, tile)) in level_tiles.iter_with_coords().enumerate() {
+            let tile_name = documents::tile_name(name, l, t_idx);
+            let data = ipr::IprImage(tile)
+                .compress(compression, 6, Some(ImageFormat::Png))
+                .unwrap();
+            files.push((format!("tiles/{}.png.gzip", tile_name), data));
+
+            tiles.push(TileDescriptor {
+                x,
+                y,
+                width: tile.width(),
+                height: tile.height(),
+                index: t_idx as u32,
+                tile_id: ObjectId::new(),
+                name: tile_name,
+                mime_type: Some(ImageFormat::Png.to_mime_type().to_string()),
+                sha256: None,
+            });
+        }
+        levels.push(PyramidLevel {
+            level: l as u32,
+            width: level_image.width(),
+            height: level_image.height(),
+            tiles,
+        });
+    }
+
+    let manifest = documents::Pyramid {
+        id: None,
+        uuid: name.to_string(),
+        url: "manifest.json".to_string(),
+        original_filename: format!("{}.png", name),
+        content_sha256: None,
+        image_files: image_names.iter().map(|_| ObjectId::new()).collect(),
+        image_sha256: Vec::new(),
+        image_docs: image_names.iter().map(|_| ObjectId::new()).collect(),
+        image_names,
+        image_urls,
+        mime_type: ImageFormat::Png.to_mime_type().to_string(),
+        compression,
+        tile_format: None,
+        page: 0,
+        reoriented: false,
+        pyramid_type: pyramid.params().pyramid_type,
+        scale_factor: pyramid.params().scale_factor,
+        filter: pyramid.params().filter,
+        dimension_rounding: ipr::PYRAMID_DIMENSION_ROUNDING.to_string(),
+        level_dims: pyramid
+            .level_dims()
+            .into_iter()
+            .map(|(width, height)| LevelDims { width, height })
+            .collect(),
+        min_dimension: None,
+        max_levels: None,
+        total_levels: None,
+        tiles: PyramidTiles::Levels(levels),
+        tile_sets: Vec::new(),
+        tags: Vec::new(),
+    };
+    files.push((
+        "manifest.json".to_string(),
+        serde_json::to_vec_pretty(&manifest).unwrap(),
+    ));
+    files
+}
+
+/// A zip archive of the given files
+pub fn zip_files(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (path, data) in files {
+        writer
+            .start_file(path, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// `image` as a JPEG, tagged with the given EXIF orientation the way cameras do, in an APP1
+/// segment right after the start-of-image marker
+pub fn jpeg_with_orientation(image: &DynamicImage, orientation: u16) -> Vec<u8> {
+    let mut jpeg = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+        .unwrap();
+
+    // A big-endian TIFF header, then one directory holding only the orientation (a SHORT)
+    let mut exif = b"Exif\0\0MM\0*".to_vec();
+    exif.extend(8u32.to_be_bytes());
+    exif.extend(1u16.to_be_bytes());
+    exif.extend(0x0112u16.to_be_bytes());
+    exif.extend(3u16.to_be_bytes());
+    exif.extend(1u32.to_be_bytes());
+    exif.extend(orientation.to_be_bytes());
+    exif.extend([0, 0]);
+    exif.extend(0u32.to_be_bytes());
+
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend((exif.len() as u16 + 2).to_be_bytes());
+    segment.extend(exif);
+    jpeg.splice(2..2, segment);
+    jpeg
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+    use jnickg_imaging::{
+        documents::{Pyramid, PyramidTiles, TilingStatus},
+        ipr::{HasImageProcessingRoutines, IprImage},
+    };
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn compare_images_identical_is_exact() {
+        let i = synthetic_image(8, 8);
+        let diff = compare_images(&i, &i).unwrap();
+        assert!(diff.within(Tolerance::EXACT));
+    }
+
+    #[test]
+    fn compare_images_tolerates_small_noise_only() {
+        let i = synthetic_image(8, 8);
+
+        let mut nudged = i.to_rgb8();
+        nudged.get_pixel_mut(3, 3).0[0] += 2;
+        let nudged = DynamicImage::ImageRgb8(nudged);
+        let diff = compare_images(&i, &nudged).unwrap();
+        assert!(!diff.within(Tolerance::EXACT));
+        assert!(diff.within(Tolerance::PERCEPTUAL));
+
+        let mut broken = i.to_rgb8();
+        broken.get_pixel_mut(3, 3).0[0] += 100;
+        let broken = DynamicImage::ImageRgb8(broken);
+        let diff = compare_images(&i, &broken).unwrap();
+        assert!(!diff.within(Tolerance::PERCEPTUAL));
+
+        assert!(compare_images(&i, &synthetic_image(8, 7)).is_err());
+    }
+
+    #[test]
+    fn fixture_documents_parse_extended_json() {
+        let pyramids = fixture_documents("pyramids").unwrap();
+        assert_eq!(pyramids.len(), 1);
+        assert!(pyramids[0].get_object_id("_id").is_ok());
+        assert_eq!(pyramids[0].get_array("image_files").unwrap().len(), 2);
+
+        let pyramid: Pyramid = mongodb::bson::from_document(pyramids[0].clone()).unwrap();
+        assert_eq!(pyramid.image_names.len(), 2);
+        assert_eq!(pyramid.tiles, PyramidTiles::Status(TilingStatus::Todo));
+    }
+
+    #[test]
+    fn level_zero_tiles_match_golden() {
+        let i = synthetic_image(40, 24);
+        let tiles = IprImage(&i).make_tiles(16, 16).unwrap();
+        assert_eq!(tiles.tiles.len(), 6);
+
+        for (t_idx, tile) in tiles.tiles.iter().enumerate() {
+            // Tiles are cut straight from the source, so these should match exactly
+            assert_matches_golden(
+                tile,
+                &format!("synthetic_40x24_L0_T{}", t_idx),
+                Tolerance::EXACT,
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn load_fixtures_into_test_database() {
+        let db = test_database().await;
+
+        load_fixtures(&db, &["pyramids", "images"]).await.unwrap();
+
+        let pyramid = db
+            .collection::<Document>("pyramids")
+            .find_one(
+                doc! { "uuid": "00000000-0000-4000-8000-000000000001" },
+                None,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let image_names = pyramid.get_array("image_names").unwrap();
+
+        let mut images = db
+            .collection::<Document>("images")
+            .find(None, None)
+            .await
+            .unwrap();
+        let mut found = 0;
+        while let Some(image) = images.next().await {
+            let name = Bson::String(image.unwrap().get_str("name").unwrap().to_string());
+            assert!(image_names.contains(&name));
+            found += 1;
+        }
+        assert_eq!(found, image_names.len());
+
+        drop_test_database(db).await;
+    }
+}
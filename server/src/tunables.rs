@@ -0,0 +1,273 @@
+use std::{path::PathBuf, sync::Arc};
+
+use arc_swap::ArcSwap;
+use jnickg_imaging::{compression::Compression, errors::Error};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Settings that can be changed while the server is running.
+///
+/// These are loaded from the JSON file passed via `--config`, and re-read whenever the server
+/// receives SIGHUP or a request to `POST /api/v1/admin/reload`. Any field missing from the file
+/// takes its default value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tunables {
+    /// Brotli quality level used when compressing tiles, from 0 to 11
+    pub brotli_level: u32,
+
+    /// Brotli window size (log2) used when compressing tiles, from 10 to 24
+    pub brotli_lg_window_size: u32,
+
+    /// Gzip level used when compressing tiles, from 0 to 9
+    pub gzip_level: u32,
+
+    /// Zstandard level used when compressing tiles, from 1 to 22
+    pub zstd_level: u32,
+
+    /// Codec used to compress the tiles of new pyramids, unless the request picks one
+    pub tile_compression: Compression,
+
+    /// Maximum width of generated tiles, in pixels
+    pub tile_width: u32,
+
+    /// Maximum height of generated tiles, in pixels
+    pub tile_height: u32,
+
+    /// Most tiles written to GridFS at once while tiling a pyramid
+    pub tile_upload_concurrency: usize,
+
+    /// Matrix operations taking more multiply-adds than this run in the background, unless the
+    /// request says otherwise. See [`crate::matrix_tasks`]
+    pub async_matrix_work: u64,
+
+    /// A `tracing` filter directive, e.g. "info" or "jnickg_tile_server=debug,tower_http=info"
+    pub log_filter: String,
+
+    /// Requests a minute each client may make to expensive routes, on average. Zero disables rate
+    /// limiting. See [`crate::rate_limit`]
+    pub rate_limit_per_minute: u32,
+
+    /// Requests to expensive routes each client may make at once, before being held to
+    /// `rate_limit_per_minute`
+    pub rate_limit_burst: u32,
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            brotli_level: Compression::Brotli.default_level(),
+            brotli_lg_window_size: 24,
+            gzip_level: Compression::Gzip.default_level(),
+            zstd_level: Compression::Zstd.default_level(),
+            tile_compression: Compression::Brotli,
+            tile_width: 512,
+            tile_height: 512,
+            tile_upload_concurrency: 8,
+            // About a 256x256 matrix squared
+            async_matrix_work: 1 << 24,
+            log_filter: "info".to_string(),
+            rate_limit_per_minute: 60,
+            rate_limit_burst: 10,
+        }
+    }
+}
+
+impl Tunables {
+    fn validate(&self) -> Result<(), Error> {
+        if self.brotli_level > 11 {
+            return Err(Error::validation("brotli_level must be between 0 and 11"));
+        }
+        if !(10..=24).contains(&self.brotli_lg_window_size) {
+            return Err(Error::validation(
+                "brotli_lg_window_size must be between 10 and 24",
+            ));
+        }
+        if !Compression::Gzip.levels().contains(&self.gzip_level) {
+            return Err(Error::validation("gzip_level must be between 0 and 9"));
+        }
+        if !Compression::Zstd.levels().contains(&self.zstd_level) {
+            return Err(Error::validation("zstd_level must be between 1 and 22"));
+        }
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(Error::validation(
+                "tile_width and tile_height must be non-zero",
+            ));
+        }
+        if self.tile_upload_concurrency == 0 {
+            return Err(Error::validation(
+                "tile_upload_concurrency must be non-zero",
+            ));
+        }
+        if self.rate_limit_burst == 0 {
+            return Err(Error::validation("rate_limit_burst must be non-zero"));
+        }
+        if EnvFilter::try_new(&self.log_filter).is_err() {
+            return Err(Error::validation(
+                "log_filter is not a valid filter directive",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The level to compress tiles at with the given codec
+    pub fn compression_level(&self, codec: Compression) -> u32 {
+        match codec {
+            Compression::Brotli => self.brotli_level,
+            Compression::Gzip => self.gzip_level,
+            Compression::Zstd => self.zstd_level,
+        }
+    }
+
+    fn from_file(path: &PathBuf) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|_| Error::internal("Failed to read config file"))?;
+        let tunables: Tunables = serde_json::from_str(&contents)
+            .map_err(|_| Error::validation("Failed to parse config file"))?;
+        tunables.validate()?;
+        Ok(tunables)
+    }
+}
+
+/// Holds the current [`Tunables`], and knows how to reload them.
+///
+/// Handlers should call [`TunablesStore::load`] each time they need a setting, rather than
+/// holding on to a copy, so that reloads take effect on the next request.
+pub struct TunablesStore {
+    current: ArcSwap<Tunables>,
+    path: Option<PathBuf>,
+    log_filter: Option<reload::Handle<EnvFilter, Registry>>,
+}
+
+impl Default for TunablesStore {
+    fn default() -> Self {
+        Self::fixed(Tunables::default())
+    }
+}
+
+impl TunablesStore {
+    /// Loads tunables from the given file (or defaults, if there is none)
+    pub fn new(path: Option<PathBuf>) -> Result<Self, Error> {
+        let tunables = match &path {
+            Some(p) => Tunables::from_file(p)?,
+            None => Tunables::default(),
+        };
+        Ok(Self {
+            current: ArcSwap::from_pointee(tunables),
+            path,
+            log_filter: None,
+        })
+    }
+
+    /// Holds the given tunables, with no config file to reload them from
+    pub fn fixed(tunables: Tunables) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(tunables),
+            path: None,
+            log_filter: None,
+        }
+    }
+
+    /// Attaches the handle used to swap out the log filter on reload
+    pub fn with_log_filter(mut self, handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        self.log_filter = Some(handle);
+        self
+    }
+
+    /// Get the current tunables
+    pub fn load(&self) -> Arc<Tunables> {
+        self.current.load_full()
+    }
+
+    /// Re-reads the config file and swaps in the new tunables.
+    ///
+    /// If the file can't be read or contains invalid settings, the current tunables are kept.
+    pub fn reload(&self) -> Result<Arc<Tunables>, Error> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| Error::validation("No config file to reload from"))?;
+        let tunables = Arc::new(Tunables::from_file(path)?);
+
+        if let Some(handle) = &self.log_filter {
+            // Validated above, so this can only fail if the subscriber is gone
+            handle
+                .reload(EnvFilter::new(&tunables.log_filter))
+                .map_err(|_| Error::internal("Failed to reload log filter"))?;
+        }
+
+        self.current.store(tunables.clone());
+        tracing::info!("Reloaded tunables: {:?}", tunables);
+        Ok(tunables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("tunables_{}_{}.json", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let path = write_config("missing_fields", r#"{ "tile_width": 256 }"#);
+        let store = TunablesStore::new(Some(path.clone())).unwrap();
+        let tunables = store.load();
+        assert_eq!(tunables.tile_width, 256);
+        assert_eq!(tunables.tile_height, Tunables::default().tile_height);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn compression_settings_are_validated() {
+        let path = write_config("compression", r#"{ "tile_compression": "zstd" }"#);
+        let store = TunablesStore::new(Some(path.clone())).unwrap();
+        let tunables = store.load();
+        assert_eq!(tunables.tile_compression, Compression::Zstd);
+        assert_eq!(
+            tunables.compression_level(Compression::Zstd),
+            tunables.zstd_level
+        );
+
+        std::fs::write(&path, r#"{ "zstd_level": 23 }"#).unwrap();
+        assert!(store.reload().is_err());
+        std::fs::write(&path, r#"{ "tile_compression": "deflate" }"#).unwrap();
+        assert!(store.reload().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tile_upload_concurrency_must_be_non_zero() {
+        let path = write_config("concurrency", r#"{ "tile_upload_concurrency": 0 }"#);
+        assert!(TunablesStore::new(Some(path.clone())).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload_swaps_in_new_values() {
+        let path = write_config("reload", r#"{ "brotli_level": 5 }"#);
+        let store = TunablesStore::new(Some(path.clone())).unwrap();
+        assert_eq!(store.load().brotli_level, 5);
+
+        std::fs::write(&path, r#"{ "brotli_level": 7 }"#).unwrap();
+        store.reload().unwrap();
+        assert_eq!(store.load().brotli_level, 7);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload_keeps_current_values_when_invalid() {
+        let path = write_config("invalid", r#"{ "brotli_level": 5 }"#);
+        let store = TunablesStore::new(Some(path.clone())).unwrap();
+
+        std::fs::write(&path, r#"{ "brotli_level": 12 }"#).unwrap();
+        assert!(store.reload().is_err());
+        assert_eq!(store.load().brotli_level, 5);
+        std::fs::remove_file(path).unwrap();
+    }
+}
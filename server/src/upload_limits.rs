@@ -0,0 +1,69 @@
+//! Limits on what clients may upload.
+//!
+//! Request bodies are capped per route, so only the routes that take images or matrices accept
+//! large ones.
+//! Images are also held to a pixel budget, checked from their headers before they're decoded: a
+//! small, highly-compressed file can otherwise decode to gigabytes.
+
+use jnickg_imaging::errors::Error;
+
+/// Upload limits. Set from the command line
+#[derive(Debug, Clone, Copy)]
+pub struct UploadLimits {
+    /// Largest request body accepted by routes that take images or matrices, in bytes
+    pub max_bytes: usize,
+
+    /// Most pixels (width times height) an uploaded image may have
+    pub max_pixels: u64,
+}
+
+impl UploadLimits {
+    /// 64 MiB
+    pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+    /// 100 megapixels, e.g. 10,000 x 10,000
+    pub const DEFAULT_MAX_PIXELS: u64 = 100_000_000;
+
+    /// Reject images with more pixels than [`UploadLimits::max_pixels`]
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), Error> {
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(Error::unprocessable(format!(
+                "Image is {}x{} ({} pixels), but at most {} pixels are allowed",
+                width, height, pixels, self.max_pixels
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: Self::DEFAULT_MAX_BYTES,
+            max_pixels: Self::DEFAULT_MAX_PIXELS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(100, 100, true ; "well under")]
+    #[test_case(200, 50, true ; "exactly at budget")]
+    #[test_case(101, 100, false ; "just over")]
+    #[test_case(u32::MAX, u32::MAX, false ; "does not overflow")]
+    fn check_dimensions_enforces_pixel_budget(width: u32, height: u32, allowed: bool) {
+        let limits = UploadLimits {
+            max_pixels: 10_000,
+            ..Default::default()
+        };
+        let result = limits.check_dimensions(width, height);
+        assert_eq!(result.is_ok(), allowed);
+        if !allowed {
+            assert!(matches!(result, Err(Error::Unprocessable(_))));
+        }
+    }
+}
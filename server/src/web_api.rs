@@ -1,20 +1,38 @@
-use ::axum::body::Body;
+use ::axum::{
+    body::Body,
+    extract::Query,
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+};
 use futures_util::{io::AsyncWriteExt, AsyncReadExt, StreamExt};
-use image::ImageFormat;
+use image::{DynamicImage, ImageFormat};
 use mongodb::{
-    bson::{doc, Document},
+    bson::{doc, oid::ObjectId, Bson, Document},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
     Collection,
 };
-use std::{collections::HashMap, io::Cursor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-use ::utoipa::OpenApi;
+use ::utoipa::{OpenApi, ToSchema};
 use askama::Template;
 use jnickg_imaging::{
+    compression::Compression,
     dims::HasDims,
+    documents::{
+        self, LevelDims, Pyramid, PyramidManifest, PyramidPage, PyramidTiles, TileSet, TilingStatus,
+    },
     dyn_matrix::DynMatrix,
+    errors::Error,
     ipr::{self, HasImageProcessingRoutines},
+    kernels, luma,
+    my_image::MyImage,
+    serde::{NamedDims, NamedDynMatrix},
+    testgen,
 };
 
+use crate::database::{Db, TraceDb};
+use crate::matrix_tasks::{self, MatrixTask, MatrixTaskState, MatrixTaskStatus};
+use crate::metrics::METRICS;
 use crate::wrappers::*;
 use crate::*;
 
@@ -41,19 +59,89 @@ macro_rules! debug_print {
         post_something_with_id,
         post_image,
         get_image,
+        post_pyramid,
+        post_pyramid_import,
+        get_pyramid,
+        get_pyramid_manifest,
+        get_pyramid_verify,
+        get_pyramids,
+        put_pyramid_tags,
+        get_images,
+        put_image_tags,
+        get_image_thumbnail,
+        get_image_pixel,
+        get_image_info,
+        get_level_info,
+        post_pyramid_retile,
+        post_pyramid_tile_set,
+        post_pyramid_tiles_batch,
+        get_image_stats,
+        post_image_equalize,
+        post_image_convolve,
+        post_image_generate,
+        post_image_resize,
+        post_image_rotate,
+        post_image_grayscale,
+        post_image_split,
+        post_images_merge,
+        post_image_threshold,
+        post_image_edges,
+        post_image_compare,
         post_matrix_with_name,
+        get_kernels,
+        post_matrix_from_kernel,
         get_matrix,
         put_matrix,
         delete_matrix,
         post_matrix_add,
         post_matrix_subtract,
         post_matrix_multiply,
-        get_matrix_dims
+        post_matrix_scale,
+        post_matrix_hadamard,
+        post_matrix_transpose,
+        post_matrix_solve,
+        post_matrix_compare,
+        get_matrix_dims,
+        get_matrix_norms,
+        get_matrix_status
     ),
     components(
         schemas(
             WrappedDynMatrix<f64>,
-            WrappedDims
+            WrappedDims,
+            Pyramid,
+            PyramidPage,
+            documents::PyramidLevel,
+            documents::TileDescriptor,
+            PyramidManifest,
+            documents::ManifestLevel,
+            documents::ManifestTile,
+            LevelDims,
+            PyramidTiles,
+            TileSet,
+            TileRef,
+            TilingStatus,
+            web_routines::VerifyReport,
+            web_routines::ChecksumMismatch,
+            Compression,
+            StoredImage,
+            ThresholdResult,
+            PixelValue,
+            ImageInfo,
+            LevelInfo,
+            ipr::ImageMetadata,
+            MatrixNorms,
+            MatrixCompareResult,
+            MatrixTaskStatus,
+            matrix_tasks::MatrixTaskState,
+            KernelInfo,
+            kernels::Kernel,
+            testgen::Pattern,
+            ImageCompareResult,
+            ipr::ImageComparison,
+            ipr::PyramidType,
+            ipr::ResampleFilter,
+            ipr::EdgeOperator
         )
     ),
     tags(
@@ -80,48 +168,545 @@ impl<'a> IndexTemplate<'a> {
     }
 }
 
-pub async fn get_api_index(State(app_state): AppState) -> Response {
+/// Pull a matrix out of a request body: JSON, or a binary format named by its `Content-Type`
+async fn read_matrix(
+    request: Request,
+    app_state: &Arc<RwLock<RuntimeData>>,
+) -> ApiResult<DynMatrix<f64>> {
+    let WrappedDynMatrix(mat) = WrappedDynMatrix::<f64>::from_request(request, app_state)
+        .await
+        .map_err(|WrappedError(e)| {
+            Error::validation(format!("Failed to read matrix from request: {}", e))
+        })?;
+    Ok(mat)
+}
+
+/// Look up a stored matrix by name
+fn get_named_matrix<'a>(app: &'a RuntimeData, name: &str) -> Result<&'a DynMatrix<f64>, Error> {
+    if app.matrix_tasks.get(name) == Some(&MatrixTask::Running) {
+        return Err(Error::conflict(format!(
+            "Matrix {} is still being computed. See /api/v1/matrix/{}/status",
+            name, name
+        )));
+    }
+    app.matrices
+        .get(name)
+        .ok_or_else(|| Error::not_found(format!("Matrix {}", name)))
+}
+
+/// Log a database (or GridFS) error, and replace it with a description of what we were doing
+fn db_error<E: std::fmt::Display>(context: &'static str) -> impl FnOnce(E) -> Error {
+    move |_e| {
+        debug_print!("Error: {}", _e);
+        Error::database(context)
+    }
+}
+
+/// How matrices are written in response bodies
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatrixFormat {
+    /// Nested arrays, one per row
+    #[default]
+    Array,
+
+    /// An object giving the shape and element type along with the (flattened) elements. See
+    /// [`NamedDynMatrix`]
+    Object,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MatrixFormatQuery {
+    #[serde(default)]
+    format: MatrixFormat,
+}
+
+/// Respond with `matrix`, written in the given JSON format, or in a binary format if the
+/// request's `Accept` header asks for one. See [`content_negotiation::choose_matrix_encoding`]
+fn matrix_response(
+    status: StatusCode,
+    matrix: DynMatrix<f64>,
+    format: MatrixFormat,
+    headers: &HeaderMap,
+) -> ApiResult {
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    if let Some(binary) = content_negotiation::choose_matrix_encoding(accept) {
+        let body = binary.to_vec(&matrix)?;
+        return Ok(Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, binary.mime_type())
+            .body(Body::from(body))
+            .unwrap());
+    }
+
+    match format {
+        MatrixFormat::Array => Ok((status, WrappedDynMatrix(matrix)).into_response()),
+        MatrixFormat::Object => json_response(status, &NamedDynMatrix(matrix)),
+    }
+}
+
+/// Serialize `value` as the JSON body of a response
+fn json_response<T: Serialize + ?Sized>(status: StatusCode, value: &T) -> ApiResult {
+    let json = serde_json::to_string(value)
+        .map_err(|_| Error::internal("Failed to serialize response"))?;
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json))
+        .unwrap())
+}
+
+/// Get the name an uploaded image should be stored under, from the `filename` given in its
+/// `Content-Disposition` header. `None` if there is no such header
+fn upload_name(request: &Request) -> Result<Option<String>, Error> {
+    let Some(content_disposition) = request.headers().get("Content-Disposition") else {
+        return Ok(None);
+    };
+    let name = content_disposition
+        .to_str()
+        .ok()
+        .and_then(|cd| {
+            cd.split(';')
+                .map(|p| p.trim())
+                .find(|p| p.starts_with("filename"))
+        })
+        .and_then(|p| p.split('=').nth(1))
+        .ok_or_else(|| Error::validation("Content-Disposition must specify a filename"))?;
+    Ok(Some(name.to_string()))
+}
+
+/// Get the format of an uploaded image from its `Content-Type` header
+fn upload_format(request: &Request) -> Result<ImageFormat, Error> {
+    let mime_type = request
+        .headers()
+        .get("Content-Type")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| {
+            Error::validation(
+                "Unable to handle request. Please pass an image body and specify a Content-Type",
+            )
+        })?;
+    let format = ImageFormat::from_mime_type(mime_type).ok_or_else(|| {
+        Error::unsupported_format(format!(
+            "The given MIME Type \"{}\" is not supported",
+            mime_type
+        ))
+    })?;
+    debug_print!("Detected MIME Type: \"{}\"", mime_type);
+    if !format.reading_enabled() {
+        return Err(Error::unsupported_format(format!(
+            "Images with MIME Type \"{}\" can be served, but not uploaded",
+            mime_type
+        )));
+    }
+    Ok(format)
+}
+
+/// Read the whole request body
+async fn read_body(request: Request, app_state: &Arc<RwLock<RuntimeData>>) -> ApiResult<Vec<u8>> {
+    let bytes = match Bytes::from_request(request, app_state).await {
+        Ok(bytes) => bytes,
+        Err(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            let max_bytes = app_state.read().await.upload_limits.max_bytes;
+            return Err(Error::too_large(format!(
+                "Request body is larger than the limit of {} bytes",
+                max_bytes
+            ))
+            .into());
+        }
+        Err(_) => {
+            return Err(Error::internal("Failed to read image data from request body").into())
+        }
+    };
+    debug_print!("Extracted image data with byte length: {}", bytes.len());
+    Ok(bytes.to_vec())
+}
+
+/// Check an uploaded image against the pixel budget, reading only its header so that oversized
+/// images are turned away before we spend the memory to decode them
+async fn check_upload_dimensions(
+    bytes: &[u8],
+    format: ImageFormat,
+    page: Option<u32>,
+    app_state: &Arc<RwLock<RuntimeData>>,
+) -> ApiResult<()> {
+    let (width, height) =
+        ipr::encoded_image_dimensions(bytes, format, page).map_err(|e| match e {
+            Error::ImageDecode(msg) => {
+                Error::unprocessable(format!("Failed to read image dimensions: {}", msg))
+            }
+            e => e,
+        })?;
+    app_state
+        .read()
+        .await
+        .upload_limits
+        .check_dimensions(width, height)?;
+    Ok(())
+}
+
+/// Decode an uploaded image. An image that can't be decoded is the client's to fix, unlike one
+/// we stored ourselves, so this fails as unprocessable rather than as an internal error
+fn decode_upload(
+    bytes: &[u8],
+    format: ImageFormat,
+    page: Option<u32>,
+) -> Result<DynamicImage, Error> {
+    ipr::decode_image(bytes, format, page).map_err(|e| match e {
+        Error::ImageDecode(msg) => Error::unprocessable(format!("Failed to decode image: {}", msg)),
+        e => e,
+    })
+}
+
+/// An uploaded image's EXIF orientation, if it has one, and whether to rotate and flip it upright
+/// to match: only if it isn't upright already, and `auto_orient`
+fn upload_orientation(bytes: &[u8], format: ImageFormat, auto_orient: bool) -> (Option<u8>, bool) {
+    let exif_orientation = ipr::read_metadata(bytes, format)
+        .ok()
+        .and_then(|m| m.exif_orientation);
+    let reorient =
+        auto_orient && format.writing_enabled() && exif_orientation.is_some_and(|o| o != 1);
+    (exif_orientation, reorient)
+}
+
+/// How an uploaded image's EXIF orientation was handled, for its image document. Empty if it has
+/// no orientation tag
+fn orientation_record(exif_orientation: Option<u8>, reoriented: bool) -> Document {
+    match exif_orientation {
+        Some(orientation) => doc! {
+            "exif_orientation": orientation as i32,
+            "reoriented": reoriented,
+        },
+        None => Document::new(),
+    }
+}
+
+/// Write `data` to a new GridFS file, returning the file's ID
+async fn upload_file(db: &Db, name: &str, data: &[u8]) -> Result<Bson, Error> {
+    let bucket = db.gridfs_bucket();
+    let mut upload_stream = bucket.open_upload_stream(name, None);
+    upload_stream
+        .write_all(data)
+        .traced("upload", "fs")
+        .await
+        .map_err(db_error("Failed to upload image to database"))?;
+    METRICS.add_gridfs_bytes_written(data.len());
+    let id = upload_stream.id().clone();
+
+    // Close out the upload to latch it
+    upload_stream
+        .close()
+        .traced("finish_upload", "fs")
+        .await
+        .map_err(db_error("Failed to close upload stream for image"))?;
+    Ok(id)
+}
+
+/// The ObjectId in `id`. We only ever let MongoDB pick IDs, so anything else is a bug
+fn object_id(id: &Bson) -> Result<ObjectId, Error> {
+    id.as_object_id()
+        .ok_or_else(|| Error::internal(format!("Expected an ObjectId, got {}", id)))
+}
+
+/// Read the whole of a GridFS file
+async fn download_file(db: &Db, id: &Bson) -> Result<Vec<u8>, Error> {
+    let bucket = db.gridfs_bucket();
+    let mut download_stream = bucket
+        .open_download_stream(id.clone())
+        .traced("open_download", "fs")
+        .await
+        .map_err(db_error("Failed to open download stream for image"))?;
+    let mut data = Vec::new();
+    download_stream
+        .read_to_end(&mut data)
+        .traced("download", "fs")
+        .await
+        .map_err(db_error("Failed to read image data from database"))?;
+    METRICS.add_gridfs_bytes_read(data.len());
+    Ok(data)
+}
+
+/// Length of a GridFS file, and the size of its chunks, in bytes
+async fn file_layout(db: &Db, id: &Bson) -> Result<(u64, u64), Error> {
+    let file = db
+        .gridfs_files::<Document>()
+        .find_one(doc! { "_id": id.clone() }, None)
+        .traced("find_one", "fs.files")
+        .await
+        .map_err(db_error("Failed to query image data in database"))?
+        .ok_or_else(|| Error::database("Failed to find image data in database"))?;
+    // Drivers differ on whether these are 32- or 64-bit
+    let size = |key| match file.get(key) {
+        Some(Bson::Int32(n)) => u64::try_from(*n).ok(),
+        Some(Bson::Int64(n)) => u64::try_from(*n).ok(),
+        _ => None,
+    };
+    match (size("length"), size("chunkSize")) {
+        (Some(length), Some(chunk_size)) if chunk_size > 0 => Ok((length, chunk_size)),
+        _ => Err(Error::database(
+            "Failed to find image data length or chunk size in database",
+        )),
+    }
+}
+
+/// Stream bytes `range` of a GridFS file whose chunks are `chunk_size` bytes, reading only the
+/// chunks that hold them
+async fn stream_file_range(
+    db: &Db,
+    id: &Bson,
+    range: byte_ranges::ByteRange,
+    chunk_size: u64,
+) -> Result<impl futures_util::Stream<Item = Result<Vec<u8>, Error>>, Error> {
+    let (first, last) = (range.start / chunk_size, range.end / chunk_size);
+    let chunks = db
+        .gridfs_chunks::<Document>()
+        .find(
+            doc! { "files_id": id.clone(), "n": { "$gte": first as i64, "$lte": last as i64 } },
+            FindOptions::builder().sort(doc! { "n": 1 }).build(),
+        )
+        .traced("find", "fs.chunks")
+        .await
+        .map_err(db_error("Failed to open download stream for image"))?;
+    Ok(chunks.map(move |chunk| {
+        let chunk = chunk.map_err(db_error("Failed to read image data from database"))?;
+        let (Ok(n), Ok(data)) = (chunk.get_i32("n"), chunk.get_binary_generic("data")) else {
+            return Err(Error::database("Failed to read image data from database"));
+        };
+        // Only the first and last chunks are cut short
+        let offset = n as u64 * chunk_size;
+        let to = (range.end + 1 - offset).min(data.len() as u64) as usize;
+        let from = (range.start.saturating_sub(offset) as usize).min(to);
+        METRICS.add_gridfs_bytes_read(to - from);
+        Ok(data[from..to].to_vec())
+    }))
+}
+
+/// How an image document's data is compressed, if it is. Documents from before the
+/// `compression` field existed mark Brotli-compressed data with `brotli: true`
+fn stored_compression(image_doc: &Document) -> Result<Option<Compression>, Error> {
+    match image_doc.get_str("compression") {
+        Ok(coding) => Compression::from_content_encoding(coding)
+            .map(Some)
+            .ok_or_else(|| Error::database(format!("Unknown image compression \"{}\"", coding))),
+        Err(_) => Ok(image_doc
+            .get_bool("brotli")
+            .unwrap_or(false)
+            .then_some(Compression::Brotli)),
+    }
+}
+
+/// Load the named image's encoded data from the given collection, decompressed if it was stored
+/// compressed, along with its format and the ID of its GridFS file
+async fn fetch_image_data(
+    db: &Db,
+    collection_name: &str,
+    name: &str,
+) -> Result<(Vec<u8>, ImageFormat, Bson), Error> {
+    let images: Collection<Document> = db.collection(collection_name);
+    let image_doc = images
+        .find_one(doc! { "name": name }, None)
+        .traced("find_one", collection_name)
+        .await
+        .map_err(db_error("Failed to query image database"))?
+        .ok_or_else(|| Error::not_found(format!("Image {}", name)))?;
+
+    let (Some(image_id), Some(format)) = (
+        image_doc.get("image"),
+        image_doc
+            .get_str("mime_type")
+            .ok()
+            .and_then(ImageFormat::from_mime_type),
+    ) else {
+        return Err(Error::database(
+            "Failed to find image id or MIME type in database",
+        ));
+    };
+
+    let mut image_bytes = download_file(db, image_id).await?;
+    if let Some(compression) = stored_compression(&image_doc)? {
+        image_bytes = compression.decompress(&image_bytes)?;
+    }
+    Ok((image_bytes, format, image_id.clone()))
+}
+
+/// Load and decode the named image from the given collection, returning it along with the format
+/// it's stored in
+async fn fetch_image(
+    db: &Db,
+    collection_name: &str,
+    name: &str,
+) -> Result<(DynamicImage, ImageFormat), Error> {
+    let (image_bytes, format, _) = fetch_image_data(db, collection_name, name).await?;
+    Ok((ipr::decode_image(&image_bytes, format, None)?, format))
+}
+
+/// When the GridFS file with the given ID was uploaded
+async fn upload_date(db: &Db, id: &Bson) -> Result<Option<mongodb::bson::DateTime>, Error> {
+    let bucket = db.gridfs_bucket();
+    let mut files = bucket
+        .find(doc! { "_id": id.clone() }, None)
+        .traced("find", "fs.files")
+        .await
+        .map_err(db_error("Failed to query image files"))?;
+    Ok(files
+        .next()
+        .await
+        .transpose()
+        .map_err(db_error("Failed to read image file"))?
+        .map(|file| file.upload_date))
+}
+
+/// Store encoded image data as a new image with the given name, unless one already exists
+async fn store_new_image(
+    db: &Db,
+    name: &str,
+    data: &[u8],
+    format: ImageFormat,
+) -> Result<(), Error> {
+    let images: Collection<Document> = db.collection("images");
+    let existing = images
+        .find_one(doc! { "name": name }, None)
+        .traced("find_one", "images")
+        .await
+        .map_err(db_error("Failed to query image database"))?;
+    if existing.is_some() {
+        return Err(Error::conflict(format!("Image {}", name)));
+    }
+
+    let image_id = upload_file(db, name, data).await?;
+    let doc = doc! {
+        "name": name,
+        "image": image_id,
+        "mime_type": format.to_mime_type(),
+    };
+    images
+        .insert_one(doc, None)
+        .traced("insert_one", "images")
+        .await
+        .map_err(db_error("Failed to insert image into database"))?;
+    Ok(())
+}
+
+/// Store each of the given PNG-encoded images under its name, failing before storing any of
+/// them if one of the names is taken
+async fn store_new_pngs(
+    db: &Db,
+    images: Vec<(String, Vec<u8>)>,
+) -> Result<Vec<StoredImage>, Error> {
+    let names: Vec<&str> = images.iter().map(|(name, _)| name.as_str()).collect();
+    let collection: Collection<Document> = db.collection("images");
+    if let Some(existing) = collection
+        .find_one(doc! { "name": { "$in": names } }, None)
+        .traced("find_one", "images")
+        .await
+        .map_err(db_error("Failed to query image database"))?
+    {
+        let existing = existing.get_str("name").unwrap_or_default();
+        return Err(Error::conflict(format!("Image {}", existing)));
+    }
+
+    let mut stored = Vec::with_capacity(images.len());
+    for (name, data) in images {
+        store_new_image(db, &name, &data, ImageFormat::Png).await?;
+        stored.push(StoredImage {
+            url: format!("/api/v1/image/{}", name),
+            name,
+        });
+    }
+    Ok(stored)
+}
+
+/// Build a new image from the named one with `edit`, which runs on a blocking thread, and store
+/// it as `output`. The new image keeps the source's format where we can write it, and is PNG
+/// otherwise. `task` names the edit, for errors
+async fn store_edited_image(
+    app_state: &Arc<RwLock<RuntimeData>>,
+    name: &str,
+    output: String,
+    task: &'static str,
+    edit: impl FnOnce(&DynamicImage) -> Result<DynamicImage, Error> + Send + 'static,
+) -> ApiResult {
+    let (image, format) = {
+        let app = &app_state.read().await;
+        fetch_image(app.db()?, "images", name).await?
+    };
+
+    let format = match format.writing_enabled() {
+        true => format,
+        false => ImageFormat::Png,
+    };
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        ipr::encode_image(&edit(&image)?, format)
+    })
+    .await
+    .map_err(|_| Error::internal(format!("{} task panicked", task)))??;
+
+    let app = &mut app_state.write().await;
+    store_new_image(app.db()?, &output, &data, format).await?;
+
+    json_response(
+        StatusCode::CREATED,
+        &StoredImage {
+            url: format!("/api/v1/image/{}", output),
+            name: output,
+        },
+    )
+}
+
+/// Remove an image document, and the GridFS file it refers to
+async fn remove_image(
+    db: &Db,
+    collection: &Collection<Document>,
+    name: &str,
+    image_doc: &Document,
+) -> Result<(), Error> {
+    let image_id = image_doc
+        .get("image")
+        .ok_or_else(|| Error::database("Failed to find image id in database"))?;
+    db.gridfs_bucket()
+        .delete(image_id.clone())
+        .traced("delete", "fs")
+        .await
+        .map_err(db_error("Failed to delete image from database"))?;
+    collection
+        .delete_one(doc! { "name": name }, None)
+        .traced("delete_one", "images")
+        .await
+        .map_err(db_error("Failed to delete image document from database"))?;
+    Ok(())
+}
+
+pub async fn get_api_index(State(app_state): AppState) -> ApiResult {
     let app = &mut app_state.read().await;
 
     // Get handle to gridfs
-    let db = app.db.as_ref().unwrap();
+    let db = app.db()?;
     let mut images = Vec::<String>::new();
     let images_coll: Collection<Document> = db.collection("images");
-    let mut cursor = match images_coll.find(None, None).await {
-        Ok(c) => c,
-        Err(_e) => {
+    let mut cursor = images_coll
+        .find(None, None)
+        .traced("find", "images")
+        .await
+        .map_err(|_e| {
             debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query image database.\n",
-            )
-                .into_response();
-        }
-    };
+            Error::database("Failed to query image database")
+        })?;
 
     // Extract `name`, `image` and `mime_type` fields from each document in images collection.
     // Then, get the GridFS file from the ObjectId defined by `image` and decode it using the
     // ImageFormat associated with the `mime_type`, and push the DynamicImage to `images` using
     // `name` as the key
     while let Some(doc) = cursor.next().await {
-        match doc {
-            Ok(d) => {
-                let name = d.get("name").unwrap().as_str().unwrap();
+        let d = doc.map_err(|_e| {
+            debug_print!("Error: {}", _e);
+            Error::database("Failed to read image document")
+        })?;
+        let name = d.get("name").unwrap().as_str().unwrap();
 
-                images.push(name.to_string());
-            }
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to read image document.\n",
-                )
-                    .into_response();
-            }
-        }
+        images.push(name.to_string());
     }
 
-    (StatusCode::OK, IndexTemplate::new(&app.matrices, &images)).into_response()
+    Ok((StatusCode::OK, IndexTemplate::new(&app.matrices, &images)).into_response())
 }
 
 pub async fn get_hello() -> Response {
@@ -136,20 +721,16 @@ pub async fn get_hello() -> Response {
         (status = StatusCode::NOT_FOUND, description = "No such something", body = ())
     )
 )]
-pub async fn get_something(State(app_state): AppState, Path(id): Path<u32>) -> Response {
+pub async fn get_something(State(app_state): AppState, Path(id): Path<u32>) -> ApiResult {
     let app = &mut app_state.read().await;
-    match app.somethings.get(&id) {
-        Some(_) => (
-            StatusCode::OK,
-            format!("Getting something with id {}\n", id),
-        )
-            .into_response(),
-        None => (
-            StatusCode::NOT_FOUND,
-            format!("Something with id {} not found.\n", id),
-        )
-            .into_response(),
+    if !app.somethings.contains(&id) {
+        return Err(Error::not_found(format!("Something with id {}", id)).into());
     }
+    Ok((
+        StatusCode::OK,
+        format!("Getting something with id {}\n", id),
+    )
+        .into_response())
 }
 
 #[utoipa::path(
@@ -186,21 +767,16 @@ pub async fn put_something(State(app_state): AppState, Path(id): Path<u32>) -> R
         (status = StatusCode::NOT_FOUND, description = "No something with the given ID to delete", body = ())
     )
 )]
-pub async fn delete_something(State(app_state): AppState, Path(id): Path<u32>) -> Response {
+pub async fn delete_something(State(app_state): AppState, Path(id): Path<u32>) -> ApiResult {
     let app = &mut app_state.write().await;
-    if app.somethings.contains(&id) {
-        app.somethings.remove(&id);
-        return (
-            StatusCode::OK,
-            format!("Deleting something with id {}\n", id),
-        )
-            .into_response();
+    if !app.somethings.remove(&id) {
+        return Err(Error::not_found(format!("Something with id {}", id)).into());
     }
-    (
-        StatusCode::NOT_FOUND,
-        format!("Something with id {} not found.\n", id),
+    Ok((
+        StatusCode::OK,
+        format!("Deleting something with id {}\n", id),
     )
-        .into_response()
+        .into_response())
 }
 
 #[utoipa::path(
@@ -238,21 +814,16 @@ pub async fn post_something(State(app_state): AppState) -> Response {
         (status = StatusCode::CONFLICT, description = "Something with that ID already exists", body = ())
     )
 )]
-pub async fn post_something_with_id(State(app_state): AppState, Path(id): Path<u32>) -> Response {
+pub async fn post_something_with_id(State(app_state): AppState, Path(id): Path<u32>) -> ApiResult {
     let app = &mut app_state.write().await;
-    let inserted = app.somethings.insert(id);
-    match inserted {
-        true => (
-            StatusCode::CREATED,
-            format!("Posting something with id {}\n", id),
-        )
-            .into_response(),
-        false => (
-            StatusCode::CONFLICT,
-            format!("Something with id {} already exists.\n", id),
-        )
-            .into_response(),
+    if !app.somethings.insert(id) {
+        return Err(Error::conflict(format!("Something with id {}", id)).into());
     }
+    Ok((
+        StatusCode::CREATED,
+        format!("Posting something with id {}\n", id),
+    )
+        .into_response())
 }
 
 #[utoipa::path(
@@ -264,7 +835,7 @@ pub async fn post_something_with_id(State(app_state): AppState, Path(id): Path<u
     ),
     responses(
         (status = StatusCode::CREATED, description = "Added matrix with the given name", body = str),
-        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed parse matrix from request body", body = ()),
+        (status = StatusCode::BAD_REQUEST, description = "Failed to parse matrix from request body", body = ()),
         (status = StatusCode::CONFLICT, description = "Cannot POST new matrix with existing name. If this is intentional, use PUT", body = ())
     )
 )]
@@ -272,80 +843,270 @@ pub async fn post_matrix_with_name(
     State(app_state): AppState,
     Path(name): Path<String>,
     request: Request,
-) -> Response {
-    let mat_from_req = WrappedDynMatrix::<f64>::from_request(request, &app_state).await;
-    match mat_from_req {
-        Ok(WrappedDynMatrix(new_mat)) => {
-            let app = &mut app_state.write().await;
-            match app.matrices.contains_key(&name) {
-                true => (
-                    StatusCode::CONFLICT,
-                    "Cannot POST new matrix with existing name. If this is intentional, use PUT",
-                )
-                    .into_response(),
-                false => {
-                    app.matrices.insert(name.clone(), new_mat.clone());
-                    (StatusCode::CREATED, format!("Matrix {} received.\n", name)).into_response()
-                }
-            }
-        }
-        Err(_) => {
-            debug_print!("Failed to deserialize matrix name from string: {}", name);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read matrix from request.\n",
-            )
-                .into_response()
-        }
+) -> ApiResult {
+    let new_mat = read_matrix(request, &app_state).await?;
+    let app = &mut app_state.write().await;
+    if app.matrices.contains_key(&name) {
+        return Err(Error::conflict(format!("Matrix {}", name)).into());
+    }
+    app.matrices.insert(name.clone(), new_mat);
+    Ok((StatusCode::CREATED, format!("Matrix {} received.\n", name)).into_response())
+}
+
+/// One of the kernels [`post_matrix_from_kernel`] can make
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KernelInfo {
+    name: kernels::Kernel,
+    description: &'static str,
+
+    /// Query parameters the kernel takes. All are optional
+    parameters: Vec<&'static str>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/kernels",
+    responses(
+        (status = StatusCode::OK, description = "Lists the named kernels that can be made into matrices", body = Vec<KernelInfo>),
+    )
+)]
+pub async fn get_kernels() -> ApiResult {
+    let kernels = kernels::Kernel::ALL
+        .iter()
+        .map(|k| KernelInfo {
+            name: *k,
+            description: k.description(),
+            parameters: k.parameters().to_vec(),
+        })
+        .collect::<Vec<KernelInfo>>();
+    json_response(StatusCode::OK, &kernels)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct KernelQuery {
+    /// Standard deviation, for Gaussian kernels
+    sigma: Option<f64>,
+
+    /// Rows (and columns), for kernels of any size. Must be odd
+    size: Option<usize>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/matrix/{name}/from_kernel/{kernel}",
+    params(
+        ("sigma" = Option<f64>, Query, description = "Standard deviation, for gaussian. Defaults to 1.0"),
+        ("size" = Option<usize>, Query, description = "Rows (and columns), for gaussian and box. Must be odd. Defaults to 3 for box, and to three standard deviations either side of the center for gaussian"),
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Made the kernel, and stored it as a matrix with the given name. The matrix is returned in JSON format", body = DynMatrix<f64>),
+        (status = StatusCode::BAD_REQUEST, description = "Kernel parameters are out of range", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No kernel with the given name. See GET /api/v1/kernels", body = ()),
+        (status = StatusCode::CONFLICT, description = "Cannot POST new matrix with existing name. If this is intentional, delete it first", body = ())
+    )
+)]
+pub async fn post_matrix_from_kernel(
+    State(app_state): AppState,
+    Path((name, kernel)): Path<(String, String)>,
+    Query(query): Query<KernelQuery>,
+    Query(repr): Query<MatrixFormatQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let kernel = kernels::Kernel::from_name(&kernel)
+        .ok_or_else(|| Error::not_found(format!("Kernel {}", kernel)))?;
+    let matrix = kernel.build(query.sigma, query.size)?;
+
+    let app = &mut app_state.write().await;
+    if app.matrices.contains_key(&name) {
+        return Err(Error::conflict(format!("Matrix {}", name)).into());
     }
+    app.matrices.insert(name, matrix.clone());
+    matrix_response(StatusCode::CREATED, matrix, repr.format, &headers)
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/matrix/{name}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Returns matrix with the given name", body = MatrixSchema<f64>),
         (status = StatusCode::NOT_FOUND, description = "Unable to find matrix withthe given name", body = ()),
     )
 )]
-pub async fn get_matrix(State(app_state): AppState, Path(name): Path<String>) -> Response {
+pub async fn get_matrix(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(repr): Query<MatrixFormatQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
     let app = &mut app_state.read().await;
-    match app.matrices.get(&name) {
-        Some(mat) => (StatusCode::OK, WrappedDynMatrix(mat.clone())).into_response(),
-        None => (
-            StatusCode::NOT_FOUND,
-            format!("Matrix {} not found.\n", name),
-        )
-            .into_response(),
-    }
+    let mat = get_named_matrix(app, &name)?;
+    matrix_response(StatusCode::OK, mat.clone(), repr.format, &headers)
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/matrix/{name}/dims",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array ([rows, cols], the default) or object ({rows, cols})"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Returns dimensions of the matrix with the given name", body = Dims),
         (status = StatusCode::NOT_FOUND, description = "Unable to find matrix withthe given name", body = ()),
     )
 )]
-pub async fn get_matrix_dims(State(app_state): AppState, Path(name): Path<String>) -> Response {
+pub async fn get_matrix_dims(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(repr): Query<MatrixFormatQuery>,
+) -> ApiResult {
     let app = &mut app_state.read().await;
-    match app.matrices.get(&name) {
-        Some(mat) => {
-            let dims = mat.dims();
-            (StatusCode::OK, WrappedDims(dims)).into_response()
-        }
-        None => (
-            StatusCode::NOT_FOUND,
-            format!("Matrix {} not found.\n", name),
-        )
-            .into_response(),
+    let dims = get_named_matrix(app, &name)?.dims();
+    match repr.format {
+        MatrixFormat::Array => Ok((StatusCode::OK, WrappedDims(dims)).into_response()),
+        MatrixFormat::Object => json_response(StatusCode::OK, &NamedDims(dims)),
+    }
+}
+
+/// Norms of a matrix. See the matching methods of [`DynMatrix`]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MatrixNorms {
+    pub l1: f64,
+    pub inf: f64,
+    pub fro: f64,
+
+    /// In the 1-norm. `None` if the matrix isn't square, or is singular
+    pub condition_number: Option<f64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/matrix/{name}/norms",
+    responses(
+        (status = StatusCode::OK, description = "Returns the 1-, infinity- and Frobenius norms of the matrix with the given name, and its condition number", body = MatrixNorms),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find matrix with the given name", body = ()),
+    )
+)]
+pub async fn get_matrix_norms(State(app_state): AppState, Path(name): Path<String>) -> ApiResult {
+    let app = &app_state.read().await;
+    let mat = get_named_matrix(app, &name)?;
+    let norms = MatrixNorms {
+        l1: mat.norm_l1(),
+        inf: mat.norm_inf(),
+        fro: mat.norm_fro(),
+        condition_number: mat.condition_number().ok().filter(|c| c.is_finite()),
+    };
+    json_response(StatusCode::OK, &norms)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/matrix/{name}/status",
+    responses(
+        (status = StatusCode::OK, description = "Returns whether the matrix with the given name is still being computed in the background, failed, or is done. Matrices stored any other way are done", body = MatrixTaskStatus),
+        (status = StatusCode::NOT_FOUND, description = "No matrix with the given name, and none being computed", body = ()),
+    )
+)]
+pub async fn get_matrix_status(State(app_state): AppState, Path(name): Path<String>) -> ApiResult {
+    let app = &app_state.read().await;
+    let status = matrix_tasks::status(app, &name)
+        .ok_or_else(|| Error::not_found(format!("Matrix {}", name)))?;
+    json_response(StatusCode::OK, &status)
+}
+
+/// Whether (and where) to compute a matrix operation in the background
+#[derive(Debug, Default, Deserialize)]
+pub struct MatrixTaskQuery {
+    /// Whether to compute the result in the background. Defaults to doing so only for operations
+    /// taking more than the `async_matrix_work` tunable's worth of multiply-adds
+    background: Option<bool>,
+
+    /// Name to store the result under. Defaults to a generated one for results computed in the
+    /// background, and to sending others back
+    store_as: Option<String>,
+}
+
+/// Where to put the result of a matrix operation
+#[derive(Debug, Default, Deserialize)]
+pub struct StoreQuery {
+    /// Name to store the result under, rather than sending it back
+    store_as: Option<String>,
+}
+
+/// A `status` response with the JSON of `body`, and a `Location` header of `location`
+fn located_response<T: Serialize>(status: StatusCode, location: &str, body: &T) -> ApiResult {
+    let mut response = json_response(status, body)?;
+    if let Ok(location) = HeaderValue::from_str(location) {
+        response.headers_mut().insert(header::LOCATION, location);
     }
+    Ok(response)
+}
+
+/// Answer a matrix operation with its `result`: stored as the matrix `store_as`, if given, so it
+/// can be used in later operations without sending it back, or else sent back in `format`
+async fn matrix_result(
+    app_state: &Arc<RwLock<RuntimeData>>,
+    result: DynMatrix<f64>,
+    store_as: Option<String>,
+    format: MatrixFormat,
+    headers: &HeaderMap,
+) -> ApiResult {
+    let Some(name) = store_as else {
+        return matrix_response(StatusCode::OK, result, format, headers);
+    };
+    let app = &mut app_state.write().await;
+    matrix_tasks::check_name_free(app, &name)?;
+    // Clears out any failed attempt at computing it in the background
+    app.matrix_tasks.remove(&name);
+    app.matrices.insert(name.clone(), result);
+    let status = MatrixTaskStatus::new(&name, MatrixTaskState::Done, None);
+    located_response(StatusCode::CREATED, &status.url, &status)
+}
+
+/// Run `op` on the matrices named `a` and `b`, which takes `work` multiply-adds. Small operations
+/// are run now, and their result sent back in `format` or stored (see [`matrix_result`]). Big
+/// ones (see [`MatrixTaskQuery`]) are started in the background with copies of the matrices, and
+/// answered with `202 Accepted` and where to find the result
+async fn run_matrix_op(
+    app_state: &Arc<RwLock<RuntimeData>>,
+    (a, b): (&str, &str),
+    task: MatrixTaskQuery,
+    work: fn(&DynMatrix<f64>, &DynMatrix<f64>) -> u64,
+    op: fn(&DynMatrix<f64>, &DynMatrix<f64>) -> Result<DynMatrix<f64>, Error>,
+    format: MatrixFormat,
+    headers: &HeaderMap,
+) -> ApiResult {
+    let (mat_a, mat_b) = {
+        let app = app_state.read().await;
+        let mat_a = get_named_matrix(&app, a)?;
+        let mat_b = get_named_matrix(&app, b)?;
+        let background = task
+            .background
+            .unwrap_or_else(|| work(mat_a, mat_b) > app.tunables.load().async_matrix_work);
+        if !background {
+            let result = op(mat_a, mat_b)?;
+            drop(app);
+            return matrix_result(app_state, result, task.store_as, format, headers).await;
+        }
+        (mat_a.clone(), mat_b.clone())
+    };
+
+    let name = task
+        .store_as
+        .unwrap_or_else(|| format!("result_{}", uuid::Uuid::new_v4().simple()));
+    let status = matrix_tasks::start(app_state.clone(), name, move || op(&mat_a, &mat_b)).await?;
+    located_response(StatusCode::ACCEPTED, &status.status_url, &status)
 }
 
 #[utoipa::path(
     put,
     path = "/api/v1/matrix/{name}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+    ),
     request_body(
         content = DynMatrix<f64>,
         content_type = "application/json"
@@ -353,118 +1114,356 @@ pub async fn get_matrix_dims(State(app_state): AppState, Path(name): Path<String
     responses(
         (status = StatusCode::OK, description = "Updated matrix with the given name", body = DynMatrix<f64>),
         (status = StatusCode::CREATED, description = "Created matrix with the given name", body = DynMatrix<f64>),
-        (status = StatusCode::NOT_FOUND, description = "Unable to find matrix withthe given name", body = ()),
+        (status = StatusCode::BAD_REQUEST, description = "Failed to parse matrix from request body", body = ()),
     )
 )]
 pub async fn put_matrix(
     State(app_state): AppState,
     Path(name): Path<String>,
+    Query(repr): Query<MatrixFormatQuery>,
+    headers: HeaderMap,
     request: Request,
-) -> Response {
-    let mat_from_req = WrappedDynMatrix::<f64>::from_request(request, &app_state).await;
-    match mat_from_req {
-        Ok(WrappedDynMatrix(new_mat)) => {
-            let app = &mut app_state.write().await;
-            match app.matrices.contains_key(&name) {
-                true => {
-                    app.matrices.insert(name.clone(), new_mat.clone());
-                    (StatusCode::OK, WrappedDynMatrix(new_mat)).into_response()
-                }
-                false => {
-                    app.matrices.insert(name.clone(), new_mat.clone());
-                    (StatusCode::CREATED, WrappedDynMatrix(new_mat)).into_response()
-                }
-            }
-        }
-        Err(_) => {
-            debug_print!("Failed to deserialize matrix name from string: {}", name);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read matrix from request.\n",
-            )
-                .into_response()
-        }
-    }
+) -> ApiResult {
+    let new_mat = read_matrix(request, &app_state).await?;
+    let app = &mut app_state.write().await;
+    let status = match app.matrices.insert(name, new_mat.clone()) {
+        Some(_) => StatusCode::OK,
+        None => StatusCode::CREATED,
+    };
+    matrix_response(status, new_mat, repr.format, &headers)
 }
 
 #[utoipa::path(
     delete,
     path = "/api/v1/matrix/{name}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Deleted matrix with the given name and returned it", body = DynMatrix<f64>),
         (status = StatusCode::NOT_FOUND, description = "Unable to find matrix withthe given name", body = ()),
     )
 )]
-pub async fn delete_matrix(State(app_state): AppState, Path(name): Path<String>) -> Response {
+pub async fn delete_matrix(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(repr): Query<MatrixFormatQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
     let app = &mut app_state.write().await;
-    match app.matrices.remove(&name) {
-        Some(mat) => (StatusCode::OK, WrappedDynMatrix(mat)).into_response(),
-        None => (
-            StatusCode::NOT_FOUND,
-            format!("Matrix {} not found.\n", name),
-        )
-            .into_response(),
-    }
+    let mat = app
+        .matrices
+        .remove(&name)
+        .ok_or_else(|| Error::not_found(format!("Matrix {}", name)))?;
+    matrix_response(StatusCode::OK, mat, repr.format, &headers)
 }
 
 #[utoipa::path(
     post,
     path = "/api/v1/matrix/multiply/{name1}/{name2}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("background" = Option<bool>, Query, description = "Whether to compute the result in the background. Defaults to doing so only for products taking more multiply-adds than the async_matrix_work tunable"),
+        ("store_as" = Option<String>, Query, description = "Name to store the result under, rather than sending it back. Defaults to a generated one for results computed in the background"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Computation completed and result is returned in JSON format", body = DynMatrix<f64>),
-        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Invalid matrix multiplication", body = ()),
+        (status = StatusCode::CREATED, description = "Result stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::ACCEPTED, description = "Computation started in the background. Poll the returned status_url, then fetch the result from url", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "One of the matrices is still being computed, or there's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Matrix dimensions are incompatible", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find a matrix with one of the given names", body = ()),
     )
 )]
 pub async fn post_matrix_multiply(
     State(app_state): AppState,
     Path((name1, name2)): Path<(String, String)>,
-) -> Response {
-    let app = &mut app_state.write().await;
-    let mat1 = app.matrices.get(&name1).unwrap();
-    let mat2 = app.matrices.get(&name2).unwrap();
-    let result = mat1 * mat2;
-    // Return result in body
-    (StatusCode::OK, WrappedDynMatrix(result.clone())).into_response()
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(task): Query<MatrixTaskQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    run_matrix_op(
+        &app_state,
+        (&name1, &name2),
+        task,
+        |a, b| (a.rows() * a.cols() * b.cols()) as u64,
+        |a, b| a.try_mul(b),
+        repr.format,
+        &headers,
+    )
+    .await
 }
 
 #[utoipa::path(
     post,
     path = "/api/v1/matrix/add/{name1}/{name2}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("store_as" = Option<String>, Query, description = "Name to store the result under, rather than sending it back"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Computation completed and result is returned in JSON format", body = DynMatrix<f64>),
-        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Invalid matrix addition (check matrix dimensions)", body = ()),
+        (status = StatusCode::CREATED, description = "Result stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "There's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Matrix dimensions are incompatible", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find a matrix with one of the given names", body = ()),
     )
 )]
 pub async fn post_matrix_add(
     State(app_state): AppState,
     Path((name1, name2)): Path<(String, String)>,
-) -> Response {
-    let app = &mut app_state.write().await;
-    let mat1 = app.matrices.get(&name1).unwrap();
-    let mat2 = app.matrices.get(&name2).unwrap();
-    let result = mat1 + mat2;
-    // Return result in body
-    (StatusCode::OK, WrappedDynMatrix(result.clone())).into_response()
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(store): Query<StoreQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let result = {
+        let app = &app_state.read().await;
+        let mat1 = get_named_matrix(app, &name1)?;
+        let mat2 = get_named_matrix(app, &name2)?;
+        mat1.try_add(mat2)?
+    };
+    matrix_result(&app_state, result, store.store_as, repr.format, &headers).await
 }
 
 #[utoipa::path(
     post,
     path = "/api/v1/matrix/subtract/{name1}/{name2}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("store_as" = Option<String>, Query, description = "Name to store the result under, rather than sending it back"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Computation completed and result is returned in JSON format", body = DynMatrix<f64>),
-        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Invalid matrix subtraction (check matrix dimensions)", body = ()),
+        (status = StatusCode::CREATED, description = "Result stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "There's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Matrix dimensions are incompatible", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find a matrix with one of the given names", body = ()),
     )
 )]
 pub async fn post_matrix_subtract(
     State(app_state): AppState,
     Path((name1, name2)): Path<(String, String)>,
-) -> Response {
-    let app = &mut app_state.write().await;
-    let mat1 = app.matrices.get(&name1).unwrap();
-    let mat2 = app.matrices.get(&name2).unwrap();
-    let result = mat1 - mat2;
-    // Return result in body
-    (StatusCode::OK, WrappedDynMatrix(result.clone())).into_response()
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(store): Query<StoreQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let result = {
+        let app = &app_state.read().await;
+        let mat1 = get_named_matrix(app, &name1)?;
+        let mat2 = get_named_matrix(app, &name2)?;
+        mat1.try_sub(mat2)?
+    };
+    matrix_result(&app_state, result, store.store_as, repr.format, &headers).await
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ScaleQuery {
+    factor: f64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/matrix/scale/{name}",
+    params(
+        ("factor" = f64, Query, description = "Value to multiply every element by"),
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("store_as" = Option<String>, Query, description = "Name to store the result under, rather than sending it back"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Computation completed and result is returned in JSON format", body = DynMatrix<f64>),
+        (status = StatusCode::CREATED, description = "Result stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "There's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::BAD_REQUEST, description = "Missing or invalid scale factor", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find matrix with the given name", body = ()),
+    )
+)]
+pub async fn post_matrix_scale(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(query): Query<ScaleQuery>,
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(store): Query<StoreQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let result = {
+        let app = &app_state.read().await;
+        get_named_matrix(app, &name)? * query.factor
+    };
+    matrix_result(&app_state, result, store.store_as, repr.format, &headers).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/matrix/hadamard/{name1}/{name2}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("store_as" = Option<String>, Query, description = "Name to store the result under, rather than sending it back"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Elementwise product computed and result is returned in JSON format", body = DynMatrix<f64>),
+        (status = StatusCode::CREATED, description = "Result stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "There's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Matrices have different dimensions", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find a matrix with one of the given names", body = ()),
+    )
+)]
+pub async fn post_matrix_hadamard(
+    State(app_state): AppState,
+    Path((name1, name2)): Path<(String, String)>,
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(store): Query<StoreQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let result = {
+        let app = &app_state.read().await;
+        let mat1 = get_named_matrix(app, &name1)?;
+        let mat2 = get_named_matrix(app, &name2)?;
+        mat1.try_hadamard(mat2)?
+    };
+    matrix_result(&app_state, result, store.store_as, repr.format, &headers).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/matrix/transpose/{name}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("store_as" = Option<String>, Query, description = "Name to store the result under, rather than sending it back"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Transpose computed and result is returned in JSON format", body = DynMatrix<f64>),
+        (status = StatusCode::CREATED, description = "Result stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "There's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find matrix with the given name", body = ()),
+    )
+)]
+pub async fn post_matrix_transpose(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(store): Query<StoreQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let result = {
+        let app = &app_state.read().await;
+        get_named_matrix(app, &name)?.transpose()
+    };
+    matrix_result(&app_state, result, store.store_as, repr.format, &headers).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/matrix/solve/{a}/{b}",
+    params(
+        ("format" = Option<String>, Query, description = "How to write the result: array (nested arrays, the default) or object ({rows, cols, dtype, data})"),
+        ("background" = Option<bool>, Query, description = "Whether to solve in the background. Defaults to doing so only for systems taking more multiply-adds than the async_matrix_work tunable"),
+        ("store_as" = Option<String>, Query, description = "Name to store x under, rather than sending it back. Defaults to a generated one for solutions found in the background"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Found x such that A·x = B, returned in JSON format", body = DynMatrix<f64>),
+        (status = StatusCode::CREATED, description = "x stored under the store_as name, which the returned url serves", body = MatrixTaskStatus),
+        (status = StatusCode::ACCEPTED, description = "Solving in the background. Poll the returned status_url, then fetch x from url", body = MatrixTaskStatus),
+        (status = StatusCode::CONFLICT, description = "One of the matrices is still being computed, or there's already a matrix with the store_as name", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find a matrix with one of the given names", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "A is not square, B has a different number of rows than A, or A is singular", body = ()),
+    )
+)]
+pub async fn post_matrix_solve(
+    State(app_state): AppState,
+    Path((a, b)): Path<(String, String)>,
+    Query(repr): Query<MatrixFormatQuery>,
+    Query(task): Query<MatrixTaskQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    // Elimination takes about n^3 / 3 multiply-adds, then n^2 for each column of B
+    run_matrix_op(
+        &app_state,
+        (&a, &b),
+        task,
+        |a, b| {
+            let n = a.rows() as u64;
+            n * n * n / 3 + n * n * b.cols() as u64
+        },
+        |a, b| a.solve(b),
+        repr.format,
+        &headers,
+    )
+    .await
+}
+
+/// How far apart matrix elements may be and still match, unless told otherwise
+pub const DEFAULT_MATRIX_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MatrixCompareQuery {
+    /// Largest difference between elements for them to match. Defaults to
+    /// [`DEFAULT_MATRIX_EPSILON`]
+    epsilon: Option<f64>,
+}
+
+/// Result of `POST /api/v1/matrix/compare/{a}/{b}`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MatrixCompareResult {
+    /// Whether the matrices have the same dimensions, and no elements differ by more than
+    /// `epsilon`
+    matches: bool,
+
+    /// The largest absolute difference between corresponding elements. `None` if the
+    /// dimensions differ
+    max_abs_diff: Option<f64>,
+
+    epsilon: f64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/matrix/compare/{a}/{b}",
+    params(
+        ("epsilon" = Option<f64>, Query, description = "Largest difference between corresponding elements for the matrices to match. Defaults to 1e-9"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Compared the matrices. Matrices with different dimensions never match", body = MatrixCompareResult),
+        (status = StatusCode::BAD_REQUEST, description = "Epsilon is negative, or not a number", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "Unable to find a matrix with one of the given names", body = ()),
+    )
+)]
+pub async fn post_matrix_compare(
+    State(app_state): AppState,
+    Path((a, b)): Path<(String, String)>,
+    Query(query): Query<MatrixCompareQuery>,
+) -> ApiResult {
+    let epsilon = query.epsilon.unwrap_or(DEFAULT_MATRIX_EPSILON);
+    if epsilon.is_nan() || epsilon < 0.0 {
+        return Err(Error::validation("Epsilon must be zero or more").into());
+    }
+    let app = &app_state.read().await;
+    let mat_a = get_named_matrix(app, &a)?;
+    let mat_b = get_named_matrix(app, &b)?;
+    let max_abs_diff = mat_a.max_abs_diff(mat_b).ok();
+    let result = MatrixCompareResult {
+        matches: mat_a.approx_eq(mat_b, epsilon),
+        // NaN has no JSON representation
+        max_abs_diff: max_abs_diff.filter(|d| !d.is_nan()),
+        epsilon,
+    };
+    json_response(StatusCode::OK, &result)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadQuery {
+    /// Whether to rotate and flip images upright per their EXIF orientation. Defaults to true
+    auto_orient: Option<bool>,
+
+    /// Comma-separated tags to give the image
+    tags: Option<String>,
+}
+
+/// The tags given with an upload, as a comma-separated list
+fn upload_tags(tags: Option<&str>) -> Result<Vec<String>, Error> {
+    match tags {
+        Some(tags) => documents::normalize_tags(&tags.split(',').collect::<Vec<_>>()),
+        None => Ok(Vec::new()),
+    }
 }
 
 #[utoipa::path(
@@ -473,201 +1472,176 @@ pub async fn post_matrix_subtract(
     request_body(
         content = Bytes,
     ),
+    params(
+        ("auto_orient" = Option<bool>, Query, description = "Whether to rotate and flip the image upright per its EXIF orientation, re-encoding it if needed. Defaults to true"),
+        ("tags" = Option<String>, Query, description = "Comma-separated tags to give the image"),
+    ),
     responses(
         (status = StatusCode::CREATED, description = "Added the image with the returned ID", body = ()),
         (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
-        (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type.", body = ()),
-        (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ())
+        (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type. Also sent for invalid tags", body = ()),
+        (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ()),
+        (status = StatusCode::PAYLOAD_TOO_LARGE, description = "Image data is larger than the upload size limit.", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Image has more pixels than allowed, or can't be decoded.", body = ())
     )
 )]
-pub async fn post_image(State(app_state): AppState, request: Request) -> Response {
-    let content_disposition_hdr = request.headers().get("Content-Disposition");
-    let image_name: String = if content_disposition_hdr.is_some() {
-        let content_disposition = content_disposition_hdr.unwrap().to_str().unwrap();
-        let parts: Vec<&str> = content_disposition.split(';').collect();
-        let name_part = parts.iter().find(|&p| p.starts_with("filename"));
-        if name_part.is_none() {
-            return (
-                StatusCode::BAD_REQUEST,
-                "Unable to handle request. Please pass an image body and specify content type.\n",
-            )
-                .into_response();
+pub async fn post_image(
+    State(app_state): AppState,
+    Query(params): Query<UploadQuery>,
+    request: Request,
+) -> ApiResult {
+    let image_name = match upload_name(&request)? {
+        Some(name) => name,
+        None => {
+            let app = &mut app_state.write().await;
+            let new_name = format!("image_{}", app.image_counter);
+            app.image_counter += 1;
+            new_name
         }
-        let name_part = name_part.unwrap();
-        let name_parts: Vec<&str> = name_part.split('=').collect();
-        let name = name_parts.get(1).unwrap();
-        name.to_string()
-    } else {
-        let app = &mut app_state.write().await;
-        let new_name = format!("image_{}", app.image_counter);
-        app.image_counter += 1;
-        new_name
     };
     debug_print!("Attempting to add new image with name {}", image_name);
 
-    let content_type_hdr = request.headers().get("Content-Type");
-    if content_type_hdr.is_none() {
-        return (
-            StatusCode::BAD_REQUEST,
-            "Unable to handle request. Please pass an image body and specify content type.\n",
-        )
-            .into_response();
-    }
-
-    let mime_type = content_type_hdr.unwrap().to_str().unwrap();
-    let format: ImageFormat = match ImageFormat::from_mime_type(mime_type) {
-        Some(fmt) => fmt,
-        None => {
-            return (
-                StatusCode::NOT_ACCEPTABLE,
-                format!("The given MIME Type \"{}\" is not supported", mime_type),
-            )
-                .into_response()
-        }
-    };
-    debug_print!("Detected MIME Type: \"{}\"", mime_type);
+    let format = upload_format(&request)?;
+    let tags = upload_tags(params.tags.as_deref())?;
+    let bytes = read_body(request, &app_state).await?;
+    check_upload_dimensions(&bytes, format, None, &app_state).await?;
+    let content_sha256 = web_routines::content_hash(&bytes);
 
-    let bytes = match Bytes::from_request(request, &app_state).await {
-        Ok(b) => b.to_vec(),
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read image data from request body.\n",
-            )
-                .into_response()
-        }
+    // Phone cameras save photos sideways and tag them with how to turn them, which browsers
+    // honor but we'd otherwise lose when tiling or processing them
+    let (exif_orientation, reorient) =
+        upload_orientation(&bytes, format, params.auto_orient.unwrap_or(true));
+    let bytes = match exif_orientation.filter(|_| reorient) {
+        Some(orientation) => tokio::task::spawn_blocking(move || {
+            let image = decode_upload(&bytes, format, None)?;
+            ipr::encode_image(&ipr::apply_exif_orientation(image, orientation), format)
+        })
+        .await
+        .map_err(|_| Error::internal("Orientation task panicked"))??,
+        None => bytes,
     };
-    debug_print!("Extracted image data with byte length: {}", bytes.len());
 
     let app = &mut app_state.write().await;
+    let db = app.db()?;
 
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
-
-    let bucket = db.gridfs_bucket(None);
-    let mut upload_stream = bucket.open_upload_stream(image_name.clone(), None);
-    let upload_result = upload_stream.write_all(&bytes).await;
-    match upload_result {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to upload image to database.\n",
-            )
-                .into_response();
-        }
-    }
-    let image_id = upload_stream.id().clone();
-
-    // Now that we have a handle to the uploaded ID and created a document, close out the
-    // upload to latch it.
-    match upload_stream.close().await {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to close upload stream for image.\n",
-            )
-                .into_response();
-        }
-    }
-
-    let doc = doc! {
+    let image_id = upload_file(db, &image_name, &bytes).await?;
+    let mut doc = doc! {
         "name": image_name.clone(),
         "image": image_id,
         "mime_type": format.to_mime_type(),
+        // Of the upload, before it was turned upright
+        "content_sha256": content_sha256,
     };
-
-    match db.collection("images").insert_one(doc, None).await {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to insert image into database.\n",
-            )
-                .into_response();
-        }
+    doc.extend(orientation_record(exif_orientation, reorient));
+    if !tags.is_empty() {
+        doc.insert("tags", tags);
     }
+    db.collection("images")
+        .insert_one(doc, None)
+        .traced("insert_one", "images")
+        .await
+        .map_err(db_error("Failed to insert image into database"))?;
 
-    (
+    Ok((
         StatusCode::CREATED,
         format!("Image added with name {}.", image_name),
     )
-        .into_response()
+        .into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImageListQuery {
+    /// Only list images with this tag
+    tag: Option<String>,
+
+    /// Only list images whose names contain this, ignoring case
+    q: Option<String>,
+}
+
+impl ImageListQuery {
+    /// The Mongo filter selecting the images this query asks for
+    fn filter(&self) -> Document {
+        let mut filter = Document::new();
+        if let Some(tag) = &self.tag {
+            filter.insert("tags", tag.as_str());
+        }
+        if let Some(q) = &self.q {
+            filter.insert("name", web_routines::contains_ignoring_case(q));
+        }
+        filter
+    }
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/images",
+    params(
+        ("tag" = Option<String>, Query, description = "Only list images with this tag"),
+        ("q" = Option<String>, Query, description = "Only list images whose names contain this, ignoring case"),
+    ),
     responses(
         (status = StatusCode::OK, description = "Returned a JSON list of image documents", body = Json),
     )
 )]
-pub async fn get_images(State(app_state): AppState) -> Response {
+pub async fn get_images(
+    State(app_state): AppState,
+    Query(params): Query<ImageListQuery>,
+) -> ApiResult {
     let app = &mut app_state.read().await;
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
+    let db = app.db()?;
     let images: Collection<Document> = db.collection("images");
-    let mut found = match images.find(None, None).await {
-        Ok(cursor) => cursor,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query image database.\n",
-            )
-                .into_response();
-        }
-    };
+    let mut found = images
+        .find(params.filter(), None)
+        .traced("find", "images")
+        .await
+        .map_err(db_error("Failed to query image database"))?;
 
     let mut image_docs = Vec::new();
     while let Some(doc) = found.next().await {
-        match doc {
-            Ok(d) => {
-                image_docs.push(d);
-            }
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to read image document.\n",
-                )
-                    .into_response();
-            }
-        }
+        image_docs.push(doc.map_err(db_error("Failed to read image document"))?);
     }
 
-    let json = match serde_json::to_string(&image_docs) {
-        Ok(j) => j,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to serialize pyramid document.\n",
-            )
-                .into_response();
-        }
-    };
+    json_response(StatusCode::OK, &image_docs)
+}
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(Body::from(json.to_string()))
-        .unwrap()
+/// Request headers that change how an image is sent
+const IMAGE_VARY: &str = "Accept, Accept-Encoding, X-Image-Quality";
+
+/// Header asking for images to be encoded at a given quality, like the `quality` query parameter
+pub const X_IMAGE_QUALITY: HeaderName = HeaderName::from_static("x-image-quality");
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImageQuery {
+    /// Quality to encode lossy formats at, from 1 to 100
+    quality: Option<u8>,
+}
+
+/// The quality the client wants images encoded at, if any. The query parameter wins over the
+/// header
+fn requested_quality(request: &Request) -> Result<Option<u8>, Error> {
+    let query = Query::<ImageQuery>::try_from_uri(request.uri())
+        .map_err(|_| Error::validation("Quality must be a whole number from 1 to 100"))?;
+    let quality = match query.0.quality {
+        Some(q) => Some(q),
+        None => match request.headers().get(X_IMAGE_QUALITY) {
+            Some(h) => Some(
+                h.to_str()
+                    .ok()
+                    .and_then(|h| h.trim().parse::<u8>().ok())
+                    .ok_or_else(|| {
+                        Error::validation("X-Image-Quality must be a whole number from 1 to 100")
+                    })?,
+            ),
+            None => None,
+        },
+    };
+    match quality {
+        Some(q) if !(1..=100).contains(&q) => Err(Error::validation(format!(
+            "Quality must be from 1 to 100, not {}",
+            q
+        ))),
+        q => Ok(q),
+    }
 }
 
 pub async fn get_image_from_collection(
@@ -675,187 +1649,146 @@ pub async fn get_image_from_collection(
     Path(name): Path<String>,
     request: Request,
     collection_name: &str,
-) -> Response {
-    // If name has an extension, try to discern the desired format from it. But drop the extension
-    // for the purpose of image lookup. We try to adhere to user request, but default to PNG if
-    // anything goes wrong
-    let ext_str = name.split('.').last().unwrap_or("png");
-    let default_format = ImageFormat::from_extension(ext_str).unwrap_or(ImageFormat::Png);
+) -> ApiResult {
+    let quality = requested_quality(&request)?;
+
+    // If name has an extension, it says which format the client wants. Drop the extension for
+    // the purpose of image lookup
+    let requested_format = name
+        .split_once('.')
+        .and_then(|(_, ext)| ImageFormat::from_extension(ext));
 
     let name_without_ext = name.split('.').next().unwrap_or(name.as_str());
     let app = &mut app_state.read().await;
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
+    let db = app.db()?;
     let images: Collection<Document> = db.collection(collection_name);
-    let mut found = match images
-        .find(
-            doc! {
-                "name": name_without_ext
-            },
-            None,
-        )
+    let image_doc = images
+        .find_one(doc! { "name": name_without_ext }, None)
+        .traced("find_one", collection_name)
         .await
-    {
-        Ok(cursor) => cursor,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query image database.\n",
-            )
-                .into_response();
-        }
-    };
-
-    // This is jank because there's no good way to count results before iterating through them.
-    let image_doc = match found.next().await {
-        Some(doc) => match doc {
-            Ok(d) => d,
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to read image document.\n",
-                )
-                    .into_response();
-            }
-        },
-        None => {
-            return (
-                StatusCode::NOT_FOUND,
-                format!("Image {} not found.\n", name),
-            )
-                .into_response();
-        }
-    };
+        .map_err(db_error("Failed to query image database"))?
+        .ok_or_else(|| Error::not_found(format!("Image {}", name)))?;
 
-    let image_id = image_doc.get("image");
-    if image_id.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to find image id in database.\n",
-        )
-            .into_response();
-    }
-    let image_id = image_id.unwrap();
+    let image_id = image_doc
+        .get("image")
+        .ok_or_else(|| Error::database("Failed to find image id in database"))?;
+    let stored_format = image_doc
+        .get_str("mime_type")
+        .ok()
+        .and_then(ImageFormat::from_mime_type)
+        .ok_or_else(|| Error::database("Failed to find image MIME type in database"))?;
 
-    let mime_type = match image_doc.get("mime_type") {
-        Some(m) => m.as_str().unwrap(),
-        None => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to find image MIME type in database.\n",
-            )
-                .into_response();
-        }
-    };
+    // The Accept header has the final say. Failing that, honor the extension in the URL, and
+    // otherwise send the image as it's stored
+    let accept = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok());
+    let preferred = requested_format
+        .into_iter()
+        .chain(std::iter::once(stored_format))
+        .collect::<Vec<ImageFormat>>();
+    let dest_format = content_negotiation::choose_image_format(accept, &preferred);
 
-    let bucket = db.gridfs_bucket(None);
-    let mut image_bytes = Vec::new();
-    let mut download_stream = match bucket.open_download_stream(image_id.clone()).await {
-        Ok(s) => s,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to open download stream for image.\n",
-            )
-                .into_response();
-        }
-    };
+    // Quality only means anything to lossy formats, but for them it means re-encoding even if the
+    // format stays the same
+    let quality = quality.filter(|_| ipr::format_supports_quality(dest_format));
+    let reencode = dest_format != stored_format || quality.is_some();
 
-    match download_stream.read_to_end(&mut image_bytes).await {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read image data from database.\n",
-            )
-                .into_response();
-        }
-    };
+    // Compressed data goes out as stored if we aren't re-encoding it, and the client can decode
+    // it. Otherwise we decompress it here
+    let stored_compression = stored_compression(&image_doc)?;
+    let accept_encoding = request
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|h| h.to_str().ok());
+    let content_encoding = stored_compression
+        .filter(|&c| !reencode && content_negotiation::accepts_encoding(accept_encoding, c));
 
-    // If a header is specified, prefer to honor that over what might be in the request URL
-    let dest_format = match request.headers().get("Accept") {
-        Some(accept_hdr) => {
-            let accept = accept_hdr.to_str().unwrap();
-            match ImageFormat::from_mime_type(accept) {
-                Some(fmt) => fmt,
-                None => default_format,
-            }
-        }
-        None => default_format,
+    // If the client already has this file in this format, there's no need to even download it
+    let etag = caching::image_etag(image_id, dest_format, quality, content_encoding);
+    let cache_control = match collection_name == "tiles"
+        || web_routines::parse_tile_name(name_without_ext).is_some()
+    {
+        true => app.cache_control.tiles.clone(),
+        false => app.cache_control.images.clone(),
     };
+    let client_is_current = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| caching::if_none_match(h, &etag));
+    METRICS.record_image_cache(client_is_current);
+    if client_is_current {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag)
+            .header(header::CACHE_CONTROL, cache_control)
+            .header(header::VARY, IMAGE_VARY)
+            .body(Body::empty())
+            .unwrap());
+    }
 
-    let is_brotli: bool = image_doc.get_bool("brotli").unwrap_or(false);
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, dest_format.to_mime_type())
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::VARY, IMAGE_VARY);
+    if let Some(compression) = content_encoding {
+        builder = builder.header(header::CONTENT_ENCODING, compression.content_encoding());
+    }
 
-    image_bytes = if dest_format == default_format {
-        image_bytes
-    } else {
-        let data_to_re_encode = if is_brotli {
-            let mut decompressed = Vec::new();
-            match brotli::BrotliDecompress(
-                &mut Cursor::new(image_bytes),
-                &mut Cursor::new(&mut decompressed),
-            ) {
-                Ok(_) => (),
-                Err(_) => {
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Failed to decompress image data.\n",
-                    )
-                        .into_response();
+    // Data that goes out just as it's stored can be sent in part, straight from GridFS. A Range
+    // header only counts if any If-Range header says the client's partial copy is this one
+    let as_stored = !reencode && (stored_compression.is_none() || content_encoding.is_some());
+    if as_stored {
+        builder = builder.header(header::ACCEPT_RANGES, "bytes");
+        let header_str = |name| request.headers().get(name).and_then(|h| h.to_str().ok());
+        let range = header_str(header::RANGE).filter(|_| {
+            header_str(header::IF_RANGE).map_or(true, |h| byte_ranges::if_range_matches(h, &etag))
+        });
+        if let Some(range) = range {
+            let (length, chunk_size) = file_layout(db, image_id).await?;
+            match byte_ranges::parse_range(range, length) {
+                byte_ranges::RangeRequest::Full => {}
+                byte_ranges::RangeRequest::Partial(range) => {
+                    let data = stream_file_range(db, image_id, range, chunk_size).await?;
+                    return Ok(builder
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header(header::CONTENT_RANGE, range.content_range(length))
+                        .header(header::CONTENT_LENGTH, range.len())
+                        .body(Body::from_stream(data))
+                        .unwrap());
+                }
+                byte_ranges::RangeRequest::Unsatisfiable => {
+                    return Ok(builder
+                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header(
+                            header::CONTENT_RANGE,
+                            byte_ranges::unsatisfied_range(length),
+                        )
+                        .body(Body::empty())
+                        .unwrap());
                 }
-            }
-            decompressed
-        } else {
-            image_bytes
-        };
-
-        let image = match image::load_from_memory_with_format(
-            &data_to_re_encode,
-            ImageFormat::from_mime_type(mime_type).unwrap(),
-        ) {
-            Ok(img) => img,
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to load image from memory.\n",
-                )
-                    .into_response();
-            }
-        };
-
-        let mut re_encoded_data = Vec::new();
-        let mut cursor = Cursor::new(&mut re_encoded_data);
-        match image.write_to(&mut cursor, dest_format) {
-            Ok(_) => re_encoded_data,
-            Err(_) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to write image data to response body.\n",
-                )
-                    .into_response()
             }
         }
-    };
+    }
 
-    let mut builder = Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", dest_format.to_mime_type());
-    if is_brotli {
-        builder = builder.header("Content-Encoding", "br");
+    let mut image_bytes = download_file(db, image_id).await?;
+    if content_encoding.is_none() {
+        if let Some(compression) = stored_compression {
+            image_bytes = compression.decompress(&image_bytes)?;
+        }
+    }
+    if reencode {
+        let image = ipr::decode_image(&image_bytes, stored_format, None)?;
+        image_bytes = ipr::encode_image_with_quality(&image, dest_format, quality)?;
     }
 
-    builder.body(Body::from(image_bytes)).unwrap()
+    Ok(builder
+        .status(StatusCode::OK)
+        .body(Body::from(image_bytes))
+        .unwrap())
 }
 
 pub async fn put_image_in_collection(
@@ -863,256 +1796,128 @@ pub async fn put_image_in_collection(
     Path(image_name): Path<String>,
     request: Request,
     collection_name: &str,
-) -> Response {
-    let content_type_hdr = request.headers().get("Content-Type");
-    if content_type_hdr.is_none() {
-        return (
-            StatusCode::BAD_REQUEST,
-            "Unable to handle request. Please pass an image body and specify content type.\n",
-        )
-            .into_response();
-    }
-
-    let mime_type = content_type_hdr.unwrap().to_str().unwrap();
-    let format: ImageFormat = match ImageFormat::from_mime_type(mime_type) {
-        Some(fmt) => fmt,
-        None => {
-            return (
-                StatusCode::NOT_ACCEPTABLE,
-                format!("The given MIME Type \"{}\" is not supported", mime_type),
-            )
-                .into_response()
-        }
-    };
-    debug_print!("Detected MIME Type: \"{}\"", mime_type);
-
-    let bytes = match Bytes::from_request(request, &app_state).await {
-        Ok(b) => b.to_vec(),
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read image data from request body.\n",
-            )
-                .into_response()
-        }
-    };
-    debug_print!("Extracted image data with byte length: {}", bytes.len());
+) -> ApiResult {
+    let format = upload_format(&request)?;
+    let bytes = read_body(request, &app_state).await?;
+    check_upload_dimensions(&bytes, format, None, &app_state).await?;
 
     let app = &mut app_state.write().await;
-
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
+    let db = app.db()?;
 
     // Check if there is an existing document in the image collection with the given name. If there
-    // is, get the `image` ObjectId for the GridFS file. Delete both the document and the GridFS file
-
+    // is, delete both the document and the GridFS file it refers to
     let image_collection: Collection<Document> = db.collection(collection_name);
-    let existing_image = match image_collection
-        .find_one(
-            doc! {
-                "name": image_name.clone()
-            },
-            None,
-        )
+    let existing_image = image_collection
+        .find_one(doc! { "name": image_name.clone() }, None)
+        .traced("find_one", collection_name)
         .await
-    {
-        Ok(d) => d,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query image database.\n",
-            )
-                .into_response();
-        }
-    };
+        .map_err(db_error("Failed to query image database"))?;
 
-    let mut deleted_old: bool = false;
-    if existing_image.is_some() {
-        deleted_old = true;
-        let existing_image = existing_image.unwrap();
-        let image_id = existing_image.get("image").unwrap();
-        let bucket = db.gridfs_bucket(None);
-        match bucket.delete(image_id.clone()).await {
-            Ok(_) => (),
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to delete image from database.\n",
-                )
-                    .into_response();
-            }
-        }
-        match image_collection
-            .delete_one(
-                doc! {
-                    "name": image_name.clone()
-                },
-                None,
-            )
-            .await
-        {
-            Ok(_) => (),
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to delete image document from database.\n",
-                )
-                    .into_response();
-            }
-        }
+    let deleted_old = existing_image.is_some();
+    // Replacing an image's data keeps its tags
+    let tags = existing_image
+        .as_ref()
+        .and_then(|existing| existing.get_array("tags").ok().cloned());
+    if let Some(existing_image) = existing_image {
+        remove_image(db, &image_collection, &image_name, &existing_image).await?;
     }
 
-    // Now, write the image to GridFS and get the ID of the new upload
-    let bucket = db.gridfs_bucket(None);
-    let mut upload_stream = bucket.open_upload_stream(image_name.clone(), None);
-    let upload_result = upload_stream.write_all(&bytes).await;
-    match upload_result {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to upload image to database.\n",
-            )
-                .into_response();
-        }
-    }
-
-    let image_id = upload_stream.id();
-    let images = db.collection(collection_name);
-    let doc = doc! {
+    // Now, write the image to GridFS and record it in the collection
+    let image_id = upload_file(db, &image_name, &bytes).await?;
+    let mut doc = doc! {
         "name": image_name.clone(),
         "image": image_id,
         "mime_type": format.to_mime_type(),
+        "content_sha256": web_routines::content_hash(&bytes),
     };
-
-    // Now that we have a handle to the uploaded ID and created a document, close out the
-    // upload to latch it.
-    match upload_stream.close().await {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to close upload stream for image.\n",
-            )
-                .into_response();
-        }
-    }
-
-    match images.insert_one(doc, None).await {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to insert image into database.\n",
-            )
-                .into_response();
-        }
+    if let Some(tags) = tags {
+        doc.insert("tags", tags);
     }
+    image_collection
+        .insert_one(doc, None)
+        .traced("insert_one", collection_name)
+        .await
+        .map_err(db_error("Failed to insert image into database"))?;
 
-    match deleted_old {
-        true => (
-            StatusCode::OK,
-            format!("Image {} updated.\n", image_name.clone()),
-        )
-            .into_response(),
+    Ok(match deleted_old {
+        true => (StatusCode::OK, format!("Image {} updated.\n", image_name)).into_response(),
         false => (
             StatusCode::CREATED,
-            format!("Image added with name {}.", image_name.clone()),
+            format!("Image added with name {}.", image_name),
         )
             .into_response(),
-    }
+    })
 }
 
 pub async fn delete_image_from_collection(
     State(app_state): AppState,
     Path(image_name): Path<String>,
     collection_name: &str,
-) -> Response {
+) -> ApiResult {
     let app = &mut app_state.write().await;
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
+    let db = app.db()?;
     let images: Collection<Document> = db.collection(collection_name);
-    let existing_image = match images
-        .find_one(
-            doc! {
-                "name": image_name.clone()
-            },
-            None,
-        )
+    let existing_image = images
+        .find_one(doc! { "name": image_name.clone() }, None)
+        .traced("find_one", collection_name)
         .await
-    {
-        Ok(d) => d,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query image database.\n",
-            )
-                .into_response();
-        }
-    };
+        .map_err(db_error("Failed to query image database"))?
+        .ok_or_else(|| Error::not_found(format!("Image {}", image_name)))?;
 
-    if existing_image.is_none() {
-        return (
-            StatusCode::NOT_FOUND,
-            format!("Image {} not found.\n", image_name),
-        )
-            .into_response();
-    }
+    remove_image(db, &images, &image_name, &existing_image).await?;
 
-    let existing_image = existing_image.unwrap();
-    let image_id = existing_image.get("image").unwrap();
-    let bucket = db.gridfs_bucket(None);
-    match bucket.delete(image_id.clone()).await {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to delete image from database.\n",
-            )
-                .into_response();
-        }
-    }
-    match images
-        .delete_one(
-            doc! {
-                "name": image_name.clone()
-            },
-            None,
-        )
-        .await
-    {
-        Ok(_) => (),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to delete image document from database.\n",
-            )
-                .into_response();
-        }
-    }
+    Ok((StatusCode::OK, format!("Image {} deleted.\n", image_name)).into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PyramidQuery {
+    /// For multi-page formats (TIFF), the zero-based page from which to build the pyramid
+    page: Option<u32>,
+
+    /// The kind of pyramid to build. Defaults to Gaussian
+    pyramid_type: Option<ipr::PyramidType>,
+
+    /// The scale of each level relative to the one before it. Defaults to 0.5
+    scale_factor: Option<f32>,
+
+    /// The filter to resample levels with. Defaults to the pyramid type's own
+    filter: Option<ipr::ResampleFilter>,
+
+    /// Don't store levels whose longest side is smaller than this. They're built on request
+    min_dimension: Option<u32>,
+
+    /// The most levels to store, counting the full-size image. The rest are built on request
+    max_levels: Option<u32>,
 
-    (StatusCode::OK, format!("Image {} deleted.\n", image_name)).into_response()
+    /// Codec to compress tiles with. Defaults to the `tile_compression` tunable
+    compression: Option<Compression>,
+
+    /// Format to encode tiles in: `auto`, or a format's extension or MIME type. Defaults to the
+    /// format of the uploaded image
+    tile_format: Option<String>,
+
+    /// Whether to rotate and flip the image upright per its EXIF orientation. Defaults to true
+    auto_orient: Option<bool>,
+
+    /// Comma-separated tags to give the pyramid
+    tags: Option<String>,
+}
+
+/// Check a requested tile format, returning it as stored in [`Pyramid::tile_format`]
+fn parse_tile_format(tile_format: &str) -> Result<String, Error> {
+    if tile_format.eq_ignore_ascii_case(documents::AUTO_TILE_FORMAT) {
+        return Ok(documents::AUTO_TILE_FORMAT.to_string());
+    }
+    ImageFormat::from_extension(tile_format)
+        .or_else(|| ImageFormat::from_mime_type(tile_format))
+        .filter(|f| f.writing_enabled())
+        .map(|f| f.to_mime_type().to_string())
+        .ok_or_else(|| {
+            Error::validation(format!(
+                "Tile format must be auto, or an image format we can write, not {}",
+                tile_format
+            ))
+        })
 }
 
 #[utoipa::path(
@@ -1121,163 +1926,132 @@ pub async fn delete_image_from_collection(
     request_body(
         content = Bytes,
     ),
+    params(
+        ("page" = Option<u32>, Query, description = "For multi-page TIFF sources, the zero-based page to use"),
+        ("pyramid_type" = Option<String>, Query, description = "One of lowpass, gaussian (default), or laplacian"),
+        ("scale_factor" = Option<f32>, Query, description = "Scale of each level relative to the previous one, in (0.0, 1.0). Defaults to 0.5"),
+        ("filter" = Option<ipr::ResampleFilter>, Query, description = "Filter to resample levels with. Defaults to gaussian for Gaussian pyramids, box for lowpass pyramids, and triangle for Laplacian pyramids. Laplacian pyramids only use it to fit levels to their dimensions"),
+        ("min_dimension" = Option<u32>, Query, description = "Skip levels whose longest side is smaller than this. Skipped levels are generated when first requested from /level/{name}. Not allowed for Laplacian pyramids"),
+        ("max_levels" = Option<u32>, Query, description = "Store at most this many levels, counting the full-size image. Skipped levels are generated when first requested from /level/{name}. Not allowed for Laplacian pyramids"),
+        ("compression" = Option<String>, Query, description = "Codec to compress tiles with: br, gzip, or zstd. Defaults to the server's tile_compression setting"),
+        ("tile_format" = Option<String>, Query, description = "Format to encode tiles in, e.g. png, jpeg or webp, or auto for PNG where tiles have transparency and JPEG elsewhere. Tiles that would lose transparency in the given format are PNG instead. Defaults to the uploaded image's format"),
+        ("auto_orient" = Option<bool>, Query, description = "Whether to rotate and flip the image upright per its EXIF orientation before building the pyramid. Defaults to true"),
+        ("tags" = Option<String>, Query, description = "Comma-separated tags to give the pyramid. Ignored if the pyramid already exists"),
+    ),
     responses(
-        (status = StatusCode::CREATED, description = "Added the image with the returned ID", body = Json),
+        (status = StatusCode::OK, description = "The same image was already built into a pyramid with the same parameters, which is returned instead of a new one", body = Pyramid),
+        (status = StatusCode::CREATED, description = "Added the image pyramid. Its tiles are generated in the background", body = Pyramid),
         (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
         (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type.", body = ()),
-        (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ())
+        (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ()),
+        (status = StatusCode::PAYLOAD_TOO_LARGE, description = "Image data is larger than the upload size limit.", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Image has more pixels than allowed, or can't be decoded.", body = ())
     )
 )]
-pub async fn post_pyramid(State(app_state): AppState, request: Request) -> Response {
-    let content_disposition_hdr = request.headers().get("Content-Disposition");
-    let image_name: String = if content_disposition_hdr.is_some() {
-        let content_disposition = content_disposition_hdr.unwrap().to_str().unwrap();
-        dbg!(&content_disposition);
-        let parts: Vec<&str> = content_disposition.split(';').map(|p| p.trim()).collect();
-        let name_part = parts.iter().find(|&p| p.starts_with("filename"));
-        if name_part.is_none() {
-            return (
-                StatusCode::BAD_REQUEST,
-                "Unable to handle request. Please pass an image body and specify content type.\n",
-            )
-                .into_response();
+pub async fn post_pyramid(
+    State(app_state): AppState,
+    Query(params): Query<PyramidQuery>,
+    request: Request,
+) -> ApiResult {
+    let image_name = match upload_name(&request)? {
+        Some(name) => name,
+        None => {
+            let app = &mut app_state.write().await;
+            let new_name = format!("image_{}", app.image_counter);
+            app.image_counter += 1;
+            new_name
         }
-        let name_part = name_part.unwrap();
-        let name_parts: Vec<&str> = name_part.split('=').collect();
-        let name = name_parts.get(1).unwrap();
-        name.to_string()
-    } else {
-        let app = &mut app_state.write().await;
-        let new_name = format!("image_{}", app.image_counter);
-        app.image_counter += 1;
-        new_name
     };
     debug_print!("Attempting to add new image with name {}", image_name);
 
-    let content_type_hdr = request.headers().get("Content-Type");
-    if content_type_hdr.is_none() {
-        return (
-            StatusCode::BAD_REQUEST,
-            "Unable to handle request. Please pass an image body and specify a Content-Type.\n",
-        )
-            .into_response();
+    let format = upload_format(&request)?;
+    let request_id = request_id::of(request.headers());
+    let tags = upload_tags(params.tags.as_deref())?;
+    let tile_format = params
+        .tile_format
+        .as_deref()
+        .map(parse_tile_format)
+        .transpose()?;
+    // Tiles are cut later, by a job that plans them from the stored pyramid, so only how the levels
+    // are built and how the tiles will be compressed are settled here
+    let tunables = app_state.read().await.tunables.load();
+    let compression = params.compression.unwrap_or(tunables.tile_compression);
+    let mut builder = ipr::PyramidBuilder::new()
+        .filter(params.filter)
+        .min_dimension(params.min_dimension)
+        .max_levels(params.max_levels)
+        .compression(compression)
+        .compression_level(Some(tunables.compression_level(compression)));
+    if let Some(pyramid_type) = params.pyramid_type {
+        builder = builder.pyramid_type(pyramid_type);
+    }
+    if let Some(scale_factor) = params.scale_factor {
+        builder = builder.scale_factor(scale_factor);
     }
+    let plan = builder.build()?;
+    let pyramid_params = *plan.params();
+    let page = params.page.unwrap_or(0);
 
-    let given_mime_type = content_type_hdr.unwrap().to_str().unwrap();
-    let format: ImageFormat = match ImageFormat::from_mime_type(given_mime_type) {
-        Some(fmt) => fmt,
-        None => {
-            return (
-                StatusCode::NOT_ACCEPTABLE,
-                format!(
-                    "The given MIME Type \"{}\" is not supported",
-                    given_mime_type
-                ),
-            )
-                .into_response()
-        }
-    };
-    debug_print!("Detected MIME Type: \"{}\"", given_mime_type);
+    let bytes = read_body(request, &app_state).await?;
+    check_upload_dimensions(&bytes, format, params.page, &app_state).await?;
+    let (exif_orientation, reorient) =
+        upload_orientation(&bytes, format, params.auto_orient.unwrap_or(true));
 
-    let bytes = match Bytes::from_request(request, &app_state).await {
-        Ok(b) => b.to_vec(),
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read image data from request body.\n",
-            )
-                .into_response();
-        }
+    // The same image, built into a pyramid the same way, would only be a copy of one we have
+    let content_sha256 = web_routines::content_hash(&bytes);
+    let existing = {
+        let app = &app_state.read().await;
+        web_routines::find_pyramid_by_content(app.db()?, &content_sha256, |p| {
+            p.page == page
+                && p.reoriented == reorient
+                && p.pyramid_type == pyramid_params.pyramid_type
+                && p.scale_factor == pyramid_params.scale_factor
+                && p.params().resample_filter() == pyramid_params.resample_filter()
+                && p.min_dimension == pyramid_params.min_dimension
+                && p.max_levels == pyramid_params.max_levels
+                && p.compression == compression
+                && p.tile_format == tile_format
+        })
+        .await?
     };
-    debug_print!("Extracted image data with byte length: {}", bytes.len());
+    if let Some(existing) = existing {
+        debug_print!("Image matches pyramid {}", existing.uuid);
+        return json_response(StatusCode::OK, &existing);
+    }
 
-    // Decode image using provided information
-    let image = match image::load_from_memory_with_format(&bytes, format) {
-        Ok(img) => img,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to load image from memory.\n",
-            )
-                .into_response();
-        }
+    // Decode image using provided information. For multi-page sources (TIFF) the user can pick
+    // which page to build the pyramid from
+    let image = decode_upload(&bytes, format, params.page)?;
+    let image = match exif_orientation.filter(|_| reorient) {
+        Some(orientation) => ipr::apply_exif_orientation(image, orientation),
+        None => image,
     };
 
-    // Generate image pyramid
-    let ipr = ipr::IprImage(&image);
-
     // For now we assume this is fast enough to do as part of the POST handler. Regardless, it is
     // much faster than tiling/compressing, so it should be done as a separate phase so that the
     // steps could be split into separate services, and scaled independently.
-    let pyramid = match ipr.generate_image_pyramid() {
-        Ok(p) => p,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to generate image pyramid.\n",
-            )
-                .into_response();
-        }
-    };
+    let (width, height) = (image.width(), image.height());
+    let pyramid = tokio::task::spawn_blocking(move || {
+        let progress = |level: usize, total: usize| {
+            tracing::debug!("Generated pyramid level {} of {}", level + 1, total);
+        };
+        plan.generate(&image, Some(&progress))
+    })
+    .await
+    .map_err(|_| Error::internal("Pyramid generation task panicked"))??
+    .into_levels();
 
     let app = &mut app_state.write().await;
+    let db = app.db()?;
 
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
-
-    // Write image pyramid levels to gridFS and aggregate the IDs
-    let bucket = db.gridfs_bucket(None);
+    // Write image pyramid levels to gridFS and aggregate the IDs, and checksums
     let mut image_ids = Vec::new();
+    let mut image_sha256 = Vec::new();
     for (i, img) in pyramid.iter().enumerate() {
-        let mut data = Vec::new();
-        let mut cursor = Cursor::new(&mut data);
-        match img.write_to(&mut cursor, format) {
-            Ok(_) => (),
-            Err(_) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to process image pyramid.\n",
-                )
-                    .into_response();
-            }
-        }
-
-        let mut upload_stream = bucket.open_upload_stream(format!("pyramid_{}", i), None);
-        match upload_stream.write_all(&data).await {
-            Ok(_) => (),
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to upload image to database.\n",
-                )
-                    .into_response();
-            }
-        }
-
-        let image_id = upload_stream.id();
-        image_ids.push(image_id.clone());
-
-        match upload_stream.close().await {
-            Ok(_) => (),
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to close upload stream for image.\n",
-                )
-                    .into_response();
-            }
-        }
+        let data = ipr::encode_image(img, format)?;
+        let image_id = upload_file(db, &format!("pyramid_{}", i), &data).await?;
+        image_ids.push(object_id(&image_id)?);
+        image_sha256.push(web_routines::content_hash(&data));
     }
 
     let pyramid_uuid = uuid::Uuid::new_v4();
@@ -1287,281 +2061,1865 @@ pub async fn post_pyramid(State(app_state): AppState, request: Request) -> Respo
     let mut image_names = Vec::new();
     let mut image_doc_ids = Vec::new();
     for (i, image_id) in image_ids.iter().enumerate() {
-        let image_name = format!("{}_L{}", pyramid_uuid, i);
+        let image_name = documents::level_name(&pyramid_uuid.to_string(), i);
         image_names.push(image_name.clone());
-        let doc = doc! {
+        let mut doc = doc! {
             "name": image_name.clone(),
             "image": image_id,
             "mime_type": format.to_mime_type(),
         };
+        doc.extend(web_routines::level_record(
+            &pyramid_uuid.to_string(),
+            i,
+            pyramid[i].width(),
+            pyramid[i].height(),
+            &image_sha256[i],
+        ));
+        doc.extend(orientation_record(exif_orientation, reorient));
+
+        let result = db
+            .collection("images")
+            .insert_one(doc, None)
+            .traced("insert_one", "images")
+            .await
+            .map_err(db_error("Failed to insert image into database"))?;
+        image_doc_ids.push(object_id(&result.inserted_id)?);
+    }
+
+    // Figure out the image URL for each of the image_name values
+    let image_urls = image_names
+        .iter()
+        .map(|name| format!("/api/v1/image/{}", name))
+        .collect::<Vec<String>>();
+
+    // Record each level's dimensions, so clients don't have to re-derive them from the rounding
+    // rule to do tile math
+    let level_dims = pyramid
+        .iter()
+        .map(|l| LevelDims {
+            width: l.width(),
+            height: l.height(),
+        })
+        .collect::<Vec<LevelDims>>();
+
+    // Levels past those stored are built on request, by GET /api/v1/level/{uuid}_L{n}
+    let total_levels =
+        ipr::pyramid_level_dims(width, height, pyramid_params.scale_factor).len() as u32;
+
+    // Now we generate the actual doc of the pyramid. Tiles come later
+    let pyramid_doc = Pyramid {
+        id: None,
+        uuid: format!("{}", pyramid_uuid),
+        url: format!("/api/v1/pyramid/{}", pyramid_uuid),
+        original_filename: image_name,
+        content_sha256: Some(content_sha256),
+        image_files: image_ids,
+        image_sha256,
+        image_names,
+        image_docs: image_doc_ids,
+        image_urls,
+        mime_type: format.to_mime_type().to_string(),
+        compression,
+        tile_format,
+        page,
+        reoriented: reorient,
+        pyramid_type: pyramid_params.pyramid_type,
+        scale_factor: pyramid_params.scale_factor,
+        filter: Some(pyramid_params.resample_filter()),
+        dimension_rounding: ipr::PYRAMID_DIMENSION_ROUNDING.to_string(),
+        level_dims,
+        min_dimension: pyramid_params.min_dimension,
+        max_levels: pyramid_params.max_levels,
+        total_levels: Some(total_levels),
+        tiles: PyramidTiles::Status(TilingStatus::Todo),
+        tile_sets: Vec::new(),
+        tags,
+    };
+
+    // Build the response before the doc is moved into the database
+    let response = json_response(StatusCode::CREATED, &pyramid_doc)?;
+
+    db.collection::<Pyramid>("pyramids")
+        .insert_one(pyramid_doc, None)
+        .traced("insert_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to insert pyramid into database"))?;
+
+    // We have an image pyramid document, now queue up a job for a worker to take the pyramid
+    // and:
+    //  0. Update the pyramid doc such that "tiles" field is now "processing"
+    //  1. Break each image into tiles of 512x512 pixels
+    //  2. Encode each tile, and compress it with the codec chosen above
+    //  3. Update the pyramid doc such that "tiles" field is now "done", when ALL tiles are done
+    //  4. Update the pyramid doc such that "tiles" field is now "failed" if it keeps failing
+    jobs::enqueue_tiling(db, &pyramid_uuid.to_string(), request_id).await?;
+    app.job_wakeup.notify_one();
+
+    // Everything is set, now let's let the user know the pyramid is created!
+    Ok(response)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/pyramid/import",
+    request_body(
+        content = Bytes,
+        content_type = "application/zip",
+        description = "A zip archive laid out like the output of the library's make_pyramid example: manifest.json, levels/ and tiles/",
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Imported the image pyramid and its tiles, which are ready to serve", body = Pyramid),
+        (status = StatusCode::NOT_ACCEPTABLE, description = "Body isn't a zip archive (application/zip).", body = ()),
+        (status = StatusCode::PAYLOAD_TOO_LARGE, description = "Archive, or a file in it, is larger than the upload size limit, or the archive inflates to more than a few times that in all.", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Archive doesn't match its manifest: a file is missing, can't be read, or isn't the size the manifest says.", body = ())
+    )
+)]
+pub async fn post_pyramid_import(State(app_state): AppState, request: Request) -> ApiResult {
+    let content_type = request
+        .headers()
+        .get("Content-Type")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    if content_type != "application/zip" {
+        return Err(Error::unsupported_format(format!(
+            "Pyramids are imported from zip archives (application/zip), not \"{}\"",
+            content_type
+        ))
+        .into());
+    }
+    let bytes = read_body(request, &app_state).await?;
+    let limits = app_state.read().await.upload_limits;
+    let archive =
+        tokio::task::spawn_blocking(move || pyramid_import::PyramidArchive::read(&bytes, &limits))
+            .await
+            .map_err(|_| Error::internal("Pyramid import task panicked"))??;
+
+    let app = app_state.read().await;
+    let db = app.db()?;
+    let manifest = archive.manifest;
+    let pyramid_uuid = uuid::Uuid::new_v4().to_string();
+
+    // Levels and tiles are stored just as if we'd built them, but renamed after the new UUID
+    let mut image_files = Vec::new();
+    let mut image_sha256 = Vec::new();
+    let mut image_names = Vec::new();
+    let mut image_docs = Vec::new();
+    let PyramidTiles::Levels(mut levels) = manifest.tiles else {
+        return Err(Error::internal("Imported pyramid has no tiles").into());
+    };
+    for (i, data) in archive.levels.iter().enumerate() {
+        let image_name = documents::level_name(&pyramid_uuid, i);
+        let image_id = object_id(&upload_file(db, &image_name, data).await?)?;
+        let sha256 = web_routines::content_hash(data);
+        let mut doc = doc! {
+            "name": image_name.clone(),
+            "image": image_id,
+            "mime_type": archive.level_format.to_mime_type(),
+        };
+        doc.extend(web_routines::level_record(
+            &pyramid_uuid,
+            i,
+            levels[i].width,
+            levels[i].height,
+            &sha256,
+        ));
+        let result = db
+            .collection("images")
+            .insert_one(doc, None)
+            .traced("insert_one", "images")
+            .await
+            .map_err(db_error("Failed to insert image into database"))?;
+        image_files.push(image_id);
+        image_sha256.push(sha256);
+        image_names.push(image_name);
+        image_docs.push(object_id(&result.inserted_id)?);
+    }
+
+    for (level, level_tiles) in levels.iter_mut().zip(archive.tiles) {
+        for (tile, archived) in level.tiles.iter_mut().zip(level_tiles) {
+            tile.name =
+                documents::tile_name(&pyramid_uuid, level.level as usize, tile.index as usize);
+            let (tile_id, sha256) = web_routines::upload_tile(
+                db,
+                &tile.name,
+                &archived.data,
+                archived.format,
+                manifest.compression,
+                web_routines::tile_record(
+                    &pyramid_uuid,
+                    None,
+                    level.level as usize,
+                    tile.x,
+                    tile.y,
+                ),
+            )
+            .await?;
+            tile.tile_id = tile_id;
+            tile.sha256 = Some(sha256);
+            tile.mime_type = Some(archived.format.to_mime_type().to_string());
+        }
+    }
+
+    let level_dims = levels
+        .iter()
+        .map(|l| LevelDims {
+            width: l.width,
+            height: l.height,
+        })
+        .collect::<Vec<LevelDims>>();
+    let total_levels = manifest.total_levels.unwrap_or_else(|| {
+        ipr::pyramid_level_dims(levels[0].width, levels[0].height, manifest.scale_factor).len()
+            as u32
+    });
+    let pyramid_doc = Pyramid {
+        id: None,
+        uuid: pyramid_uuid.clone(),
+        url: format!("/api/v1/pyramid/{}", pyramid_uuid),
+        original_filename: manifest.original_filename,
+        // The archive's contents aren't an upload we'd see again
+        content_sha256: None,
+        image_files,
+        image_sha256,
+        image_urls: image_names
+            .iter()
+            .map(|name| format!("/api/v1/image/{}", name))
+            .collect(),
+        image_names,
+        image_docs,
+        mime_type: archive.level_format.to_mime_type().to_string(),
+        compression: manifest.compression,
+        tile_format: manifest.tile_format,
+        page: manifest.page,
+        reoriented: manifest.reoriented,
+        pyramid_type: manifest.pyramid_type,
+        scale_factor: manifest.scale_factor,
+        filter: manifest.filter,
+        dimension_rounding: manifest.dimension_rounding,
+        level_dims,
+        min_dimension: manifest.min_dimension,
+        max_levels: manifest.max_levels,
+        total_levels: Some(total_levels),
+        tiles: PyramidTiles::Levels(levels),
+        // Only the pyramid's own tiles come with it
+        tile_sets: Vec::new(),
+        tags: manifest.tags,
+    };
+
+    let response = json_response(StatusCode::CREATED, &pyramid_doc)?;
+    db.collection::<Pyramid>("pyramids")
+        .insert_one(pyramid_doc, None)
+        .traced("insert_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to insert pyramid into database"))?;
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pyramid/{uuid}",
+    request_body(
+        content = Bytes,
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Returned the image pyramid info with of the given uuid", body = Pyramid),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+    )
+)]
+pub async fn get_pyramid(State(app_state): AppState, Path(uuid): Path<String>) -> ApiResult {
+    let app = &mut app_state.read().await;
+    let db = app.db()?;
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = pyramids
+        .find_one(doc! { "uuid": uuid.clone() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query image database"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+
+    json_response(StatusCode::OK, &pyramid)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pyramid/{uuid}/manifest",
+    responses(
+        (status = StatusCode::OK, description = "Returned the levels of the pyramid with the given uuid, with their tile grids and the URL of each tile (once tiled)", body = PyramidManifest),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+    )
+)]
+pub async fn get_pyramid_manifest(
+    State(app_state): AppState,
+    Path(uuid): Path<String>,
+) -> ApiResult {
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = pyramids
+        .find_one(doc! { "uuid": uuid.clone() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query image database"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+
+    json_response(StatusCode::OK, &PyramidManifest::from(&pyramid))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pyramid/{uuid}/verify",
+    responses(
+        (status = StatusCode::OK, description = "Read back every stored level and tile of the pyramid with the given uuid, and compared each with the checksum recorded when it was written. Any that didn't match (or couldn't be read) are listed", body = web_routines::VerifyReport),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+    )
+)]
+pub async fn get_pyramid_verify(State(app_state): AppState, Path(uuid): Path<String>) -> ApiResult {
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = pyramids
+        .find_one(doc! { "uuid": uuid.clone() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query image database"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+
+    // Reading a pyramid back is much like tiling one, so read as many files at once as we'd write
+    let concurrency = app.tunables.load().tile_upload_concurrency;
+    let report = web_routines::verify_pyramid(db, &pyramid, concurrency).await?;
+    json_response(StatusCode::OK, &report)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RetileQuery {
+    /// Codec to compress the new tiles with. Defaults to the one the pyramid already uses
+    compression: Option<Compression>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/pyramid/{uuid}/retile",
+    params(
+        ("compression" = Option<String>, Query, description = "Codec to compress the new tiles with: br, gzip, or zstd. Defaults to the pyramid's current codec"),
+    ),
+    responses(
+        (status = StatusCode::ACCEPTED, description = "Removed any partial tiles, and queued the pyramid to be tiled again", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+        (status = StatusCode::CONFLICT, description = "The pyramid is already tiled, or is queued to be tiled or being tiled right now", body = ()),
+    )
+)]
+pub async fn post_pyramid_retile(
+    State(app_state): AppState,
+    Path(uuid): Path<String>,
+    Query(params): Query<RetileQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let app = &mut app_state.write().await;
+    let db = app.db()?;
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = pyramids
+        .find_one(doc! { "uuid": uuid.as_str() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query pyramid database"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+
+    // Untiled pyramids can be retried as long as no job is queued for them or being worked on.
+    // A pyramid can be left "processing" with no job working on it, e.g. if its job's worker died
+    // on its last attempt
+    if let PyramidTiles::Levels(_) = pyramid.tiles {
+        return Err(Error::conflict(format!("A tile set for pyramid {}", uuid)).into());
+    }
+    if jobs::active_job(db, &uuid, None).await?.is_some() {
+        return Err(Error::conflict(format!("A tiling job for pyramid {}", uuid)).into());
+    }
+
+    let _deleted = web_routines::delete_pyramid_tiles(db, &uuid).await?;
+    debug_print!("Deleted {} partial tiles of pyramid {}", _deleted, uuid);
+    let compression = params.compression.unwrap_or(pyramid.compression);
+    pyramids
+        .update_one(
+            doc! { "uuid": uuid.as_str() },
+            doc! { "$set": {
+                "tiles": TilingStatus::Todo.as_str(),
+                "compression": compression.content_encoding(),
+            } },
+            None,
+        )
+        .traced("update_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to update pyramid"))?;
+
+    jobs::enqueue_tiling(db, &uuid, request_id::of(&headers)).await?;
+    app.job_wakeup.notify_one();
+
+    Ok((
+        StatusCode::ACCEPTED,
+        format!("Retiling pyramid {}.\n", uuid),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TileSetQuery {
+    /// Width of the new tiles, in pixels. Defaults to the `tile_width` tunable
+    tile_width: Option<u32>,
+
+    /// Height of the new tiles, in pixels. Defaults to the `tile_height` tunable
+    tile_height: Option<u32>,
+
+    /// Format to encode the new tiles in: `auto`, or a format's extension or MIME type. Defaults
+    /// to the format the pyramid's own tiles were asked for in
+    format: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/pyramid/{uuid}/tiles",
+    params(
+        ("tile_width" = Option<u32>, Query, description = "Width of the new tiles, in pixels. Defaults to the server's tile width"),
+        ("tile_height" = Option<u32>, Query, description = "Height of the new tiles, in pixels. Defaults to the server's tile height"),
+        ("format" = Option<String>, Query, description = "Format to encode the new tiles in, e.g. png, jpeg or webp, or auto. Defaults to the format of the pyramid's own tiles"),
+    ),
+    responses(
+        (status = StatusCode::ACCEPTED, description = "Added a tile set with the given parameters to the pyramid, and queued it to be tiled. Its key tells it apart from the pyramid's other tile sets", body = TileSet),
+        (status = StatusCode::BAD_REQUEST, description = "Tile width or height is zero, or the format isn't one tiles can be written in", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+        (status = StatusCode::CONFLICT, description = "The pyramid already has a tile set with these parameters, which hasn't failed", body = ()),
+    )
+)]
+pub async fn post_pyramid_tile_set(
+    State(app_state): AppState,
+    Path(uuid): Path<String>,
+    Query(params): Query<TileSetQuery>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let tunables = app.tunables.load();
+    let tile_width = params.tile_width.unwrap_or(tunables.tile_width);
+    let tile_height = params.tile_height.unwrap_or(tunables.tile_height);
+    if tile_width == 0 || tile_height == 0 {
+        return Err(Error::validation("Tile width and height must be non-zero").into());
+    }
 
-        let result = match db.collection("images").insert_one(doc, None).await {
-            Ok(r) => r,
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to insert image into database.\n",
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = pyramids
+        .find_one(doc! { "uuid": uuid.as_str() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query pyramid database"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+    let tile_format = match params.format.as_deref() {
+        Some(format) => parse_tile_format(format)?,
+        None => pyramid
+            .tile_format
+            .clone()
+            .unwrap_or_else(|| pyramid.mime_type.clone()),
+    };
+    let key = documents::tile_set_key(tile_width, tile_height, &tile_format);
+    let tile_set = TileSet {
+        key: key.clone(),
+        tile_width,
+        tile_height,
+        tile_format,
+        tiles: PyramidTiles::Status(TilingStatus::Todo),
+    };
+
+    // Sets that failed can be tried again. Any others are left as they are
+    match pyramid.tile_set(&key) {
+        Some(existing) if existing.tiles != PyramidTiles::Status(TilingStatus::Failed) => {
+            return Err(Error::conflict(format!("Tile set {} of pyramid {}", key, uuid)).into());
+        }
+        Some(_) => {
+            let _deleted = web_routines::delete_tile_set_tiles(db, &uuid, &key).await?;
+            debug_print!("Deleted {} partial tiles of tile set {}", _deleted, key);
+            pyramids
+                .update_one(
+                    doc! { "uuid": uuid.as_str(), "tile_sets.key": key.as_str() },
+                    doc! { "$set": { "tile_sets.$.tiles": TilingStatus::Todo.as_str() } },
+                    None,
+                )
+                .traced("update_one", "pyramids")
+                .await
+                .map_err(db_error("Failed to update pyramid"))?;
+        }
+        None => {
+            let tile_set_doc = mongodb::bson::to_bson(&tile_set)
+                .map_err(|_| Error::internal("Failed to serialize tile set"))?;
+            // Only add it if nobody else has in the meantime
+            let result = pyramids
+                .update_one(
+                    doc! { "uuid": uuid.as_str(), "tile_sets.key": { "$ne": key.as_str() } },
+                    doc! { "$push": { "tile_sets": tile_set_doc } },
+                    None,
                 )
-                    .into_response();
+                .traced("update_one", "pyramids")
+                .await
+                .map_err(db_error("Failed to update pyramid"))?;
+            if result.modified_count == 0 {
+                return Err(
+                    Error::conflict(format!("Tile set {} of pyramid {}", key, uuid)).into(),
+                );
+            }
+        }
+    }
+
+    jobs::enqueue_tile_set(db, &uuid, &key, request_id::of(&headers)).await?;
+    app.job_wakeup.notify_one();
+
+    json_response(StatusCode::ACCEPTED, &tile_set)
+}
+
+/// Most tiles `POST /api/v1/pyramid/{uuid}/tiles/batch` sends in one response
+pub const MAX_BATCH_TILES: usize = 256;
+
+/// One tile of a pyramid, by its level and its place in that level's grid of tiles
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct TileRef {
+    pub level: u32,
+
+    /// Column of the tile, counting from the left
+    pub x: u32,
+
+    /// Row of the tile, counting from the top
+    pub y: u32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/pyramid/{uuid}/tiles/batch",
+    request_body(
+        content = Vec<TileRef>,
+        content_type = "application/json"
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Streams the tiles as a multipart/mixed body, one part per tile, in the order they were asked for. Each part has the tile's Content-Type and Content-Length, and a Content-Location where the API serves it on its own. Tiles are sent decompressed", content_type = "multipart/mixed", body = Vec<u8>),
+        (status = StatusCode::BAD_REQUEST, description = "The body isn't a list of tiles, or lists more than 256", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available, or it has no such tile", body = ()),
+        (status = StatusCode::CONFLICT, description = "The pyramid hasn't been tiled yet", body = ()),
+    )
+)]
+pub async fn post_pyramid_tiles_batch(
+    State(app_state): AppState,
+    Path(uuid): Path<String>,
+    body: Bytes,
+) -> ApiResult {
+    let requested: Vec<TileRef> = serde_json::from_slice(&body)
+        .map_err(|_e| Error::validation(format!("Body must be a list of tiles: {}", _e)))?;
+    if requested.len() > MAX_BATCH_TILES {
+        return Err(Error::validation(format!(
+            "At most {} tiles can be fetched at once, not {}",
+            MAX_BATCH_TILES,
+            requested.len()
+        ))
+        .into());
+    }
+
+    // The response outlives the request's hold on the app state, so it gets its own handle
+    let db = app_state.read().await.db()?.clone();
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = pyramids
+        .find_one(doc! { "uuid": uuid.as_str() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query pyramid database"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+    let Some(levels) = pyramid.tiles.levels() else {
+        return Err(Error::conflict(format!("Pyramid {} hasn't been tiled yet", uuid)).into());
+    };
+    let names = requested
+        .iter()
+        .map(|t| {
+            levels
+                .iter()
+                .find(|l| l.level == t.level)
+                .and_then(|l| l.tile_at(t.x, t.y))
+                .map(|tile| tile.name.clone())
+                .ok_or_else(|| {
+                    Error::not_found(format!(
+                        "Tile ({}, {}) of level {} of pyramid {}",
+                        t.x, t.y, t.level, uuid
+                    ))
+                })
+        })
+        .collect::<Result<Vec<String>, Error>>()?;
+
+    // Everything is looked up before the response starts, so a missing tile is still a 404
+    let images: Collection<Document> = db.collection("images");
+    let mut cursor = images
+        .find(doc! { "name": { "$in": names.clone() } }, None)
+        .traced("find", "images")
+        .await
+        .map_err(db_error("Failed to query image database"))?;
+    let mut image_docs = HashMap::new();
+    while let Some(image_doc) = cursor.next().await {
+        let image_doc = image_doc.map_err(db_error("Failed to read image document"))?;
+        if let Ok(name) = image_doc.get_str("name") {
+            image_docs.insert(name.to_string(), image_doc);
+        }
+    }
+    let tiles = names
+        .into_iter()
+        .map(|name| {
+            let image_doc = image_docs
+                .get(&name)
+                .ok_or_else(|| Error::not_found(format!("Image {}", name)))?;
+            let (Some(image_id), Ok(mime_type)) =
+                (image_doc.get("image"), image_doc.get_str("mime_type"))
+            else {
+                return Err(Error::database(format!(
+                    "Image {} is missing its data or MIME type",
+                    name
+                )));
+            };
+            Ok((
+                name.clone(),
+                image_id.clone(),
+                mime_type.to_string(),
+                stored_compression(image_doc)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // The tiles are sent as they're read, rather than all held in memory at once
+    let boundary = uuid::Uuid::new_v4().simple().to_string();
+    let content_type = format!("multipart/mixed; boundary={}", boundary);
+    let closing = format!("--{}--\r\n", boundary);
+    let parts = futures_util::stream::iter(tiles).then(
+        move |(name, image_id, mime_type, compression)| {
+            let (db, boundary) = (db.clone(), boundary.clone());
+            async move {
+                let mut data = download_file(&db, &image_id).await?;
+                if let Some(compression) = compression {
+                    data = compression.decompress(&data)?;
+                }
+                let mut part = format!(
+                    "--{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nContent-Location: /api/v1/image/{}\r\n\r\n",
+                    boundary,
+                    mime_type,
+                    data.len(),
+                    name
+                )
+                .into_bytes();
+                part.extend(data);
+                part.extend_from_slice(b"\r\n");
+                Ok::<_, Error>(part)
+            }
+        },
+    );
+    let body = parts.chain(futures_util::stream::once(async move {
+        Ok(closing.into_bytes())
+    }));
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from_stream(body))
+        .unwrap())
+}
+
+/// Tiling progress, as `GET /api/v1/pyramids` filters by it
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PyramidStatusFilter {
+    Todo,
+    Processing,
+    Failed,
+
+    /// Tiled
+    Done,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PyramidListQuery {
+    /// Most pyramids to return. Defaults to [`PyramidListQuery::DEFAULT_LIMIT`], and is capped at
+    /// [`PyramidListQuery::MAX_LIMIT`]
+    limit: Option<u64>,
+
+    /// Number of matching pyramids to skip
+    offset: Option<u64>,
+
+    status: Option<PyramidStatusFilter>,
+
+    /// Only list pyramids built from an image of this name
+    original_filename: Option<String>,
+
+    /// Only list pyramids with this tag
+    tag: Option<String>,
+
+    /// Only list pyramids built from images whose names contain this, ignoring case
+    q: Option<String>,
+}
+
+impl PyramidListQuery {
+    const DEFAULT_LIMIT: u64 = 50;
+    const MAX_LIMIT: u64 = 500;
+
+    /// The Mongo filter selecting the pyramids this query asks for
+    fn filter(&self) -> Document {
+        let mut filter = Document::new();
+        if let Some(status) = self.status {
+            let tiles = match status {
+                PyramidStatusFilter::Todo => Bson::from(TilingStatus::Todo.as_str()),
+                PyramidStatusFilter::Processing => Bson::from(TilingStatus::Processing.as_str()),
+                PyramidStatusFilter::Failed => Bson::from(TilingStatus::Failed.as_str()),
+                // Tiled pyramids hold their levels' tiles where the others hold a status
+                PyramidStatusFilter::Done => Bson::from(doc! { "$type": "array" }),
+            };
+            filter.insert("tiles", tiles);
+        }
+        let mut original_filename = Document::new();
+        if let Some(name) = &self.original_filename {
+            original_filename.insert("$eq", name.as_str());
+        }
+        if let Some(q) = &self.q {
+            original_filename.extend(web_routines::contains_ignoring_case(q));
+        }
+        if !original_filename.is_empty() {
+            filter.insert("original_filename", original_filename);
+        }
+        if let Some(tag) = &self.tag {
+            filter.insert("tags", tag.as_str());
+        }
+        filter
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pyramids",
+    params(
+        ("limit" = Option<u64>, Query, description = "Most pyramids to return. Defaults to 50, and can be at most 500"),
+        ("offset" = Option<u64>, Query, description = "Number of matching pyramids to skip. Defaults to 0"),
+        ("status" = Option<String>, Query, description = "Only list pyramids whose tiling is todo, processing, failed, or done"),
+        ("original_filename" = Option<String>, Query, description = "Only list pyramids built from the image of this name"),
+        ("tag" = Option<String>, Query, description = "Only list pyramids with this tag"),
+        ("q" = Option<String>, Query, description = "Only list pyramids built from images whose names contain this, ignoring case"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Returned a page of image pyramids, oldest first", body = PyramidPage),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid limit, offset, or status", body = ()),
+    )
+)]
+pub async fn get_pyramids(
+    State(app_state): AppState,
+    Query(params): Query<PyramidListQuery>,
+) -> ApiResult {
+    let limit = params
+        .limit
+        .unwrap_or(PyramidListQuery::DEFAULT_LIMIT)
+        .clamp(1, PyramidListQuery::MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let filter = params.filter();
+
+    let app = &mut app_state.read().await;
+    let db = app.db()?;
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let total = pyramids
+        .count_documents(filter.clone(), None)
+        .traced("count_documents", "pyramids")
+        .await
+        .map_err(db_error("Failed to count pyramids"))?;
+
+    // Sort by ID, so pages don't overlap or skip anything as pyramids are added
+    let options = FindOptions::builder()
+        .sort(doc! { "_id": 1 })
+        .skip(offset)
+        .limit(limit as i64)
+        .build();
+    let mut found = pyramids
+        .find(filter, options)
+        .traced("find", "pyramids")
+        .await
+        .map_err(db_error("Failed to query pyramid database"))?;
+
+    let mut pyramid_docs = Vec::new();
+    while let Some(doc) = found.next().await {
+        pyramid_docs.push(doc.map_err(db_error("Failed to read pyramid document"))?);
+    }
+
+    let next_offset = offset + pyramid_docs.len() as u64;
+    json_response(
+        StatusCode::OK,
+        &PyramidPage {
+            pyramids: pyramid_docs,
+            total,
+            next_offset: (next_offset < total).then_some(next_offset),
+        },
+    )
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/pyramid/{uuid}/tags",
+    request_body(
+        content = Vec<String>,
+        content_type = "application/json"
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Replaced the pyramid's tags with those given, returning the updated pyramid. Tags are trimmed, without empty tags or repeats", body = Pyramid),
+        (status = StatusCode::BAD_REQUEST, description = "The body isn't a list of tags, or has too many, or some are too long or have commas", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+    )
+)]
+pub async fn put_pyramid_tags(
+    State(app_state): AppState,
+    Path(uuid): Path<String>,
+    body: Bytes,
+) -> ApiResult {
+    let tags = body_tags(&body)?;
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+    let pyramid = db
+        .collection::<Pyramid>("pyramids")
+        .find_one_and_update(
+            doc! { "uuid": uuid.as_str() },
+            doc! { "$set": { "tags": tags.clone() } },
+            options,
+        )
+        .traced("find_one_and_update", "pyramids")
+        .await
+        .map_err(db_error("Failed to update pyramid"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", uuid)))?;
+    json_response(StatusCode::OK, &pyramid)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/image/{name}",
+    params(
+        ("quality" = Option<u8>, Query, description = "Quality to encode JPEG, WebP or AVIF at, from 1 to 100. Also taken from the X-Image-Quality header. Makes WebP lossy")
+    ),
+    request_body(
+        content = Bytes,
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Returned the image of the given name", body = Vec<u8>),
+        (status = StatusCode::PARTIAL_CONTENT, description = "Returned the byte range asked for by the Range header, as given by Content-Range. Ranges are served for images sent as stored (not re-encoded or decompressed), which say so with Accept-Ranges: bytes", body = Vec<u8>),
+        (status = StatusCode::BAD_REQUEST, description = "Quality isn't from 1 to 100", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::RANGE_NOT_SATISFIABLE, description = "The Range header starts past the end of the image", body = ()),
+    )
+)]
+pub async fn get_image(state: AppState, path: Path<String>, request: Request) -> ApiResult {
+    get_image_from_collection(state, path, request, "images").await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/image/{name}",
+    request_body(
+        content = Bytes,
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Image of the given name is updated to the provided image data", body = ()),
+        (status = StatusCode::CREATED, description = "No existing image found, so, image of the given name was added", body = ()),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
+        (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type.", body = ()),
+        (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ()),
+        (status = StatusCode::PAYLOAD_TOO_LARGE, description = "Image data is larger than the upload size limit.", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Image has more pixels than allowed, or its dimensions can't be read.", body = ()),
+    )
+)]
+pub async fn put_image(state: AppState, path: Path<String>, request: Request) -> ApiResult {
+    put_image_in_collection(state, path, request, "images").await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/image/{name}",
+    request_body(
+        content = Bytes,
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Image of the given name is updated to the provided image data", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No existing image found; could not delete.", body = ()),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
+    )
+)]
+pub async fn delete_image(state: AppState, path: Path<String>) -> ApiResult {
+    delete_image_from_collection(state, path, "images").await
+}
+
+/// Thumbnail size used when the client doesn't ask for one
+pub const THUMBNAIL_DEFAULT_SIZE: u32 = 256;
+
+/// Largest thumbnail we'll generate. Anything bigger should use the image (or a level) directly
+pub const THUMBNAIL_MAX_SIZE: u32 = 1024;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ThumbnailQuery {
+    max: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/image/{name}/thumbnail",
+    params(
+        ("max" = Option<u32>, Query, description = "Longest side of the thumbnail, in pixels. Defaults to 256, and is clamped to 1024")
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Returned a downscaled copy of the image of the given name", body = Vec<u8>),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read or downscale the image", body = ()),
+    )
+)]
+pub async fn get_image_thumbnail(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+) -> ApiResult {
+    let max = query
+        .max
+        .unwrap_or(THUMBNAIL_DEFAULT_SIZE)
+        .clamp(1, THUMBNAIL_MAX_SIZE);
+
+    let app = &app_state.read().await;
+    let db = app.db()?;
+
+    // If this image is a pyramid level, a smaller level may already be big enough, which saves us
+    // downloading and decoding the full-resolution image
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let source_name = pyramids
+        .find_one(doc! { "image_names": name.as_str() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(db_error("Failed to query pyramid database"))?
+        .and_then(|pyramid| web_routines::thumbnail_source_level(&pyramid, &name, max))
+        .unwrap_or_else(|| name.clone());
+
+    let (image, format) = fetch_image(db, "images", &source_name).await?;
+    let thumbnail = if image.width() > max || image.height() > max {
+        image.thumbnail(max, max)
+    } else {
+        image
+    };
+
+    // Photos stay JPEG so thumbnails stay small; everything else becomes PNG, which every browser
+    // can display
+    let dest_format = match format {
+        ImageFormat::Jpeg => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    };
+    let data = ipr::encode_image(&thumbnail, dest_format)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", dest_format.to_mime_type())
+        .body(Body::from(data))
+        .unwrap())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PixelQuery {
+    x: Option<u32>,
+    y: Option<u32>,
+
+    /// Pyramid level to read from, in which case `x` and `y` are in that level's pixels
+    level: Option<usize>,
+}
+
+/// The value of one pixel
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PixelValue {
+    pub x: u32,
+    pub y: u32,
+
+    /// Which channels `value` holds: `l`, `la`, `rgb`, or `rgba`
+    pub channels: &'static str,
+
+    /// One sample per channel, in the image's native range (e.g. 0 to 255 for 8-bit images)
+    pub value: Vec<f64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/image/{name}/pixel",
+    params(
+        ("x" = u32, Query, description = "Column of the pixel, from the left"),
+        ("y" = u32, Query, description = "Row of the pixel, from the top"),
+        ("level" = Option<usize>, Query, description = "Read from this level of the pyramid the image belongs to ({name} may be the pyramid's UUID or any of its levels). x and y are then in that level's pixels"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Returned the value of the pixel", body = PixelValue),
+        (status = StatusCode::BAD_REQUEST, description = "x or y is missing, or outside the image", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image, or pyramid level, available", body = ()),
+    )
+)]
+pub async fn get_image_pixel(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<PixelQuery>,
+) -> ApiResult {
+    let (Some(x), Some(y)) = (params.x, params.y) else {
+        return Err(Error::validation("Both x and y are required").into());
+    };
+
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let (image, _) = match params.level {
+        Some(level) => {
+            let uuid = web_routines::parse_level_name(&name).map_or(name.as_str(), |(u, _)| u);
+            let level_name = documents::level_name(uuid, level);
+            let collection = web_routines::resolve_pyramid_level(db, &level_name).await?;
+            fetch_image(db, collection, &level_name).await?
+        }
+        None => fetch_image(db, "images", &name).await?,
+    };
+
+    let value = ipr::pixel_samples(&image, x, y)?;
+    let channels = match value.len() {
+        1 => "l",
+        2 => "la",
+        3 => "rgb",
+        _ => "rgba",
+    };
+    json_response(
+        StatusCode::OK,
+        &PixelValue {
+            x,
+            y,
+            channels,
+            value,
+        },
+    )
+}
+
+/// What `GET /api/v1/image/{name}/info` says about an image
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageInfo {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub metadata: ipr::ImageMetadata,
+
+    pub mime_type: String,
+
+    /// Size of the encoded image, in bytes
+    pub size: u64,
+
+    /// When the image was uploaded, as an RFC 3339 timestamp
+    pub uploaded: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/image/{name}/info",
+    responses(
+        (status = StatusCode::OK, description = "Returned the dimensions, channels, MIME type, size, upload time, and EXIF orientation (if any) of the image of the given name, without its pixels", body = ImageInfo),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read the image", body = ()),
+    )
+)]
+pub async fn get_image_info(State(app_state): AppState, Path(name): Path<String>) -> ApiResult {
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let (data, format, image_id) = fetch_image_data(db, "images", &name).await?;
+    let uploaded = upload_date(db, &image_id)
+        .await?
+        .and_then(|date| date.try_to_rfc3339_string().ok());
+
+    json_response(
+        StatusCode::OK,
+        &ImageInfo {
+            name,
+            metadata: ipr::read_metadata(&data, format)?,
+            mime_type: format.to_mime_type().to_string(),
+            size: data.len() as u64,
+            uploaded,
+        },
+    )
+}
+
+/// Read a list of tags from a request body. See [`documents::normalize_tags`]
+fn body_tags(body: &[u8]) -> Result<Vec<String>, Error> {
+    let tags: Vec<String> = serde_json::from_slice(body)
+        .map_err(|_e| Error::validation(format!("Body must be a list of tags: {}", _e)))?;
+    documents::normalize_tags(&tags)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/image/{name}/tags",
+    request_body(
+        content = Vec<String>,
+        content_type = "application/json"
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Replaced the image's tags with those given, returning them as they were stored: trimmed, without empty tags or repeats", body = Vec<String>),
+        (status = StatusCode::BAD_REQUEST, description = "The body isn't a list of tags, or has too many, or some are too long or have commas", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+    )
+)]
+pub async fn put_image_tags(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> ApiResult {
+    let tags = body_tags(&body)?;
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let result = db
+        .collection::<Document>("images")
+        .update_one(
+            doc! { "name": name.as_str() },
+            doc! { "$set": { "tags": tags.clone() } },
+            None,
+        )
+        .traced("update_one", "images")
+        .await
+        .map_err(db_error("Failed to update image"))?;
+    if result.matched_count == 0 {
+        return Err(Error::not_found(format!("Image {}", name)).into());
+    }
+    json_response(StatusCode::OK, &tags)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/image/{name}/stats",
+    responses(
+        (status = StatusCode::OK, description = "Returned per-channel histogram, mean, standard deviation, min and max of the image of the given name", body = Json),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read the image", body = ()),
+    )
+)]
+pub async fn get_image_stats(State(app_state): AppState, Path(name): Path<String>) -> ApiResult {
+    let (image, _) = {
+        let app = &app_state.read().await;
+        fetch_image(app.db()?, "images", &name).await?
+    };
+
+    let stats = tokio::task::spawn_blocking(move || web_routines::image_stats(&image))
+        .await
+        .map_err(|_| Error::internal("Statistics task panicked"))??;
+
+    json_response(StatusCode::OK, &stats)
+}
+
+/// How `POST /api/v1/image/{name}/equalize` should remap an image's values
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EqualizeMethod {
+    /// Spread values evenly across the range (histogram equalization)
+    #[default]
+    Histogram,
+
+    /// Linearly stretch values between two percentiles across the range
+    Stretch,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EqualizeQuery {
+    method: Option<EqualizeMethod>,
+
+    /// For `stretch`, the percentile that becomes black. Defaults to 1.0
+    low_pct: Option<f64>,
+
+    /// For `stretch`, the percentile that becomes white. Defaults to 99.0
+    high_pct: Option<f64>,
+
+    /// Name of the new image. Defaults to `{name}_equalized`
+    output: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/image/{name}/equalize",
+    params(
+        ("method" = Option<String>, Query, description = "Either histogram (default) or stretch"),
+        ("low_pct" = Option<f64>, Query, description = "For stretch, the percentile that becomes black. Defaults to 1.0"),
+        ("high_pct" = Option<f64>, Query, description = "For stretch, the percentile that becomes white. Defaults to 99.0"),
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {name}_equalized"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Added the equalized image under the output name", body = ()),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid percentiles", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+    )
+)]
+pub async fn post_image_equalize(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<EqualizeQuery>,
+) -> ApiResult {
+    let output = params
+        .output
+        .unwrap_or_else(|| format!("{}_equalized", name));
+    let (image, format) = {
+        let app = &app_state.read().await;
+        fetch_image(app.db()?, "images", &name).await?
+    };
+
+    // Keep the source's format where we can, so e.g. 16-bit scans stay 16-bit
+    let format = match format.writing_enabled() {
+        true => format,
+        false => ImageFormat::Png,
+    };
+    let method = params.method.unwrap_or_default();
+    let (low_pct, high_pct) = (
+        params.low_pct.unwrap_or(1.0),
+        params.high_pct.unwrap_or(99.0),
+    );
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        let image = ipr::IprImage(&image);
+        let result = match method {
+            EqualizeMethod::Histogram => image.equalize_histogram()?,
+            EqualizeMethod::Stretch => image.stretch_contrast(low_pct, high_pct)?,
+        };
+        ipr::encode_image(&result, format)
+    })
+    .await
+    .map_err(|_| Error::internal("Equalization task panicked"))??;
+
+    let app = &mut app_state.write().await;
+    store_new_image(app.db()?, &output, &data, format).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        format!("Image added with name {}.", output),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ConvolveQuery {
+    /// Name of the new image. Defaults to `{name}_{matrix_name}`
+    output: Option<String>,
+
+    /// Convolve the image's luminance instead of each of its channels, and store the result as
+    /// grayscale, with values mapped to black through white this way
+    normalize: Option<luma::Normalization>,
+}
+
+/// Where the API serves an image it just stored
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StoredImage {
+    pub name: String,
+    pub url: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/image/{name}/convolve/{matrix_name}",
+    params(
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {name}_{matrix_name}"),
+        ("normalize" = Option<String>, Query, description = "Convolve the image's luminance rather than each channel, storing a grayscale result with values mapped to black through white by clamp (0 to 1), abs (magnitudes, 0 to 1), or min_max (smallest to largest). Without it, each channel is convolved and clamped"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Added the convolved image under the output name", body = StoredImage),
+        (status = StatusCode::BAD_REQUEST, description = "The matrix isn't square with an odd number of rows", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image or matrix available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+    )
+)]
+pub async fn post_image_convolve(
+    State(app_state): AppState,
+    Path((name, matrix_name)): Path<(String, String)>,
+    Query(params): Query<ConvolveQuery>,
+) -> ApiResult {
+    let output = params
+        .output
+        .unwrap_or_else(|| format!("{}_{}", name, matrix_name));
+    let kernel = get_named_matrix(&*app_state.read().await, &matrix_name)?.clone();
+    store_edited_image(
+        &app_state,
+        &name,
+        output,
+        "Convolution",
+        move |image| match params.normalize {
+            Some(normalization) => DynMatrix::from_luma_image(image)
+                .convolve(&kernel)?
+                .to_luma_image(normalization),
+            None => ipr::IprImage(image).convolve(&kernel),
+        },
+    )
+    .await
+}
+
+/// Width and height of generated images when not given
+pub const DEFAULT_GENERATED_SIZE: u32 = 256;
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateQuery {
+    pattern: testgen::Pattern,
+
+    /// Defaults to [`DEFAULT_GENERATED_SIZE`]
+    width: Option<u32>,
+
+    /// Defaults to [`DEFAULT_GENERATED_SIZE`]
+    height: Option<u32>,
+
+    // These would be a flattened `testgen::PatternParams`, but flattening loses the numbers'
+    // types when parsing query strings
+    cell_size: Option<u32>,
+    seed: Option<u64>,
+    spokes: Option<u32>,
+
+    /// Name of the new image. Defaults to `{pattern}_{width}x{height}`
+    output: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/image/generate",
+    params(
+        ("pattern" = testgen::Pattern, Query, description = "What to draw: checkerboard, gradient, noise, or siemens_star"),
+        ("width" = Option<u32>, Query, description = "Width, in pixels. Defaults to 256"),
+        ("height" = Option<u32>, Query, description = "Height, in pixels. Defaults to 256"),
+        ("cell_size" = Option<u32>, Query, description = "For checkerboards, the side of each square, in pixels. Defaults to 32"),
+        ("seed" = Option<u64>, Query, description = "For noise, which noise to make. The same seed always makes the same noise. Defaults to 0"),
+        ("spokes" = Option<u32>, Query, description = "For Siemens stars, the number of dark wedges. Defaults to 16"),
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {pattern}_{width}x{height}"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Added the generated image, as a PNG, under the output name", body = StoredImage),
+        (status = StatusCode::BAD_REQUEST, description = "Unknown pattern, or the size or a parameter is out of range", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "The image would have more pixels than allowed", body = ()),
+    )
+)]
+pub async fn post_image_generate(
+    State(app_state): AppState,
+    Query(params): Query<GenerateQuery>,
+) -> ApiResult {
+    let width = params.width.unwrap_or(DEFAULT_GENERATED_SIZE);
+    let height = params.height.unwrap_or(DEFAULT_GENERATED_SIZE);
+    let output = params
+        .output
+        .unwrap_or_else(|| format!("{}_{}x{}", params.pattern.name(), width, height));
+    app_state
+        .read()
+        .await
+        .upload_limits
+        .check_dimensions(width, height)?;
+
+    let pattern = params.pattern;
+    let pattern_params = testgen::PatternParams {
+        cell_size: params.cell_size,
+        seed: params.seed,
+        spokes: params.spokes,
+    };
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        let image = pattern.build(width, height, &pattern_params)?;
+        ipr::encode_image(&image, ImageFormat::Png)
+    })
+    .await
+    .map_err(|_| Error::internal("Generate task panicked"))??;
+
+    let app = &mut app_state.write().await;
+    store_new_image(app.db()?, &output, &data, ImageFormat::Png).await?;
+
+    json_response(
+        StatusCode::CREATED,
+        &StoredImage {
+            url: format!("/api/v1/image/{}", output),
+            name: output,
+        },
+    )
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResizeQuery {
+    /// New width, in pixels. Defaults to keeping the aspect ratio, given `h`
+    w: Option<u32>,
+
+    /// New height, in pixels. Defaults to keeping the aspect ratio, given `w`
+    h: Option<u32>,
+
+    filter: Option<ipr::ResampleFilter>,
+
+    /// Name of the new image. Defaults to `{name}_resized`
+    output: Option<String>,
+}
+
+/// The length of one side of an image scaled so that its other side goes from `old` to `new`
+/// pixels, keeping its aspect ratio. At least one pixel
+fn keep_aspect(side: u32, old: u32, new: u32) -> u32 {
+    let scaled = (side as u64 * new as u64 + old as u64 / 2) / old.max(1) as u64;
+    scaled.clamp(1, u32::MAX as u64) as u32
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/image/{name}/resize",
+    params(
+        ("w" = Option<u32>, Query, description = "New width, in pixels. If missing, the aspect ratio is kept"),
+        ("h" = Option<u32>, Query, description = "New height, in pixels. If missing, the aspect ratio is kept"),
+        ("filter" = Option<ipr::ResampleFilter>, Query, description = "Resampling filter. Defaults to triangle"),
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {name}_resized"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Added the resized image under the output name", body = StoredImage),
+        (status = StatusCode::BAD_REQUEST, description = "Neither w nor h was given, or one of them is zero", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "The resized image would have more pixels than allowed", body = ()),
+    )
+)]
+pub async fn post_image_resize(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<ResizeQuery>,
+) -> ApiResult {
+    let output = params.output.unwrap_or_else(|| format!("{}_resized", name));
+    let filter = params.filter.unwrap_or_default();
+    let limits = app_state.read().await.upload_limits;
+    store_edited_image(&app_state, &name, output, "Resize", move |image| {
+        let (width, height) = match (params.w, params.h) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, keep_aspect(image.height(), image.width(), w)),
+            (None, Some(h)) => (keep_aspect(image.width(), image.height(), h), h),
+            (None, None) => {
+                return Err(Error::validation(
+                    "Resizing needs a width (w), height (h), or both",
+                ))
             }
         };
-        image_doc_ids.push(result.inserted_id);
-    }
-
-    // Figure out the image URL for each of the image_name values
-    let image_urls = image_names
-        .iter()
-        .map(|name| format!("/api/v1/image/{}", name))
-        .collect::<Vec<String>>();
+        limits.check_dimensions(width, height)?;
+        ipr::IprImage(image).resize(width, height, filter)
+    })
+    .await
+}
 
-    // Now we generate the actual doc of the pyramid. Set "tiles" to null
-    let pyramid_doc = doc! {
-        "uuid": format!("{}", pyramid_uuid),
-        "url": format!("/api/v1/pyramid/{}", pyramid_uuid),
-        "original_filename": image_name,
-        "image_files": image_ids,
-        "image_names": image_names,
-        "image_docs": image_doc_ids,
-        "image_urls": image_urls,
-        "mime_type": format.to_mime_type(),
-        "tiles": "todo",
-    };
+#[derive(Debug, Default, Deserialize)]
+pub struct RotateQuery {
+    /// Degrees to rotate clockwise: 90, 180, or 270
+    deg: Option<u32>,
 
-    let doc_json = match serde_json::to_string(&pyramid_doc) {
-        Ok(j) => j,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to serialize pyramid document.\n",
-            )
-                .into_response();
-        }
-    };
+    /// Name of the new image. Defaults to `{name}_rotated`
+    output: Option<String>,
+}
 
-    match db
-        .collection("pyramids")
-        .insert_one(pyramid_doc, None)
-        .await
-    {
-        Ok(r) => r,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to insert pyramid into database.\n",
-            )
-                .into_response();
-        }
-    };
+#[utoipa::path(
+    post,
+    path = "/api/v1/image/{name}/rotate",
+    params(
+        ("deg" = u32, Query, description = "Degrees to rotate clockwise: 90, 180, or 270"),
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {name}_rotated"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Added the rotated image under the output name", body = StoredImage),
+        (status = StatusCode::BAD_REQUEST, description = "deg is missing, or isn't a multiple of 90", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+    )
+)]
+pub async fn post_image_rotate(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<RotateQuery>,
+) -> ApiResult {
+    let degrees = params
+        .deg
+        .ok_or_else(|| Error::validation("Rotating needs a number of degrees (deg)"))?;
+    let output = params.output.unwrap_or_else(|| format!("{}_rotated", name));
+    store_edited_image(&app_state, &name, output, "Rotation", move |image| {
+        ipr::IprImage(image).rotate(degrees)
+    })
+    .await
+}
 
-    // We have an image pyramid document, now kick off a new background task that takes the pyramid
-    // and:
-    //  0. Updates the pyramid doc such that "tiles" field is now "processing" and releases doc lock
-    //  1. Breaks each image into tiles of 512x512 pixels
-    //  2. Encodes the tile as a PNG and Brotli compresses the PNG data
-    //  3. Updates the pyramid doc such that "tiles" field is now "done", when ALL tiles are done
-    //  4. Updates the pyramid doc such that "tiles" field is now "failed" if any tile fails
-    let pyramid_uuid_to_move = pyramid_uuid;
-    let app_state_to_move = app_state.clone();
-    let work = move || {
-        web_routines::generate_tiles_for_pyramid(State(app_state_to_move), pyramid_uuid_to_move)
-            .unwrap();
-    };
-    let bg_task = tokio::task::spawn_blocking(work);
+#[derive(Debug, Default, Deserialize)]
+pub struct GrayscaleQuery {
+    /// Name of the new image. Defaults to `{name}_gray`
+    output: Option<String>,
+}
 
-    // Push bg_task join handle to app state... just in case.
-    app.bg_tasks.insert(pyramid_uuid, Arc::new(bg_task));
+#[utoipa::path(
+    post,
+    path = "/api/v1/image/{name}/grayscale",
+    params(
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {name}_gray"),
+    ),
+    responses(
+        (status = StatusCode::CREATED, description = "Added the grayscale image under the output name", body = StoredImage),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+    )
+)]
+pub async fn post_image_grayscale(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<GrayscaleQuery>,
+) -> ApiResult {
+    let output = params.output.unwrap_or_else(|| format!("{}_gray", name));
+    store_edited_image(&app_state, &name, output, "Grayscale", move |image| {
+        ipr::IprImage(image).to_grayscale()
+    })
+    .await
+}
 
-    // Everything is set, now let's let the user know the pyramid is created!
-    Response::builder()
-        .status(StatusCode::CREATED)
-        .header("Content-Type", "application/json")
-        .body(Body::from(doc_json.to_string()))
-        .unwrap()
+#[derive(Debug, Default, Deserialize)]
+pub struct SplitQuery {
+    /// Prefix of the new images' names, which end with the channel index. Defaults to `{name}`
+    prefix: Option<String>,
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/v1/pyramid/{uuid}",
-    request_body(
-        content = Bytes,
+    post,
+    path = "/api/v1/image/{name}/split",
+    params(
+        ("prefix" = Option<String>, Query, description = "Prefix of the new images' names, which are {prefix}_c0, {prefix}_c1, and so on. Defaults to {name}"),
     ),
     responses(
-        (status = StatusCode::OK, description = "Returned the image pyramid info with of the given uuid", body = Json),
-        (status = StatusCode::NOT_FOUND, description = "No such image pyramid available", body = ()),
+        (status = StatusCode::CREATED, description = "Added each channel as an 8-bit grayscale PNG, in channel order", body = [StoredImage]),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with one of the channel names already exists", body = ()),
     )
 )]
-pub async fn get_pyramid(State(app_state): AppState, Path(uuid): Path<String>) -> Response {
-    let app = &mut app_state.read().await;
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
-    let pyramids: Collection<Document> = db.collection("pyramids");
-    let pyramid = match pyramids
-        .find_one(
-            doc! {
-                "uuid": uuid.clone()
-            },
-            None,
-        )
-        .await
-    {
-        Ok(d) => d,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query image database.\n",
-            )
-                .into_response();
-        }
+pub async fn post_image_split(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<SplitQuery>,
+) -> ApiResult {
+    let prefix = params.prefix.unwrap_or_else(|| name.clone());
+    let (image, _) = {
+        let app = &app_state.read().await;
+        fetch_image(app.db()?, "images", &name).await?
     };
 
-    if pyramid.is_none() {
-        return (
-            StatusCode::NOT_FOUND,
-            format!("Pyramid {} not found.\n", uuid),
-        )
-            .into_response();
-    }
+    let channels = tokio::task::spawn_blocking(move || -> Result<Vec<Vec<u8>>, Error> {
+        ipr::IprImage(&image)
+            .split_channels()?
+            .iter()
+            .map(|channel| ipr::encode_image(&channel.to_dynamic_image()?, ImageFormat::Png))
+            .collect()
+    })
+    .await
+    .map_err(|_| Error::internal("Channel split task panicked"))??;
 
-    let pyramid = pyramid.unwrap();
-    let json = match serde_json::to_string(&pyramid) {
-        Ok(j) => j,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to serialize pyramid document.\n",
-            )
-                .into_response();
-        }
-    };
+    let images = channels
+        .into_iter()
+        .enumerate()
+        .map(|(c, data)| (format!("{}_c{}", prefix, c), data))
+        .collect();
+    let app = &mut app_state.write().await;
+    let stored = store_new_pngs(app.db()?, images).await?;
+    json_response(StatusCode::CREATED, &stored)
+}
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(Body::from(json.to_string()))
-        .unwrap()
+#[derive(Debug, Default, Deserialize)]
+pub struct MergeQuery {
+    /// Comma-separated names of the single-channel images to merge, in channel order
+    channels: Option<String>,
+
+    /// Name of the new image
+    output: Option<String>,
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/v1/pyramids",
+    post,
+    path = "/api/v1/images/merge",
+    params(
+        ("channels" = String, Query, description = "Comma-separated names of 1 to 4 single-channel images of the same size, which become gray, gray and alpha, RGB, or RGBA in that order"),
+        ("output" = String, Query, description = "Name of the new image"),
+    ),
     responses(
-        (status = StatusCode::OK, description = "Returned a JSON list of image documents", body = Json),
+        (status = StatusCode::CREATED, description = "Added the merged image, as an 8-bit PNG, under the output name", body = StoredImage),
+        (status = StatusCode::BAD_REQUEST, description = "channels or output is missing, or there are more than 4 channels", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "The channels aren't all single-channel images of the same size", body = ()),
     )
 )]
-pub async fn get_pyramids(State(app_state): AppState) -> Response {
-    let app = &mut app_state.read().await;
-    if app.db.is_none() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to acquire handle to image database.\n",
-        )
-            .into_response();
-    }
-    let db = app.db.as_ref().unwrap();
-    let pyramids: Collection<Document> = db.collection("pyramids");
-    let mut found = match pyramids.find(None, None).await {
-        Ok(cursor) => cursor,
-        Err(_e) => {
-            debug_print!("Error: {}", _e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query pyramid database.\n",
-            )
-                .into_response();
-        }
-    };
+pub async fn post_images_merge(
+    State(app_state): AppState,
+    Query(params): Query<MergeQuery>,
+) -> ApiResult {
+    let names: Vec<&str> = params
+        .channels
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    let output = params
+        .output
+        .ok_or_else(|| Error::validation("Merging needs a name for the new image (output)"))?;
 
-    let mut image_docs = Vec::new();
-    while let Some(doc) = found.next().await {
-        match doc {
-            Ok(d) => {
-                image_docs.push(d);
-            }
-            Err(_e) => {
-                debug_print!("Error: {}", _e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to read pyramid document.\n",
-                )
-                    .into_response();
-            }
+    let mut images = Vec::with_capacity(names.len());
+    {
+        let app = &app_state.read().await;
+        let db = app.db()?;
+        for name in &names {
+            images.push(fetch_image(db, "images", name).await?.0);
         }
     }
 
-    let json = match serde_json::to_string(&image_docs) {
-        Ok(j) => j,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to serialize pyramid document.\n",
-            )
-                .into_response();
-        }
-    };
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        let channels: Vec<MyImage<u8>> = images.iter().map(MyImage::from).collect();
+        ipr::encode_image(&ipr::merge_channels(&channels)?, ImageFormat::Png)
+    })
+    .await
+    .map_err(|_| Error::internal("Channel merge task panicked"))??;
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(Body::from(json.to_string()))
-        .unwrap()
+    let app = &mut app_state.write().await;
+    store_new_image(app.db()?, &output, &data, ImageFormat::Png).await?;
+
+    json_response(
+        StatusCode::CREATED,
+        &StoredImage {
+            url: format!("/api/v1/image/{}", output),
+            name: output,
+        },
+    )
+}
+
+/// How `POST /api/v1/image/{name}/threshold` should pick its threshold
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdMethod {
+    /// Use the given value
+    #[default]
+    Fixed,
+
+    /// Pick the value that best separates dark from light (Otsu's method)
+    Otsu,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ThresholdQuery {
+    method: Option<ThresholdMethod>,
+
+    /// For `fixed`, the 8-bit luminance above which pixels become white. Defaults to 128
+    value: Option<u8>,
+
+    /// Name of the new image. Defaults to `{name}_binary`
+    output: Option<String>,
+}
+
+/// Result of `POST /api/v1/image/{name}/threshold`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ThresholdResult {
+    #[serde(flatten)]
+    stored: StoredImage,
+
+    /// The 8-bit luminance above which pixels became white, whether given or picked
+    threshold: u8,
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/v1/image/{name}",
-    request_body(
-        content = Bytes,
+    post,
+    path = "/api/v1/image/{name}/threshold",
+    params(
+        ("method" = Option<String>, Query, description = "Either fixed (default) or otsu"),
+        ("value" = Option<u8>, Query, description = "For fixed, the 8-bit luminance above which pixels become white. Defaults to 128"),
+        ("output" = Option<String>, Query, description = "Name of the new image. Defaults to {name}_binary"),
     ),
     responses(
-        (status = StatusCode::OK, description = "Returned the image of the given name", body = Vec<u8>),
+        (status = StatusCode::CREATED, description = "Added the binary image, as an 8-bit grayscale PNG, under the output name", body = ThresholdResult),
+        (status = StatusCode::BAD_REQUEST, description = "The image has no pixels to pick a threshold from", body = ()),
         (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the output name already exists", body = ()),
     )
 )]
-pub async fn get_image(state: AppState, path: Path<String>, request: Request) -> Response {
-    get_image_from_collection(state, path, request, "images").await
+pub async fn post_image_threshold(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<ThresholdQuery>,
+) -> ApiResult {
+    let output = params.output.unwrap_or_else(|| format!("{}_binary", name));
+    let (image, _) = {
+        let app = &app_state.read().await;
+        fetch_image(app.db()?, "images", &name).await?
+    };
+
+    let method = params.method.unwrap_or_default();
+    let value = params.value.unwrap_or(128);
+    let (threshold, data) = tokio::task::spawn_blocking(move || -> Result<_, Error> {
+        let image = ipr::IprImage(&image);
+        let threshold = match method {
+            ThresholdMethod::Fixed => value,
+            ThresholdMethod::Otsu => image.otsu_threshold()?,
+        };
+        // Binary images compress well losslessly, and lossy formats would blur their edges
+        let data = ipr::encode_image(&image.threshold(threshold)?, ImageFormat::Png)?;
+        Ok((threshold, data))
+    })
+    .await
+    .map_err(|_| Error::internal("Threshold task panicked"))??;
+
+    let app = &mut app_state.write().await;
+    store_new_image(app.db()?, &output, &data, ImageFormat::Png).await?;
+
+    json_response(
+        StatusCode::CREATED,
+        &ThresholdResult {
+            stored: StoredImage {
+                url: format!("/api/v1/image/{}", output),
+                name: output,
+            },
+            threshold,
+        },
+    )
+}
+
+/// What `POST /api/v1/image/{name}/edges` should store
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeOutput {
+    /// One image of the gradient's magnitude
+    #[default]
+    Magnitude,
+
+    /// Two images, of the gradient's horizontal (dx) and vertical (dy) components
+    Gradients,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EdgesQuery {
+    operator: Option<ipr::EdgeOperator>,
+
+    mode: Option<EdgeOutput>,
+
+    /// Name of the new image, or prefix of the new images' names for `gradients`. Defaults to
+    /// `{name}_edges`
+    output: Option<String>,
 }
 
 #[utoipa::path(
-    put,
-    path = "/api/v1/image/{name}",
-    request_body(
-        content = Bytes,
+    post,
+    path = "/api/v1/image/{name}/edges",
+    params(
+        ("operator" = Option<ipr::EdgeOperator>, Query, description = "Kernels estimating the gradient. Defaults to sobel"),
+        ("mode" = Option<String>, Query, description = "Either magnitude (default), storing one image, or gradients, storing {output}_dx and {output}_dy with 128 for no change"),
+        ("output" = Option<String>, Query, description = "Name of the new image, or prefix of the new images' names for gradients. Defaults to {name}_edges"),
     ),
     responses(
-        (status = StatusCode::OK, description = "Image of the given name is updated to the provided image data", body = ()),
-        (status = StatusCode::CREATED, description = "No existing image found, so, image of the given name was added", body = ()),
-        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
-        (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type.", body = ()),
-        (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ()),
+        (status = StatusCode::CREATED, description = "Added the edge image(s), as 8-bit grayscale PNGs", body = [StoredImage]),
+        (status = StatusCode::BAD_REQUEST, description = "The image has no pixels", body = ()),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with one of the output names already exists", body = ()),
     )
 )]
-pub async fn put_image(state: AppState, path: Path<String>, request: Request) -> Response {
-    put_image_in_collection(state, path, request, "images").await
+pub async fn post_image_edges(
+    State(app_state): AppState,
+    Path(name): Path<String>,
+    Query(params): Query<EdgesQuery>,
+) -> ApiResult {
+    let output = params.output.unwrap_or_else(|| format!("{}_edges", name));
+    let (image, _) = {
+        let app = &app_state.read().await;
+        fetch_image(app.db()?, "images", &name).await?
+    };
+
+    let operator = params.operator.unwrap_or_default();
+    let mode = params.mode.unwrap_or_default();
+    let images = tokio::task::spawn_blocking(move || -> Result<Vec<_>, Error> {
+        let image = ipr::IprImage(&image);
+        let png = |image: &DynamicImage| ipr::encode_image(image, ImageFormat::Png);
+        Ok(match mode {
+            EdgeOutput::Magnitude => vec![(output, png(&image.edges(operator)?)?)],
+            EdgeOutput::Gradients => {
+                let (dx, dy) = image.gradients(operator)?;
+                vec![
+                    (format!("{}_dx", output), png(&dx)?),
+                    (format!("{}_dy", output), png(&dy)?),
+                ]
+            }
+        })
+    })
+    .await
+    .map_err(|_| Error::internal("Edge detection task panicked"))??;
+
+    let app = &mut app_state.write().await;
+    let stored = store_new_pngs(app.db()?, images).await?;
+    json_response(StatusCode::CREATED, &stored)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CompareQuery {
+    /// Whether to also compute SSIM, which takes a while for large images
+    ssim: Option<bool>,
+
+    /// If given, store the difference between the images as a new image of this name
+    diff: Option<String>,
+}
+
+/// Result of `POST /api/v1/image/compare/{name1}/{name2}`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageCompareResult {
+    #[serde(flatten)]
+    comparison: ipr::ImageComparison,
+
+    /// Where the difference image was stored, if one was asked for
+    difference: Option<StoredImage>,
 }
 
 #[utoipa::path(
-    delete,
-    path = "/api/v1/image/{name}",
-    request_body(
-        content = Bytes,
+    post,
+    path = "/api/v1/image/compare/{name1}/{name2}",
+    params(
+        ("ssim" = Option<bool>, Query, description = "Also compute SSIM. Defaults to false"),
+        ("diff" = Option<String>, Query, description = "If given, store the absolute difference of the images as a PNG of this name"),
     ),
     responses(
-        (status = StatusCode::OK, description = "Image of the given name is updated to the provided image data", body = ()),
-        (status = StatusCode::NOT_FOUND, description = "No existing image found; could not delete.", body = ()),
-        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
+        (status = StatusCode::OK, description = "Returned MSE and PSNR (null for identical images) of the images, and SSIM if asked for", body = ImageCompareResult),
+        (status = StatusCode::CREATED, description = "As above, and added the difference image under the diff name", body = ImageCompareResult),
+        (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
+        (status = StatusCode::CONFLICT, description = "An image with the diff name already exists", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "The images aren't the same size", body = ()),
     )
 )]
-pub async fn delete_image(state: AppState, path: Path<String>) -> Response {
-    delete_image_from_collection(state, path, "images").await
+pub async fn post_image_compare(
+    State(app_state): AppState,
+    Path((name1, name2)): Path<(String, String)>,
+    Query(params): Query<CompareQuery>,
+) -> ApiResult {
+    let (first, second) = {
+        let app = &app_state.read().await;
+        let db = app.db()?;
+        let (first, _) = fetch_image(db, "images", &name1).await?;
+        let (second, _) = fetch_image(db, "images", &name2).await?;
+        (first, second)
+    };
+
+    let with_ssim = params.ssim.unwrap_or(false);
+    let with_diff = params.diff.is_some();
+    let (comparison, diff) = tokio::task::spawn_blocking(move || -> Result<_, Error> {
+        let image = ipr::IprImage(&first);
+        let comparison = image.compare(&second, with_ssim)?;
+        let diff = match with_diff {
+            true => Some(ipr::encode_image(
+                &image.difference(&second)?,
+                ImageFormat::Png,
+            )?),
+            false => None,
+        };
+        Ok((comparison, diff))
+    })
+    .await
+    .map_err(|_| Error::internal("Comparison task panicked"))??;
+
+    let difference = match (params.diff, diff) {
+        (Some(output), Some(data)) => {
+            let app = &mut app_state.write().await;
+            store_new_image(app.db()?, &output, &data, ImageFormat::Png).await?;
+            Some(StoredImage {
+                url: format!("/api/v1/image/{}", output),
+                name: output,
+            })
+        }
+        _ => None,
+    };
+
+    let status = match difference {
+        Some(_) => StatusCode::CREATED,
+        None => StatusCode::OK,
+    };
+    json_response(
+        status,
+        &ImageCompareResult {
+            comparison,
+            difference,
+        },
+    )
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -1571,16 +3929,121 @@ pub async fn delete_image(state: AppState, path: Path<String>) -> Response {
 #[utoipa::path(
     get,
     path = "/api/v1/level/{name}",
+    params(
+        ("quality" = Option<u8>, Query, description = "Quality to encode JPEG, WebP or AVIF at, from 1 to 100. Also taken from the X-Image-Quality header. Makes WebP lossy")
+    ),
     request_body(
         content = Bytes,
     ),
     responses(
         (status = StatusCode::OK, description = "Returned the image of the given name", body = Vec<u8>),
+        (status = StatusCode::BAD_REQUEST, description = "Quality isn't from 1 to 100", body = ()),
         (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
     )
 )]
-pub async fn get_level(state: AppState, path: Path<String>, request: Request) -> Response {
-    get_image_from_collection(state, path, request, "levels").await
+pub async fn get_level(state: AppState, path: Path<String>, request: Request) -> ApiResult {
+    let name_without_ext = path.split('.').next().unwrap_or(path.as_str()).to_string();
+
+    // Stored pyramid levels live alongside other images, while levels that were skipped when the
+    // pyramid was created are built (and kept) here the first time someone asks for them
+    let collection = {
+        let app = &state.read().await;
+        match app.db.as_ref() {
+            Some(db) => web_routines::resolve_pyramid_level(db, &name_without_ext).await?,
+            None => "levels",
+        }
+    };
+    get_image_from_collection(state, path, request, collection).await
+}
+
+/// What `GET /api/v1/level/{name}/info` says about a pyramid level
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LevelInfo {
+    pub name: String,
+    pub pyramid_uuid: String,
+
+    /// Which level this is, counting down from 0 for the full-size image
+    pub level: u32,
+
+    pub width: u32,
+    pub height: u32,
+    pub mime_type: String,
+
+    /// Where to get the level's image
+    pub url: String,
+}
+
+/// The pyramid, level and dimensions recorded in a level's document, if they were. Levels stored
+/// before these were recorded have none of them
+fn recorded_level_info(doc: &Document) -> Option<(String, u32, LevelDims)> {
+    let dims = LevelDims {
+        width: doc.get_i64("width").ok()? as u32,
+        height: doc.get_i64("height").ok()? as u32,
+    };
+    Some((
+        doc.get_str("pyramid_uuid").ok()?.to_string(),
+        doc.get_i64("level").ok()? as u32,
+        dims,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/level/{name}/info",
+    responses(
+        (status = StatusCode::OK, description = "Returned the pyramid, level index and dimensions of the pyramid level of the given name, building the level first if it was skipped", body = LevelInfo),
+        (status = StatusCode::NOT_FOUND, description = "No such level available", body = ()),
+    )
+)]
+pub async fn get_level_info(State(app_state): AppState, Path(name): Path<String>) -> ApiResult {
+    let name = name.split('.').next().unwrap_or(name.as_str()).to_string();
+    let app = &app_state.read().await;
+    let db = app.db()?;
+    let collection = web_routines::resolve_pyramid_level(db, &name).await?;
+    let level_doc = db
+        .collection::<Document>(collection)
+        .find_one(doc! { "name": &name }, None)
+        .traced("find_one", collection)
+        .await
+        .map_err(db_error("Failed to query levels"))?
+        .ok_or_else(|| Error::not_found(format!("Level {}", name)))?;
+    let mime_type = level_doc
+        .get_str("mime_type")
+        .map_err(|_| Error::internal("Level has no MIME type"))?
+        .to_string();
+
+    let (pyramid_uuid, level, dims) = match recorded_level_info(&level_doc) {
+        Some(info) => info,
+        None => {
+            // Older levels don't say, so ask their pyramid
+            let not_found = || Error::not_found(format!("Pyramid of level {}", name));
+            let (uuid, level) = web_routines::parse_level_name(&name).ok_or_else(not_found)?;
+            let pyramid = db
+                .collection::<Pyramid>("pyramids")
+                .find_one(doc! { "uuid": uuid }, None)
+                .traced("find_one", "pyramids")
+                .await
+                .map_err(db_error("Failed to query pyramids"))?
+                .ok_or_else(not_found)?;
+            let dims = pyramid.dims_of_level(level).ok_or_else(|| {
+                Error::not_found(format!("Dimensions of level {} of pyramid {}", level, uuid))
+            })?;
+            (uuid.to_string(), level as u32, dims)
+        }
+    };
+
+    json_response(
+        StatusCode::OK,
+        &LevelInfo {
+            url: format!("/api/v1/level/{}", name),
+            name,
+            pyramid_uuid,
+            level,
+            width: dims.width,
+            height: dims.height,
+            mime_type,
+        },
+    )
 }
 
 #[utoipa::path(
@@ -1595,9 +4058,11 @@ pub async fn get_level(state: AppState, path: Path<String>, request: Request) ->
         (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
         (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type.", body = ()),
         (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ()),
+        (status = StatusCode::PAYLOAD_TOO_LARGE, description = "Image data is larger than the upload size limit.", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Image has more pixels than allowed, or its dimensions can't be read.", body = ()),
     )
 )]
-pub async fn put_level(state: AppState, path: Path<String>, request: Request) -> Response {
+pub async fn put_level(state: AppState, path: Path<String>, request: Request) -> ApiResult {
     put_image_in_collection(state, path, request, "levels").await
 }
 
@@ -1613,7 +4078,7 @@ pub async fn put_level(state: AppState, path: Path<String>, request: Request) ->
         (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
     )
 )]
-pub async fn delete_level(state: AppState, path: Path<String>) -> Response {
+pub async fn delete_level(state: AppState, path: Path<String>) -> ApiResult {
     delete_image_from_collection(state, path, "levels").await
 }
 
@@ -1624,15 +4089,19 @@ pub async fn delete_level(state: AppState, path: Path<String>) -> Response {
 #[utoipa::path(
     get,
     path = "/api/v1/tile/{name}",
+    params(
+        ("quality" = Option<u8>, Query, description = "Quality to encode JPEG, WebP or AVIF at, from 1 to 100. Also taken from the X-Image-Quality header. Makes WebP lossy")
+    ),
     request_body(
         content = Bytes,
     ),
     responses(
         (status = StatusCode::OK, description = "Returned the image of the given name", body = Vec<u8>),
+        (status = StatusCode::BAD_REQUEST, description = "Quality isn't from 1 to 100", body = ()),
         (status = StatusCode::NOT_FOUND, description = "No such image available", body = ()),
     )
 )]
-pub async fn get_tile(state: AppState, path: Path<String>, request: Request) -> Response {
+pub async fn get_tile(state: AppState, path: Path<String>, request: Request) -> ApiResult {
     get_image_from_collection(state, path, request, "tiles").await
 }
 
@@ -1648,9 +4117,11 @@ pub async fn get_tile(state: AppState, path: Path<String>, request: Request) ->
         (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
         (status = StatusCode::BAD_REQUEST, description = "Unable to handle request. Please pass an image body and specify content type.", body = ()),
         (status = StatusCode::NOT_ACCEPTABLE, description = "Unsupported image format.", body = ()),
+        (status = StatusCode::PAYLOAD_TOO_LARGE, description = "Image data is larger than the upload size limit.", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Image has more pixels than allowed, or its dimensions can't be read.", body = ()),
     )
 )]
-pub async fn put_tile(state: AppState, path: Path<String>, request: Request) -> Response {
+pub async fn put_tile(state: AppState, path: Path<String>, request: Request) -> ApiResult {
     put_image_in_collection(state, path, request, "tiles").await
 }
 
@@ -1666,6 +4137,85 @@ pub async fn put_tile(state: AppState, path: Path<String>, request: Request) ->
         (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Failed to read image from request", body = ()),
     )
 )]
-pub async fn delete_tile(state: AppState, path: Path<String>) -> Response {
+pub async fn delete_tile(state: AppState, path: Path<String>) -> ApiResult {
     delete_image_from_collection(state, path, "tiles").await
 }
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Administration
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/reload",
+    responses(
+        (status = StatusCode::OK, description = "Reloaded tunables from the config file, and returned them", body = Json),
+        (status = StatusCode::UNAUTHORIZED, description = "Missing or wrong admin token", body = ()),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Config file is missing or invalid. Current tunables were kept", body = ()),
+    )
+)]
+pub async fn post_admin_reload(State(app_state): AppState) -> ApiResult {
+    // Grab the store and drop the lock right away; reloading doesn't need it
+    let tunables = app_state.read().await.tunables.clone();
+    // Not really a bad request: the file on disk is what needs fixing
+    let reloaded = tunables
+        .reload()
+        .map_err(|e| Error::unprocessable(format!("Failed to reload tunables: {}", e)))?;
+
+    json_response(StatusCode::OK, reloaded.as_ref())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BenchmarkQuery {
+    size: Option<u32>,
+    iterations: Option<u32>,
+}
+
+/// Upper bounds on benchmark parameters, so the endpoint can't be used to tie up the server
+const BENCHMARK_MAX_SIZE: u32 = 4096;
+const BENCHMARK_MAX_ITERATIONS: u32 = 10;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/benchmark",
+    params(
+        ("size" = Option<u32>, Query, description = "Width and height of the synthetic test image. Defaults to 2048, max 4096"),
+        ("iterations" = Option<u32>, Query, description = "Number of times to run the workload. Defaults to 3, max 10"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Ran the benchmark, and returned per-stage timings", body = Json),
+        (status = StatusCode::BAD_REQUEST, description = "Benchmark parameters out of range", body = ()),
+        (status = StatusCode::UNAUTHORIZED, description = "Missing or wrong admin token", body = ()),
+        (status = StatusCode::INTERNAL_SERVER_ERROR, description = "Benchmark failed to run", body = ()),
+    )
+)]
+pub async fn post_admin_benchmark(
+    State(app_state): AppState,
+    Query(params): Query<BenchmarkQuery>,
+) -> ApiResult {
+    let size = params.size.unwrap_or(2048);
+    let iterations = params.iterations.unwrap_or(3);
+    if !(1..=BENCHMARK_MAX_SIZE).contains(&size) {
+        return Err(Error::validation(format!(
+            "Size must be between 1 and {}",
+            BENCHMARK_MAX_SIZE
+        ))
+        .into());
+    }
+    if !(1..=BENCHMARK_MAX_ITERATIONS).contains(&iterations) {
+        return Err(Error::validation(format!(
+            "Iterations must be between 1 and {}",
+            BENCHMARK_MAX_ITERATIONS
+        ))
+        .into());
+    }
+
+    let tunables = app_state.read().await.tunables.load();
+    let results = tokio::task::spawn_blocking(move || {
+        web_routines::run_benchmark(&tunables, size, iterations)
+    })
+    .await
+    .map_err(|_| Error::internal("Benchmark task panicked"))??;
+
+    json_response(StatusCode::OK, &results)
+}
@@ -1,19 +1,28 @@
 use axum::extract::State;
-use jnickg_imaging::dyn_matrix::DynMatrix;
-use mongodb::Database;
+use jnickg_imaging::{dyn_matrix::DynMatrix, errors::Error};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio::task::JoinHandle;
-use uuid::Uuid;
+use tokio::sync::{Notify, RwLock};
+
+use crate::caching::CacheControl;
+use crate::database::Db;
+use crate::matrix_tasks::MatrixTasks;
+use crate::tunables::TunablesStore;
+use crate::upload_limits::UploadLimits;
 
 #[derive(Clone)]
 pub struct RuntimeData {
     pub somethings: HashSet<u32>,
     pub matrices: HashMap<String, DynMatrix<f64>>,
+    /// Matrices being computed in the background. See [`crate::matrix_tasks`]
+    pub matrix_tasks: MatrixTasks,
     pub image_counter: usize,
-    pub db: Option<Database>,
-    pub bg_tasks: HashMap<Uuid, Arc<JoinHandle<()>>>,
+    pub db: Option<Db>,
+    /// Wakes an idle worker when a job is queued. See [`crate::jobs`]
+    pub job_wakeup: Arc<Notify>,
+    pub tunables: Arc<TunablesStore>,
+    pub cache_control: CacheControl,
+    pub upload_limits: UploadLimits,
 }
 
 impl RuntimeData {
@@ -21,11 +30,32 @@ impl RuntimeData {
         RuntimeData {
             somethings: HashSet::<u32>::new(),
             matrices: HashMap::<String, DynMatrix<f64>>::new(),
+            matrix_tasks: MatrixTasks::new(),
             image_counter: 0,
             db: None,
-            bg_tasks: HashMap::<Uuid, Arc<JoinHandle<()>>>::new(),
+            job_wakeup: Arc::new(Notify::new()),
+            tunables: Arc::new(TunablesStore::default()),
+            cache_control: CacheControl::default(),
+            upload_limits: UploadLimits::default(),
         }
     }
+
+    /// Get the database handle, or an error if we aren't connected. If health checks have found
+    /// the connection bad, it's replaced first
+    pub fn db(&self) -> Result<&Db, Error> {
+        let db = self
+            .db
+            .as_ref()
+            .ok_or_else(|| Error::database("Failed to acquire handle to image database"))?;
+        db.reconnect_if_unhealthy();
+        Ok(db)
+    }
+}
+
+impl Default for RuntimeData {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub type AppState = State<Arc<RwLock<RuntimeData>>>;
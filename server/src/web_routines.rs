@@ -1,192 +1,1131 @@
-use std::sync::Mutex;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
 
-use futures::{executor::block_on, AsyncWriteExt};
-use futures_util::AsyncReadExt;
-use image::{DynamicImage, ImageFormat};
+use futures::AsyncWriteExt;
+use futures_util::{AsyncReadExt, StreamExt, TryStreamExt};
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
 use mongodb::{
-    bson::{doc, Bson, Document},
+    bson::{doc, oid::ObjectId, Bson, Document},
     Collection,
 };
 use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::*;
 
-use jnickg_imaging::ipr::{HasImageProcessingRoutines, ImageTiles, IprImage};
+use jnickg_imaging::{
+    compression::Compression,
+    documents::{self, Pyramid, PyramidLevel, PyramidTiles, TileDescriptor, TilingStatus},
+    errors::Error,
+    ipr::{self, HasImageProcessingRoutines, ImageTiles, IprImage},
+};
+
+use crate::database::{Db, TraceDb};
+use crate::metrics::METRICS;
+use crate::tunables::Tunables;
 
 /// Generate tiles for a pyramid
 ///
-/// With the given image pyramid document, this function represents a background task that takes
-/// the pyramid, and generates tiles for each level of the pyramid. The tiles are 512x512 pixels.
+/// With the given image pyramid document, this function represents a tiling job (run by one of
+/// the workers in [`crate::jobs`]) that takes the pyramid, and generates tiles for each level of
+/// the pyramid. Tile dimensions, compression levels, and how many tiles are uploaded at once come
+/// from the current [`Tunables`](crate::tunables::Tunables).
 ///  0. Updates the pyramid doc such that "tiles" field is now "processing" and releases doc lock
-///  1. Breaks each image into tiles of 512x512 pixels
-///  2. Encodes the tile as a PNG and Brotli compresses the PNG data
+///  1. Breaks each image into tiles of (at most) `tile_width`x`tile_height` pixels
+///  2. Encodes each tile in the pyramid's format, and compresses it with the pyramid's codec
 ///  3. Updates the pyramid doc such that "tiles" field is now "done", when ALL tiles are done
-///  4. Updates the pyramid doc such that "tiles" field is now "failed" if any tile fails
-pub fn generate_tiles_for_pyramid(
+///
+/// Errors are left to the job queue, which retries the job or marks the pyramid as failed.
+pub async fn generate_tiles_for_pyramid(
     app_state: AppState,
     pyramid_uuid: Uuid,
-) -> Result<(), &'static str> {
-    let (dest_format, pyramid_images): (ImageFormat, Vec<Arc<DynamicImage>>) = {
-        let app = &mut app_state.blocking_read();
-        let db = app.db.as_ref().ok_or("Database not connected")?;
-        let pyramids_collection: Collection<Document> = db.collection("pyramids");
-        // Update document so "tiles" field says "processing" and update the db
-        match block_on(pyramids_collection.update_one(
+) -> Result<(), Error> {
+    let start = Instant::now();
+
+    // Take what we need from the app state up front, rather than holding its lock across I/O
+    let (tunables, db) = {
+        let app = app_state.read().await;
+        (app.tunables.load(), app.db()?.clone())
+    };
+
+    let pyramids_collection: Collection<Pyramid> = db.collection("pyramids");
+    // Update document so "tiles" field says "processing" and update the db
+    pyramids_collection
+        .update_one(
             doc! { "uuid": pyramid_uuid.to_string() },
-            doc! { "$set": { "tiles": "processing" } },
+            doc! { "$set": { "tiles": TilingStatus::Processing.as_str() } },
             None,
-        )) {
-            Ok(_) => (),
-            Err(_) => return Err("Error updating pyramid"),
-        };
-        // Now get a handle to the document and return it from the scope block
-        let pyramid_doc = match block_on(
-            pyramids_collection.find_one(doc! { "uuid": pyramid_uuid.to_string() }, None),
-        ) {
-            Ok(Some(doc)) => doc,
-            Ok(None) => return Err("Pyramid not found"),
-            Err(_) => return Err("Error fetching pyramid"),
-        };
+        )
+        .traced("update_one", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error updating pyramid"))?;
+    let pyramid = pyramids_collection
+        .find_one(doc! { "uuid": pyramid_uuid.to_string() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error fetching pyramid"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", pyramid_uuid)))?;
 
-        let mime_type = match pyramid_doc.get("mime_type") {
-            Some(m) => m.as_str().unwrap(),
-            None => return Err("Failed to determine mime type"),
-        };
-        let dest_format = ImageFormat::from_mime_type(mime_type).unwrap();
+    tracing::info!(
+        original_filename = %pyramid.original_filename,
+        levels = pyramid.image_files.len(),
+        "Generating tiles"
+    );
 
-        // Grab each of the image files from GridFS
-        let image_ids: &Vec<Bson> = match pyramid_doc.get_array("image_files") {
-            Ok(arr) => arr,
-            _ => return Err("Error fetching image files"),
-        };
+    let uuid = pyramid_uuid.to_string();
+    let plan = tiling_plan(
+        &pyramid,
+        &tunables,
+        tunables.tile_width,
+        tunables.tile_height,
+        pyramid.requested_tile_format(),
+    )?;
+    let levels = tile_levels(
+        &db,
+        &pyramid,
+        plan,
+        tunables.tile_upload_concurrency,
+        None,
+        |level, t_idx| documents::tile_name(&uuid, level, t_idx),
+    )
+    .await?;
+    let tile_count = levels.iter().map(|l| l.tiles.len()).sum::<usize>();
 
-        let bucket = db.gridfs_bucket(None);
-
-        let pyramid_images = image_ids
-            .iter()
-            .map(|id| {
-                let mut image_bytes = Vec::new();
-                let mut image_stream = block_on(bucket.open_download_stream(id.clone())).unwrap();
-                match block_on(image_stream.read_to_end(&mut image_bytes)) {
-                    Ok(_) => (),
-                    Err(_) => {
-                        todo!();
-                    }
-                };
-                Arc::new(image::load_from_memory_with_format(&image_bytes, dest_format).unwrap())
-            })
-            .collect();
+    let tiles = mongodb::bson::to_bson(&PyramidTiles::Levels(levels))
+        .map_err(|_| Error::internal("Error serializing tile handles"))?;
+    // Update document so "tiles" field contains all the tiles
+    pyramids_collection
+        .update_one(
+            doc! { "uuid": pyramid_uuid.to_string() },
+            doc! { "$set": { "tiles": tiles } },
+            None,
+        )
+        .traced("update_one", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error updating pyramid with tile handles"))?;
+    METRICS.record_tile_generation(start.elapsed());
+    tracing::info!(
+        tiles = tile_count,
+        elapsed_ms = elapsed_ms(start),
+        "Generated tiles"
+    );
+    Ok(())
+}
 
-        (dest_format, pyramid_images)
+/// Generate the tiles of one of a pyramid's tile sets (see [`documents::TileSet`])
+///
+/// Like [`generate_tiles_for_pyramid`], but the tiles are the size and format the set asks for,
+/// and go in the set's `tiles` rather than the pyramid's. The pyramid's own tiles, and its other
+/// sets, are left alone.
+pub async fn generate_tile_set(
+    app_state: AppState,
+    pyramid_uuid: Uuid,
+    key: &str,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    let (tunables, db) = {
+        let app = app_state.read().await;
+        (app.tunables.load(), app.db()?.clone())
     };
 
-    // Now that we've grabbed all the images in the pyramid and updated the doc, actually create
-    // the tiles for each pyramid level, then encode them to the destination format and brotli
-    // compress them. Use Rayon to process each pyramid level separately when breaking into tiles,
-    // then use Rayon to process each tile separately to encode/compress. Then we collect them into
-    // a 3D array where the first dimension is the pyramid level, the second dimension is the list
-    // of tiles, and the third is the bytes for that given tile
-    let pyramid_level_tiles = vec![Arc::<ImageTiles>::default(); pyramid_images.len()];
-    let locking_pyramid_level_tiles = Arc::new(Mutex::new(pyramid_level_tiles));
-    let compressed_level_tiles: Vec<Vec<Vec<u8>>> = pyramid_images
-        .par_iter()
+    let pyramids_collection: Collection<Pyramid> = db.collection("pyramids");
+    let set_filter = doc! { "uuid": pyramid_uuid.to_string(), "tile_sets.key": key };
+    pyramids_collection
+        .update_one(
+            set_filter.clone(),
+            doc! { "$set": { "tile_sets.$.tiles": TilingStatus::Processing.as_str() } },
+            None,
+        )
+        .traced("update_one", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error updating pyramid"))?;
+    let pyramid = pyramids_collection
+        .find_one(doc! { "uuid": pyramid_uuid.to_string() }, None)
+        .traced("find_one", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error fetching pyramid"))?
+        .ok_or_else(|| Error::not_found(format!("Pyramid {}", pyramid_uuid)))?;
+    let tile_set = pyramid
+        .tile_set(key)
+        .ok_or_else(|| Error::not_found(format!("Tile set {} of pyramid {}", key, pyramid_uuid)))?;
+    tracing::info!(tile_set = key, "Generating tile set");
+
+    let uuid = pyramid_uuid.to_string();
+    let plan = tiling_plan(
+        &pyramid,
+        &tunables,
+        tile_set.tile_width,
+        tile_set.tile_height,
+        tile_set.requested_tile_format(),
+    )?;
+    let levels = tile_levels(
+        &db,
+        &pyramid,
+        plan,
+        tunables.tile_upload_concurrency,
+        Some(key),
+        |level, t_idx| documents::tile_set_tile_name(&uuid, key, level, t_idx),
+    )
+    .await?;
+    let tile_count = levels.iter().map(|l| l.tiles.len()).sum::<usize>();
+
+    let tiles = mongodb::bson::to_bson(&PyramidTiles::Levels(levels))
+        .map_err(|_| Error::internal("Error serializing tile handles"))?;
+    pyramids_collection
+        .update_one(
+            set_filter,
+            doc! { "$set": { "tile_sets.$.tiles": tiles } },
+            None,
+        )
+        .traced("update_one", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error updating pyramid with tile handles"))?;
+    METRICS.record_tile_generation(start.elapsed());
+    tracing::info!(
+        tiles = tile_count,
+        elapsed_ms = elapsed_ms(start),
+        "Generated tile set"
+    );
+    Ok(())
+}
+
+/// How to cut `pyramid`'s stored levels into tiles of (at most) `tile_width`x`tile_height`, in
+/// `tile_format` (`None` to pick each tile's format by its content), compressed with the pyramid's
+/// codec at the level set in `tunables`
+fn tiling_plan(
+    pyramid: &Pyramid,
+    tunables: &Tunables,
+    tile_width: u32,
+    tile_height: u32,
+    tile_format: Option<ImageFormat>,
+) -> Result<ipr::PyramidPlan, Error> {
+    ipr::PyramidBuilder::new()
+        .params(pyramid.params())
+        .tile_size(tile_width, tile_height)
+        .tile_format(tile_format)
+        .compression(pyramid.compression)
+        .compression_level(Some(tunables.compression_level(pyramid.compression)))
+        .brotli_window_size(Some(tunables.brotli_lg_window_size))
+        .build()
+}
+
+/// Break each stored level of `pyramid` into tiles, encoded and compressed per `plan`, and store
+/// them under the names `tile_name` gives them (by level and tile index), up to
+/// `upload_concurrency` at once. They're recorded as part of `tile_set`, if given. Returns a
+/// description of each level's tiles, largest level first
+async fn tile_levels(
+    db: &Db,
+    pyramid: &Pyramid,
+    plan: ipr::PyramidPlan,
+    upload_concurrency: usize,
+    tile_set: Option<&str>,
+    tile_name: impl Fn(usize, usize) -> String,
+) -> Result<Vec<PyramidLevel>, Error> {
+    let level_format = ImageFormat::from_mime_type(&pyramid.mime_type)
+        .ok_or_else(|| Error::internal("Failed to determine mime type"))?;
+    let compression = plan.compression();
+
+    // Grab each of the image files from GridFS
+    let bucket = db.gridfs_bucket();
+    let mut level_bytes = Vec::new();
+    for id in pyramid.image_files.iter() {
+        let mut image_bytes = Vec::new();
+        let mut image_stream = bucket
+            .open_download_stream(Bson::ObjectId(*id))
+            .traced("open_download", "fs")
+            .await
+            .map_err(|_| Error::database("Error opening pyramid level in GridFS"))?;
+        let n = image_stream
+            .read_to_end(&mut image_bytes)
+            .traced("download", "fs")
+            .await
+            .map_err(|_| Error::database("Error reading pyramid level from GridFS"))?;
+        METRICS.add_gridfs_bytes_read(n);
+        level_bytes.push(image_bytes);
+    }
+
+    // Now that we've grabbed all the images in the pyramid, actually create
+    // the tiles for each pyramid level, then encode them to the destination format and compress
+    // them. That's all CPU-bound, so it happens on a blocking thread, where Rayon processes each
+    // pyramid level separately when decoding and breaking into tiles, then each tile separately
+    // to encode/compress. Each tile gets the requested format, unless it would lose transparency
+    // in it. For each level we get its dimensions, and its compressed tiles
+    let tiled_levels = tokio::task::spawn_blocking(move || {
+        level_bytes
+            .par_iter()
+            .map(|bytes| -> Result<(u32, u32, Vec<CompressedTile>), Error> {
+                let image = ipr::decode_image(bytes, level_format, None)?;
+                let compressed_tiles = plan
+                    .tile(&image)?
+                    .iter_with_coords()
+                    .collect::<Vec<_>>()
+                    .into_par_iter()
+                    .map(|(x, y, _, _, tile)| {
+                        let (format, data) = plan.compress_tile(tile)?;
+                        Ok(CompressedTile {
+                            x,
+                            y,
+                            width: tile.width(),
+                            height: tile.height(),
+                            format,
+                            data,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                Ok((image.width(), image.height(), compressed_tiles))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    })
+    .await
+    .map_err(|_| Error::internal("Tiling task panicked"))??;
+
+    // Each tile is written to GridFS, and described by where it is in its level. Uploads don't
+    // depend on each other, so up to `tile_upload_concurrency` of them are in flight at once. They
+    // finish in any order, so they're sorted back into level and tile order before being
+    // aggregated into a `PyramidLevel` per pyramid level
+    let uploads = tiled_levels
+        .iter()
         .enumerate()
-        .map(|(idx, i): (usize, &Arc<DynamicImage>)| -> Vec<Vec<u8>> {
-            let image = IprImage(i);
-            let tiles = image.make_tiles(512, 512).unwrap();
-            let compressed_tiles: Vec<Vec<u8>> = tiles
-                .tiles
-                .par_iter()
-                .map(|t: &DynamicImage| -> Vec<u8> {
-                    let tile = IprImage(t);
-                    tile.compress_brotli(10, 24, Some(dest_format)).unwrap()
-                })
-                .collect();
-            let plt = &mut locking_pyramid_level_tiles.lock().unwrap();
-            plt[idx] = Arc::new(tiles);
+        .flat_map(|(level, (_, _, compressed_tiles))| {
             compressed_tiles
+                .iter()
+                .enumerate()
+                .map(move |(t_idx, tile)| (level, t_idx, tile))
+        });
+    let mut uploaded = futures::stream::iter(uploads)
+        .map(|(level, t_idx, tile)| {
+            let tile_name = &tile_name;
+            async move {
+                let name = tile_name(level, t_idx);
+                let record = tile_record(&pyramid.uuid, tile_set, level, tile.x, tile.y);
+                let (tile_id, sha256) =
+                    upload_tile(db, &name, &tile.data, tile.format, compression, record).await?;
+                let descriptor = TileDescriptor {
+                    x: tile.x,
+                    y: tile.y,
+                    width: tile.width,
+                    height: tile.height,
+                    index: t_idx as u32,
+                    tile_id,
+                    name,
+                    mime_type: Some(tile.format.to_mime_type().to_string()),
+                    sha256: Some(sha256),
+                };
+                Ok::<_, Error>((level, descriptor))
+            }
         })
-        .collect();
+        .buffer_unordered(upload_concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+    uploaded.sort_by_key(|(level, tile)| (*level, tile.index));
 
-    // We don't need the mutex any more, to slurp the vec back out
-    let pyramid_level_tiles = locking_pyramid_level_tiles.lock().unwrap();
-
-    // For each Pyramid level & tile, we write that object to GridFS and return a doc describing
-    // the tile (x/y loc, w/h, index. In the outer layer, aggregate all Bson::Documents into a
-    // single array doc containing all the tile docs for that pyramid level, as well as some
-    // metadata about that pyramid level (index, w/h)
-    let app = &mut app_state.blocking_write();
-    let db = app.db.as_ref().unwrap();
-    let bucket = db.gridfs_bucket(None);
-    let mut level_docs = Vec::new();
-    for (pyramid_level, level_tiles) in compressed_level_tiles.iter().enumerate() {
-        let mut tile_docs = Vec::new();
-        for (t_idx, tile) in level_tiles.iter().enumerate() {
-            let tile_name_base = format!("{}_L{}_T{}", pyramid_uuid, pyramid_level, t_idx);
-
-            let mut upload_stream = bucket.open_upload_stream(&tile_name_base, None);
-            match block_on(upload_stream.write_all(tile)) {
-                Ok(_) => (),
-                Err(_) => return Err("Error writing tile to GridFS"),
-            }
-            let tile_obj_id = upload_stream.id().clone();
-            let level_tiles = &pyramid_level_tiles[pyramid_level];
-            let tile_image = &level_tiles.tiles[t_idx];
-
-            match block_on(upload_stream.close()) {
-                Ok(_) => (),
-                Err(_) => {
-                    return Err("Error closing upload stream");
-                }
+    let mut levels = tiled_levels
+        .iter()
+        .enumerate()
+        .map(|(level, &(width, height, _))| PyramidLevel {
+            level: level as u32,
+            width,
+            height,
+            tiles: Vec::new(),
+        })
+        .collect::<Vec<PyramidLevel>>();
+    for (level, tile) in uploaded {
+        levels[level].tiles.push(tile);
+    }
+
+    Ok(levels)
+}
+
+/// A tile of a pyramid level, encoded and compressed, and where it is in the level, in pixels
+struct CompressedTile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    format: ImageFormat,
+    data: Vec<u8>,
+}
+
+/// Write one compressed tile to GridFS, and add an image doc for it, with the fields in `record`
+/// (see [`tile_record`]). Returns the tile's GridFS ID, and the [`content_hash`] of what was
+/// written, which the image doc records too
+pub async fn upload_tile(
+    db: &Db,
+    name: &str,
+    data: &[u8],
+    format: ImageFormat,
+    compression: Compression,
+    record: Document,
+) -> Result<(ObjectId, String), Error> {
+    let mut upload_stream = db.gridfs_bucket().open_upload_stream(name, None);
+    upload_stream
+        .write_all(data)
+        .traced("upload", "fs")
+        .await
+        .map_err(|_| Error::database("Error writing tile to GridFS"))?;
+    METRICS.add_gridfs_bytes_written(data.len());
+    let tile_id = upload_stream
+        .id()
+        .as_object_id()
+        .ok_or_else(|| Error::internal("GridFS gave the tile a non-ObjectId ID"))?;
+    upload_stream
+        .close()
+        .traced("finish_upload", "fs")
+        .await
+        .map_err(|_| Error::database("Error closing upload stream"))?;
+
+    let sha256 = content_hash(data);
+    let mut image_doc = doc! {
+        "name": name,
+        "image": tile_id,
+        "mime_type": format.to_mime_type(),
+        "compression": compression.content_encoding(),
+        "sha256": sha256.as_str(),
+    };
+    image_doc.extend(record);
+    db.collection::<Document>("images")
+        .insert_one(image_doc, None)
+        .traced("insert_one", "images")
+        .await
+        .map_err(|_| Error::database("Error inserting image into database"))?;
+    Ok((tile_id, sha256))
+}
+
+/// Remove any tiles of the given pyramid, including the GridFS files of tiles whose image doc
+/// never made it into the database. Its tile sets are left alone. Returns the number of files
+/// removed
+pub async fn delete_pyramid_tiles(db: &Db, pyramid_uuid: &str) -> Result<usize, Error> {
+    delete_tiles_named(db, &format!("^{}_L[0-9]+_T[0-9]+$", pyramid_uuid)).await
+}
+
+/// Like [`delete_pyramid_tiles`], but for the tiles of the pyramid's tile set with the given key
+pub async fn delete_tile_set_tiles(db: &Db, pyramid_uuid: &str, key: &str) -> Result<usize, Error> {
+    delete_tiles_named(db, &format!("^{}_{}_L[0-9]+_T[0-9]+$", pyramid_uuid, key)).await
+}
+
+/// Remove the tiles whose names match the regular expression `pattern`
+async fn delete_tiles_named(db: &Db, pattern: &str) -> Result<usize, Error> {
+    let tile_names = doc! { "$regex": pattern };
+
+    let bucket = db.gridfs_bucket();
+    let mut files = bucket
+        .find(doc! { "filename": tile_names.clone() }, None)
+        .traced("find", "fs.files")
+        .await
+        .map_err(|_| Error::database("Error finding tile files"))?;
+    let mut deleted = 0;
+    while let Some(file) = files.next().await {
+        let file = file.map_err(|_| Error::database("Error reading tile file"))?;
+        bucket
+            .delete(file.id)
+            .traced("delete", "fs")
+            .await
+            .map_err(|_| Error::database("Error deleting tile file"))?;
+        deleted += 1;
+    }
+
+    db.collection::<Document>("images")
+        .delete_many(doc! { "name": tile_names }, None)
+        .traced("delete_many", "images")
+        .await
+        .map_err(|_| Error::database("Error deleting tile documents"))?;
+    Ok(deleted)
+}
+
+/// SHA-256 of `data`, in lowercase hex, for spotting uploads we've seen before, and checking
+/// stored files haven't changed since they were written (see [`verify_pyramid`])
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// A stored level or tile whose file doesn't match the checksum recorded when it was written
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ChecksumMismatch {
+    /// Image name of the level or tile
+    pub name: String,
+
+    /// SHA-256 recorded when the file was written
+    pub expected: String,
+
+    /// SHA-256 of the file as it's stored now. Absent if the file is missing, or couldn't be read
+    /// in full (e.g. some of its chunks are gone)
+    pub actual: Option<String>,
+}
+
+/// Results of [`verify_pyramid`]
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct VerifyReport {
+    pub uuid: String,
+
+    /// Whether every file that has a checksum matched it
+    pub ok: bool,
+
+    /// Number of levels and tiles whose files were read and checked
+    pub checked: usize,
+
+    /// Number of levels and tiles written before checksums were recorded, which can't be checked
+    pub unchecked: usize,
+
+    /// Files that didn't match their checksums, by name
+    pub mismatches: Vec<ChecksumMismatch>,
+}
+
+/// Read every stored level and tile of `pyramid` (those of its tile sets included) back from
+/// GridFS, and compare each with the checksum recorded when it was written. Up to `concurrency`
+/// files are read at once
+pub async fn verify_pyramid(
+    db: &Db,
+    pyramid: &Pyramid,
+    concurrency: usize,
+) -> Result<VerifyReport, Error> {
+    let mut files = Vec::new();
+    for (level, (name, id)) in pyramid
+        .image_names
+        .iter()
+        .zip(&pyramid.image_files)
+        .enumerate()
+    {
+        files.push((name, *id, pyramid.image_sha256.get(level)));
+    }
+    let tile_sets =
+        std::iter::once(&pyramid.tiles).chain(pyramid.tile_sets.iter().map(|set| &set.tiles));
+    for tiles in tile_sets {
+        for level in tiles.levels().unwrap_or_default() {
+            for tile in &level.tiles {
+                files.push((&tile.name, tile.tile_id, tile.sha256.as_ref()));
             }
+        }
+    }
+    let total = files.len();
+    let checksummed = files
+        .into_iter()
+        .filter_map(|(name, id, expected)| Some((name, id, expected?)))
+        .collect::<Vec<_>>();
+    let (checked, unchecked) = (checksummed.len(), total - checksummed.len());
+
+    let mut mismatches = futures::stream::iter(checksummed)
+        .map(|(name, id, expected)| async move {
+            let actual = stored_file_hash(db, id).await;
+            (actual.as_ref() != Some(expected)).then(|| ChecksumMismatch {
+                name: name.clone(),
+                expected: expected.clone(),
+                actual,
+            })
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(futures::future::ready)
+        .collect::<Vec<_>>()
+        .await;
+    mismatches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if !mismatches.is_empty() {
+        tracing::warn!(
+            pyramid = pyramid.uuid,
+            mismatches = mismatches.len(),
+            "Stored files don't match their checksums"
+        );
+    }
+    Ok(VerifyReport {
+        uuid: pyramid.uuid.clone(),
+        ok: mismatches.is_empty(),
+        checked,
+        unchecked,
+        mismatches,
+    })
+}
+
+/// [`content_hash`] of the GridFS file with the given ID, or `None` if it's missing or can't be
+/// read in full
+async fn stored_file_hash(db: &Db, id: ObjectId) -> Option<String> {
+    let mut data = Vec::new();
+    let mut download_stream = db
+        .gridfs_bucket()
+        .open_download_stream(Bson::ObjectId(id))
+        .traced("open_download", "fs")
+        .await
+        .ok()?;
+    let n = download_stream
+        .read_to_end(&mut data)
+        .traced("download", "fs")
+        .await
+        .ok()?;
+    METRICS.add_gridfs_bytes_read(n);
+    Some(content_hash(&data))
+}
 
-            let image_doc = doc! {
-                "name": tile_name_base.clone(),
-                "image": tile_obj_id.clone(),
-                "mime_type": dest_format.to_mime_type(),
-                "brotli": true,
-            };
-
-            match block_on(db.collection("images").insert_one(image_doc, None)) {
-                Ok(_) => (),
-                Err(_) => return Err("Error inserting image into database"),
-            };
-
-            // Based on tile size, original dimensions, and tile index, determine our x/y;
-            let t_idx: u32 = t_idx.try_into().unwrap();
-            let x = (t_idx % level_tiles.count_across) * level_tiles.tile_width;
-            let y = (t_idx / level_tiles.count_across) * level_tiles.tile_height;
-
-            tile_docs.push(doc! {
-                "x": x,
-                "y": y,
-                "width": tile_image.width(),
-                "height": tile_image.height(),
-                "index": t_idx,
-                "tile_id": tile_obj_id.clone(),
-                "name": tile_name_base.clone()
-            });
+/// A Mongo condition matching strings that contain `text`, ignoring case. `text` is matched
+/// literally, so characters that mean something in a regular expression are escaped
+pub fn contains_ignoring_case(text: &str) -> Document {
+    let mut pattern = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            pattern.push('\\');
         }
-        // Now that we have all the tile docs for this pyramid level, we need to add some
-        // metadata about the pyramid level itself
-        let pyramid_level_u32: u32 = pyramid_level.try_into().unwrap(); // How annoying
-        level_docs.push(doc! {
-            "level": pyramid_level_u32,
-            "width": pyramid_images[pyramid_level].width(),
-            "height": pyramid_images[pyramid_level].height(),
-            "tiles": tile_docs
-        });
+        pattern.push(c);
     }
+    doc! { "$regex": pattern, "$options": "i" }
+}
 
-    let pyramids_collection: Collection<Document> = db.collection("pyramids");
-    // Update document so "tiles" field contains all the tiles
-    match block_on(pyramids_collection.update_one(
-        doc! { "uuid": pyramid_uuid.to_string() },
-        doc! { "$set": { "tiles": level_docs } },
-        None,
-    )) {
-        Ok(_) => Ok(()),
-        Err(_) => Err("Error updating pyramid with tile handles"),
+/// A pyramid built from an upload with the given [`content_hash`], for which `same_parameters`
+/// holds, if there is one
+pub async fn find_pyramid_by_content(
+    db: &Db,
+    content_sha256: &str,
+    same_parameters: impl Fn(&Pyramid) -> bool,
+) -> Result<Option<Pyramid>, Error> {
+    let mut candidates = db
+        .collection::<Pyramid>("pyramids")
+        .find(doc! { "content_sha256": content_sha256 }, None)
+        .traced("find", "pyramids")
+        .await
+        .map_err(|_| Error::database("Error querying pyramids"))?;
+    while let Some(pyramid) = candidates.next().await {
+        let pyramid = pyramid.map_err(|_| Error::database("Error reading pyramid"))?;
+        if same_parameters(&pyramid) {
+            return Ok(Some(pyramid));
+        }
+    }
+    Ok(None)
+}
+
+/// Fields recording which pyramid a level image belongs to, and how big it is, for
+/// `GET /api/v1/level/{name}/info`, along with the [`content_hash`] of its file
+pub fn level_record(
+    pyramid_uuid: &str,
+    level: usize,
+    width: u32,
+    height: u32,
+    sha256: &str,
+) -> Document {
+    doc! {
+        "pyramid_uuid": pyramid_uuid,
+        "level": level as i64,
+        "width": width as i64,
+        "height": height as i64,
+        "sha256": sha256,
+    }
+}
+
+/// Fields recording where a tile is: its pyramid, tile set (if it's in one), level, and the
+/// top-left corner it covers in that level, in pixels. Tiles are indexed by these (see
+/// [`crate::schema`]), so they can be looked up by position
+pub fn tile_record(
+    pyramid_uuid: &str,
+    tile_set: Option<&str>,
+    level: usize,
+    x: u32,
+    y: u32,
+) -> Document {
+    let mut record = doc! {
+        "pyramid_uuid": pyramid_uuid,
+        "level": level as i64,
+        "x": x as i64,
+        "y": y as i64,
+    };
+    if let Some(tile_set) = tile_set {
+        record.insert("tile_set", tile_set);
+    }
+    record
+}
+
+/// Splits a pyramid level's image name (`<pyramid uuid>_L<level>`) into its parts
+pub fn parse_level_name(name: &str) -> Option<(&str, usize)> {
+    let (uuid, level) = name.rsplit_once("_L")?;
+    Some((uuid, level.parse().ok()?))
+}
+
+/// Splits a pyramid tile's image name (`<pyramid uuid>_L<level>_T<index>`) into its parts
+pub fn parse_tile_name(name: &str) -> Option<(&str, usize, usize)> {
+    let (level_name, index) = name.rsplit_once("_T")?;
+    let (uuid, level) = parse_level_name(level_name)?;
+    Some((uuid, level, index.parse().ok()?))
+}
+
+/// Work out which collection `GET /level/{name}` should serve `name` from, building the level
+/// first if need be.
+///
+/// - Levels already in the `levels` collection are served from there
+/// - Levels that were stored when the pyramid was created are served from `images`
+/// - Levels that were skipped (because of `min_dimension` or `max_levels`) are built from the
+///   smallest stored level, and added to `levels` for next time
+///
+/// Anything else resolves to `levels`, where the lookup will come up empty.
+pub async fn resolve_pyramid_level(db: &Db, name: &str) -> Result<&'static str, Error> {
+    let levels: Collection<Document> = db.collection("levels");
+    let existing = levels
+        .find_one(doc! { "name": name }, None)
+        .traced("find_one", "levels")
+        .await
+        .map_err(|_| Error::database("Error querying levels"))?;
+    if existing.is_some() {
+        return Ok("levels");
+    }
+
+    let Some((uuid, level)) = parse_level_name(name) else {
+        return Ok("levels");
+    };
+    let pyramids: Collection<Pyramid> = db.collection("pyramids");
+    let pyramid = match pyramids
+        .find_one(doc! { "uuid": uuid }, None)
+        .traced("find_one", "pyramids")
+        .await
+    {
+        Ok(Some(p)) => p,
+        Ok(None) => return Ok("levels"),
+        Err(_) => return Err(Error::database("Error fetching pyramid")),
+    };
+
+    let stored_levels = pyramid.image_names.len();
+    if level < stored_levels {
+        return Ok("images");
+    }
+    // Laplacian levels can't be built from the levels above them, so those pyramids store every
+    // level they have
+    if level >= pyramid.total_levels()
+        || stored_levels == 0
+        || pyramid.pyramid_type == ipr::PyramidType::Laplacian
+    {
+        return Ok("levels");
+    }
+
+    // Requests for a level that's already being built wait for it, then find it in `levels`,
+    // rather than building and uploading it again
+    let lock = synthesis_lock(name);
+    let guard = lock.lock().await;
+    let built_meanwhile = levels
+        .find_one(doc! { "name": name }, None)
+        .traced("find_one", "levels")
+        .await
+        .map_err(|_| Error::database("Error querying levels"))?
+        .is_some();
+    let result = match built_meanwhile {
+        true => Ok(()),
+        false => synthesize_pyramid_level(db, &pyramid, stored_levels - 1, level, name).await,
+    };
+    drop(guard);
+    release_synthesis_lock(name, lock);
+    result.map(|_| "levels")
+}
+
+/// Locks on the levels this process is synthesizing, by name
+static SYNTHESIS_LOCKS: std::sync::Mutex<BTreeMap<String, Arc<tokio::sync::Mutex<()>>>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+/// The lock to hold while synthesizing the level `name`
+fn synthesis_lock(name: &str) -> Arc<tokio::sync::Mutex<()>> {
+    SYNTHESIS_LOCKS
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Let go of a lock from [`synthesis_lock`], forgetting it once nobody else is waiting on it
+fn release_synthesis_lock(name: &str, lock: Arc<tokio::sync::Mutex<()>>) {
+    let mut locks = SYNTHESIS_LOCKS.lock().unwrap();
+    // One reference is the map's, and the other is ours
+    if Arc::strong_count(&lock) <= 2 {
+        locks.remove(name);
+    }
+}
+
+/// Build pyramid level `level` from stored level `source_level`, and add it to the `levels`
+/// collection under `name`
+#[tracing::instrument(skip(db, pyramid), fields(pyramid_uuid = %pyramid.uuid))]
+async fn synthesize_pyramid_level(
+    db: &Db,
+    pyramid: &Pyramid,
+    source_level: usize,
+    level: usize,
+    name: &str,
+) -> Result<(), Error> {
+    let format = ImageFormat::from_mime_type(&pyramid.mime_type)
+        .ok_or_else(|| Error::internal("Failed to determine mime type"))?;
+    let params = ipr::PyramidParams {
+        min_dimension: None,
+        max_levels: None,
+        ..pyramid.params()
+    };
+
+    let source_id = pyramid
+        .image_files
+        .get(source_level)
+        .ok_or_else(|| Error::internal("Pyramid has no image file for source level"))?;
+    let bucket = db.gridfs_bucket();
+    let mut source_bytes = Vec::new();
+    let mut download_stream = bucket
+        .open_download_stream(Bson::ObjectId(*source_id))
+        .traced("open_download", "fs")
+        .await
+        .map_err(|_| Error::database("Error opening source level"))?;
+    download_stream
+        .read_to_end(&mut source_bytes)
+        .traced("download", "fs")
+        .await
+        .map_err(|_| Error::database("Error reading source level"))?;
+    METRICS.add_gridfs_bytes_read(source_bytes.len());
+
+    // Decoding and resampling are CPU-bound, so keep them off the async runtime
+    let (data, width, height) = tokio::task::spawn_blocking(move || -> Result<_, Error> {
+        let source = ipr::decode_image(&source_bytes, format, None)?;
+        let image = ipr::synthesize_pyramid_level(&source, level - source_level, &params)?;
+        Ok((
+            ipr::encode_image(&image, format)?,
+            image.width(),
+            image.height(),
+        ))
+    })
+    .await
+    .map_err(|_| Error::internal("Level generation task failed"))??;
+
+    let mut upload_stream = bucket.open_upload_stream(name, None);
+    upload_stream
+        .write_all(&data)
+        .traced("upload", "fs")
+        .await
+        .map_err(|_| Error::database("Error writing level to GridFS"))?;
+    METRICS.add_gridfs_bytes_written(data.len());
+    let image_id = upload_stream.id().clone();
+    upload_stream
+        .close()
+        .traced("finish_upload", "fs")
+        .await
+        .map_err(|_| Error::database("Error closing upload stream"))?;
+
+    let mut level_doc = doc! {
+        "name": name,
+        "image": image_id.clone(),
+        "mime_type": format.to_mime_type(),
+    };
+    level_doc.extend(level_record(
+        &pyramid.uuid,
+        level,
+        width,
+        height,
+        &content_hash(&data),
+    ));
+    let inserted = db
+        .collection("levels")
+        .insert_one(level_doc, None)
+        .traced("insert_one", "levels")
+        .await;
+    match inserted {
+        Ok(_) => {}
+        // Another process built the level first. Theirs is kept, so ours can go
+        Err(e) if is_duplicate_key(&e) => {
+            bucket
+                .delete(image_id)
+                .traced("delete", "fs")
+                .await
+                .map_err(|_| Error::database("Error deleting duplicate level"))?;
+            tracing::info!("Pyramid level was synthesized elsewhere first");
+            return Ok(());
+        }
+        Err(_) => return Err(Error::database("Error inserting level into database")),
+    }
+    tracing::info!(bytes = data.len(), "Synthesized pyramid level");
+    Ok(())
+}
+
+/// Whether a write failed because it would have broken a unique index
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    matches!(
+        e.kind.as_ref(),
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w))
+            if w.code == DUPLICATE_KEY
+    )
+}
+
+/// Pick which image to downscale for a thumbnail of (at most) `max`x`max` pixels
+///
+/// Given the pyramid that `name` is a level of, this returns the name of the smallest level, at or
+/// below `name`, that is still at least `max` pixels along its longest side. If even `name` is
+/// smaller than that, it's `name` itself. Pyramids that don't record their level dimensions yield
+/// `None`, in which case `name` itself should be used too.
+pub fn thumbnail_source_level(pyramid: &Pyramid, name: &str, max: u32) -> Option<String> {
+    let start = pyramid.image_names.iter().position(|n| n == name)?;
+    if pyramid.level_dims.len() <= start {
+        return None;
+    }
+
+    let source = pyramid
+        .image_names
+        .iter()
+        .zip(pyramid.level_dims.iter())
+        .skip(start)
+        .take_while(|(_, d)| d.width.max(d.height) >= max)
+        .last()
+        .map_or(name, |(n, _)| n.as_str());
+    Some(source.to_string())
+}
+
+/// Statistics for one channel of an image, in the image's native range
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelStats {
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub histogram: Vec<u64>,
+}
+
+/// Results of [`image_stats`], suitable for returning to the client as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageStats {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: String,
+
+    /// The largest value a sample can take, e.g. 255 for 8-bit images. Histogram bins evenly
+    /// divide the range from zero to this
+    pub sample_max: f64,
+    pub channels: Vec<ChannelStats>,
+}
+
+/// Gather per-channel statistics for an image
+///
+/// This reads every pixel a few times over, so callers in an async context should run it via
+/// `spawn_blocking`.
+pub fn image_stats(image: &DynamicImage) -> Result<ImageStats, Error> {
+    let i = IprImage(image);
+    let histograms = i.histogram()?;
+    let mean_stddev = i.mean_stddev()?;
+    let min_max = i.min_max()?;
+
+    let channels = histograms
+        .iter()
+        .zip(mean_stddev)
+        .zip(min_max)
+        .map(|((histogram, (mean, stddev)), (min, max))| ChannelStats {
+            mean,
+            stddev,
+            min,
+            max,
+            histogram: histogram.to_vec(),
+        })
+        .collect();
+
+    Ok(ImageStats {
+        width: image.width(),
+        height: image.height(),
+        color_type: format!("{:?}", image.color()),
+        sample_max: ipr::sample_max(image),
+        channels,
+    })
+}
+
+/// Timings (in milliseconds) for one pass of the synthetic benchmark workload
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BenchmarkIteration {
+    pub generate_image_ms: f64,
+    pub generate_pyramid_ms: f64,
+    pub make_tiles_ms: f64,
+    pub compress_tiles_ms: f64,
+    pub total_ms: f64,
+}
+
+/// Results of [`run_benchmark`], suitable for returning to the client as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkResults {
+    pub image_size: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub brotli_level: u32,
+    pub brotli_lg_window_size: u32,
+    pub pyramid_levels: usize,
+    pub tile_count: usize,
+    pub compressed_bytes: usize,
+    pub worker_threads: usize,
+    pub iterations: Vec<BenchmarkIteration>,
+    pub mean: BenchmarkIteration,
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Run a synthetic tiling workload and time each stage
+///
+/// Each iteration mirrors what happens when a pyramid is uploaded, minus the database:
+///  1. Generates a `size`x`size` RGB test image (a gradient with some high-frequency detail, so
+///     compression has something to chew on)
+///  2. Generates a Gaussian pyramid from it
+///  3. Breaks each pyramid level into tiles
+///  4. Encodes each tile as PNG and Brotli compresses it
+///
+/// This is CPU-bound, so callers in an async context should run it via `spawn_blocking`.
+pub fn run_benchmark(
+    tunables: &Tunables,
+    size: u32,
+    iterations: u32,
+) -> Result<BenchmarkResults, Error> {
+    if size == 0 || iterations == 0 {
+        return Err(Error::validation(
+            "Benchmark size and iterations must be non-zero",
+        ));
+    }
+
+    let mut results = BenchmarkResults {
+        image_size: size,
+        tile_width: tunables.tile_width,
+        tile_height: tunables.tile_height,
+        brotli_level: tunables.brotli_level,
+        brotli_lg_window_size: tunables.brotli_lg_window_size,
+        pyramid_levels: 0,
+        tile_count: 0,
+        compressed_bytes: 0,
+        worker_threads: rayon::current_num_threads(),
+        iterations: Vec::new(),
+        mean: BenchmarkIteration::default(),
+    };
+
+    for _ in 0..iterations {
+        let mut timings = BenchmarkIteration::default();
+        let total_start = Instant::now();
+
+        let start = Instant::now();
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(size, size, |x, y| {
+            Rgb([
+                (x * 255 / size) as u8,
+                (y * 255 / size) as u8,
+                ((x ^ y) & 0xFF) as u8,
+            ])
+        }));
+        timings.generate_image_ms = elapsed_ms(start);
+
+        let start = Instant::now();
+        let mut pyramid = ipr::Pyramid::new(&image, None)?;
+        timings.generate_pyramid_ms = elapsed_ms(start);
+
+        let start = Instant::now();
+        pyramid.tile_all_levels(tunables.tile_width, tunables.tile_height)?;
+        timings.make_tiles_ms = elapsed_ms(start);
+
+        let start = Instant::now();
+        let level_tiles: Vec<&ImageTiles> = (0..pyramid.len())
+            .filter_map(|l| pyramid.tiles(l))
+            .collect();
+        let compressed = level_tiles
+            .par_iter()
+            .flat_map(|lt| lt.tiles.par_iter())
+            .map(|t| {
+                IprImage(t).compress_brotli(
+                    tunables.brotli_level,
+                    tunables.brotli_lg_window_size,
+                    Some(ImageFormat::Png),
+                )
+            })
+            .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+        timings.compress_tiles_ms = elapsed_ms(start);
+
+        timings.total_ms = elapsed_ms(total_start);
+
+        results.pyramid_levels = pyramid.len();
+        results.tile_count = compressed.len();
+        results.compressed_bytes = compressed.iter().map(Vec::len).sum();
+        results.iterations.push(timings);
+    }
+
+    let n = results.iterations.len() as f64;
+    for i in &results.iterations {
+        results.mean.generate_image_ms += i.generate_image_ms / n;
+        results.mean.generate_pyramid_ms += i.generate_pyramid_ms / n;
+        results.mean.make_tiles_ms += i.make_tiles_ms / n;
+        results.mean.compress_tiles_ms += i.compress_tiles_ms / n;
+        results.mean.total_ms += i.total_ms / n;
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// The fixture pyramid (40x24 and 20x12), with two more levels: 10x6 and 5x3
+    fn four_level_pyramid() -> Pyramid {
+        let fixture = crate::test_support::fixture_documents("pyramids").unwrap();
+        let mut pyramid: Pyramid = mongodb::bson::from_document(fixture[0].clone()).unwrap();
+        for (width, height) in [(10, 6), (5, 3)] {
+            let level = pyramid.image_names.len();
+            pyramid
+                .image_names
+                .push(documents::level_name(&pyramid.uuid, level));
+            pyramid
+                .level_dims
+                .push(documents::LevelDims { width, height });
+        }
+        pyramid
+    }
+
+    #[test_case(0, 10 => Some(2) ; "level exactly the size asked for")]
+    #[test_case(0, 12 => Some(1) ; "smallest level bigger than asked for")]
+    #[test_case(0, 64 => Some(0) ; "full image when every level is too small")]
+    #[test_case(2, 64 => Some(2) ; "level asked about when it is too small")]
+    #[test_case(1, 1 => Some(3) ; "smallest level of all")]
+    fn thumbnails_come_from_the_smallest_big_enough_level(level: usize, max: u32) -> Option<usize> {
+        let pyramid = four_level_pyramid();
+        let source = thumbnail_source_level(&pyramid, &pyramid.image_names[level], max)?;
+        pyramid.image_names.iter().position(|n| *n == source)
+    }
+
+    #[test]
+    fn thumbnails_need_recorded_level_dims() {
+        let mut pyramid = four_level_pyramid();
+        assert_eq!(thumbnail_source_level(&pyramid, "not a level", 10), None);
+        pyramid.level_dims.clear();
+        assert_eq!(
+            thumbnail_source_level(&pyramid, &pyramid.image_names[0], 10),
+            None
+        );
+    }
+
+    #[test]
+    fn benchmark_reports_every_iteration() {
+        let tunables = Tunables {
+            tile_width: 32,
+            tile_height: 32,
+            brotli_level: 1,
+            ..Tunables::default()
+        };
+        let results = run_benchmark(&tunables, 64, 2).unwrap();
+        assert_eq!(results.image_size, 64);
+        assert_eq!(results.tile_width, 32);
+        assert_eq!(results.iterations.len(), 2);
+        assert!(results.pyramid_levels > 1);
+        // Four tiles for the full-size level, and one for each level after it
+        assert_eq!(results.tile_count, 4 + results.pyramid_levels - 1);
+        assert!(results.compressed_bytes > 0);
+
+        let json = serde_json::to_value(&results).unwrap();
+        for stage in [
+            "generate_image_ms",
+            "generate_pyramid_ms",
+            "make_tiles_ms",
+            "compress_tiles_ms",
+            "total_ms",
+        ] {
+            assert!(json["mean"][stage].is_f64(), "mean is missing {}", stage);
+            assert!(json["iterations"][1][stage].is_f64());
+        }
+    }
+
+    #[test]
+    fn benchmark_rejects_empty_workloads() {
+        assert!(matches!(
+            run_benchmark(&Tunables::default(), 0, 1),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            run_benchmark(&Tunables::default(), 64, 0),
+            Err(Error::Validation(_))
+        ));
     }
 }
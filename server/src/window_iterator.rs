@@ -5,6 +5,70 @@ pub struct ImageDescriptor<'a, T> {
     height: usize,
 }
 
+impl<'a, T> ImageDescriptor<'a, T> {
+    /// The element at (`x`, `y`), with coordinates outside the image handled per `border`
+    fn get(&self, x: isize, y: isize, border: &BorderMode<'a, T>) -> &'a T {
+        match (
+            border.resolve(x, self.width),
+            border.resolve(y, self.height),
+        ) {
+            (Some(x), Some(y)) => &self.data[y * self.width + x],
+            _ => match border {
+                BorderMode::Constant(value) => *value,
+                _ => unreachable!("Only constant borders leave coordinates unresolved"),
+            },
+        }
+    }
+}
+
+/// How a window reads elements outside its image, e.g. for a ROI shifted past an edge
+pub enum BorderMode<'a, T> {
+    /// Repeat the nearest edge element: `aaa|abcd|ddd`
+    Clamp,
+
+    /// Mirror about the edge element, without repeating it: `dcb|abcd|cba`
+    Reflect,
+
+    /// Continue from the opposite edge: `bcd|abcd|abc`
+    Wrap,
+
+    /// Use the given value: `xxx|abcd|xxx`
+    Constant(&'a T),
+}
+
+// Derived impls would needlessly require `T: Clone`
+impl<'a, T> Clone for BorderMode<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for BorderMode<'a, T> {}
+
+impl<'a, T> BorderMode<'a, T> {
+    /// Map coordinate `i` along an axis of length `len` to one inside the image, or `None` if
+    /// it's outside and should take the constant value
+    fn resolve(&self, i: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if (0..len).contains(&i) {
+            return Some(i as usize);
+        }
+
+        match self {
+            BorderMode::Clamp => Some(i.clamp(0, len - 1) as usize),
+            BorderMode::Reflect if len == 1 => Some(0),
+            BorderMode::Reflect => {
+                let period = 2 * (len - 1);
+                let i = i.rem_euclid(period);
+                let i = if i < len { i } else { period - i };
+                Some(i as usize)
+            }
+            BorderMode::Wrap => Some(i.rem_euclid(len) as usize),
+            BorderMode::Constant(_) => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct StrideDescriptor {
     /// How far to stride when iterating horizontally
@@ -28,7 +92,7 @@ pub struct ImageBufferWindow<'a, T> {
     image: ImageDescriptor<'a, T>,
     stride: StrideDescriptor,
     roi: RoiDescriptor,
-    default: &'a T,
+    border: BorderMode<'a, T>,
     dist_from_x1_to_x2: usize,
     counter: usize,
     total_els: usize,
@@ -39,7 +103,7 @@ pub struct ImageBufferWindowBuilder<'a, T> {
     image: ImageDescriptor<'a, T>,
     stride: Option<StrideDescriptor>,
     roi: Option<RoiDescriptor>,
-    default: Option<&'a T>,
+    border: Option<BorderMode<'a, T>>,
 }
 
 impl<'a, T> ImageBufferWindowBuilder<'a, T> {
@@ -80,9 +144,16 @@ impl<'a, T> ImageBufferWindowBuilder<'a, T> {
         self
     }
 
+    /// Shorthand for a [`BorderMode::Constant`] border of `default`
     #[allow(dead_code)]
-    pub fn with_default(mut self, default: &'a T) -> Self {
-        self.default = Some(default);
+    pub fn with_default(self, default: &'a T) -> Self {
+        self.with_border(BorderMode::Constant(default))
+    }
+
+    /// How to read elements outside the image. Clamps to the nearest edge if not set
+    #[allow(dead_code)]
+    pub fn with_border(mut self, border: BorderMode<'a, T>) -> Self {
+        self.border = Some(border);
         self
     }
 
@@ -97,7 +168,7 @@ impl<'a, T> ImageBufferWindowBuilder<'a, T> {
             image: self.image,
             stride: self.stride.unwrap(),
             roi,
-            default: self.default.unwrap(),
+            border: self.border.unwrap_or(BorderMode::Clamp),
             dist_from_x1_to_x2,
             counter: 0,
             total_els,
@@ -117,52 +188,121 @@ impl<'a, T> ImageBufferWindow<'a, T> {
             },
             stride: None,
             roi: None,
-            default: None,
+            border: None,
+        }
+    }
+
+    /// Image coordinates of the `counter`th element of the window, in row-major order
+    fn position(&self, counter: usize) -> (isize, isize) {
+        let roi_x: isize = (counter % (self.dist_from_x1_to_x2 + 1) * self.stride.per_element)
+            .try_into()
+            .unwrap();
+        let roi_y: isize = (counter / (self.dist_from_x1_to_x2 + 1) * self.stride.per_row)
+            .try_into()
+            .unwrap();
+        (self.roi.x1 + roi_x, self.roi.y1 + roi_y)
+    }
+
+    /// Iterate over the `kernel_w`x`kernel_h` neighborhood of each element of the window, in the
+    /// same order as iterating over the window itself. Each element sits at
+    /// (`kernel_w / 2`, `kernel_h / 2`) within its neighborhood, and neighbors outside the image
+    /// are handled per the window's [`BorderMode`]
+    #[allow(dead_code)]
+    pub fn windows(self, kernel_w: usize, kernel_h: usize) -> NeighborhoodIterator<'a, T> {
+        NeighborhoodIterator {
+            window: self,
+            kernel_w,
+            kernel_h,
         }
     }
 }
 
-pub struct ImageBufferWindowIterator<'a, T> {
+/// The neighborhood of one element of an [`ImageBufferWindow`]. See
+/// [`ImageBufferWindow::windows`]
+#[derive(Clone, Copy)]
+pub struct Neighborhood<'a, T> {
+    image: ImageDescriptor<'a, T>,
+    border: BorderMode<'a, T>,
+
+    /// Image coordinates of the neighborhood's top-left element
+    x0: isize,
+    y0: isize,
+
+    width: usize,
+    height: usize,
+}
+
+#[allow(dead_code)]
+impl<'a, T> Neighborhood<'a, T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The element in column `i` and row `j` of the neighborhood
+    pub fn get(&self, i: usize, j: usize) -> &'a T {
+        assert!(i < self.width && j < self.height);
+        self.image
+            .get_or(self.x0 + i as isize, self.y0 + j as isize, &self.border)
+    }
+
+    /// Every element of the neighborhood, in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + '_ {
+        (0..self.height).flat_map(move |j| (0..self.width).map(move |i| self.get(i, j)))
+    }
+}
+
+pub struct NeighborhoodIterator<'a, T> {
     window: ImageBufferWindow<'a, T>,
+    kernel_w: usize,
+    kernel_h: usize,
 }
 
-impl<'a, T> Iterator for ImageBufferWindowIterator<'a, T>
+impl<'a, T> Iterator for NeighborhoodIterator<'a, T>
 where
     T: Copy,
 {
-    type Item = &'a T;
+    type Item = Neighborhood<'a, T>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.window.counter >= self.window.total_els {
             return None;
         }
 
-        let counter = self.window.counter;
+        let (x, y) = self.window.position(self.window.counter);
         self.window.counter += 1;
+        Some(Neighborhood {
+            image: self.window.image,
+            border: self.window.border,
+            x0: x - (self.kernel_w / 2) as isize,
+            y0: y - (self.kernel_h / 2) as isize,
+            width: self.kernel_w,
+            height: self.kernel_h,
+        })
+    }
+}
 
-        let roi_x: isize = (counter % (self.window.dist_from_x1_to_x2 + 1)
-            * self.window.stride.per_element)
-            .try_into()
-            .unwrap();
-        let roi_y: isize = (counter / (self.window.dist_from_x1_to_x2 + 1)
-            * self.window.stride.per_row)
-            .try_into()
-            .unwrap();
+pub struct ImageBufferWindowIterator<'a, T> {
+    window: ImageBufferWindow<'a, T>,
+}
 
-        let x: isize = self.window.roi.x1 + roi_x;
-        let y: isize = self.window.roi.y1 + roi_y;
-        if x < 0 || y < 0 {
-            return Some(self.window.default);
-        }
+impl<'a, T> Iterator for ImageBufferWindowIterator<'a, T>
+where
+    T: Copy,
+{
+    type Item = &'a T;
 
-        let x: usize = x.try_into().unwrap();
-        let y: usize = y.try_into().unwrap();
-        if x >= self.window.image.width || y >= self.window.image.height {
-            return Some(self.window.default);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window.counter >= self.window.total_els {
+            return None;
         }
 
-        let idx: usize = y * self.window.image.width + x;
-        Some(&self.window.image.data[idx])
+        let (x, y) = self.window.position(self.window.counter);
+        self.window.counter += 1;
+        Some(self.window.image.get(x, y, &self.window.border))
     }
 }
 
@@ -224,7 +364,6 @@ where
 mod tests {
     use super::*;
     extern crate test;
-    use itertools::izip;
     use std::iter::zip;
     use test::Bencher;
 
@@ -350,62 +489,21 @@ mod tests {
         */
 
         let data: Vec<u8> = (0..25).collect();
+        let window = ImageBufferWindow::new(&data, 5, 5)
+            .with_stride(1, 1)
+            .with_max_roi()
+            .with_default(&0)
+            .build();
 
-        #[rustfmt::skip]
-        let shifts = { vec![
-            (-1, -1), (0, -1), (1, -1),
-            (-1,  0), (0,  0), (1,  0),
-            (-1,  1), (0,  1), (1,  1),
-        ] };
-        let windows: Vec<ImageBufferWindow<u8>> = shifts
-            .iter()
-            .map(|(dx, dy)| {
-                ImageBufferWindow::new(&data, 5, 5)
-                    .with_stride(1, 1)
-                    .with_max_roi()
-                    .shift_roi(*dx, *dy)
-                    .with_default(&0)
-                    .build()
-            })
-            .collect();
-
-        let mut results = [0f32; 25];
-        let box_3x3: Vec<i16> = vec![1, 1, 1, 1, 1, 1, 1, 1, 1];
-        let box_3x3: Vec<f32> = box_3x3
-            .iter()
-            .map(|x| {
-                let normalized: f32 = (*x).try_into().unwrap();
-                normalized / 9f32
+        let box_3x3 = [1f32 / 9f32; 9];
+        let results: Vec<f32> = window
+            .windows(3, 3)
+            .map(|neighborhood| {
+                zip(neighborhood.iter(), box_3x3.iter())
+                    .fold(0.0, |sum, (&w, g)| sum + w as f32 * g)
             })
             .collect();
 
-        // Below we transpose `windows` so that, instead of a vector of iterable windows, we have
-        // an iterable collection where each element N is a tuple of all the "Nth" index of each of
-        // the windows
-        let transposed_windows = izip!(
-            windows[0].into_iter(),
-            windows[1].into_iter(),
-            windows[2].into_iter(),
-            windows[3].into_iter(),
-            windows[4].into_iter(),
-            windows[5].into_iter(),
-            windows[6].into_iter(),
-            windows[7].into_iter(),
-            windows[8].into_iter()
-        );
-
-        for (i, ws) in transposed_windows.enumerate() {
-            let mut sum: f32 = 0.0;
-            let neighborhood = &[
-                &ws.0, &ws.1, &ws.2, &ws.3, &ws.4, &ws.5, &ws.6, &ws.7, &ws.8,
-            ];
-            for (w, g) in zip(neighborhood.iter(), box_3x3.iter()) {
-                let w: f32 = (***w).try_into().unwrap();
-                sum += w * g;
-            }
-            results[i] = sum;
-        }
-
         #[rustfmt::skip]
         let expected_results: Vec<f32> = vec![
              1.3333334,  2.3333335, 3.0,  3.6666667, 2.6666667,
@@ -420,6 +518,90 @@ mod tests {
         }
     }
 
+    #[test]
+    fn neighborhoods_are_centered_and_padded() {
+        let data: Vec<u8> = (0..100).collect();
+        let window = ImageBufferWindow::new(&data, 10, 10)
+            .with_stride(1, 1)
+            .with_roi(0, 9, 4, 4)
+            .with_default(&255)
+            .build();
+
+        let neighborhoods: Vec<Neighborhood<u8>> = window.windows(3, 5).collect();
+        assert_eq!(neighborhoods.len(), 10);
+        let n = neighborhoods[5];
+        assert_eq!((n.width(), n.height()), (3, 5));
+        assert_eq!(*n.get(1, 2), 45);
+        assert_eq!(*n.get(0, 0), 24);
+        assert_eq!(*n.get(2, 4), 66);
+
+        // The leftmost column of the first neighborhood is past the image's left edge
+        #[rustfmt::skip]
+        let expected: Vec<u8> = vec![
+            255, 20, 21,
+            255, 30, 31,
+            255, 40, 41,
+            255, 50, 51,
+            255, 60, 61,
+        ];
+        let first: Vec<u8> = neighborhoods[0].iter().copied().collect();
+        assert_eq!(first, expected);
+    }
+
+    #[test]
+    fn border_modes_pad_each_edge() {
+        let data: Vec<u8> = vec![0, 1, 2, 3];
+        let row = |border: BorderMode<u8>| -> Vec<u8> {
+            ImageBufferWindow::new(&data, 4, 1)
+                .with_stride(1, 1)
+                .with_roi(-5, 8, 0, 0)
+                .with_border(border)
+                .build()
+                .into_iter()
+                .copied()
+                .collect()
+        };
+
+        #[rustfmt::skip]
+        let cases = vec![
+            (BorderMode::Clamp,          vec![0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3]),
+            (BorderMode::Reflect,        vec![1, 2, 3, 2, 1, 0, 1, 2, 3, 2, 1, 0, 1, 2]),
+            (BorderMode::Wrap,           vec![3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0]),
+            (BorderMode::Constant(&9),   vec![9, 9, 9, 9, 9, 0, 1, 2, 3, 9, 9, 9, 9, 9]),
+        ];
+        for (border, expected) in cases {
+            assert_eq!(row(border), expected);
+        }
+    }
+
+    #[test]
+    fn border_modes_apply_vertically() {
+        let data: Vec<u8> = vec![0, 1, 2];
+        let column: Vec<u8> = ImageBufferWindow::new(&data, 1, 3)
+            .with_stride(1, 1)
+            .with_roi(0, 0, -2, 4)
+            .with_border(BorderMode::Reflect)
+            .build()
+            .into_iter()
+            .copied()
+            .collect();
+        assert_eq!(column, vec![2, 1, 0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn reflect_handles_single_element_images() {
+        let data: Vec<u8> = vec![7];
+        let neighborhood = ImageBufferWindow::new(&data, 1, 1)
+            .with_stride(1, 1)
+            .with_max_roi()
+            .with_border(BorderMode::Reflect)
+            .build()
+            .windows(3, 3)
+            .next()
+            .unwrap();
+        assert!(neighborhood.iter().all(|&v| v == 7));
+    }
+
     #[bench]
     fn bench_iterate_over_window(b: &mut Bencher) {
         let data: Vec<u8> = vec![0; 1000000];
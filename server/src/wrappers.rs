@@ -1,6 +1,13 @@
-use jnickg_imaging::{dims::Dims, dyn_matrix::DynMatrix, element::Element, matrix::Matrix};
+use axum::response::Response;
+use jnickg_imaging::{
+    dims::Dims, dyn_matrix::DynMatrix, element::Element, errors::Error, matrix::Matrix,
+};
 
 #[allow(dead_code)]
-pub(crate) struct WrappedMatrix<T: Element, const R: usize, const C: usize>(pub Matrix<T, R, C>);
-pub(crate) struct WrappedDynMatrix<T: Element>(pub DynMatrix<T>);
-pub(crate) struct WrappedDims(pub Dims);
+pub struct WrappedMatrix<T: Element, const R: usize, const C: usize>(pub Matrix<T, R, C>);
+pub struct WrappedDynMatrix<T: Element>(pub DynMatrix<T>);
+pub struct WrappedDims(pub Dims);
+pub struct WrappedError(pub Error);
+
+/// What handlers return. Errors become responses with a status code matching their kind
+pub type ApiResult<T = Response> = Result<T, WrappedError>;